@@ -1,11 +1,15 @@
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use server::error::ApiError;
 use solana_sdk::pubkey::Pubkey;
 use state::account_state::AccountState;
+use state_commitment::pipeline::{self, PendingAccount};
 use state_management::state_management::{ManageState, StateManager};
 use std::str::FromStr;
 use std::sync::Arc;
 use warp::{reply::json, Rejection, Reply};
 use state::config::TrollupConfig;
+use crate::export::{self, ExportFormat};
 
 type Result<T> = std::result::Result<T, Rejection>;
 
@@ -13,6 +17,22 @@ lazy_static! {
     static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
 }
 
+#[derive(Deserialize, Default)]
+pub struct AccountQuery {
+    /// Also return the account as written by batches that aren't finalized yet
+    #[serde(default)]
+    pub include_pending: bool,
+}
+
+/// An account as finalized, next to its latest pending state. Either may be missing, an account
+/// created by a pending batch has no finalized state.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountWithPending {
+    pub account: Option<AccountState>,
+    pub pending: Option<PendingAccount>,
+}
+
 pub struct AccountHandler<A: ManageState<Record=AccountState>> {
     account_state_management: Arc<StateManager<A>>,
 }
@@ -22,15 +42,24 @@ impl <A: ManageState<Record=AccountState>> AccountHandler<A> {
         AccountHandler { account_state_management }
     }
 
-    pub async fn get_account(&self, account_id: &str) -> Result<impl Reply> {
-        let pubkey = Pubkey::from_str(account_id).unwrap();
+    pub async fn get_account(&self, account_id: &str, query: AccountQuery) -> Result<impl Reply> {
+        let pubkey = match Pubkey::from_str(account_id) {
+            Ok(pubkey) => pubkey,
+            Err(_) => return Ok(ApiError::invalid_request(format!("Invalid account id: {:?}", account_id)).into_response()),
+        };
         let option = self.account_state_management.get_state_record(&pubkey.to_bytes());
+        if query.include_pending {
+            let pending = pipeline::pending_account(&pubkey.to_bytes());
+            if option.is_some() || pending.is_some() {
+                return Ok(json(&AccountWithPending { account: option, pending }).into_response());
+            }
+        }
         match option {
             None => {
-                Ok(json(&format!("No account found for: {:?}", account_id)))
+                Ok(ApiError::not_found(format!("No account found for: {:?}", account_id)).into_response())
             }
             Some(account) => {
-                Ok(json(&account))
+                Ok(json(&account).into_response())
             }
         }
     }
@@ -39,4 +68,11 @@ impl <A: ManageState<Record=AccountState>> AccountHandler<A> {
         let accounts = self.account_state_management.get_all_entries();
         Ok(json(&accounts))
     }
+
+    pub async fn export_accounts(&self, format: ExportFormat) -> Result<impl Reply>
+    where
+        A: Send + Sync + 'static,
+    {
+        Ok(export::stream(Arc::clone(&self.account_state_management), format, Some))
+    }
 }
\ No newline at end of file
@@ -0,0 +1,63 @@
+//! API key authentication of the submission routes. Reads stay public, only the routes that put
+//! transactions in the pool ask for a key once any are configured. The admin routes that change
+//! the node take an operator token instead, see `require_admin_token`.
+
+use log::warn;
+use server::access_log::API_KEY_HEADER;
+use server::auth::constant_time_eq;
+pub use server::auth::{require_admin_token, AdminToken};
+use server::error::{ApiError, ErrorCode};
+use state::config::TrollupConfig;
+use std::sync::Arc;
+use warp::{Filter, Rejection};
+
+/// The keys the submission routes accept, `API_KEYS`. Without any the routes are open.
+#[derive(Clone, Default)]
+pub struct ApiKeys {
+    keys: Arc<Vec<String>>,
+}
+
+impl ApiKeys {
+    pub fn new(keys: Vec<String>) -> Self {
+        ApiKeys { keys: Arc::new(keys) }
+    }
+
+    pub fn from_config(config: &TrollupConfig) -> Self {
+        Self::new(config.api_keys.clone())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Checks `presented` against every key, so the time taken doesn't reveal which one it
+    /// came close to.
+    fn accepts(&self, presented: &str) -> bool {
+        self.keys.iter().fold(false, |accepted, key| accepted | constant_time_eq(presented.as_bytes(), key.as_bytes()))
+    }
+}
+
+/// Rejects with unauthorized unless the request carries one of `keys`, as `Authorization: Bearer
+/// <key>` or in `x-api-key`. Passes everything while no keys are configured.
+pub fn require_api_key(keys: ApiKeys) -> impl Filter<Extract=(), Error=Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::header::optional::<String>(API_KEY_HEADER))
+        .and_then(move |authorization: Option<String>, api_key: Option<String>| {
+            let keys = keys.clone();
+            async move {
+                if !keys.is_enabled() {
+                    return Ok(());
+                }
+                let bearer = authorization.as_deref().and_then(|header| header.strip_prefix("Bearer "));
+                match bearer.or(api_key.as_deref()) {
+                    Some(presented) if keys.accepts(presented) => Ok(()),
+                    Some(_) => {
+                        warn!(target: "audit", "Rejected submission with an unknown API key");
+                        Err(warp::reject::custom(ApiError::new(ErrorCode::Unauthorized, "Invalid API key")))
+                    }
+                    None => Err(warp::reject::custom(ApiError::new(ErrorCode::Unauthorized, "API key required"))),
+                }
+            }
+        })
+        .untuple_one()
+}
@@ -0,0 +1,432 @@
+use crate::cross_rollup_handler::signed_commitments;
+use execution::replay::replay_witness;
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::UiTransactionEncoding;
+use state::account_state::AccountState;
+use state::batch_data::BatchData;
+use state::block::{Block, L1Anchor};
+use state::config::TrollupConfig;
+use state::cross_rollup::{outbox_messages, outbox_root};
+use state::merkle::{hash_leaves, parallel_root};
+use state::state_record::StateRecord;
+use state::transaction::TrollupTransaction;
+use state::witness::account_leaf;
+use state_commitment::batch_data::{self, store_from_config};
+use state_commitment::{l1_divergence, merkle_index, rollup_status, transaction_index};
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::{ManageState, StateManager};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use trollup_commitment_message::BlockProducer;
+
+/// Most blocks a rollback rewinds, a longer divergence is rebuilt with `--backfill-from-l1`.
+const MAX_ROLLBACK_BLOCKS: u64 = 1_000;
+
+/// A root the signature verifier accepted, as read from the commitment transaction.
+struct CommittedRoot {
+    root: [u8; 32],
+    producer: BlockProducer,
+    slot: u64,
+    signature: String,
+}
+
+/// What `--backfill-from-l1` rebuilt.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BackfillReport {
+    /// First and last block rebuilt, `None` when the node wasn't behind L1
+    pub first_block: Option<u64>,
+    pub last_block: Option<u64>,
+    /// Rebuilt blocks whose root was checked against their L1 commitment, the others are
+    /// heartbeats, checked against their parent
+    pub blocks_verified_on_l1: u64,
+}
+
+/// What a rollback to L1 undid and rebuilt.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RollbackReport {
+    /// Last local block that matches its L1 commitment
+    pub common_block: u64,
+    /// Local blocks after it that were discarded
+    pub discarded_blocks: u64,
+    /// The blocks rebuilt from L1 after it
+    pub backfill: BackfillReport,
+}
+
+/// Rebuilds the blocks between the node's latest block and the last one committed on L1.
+///
+/// The commitment transactions of the signature verifier give the root of every committed
+/// block, the batch data store the transactions and witness to replay, signed by the
+/// sequencer. Each batch is replayed
+/// on top of the rebuilt account store and its root must match the one L1 accepted before the
+/// block, its accounts and transactions are written. A failure stops the backfill at the last
+/// good block, so it can be resumed once the missing data is found.
+pub async fn backfill(config: &TrollupConfig) -> Result<BackfillReport, String> {
+    let account_state_manager = StateManager::<SledStateManagement<AccountState>>::new(&config.account_state_manager_db_path);
+    let block_state_manager = StateManager::<SledStateManagement<Block>>::new(&config.block_state_manager_db_path);
+    let transaction_state_manager = StateManager::<SledStateManagement<TrollupTransaction>>::new(&config.transaction_state_manager_db_path);
+    backfill_into(config, &account_state_manager, &block_state_manager, &transaction_state_manager).await
+}
+
+/// `backfill` on stores that are already open.
+async fn backfill_into<A, B, T>(
+    config: &TrollupConfig,
+    account_state_manager: &StateManager<A>,
+    block_state_manager: &StateManager<B>,
+    transaction_state_manager: &StateManager<T>,
+) -> Result<BackfillReport, String>
+where
+    A: ManageState<Record=AccountState>,
+    B: ManageState<Record=Block>,
+    T: ManageState<Record=TrollupTransaction>,
+{
+    let store = store_from_config(&config.batch_data_location)
+        .ok_or("BATCH_DATA_LOCATION is not set, there is no batch data to replay")?;
+    let signer = batch_data::trusted_signer(config)?;
+    let program_id = signature_verifier_program_id(config)?;
+
+    let latest_block = block_state_manager
+        .get_latest_block_id()
+        .and_then(|id| block_state_manager.get_state_record(&id));
+    let local_block_number = latest_block.as_ref().map(|block| block.block_number).unwrap_or_default();
+    let rpc_client = RpcClient::new_with_commitment(config.rpc_url_current_env().to_string(), CommitmentConfig::finalized());
+    let committed = committed_roots(&rpc_client, &program_id, local_block_number).await?;
+    let Some(&last_committed) = committed.keys().next_back() else {
+        info!("L1 committed nothing after block {}, nothing to backfill", local_block_number);
+        return Ok(BackfillReport { first_block: None, last_block: None, blocks_verified_on_l1: 0 });
+    };
+    info!("Backfilling blocks {} to {} from L1 history", local_block_number + 1, last_committed);
+
+    let mut parent_root = latest_block.map(|block| *block.accounts_merkle_root).unwrap_or_default();
+    let mut blocks_verified_on_l1 = 0;
+    for block_number in local_block_number + 1..=last_committed {
+        let batch = batch_data::fetch(store.as_ref(), block_number, &signer, config.batch_data_allow_unsigned)
+            .await
+            .map_err(|error| format!("Failed to fetch the batch data of block {}: {}", block_number, error))?
+            .ok_or_else(|| format!("No batch data was published for block {}", block_number))?;
+        let l1_commitment = committed.get(&block_number);
+        let (mut block, accounts) = match &batch.witness {
+            None if batch.transactions.is_empty() => (heartbeat_block(block_number, parent_root), vec![]),
+            None => return Err(format!("The batch data of block {} has transactions but no witness", block_number)),
+            Some(_) => replay_block(config, account_state_manager, &batch)?,
+        };
+        block.set_parameters_hash(batch.parameters.hash());
+
+        match l1_commitment {
+            Some(committed_root) if committed_root.root != *block.accounts_merkle_root => {
+                return Err(format!(
+                    "Block {} replays to root {} but L1 committed {}",
+                    block_number,
+                    hex::encode(*block.accounts_merkle_root),
+                    hex::encode(committed_root.root),
+                ));
+            }
+            Some(committed_root) => {
+                block.set_producer(committed_root.producer);
+                block.l1_anchor = L1Anchor {
+                    slot: Some(committed_root.slot),
+                    signature: Some(committed_root.signature.clone()),
+                };
+                blocks_verified_on_l1 += 1;
+            }
+            None if !block.is_heartbeat() => return Err(format!("Block {} has transactions but no commitment on L1", block_number)),
+            None => {}
+        }
+
+        // Accounts and transactions before the block, so the latest block never points past them
+        account_state_manager.set_state_records(&accounts);
+        transaction_state_manager.set_state_records(&batch.transactions);
+        account_state_manager.commit();
+        transaction_state_manager.commit();
+        block_state_manager.set_latest_block_id(&block.get_key());
+        block_state_manager.set_state_record(&block);
+        block_state_manager.commit();
+        transaction_index::record_block(&block);
+        transaction_index::commit();
+        merkle_index::record_block(&block);
+        merkle_index::commit();
+        parent_root = *block.accounts_merkle_root;
+        info!("Rebuilt block {}", block_number);
+    }
+
+    Ok(BackfillReport {
+        first_block: Some(local_block_number + 1),
+        last_block: Some(last_committed),
+        blocks_verified_on_l1,
+    })
+}
+
+/// Undoes the local blocks L1 didn't commit and rebuilds the chain from the committed ones, after
+/// the divergence watch halted commitments.
+///
+/// Only the signature verifier's state account is trusted for the current root, the program only
+/// writes it after checking the validator's signature. The commitment transactions then give the
+/// root of every block before it. The local chain is walked back from its tip to the last block
+/// whose root L1 committed, and every block after it is rewound with the pre-state its batch
+/// data recorded. The blocks L1 committed after it are replayed as by `--backfill-from-l1`.
+/// Nothing is rewound when the batch data of a block is missing or doesn't match it.
+pub async fn roll_back_to_l1<A, B, T>(
+    config: &TrollupConfig,
+    account_state_manager: &StateManager<A>,
+    block_state_manager: &StateManager<B>,
+    transaction_state_manager: &StateManager<T>,
+) -> Result<RollbackReport, String>
+where
+    A: ManageState<Record=AccountState>,
+    B: ManageState<Record=Block>,
+    T: ManageState<Record=TrollupTransaction>,
+{
+    let store = store_from_config(&config.batch_data_location)
+        .ok_or("BATCH_DATA_LOCATION is not set, there is no batch data to roll back with")?;
+    let signer = batch_data::trusted_signer(config)?;
+    let program_id = signature_verifier_program_id(config)?;
+    let rpc_client = RpcClient::new_with_commitment(config.rpc_url_current_env().to_string(), CommitmentConfig::finalized());
+    let l1_root = l1_divergence::fetch_l1_root(&rpc_client, &program_id)
+        .await
+        .map_err(|error| format!("Failed to read the root committed on L1: {}", error))?;
+
+    let latest_block_number = block_state_manager
+        .get_latest_block_id()
+        .and_then(|id| block_state_manager.get_state_record(&id))
+        .map(|block| block.block_number)
+        .unwrap_or_default();
+    let after_block = latest_block_number
+        .min(l1_root.map(|l1_root| l1_root.sequence).unwrap_or_default())
+        .saturating_sub(MAX_ROLLBACK_BLOCKS);
+    let committed = committed_roots(&rpc_client, &program_id, after_block).await?;
+    // The history is only as good as the state account it has to end in
+    if let Some(l1_root) = l1_root {
+        if committed.get(&l1_root.sequence).map(|committed_root| committed_root.root) != Some(l1_root.root) {
+            return Err(format!("The commitment history doesn't end in the root L1 holds for block {}", l1_root.sequence));
+        }
+    }
+
+    let common_block = (after_block + 1..=latest_block_number)
+        .rev()
+        .find(|block_number| {
+            let local_root = block_state_manager.get_state_record(&Block::get_id(*block_number)).map(|block| *block.accounts_merkle_root);
+            committed.get(block_number).is_some_and(|committed_root| Some(committed_root.root) == local_root)
+        });
+    let common_block = match common_block {
+        Some(block_number) => block_number,
+        None if after_block == 0 => 0,
+        None => {
+            return Err(format!(
+                "No block in the last {} matches L1, rebuild the node with --backfill-from-l1",
+                MAX_ROLLBACK_BLOCKS,
+            ));
+        }
+    };
+
+    warn!("Rolling back blocks {} to {} to match L1", common_block + 1, latest_block_number);
+    for block_number in (common_block + 1..=latest_block_number).rev() {
+        let block = block_state_manager
+            .get_state_record(&Block::get_id(block_number))
+            .ok_or_else(|| format!("Block {} is missing from the local chain", block_number))?;
+        let parent_root = block_state_manager
+            .get_state_record(&Block::get_id(block_number - 1))
+            .map(|parent| *parent.accounts_merkle_root)
+            .unwrap_or_default();
+        let batch = if block.is_heartbeat() {
+            None
+        } else {
+            batch_data::fetch(store.as_ref(), block_number, &signer, config.batch_data_allow_unsigned)
+                .await
+                .map_err(|error| format!("Failed to fetch the batch data of block {}: {}", block_number, error))?
+                .map(Some)
+                .ok_or_else(|| format!("No batch data was published for block {}, it can't be rolled back", block_number))?
+        };
+        rewind_block(&block, batch.as_ref(), parent_root, account_state_manager, block_state_manager, transaction_state_manager)?;
+        info!("Rolled back block {}", block_number);
+    }
+
+    let backfill = backfill_into(config, account_state_manager, block_state_manager, transaction_state_manager).await?;
+    let tip = backfill.last_block.unwrap_or(common_block);
+    rollup_status::set_finalized_block_number(tip);
+    Ok(RollbackReport {
+        common_block,
+        discarded_blocks: latest_block_number - common_block,
+        backfill,
+    })
+}
+
+/// Removes `block`, the tip of the local chain, and puts the accounts it wrote back the way its
+/// batch data found them. Accounts the witness doesn't hold were created by the block.
+fn rewind_block<A, B, T>(
+    block: &Block,
+    batch: Option<&BatchData>,
+    parent_root: [u8; 32],
+    account_state_manager: &StateManager<A>,
+    block_state_manager: &StateManager<B>,
+    transaction_state_manager: &StateManager<T>,
+) -> Result<(), String>
+where
+    A: ManageState<Record=AccountState>,
+    B: ManageState<Record=Block>,
+    T: ManageState<Record=TrollupTransaction>,
+{
+    if let Some(batch) = batch {
+        if batch.account_state_root != *block.accounts_merkle_root {
+            return Err(format!("The batch data of block {} is for another root than the local block", block.block_number));
+        }
+        let witness = batch
+            .witness
+            .as_ref()
+            .ok_or_else(|| format!("The batch data of block {} has no witness to roll back with", block.block_number))?;
+        if witness.parent_state_root.is_some_and(|root| root != parent_root) {
+            return Err(format!("The witness of block {} doesn't start from its parent's root", block.block_number));
+        }
+        for address in &block.accounts {
+            let pre_state = witness.accounts.iter().find(|account_witness| account_witness.account.address.to_bytes() == *address);
+            match pre_state {
+                Some(account_witness) => account_state_manager.set_state_record(&account_witness.account),
+                None => {
+                    account_state_manager.delete_state_record(address);
+                }
+            }
+        }
+    }
+    for transaction_id in &block.transactions {
+        transaction_state_manager.delete_state_record(transaction_id);
+    }
+    account_state_manager.commit();
+    transaction_state_manager.commit();
+    transaction_index::remove_block(block);
+    transaction_index::commit();
+    merkle_index::remove_block(block);
+    merkle_index::commit();
+    block_state_manager.set_latest_block_id(&Block::get_id(block.block_number - 1));
+    block_state_manager.delete_state_record(&block.get_key());
+    block_state_manager.commit();
+    Ok(())
+}
+
+fn signature_verifier_program_id(config: &TrollupConfig) -> Result<Pubkey, String> {
+    Pubkey::from_str(config.signature_verifier_program_id_current_env())
+        .map_err(|_| "Invalid SIGNATURE_VERIFIER_PROGRAM_ID".to_string())
+}
+
+/// Roots the signature verifier committed for blocks after `after_block`, by block number. Pages
+/// back through the state account's transactions from the newest until a page reaches
+/// `after_block` or the history ends.
+async fn committed_roots(rpc_client: &RpcClient, program_id: &Pubkey, after_block: u64) -> Result<BTreeMap<u64, CommittedRoot>, String> {
+    let state_pda = l1_divergence::state_pda(program_id);
+    let mut committed = BTreeMap::new();
+    let mut before = None;
+    loop {
+        let config = GetConfirmedSignaturesForAddress2Config {
+            before,
+            until: None,
+            limit: None,
+            commitment: Some(CommitmentConfig::finalized()),
+        };
+        let statuses = rpc_client
+            .get_signatures_for_address_with_config(&state_pda, config)
+            .await
+            .map_err(|error| format!("Failed to list the signature verifier's transactions: {}", error))?;
+        let Some(oldest) = statuses.last() else {
+            return Ok(committed);
+        };
+        before = Some(Signature::from_str(&oldest.signature).map_err(|error| error.to_string())?);
+
+        let mut reached = false;
+        for status in statuses.iter().filter(|status| status.err.is_none()) {
+            let signature = Signature::from_str(&status.signature).map_err(|error| error.to_string())?;
+            let transaction = rpc_client
+                .get_transaction(&signature, UiTransactionEncoding::Base64)
+                .await
+                .map_err(|error| format!("Failed to get L1 transaction {}: {}", signature, error))?;
+            let Some(decoded) = transaction.transaction.transaction.decode() else {
+                warn!("Skipping L1 transaction {}, it can't be decoded", signature);
+                continue;
+            };
+            for commitment in signed_commitments(&decoded, program_id) {
+                if commitment.sequence <= after_block {
+                    reached = true;
+                    continue;
+                }
+                committed.insert(commitment.sequence, CommittedRoot {
+                    root: commitment.new_state_root,
+                    producer: commitment.producer,
+                    slot: transaction.slot,
+                    signature: status.signature.clone(),
+                });
+            }
+        }
+        if reached {
+            return Ok(committed);
+        }
+    }
+}
+
+/// Replays the batch of a block against the rebuilt account store and returns the block with
+/// the accounts it wrote.
+fn replay_block<A: ManageState<Record=AccountState>>(
+    config: &TrollupConfig,
+    account_state_manager: &StateManager<A>,
+    batch: &BatchData,
+) -> Result<(Block, Vec<AccountState>), String> {
+    let witness = batch.witness.as_ref().expect("Only batches with a witness are replayed");
+    if witness.rollup_metadata.fee_schedule.lamports_per_signature != batch.parameters.lamports_per_signature {
+        return Err(format!("Block {} was executed with another fee schedule than its parameters set", batch.block_number));
+    }
+    // The witness stands in for the account store when replaying, it has to agree with the
+    // accounts rebuilt so far
+    for account_witness in &witness.accounts {
+        let address = account_witness.account.address;
+        let stored = account_state_manager.get_state_record(&address.to_bytes());
+        if stored.as_ref().map(account_leaf) != Some(account_leaf(&account_witness.account)) {
+            return Err(format!("The witness of block {} disagrees with the rebuilt state of {}", batch.block_number, address));
+        }
+    }
+
+    let accounts = replay_witness(config, witness);
+    let account_leaves = hash_leaves(&accounts, account_leaf);
+    let accounts_root = parallel_root(&account_leaves).unwrap_or_default();
+    if accounts_root != batch.account_state_root {
+        return Err(format!("Block {} replays to a different root than its batch data records", batch.block_number));
+    }
+    let transaction_leaves = hash_leaves(&batch.transactions, |transaction| Sha256::digest(borsh::to_vec(transaction).unwrap()).into());
+    let messages = outbox_messages(&accounts, |address| account_state_manager.get_state_record(&address.to_bytes()));
+
+    // The proof isn't part of the batch data, the L1 commitment vouches for the block instead
+    let mut block = Block::new(
+        batch.block_number,
+        Block::get_id(batch.block_number - 1),
+        Box::new(parallel_root(&transaction_leaves).unwrap_or_default()),
+        Box::new(accounts_root),
+        vec![],
+        vec![],
+        [0u8; 32],
+        batch.transaction_ids.clone(),
+        accounts.iter().map(|account| account.address.to_bytes()).collect(),
+    );
+    block.account_leaves = account_leaves;
+    block.outbox_root = outbox_root(&messages);
+    block.outbox_messages = messages;
+    block.fees = batch.fees;
+    Ok((block, accounts))
+}
+
+/// Heartbeat blocks carry their parent's root and aren't committed to L1.
+fn heartbeat_block(block_number: u64, parent_root: [u8; 32]) -> Block {
+    Block::new(
+        block_number,
+        Block::get_id(block_number - 1),
+        Box::new([0u8; 32]),
+        Box::new(parent_root),
+        vec![],
+        vec![],
+        [0u8; 32],
+        vec![],
+        vec![],
+    )
+}
@@ -0,0 +1,37 @@
+use server::error::ApiError;
+use state::blob::{Blob, BlobReferences};
+use state_management::blob_store::BlobStore;
+use state_management::state_management::ManageState;
+use std::sync::Arc;
+use warp::http::header;
+use warp::{Rejection, Reply};
+
+type Result<T> = std::result::Result<T, Rejection>;
+
+pub struct BlobHandler<L: ManageState<Record=Blob>, R: ManageState<Record=BlobReferences>> {
+    blob_store: Arc<BlobStore<L, R>>,
+}
+
+impl <L: ManageState<Record=Blob>, R: ManageState<Record=BlobReferences>> BlobHandler<L, R> {
+    pub fn new(blob_store: Arc<BlobStore<L, R>>) -> Self {
+        BlobHandler { blob_store }
+    }
+
+    /// Returns the raw artifact for a hex encoded content hash. Blobs never change once stored,
+    /// so responses can be cached indefinitely and the hash doubles as the ETag.
+    pub async fn get_blob(&self, hash: &str) -> Result<warp::reply::Response> {
+        let key: [u8; 32] = match hex::decode(hash).ok().and_then(|bytes| bytes.try_into().ok()) {
+            Some(key) => key,
+            None => return Ok(ApiError::invalid_request(format!("Invalid blob hash: {:?}", hash)).into_response()),
+        };
+        match self.blob_store.get(&key) {
+            None => Ok(ApiError::not_found(format!("No blob found for: {:?}", hash)).into_response()),
+            Some(data) => {
+                let reply = warp::reply::with_header(data, header::CONTENT_TYPE, "application/octet-stream");
+                let reply = warp::reply::with_header(reply, header::CACHE_CONTROL, "public, max-age=31536000, immutable");
+                let reply = warp::reply::with_header(reply, header::ETAG, format!("\"{}\"", hex::encode(key)));
+                Ok(reply.into_response())
+            }
+        }
+    }
+}
@@ -1,6 +1,16 @@
+use crate::block_verification::{recompute_public_inputs, BlockVerification, BlockVerifier, RecomputedInputs};
+use crate::peer_sync;
 use lazy_static::lazy_static;
-use state::block::Block;
+use serde_derive::{Deserialize, Serialize};
+use server::error::ApiError;
+use solana_sdk::pubkey::Pubkey;
+use state::account_data::DataRangeProof;
+use state::account_state::AccountState;
+use state::block::{Block, L1Anchor};
+use state::views::BlockView;
+use state_commitment::merkle_index;
 use state_management::state_management::{ManageState, StateManager};
+use std::str::FromStr;
 use std::sync::Arc;
 use warp::{reply::json, Rejection, Reply};
 use state::config::TrollupConfig;
@@ -11,6 +21,104 @@ lazy_static! {
     static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
 }
 
+#[derive(Serialize, Deserialize, Default)]
+pub struct GetBlockQuery {
+    /// Verify the block's proof before returning it
+    #[serde(default)]
+    pub verify: bool,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct AccountProofQuery {
+    /// Block to prove against, the latest block that wrote the account when missing
+    #[serde(default)]
+    pub block: Option<u64>,
+}
+
+/// Inclusion proof of an account leaf in a block's account tree. Hashes are hex encoded.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountProofResponse {
+    pub block_number: u64,
+    pub address: String,
+    pub root: String,
+    pub leaf: String,
+    pub leaf_index: u64,
+    pub leaf_count: u64,
+    /// Sibling hashes from the leaf up, as produced by rs_merkle
+    pub proof: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct AccountDataProofQuery {
+    /// Byte range of the account data to prove
+    #[serde(default)]
+    pub offset: u64,
+    #[serde(default)]
+    pub length: u64,
+    /// Block to prove against, the latest block that wrote the account when missing
+    #[serde(default)]
+    pub block: Option<u64>,
+}
+
+/// Proof of a byte range of an account's data: the chunks covering it against the account's
+/// data root, and the account, without its data, against the block's account root. Hashes and
+/// chunks are hex encoded.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDataProofResponse {
+    pub block_number: u64,
+    /// The account as its leaf hashes it, with empty data
+    pub account: AccountState,
+    pub root: String,
+    pub leaf_index: u64,
+    pub leaf_count: u64,
+    pub proof: Vec<String>,
+    pub data_root: String,
+    pub data_len: u64,
+    /// Index of the first chunk, chunks are `DATA_CHUNK_SIZE` bytes
+    pub first_chunk: u64,
+    pub chunks: Vec<String>,
+    pub chunk_proof: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VerifiedBlockResponse {
+    pub block: BlockView,
+    pub verification: BlockVerification,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct L1AnchorResponse {
+    pub block_number: u64,
+    #[serde(flatten)]
+    pub l1_anchor: L1Anchor,
+}
+
+/// Public inputs of a block's proof recomputed from the block's accounts and roots, next to the
+/// prepared inputs the block stores. Roots and inputs are hex encoded.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockPublicInputsResponse {
+    pub block_number: u64,
+    /// Account root of the parent block, all zeros for the first block
+    pub parent_root: String,
+    pub transactions_root: String,
+    /// The inputs in circuit order, the account hash and the lamports sum as field elements.
+    /// Missing when an account of the block changed since
+    pub public_inputs: Option<Vec<String>>,
+    pub lamports_sum: Option<u64>,
+    /// Accounts whose state as of the block isn't in the account store anymore
+    pub changed_accounts: Vec<String>,
+    /// The recomputed inputs prepared against the node's verifying key
+    pub prepared: Option<String>,
+    /// The prepared inputs stored in the block
+    pub stored: String,
+    /// Whether `prepared` equals `stored`, missing when `prepared` is
+    pub matches: Option<bool>,
+}
+
 pub struct BlockHandler<B: ManageState<Record=Block>> {
     block_state_management: Arc<StateManager<B>>,
 }
@@ -25,29 +133,174 @@ impl <B: ManageState<Record=Block>> BlockHandler<B> {
         let option = self.block_state_management.get_state_record(&id);
         match option {
             None => {
-                Ok(json(&format!("No block found for: {:?}", block_id)))
+                Ok(ApiError::not_found(format!("No block found for: {:?}", block_id)).into_response())
+            }
+            Some(block) => {
+                Ok(json(&BlockView::from(&block)).into_response())
             }
+        }
+    }
+
+    pub async fn get_verified_block<A: ManageState<Record=AccountState>>(
+        &self,
+        block_id: u64,
+        account_state_management: &StateManager<A>,
+        verifier: &BlockVerifier,
+    ) -> Result<impl Reply> {
+        let id = Block::get_id(block_id);
+        match self.block_state_management.get_state_record(&id) {
+            None => Ok(ApiError::not_found(format!("No block found for: {:?}", block_id)).into_response()),
             Some(block) => {
-                Ok(json(&format!("block details: {:?}", block)))
+                let verification = verifier.verify(&block, &self.block_state_management, account_state_management);
+                Ok(json(&VerifiedBlockResponse { block: BlockView::from(&block), verification }).into_response())
             }
         }
     }
 
+    pub async fn get_l1_anchor(&self, block_id: u64) -> Result<impl Reply> {
+        match self.block_state_management.get_state_record(&Block::get_id(block_id)) {
+            None => Ok(ApiError::not_found(format!("No block found for: {:?}", block_id)).into_response()),
+            Some(block) => Ok(json(&L1AnchorResponse { block_number: block.block_number, l1_anchor: block.l1_anchor }).into_response()),
+        }
+    }
+
     pub async fn get_latest_block(&self) -> Result<impl Reply> {
         let option = self.block_state_management.get_latest_block_id();
         match option {
             None => {
-                Ok(json(&"No blocks exist".to_string()))
-            }
-            Some(block) => {
-                let block_option = self.block_state_management.get_state_record(&block);
-                Ok(json(&format!("Block details: {:?}", block_option)))
+                Ok(ApiError::not_found("No blocks exist").into_response())
             }
+            Some(block) => match self.block_state_management.get_state_record(&block) {
+                None => Ok(ApiError::not_found("No blocks exist").into_response()),
+                Some(block) => Ok(json(&BlockView::from(&block)).into_response()),
+            },
         }
     }
 
+    /// Identity, chain and supported versions of this node, what peers handshake against before
+    /// syncing from it.
+    pub async fn get_node_info(&self) -> Result<impl Reply> {
+        let identity = CONFIG.node_identity().ok();
+        Ok(json(&peer_sync::local_node_info(&self.block_state_management, identity.as_ref())))
+    }
+
     pub async fn get_all_blocks(&self) -> Result<impl Reply> {
         let blocks: Vec<([u8;32], Block)> = self.block_state_management.get_all_entries();
         Ok(json(&blocks))
     }
-}
\ No newline at end of file
+
+    /// Proves the account against the stored tree layers of a block, without rebuilding the
+    /// tree. Blocks not in the Merkle index, from before it existed, need a reindex first.
+    pub async fn get_account_proof(&self, account_id: &str, query: AccountProofQuery) -> Result<impl Reply> {
+        let address = match Pubkey::from_str(account_id) {
+            Ok(pubkey) => pubkey.to_bytes(),
+            Err(_) => return Ok(ApiError::invalid_request(format!("Invalid account id: {:?}", account_id)).into_response()),
+        };
+        let block_number = match query.block {
+            Some(block_number) => block_number,
+            None => match merkle_index::latest_block_writing(&address) {
+                Some(block_number) => block_number,
+                None => return Ok(ApiError::not_found(format!("No block wrote account: {:?}", account_id)).into_response()),
+            },
+        };
+        let Some(index) = merkle_index::get(block_number) else {
+            return Ok(ApiError::not_found(format!("Block {} is not in the Merkle index", block_number)).into_response());
+        };
+        let Some(inclusion) = index.inclusion(&address) else {
+            return Ok(ApiError::not_found(format!("Block {} didn't write account: {:?}", block_number, account_id)).into_response());
+        };
+        Ok(json(&AccountProofResponse {
+            block_number,
+            address: account_id.to_string(),
+            root: hex::encode(inclusion.root),
+            leaf: hex::encode(index.layers[0][inclusion.leaf_index as usize]),
+            leaf_index: inclusion.leaf_index,
+            leaf_count: inclusion.leaf_count,
+            proof: inclusion.proof.iter().map(hex::encode).collect(),
+        }).into_response())
+    }
+
+    /// Proves `query.length` bytes of the account's data from `query.offset`. The account store
+    /// only holds the latest state, so the account must not have changed since the block.
+    pub async fn get_account_data_proof<A: ManageState<Record=AccountState>>(
+        &self,
+        account_id: &str,
+        query: AccountDataProofQuery,
+        account_state_management: &StateManager<A>,
+    ) -> Result<impl Reply> {
+        let address = match Pubkey::from_str(account_id) {
+            Ok(pubkey) => pubkey.to_bytes(),
+            Err(_) => return Ok(ApiError::invalid_request(format!("Invalid account id: {:?}", account_id)).into_response()),
+        };
+        let block_number = match query.block {
+            Some(block_number) => block_number,
+            None => match merkle_index::latest_block_writing(&address) {
+                Some(block_number) => block_number,
+                None => return Ok(ApiError::not_found(format!("No block wrote account: {:?}", account_id)).into_response()),
+            },
+        };
+        let Some(inclusion) = merkle_index::account_inclusion(block_number, &address) else {
+            return Ok(ApiError::not_found(format!("Block {} didn't write account {:?} or is not in the Merkle index", block_number, account_id)).into_response());
+        };
+        let Some(account) = account_state_management.get_state_record(&address) else {
+            return Ok(ApiError::not_found(format!("No account found for: {:?}", account_id)).into_response());
+        };
+        if !inclusion.verify(&account) {
+            return Ok(ApiError::unprocessable(format!("Account {:?} changed after block {}", account_id, block_number)).into_response());
+        }
+        let Some(data_root) = account.data_root else {
+            return Ok(ApiError::unprocessable(format!("Account {:?} has no data commitment, prove the whole account instead", account_id)).into_response());
+        };
+        let Some(data_proof) = DataRangeProof::build(&account.data, query.offset, query.length) else {
+            return Ok(ApiError::invalid_request(format!("Range {}+{} is outside the {} bytes of data", query.offset, query.length, account.data.len())).into_response());
+        };
+
+        Ok(json(&AccountDataProofResponse {
+            block_number,
+            account: account.without_data(),
+            root: hex::encode(inclusion.root),
+            leaf_index: inclusion.leaf_index,
+            leaf_count: inclusion.leaf_count,
+            proof: inclusion.proof.iter().map(hex::encode).collect(),
+            data_root: hex::encode(data_root),
+            data_len: data_proof.data_len,
+            first_chunk: data_proof.first_chunk,
+            chunks: data_proof.chunks.iter().map(hex::encode).collect(),
+            chunk_proof: data_proof.proof.iter().map(hex::encode).collect(),
+        }).into_response())
+    }
+
+    /// Recomputes the public inputs of the block's proof, see `recompute_public_inputs`.
+    pub async fn get_block_public_inputs<A: ManageState<Record=AccountState>>(
+        &self,
+        block_id: u64,
+        account_state_management: &StateManager<A>,
+        verifier: &BlockVerifier,
+    ) -> Result<impl Reply> {
+        let Some(block) = self.block_state_management.get_state_record(&Block::get_id(block_id)) else {
+            return Ok(ApiError::not_found(format!("No block found for: {:?}", block_id)).into_response());
+        };
+        if block.is_heartbeat() {
+            return Ok(ApiError::not_found(format!("Block {} is a heartbeat block and has no proof", block_id)).into_response());
+        }
+        let RecomputedInputs { parent_root, public_inputs, changed_accounts } = match recompute_public_inputs(&block, &self.block_state_management, account_state_management) {
+            Ok(recomputed) => recomputed,
+            Err(parent) => return Ok(ApiError::not_found(format!("No block found for: {:?}", parent)).into_response()),
+        };
+        let prepared = public_inputs
+            .zip(verifier.prepared_verifying_key())
+            .and_then(|(public_inputs, prepared_verifying_key)| public_inputs.prepare(prepared_verifying_key).ok());
+
+        Ok(json(&BlockPublicInputsResponse {
+            block_number: block.block_number,
+            parent_root: hex::encode(parent_root),
+            transactions_root: hex::encode(*block.transactions_merkle_root),
+            public_inputs: public_inputs.map(|public_inputs| public_inputs.to_bytes().iter().map(hex::encode).collect()),
+            lamports_sum: public_inputs.map(|public_inputs| public_inputs.lamports_sum),
+            changed_accounts,
+            prepared: prepared.map(hex::encode),
+            stored: hex::encode(&block.accounts_zk_public_inputs),
+            matches: prepared.map(|prepared| prepared[..] == block.accounts_zk_public_inputs[..]),
+        }).into_response())
+    }
+}
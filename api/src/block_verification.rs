@@ -0,0 +1,133 @@
+use ark_bn254::Bn254;
+use ark_groth16::{prepare_verifying_key, PreparedVerifyingKey};
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use state::account_state::AccountState;
+use state::block::Block;
+use state::witness::account_leaf;
+use state_management::state_management::{ManageState, StateManager};
+use std::sync::Arc;
+use trollup_zk::prove::load_verifying_key;
+use trollup_zk::public_inputs::{compute_public_inputs, PublicInputs};
+use trollup_zk::verify::verify_serialized;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase", tag = "status", content = "reason")]
+pub enum BlockVerification {
+    Verified,
+    /// The block's hash or proof doesn't check out
+    Invalid(String),
+    /// The block can't be checked by this node, e.g. its accounts changed since
+    Unverifiable(String),
+}
+
+/// A block's public inputs recomputed from its roots and the accounts it wrote.
+pub struct RecomputedInputs {
+    /// Accounts root of the parent block, all zeros for the first block
+    pub parent_root: [u8; 32],
+    /// `None` when one of the block's accounts changed after it
+    pub public_inputs: Option<PublicInputs>,
+    /// Base58 addresses of the block's accounts that changed after it
+    pub changed_accounts: Vec<String>,
+}
+
+/// Recomputes the public inputs of `block` with `compute_public_inputs`, from its roots, its
+/// parent's accounts root and the accounts it wrote. The account store only holds the latest
+/// state, so this works while none of the block's accounts changed after it, which is checked
+/// against the block's account leaves. Returns the number of the parent block when it is missing.
+pub fn recompute_public_inputs<B: ManageState<Record=Block>, A: ManageState<Record=AccountState>>(
+    block: &Block,
+    block_state_management: &StateManager<B>,
+    account_state_management: &StateManager<A>,
+) -> Result<RecomputedInputs, u64> {
+    let parent_root = match block.block_number {
+        0 | 1 => [0u8; 32],
+        block_number => match block_state_management.get_state_record(&Block::get_id(block_number - 1)) {
+            Some(parent) => *parent.accounts_merkle_root,
+            None => return Err(block_number - 1),
+        },
+    };
+
+    let mut accounts = Vec::with_capacity(block.accounts.len());
+    let mut changed_accounts = Vec::new();
+    for (index, address) in block.accounts.iter().enumerate() {
+        let account = account_state_management
+            .get_state_record(address)
+            .filter(|account| block.account_leaves.get(index) == Some(&account_leaf(account)));
+        match account {
+            Some(account) => accounts.push(account),
+            None => changed_accounts.push(Pubkey::new_from_array(*address).to_string()),
+        }
+    }
+    let public_inputs = changed_accounts
+        .is_empty()
+        .then(|| compute_public_inputs(&accounts, &parent_root, &block.transactions_merkle_root, &block.accounts_merkle_root));
+    Ok(RecomputedInputs { parent_root, public_inputs, changed_accounts })
+}
+
+/// Re-verifies stored blocks: the block hash against its roots and the stored
+/// `accounts_zk_proof` against public inputs recomputed from the block's roots and accounts with
+/// the node's verifying key. The public inputs the block stores are never trusted, a block whose
+/// stored inputs differ from the recomputed ones is invalid.
+#[derive(Clone)]
+pub struct BlockVerifier {
+    prepared_verifying_key: Option<Arc<PreparedVerifyingKey<Bn254>>>,
+}
+
+impl BlockVerifier {
+    /// Uses the verifying key in `vk.bin`. Without it blocks can still be checked against their
+    /// roots, but proofs are reported as unverifiable.
+    pub fn load() -> Self {
+        let prepared_verifying_key = match load_verifying_key() {
+            Ok(verifying_key) => Some(Arc::new(prepare_verifying_key(&verifying_key))),
+            Err(error) => {
+                warn!("Block proofs can't be verified, failed to load vk.bin: {}", error);
+                None
+            }
+        };
+        BlockVerifier { prepared_verifying_key }
+    }
+
+    /// The node's verifying key, `None` when `vk.bin` couldn't be loaded.
+    pub fn prepared_verifying_key(&self) -> Option<&PreparedVerifyingKey<Bn254>> {
+        self.prepared_verifying_key.as_deref()
+    }
+
+    pub fn verify<B: ManageState<Record=Block>, A: ManageState<Record=AccountState>>(
+        &self,
+        block: &Block,
+        block_state_management: &StateManager<B>,
+        account_state_management: &StateManager<A>,
+    ) -> BlockVerification {
+        if !block.has_consistent_hash() {
+            return BlockVerification::Invalid("Block hash doesn't match the block's roots".to_string());
+        }
+        // Nothing to prove, the hash already ties the block to its parent's accounts root
+        if block.is_heartbeat() {
+            return BlockVerification::Verified;
+        }
+        let public_inputs = match recompute_public_inputs(block, block_state_management, account_state_management) {
+            Ok(RecomputedInputs { public_inputs: Some(public_inputs), .. }) => public_inputs,
+            Ok(RecomputedInputs { changed_accounts, .. }) => {
+                return BlockVerification::Unverifiable(format!("Public inputs can't be recomputed, accounts changed since: {}", changed_accounts.join(", ")));
+            }
+            Err(parent) => return BlockVerification::Unverifiable(format!("Parent block {} is missing", parent)),
+        };
+        let Some(prepared_verifying_key) = &self.prepared_verifying_key else {
+            return BlockVerification::Unverifiable("No verifying key loaded".to_string());
+        };
+        let prepared = match public_inputs.prepare(prepared_verifying_key) {
+            Ok(prepared) => prepared,
+            Err(error) => return BlockVerification::Invalid(format!("Public inputs can't be prepared: {}", error)),
+        };
+        if !block.accounts_zk_public_inputs.is_empty() && block.accounts_zk_public_inputs[..] != prepared[..] {
+            return BlockVerification::Invalid("Stored public inputs don't match the block's roots and accounts".to_string());
+        }
+        match verify_serialized(&block.accounts_zk_proof, &prepared, prepared_verifying_key) {
+            Ok(true) => BlockVerification::Verified,
+            Ok(false) => BlockVerification::Invalid("Proof doesn't verify".to_string()),
+            Err(error) => BlockVerification::Invalid(format!("Malformed proof: {}", error)),
+        }
+    }
+}
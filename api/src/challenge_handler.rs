@@ -0,0 +1,169 @@
+use base64::{engine::general_purpose, Engine as _};
+use execution::transaction_pool::TransactionPool;
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+use server::error::ApiError;
+use sha2::{Digest, Sha256};
+use solana_sdk::hash::Hash;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+use state::account_state::AccountState;
+use state::challenge::{bond_transfer, BondState, Challenge, ChallengeStatus};
+use state::config::TrollupConfig;
+use state::state_record::{StateCommitmentPackage, StateRecord};
+use state::transaction::convert_to_trollup_transaction;
+use state_commitment::{challenges, transaction_index};
+use state_management::state_management::{ManageState, StateManager};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use warp::{reply::json, Rejection, Reply};
+
+type Result<T> = std::result::Result<T, Rejection>;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostChallengeRequest {
+    /// URL-safe base64 state root of the pending commitment, as in `/get-pending-commitments`
+    pub state_root: String,
+    /// Signed system transfer of at least `CHALLENGE_BOND_LAMPORTS` to the sequencer
+    pub transaction: Transaction,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengeView {
+    /// Hex encoded, used to claim the challenge
+    pub id: String,
+    pub state_root: String,
+    pub challenger: String,
+    pub status: ChallengeStatus,
+    pub bond_lamports: u64,
+    pub bond_signature: String,
+    pub reward_lamports: u64,
+    pub payout_lamports: u64,
+    pub posted_at: u64,
+    pub resolved_in_block: Option<u64>,
+    pub payout_signature: Option<String>,
+}
+
+impl From<&Challenge> for ChallengeView {
+    fn from(challenge: &Challenge) -> Self {
+        ChallengeView {
+            id: hex::encode(challenge.id),
+            state_root: general_purpose::URL_SAFE.encode(challenge.state_root),
+            challenger: challenge.challenger.to_string(),
+            status: challenge.status,
+            bond_lamports: challenge.bond_lamports,
+            bond_signature: challenge.bond_signature.clone(),
+            reward_lamports: challenge.reward_lamports,
+            payout_lamports: challenge.payout(),
+            posted_at: challenge.posted_at,
+            resolved_in_block: challenge.resolved_in_block,
+            payout_signature: challenge.payout_signature.clone(),
+        }
+    }
+}
+
+pub struct ChallengeHandler<O: ManageState<Record=StateCommitmentPackage<AccountState>>> {
+    transaction_pool: Arc<Mutex<TransactionPool>>,
+    optimistic_commitment_state_management: Arc<StateManager<O>>,
+}
+
+impl<O: ManageState<Record=StateCommitmentPackage<AccountState>>> ChallengeHandler<O> {
+    pub fn new(transaction_pool: Arc<Mutex<TransactionPool>>, optimistic_commitment_state_management: Arc<StateManager<O>>) -> Self {
+        ChallengeHandler { transaction_pool, optimistic_commitment_state_management }
+    }
+
+    /// Challenges a pending commitment. The bond transfer is added to the pool like any other
+    /// transaction, a challenge only pays out once it was finalized.
+    pub async fn post_challenge(&self, request: PostChallengeRequest, request_id: &str) -> Result<impl Reply> {
+        let sequencer = match Keypair::from_bytes(&CONFIG.trollup_api_keypair) {
+            Ok(keypair) => keypair,
+            Err(_) => return Ok(ApiError::unavailable("Challenges are not available").into_response()),
+        };
+        let state_root: [u8; 32] = match general_purpose::URL_SAFE.decode(&request.state_root).ok().and_then(|bytes| bytes.try_into().ok()) {
+            Some(state_root) => state_root,
+            None => return Ok(ApiError::invalid_request(format!("Invalid state root: {:?}", request.state_root)).into_response()),
+        };
+        if self.optimistic_commitment_state_management.get_state_record(&state_root).is_none() {
+            return Ok(ApiError::not_found(format!("No pending commitment for: {:?}", request.state_root)).into_response());
+        }
+        if request.transaction.verify().is_err() {
+            return Ok(ApiError::invalid_transaction("Invalid bond transaction signature").into_response());
+        }
+        let (challenger, bond_lamports) = match bond_transfer(&request.transaction, &sequencer.pubkey(), CONFIG.challenge_bond_lamports) {
+            Ok(bond) => bond,
+            Err(message) => return Ok(ApiError::invalid_transaction(message).into_response()),
+        };
+
+        let bond_signature = request.transaction.signatures[0].to_string();
+        let trollup_transaction = match convert_to_trollup_transaction(request.transaction) {
+            Ok(trollup_transaction) => trollup_transaction,
+            Err(error) => return Ok(ApiError::invalid_transaction(format!("Invalid transaction: {}", error)).into_response()),
+        };
+        let posted_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let challenge = Challenge::new(state_root, challenger, bond_lamports, bond_signature, posted_at);
+        if let Err(message) = challenges::post(&challenge, &trollup_transaction.get_key()) {
+            return Ok(ApiError::conflict(message).into_response());
+        }
+        self.transaction_pool.lock().await.add_transaction_for_request(trollup_transaction, request_id);
+
+        Ok(json(&ChallengeView::from(&challenge)).into_response())
+    }
+
+    /// Pays out an upheld or void challenge from the sequencer's account. Anyone can claim, the
+    /// payout always goes to the challenger.
+    pub async fn claim_challenge(&self, id: &str) -> Result<impl Reply> {
+        let id: [u8; 32] = match hex::decode(id).ok().and_then(|bytes| bytes.try_into().ok()) {
+            Some(id) => id,
+            None => return Ok(ApiError::invalid_request(format!("Invalid challenge id: {}", id)).into_response()),
+        };
+        let sequencer = match Keypair::from_bytes(&CONFIG.trollup_api_keypair) {
+            Ok(keypair) => keypair,
+            Err(_) => return Ok(ApiError::unavailable("Challenges are not available").into_response()),
+        };
+
+        let mut pool = self.transaction_pool.lock().await;
+        let claimed = challenges::claim(&id, |challenge| {
+            let bond_signature = Signature::from_str(&challenge.bond_signature).map_err(|_| "Invalid bond signature".to_string())?;
+            let bond_key: [u8; 32] = Sha256::digest(bond_signature.as_ref()).into();
+            if transaction_index::locate(&bond_key).is_none() {
+                return Err("The bond transaction isn't finalized".to_string());
+            }
+            // Failed transactions are finalized too, only a transfer that went through bonds
+            match challenges::bond(&bond_key).map(|bond| bond.state) {
+                Some(BondState::Posted) => {}
+                Some(BondState::Failed) => return Err("The bond transfer failed, nothing was bonded".to_string()),
+                _ => return Err("The bond transaction wasn't executed".to_string()),
+            }
+            // The challenge id as blockhash keeps payouts of equal amounts to the same challenger apart
+            let payout = Transaction::new_signed_with_payer(
+                &[system_instruction::transfer(&sequencer.pubkey(), &challenge.challenger, challenge.payout())],
+                Some(&sequencer.pubkey()),
+                &[&sequencer],
+                Hash::new_from_array(challenge.id),
+            );
+            let signature = payout.signatures[0].to_string();
+            let trollup_transaction = convert_to_trollup_transaction(payout).map_err(|error| format!("Invalid payout transaction: {}", error))?;
+            pool.add_transaction(trollup_transaction);
+            Ok(signature)
+        });
+
+        match claimed {
+            Ok(challenge) => Ok(json(&ChallengeView::from(&challenge)).into_response()),
+            Err(message) => Ok(ApiError::unprocessable(message).into_response()),
+        }
+    }
+
+    pub async fn get_challenges(&self) -> Result<impl Reply> {
+        let challenges: Vec<ChallengeView> = challenges::all().iter().map(ChallengeView::from).collect();
+        Ok(json(&challenges))
+    }
+}
@@ -0,0 +1,238 @@
+use crate::sequencing_feed;
+use borsh::BorshDeserialize;
+use execution::transaction_pool::TransactionPool;
+use lazy_static::lazy_static;
+use log::info;
+use server::error::ApiError;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::{Transaction, VersionedTransaction};
+use solana_transaction_status::UiTransactionEncoding;
+use state::block::Block;
+use state::config::TrollupConfig;
+use state::cross_rollup::{CrossRollupClaim, DeliveredMessage, OutboxInclusion};
+use state::state_record::{ChainedCommitment, Ed25519Commitment, ZkProofCommitment};
+use state::transaction::convert_to_trollup_transaction;
+use state_management::state_management::{ManageState, StateManager};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use trollup_commitment_message::BlockProducer;
+use warp::{reply::json, Rejection, Reply};
+
+type Result<T> = std::result::Result<T, Rejection>;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+}
+
+/// Instructions of the signature verifier program, only used to decode the commitments of an L1
+/// transaction.
+#[derive(BorshDeserialize)]
+enum SignatureVerifierInstruction {
+    #[allow(dead_code)]
+    Initialize,
+    VerifySig(ZkProofCommitment),
+    VerifySigBatch(Vec<ChainedCommitment>),
+    VerifyEd25519(Ed25519Commitment),
+}
+
+/// The parts of a commitment claims and backfills are checked against, from any of the
+/// instructions.
+pub(crate) struct SignedCommitment {
+    /// 65 bytes for secp256k1 commitments, 32 for ed25519
+    pub public_key: Vec<u8>,
+    pub new_state_root: [u8; 32],
+    pub sequence: u64,
+    pub outbox_root: [u8; 32],
+    pub producer: BlockProducer,
+}
+
+/// Serves this rollup's outbox claims and delivers claims from other rollups.
+pub struct CrossRollupHandler<B: ManageState<Record=Block>, D: ManageState<Record=DeliveredMessage>> {
+    transaction_pool: Arc<Mutex<TransactionPool>>,
+    block_state_management: Arc<StateManager<B>>,
+    inbox_state_management: Arc<StateManager<D>>,
+}
+
+impl<B: ManageState<Record=Block>, D: ManageState<Record=DeliveredMessage>> CrossRollupHandler<B, D> {
+    pub fn new(transaction_pool: Arc<Mutex<TransactionPool>>, block_state_management: Arc<StateManager<B>>, inbox_state_management: Arc<StateManager<D>>) -> Self {
+        CrossRollupHandler { transaction_pool, block_state_management, inbox_state_management }
+    }
+
+    /// The claim for message `index` of block `block_number`, to be posted to the destination's
+    /// inbox.
+    pub async fn get_outbox_claim(&self, block_number: u64, index: usize) -> Result<impl Reply> {
+        let Some(block) = self.block_state_management.get_state_record(&Block::get_id(block_number)) else {
+            return Ok(ApiError::not_found(format!("Block not found: {}", block_number)).into_response());
+        };
+        let Some(l1_signature) = block.l1_anchor.signature.clone() else {
+            return Ok(ApiError::unprocessable("Block has no validator commitment on L1, its messages can't be claimed").into_response());
+        };
+        let Some(inclusion) = OutboxInclusion::new(block_number, &block.outbox_messages, index) else {
+            return Ok(ApiError::not_found(format!("Block {} has no outbox message {}", block_number, index)).into_response());
+        };
+
+        Ok(json(&CrossRollupClaim {
+            source: CONFIG.rollup_id.clone(),
+            message: block.outbox_messages[index].clone(),
+            inclusion,
+            l1_signature,
+        }).into_response())
+    }
+
+    /// Checks the claim against the source's L1 commitment and pays the message out to its
+    /// recipient with a transfer from the sequencer.
+    pub async fn receive_claim(&self, claim: CrossRollupClaim, request_id: &str) -> Result<impl Reply> {
+        if claim.message.destination != CONFIG.rollup_id {
+            return Ok(ApiError::invalid_request(format!("Message is addressed to: {}", claim.message.destination)).into_response());
+        }
+        let Some((program_id, public_key)) = CONFIG.cross_rollup_sources.get(&claim.source).and_then(|source| parse_source(source)) else {
+            return Ok(ApiError::invalid_request(format!("Unknown source rollup: {}", claim.source)).into_response());
+        };
+        let sequencer = match Keypair::from_bytes(&CONFIG.trollup_api_keypair) {
+            Ok(keypair) => keypair,
+            Err(_) => return Ok(ApiError::unavailable("Cross-rollup delivery is not available").into_response()),
+        };
+
+        // The pool lock serializes deliveries so the same claim can't be delivered twice
+        let mut pool = self.transaction_pool.lock().await;
+        let key = DeliveredMessage::key(&claim.source, &claim.message.address);
+        if self.inbox_state_management.get_state_record(&key).is_some() {
+            return Ok(ApiError::conflict("Message was already delivered").into_response());
+        }
+        if !claim.inclusion.verify(&claim.message) {
+            return Ok(ApiError::invalid_request("Message is not included in the outbox root").into_response());
+        }
+        if let Err(message) = verify_l1_commitment(&claim, &program_id, &public_key).await {
+            return Ok(ApiError::unprocessable(message).into_response());
+        }
+
+        let mut transfer = Transaction::new_with_payer(
+            &[system_instruction::transfer(&sequencer.pubkey(), &claim.message.recipient, claim.message.lamports)],
+            Some(&sequencer.pubkey()),
+        );
+        // The message leaf stands in for a blockhash, making every delivery a distinct transaction
+        transfer.sign(&[&sequencer], Hash::new_from_array(claim.message.leaf()));
+        let delivery_signature = transfer.signatures[0].to_string();
+        let trollup_transaction = match convert_to_trollup_transaction(transfer) {
+            Ok(trollup_transaction) => trollup_transaction,
+            Err(error) => return Ok(ApiError::internal(format!("Failed to build delivery: {}", error)).into_response()),
+        };
+
+        let delivered = DeliveredMessage {
+            source: claim.source.clone(),
+            address: claim.message.address,
+            block_number: claim.inclusion.block_number,
+            delivery_signature,
+            payload: claim.message.payload.clone(),
+        };
+        self.inbox_state_management.set_state_record(&delivered);
+        sequencing_feed::append(&trollup_transaction);
+        pool.add_transaction_for_request(trollup_transaction, request_id);
+        info!("Delivered message {} from {} for request {}", delivered.address, delivered.source, request_id);
+
+        Ok(json(&delivered).into_response())
+    }
+
+    pub async fn get_all_delivered(&self) -> Result<impl Reply> {
+        let delivered: Vec<DeliveredMessage> = self.inbox_state_management
+            .get_all_entries()
+            .into_iter()
+            .map(|(_, message)| message)
+            .collect();
+        Ok(json(&delivered))
+    }
+}
+
+/// Splits a `CROSS_ROLLUP_SOURCES` value, `<signature verifier program id>:<validator public key hex>`.
+/// The key is a 65 byte secp256k1 or a 32 byte ed25519 key.
+fn parse_source(source: &str) -> Option<(Pubkey, Vec<u8>)> {
+    let (program_id, public_key) = source.split_once(':')?;
+    let program_id = Pubkey::from_str(program_id.trim()).ok()?;
+    let public_key = hex::decode(public_key.trim()).ok()?;
+    if public_key.len() != 65 && public_key.len() != 32 {
+        return None;
+    }
+    Some((program_id, public_key))
+}
+
+/// Finds the source's signature verifier instruction in the claimed L1 transaction and checks
+/// that the configured validator signed the claimed block's outbox root.
+async fn verify_l1_commitment(claim: &CrossRollupClaim, program_id: &Pubkey, public_key: &[u8]) -> std::result::Result<(), String> {
+    let signature = Signature::from_str(&claim.l1_signature).map_err(|_| "Invalid L1 signature".to_string())?;
+    let url = CONFIG.rpc_urls.get(&CONFIG.solana_environment)
+        .ok_or_else(|| format!("No RPC url configured for {}", CONFIG.solana_environment))?;
+    let client = RpcClient::new(url.to_string());
+    let transaction = client
+        .get_transaction(&signature, UiTransactionEncoding::Base64)
+        .await
+        .map_err(|error| format!("Failed to get L1 transaction: {}", error))?;
+    if transaction.transaction.meta.as_ref().map_or(true, |meta| meta.err.is_some()) {
+        return Err("L1 transaction did not succeed".to_string());
+    }
+    let decoded = transaction.transaction.transaction.decode()
+        .ok_or_else(|| "Failed to decode L1 transaction".to_string())?;
+
+    // A batched transaction commits several blocks, the claim's block is the one with its sequence
+    let commitments = signed_commitments(&decoded, program_id);
+    if commitments.is_empty() {
+        return Err("L1 transaction has no signature verifier commitment".to_string());
+    }
+    let commitment = commitments
+        .iter()
+        .find(|commitment| commitment.sequence == claim.inclusion.block_number)
+        .unwrap_or(&commitments[0]);
+
+    if commitment.public_key != public_key {
+        return Err("L1 commitment was signed by an unknown validator".to_string());
+    }
+    if commitment.sequence != claim.inclusion.block_number {
+        return Err("L1 commitment is for a different block".to_string());
+    }
+    if commitment.outbox_root != claim.inclusion.outbox_root {
+        return Err("Outbox root doesn't match the L1 commitment".to_string());
+    }
+    Ok(())
+}
+
+/// The commitments of `program_id`, a signature verifier, in an L1 transaction. A batched
+/// transaction holds several, in sequence order.
+pub(crate) fn signed_commitments(transaction: &VersionedTransaction, program_id: &Pubkey) -> Vec<SignedCommitment> {
+    let account_keys = transaction.message.static_account_keys();
+    transaction.message.instructions()
+        .iter()
+        .filter(|instruction| account_keys.get(instruction.program_id_index as usize) == Some(program_id))
+        .flat_map(|instruction| match SignatureVerifierInstruction::try_from_slice(&instruction.data) {
+            Ok(SignatureVerifierInstruction::VerifySig(commitment)) => vec![SignedCommitment {
+                public_key: commitment.public_key.to_vec(),
+                new_state_root: commitment.new_state_root,
+                sequence: commitment.sequence,
+                outbox_root: commitment.outbox_root,
+                producer: commitment.producer,
+            }],
+            Ok(SignatureVerifierInstruction::VerifySigBatch(commitments)) => commitments
+                .into_iter()
+                .map(|commitment| SignedCommitment {
+                    public_key: commitment.public_key.to_vec(),
+                    new_state_root: commitment.new_state_root,
+                    sequence: commitment.sequence,
+                    outbox_root: commitment.outbox_root,
+                    producer: commitment.producer,
+                })
+                .collect(),
+            // The program only accepts it after the ed25519 program verified the signature
+            Ok(SignatureVerifierInstruction::VerifyEd25519(commitment)) => vec![SignedCommitment {
+                public_key: commitment.public_key.to_vec(),
+                new_state_root: commitment.new_state_root,
+                sequence: commitment.sequence,
+                outbox_root: commitment.outbox_root,
+                producer: commitment.producer,
+            }],
+            _ => vec![],
+        })
+        .collect()
+}
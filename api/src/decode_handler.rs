@@ -0,0 +1,177 @@
+use base64::{engine::general_purpose, Engine as _};
+use execution::cost_model::CostModel;
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_sdk::bs58;
+use solana_sdk::sanitize::Sanitize;
+use solana_sdk::transaction::Transaction;
+use state::config::TrollupConfig;
+use state::transaction::{convert_to_sanitized_transaction, convert_to_solana_transaction, convert_to_trollup_transaction, TrollupTransaction};
+use state::views::TransactionView;
+use warp::{reply::json, Rejection, Reply};
+
+type Result<T> = std::result::Result<T, Rejection>;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref COST_MODEL: CostModel = CostModel::from_config(&CONFIG);
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PayloadEncoding {
+    #[default]
+    Base64,
+    Base58,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PayloadFormat {
+    /// A Solana transaction as web3 libraries and the CLI serialize it
+    #[default]
+    Wire,
+    /// A borsh `TrollupTransaction`, as stored by the node and exported by `/export/transactions`
+    Borsh,
+}
+
+/// Body of `/decode-transaction`: either an encoded payload or the JSON `/send-transaction` takes.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodeTransactionRequest {
+    #[serde(default)]
+    pub encoded: Option<String>,
+    #[serde(default)]
+    pub encoding: PayloadEncoding,
+    #[serde(default)]
+    pub format: PayloadFormat,
+    /// Used when `encoded` is missing
+    #[serde(default)]
+    pub transaction: Option<Value>,
+}
+
+/// The steps a submission goes through before it is sequenced, in order.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum DecodeStage {
+    /// Base64 or base58 text to bytes
+    Decode,
+    /// Bytes or JSON to a transaction
+    Deserialize,
+    /// The signatures the message header asks for are present
+    Signatures,
+    /// Conversion to the rollup's transaction format
+    Convert,
+    /// The checks the execution engine runs before loading accounts
+    Sanitize,
+    /// Every signature verifies against the message
+    Verify,
+    /// The compute preflight of `/send-transaction`
+    CostModel,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum DecodeTransactionResponse {
+    /// The transaction passed every stage
+    #[serde(rename_all = "camelCase")]
+    Decoded {
+        transaction: TransactionView,
+        estimated_compute_units: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    Failed {
+        stage: DecodeStage,
+        reason: String,
+        /// As far as it could be decoded before the failing stage
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transaction: Option<TransactionView>,
+    },
+}
+
+/// Runs `request` through the conversion pipeline of `/send-transaction` without submitting it,
+/// stopping at the first stage that fails.
+pub fn decode_transaction(request: DecodeTransactionRequest) -> DecodeTransactionResponse {
+    let failed = |stage: DecodeStage, reason: String, transaction: Option<&Transaction>| DecodeTransactionResponse::Failed {
+        stage,
+        reason,
+        transaction: transaction
+            .and_then(|transaction| convert_to_trollup_transaction(transaction.clone()).ok())
+            .map(|transaction| TransactionView::from(&transaction)),
+    };
+
+    let transaction = match (request.encoded, request.transaction) {
+        (Some(encoded), _) => {
+            let bytes = match request.encoding {
+                PayloadEncoding::Base64 => general_purpose::STANDARD.decode(encoded.trim()).map_err(|error| error.to_string()),
+                PayloadEncoding::Base58 => bs58::decode(encoded.trim()).into_vec().map_err(|error| error.to_string()),
+            };
+            let bytes = match bytes {
+                Ok(bytes) => bytes,
+                Err(error) => return failed(DecodeStage::Decode, format!("Not valid {:?}: {}", request.encoding, error), None),
+            };
+            let transaction = match request.format {
+                PayloadFormat::Wire => bincode::deserialize::<Transaction>(&bytes).map_err(|error| error.to_string()),
+                PayloadFormat::Borsh => borsh::from_slice::<TrollupTransaction>(&bytes)
+                    .map_err(|error| error.to_string())
+                    .and_then(|transaction| convert_to_solana_transaction(transaction).map_err(|error| error.to_string())),
+            };
+            match transaction {
+                Ok(transaction) => transaction,
+                Err(error) => return failed(DecodeStage::Deserialize, format!("{} bytes are not a {:?} transaction: {}", bytes.len(), request.format, error), None),
+            }
+        }
+        (None, Some(value)) => match serde_json::from_value::<Transaction>(value) {
+            Ok(transaction) => transaction,
+            Err(error) => return failed(DecodeStage::Deserialize, format!("Not a transaction: {}", error), None),
+        },
+        (None, None) => return failed(DecodeStage::Decode, "Either encoded or transaction is required".to_string(), None),
+    };
+
+    let required = transaction.message.header.num_required_signatures as usize;
+    if transaction.signatures.is_empty() {
+        return failed(DecodeStage::Signatures, "Transaction must be signed".to_string(), Some(&transaction));
+    }
+    if transaction.signatures.len() != required {
+        let reason = format!("The message header requires {} signatures but the transaction has {}", required, transaction.signatures.len());
+        return failed(DecodeStage::Signatures, reason, Some(&transaction));
+    }
+
+    let trollup_transaction = match convert_to_trollup_transaction(transaction.clone()) {
+        Ok(trollup_transaction) => trollup_transaction,
+        Err(error) => return failed(DecodeStage::Convert, error.to_string(), Some(&transaction)),
+    };
+
+    // The message checks name the offending index or value, which the sanitized transaction's
+    // errors don't
+    if let Err(error) = transaction.sanitize() {
+        return failed(DecodeStage::Sanitize, format!("Invalid message: {}", error), Some(&transaction));
+    }
+    if let Err(error) = convert_to_sanitized_transaction(&trollup_transaction) {
+        return failed(DecodeStage::Sanitize, error.to_string(), Some(&transaction));
+    }
+
+    let invalid: Vec<String> = transaction
+        .verify_with_results()
+        .iter()
+        .zip(&transaction.message.account_keys)
+        .filter(|(verified, _)| !**verified)
+        .map(|(_, signer)| signer.to_string())
+        .collect();
+    if !invalid.is_empty() {
+        return failed(DecodeStage::Verify, format!("Signatures don't verify for: {}", invalid.join(", ")), Some(&transaction));
+    }
+
+    match COST_MODEL.check(&trollup_transaction) {
+        Ok(estimated_compute_units) => DecodeTransactionResponse::Decoded {
+            transaction: TransactionView::from(&trollup_transaction),
+            estimated_compute_units,
+        },
+        Err(reason) => failed(DecodeStage::CostModel, reason, Some(&transaction)),
+    }
+}
+
+pub async fn decode_transaction_handler(request: DecodeTransactionRequest) -> Result<impl Reply> {
+    Ok(json(&decode_transaction(request)))
+}
@@ -0,0 +1,87 @@
+use serde_derive::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use state::account_state::AccountState;
+use state::checkpoint::{self, Checkpoint};
+use state::config::TrollupConfig;
+use state_commitment::vault_reconciliation;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::StateManager;
+use std::str::FromStr;
+
+/// What a user needs to build the vault's `EmergencyWithdrawal` for their account: the account
+/// as the checkpoint's account set committed it and its proof against the account set root last
+/// posted to the vault. Hashes are hex encoded. The account's fields are those of the vault's
+/// `RollupAccount`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EscapeProof {
+    /// Checkpoint epoch of the root
+    pub epoch: u64,
+    /// Last block of the epoch, the account set is the state after it
+    pub block_number: u64,
+    pub root: String,
+    pub account: AccountState,
+    pub leaf_index: u64,
+    pub leaf_count: u64,
+    pub proof: Vec<String>,
+}
+
+/// Builds the escape proof of `address` against the checkpoint whose account set root the vault
+/// holds, falling back to the latest checkpoint when the RPC can't be reached.
+///
+/// The account database only holds the latest state, so the proof can only be built while every
+/// account is as the checkpoint left it. That is the case once the rollup stopped, which is when
+/// the escape hatch opens.
+pub async fn escape_proof(config: &TrollupConfig, address: &str) -> Result<EscapeProof, String> {
+    let address = Pubkey::from_str(address).map_err(|_| format!("Invalid account id: {}", address))?;
+    let checkpoint_state_manager = StateManager::<SledStateManagement<Checkpoint>>::new(&config.checkpoint_db_path);
+    let account_state_manager = StateManager::<SledStateManagement<AccountState>>::new(&config.account_state_manager_db_path);
+
+    let checkpoint = match posted_epoch(config).await {
+        Some(0) => return Err("No account set root was posted to the vault".to_string()),
+        Some(epoch) => checkpoint_state_manager
+            .get_state_record(&Checkpoint::get_id(epoch))
+            .ok_or_else(|| format!("Checkpoint {}, the last posted to the vault, is not in this data directory", epoch))?,
+        None => checkpoint_state_manager
+            .get_all_entries()
+            .into_iter()
+            .map(|(_, checkpoint)| checkpoint)
+            .max_by_key(|checkpoint| checkpoint.epoch)
+            .ok_or("No checkpoint was produced")?,
+    };
+
+    let accounts: Vec<AccountState> = account_state_manager
+        .get_all_entries()
+        .into_iter()
+        .map(|(_, account)| account)
+        .collect();
+    if checkpoint::account_set_root(&accounts) != checkpoint.account_set_root {
+        return Err(format!("Accounts changed after block {}, the state of checkpoint {} is no longer stored", checkpoint.last_block, checkpoint.epoch));
+    }
+    let inclusion = checkpoint::account_set_inclusion(checkpoint.last_block, &accounts, &address)
+        .ok_or_else(|| format!("No account found for: {}", address))?;
+    let account = accounts
+        .into_iter()
+        .find(|account| account.address == address)
+        .ok_or_else(|| format!("No account found for: {}", address))?;
+
+    Ok(EscapeProof {
+        epoch: checkpoint.epoch,
+        block_number: checkpoint.last_block,
+        root: hex::encode(inclusion.root),
+        leaf_index: inclusion.leaf_index,
+        leaf_count: inclusion.leaf_count,
+        proof: inclusion.proof.iter().map(hex::encode).collect(),
+        // The leaf of an account with a data root doesn't cover the data, the claim can leave it out
+        account: if account.data_root.is_some() { account.without_data() } else { account },
+    })
+}
+
+/// Epoch of the account set root the vault holds.
+async fn posted_epoch(config: &TrollupConfig) -> Option<u64> {
+    let vault_program_id = Pubkey::from_str(&config.vault_program_id).ok()?;
+    let rpc_client = RpcClient::new_with_commitment(config.rpc_url_current_env().to_string(), CommitmentConfig::finalized());
+    vault_reconciliation::posted_account_set_epoch(&rpc_client, &vault_program_id).await.ok()
+}
@@ -0,0 +1,91 @@
+use borsh::to_vec;
+use log::warn;
+use serde::Serialize;
+use serde_derive::Deserialize;
+use state_management::state_management::{ManageState, StateManager};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use warp::http::header::{HeaderValue, CONTENT_TYPE};
+use warp::hyper::Body;
+use warp::reply::Response;
+
+/// Encoded records buffered ahead of the client. Once it's full the database iterator waits for
+/// the client to catch up.
+const EXPORT_BUFFER_RECORDS: usize = 256;
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    /// One JSON document per line
+    #[default]
+    Ndjson,
+    /// Each record's borsh encoding prefixed with its length as a little endian u32
+    Borsh,
+}
+
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Borsh => "application/octet-stream",
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Streams every record of `state_manager` with chunked transfer encoding. Records are read
+/// from the database as the client consumes them, so an export holds at most
+/// `EXPORT_BUFFER_RECORDS` in memory however large the store is. `to_json` maps a record to
+/// its NDJSON line, records it returns `None` for are skipped; borsh exports the records as
+/// stored.
+pub fn stream<A, J, T>(state_manager: Arc<StateManager<A>>, format: ExportFormat, to_json: J) -> Response
+where
+    A: ManageState + Send + Sync + 'static,
+    J: Fn(A::Record) -> Option<T> + Send + 'static,
+    T: Serialize,
+{
+    let (sender, receiver) = mpsc::channel::<Result<Vec<u8>, Infallible>>(EXPORT_BUFFER_RECORDS);
+    // sled iterates synchronously, keep it off the async workers
+    tokio::task::spawn_blocking(move || {
+        for (key, record) in state_manager.iter_entries() {
+            let encoded = match format {
+                ExportFormat::Ndjson => to_json(record).map(|value| serde_json::to_vec(&value)),
+                ExportFormat::Borsh => Some(to_vec(&record)),
+            };
+            let mut encoded = match encoded {
+                Some(Ok(encoded)) => encoded,
+                Some(Err(error)) => {
+                    warn!("Skipping record {} in export: {}", hex::encode(key), error);
+                    continue;
+                }
+                None => continue,
+            };
+            let chunk = match format {
+                ExportFormat::Ndjson => {
+                    encoded.push(b'\n');
+                    encoded
+                }
+                ExportFormat::Borsh => {
+                    let mut chunk = (encoded.len() as u32).to_le_bytes().to_vec();
+                    chunk.append(&mut encoded);
+                    chunk
+                }
+            };
+            // Fails once the client disconnected, nobody is left to read the rest
+            if sender.blocking_send(Ok(chunk)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut response = Response::new(Body::wrap_stream(ReceiverStream::new(receiver)));
+    response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static(format.content_type()));
+    response
+}
@@ -0,0 +1,131 @@
+//! Routes of the governed parameters, see `state_commitment::governance`.
+
+use serde_derive::{Deserialize, Serialize};
+use server::error::ApiError;
+use solana_sdk::pubkey::Pubkey;
+use state::parameters::{GovernedParameters, ParameterProposal};
+use state_commitment::governance;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use warp::reply::{json, Response};
+use warp::{Rejection, Reply};
+
+type Result<T> = std::result::Result<T, Rejection>;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ParametersQuery {
+    /// The set in force at this block, the next block's when missing
+    pub block: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParametersView {
+    /// Hex hash, as blocks record it
+    pub hash: String,
+    pub parameters: GovernedParameters,
+}
+
+impl From<GovernedParameters> for ParametersView {
+    fn from(parameters: GovernedParameters) -> Self {
+        ParametersView { hash: hex::encode(parameters.hash()), parameters }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProposalView {
+    /// Hex encoded, what the governance signers sign
+    pub id: String,
+    /// Hex hash of the proposed parameters
+    pub parameters_hash: String,
+    pub parameters: GovernedParameters,
+    pub effective_block: u64,
+    /// Base58 keys of the signers that approved
+    pub approvals: Vec<String>,
+    pub proposed_at: u64,
+    /// Latest finalized block when the proposal was scheduled, `None` while it collects approvals
+    pub scheduled_at: Option<u64>,
+}
+
+impl From<&ParameterProposal> for ProposalView {
+    fn from(proposal: &ParameterProposal) -> Self {
+        ProposalView {
+            id: hex::encode(proposal.id),
+            parameters_hash: hex::encode(proposal.parameters.hash()),
+            parameters: proposal.parameters.clone(),
+            effective_block: proposal.effective_block,
+            approvals: proposal.approvals.iter().map(|approval| approval.signer.to_string()).collect(),
+            proposed_at: proposal.proposed_at,
+            scheduled_at: proposal.scheduled_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProposeParametersRequest {
+    pub parameters: GovernedParameters,
+    pub effective_block: u64,
+    /// Base58 key of the proposing governance signer, its signature counts as the first approval
+    pub signer: String,
+    /// Base58 signature of the signer over the proposal ID, see `ParameterProposal::id_for`
+    pub signature: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApproveProposalRequest {
+    pub signer: String,
+    pub signature: String,
+}
+
+pub async fn get_parameters_handler(query: ParametersQuery) -> Result<Response> {
+    let parameters = match query.block {
+        Some(block_number) => governance::effective(block_number),
+        None => governance::current(),
+    };
+    Ok(json(&ParametersView::from(parameters)).into_response())
+}
+
+pub async fn get_parameters_by_hash_handler(hash: String) -> Result<Response> {
+    let Some(hash) = hex::decode(&hash).ok().and_then(|hash| <[u8; 32]>::try_from(hash).ok()) else {
+        return Ok(ApiError::invalid_request("Invalid parameters hash").into_response());
+    };
+    match governance::by_hash(&hash) {
+        Some(parameters) => Ok(json(&ParametersView::from(parameters)).into_response()),
+        None => Ok(ApiError::not_found("No parameters with this hash were ever scheduled").into_response()),
+    }
+}
+
+pub async fn get_proposals_handler() -> Result<Response> {
+    let proposals: Vec<ProposalView> = governance::proposals().iter().map(ProposalView::from).collect();
+    Ok(json(&proposals).into_response())
+}
+
+pub async fn propose_parameters_handler(request: ProposeParametersRequest) -> Result<Response> {
+    let Ok(signer) = Pubkey::from_str(&request.signer) else {
+        return Ok(ApiError::invalid_request("Invalid signer").into_response());
+    };
+    let proposed_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or_default();
+    match governance::propose(request.parameters, request.effective_block, &signer, &request.signature, proposed_at) {
+        Ok(proposal) => Ok(json(&ProposalView::from(&proposal)).into_response()),
+        Err(message) => Ok(ApiError::unprocessable(message).into_response()),
+    }
+}
+
+pub async fn approve_proposal_handler(id: String, request: ApproveProposalRequest) -> Result<Response> {
+    let Some(id) = hex::decode(&id).ok().and_then(|id| <[u8; 32]>::try_from(id).ok()) else {
+        return Ok(ApiError::invalid_request("Invalid proposal ID").into_response());
+    };
+    let Ok(signer) = Pubkey::from_str(&request.signer) else {
+        return Ok(ApiError::invalid_request("Invalid signer").into_response());
+    };
+    if governance::get(&id).is_none() {
+        return Ok(ApiError::not_found("Proposal not found").into_response());
+    }
+    match governance::approve(&id, &signer, &request.signature) {
+        Ok(proposal) => Ok(json(&ProposalView::from(&proposal)).into_response()),
+        Err(message) => Ok(ApiError::unprocessable(message).into_response()),
+    }
+}
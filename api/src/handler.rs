@@ -1,24 +1,64 @@
+use crate::health::{HealthEvent, HealthEvents, Severity};
+use crate::load_shedding::SheddingThresholds;
+use crate::reputation::{Offence, Submitter};
+use crate::sequencing_feed::{self, SequenceEntryView};
+use execution::cost_model::CostModel;
 use execution::transaction_pool::TransactionPool;
 use lazy_static::lazy_static;
+use log::{info, warn};
 use solana_sdk::transaction::Transaction;
+use state::sequence_entry::SequenceEntry;
+use state::state_record::StateRecord;
 use state::transaction::convert_to_trollup_transaction;
+use std::collections::HashSet;
 use std::sync::{Arc};
 use serde_derive::{Deserialize, Serialize};
 use serde_json::json;
+use server::auth::constant_time_eq;
+use server::error::{ApiError, ErrorCode};
 use tokio::sync::Mutex;
 use utoipa_gen::{OpenApi, ToSchema};
-use warp::{http::StatusCode, reply::json, Filter, Rejection, Reply};
+use warp::{http::StatusCode, reply::json, reply::Response, Filter, Rejection, Reply};
 use state::config::TrollupConfig;
+use state_commitment::bundles;
+use state_commitment::fee_payer::{self, FeePayerBalance};
+use state_commitment::governance;
+use state_commitment::pipeline::{self, BatchStatus, Stage};
 
 type Result<T> = std::result::Result<T, Rejection>;
 
 lazy_static! {
     static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref COST_MODEL: CostModel = CostModel::from_config(&CONFIG);
+    static ref SHEDDING: SheddingThresholds = SheddingThresholds::from_config(&CONFIG);
 }
 // pub async fn send_transaction_handler(transaction: Transaction) -> Result<impl Reply> {
 //     Ok(json(&""))
 // }
 
+/// Snapshot of every stage a transaction passes through on its way to a finalized block.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineSnapshot {
+    pub transaction_pool: PoolSnapshot,
+    /// Operator transactions waiting in the system lane, also counted in `transaction_pool`
+    pub system_lane: usize,
+    pub executing: Vec<BatchStatus>,
+    pub awaiting_proof: Vec<BatchStatus>,
+    pub proving: Vec<BatchStatus>,
+    pub awaiting_validator: Vec<BatchStatus>,
+    pub awaiting_l1: Vec<BatchStatus>,
+    pub optimistic: Vec<BatchStatus>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolSnapshot {
+    pub count: usize,
+    /// How long the next transaction to execute has been waiting, in milliseconds
+    pub oldest_age_ms: Option<u64>,
+}
+
 pub struct Handler {
     transaction_pool: Arc<Mutex<TransactionPool>>,
 }
@@ -28,23 +68,226 @@ impl Handler {
         Handler { transaction_pool }
     }
     
-    pub async fn send_transaction_handler(&self, transaction: Transaction) -> Result<impl Reply> {
+    pub async fn send_transaction_handler(&self, transaction: Transaction, request_id: &str, submitter: &Submitter) -> Result<Response> {
+        if let Err(banned) = submitter.admit(&transaction) {
+            return Ok(banned);
+        }
+        if transaction.signatures.is_empty() {
+            submitter.offence(&transaction, Offence::InvalidSubmission);
+            return Ok(ApiError::invalid_transaction("Transaction must be signed").into_response());
+        }
         let mut pool = self.transaction_pool.lock().await;
-        let trollup_transaction = convert_to_trollup_transaction(transaction).unwrap();
-        pool.add_transaction(trollup_transaction);
-        Ok(json(&"Transaction submitted successfully"))
+        let trollup_transaction = match convert_to_trollup_transaction(transaction.clone()) {
+            Ok(trollup_transaction) => trollup_transaction,
+            Err(error) => {
+                submitter.offence(&transaction, Offence::InvalidSubmission);
+                return Ok(ApiError::invalid_transaction(format!("Invalid transaction: {}", error)).into_response());
+            }
+        };
+        if let Err(message) = COST_MODEL.check(&trollup_transaction) {
+            submitter.offence(&transaction, Offence::InvalidSubmission);
+            return Ok(ApiError::invalid_transaction(message).into_response());
+        }
+        if let Err(message) = SHEDDING.admit(&trollup_transaction) {
+            return Ok(overloaded(&message));
+        }
+        let sequence = sequencing_feed::append(&trollup_transaction);
+        pool.add_transaction_for_request(trollup_transaction, request_id);
+        Ok(submitted("Transaction submitted successfully", sequence.as_ref()).into_response())
     }
     
-    pub async fn send_transaction_optimistic_handler(&self, transaction: Transaction) -> Result<impl Reply> {
+    pub async fn send_transaction_optimistic_handler(&self, transaction: Transaction, request_id: &str, submitter: &Submitter) -> Result<Response> {
+        if let Err(banned) = submitter.admit(&transaction) {
+            return Ok(banned);
+        }
+        if transaction.signatures.is_empty() {
+            submitter.offence(&transaction, Offence::InvalidSubmission);
+            return Ok(ApiError::invalid_transaction("Transaction must be signed").into_response());
+        }
         let mut pool = self.transaction_pool.lock().await;
-        let mut trollup_transaction = convert_to_trollup_transaction(transaction).unwrap();
+        let mut trollup_transaction = match convert_to_trollup_transaction(transaction.clone()) {
+            Ok(trollup_transaction) => trollup_transaction,
+            Err(error) => {
+                submitter.offence(&transaction, Offence::InvalidSubmission);
+                return Ok(ApiError::invalid_transaction(format!("Invalid transaction: {}", error)).into_response());
+            }
+        };
         trollup_transaction.optimistic = true;
-        pool.add_transaction(trollup_transaction);
-        Ok(json(&"Optimistic transaction submitted successfully"))
+        if let Err(message) = COST_MODEL.check(&trollup_transaction) {
+            submitter.offence(&transaction, Offence::InvalidSubmission);
+            return Ok(ApiError::invalid_transaction(message).into_response());
+        }
+        if let Err(message) = SHEDDING.admit(&trollup_transaction) {
+            return Ok(overloaded(&message));
+        }
+        let sequence = sequencing_feed::append(&trollup_transaction);
+        pool.add_transaction_for_request(trollup_transaction, request_id);
+        Ok(submitted("Optimistic transaction submitted successfully", sequence.as_ref()).into_response())
+    }
+
+    /// Queues transactions that either all make it into a block or none does. They execute in
+    /// order in the same batch, if one fails the others are dropped with it.
+    pub async fn send_transaction_bundle_handler(&self, transactions: Vec<Transaction>, request_id: &str, submitter: &Submitter) -> Result<Response> {
+        let parameters = governance::current();
+        let max_transactions = parameters.max_bundle_transactions.min(parameters.transaction_batch_amount) as usize;
+        if transactions.is_empty() || transactions.len() > max_transactions {
+            return Ok(ApiError::invalid_request(format!("A bundle holds 1 to {} transactions", max_transactions)).into_response());
+        }
+        let mut trollup_transactions = Vec::with_capacity(transactions.len());
+        for transaction in transactions {
+            if let Err(banned) = submitter.admit(&transaction) {
+                return Ok(banned);
+            }
+            if transaction.signatures.is_empty() {
+                submitter.offence(&transaction, Offence::InvalidSubmission);
+                return Ok(ApiError::invalid_transaction("Transaction must be signed").into_response());
+            }
+            let trollup_transaction = match convert_to_trollup_transaction(transaction.clone()) {
+                Ok(trollup_transaction) => trollup_transaction,
+                Err(error) => {
+                    submitter.offence(&transaction, Offence::InvalidSubmission);
+                    return Ok(ApiError::invalid_transaction(format!("Invalid transaction: {}", error)).into_response());
+                }
+            };
+            if let Err(message) = COST_MODEL.check(&trollup_transaction) {
+                submitter.offence(&transaction, Offence::InvalidSubmission);
+                return Ok(ApiError::invalid_transaction(message).into_response());
+            }
+            if let Err(message) = SHEDDING.admit(&trollup_transaction) {
+                return Ok(overloaded(&message));
+            }
+            trollup_transactions.push(trollup_transaction);
+        }
+        let mut keys = HashSet::new();
+        if !trollup_transactions.iter().all(|transaction| keys.insert(transaction.get_key())) {
+            return Ok(ApiError::invalid_request("A bundle can't hold the same transaction twice").into_response());
+        }
+
+        let mut pool = self.transaction_pool.lock().await;
+        for transaction in &trollup_transactions {
+            sequencing_feed::append(transaction);
+        }
+        match pool.add_bundle(trollup_transactions, request_id) {
+            Ok(bundle_id) => Ok(json(&BundleSubmission {
+                message: "Bundle submitted successfully".to_string(),
+                bundle_id: hex::encode(bundle_id),
+            }).into_response()),
+            Err(message) => Ok(ApiError::conflict(message).into_response()),
+        }
+    }
+
+    /// Status of the bundle with hex ID `bundle_id`, as returned when it was submitted.
+    pub async fn get_bundle_status_handler(&self, bundle_id: &str) -> Result<impl Reply> {
+        let status = hex::decode(bundle_id)
+            .ok()
+            .and_then(|id| <[u8; 32]>::try_from(id).ok())
+            .and_then(|id| bundles::status(&id));
+        match status {
+            Some(status) => Ok(json(&status).into_response()),
+            None => Ok(ApiError::not_found("Bundle not found").into_response()),
+        }
+    }
+
+    /// Queues an operator maintenance transaction in the system lane. It skips the cost model and
+    /// executes without fees, so the caller has to present `SYSTEM_LANE_TOKEN` as a bearer token.
+    pub async fn send_system_transaction_handler(&self, transaction: Transaction, authorization: Option<String>, request_id: &str) -> Result<impl Reply> {
+        if CONFIG.system_lane_token.is_empty() {
+            return Ok(ApiError::not_found("System lane is disabled").into_response());
+        }
+        let presented = authorization.as_deref().and_then(|header| header.strip_prefix("Bearer ")).unwrap_or_default();
+        if !constant_time_eq(presented.as_bytes(), CONFIG.system_lane_token.as_bytes()) {
+            warn!(target: "audit", "Rejected system transaction for request {}, bad token", request_id);
+            return Ok(ApiError::new(ErrorCode::Unauthorized, "Invalid system lane token").into_response());
+        }
+        if transaction.signatures.is_empty() {
+            return Ok(ApiError::invalid_transaction("Transaction must be signed").into_response());
+        }
+        let signature = transaction.signatures[0];
+        let trollup_transaction = match convert_to_trollup_transaction(transaction) {
+            Ok(trollup_transaction) => trollup_transaction,
+            Err(error) => return Ok(ApiError::invalid_transaction(format!("Invalid transaction: {}", error)).into_response()),
+        };
+        let mut pool = self.transaction_pool.lock().await;
+        if pool.system_lane_size() >= CONFIG.system_lane_capacity {
+            return Ok(ApiError::unavailable("System lane is full").into_response());
+        }
+        sequencing_feed::append(&trollup_transaction);
+        if let Err(message) = pool.add_system_transaction(trollup_transaction, request_id) {
+            return Ok(ApiError::unavailable(message).into_response());
+        }
+        info!(target: "audit", "Queued system transaction {} for request {}", signature, request_id);
+        Ok(json(&"System transaction submitted successfully").into_response())
     }
 
-    pub async fn health_handler(&self) -> Result<impl Reply> {
-        Ok(StatusCode::OK)
+    pub async fn pipeline_handler(&self) -> Result<impl Reply> {
+        let pool = self.transaction_pool.lock().await;
+        let system_lane = pool.system_lane_size();
+        let transaction_pool = PoolSnapshot {
+            count: pool.pool_size(),
+            oldest_age_ms: pool.oldest_age().map(|age| age.as_millis() as u64),
+        };
+        drop(pool);
+
+        let batches = pipeline::batches();
+        let in_stage = |stage: Stage| batches.iter().filter(|batch| batch.stage == stage).cloned().collect::<Vec<_>>();
+        Ok(json(&PipelineSnapshot {
+            transaction_pool,
+            system_lane,
+            executing: in_stage(Stage::Executing),
+            awaiting_proof: in_stage(Stage::AwaitingProof),
+            proving: in_stage(Stage::Proving),
+            awaiting_validator: in_stage(Stage::AwaitingValidator),
+            awaiting_l1: in_stage(Stage::AwaitingL1),
+            optimistic: in_stage(Stage::Optimistic),
+        }))
+    }
+
+    /// Unhealthy (503 with the events) once a background check reported a critical event, or
+    /// while the commitment fee payer can't pay for L1 transactions.
+    pub async fn health_handler(&self, health_events: &HealthEvents) -> Result<impl Reply> {
+        let mut critical = health_events.critical();
+        // Unlike reported events this clears on its own once the fee payer is topped up
+        if let Some(balance) = fee_payer::last_balance().filter(FeePayerBalance::is_refusing) {
+            critical.push(HealthEvent {
+                severity: Severity::Critical,
+                source: "fee-payer".to_string(),
+                message: format!("Refusing L1 transactions: {}", balance),
+                timestamp: balance.checked_at,
+            });
+        }
+        if critical.is_empty() {
+            Ok(warp::reply::with_status(json(&"ok"), StatusCode::OK))
+        } else {
+            Ok(warp::reply::with_status(json(&critical), StatusCode::SERVICE_UNAVAILABLE))
+        }
+    }
+}
+
+/// 503 for a submission shed under load, clients should retry once the backlog drains.
+pub fn overloaded(message: &str) -> Response {
+    ApiError::unavailable(message).into_response()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SubmissionResponse {
+    pub message: String,
+    pub sequence: SequenceEntryView,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleSubmission {
+    pub message: String,
+    /// Hex ID to follow the bundle with at `get-bundle-status`
+    pub bundle_id: String,
+}
+
+/// With the sequencing feed enabled the submitter gets the signed sequencing entry, otherwise
+/// just the message.
+fn submitted(message: &str, sequence: Option<&SequenceEntry>) -> warp::reply::Json {
+    match sequence {
+        Some(entry) => json(&SubmissionResponse { message: message.to_string(), sequence: entry.into() }),
+        None => json(&message),
     }
 }
 
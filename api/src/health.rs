@@ -0,0 +1,57 @@
+use log::error;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of health events kept in memory, older events are dropped.
+const MAX_HEALTH_EVENTS: usize = 100;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    Warning,
+    Critical,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthEvent {
+    pub severity: Severity,
+    pub source: String,
+    pub message: String,
+    pub timestamp: u64,
+}
+
+/// Problems found by background checks. Any critical event turns `/health` unhealthy until the
+/// node is restarted or the check that reported it resolves it, they point at corrupted or
+/// tampered data that needs an operator.
+#[derive(Clone, Default)]
+pub struct HealthEvents {
+    events: Arc<RwLock<VecDeque<HealthEvent>>>,
+}
+
+impl HealthEvents {
+    pub fn report(&self, severity: Severity, source: &str, message: String) {
+        error!(target: "health", "{:?} health event from {}: {}", severity, source, message);
+        let mut events = self.events.write().unwrap();
+        if events.len() == MAX_HEALTH_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(HealthEvent {
+            severity,
+            source: source.to_string(),
+            message,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+        });
+    }
+
+    /// Drops the critical events of `source` once it recovered, its warnings stay as a record.
+    pub fn resolve(&self, source: &str) {
+        self.events.write().unwrap().retain(|event| event.severity != Severity::Critical || event.source != source);
+    }
+
+    pub fn critical(&self) -> Vec<HealthEvent> {
+        self.events.read().unwrap().iter().filter(|event| event.severity == Severity::Critical).cloned().collect()
+    }
+}
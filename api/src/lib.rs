@@ -1,5 +1,32 @@
+pub mod auth;
 pub mod handler;
+pub mod health;
 pub mod account_handler;
 pub mod transaction_handler;
+pub mod export;
 pub mod block_handler;
-pub mod optimistic_handler;
\ No newline at end of file
+pub mod block_verification;
+pub mod blob_handler;
+pub mod optimistic_handler;
+pub mod challenge_handler;
+pub mod governance_handler;
+pub mod scheduler;
+pub mod maintenance_jobs;
+pub mod metrics;
+pub mod sponsorship_handler;
+pub mod cross_rollup_handler;
+pub mod simulation_handler;
+pub mod decode_handler;
+pub mod self_test;
+pub mod reindex;
+pub mod escape;
+pub mod backfill;
+pub mod load_shedding;
+pub mod peer_sync;
+pub mod rate_limit;
+pub mod reputation;
+pub mod routes;
+pub mod sequencing_feed;
+pub mod telemetry;
+pub mod warmup;
+pub mod ws;
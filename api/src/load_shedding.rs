@@ -0,0 +1,102 @@
+use execution::ordering::compute_unit_price;
+use serde_derive::{Deserialize, Serialize};
+use state::config::{OperationMode, TrollupConfig};
+use state::transaction::TrollupTransaction;
+use state_commitment::pipeline::{self, Stage};
+
+/// How much optional load the API sheds, from the proof backlog. Each level includes the ones
+/// before it.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum SheddingLevel {
+    Normal,
+    /// Optimistic submissions are refused
+    RejectOptimistic,
+    /// Submissions have to offer at least the minimum compute unit price
+    MinimumFee,
+    /// Every new submission is refused
+    RejectAll,
+}
+
+/// Backlogs, in batches waiting for or being proved, at which each level starts. 0 disables a
+/// level.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SheddingThresholds {
+    pub reject_optimistic: usize,
+    pub minimum_fee: usize,
+    pub reject_all: usize,
+    /// Micro-lamports per compute unit required at `MinimumFee`
+    pub minimum_compute_unit_price: u64,
+    #[serde(skip)]
+    pub mode: OperationMode,
+}
+
+/// Body of `/stats`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SheddingStatus {
+    pub proof_backlog: usize,
+    pub level: SheddingLevel,
+    pub thresholds: SheddingThresholds,
+}
+
+impl SheddingThresholds {
+    pub fn from_config(config: &TrollupConfig) -> Self {
+        SheddingThresholds {
+            reject_optimistic: config.shed_optimistic_backlog,
+            minimum_fee: config.shed_minimum_fee_backlog,
+            reject_all: config.shed_reject_all_backlog,
+            minimum_compute_unit_price: config.shed_minimum_compute_unit_price,
+            mode: config.mode,
+        }
+    }
+
+    pub fn level_for(&self, backlog: usize) -> SheddingLevel {
+        let reached = |threshold: usize| threshold > 0 && backlog >= threshold;
+        if reached(self.reject_all) {
+            SheddingLevel::RejectAll
+        } else if reached(self.minimum_fee) {
+            SheddingLevel::MinimumFee
+        } else if reached(self.reject_optimistic) {
+            SheddingLevel::RejectOptimistic
+        } else {
+            SheddingLevel::Normal
+        }
+    }
+
+    /// The current backlog and level. Both are derived from the pipeline on every call, so
+    /// shedding stops as soon as the prover catches up.
+    pub fn status(&self) -> SheddingStatus {
+        let proof_backlog = proof_backlog();
+        SheddingStatus {
+            proof_backlog,
+            level: self.level_for(proof_backlog),
+            thresholds: *self,
+        }
+    }
+
+    /// Whether a new submission is accepted at the current level, or why it is refused.
+    pub fn admit(&self, transaction: &TrollupTransaction) -> Result<(), String> {
+        let status = self.status();
+        match status.level {
+            SheddingLevel::RejectAll => Err(format!("Proof backlog of {} batches, not accepting transactions", status.proof_backlog)),
+            SheddingLevel::MinimumFee if compute_unit_price(transaction) < self.minimum_compute_unit_price => Err(format!(
+                "Proof backlog of {} batches, transactions must offer a compute unit price of at least {} micro-lamports",
+                status.proof_backlog, self.minimum_compute_unit_price,
+            )),
+            level if self.mode.is_optimistic(transaction.optimistic) && level >= SheddingLevel::RejectOptimistic => {
+                Err(format!("Proof backlog of {} batches, not accepting optimistic transactions", status.proof_backlog))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Batches executed but not yet proved.
+pub fn proof_backlog() -> usize {
+    pipeline::batches()
+        .iter()
+        .filter(|batch| matches!(batch.stage, Stage::AwaitingProof | Stage::Proving))
+        .count()
+}
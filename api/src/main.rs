@@ -1,37 +1,55 @@
 use execution::execution_engine::ExecutionEngine;
+use execution::sysvars::SysvarProvider;
 use execution::transaction_pool::TransactionPool;
 use lazy_static::lazy_static;
-use serde_derive::{Deserialize, Serialize};
-use solana_sdk::transaction::Transaction;
+use log::{error, info};
+use server::access_log::{AccessLog, AccessLogSettings};
+use server::client_ip::TrustedProxies;
+use server::http::serve;
+use server::logging::{self, LogSettings};
+use server::request_id::REQUEST_ID_HEADER;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
 use state::account_state::AccountState;
+use state::blob::{Blob, BlobReferences};
 use state::block::Block;
 use state::config::TrollupConfig;
+use state::cross_rollup::DeliveredMessage;
+use state::deposit::CreditedDeposit;
+use state::sponsorship::SponsorSpend;
 use state::state_record::StateCommitmentPackage;
 use state::transaction::TrollupTransaction;
+use state_commitment::batch_data::{self, store_from_config};
+use state_commitment::l1_anchor::L1AnchorTracker;
+use state_commitment::l1_divergence::L1RootWatch;
+use state_commitment::vault_reconciliation::VaultReconciler;
+use state_commitment::message_bus::build_publisher;
 use state_commitment::state_commitment_layer::{StateCommitment, StateCommitter};
 use state_commitment::state_commitment_pool::{StateCommitmentPool, StatePool};
+use state_management::blob_store::BlobStore;
+use state_management::compression;
 use state_management::sled_state_management::SledStateManagement;
 use state_management::state_management::StateManager;
-use std::convert::Infallible;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 use tokio::runtime::Runtime;
 use tokio::sync::Mutex;
-use trollup_api::account_handler::AccountHandler;
-use trollup_api::block_handler::BlockHandler;
-use trollup_api::handler::Handler;
-use trollup_api::optimistic_handler::OptimisticHandler;
-use trollup_api::transaction_handler::TransactionHandler;
-use utoipa::{Modify, OpenApi};
-use utoipa_gen::ToSchema;
-use utoipa_swagger_ui::Config as SwaggerConfig;
-use warp::body::json;
-use warp::{
-    http::Uri,
-    hyper::{Response, StatusCode},
-    path::{FullPath, Tail},
-    Filter, Rejection, Reply,
-};
+use trollup_api::auth::{AdminToken, ApiKeys};
+use trollup_api::block_verification::BlockVerifier;
+use trollup_api::simulation_handler::SimulationCache;
+use trollup_api::health::HealthEvents;
+use trollup_api::load_shedding::SheddingThresholds;
+use trollup_api::peer_sync;
+use trollup_api::rate_limit::RateLimiter;
+use trollup_api::reputation::Reputation;
+use trollup_api::warmup;
+use trollup_api::maintenance_jobs::{BatchDataVerificationJob, BlockIntegrityScanJob, DepositCreditJob, FeePayerBalanceJob, FlushStateJob, L1AnchorJob, L1DivergenceJob, L1Rollback, MetricsFlushJob, OutboxRelayJob, PoolSweepJob, VaultReconciliationJob, WithdrawalBatchJob};
+use trollup_api::routes::{routes, ApiState, ApiVersioning};
+use trollup_api::scheduler::Scheduler;
+use trollup_api::telemetry::TelemetryJob;
+use warp::Filter;
 
 lazy_static! {
     static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
@@ -41,324 +59,327 @@ lazy_static! {
 async fn main() {
     let _ = TrollupConfig::load();
 
-    env_logger::init();
-    
-    //Initialize our state managers. Currently only sled is implemented, but the idea is to use be able to use different DBs (RocksDB, etc...), but still utilize the StateManager as the interface
-    let account_state_manager = Arc::new(StateManager::<SledStateManagement<AccountState>>::new(&CONFIG.account_state_manager_db_path));
-    let block_state_manager = Arc::new(StateManager::<SledStateManagement<Block>>::new(&CONFIG.block_state_manager_db_path));
-    let transaction_state_manager = Arc::new(StateManager::<SledStateManagement<TrollupTransaction>>::new(&CONFIG.transaction_state_manager_db_path));
-    let optimistic_commitment_state_management = Arc::new(StateManager::<SledStateManagement<StateCommitmentPackage<AccountState>>>::new(&CONFIG.optimistic_commitment_state_manager_db_path));
-    // Clone Arc references for the thread
-    let thread_account_state_manager = Arc::clone(&account_state_manager);
-    let transaction_pool = Arc::new(Mutex::new(TransactionPool::new()));
-    let commitment_pool = Arc::new(Mutex::new(StateCommitmentPool::new()));
-
-    let engine_tx_pool = Arc::clone(&transaction_pool);
-    let engine_commitment_pool = Arc::clone(&commitment_pool);
-
-    // Spawn a new thread
-    let engine_handle = thread::spawn(move || {
-        // Create a new Tokio runtime
-        let rt = Runtime::new().unwrap();
+    let args: Vec<String> = std::env::args().collect();
+    // A read-only follower serves the read API from an existing data directory, e.g. a snapshot
+    // copied from a live node, without executing, committing or talking to the validator
+    let read_only = args.iter().any(|arg| arg == "--read-only");
+    // `load` already moved the databases under --data-dir
+    if let Some(position) = args.iter().position(|arg| arg == "--data-dir") {
+        if args.get(position + 1).is_none() {
+            eprintln!("--data-dir requires a directory");
+            std::process::exit(1);
+        }
+    } else if read_only {
+        eprintln!("--read-only requires --data-dir");
+        std::process::exit(1);
+    }
 
-        // Run the async code on the new runtime
-        rt.block_on(async {
-            let mut engine = ExecutionEngine::new(&thread_account_state_manager, engine_tx_pool, engine_commitment_pool);
-            engine.start().await;
-        });
+    let _log_guard = logging::init(&LogSettings {
+        level: CONFIG.log_level.clone(),
+        file_path: CONFIG.log_file_path.clone(),
+        rotation: CONFIG.log_rotation.clone(),
+        max_file_size: CONFIG.log_max_file_size,
+        max_files: CONFIG.log_max_files,
+        format: CONFIG.log_format.clone(),
     });
 
-    let state_commitment_pool = Arc::clone(&commitment_pool);
-    let state_commitment_account_state_manager = Arc::clone(&account_state_manager);
-    let state_commitment_transaction_state_manager = Arc::clone(&transaction_state_manager);
-    let state_commitment_block_state_manager = Arc::clone(&block_state_manager);
-    let state_commitment_optimistic_commitment_state_management = Arc::clone(&optimistic_commitment_state_management);
-    let commitment_handle = thread::spawn(move || {
-        // Create a new Tokio runtime
-        let rt = Runtime::new().unwrap();
+    if std::env::args().any(|arg| arg == "--self-test") {
+        let report = trollup_api::self_test::run().await;
+        report.print();
+        std::process::exit(if report.passed() { 0 } else { 1 });
+    }
 
-        // Run the async code on the new runtime
-        rt.block_on(async {
-            let mut state_commitment = StateCommitment::new(&state_commitment_account_state_manager, state_commitment_pool, &state_commitment_block_state_manager, &state_commitment_transaction_state_manager, state_commitment_optimistic_commitment_state_management);
-            state_commitment.start().await;
+    // Proof for the vault's emergency withdrawal, built from this node's data directory after
+    // the sequencer stopped committing
+    if let Some(position) = args.iter().position(|arg| arg == "--escape-proof") {
+        let Some(address) = args.get(position + 1) else {
+            eprintln!("--escape-proof requires an account address");
+            std::process::exit(1);
+        };
+        std::process::exit(match trollup_api::escape::escape_proof(&CONFIG, address).await {
+            Ok(proof) => {
+                println!("{}", serde_json::to_string_pretty(&proof).unwrap());
+                0
+            }
+            Err(error) => {
+                eprintln!("Failed to build escape proof: {}", error);
+                1
+            }
         });
-    });
-
-    // let routes = routes(transaction_pool);
-    let routes = routes(Arc::clone(&transaction_pool), Arc::clone(&account_state_manager), Arc::clone(&transaction_state_manager), Arc::clone(&block_state_manager), Arc::clone(&optimistic_commitment_state_management));
-
-    let cors = warp::cors().allow_any_origin();
-    warp::serve(routes.with(cors)).run(([0, 0, 0, 0], 27182)).await;
-
-    // Wait for the thread to finish
-    engine_handle.join().unwrap();
-    commitment_handle.join().unwrap();
-}
-
-pub fn routes(
-    pool: Arc<Mutex<TransactionPool>>,
-    account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>,
-    transaction_state_manager: Arc<StateManager<SledStateManagement<TrollupTransaction>>>,
-    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>,
-    optimistic_commitment_state_management: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>,
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-
-    let api_doc_config = Arc::new(SwaggerConfig::from("/api-doc.json"));
-
-    #[derive(OpenApi)]
-    #[openapi(
-        info(
-            title = "Trollup-Validator API",
-            description = "The Trollup API provides functionality to get and validate proofs",
-            version = "0.0.1"
-        ),
-        paths(send_transaction_route),
-            components(
-                schemas(TransactionSchema)
-            ),
-        tags(
-        (name = "handler", description = "Trollup-Validator API endpoints")
-        )
-    )]
-    struct ApiDoc;
-
-    let api_doc = warp::path("api-doc.json")
-        .and(warp::get())
-        .map(|| warp::reply::json(&ApiDoc::openapi()));
-
-    let swagger_ui = warp::path("swagger-ui")
-        .and(warp::get())
-        .and(warp::path::full())
-        .and(warp::path::tail())
-        .and(warp::any().map(move || api_doc_config.clone()))
-        .and_then(serve_swagger);
-
-    health_route(Arc::clone(&pool))
-        .or(send_transaction_route(Arc::clone(&pool)))
-        .or(send_transaction_optimistic_route(Arc::clone(&pool)))
-        .or(get_transaction_route(Arc::clone(&transaction_state_manager)))
-        .or(get_all_transaction_route(Arc::clone(&transaction_state_manager)))
-        .or(get_all_pending_commitments_route(Arc::clone(&optimistic_commitment_state_management)))
-        .or(get_account_route(Arc::clone(&account_state_manager)))
-        .or(get_all_accounts_route(Arc::clone(&account_state_manager)))
-        .or(get_all_blocks_route(Arc::clone(&block_state_manager)))
-        .or(get_block_route(Arc::clone(&block_state_manager)))
-        .or(get_block_route(Arc::clone(&block_state_manager)))
-        .or(api_doc).or(swagger_ui)
-}
-
-fn with_pool(
-    pool: Arc<Mutex<TransactionPool>>,
-) -> impl Filter<Extract=(Arc<Mutex<TransactionPool>>,), Error=std::convert::Infallible> + Clone {
-    warp::any().map(move || Arc::clone(&pool))
-}
-
-fn health_route(
-    pool: Arc<Mutex<TransactionPool>>,
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-    warp::path!("health")
-        .and(with_pool(pool))
-        .and_then(|pool: Arc<Mutex<TransactionPool>>| async move {
-            let handler = Handler::new(pool);
-            handler.health_handler().await
-        })
-}
-
-#[derive(Serialize, Deserialize, ToSchema, Clone)]
-pub struct TransactionSchema(Transaction);
-
-#[utoipa::path(
-        post,
-        path = "/send_transaction",
-        request_body = Transaction,
-        responses(
-            (status = 200, description = "Transaction submitted successfully", body = String),
-            (status = 400, description = "Invalid transaction")
-        ),
-        tag = "transactions"
-)]
-fn send_transaction_route(
-    pool: Arc<Mutex<TransactionPool>>,
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-    warp::path("send-transaction")
-        .and(with_pool(pool))
-        .and(json())
-        .and_then(|pool: Arc<Mutex<TransactionPool>>, transaction: Transaction| async move {
-            let handler = Handler::new(pool);
-            handler.send_transaction_handler(transaction).await
-        })
-}
-
-fn send_transaction_optimistic_route(
-    pool: Arc<Mutex<TransactionPool>>,
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-    warp::path("send-transaction-optimistic")
-        .and(with_pool(pool))
-        .and(json())
-        .and_then(|pool: Arc<Mutex<TransactionPool>>, transaction: Transaction| async move {
-            let handler = Handler::new(pool);
-            handler.send_transaction_optimistic_handler(transaction).await
-        })
-}
-
-fn get_account_route(
-    account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-    warp::path("get-account")
-        .and(warp::path::param())
-        .and(create_account_handler_filter(account_state_manager))
-        .and_then(|account_id: String, handler: AccountHandler<SledStateManagement<AccountState>>| async move {
-            handler.get_account(&account_id).await
-        })
-}
-
-fn get_all_accounts_route(
-    account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-    warp::path("get-all-accounts")
-        .and(create_account_handler_filter(account_state_manager))
-        .and_then(|handler: AccountHandler<SledStateManagement<AccountState>>| async move {
-            handler.get_all_accounts().await
-        })
-}
-
-fn create_account_handler_filter(
-    state_manager: Arc<StateManager<SledStateManagement<AccountState>>>
-) -> impl Filter<Extract=(AccountHandler<SledStateManagement<AccountState>>,), Error=Infallible> + Clone {
-    let handler_filter = warp::any().map(move || AccountHandler::new(Arc::clone(&state_manager)));
-    handler_filter
-}
-
-fn get_transaction_route(
-    transaction_state_manager: Arc<StateManager<SledStateManagement<TrollupTransaction>>>
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-    warp::path("get-transaction")
-        .and(warp::path::param())
-        .and(create_transaction_handler_filter(transaction_state_manager))
-        .and_then(|signature: String, handler: TransactionHandler<SledStateManagement<TrollupTransaction>>| async move {
-            handler.get_transaction(&signature).await
-        })
-}
-
-fn get_all_transaction_route(
-    transaction_state_manager: Arc<StateManager<SledStateManagement<TrollupTransaction>>>
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-    warp::path("get-all-transactions")
-        .and(create_transaction_handler_filter(transaction_state_manager))
-        .and_then(|handler: TransactionHandler<SledStateManagement<TrollupTransaction>>| async move {
-            handler.get_all_transactions().await
-        })
-}
-
-fn create_transaction_handler_filter(
-    state_manager: Arc<StateManager<SledStateManagement<TrollupTransaction>>>
-) -> impl Filter<Extract=(TransactionHandler<SledStateManagement<TrollupTransaction>>,), Error=Infallible> + Clone {
-    let handler_filter = warp::any().map(move || TransactionHandler::new(Arc::clone(&state_manager)));
-    handler_filter
-}
-
-fn get_block_route(
-    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-    warp::path("get-block")
-        .and(warp::path::param())
-        .and(create_block_handler_filter(block_state_manager))
-        .and_then(|block_id: u64, handler: BlockHandler<SledStateManagement<Block>>| async move {
-            handler.get_block(block_id).await
-        })
-}
-
-fn get_latest_block_route(
-    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-    warp::path("get-latest-block")
-        .and(create_block_handler_filter(block_state_manager))
-        .and_then(|handler: BlockHandler<SledStateManagement<Block>>| async move {
-            handler.get_latest_block().await
-        })
-}
-
-fn get_all_blocks_route(
-    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-    warp::path("get-all-blocks")
-        .and(create_block_handler_filter(block_state_manager))
-        .and_then(|handler: BlockHandler<SledStateManagement<Block>>| async move {
-            handler.get_all_blocks().await
-        })
-}
+    }
 
-fn create_block_handler_filter(
-    state_manager: Arc<StateManager<SledStateManagement<Block>>>
-) -> impl Filter<Extract=(BlockHandler<SledStateManagement<Block>>,), Error=Infallible> + Clone {
-    let handler_filter = warp::any().map(move || BlockHandler::new(Arc::clone(&state_manager)));
-    handler_filter
-}
+    // Rebuilds the blocks this node lost from the roots committed on L1 and the published batch data
+    if args.iter().any(|arg| arg == "--backfill-from-l1") {
+        std::process::exit(match trollup_api::backfill::backfill(&CONFIG).await {
+            Ok(report) => {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+                0
+            }
+            Err(error) => {
+                eprintln!("Backfill stopped: {}", error);
+                1
+            }
+        });
+    }
 
-fn create_optimistic_handler_filter(
-    state_manager: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>
-) -> impl Filter<Extract=(OptimisticHandler<SledStateManagement<StateCommitmentPackage<AccountState>>>,), Error=Infallible> + Clone {
-    let handler_filter = warp::any().map(move || OptimisticHandler::new(Arc::clone(&state_manager)));
-    handler_filter
-}
+    if let Some(position) = args.iter().position(|arg| arg == "--train-compression-dictionary") {
+        let output = args.get(position + 1).map(String::as_str).unwrap_or("state-compression.dict");
+        std::process::exit(match train_compression_dictionary(output) {
+            Ok(size) => {
+                println!("Wrote {} byte dictionary to {}, set STATE_COMPRESSION_DICTIONARY_PATH to use it", size, output);
+                0
+            }
+            Err(error) => {
+                eprintln!("Failed to train compression dictionary: {}", error);
+                1
+            }
+        });
+    }
 
-fn get_all_pending_commitments_route(
-    optimistic_commit_state_manager: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-    warp::path("get-all-pending-commitments")
-        .and(create_optimistic_handler_filter(optimistic_commit_state_manager))
-        .and_then(|handler: OptimisticHandler<SledStateManagement<StateCommitmentPackage<AccountState>>>| async move {
-            handler.get_all_transactions().await
-        })
-}
+    if let Err(message) = CONFIG.validate_mode().and_then(|_| CONFIG.validate_merkle()) {
+        error!("Inconsistent configuration: {}", message);
+        std::process::exit(1);
+    }
+    let proxies = match TrustedProxies::new(&CONFIG.trusted_proxies, CONFIG.proxy_protocol) {
+        Ok(proxies) => proxies,
+        Err(message) => {
+            error!("Inconsistent configuration: {}", message);
+            std::process::exit(1);
+        }
+    };
 
-fn get_pending_commitment_route(
-    optimistic_commit_state_manager: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>
-) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
-    warp::path("get-pending-commitments")
-        .and(warp::path::param())
-        .and(create_optimistic_handler_filter(optimistic_commit_state_manager))
-        .and_then(|state_root: String, handler: OptimisticHandler<SledStateManagement<StateCommitmentPackage<AccountState>>>| async move {
-            handler.get_pending_transaction_batch(&state_root).await
-        })
-}
+    //Initialize our state managers. Currently only sled is implemented, but the idea is to use be able to use different DBs (RocksDB, etc...), but still utilize the StateManager as the interface
+    let account_state_manager = Arc::new(StateManager::<SledStateManagement<AccountState>>::new(&CONFIG.account_state_manager_db_path));
+    let block_state_manager = Arc::new(StateManager::<SledStateManagement<Block>>::new(&CONFIG.block_state_manager_db_path));
+    let transaction_state_manager = Arc::new(StateManager::<SledStateManagement<TrollupTransaction>>::new(&CONFIG.transaction_state_manager_db_path));
+    let optimistic_commitment_state_management = Arc::new(StateManager::<SledStateManagement<StateCommitmentPackage<AccountState>>>::new(&CONFIG.optimistic_commitment_state_manager_db_path));
+    let sponsor_state_manager = Arc::new(StateManager::<SledStateManagement<SponsorSpend>>::new(&CONFIG.sponsor_state_manager_db_path));
+    let inbox_state_manager = Arc::new(StateManager::<SledStateManagement<DeliveredMessage>>::new(&CONFIG.inbox_db_path));
+    let blob_state_manager = Arc::new(StateManager::<SledStateManagement<Blob>>::new(&CONFIG.blob_state_manager_db_path));
+    let blob_references_state_manager = Arc::new(StateManager::<SledStateManagement<BlobReferences>>::new(&CONFIG.blob_references_db_path));
+    let blob_store = Arc::new(BlobStore::new(Arc::clone(&blob_state_manager), Arc::clone(&blob_references_state_manager)));
+    let transaction_pool = Arc::new(Mutex::new(TransactionPool::new().with_system_lane_capacity(CONFIG.system_lane_capacity)));
+    let commitment_pool = Arc::new(Mutex::new(StateCommitmentPool::new()));
 
-fn with_value(value: String) -> impl Filter<Extract=(String,), Error=Infallible> + Clone {
-    warp::any().map(move || value.clone())
-}
+    // A follower only syncs from a peer on the same chain whose records it can read
+    if read_only && !CONFIG.sync_peer_url.is_empty() {
+        let identity = CONFIG.node_identity().ok();
+        let local = peer_sync::local_node_info(&block_state_manager, identity.as_ref());
+        if let Err(message) = peer_sync::handshake_with_peer(&CONFIG.sync_peer_url, &local).await {
+            error!("Refusing to sync from {}: {}", CONFIG.sync_peer_url, message);
+            std::process::exit(1);
+        }
+    }
 
-fn with_config(value: TrollupConfig) -> impl Filter<Extract=(TrollupConfig,), Error=Infallible> + Clone {
-    warp::any().map(move || value.clone())
-}
+    let workers = if read_only {
+        info!("Read-only follower, serving {} without the engine or committer", CONFIG.block_state_manager_db_path);
+        None
+    } else {
+        // The first blocks shouldn't wait on L1 for programs or on disk for keys and accounts
+        tokio::task::block_in_place(|| warmup::warm_up(&account_state_manager, &block_state_manager, &CONFIG));
+
+        // Clone Arc references for the thread
+        let thread_account_state_manager = Arc::clone(&account_state_manager);
+        let engine_tx_pool = Arc::clone(&transaction_pool);
+        let engine_commitment_pool = Arc::clone(&commitment_pool);
+        let next_block_number = block_state_manager
+            .get_latest_block_id()
+            .and_then(|id| block_state_manager.get_state_record(&id))
+            .map(|block| block.block_number + 1)
+            .unwrap_or(1);
+        let engine_sysvars = SysvarProvider::from_config(&CONFIG, next_block_number);
+
+        // Spawn a new thread
+        let engine_handle = thread::spawn(move || {
+            // Create a new Tokio runtime
+            let rt = Runtime::new().unwrap();
+
+            // Run the async code on the new runtime
+            rt.block_on(async {
+                let mut engine = ExecutionEngine::new(&thread_account_state_manager, engine_tx_pool, engine_commitment_pool, engine_sysvars);
+                engine.start().await;
+            });
+        });
 
-async fn serve_swagger(
-    full_path: FullPath,
-    tail: Tail,
-    config: Arc<SwaggerConfig<'static>>,
-) -> Result<Box<dyn Reply + 'static>, Rejection> {
-    if full_path.as_str() == "/swagger-ui" {
-        return Ok(Box::new(warp::redirect::found(Uri::from_static(
-            "/swagger-ui/",
-        ))));
+        let state_commitment_pool = Arc::clone(&commitment_pool);
+        let state_commitment_account_state_manager = Arc::clone(&account_state_manager);
+        let state_commitment_transaction_state_manager = Arc::clone(&transaction_state_manager);
+        let state_commitment_block_state_manager = Arc::clone(&block_state_manager);
+        let state_commitment_optimistic_commitment_state_management = Arc::clone(&optimistic_commitment_state_management);
+        let state_commitment_blob_store = Arc::clone(&blob_store);
+        // Transactions of rolled back commitments go back to the transaction pool
+        let (requeue_sender, mut requeue_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let requeue_pool = Arc::clone(&transaction_pool);
+        tokio::spawn(async move {
+            while let Some(transactions) = requeue_receiver.recv().await {
+                requeue_pool.lock().await.requeue_transactions(transactions);
+            }
+        });
+        let commitment_handle = thread::spawn(move || {
+            // Create a new Tokio runtime
+            let rt = Runtime::new().unwrap();
+
+            // Run the async code on the new runtime
+            rt.block_on(async {
+                let mut state_commitment = StateCommitment::new(&state_commitment_account_state_manager, state_commitment_pool, &state_commitment_block_state_manager, &state_commitment_transaction_state_manager, state_commitment_optimistic_commitment_state_management, state_commitment_blob_store, requeue_sender);
+                state_commitment.start().await;
+            });
+        });
+        Some((engine_handle, commitment_handle))
+    };
+
+    let mut scheduler = Scheduler::new(CONFIG.scheduler_jobs.clone());
+    scheduler.register(Arc::new(MetricsFlushJob::new(Arc::clone(&transaction_pool), Arc::clone(&commitment_pool))), Duration::from_secs(60));
+    let block_verifier = BlockVerifier::load();
+    let health_events = HealthEvents::default();
+    scheduler.register(Arc::new(BlockIntegrityScanJob::new(Arc::clone(&block_state_manager), Arc::clone(&account_state_manager), block_verifier.clone(), health_events.clone())), Duration::from_secs(300));
+    if let Some(store) = store_from_config(&CONFIG.batch_data_location) {
+        match batch_data::trusted_signer(&CONFIG) {
+            Ok(signer) => {
+                let job = BatchDataVerificationJob::new(Arc::clone(&block_state_manager), store, signer, CONFIG.batch_data_allow_unsigned, health_events.clone());
+                scheduler.register(Arc::new(job), Duration::from_secs(600));
+            }
+            Err(error) => error!("Batch data isn't re-verified: {}", error),
+        }
     }
-
-    let path = tail.as_str();
-    match utoipa_swagger_ui::serve(path, config) {
-        Ok(file) => {
-            if let Some(file) = file {
-                Ok(Box::new(
-                    Response::builder()
-                        .header("Content-Type", file.content_type)
-                        .body(file.bytes),
-                ))
-            } else {
-                Ok(Box::new(StatusCode::NOT_FOUND))
+    // The remaining jobs write to the databases or act on L1, which a follower leaves to the live node
+    if !read_only {
+        scheduler.register(Arc::new(PoolSweepJob::new(Arc::clone(&transaction_pool))), Duration::from_secs(30));
+        let flush_accounts = Arc::clone(&account_state_manager);
+        let flush_blocks = Arc::clone(&block_state_manager);
+        let flush_transactions = Arc::clone(&transaction_state_manager);
+        let flush_optimistic = Arc::clone(&optimistic_commitment_state_management);
+        let flush_blobs = Arc::clone(&blob_state_manager);
+        let flush_blob_references = Arc::clone(&blob_references_state_manager);
+        scheduler.register(Arc::new(FlushStateJob::new(vec![
+            Box::new(move || flush_accounts.commit()),
+            Box::new(move || flush_blocks.commit()),
+            Box::new(move || flush_transactions.commit()),
+            Box::new(move || flush_optimistic.commit()),
+            Box::new(move || flush_blobs.commit()),
+            Box::new(move || flush_blob_references.commit()),
+        ])), Duration::from_secs(60));
+        match build_publisher(&CONFIG).await {
+            Ok(Some(publisher)) => scheduler.register(Arc::new(OutboxRelayJob::new(publisher)), Duration::from_secs(1)),
+            Ok(None) => {}
+            Err(error) => error!("Message bus disabled: {}", error),
+        }
+        match Pubkey::from_str(CONFIG.proof_verifier_program_id_current_env()) {
+            Ok(program_id) => {
+                let tracker = L1AnchorTracker::new(Arc::clone(&block_state_manager), CONFIG.rpc_url_current_env().to_string(), &program_id);
+                scheduler.register(Arc::new(L1AnchorJob::new(tracker)), Duration::from_secs(30));
             }
+            Err(error) => error!("L1 anchor tracking disabled, invalid PROOF_VERIFIER_PROGRAM_ID: {}", error),
+        }
+        match Pubkey::from_str(CONFIG.signature_verifier_program_id_current_env()) {
+            Ok(program_id) => {
+                let watch = L1RootWatch::new(Arc::clone(&block_state_manager), CONFIG.rpc_url_current_env().to_string(), &program_id);
+                let rollback = L1Rollback {
+                    config: CONFIG.clone(),
+                    account_state_manager: Arc::clone(&account_state_manager),
+                    block_state_manager: Arc::clone(&block_state_manager),
+                    transaction_state_manager: Arc::clone(&transaction_state_manager),
+                };
+                let tolerance = Duration::from_secs(CONFIG.l1_divergence_tolerance_secs);
+                scheduler.register(Arc::new(L1DivergenceJob::new(watch, rollback, health_events.clone(), tolerance, CONFIG.l1_divergence_webhook_url.clone())), Duration::from_secs(30));
+            }
+            Err(error) => error!("L1 divergence watch disabled, invalid SIGNATURE_VERIFIER_PROGRAM_ID: {}", error),
+        }
+        match CONFIG.commitment_fee_payer() {
+            Ok(fee_payer) => {
+                let job = FeePayerBalanceJob::new(
+                    CONFIG.rpc_url_current_env().to_string(),
+                    fee_payer.pubkey(),
+                    CONFIG.fee_payer_low_balance_lamports,
+                    CONFIG.fee_payer_alert_webhook_url.clone(),
+                    health_events.clone(),
+                );
+                scheduler.register(Arc::new(job), Duration::from_secs(60));
+            }
+            Err(error) => error!("Fee payer monitoring disabled: {}", error),
+        }
+        if !CONFIG.vault_program_id.is_empty() {
+            match Pubkey::from_str(&CONFIG.vault_program_id) {
+                Ok(program_id) => {
+                    let reconciler = VaultReconciler::new(Arc::clone(&account_state_manager), CONFIG.rpc_url_current_env().to_string(), program_id);
+                    scheduler.register(Arc::new(VaultReconciliationJob::new(reconciler, health_events.clone())), Duration::from_secs(300));
+                    scheduler.register(Arc::new(WithdrawalBatchJob), Duration::from_secs(5));
+                    match Keypair::from_bytes(&CONFIG.trollup_api_keypair) {
+                        Ok(sequencer) => {
+                            let deposits = Arc::new(StateManager::<SledStateManagement<CreditedDeposit>>::new(&CONFIG.deposits_db_path));
+                            let job = DepositCreditJob::new(Arc::clone(&transaction_pool), deposits, CONFIG.rpc_url_current_env().to_string(), program_id, sequencer);
+                            scheduler.register(Arc::new(job), Duration::from_secs(30));
+                        }
+                        Err(error) => error!("Vault deposits aren't credited, invalid API keypair: {}", error),
+                    }
+                }
+                Err(error) => error!("Vault reconciliation disabled, invalid VAULT_PROGRAM_ID: {}", error),
+            }
+        }
+    }
+    let access_log = AccessLog::new("trollup-api", AccessLogSettings {
+        enabled: CONFIG.access_log_enabled,
+        sample_percent: CONFIG.access_log_sample_percent,
+    });
+    if CONFIG.telemetry_enabled {
+        if CONFIG.telemetry_endpoint.is_empty() {
+            error!("Telemetry disabled, TELEMETRY_ENABLED is set but TELEMETRY_ENDPOINT isn't");
+        } else {
+            info!(target: "audit", "Telemetry enabled, anonymized node stats are reported to {}", CONFIG.telemetry_endpoint);
+            scheduler.register(Arc::new(TelemetryJob::new(CONFIG.telemetry_endpoint.clone(), CONFIG.mode, access_log.clone())), Duration::from_secs(3600));
         }
-        Err(error) => Ok(Box::new(
-            Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(error.to_string()),
-        )),
     }
+    let scheduler_status = scheduler.start();
+
+    let simulation_cache = SimulationCache::from_config(&CONFIG);
+    simulation_cache.invalidate_on_finalize();
+
+    let routes = routes(ApiState {
+        transaction_pool: Arc::clone(&transaction_pool),
+        account_state_manager: Arc::clone(&account_state_manager),
+        transaction_state_manager: Arc::clone(&transaction_state_manager),
+        block_state_manager: Arc::clone(&block_state_manager),
+        optimistic_commitment_state_management: Arc::clone(&optimistic_commitment_state_management),
+        sponsor_state_manager: Arc::clone(&sponsor_state_manager),
+        inbox_state_manager: Arc::clone(&inbox_state_manager),
+        blob_store,
+        scheduler_status,
+        access_log: access_log.clone(),
+        simulation_cache,
+        operation_mode: CONFIG.mode,
+        read_only,
+        shedding: SheddingThresholds::from_config(&CONFIG),
+        block_verifier,
+        health_events,
+        versioning: ApiVersioning {
+            legacy_routes_enabled: CONFIG.api_legacy_routes_enabled,
+            legacy_routes_sunset: CONFIG.api_legacy_routes_sunset.clone(),
+        },
+        reputation: Reputation::from_config(&CONFIG),
+        api_keys: ApiKeys::from_config(&CONFIG),
+        rate_limiter: RateLimiter::from_config(&CONFIG),
+        admin_token: AdminToken::new(CONFIG.admin_token.clone()),
+    });
+
+    let cors = warp::cors().allow_any_origin().expose_header(REQUEST_ID_HEADER);
+    serve(routes.with(cors), ([0, 0, 0, 0], 27182), access_log, proxies).await;
+
+    // Wait for the threads to finish
+    if let Some((engine_handle, commitment_handle)) = workers {
+        engine_handle.join().unwrap();
+        commitment_handle.join().unwrap();
+    }
+}
+
+/// Trains a compression dictionary on the accounts currently in the account store.
+fn train_compression_dictionary(output: &str) -> anyhow::Result<usize> {
+    let account_state_manager = StateManager::<SledStateManagement<AccountState>>::new(&CONFIG.account_state_manager_db_path);
+    let samples = account_state_manager
+        .get_all_entries()
+        .into_iter()
+        .map(|(_, account)| borsh::to_vec(&account))
+        .collect::<Result<Vec<Vec<u8>>, _>>()?;
+    let dictionary = compression::train_dictionary(&samples, 64 * 1024)?;
+    std::fs::write(output, &dictionary)?;
+    Ok(dictionary.len())
 }
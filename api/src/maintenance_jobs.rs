@@ -0,0 +1,589 @@
+use crate::backfill::roll_back_to_l1;
+use crate::block_verification::{BlockVerification, BlockVerifier};
+use crate::health::{HealthEvents, Severity};
+use crate::scheduler::ScheduledJob;
+use crate::sequencing_feed;
+use async_trait::async_trait;
+use execution::transaction_pool::TransactionPool;
+use log::{error, info, warn};
+use rand::Rng;
+use state::account_state::AccountState;
+use state::block::Block;
+use state::config::TrollupConfig;
+use state::deposit::CreditedDeposit;
+use state::transaction::{convert_to_trollup_transaction, TrollupTransaction};
+use state_commitment::batch_data::{self, BatchDataStore};
+use state_commitment::fee_payer;
+use state_commitment::http_client;
+use state_commitment::l1_anchor::L1AnchorTracker;
+use state_commitment::l1_divergence::L1RootWatch;
+use state_commitment::load_failures;
+use state_commitment::message_bus::{relay_outbox, MessagePublisher};
+use state_commitment::rollup_status;
+use state_commitment::state_commitment_pool::{StateCommitmentPool, StatePool};
+use state_commitment::vault_reconciliation::{self, VaultReconciler};
+use state_commitment::withdrawal_aggregator;
+use state_management::compression;
+use state_management::state_management::{ManageState, StateManager};
+use serde_json::json;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Number of historical blocks re-verified per run of the integrity scan.
+const BLOCKS_PER_SCAN: usize = 5;
+
+/// Removes transactions that were submitted more than once while they were still waiting in the
+/// transaction pool.
+pub struct PoolSweepJob {
+    transaction_pool: Arc<Mutex<TransactionPool>>,
+}
+
+impl PoolSweepJob {
+    pub fn new(transaction_pool: Arc<Mutex<TransactionPool>>) -> Self {
+        PoolSweepJob { transaction_pool }
+    }
+}
+
+#[async_trait]
+impl ScheduledJob for PoolSweepJob {
+    fn name(&self) -> &'static str {
+        "pool-sweep"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let removed = self.transaction_pool.lock().await.remove_duplicates();
+        if removed > 0 {
+            info!("Removed {} duplicate transactions from the pool", removed);
+        }
+        Ok(())
+    }
+}
+
+/// Flushes the state stores to disk so a crash loses at most one interval of writes.
+pub struct FlushStateJob {
+    stores: Vec<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl FlushStateJob {
+    pub fn new(stores: Vec<Box<dyn Fn() + Send + Sync>>) -> Self {
+        FlushStateJob { stores }
+    }
+}
+
+#[async_trait]
+impl ScheduledJob for FlushStateJob {
+    fn name(&self) -> &'static str {
+        "flush-state"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        for flush in &self.stores {
+            flush();
+        }
+        Ok(())
+    }
+}
+
+/// Writes the pool gauges and the load failure counters to the `metrics` log target.
+pub struct MetricsFlushJob {
+    transaction_pool: Arc<Mutex<TransactionPool>>,
+    commitment_pool: Arc<Mutex<StateCommitmentPool<AccountState>>>,
+}
+
+impl MetricsFlushJob {
+    pub fn new(transaction_pool: Arc<Mutex<TransactionPool>>, commitment_pool: Arc<Mutex<StateCommitmentPool<AccountState>>>) -> Self {
+        MetricsFlushJob { transaction_pool, commitment_pool }
+    }
+}
+
+#[async_trait]
+impl ScheduledJob for MetricsFlushJob {
+    fn name(&self) -> &'static str {
+        "metrics-flush"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let transaction_pool_size = self.transaction_pool.lock().await.pool_size();
+        let commitment_pool_size = self.commitment_pool.lock().await.pool_size();
+        let storage = compression::stats();
+        info!(target: "metrics", "transaction_pool_size={} commitment_pool_size={} storage_bytes_before={} storage_bytes_stored={} storage_values_compressed={}",
+            transaction_pool_size, commitment_pool_size, storage.bytes_before, storage.bytes_stored, storage.values_compressed);
+        let load_failures: Vec<String> = load_failures::totals()
+            .into_iter()
+            .map(|(cause, count)| format!("load_failures_{}={}", cause, count))
+            .collect();
+        if !load_failures.is_empty() {
+            info!(target: "metrics", "{}", load_failures.join(" "));
+        }
+        Ok(())
+    }
+}
+
+/// Re-verifies a random sample of historical blocks. A block that fails verification is reported
+/// as a critical health event.
+pub struct BlockIntegrityScanJob<B: ManageState<Record=Block>, A: ManageState<Record=AccountState>> {
+    block_state_management: Arc<StateManager<B>>,
+    account_state_management: Arc<StateManager<A>>,
+    verifier: BlockVerifier,
+    health_events: HealthEvents,
+}
+
+impl<B: ManageState<Record=Block>, A: ManageState<Record=AccountState>> BlockIntegrityScanJob<B, A> {
+    pub fn new(block_state_management: Arc<StateManager<B>>, account_state_management: Arc<StateManager<A>>, verifier: BlockVerifier, health_events: HealthEvents) -> Self {
+        BlockIntegrityScanJob { block_state_management, account_state_management, verifier, health_events }
+    }
+}
+
+#[async_trait]
+impl<B: ManageState<Record=Block> + Send + Sync, A: ManageState<Record=AccountState> + Send + Sync> ScheduledJob for BlockIntegrityScanJob<B, A> {
+    fn name(&self) -> &'static str {
+        "block-integrity-scan"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let latest_block_number = match self.block_state_management
+            .get_latest_block_id()
+            .and_then(|id| self.block_state_management.get_state_record(&id)) {
+            Some(block) => block.block_number,
+            None => return Ok(()),
+        };
+
+        for _ in 0..BLOCKS_PER_SCAN.min(latest_block_number as usize) {
+            let block_number = rand::thread_rng().gen_range(1..=latest_block_number);
+            let Some(block) = self.block_state_management.get_state_record(&Block::get_id(block_number)) else {
+                self.health_events.report(Severity::Critical, self.name(), format!("Block {} is missing", block_number));
+                continue;
+            };
+            match self.verifier.verify(&block, &self.block_state_management, &self.account_state_management) {
+                BlockVerification::Verified => {}
+                BlockVerification::Unverifiable(reason) => warn!("Block {} can't be verified: {}", block_number, reason),
+                BlockVerification::Invalid(reason) => {
+                    self.health_events.report(Severity::Critical, self.name(), format!("Block {} failed verification: {}", block_number, reason));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Re-reads the batch data published for the latest block and a random sample of older ones,
+/// and reports a critical health event for any that went missing or no longer match the block,
+/// since the node couldn't be rebuilt from it.
+pub struct BatchDataVerificationJob<B: ManageState<Record=Block>> {
+    block_state_management: Arc<StateManager<B>>,
+    store: Box<dyn BatchDataStore>,
+    signer: Pubkey,
+    allow_unsigned: bool,
+    health_events: HealthEvents,
+}
+
+impl<B: ManageState<Record=Block>> BatchDataVerificationJob<B> {
+    pub fn new(block_state_management: Arc<StateManager<B>>, store: Box<dyn BatchDataStore>, signer: Pubkey, allow_unsigned: bool, health_events: HealthEvents) -> Self {
+        BatchDataVerificationJob { block_state_management, store, signer, allow_unsigned, health_events }
+    }
+}
+
+#[async_trait]
+impl<B: ManageState<Record=Block> + Send + Sync> ScheduledJob for BatchDataVerificationJob<B> {
+    fn name(&self) -> &'static str {
+        "batch-data-verification"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let latest_block_number = match self.block_state_management
+            .get_latest_block_id()
+            .and_then(|id| self.block_state_management.get_state_record(&id)) {
+            Some(block) => block.block_number,
+            None => return Ok(()),
+        };
+
+        let mut block_numbers = vec![latest_block_number];
+        for _ in 0..BLOCKS_PER_SCAN.min(latest_block_number as usize) {
+            block_numbers.push(rand::thread_rng().gen_range(1..=latest_block_number));
+        }
+        for block_number in block_numbers {
+            let Some(block) = self.block_state_management.get_state_record(&Block::get_id(block_number)) else {
+                continue;
+            };
+            if let Err(reason) = batch_data::verify_published(self.store.as_ref(), &block, &self.signer, self.allow_unsigned).await {
+                self.health_events.report(Severity::Critical, self.name(), reason);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Publishes finalized block messages from the outbox to the message bus.
+pub struct OutboxRelayJob {
+    publisher: Arc<dyn MessagePublisher>,
+}
+
+impl OutboxRelayJob {
+    pub fn new(publisher: Arc<dyn MessagePublisher>) -> Self {
+        OutboxRelayJob { publisher }
+    }
+}
+
+#[async_trait]
+impl ScheduledJob for OutboxRelayJob {
+    fn name(&self) -> &'static str {
+        "outbox-relay"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        relay_outbox(self.publisher.as_ref()).await.map(|_| ())
+    }
+}
+
+/// Fills in the L1 slot and signature of recently finalized blocks.
+pub struct L1AnchorJob<B: ManageState<Record=Block>> {
+    tracker: L1AnchorTracker<B>,
+}
+
+impl<B: ManageState<Record=Block>> L1AnchorJob<B> {
+    pub fn new(tracker: L1AnchorTracker<B>) -> Self {
+        L1AnchorJob { tracker }
+    }
+}
+
+#[async_trait]
+impl<B: ManageState<Record=Block> + Send + Sync> ScheduledJob for L1AnchorJob<B> {
+    fn name(&self) -> &'static str {
+        "l1-anchor"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        self.tracker.resolve_pending().await.map(|_| ())
+    }
+}
+
+/// Credits lamport deposits into the L1 vault to their rollup recipients with a transfer from the
+/// sequencer, the way the inbox pays out cross-rollup messages. Deposits are recorded in
+/// `DEPOSITS_DB_PATH` so each one is credited once. Token deposits aren't credited yet.
+pub struct DepositCreditJob<D: ManageState<Record=CreditedDeposit>> {
+    transaction_pool: Arc<Mutex<TransactionPool>>,
+    deposits: Arc<StateManager<D>>,
+    rpc_client: RpcClient,
+    vault_program_id: Pubkey,
+    sequencer: Keypair,
+    /// Newest vault transaction read, the next run only reads later ones
+    cursor: Mutex<Option<Signature>>,
+}
+
+impl<D: ManageState<Record=CreditedDeposit>> DepositCreditJob<D> {
+    pub fn new(transaction_pool: Arc<Mutex<TransactionPool>>, deposits: Arc<StateManager<D>>, rpc_url: String, vault_program_id: Pubkey, sequencer: Keypair) -> Self {
+        DepositCreditJob {
+            transaction_pool,
+            deposits,
+            rpc_client: RpcClient::new_with_commitment(rpc_url, CommitmentConfig::finalized()),
+            vault_program_id,
+            sequencer,
+            cursor: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl<D: ManageState<Record=CreditedDeposit> + Send + Sync> ScheduledJob for DepositCreditJob<D> {
+    fn name(&self) -> &'static str {
+        "vault-deposits"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let mut cursor = self.cursor.lock().await;
+        let (deposits, newest) = vault_reconciliation::fetch_lamport_deposits(&self.rpc_client, &self.vault_program_id, *cursor).await?;
+
+        let mut pool = self.transaction_pool.lock().await;
+        for deposit in deposits {
+            let l1_signature = deposit.l1_signature.to_string();
+            let key = CreditedDeposit::key(&l1_signature, deposit.instruction_index);
+            if self.deposits.get_state_record(&key).is_some() {
+                continue;
+            }
+            let mut transfer = Transaction::new_with_payer(
+                &[system_instruction::transfer(&self.sequencer.pubkey(), &deposit.rollup_recipient, deposit.amount)],
+                Some(&self.sequencer.pubkey()),
+            );
+            // The deposit key stands in for a blockhash, making every credit a distinct transaction
+            transfer.sign(&[&self.sequencer], Hash::new_from_array(key));
+            let credit_signature = transfer.signatures[0].to_string();
+            let trollup_transaction = convert_to_trollup_transaction(transfer)
+                .map_err(|error| anyhow::anyhow!("Failed to build the credit of {}: {}", l1_signature, error))?;
+
+            self.deposits.set_state_record(&CreditedDeposit {
+                l1_signature,
+                instruction_index: deposit.instruction_index,
+                rollup_recipient: deposit.rollup_recipient,
+                lamports: deposit.amount,
+                credit_signature,
+            });
+            self.deposits.commit();
+            sequencing_feed::append(&trollup_transaction);
+            pool.add_transaction(trollup_transaction);
+            info!("Credited the deposit of {} lamports in {} to {}", deposit.amount, deposit.l1_signature, deposit.rollup_recipient);
+        }
+        *cursor = newest;
+        Ok(())
+    }
+}
+
+/// Compares the L1 vault against its recorded liabilities and the rollup supply. A vault holding
+/// less than it owes is critical, a rollup holding more bridged tokens than were deposited is a
+/// warning since deposits can still be in flight.
+pub struct VaultReconciliationJob<A: ManageState<Record=AccountState>> {
+    reconciler: VaultReconciler<A>,
+    health_events: HealthEvents,
+}
+
+impl<A: ManageState<Record=AccountState>> VaultReconciliationJob<A> {
+    pub fn new(reconciler: VaultReconciler<A>, health_events: HealthEvents) -> Self {
+        VaultReconciliationJob { reconciler, health_events }
+    }
+}
+
+#[async_trait]
+impl<A: ManageState<Record=AccountState> + Send + Sync> ScheduledJob for VaultReconciliationJob<A> {
+    fn name(&self) -> &'static str {
+        "vault-reconciliation"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let reconciliation = self.reconciler.reconcile().await?;
+        info!("Vault reconciliation: {:?}", reconciliation);
+        for asset in std::iter::once(&reconciliation.lamports).chain(reconciliation.tokens.iter()) {
+            let name = asset.mint.as_deref().unwrap_or("lamports");
+            if !asset.is_solvent() {
+                self.health_events.report(Severity::Critical, self.name(), format!("Vault holds {} {} but owes {}", asset.vault_balance, name, asset.liabilities));
+            }
+            if asset.mint.is_some() && !asset.is_backed() {
+                self.health_events.report(Severity::Warning, self.name(), format!("Rollup supply of {} is {} but vault liabilities are {}", name, asset.rollup_supply, asset.liabilities));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pays out the approved withdrawals whose `WITHDRAWAL_BATCH_WINDOW_SECS` window closed, in
+/// batches proven by one multiproof each, see `withdrawal_aggregator`.
+pub struct WithdrawalBatchJob;
+
+#[async_trait]
+impl ScheduledJob for WithdrawalBatchJob {
+    fn name(&self) -> &'static str {
+        "withdrawal-batches"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        withdrawal_aggregator::pay_closed_windows().await.map(|_| ())
+    }
+}
+
+/// The stores a rollback to L1 rewinds, see `backfill::roll_back_to_l1`.
+pub struct L1Rollback<A, B, T>
+where
+    A: ManageState<Record=AccountState>,
+    B: ManageState<Record=Block>,
+    T: ManageState<Record=TrollupTransaction>,
+{
+    pub config: TrollupConfig,
+    pub account_state_manager: Arc<StateManager<A>>,
+    pub block_state_manager: Arc<StateManager<B>>,
+    pub transaction_state_manager: Arc<StateManager<T>>,
+}
+
+/// Compares the root committed on L1 with the local chain. A divergence that outlasts the
+/// tolerance window is reported as a critical health event and paged to the webhook, and halts
+/// commitments, which rolls back the pending optimistic commitments. Once the engine and the
+/// committer paused, the local chain is rolled back to the last block L1 committed and rebuilt
+/// from L1, and commitments resume when the roots match again. A failed rollback leaves
+/// commitments halted for an operator and is tried again on the next run.
+pub struct L1DivergenceJob<A, B, T>
+where
+    A: ManageState<Record=AccountState>,
+    B: ManageState<Record=Block>,
+    T: ManageState<Record=TrollupTransaction>,
+{
+    watch: L1RootWatch<B>,
+    rollback: L1Rollback<A, B, T>,
+    health_events: HealthEvents,
+    tolerance: Duration,
+    webhook_url: String,
+    diverged_since: std::sync::Mutex<Option<Instant>>,
+    /// Whether this job halted commitments, an operator's halt is left alone
+    halted: AtomicBool,
+}
+
+impl<A, B, T> L1DivergenceJob<A, B, T>
+where
+    A: ManageState<Record=AccountState>,
+    B: ManageState<Record=Block>,
+    T: ManageState<Record=TrollupTransaction>,
+{
+    pub fn new(watch: L1RootWatch<B>, rollback: L1Rollback<A, B, T>, health_events: HealthEvents, tolerance: Duration, webhook_url: String) -> Self {
+        L1DivergenceJob {
+            watch,
+            rollback,
+            health_events,
+            tolerance,
+            webhook_url,
+            diverged_since: std::sync::Mutex::new(None),
+            halted: AtomicBool::new(false),
+        }
+    }
+
+    /// Rolls the local chain back to L1 once nothing executes or commits on top of it anymore.
+    async fn roll_back(&self) -> anyhow::Result<()> {
+        if !rollup_status::is_paused() {
+            info!("Waiting for the committer to pause before rolling back to L1");
+            return Ok(());
+        }
+        let report = roll_back_to_l1(
+            &self.rollback.config,
+            &self.rollback.account_state_manager,
+            &self.rollback.block_state_manager,
+            &self.rollback.transaction_state_manager,
+        ).await;
+        let report = match report {
+            Ok(report) => report,
+            Err(error) => {
+                let message = format!("Rolling back to L1 failed, commitments stay halted: {}", error);
+                self.health_events.report(Severity::Critical, self.name(), message.clone());
+                page(&self.webhook_url, "critical", "l1-root-rollback-failed", &message).await;
+                return Ok(());
+            }
+        };
+        let comparison = self.watch.compare().await?;
+        if comparison.is_divergent() {
+            warn!("Still diverged from L1 after rolling back to block {}: {}", report.common_block, comparison);
+            return Ok(());
+        }
+        self.resume(format!("Rolled back {} blocks to block {} and rebuilt {:?}, {}", report.discarded_blocks, report.common_block, report.backfill, comparison)).await;
+        Ok(())
+    }
+
+    async fn resume(&self, message: String) {
+        self.halted.store(false, Ordering::SeqCst);
+        *self.diverged_since.lock().unwrap() = None;
+        rollup_status::resume_commitments();
+        self.health_events.resolve(self.name());
+        self.health_events.report(Severity::Warning, self.name(), message.clone());
+        page(&self.webhook_url, "warning", "l1-root-divergence-resolved", &message).await;
+    }
+}
+
+/// Posts an alert to an operator webhook, nothing when `webhook_url` is empty.
+async fn page(webhook_url: &str, severity: &str, event: &str, message: &str) {
+    if webhook_url.is_empty() {
+        return;
+    }
+    let payload = json!({
+        "source": "trollup-api",
+        "severity": severity,
+        "event": event,
+        "message": message,
+    });
+    let request = match http_client::canonical_json_body(http_client::shared_client().post(webhook_url), &payload) {
+        Ok(request) => request,
+        Err(error) => {
+            error!("Failed to page {}: {}", event, error);
+            return;
+        }
+    };
+    if let Err(error) = request.send().await.and_then(|response| response.error_for_status()) {
+        error!("Failed to page {}: {}", event, error);
+    }
+}
+
+#[async_trait]
+impl<A, B, T> ScheduledJob for L1DivergenceJob<A, B, T>
+where
+    A: ManageState<Record=AccountState> + Send + Sync,
+    B: ManageState<Record=Block> + Send + Sync,
+    T: ManageState<Record=TrollupTransaction> + Send + Sync,
+{
+    fn name(&self) -> &'static str {
+        "l1-divergence"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        if self.halted.load(Ordering::SeqCst) {
+            return self.roll_back().await;
+        }
+        let comparison = self.watch.compare().await?;
+        if !comparison.is_divergent() {
+            *self.diverged_since.lock().unwrap() = None;
+            return Ok(());
+        }
+
+        let diverged_for = self.diverged_since.lock().unwrap().get_or_insert_with(Instant::now).elapsed();
+        if diverged_for < self.tolerance {
+            warn!("L1 root divergence for {:?}: {}", diverged_for, comparison);
+            return Ok(());
+        }
+        if rollup_status::commitments_halted() {
+            return Ok(());
+        }
+
+        let message = format!("{} for {:?}, commitments are halted and the chain is rolled back to L1", comparison, diverged_for);
+        self.halted.store(true, Ordering::SeqCst);
+        rollup_status::halt_commitments();
+        self.health_events.report(Severity::Critical, self.name(), message.clone());
+        page(&self.webhook_url, "critical", "l1-root-divergence", &message).await;
+        Ok(())
+    }
+}
+
+/// Reads the commitment fee payer's balance from L1. Dropping below `FEE_PAYER_LOW_BALANCE_LAMPORTS`
+/// is reported as a warning and paged once, until the balance recovers; a balance too low for the
+/// last L1 transaction turns `/health` unhealthy while it lasts.
+pub struct FeePayerBalanceJob {
+    rpc_client: RpcClient,
+    fee_payer: Pubkey,
+    low_balance_threshold: u64,
+    webhook_url: String,
+    health_events: HealthEvents,
+    low: AtomicBool,
+}
+
+impl FeePayerBalanceJob {
+    pub fn new(rpc_url: String, fee_payer: Pubkey, low_balance_threshold: u64, webhook_url: String, health_events: HealthEvents) -> Self {
+        FeePayerBalanceJob {
+            rpc_client: RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed()),
+            fee_payer,
+            low_balance_threshold,
+            webhook_url,
+            health_events,
+            low: AtomicBool::new(false),
+        }
+    }
+}
+
+#[async_trait]
+impl ScheduledJob for FeePayerBalanceJob {
+    fn name(&self) -> &'static str {
+        "fee-payer-balance"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let balance = fee_payer::check_balance(&self.rpc_client, &self.fee_payer, self.low_balance_threshold).await?;
+        let was_low = self.low.swap(balance.is_low(), Ordering::SeqCst);
+        if balance.is_low() && !was_low {
+            let message = format!("Low balance: {}", balance);
+            self.health_events.report(Severity::Warning, self.name(), message.clone());
+            page(&self.webhook_url, "warning", "fee-payer-low-balance", &message).await;
+        } else if !balance.is_low() && was_low {
+            info!("Fee payer balance recovered: {}", balance);
+        }
+        Ok(())
+    }
+}
+
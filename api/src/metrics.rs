@@ -0,0 +1,85 @@
+//! Prometheus metrics of the API, served on `/metrics` in the text exposition format.
+//!
+//! Every response is counted by route, method and status and timed by route, see
+//! `record_request`. The transaction pool gauges are read when the endpoint is scraped.
+
+use crate::routes::API_VERSION;
+use execution::transaction_pool::TransactionPool;
+use lazy_static::lazy_static;
+use prometheus::{register_gauge, register_histogram_vec, register_int_counter_vec, register_int_gauge, Encoder, Gauge, HistogramVec, IntCounterVec, IntGauge, TextEncoder};
+use server::error::ApiError;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use warp::http::header::CONTENT_TYPE;
+use warp::log::Info;
+use warp::reply::Response;
+use warp::Reply;
+
+/// Distinct route labels, requests for paths past the limit are counted as `other`. Bounds the
+/// series requests for arbitrary paths can create.
+const MAX_ROUTE_LABELS: usize = 128;
+
+lazy_static! {
+    static ref REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "trollup_api_requests_total", "Requests answered, by route, method and status", &["route", "method", "status"]
+    ).unwrap();
+    static ref REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "trollup_api_request_duration_seconds", "Time to answer a request, by route", &["route"]
+    ).unwrap();
+    static ref POOL_DEPTH: IntGauge = register_int_gauge!(
+        "trollup_api_transaction_pool_depth", "Transactions waiting in the pool, system lane included"
+    ).unwrap();
+    static ref SYSTEM_LANE_DEPTH: IntGauge = register_int_gauge!(
+        "trollup_api_system_lane_depth", "Operator transactions waiting in the system lane"
+    ).unwrap();
+    static ref POOL_OLDEST_AGE: Gauge = register_gauge!(
+        "trollup_api_transaction_pool_oldest_age_seconds", "How long the longest waiting transaction has been in the pool"
+    ).unwrap();
+    static ref ROUTE_LABELS: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+/// For `warp::log::custom` on the complete router, so rejections are counted with the status
+/// they were answered with.
+pub fn record_request(info: Info) {
+    let route = route_label(info.path());
+    let status = info.status();
+    REQUESTS.with_label_values(&[&route, info.method().as_str(), status.as_str()]).inc();
+    REQUEST_DURATION.with_label_values(&[&route]).observe(info.elapsed().as_secs_f64());
+}
+
+/// The route a path belongs to, without the version prefix and the path parameters: the first
+/// segment, or the first two under `admin`.
+fn route_label(path: &str) -> String {
+    let mut segments = path.trim_start_matches('/').split('/').filter(|segment| !segment.is_empty()).peekable();
+    if segments.peek() == Some(&API_VERSION) {
+        segments.next();
+    }
+    let route = match segments.next() {
+        Some("admin") => format!("admin/{}", segments.next().unwrap_or_default()),
+        Some(segment) => segment.to_string(),
+        None => "/".to_string(),
+    };
+    let mut labels = ROUTE_LABELS.lock().unwrap();
+    if labels.contains(&route) || labels.len() < MAX_ROUTE_LABELS {
+        labels.insert(route.clone());
+        route
+    } else {
+        "other".to_string()
+    }
+}
+
+/// Every registered metric, with the pool gauges read from `pool`.
+pub async fn metrics_handler(pool: Arc<tokio::sync::Mutex<TransactionPool>>) -> Result<Response, warp::Rejection> {
+    {
+        let pool = pool.lock().await;
+        POOL_DEPTH.set(pool.pool_size() as i64);
+        SYSTEM_LANE_DEPTH.set(pool.system_lane_size() as i64);
+        POOL_OLDEST_AGE.set(pool.oldest_age().unwrap_or_default().as_secs_f64());
+    }
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    if let Err(error) = encoder.encode(&prometheus::gather(), &mut body) {
+        return Ok(ApiError::internal(format!("Failed to encode metrics: {}", error)).into_response());
+    }
+    Ok(warp::reply::with_header(body, CONTENT_TYPE, encoder.format_type()).into_response())
+}
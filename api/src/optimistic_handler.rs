@@ -1,38 +1,91 @@
 use base64::{engine::general_purpose, Engine as _};
-use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+use server::error::ApiError;
 use state::account_state::AccountState;
-use state::config::TrollupConfig;
+use state::blob::{Blob, BlobReferences};
 use state::state_record::{StateCommitmentPackage, StateCommitmentPackageUI};
+use state_commitment::governance;
+use state_commitment::optimistic_commitments::{self, CommitmentRecord};
+use state_management::blob_store::BlobStore;
 use state_management::state_management::{ManageState, StateManager};
 use std::sync::Arc;
+use std::time::Duration;
 use warp::{reply::json, Rejection, Reply};
 
 type Result<T> = std::result::Result<T, Rejection>;
 
-lazy_static! {
-    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+/// An account of a pending commitment, as currently committed and as the commitment proposes it.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDiff {
+    pub address: String,
+    /// `None` when the commitment creates the account
+    pub committed: Option<AccountState>,
+    pub proposed: AccountState,
+    /// Fields that differ, empty when the commitment leaves the account unchanged
+    pub changed: Vec<&'static str>,
 }
 
-pub struct OptimisticHandler<T: ManageState<Record=StateCommitmentPackage<AccountState>>> {
+impl AccountDiff {
+    fn new(committed: Option<AccountState>, proposed: AccountState) -> Self {
+        let changed = match &committed {
+            None => vec!["created"],
+            Some(committed) => [
+                ("lamports", committed.lamports != proposed.lamports),
+                ("data", committed.data != proposed.data),
+                ("owner", committed.owner != proposed.owner),
+                ("executable", committed.executable != proposed.executable),
+                ("rentEpoch", committed.rent_epoch != proposed.rent_epoch),
+            ]
+            .into_iter()
+            .filter_map(|(field, differs)| differs.then_some(field))
+            .collect(),
+        };
+        AccountDiff { address: proposed.address.to_string(), committed, proposed, changed }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingCommitmentDiff {
+    pub state_root: Option<[u8; 32]>,
+    pub parent_state_root: Option<[u8; 32]>,
+    pub accounts: Vec<AccountDiff>,
+}
+
+/// Commitments pending for at least `older_than_secs`, by default for longer than their window.
+pub fn stuck_commitments(older_than_secs: Option<u64>) -> Vec<CommitmentRecord> {
+    optimistic_commitments::stuck(older_than_secs.map(Duration::from_secs).unwrap_or_else(|| governance::current().challenge_window()))
+}
+
+pub struct OptimisticHandler<T: ManageState<Record=StateCommitmentPackage<AccountState>>, L: ManageState<Record=Blob>, R: ManageState<Record=BlobReferences>> {
     optimistic_commitment_state_management: Arc<StateManager<T>>,
+    blob_store: Arc<BlobStore<L, R>>,
 }
 
-impl <T: ManageState<Record=StateCommitmentPackage<AccountState>>> OptimisticHandler<T> {
-    pub fn new(optimistic_commitment_state_management: Arc<StateManager<T>>) -> Self {
-        OptimisticHandler { optimistic_commitment_state_management }
+impl <T: ManageState<Record=StateCommitmentPackage<AccountState>>, L: ManageState<Record=Blob>, R: ManageState<Record=BlobReferences>> OptimisticHandler<T, L, R> {
+    pub fn new(optimistic_commitment_state_management: Arc<StateManager<T>>, blob_store: Arc<BlobStore<L, R>>) -> Self {
+        OptimisticHandler { optimistic_commitment_state_management, blob_store }
     }
 
     pub async fn get_pending_transaction_batch(&self, state_root: &str) -> Result<impl Reply> {
-        let state_root_result = general_purpose::URL_SAFE.decode(state_root).expect("Error decoding state root.");
-        let new_state_root_bytes: &[u8; 32] = <&[u8; 32]>::try_from(state_root_result.as_slice()).unwrap();
+        let state_root_result = match general_purpose::URL_SAFE.decode(state_root) {
+            Ok(bytes) => bytes,
+            Err(_) => return Ok(ApiError::invalid_request(format!("Invalid state root: {:?}", state_root)).into_response()),
+        };
+        let new_state_root_bytes: &[u8; 32] = match <&[u8; 32]>::try_from(state_root_result.as_slice()) {
+            Ok(bytes) => bytes,
+            Err(_) => return Ok(ApiError::invalid_request(format!("Invalid state root: {:?}", state_root)).into_response()),
+        };
         let option = self.optimistic_commitment_state_management.get_state_record(new_state_root_bytes);
         match option {
             None => {
-                Ok(json(&format!("No pending batches found for: {:?}", state_root_result)))
+                Ok(ApiError::not_found(format!("No pending batches found for: {:?}", state_root_result)).into_response())
             }
             Some(pending_commitment) => {
+                let pending_commitment = self.blob_store.get_package(pending_commitment);
                 let ui_package: StateCommitmentPackageUI<AccountState> = (&pending_commitment).into();
-                Ok(json(&ui_package))
+                Ok(json(&ui_package).into_response())
             }
         }
     }
@@ -41,9 +94,35 @@ impl <T: ManageState<Record=StateCommitmentPackage<AccountState>>> OptimisticHan
         let pending_commitments: Vec<([u8; 32], StateCommitmentPackage<AccountState>)> = self.optimistic_commitment_state_management.get_all_entries();
         let mut ui_pending_commitments = Vec::with_capacity(pending_commitments.iter().len());
         for (_, value) in pending_commitments {
-            ui_pending_commitments.push(value.to_ui_package());
+            ui_pending_commitments.push(self.blob_store.get_package(value).to_ui_package());
         }
         Ok(json(&ui_pending_commitments))
     }
 
+    /// Every account of the pending commitment next to its currently committed state, so a
+    /// watcher deciding whether to challenge doesn't have to rebuild the diff.
+    pub async fn get_pending_commitment_diff<A: ManageState<Record=AccountState>>(
+        &self,
+        state_root: &str,
+        account_state_management: &StateManager<A>,
+    ) -> Result<impl Reply> {
+        let state_root_bytes = match general_purpose::URL_SAFE.decode(state_root).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) {
+            Some(bytes) => bytes,
+            None => return Ok(ApiError::invalid_request(format!("Invalid state root: {:?}", state_root)).into_response()),
+        };
+        let pending_commitment = match self.optimistic_commitment_state_management.get_state_record(&state_root_bytes) {
+            Some(pending_commitment) => self.blob_store.get_package(pending_commitment),
+            None => return Ok(ApiError::not_found(format!("No pending batches found for: {:?}", state_root)).into_response()),
+        };
+        let accounts = pending_commitment
+            .state_records
+            .into_iter()
+            .map(|proposed| AccountDiff::new(account_state_management.get_state_record(&proposed.address.to_bytes()), proposed))
+            .collect();
+        Ok(json(&PendingCommitmentDiff {
+            state_root: pending_commitment.state_root,
+            parent_state_root: pending_commitment.parent_state_root,
+            accounts,
+        }).into_response())
+    }
 }
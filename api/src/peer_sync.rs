@@ -0,0 +1,48 @@
+use crate::routes::{API_VERSION, API_VERSIONS};
+use log::info;
+use solana_sdk::signature::Keypair;
+use state::block::Block;
+use state::node_info::{handshake, NodeInfo, PROTOCOL_VERSIONS, SCHEMA_VERSION};
+use state_commitment::http_client;
+use state_commitment::rollup_status::NODE_VERSION;
+use state_management::state_management::{ManageState, StateManager};
+
+/// This node's info, signed with `identity`. Left unsigned without an identity, peers then
+/// refuse to sync from the node.
+pub fn local_node_info<B: ManageState<Record = Block>>(blocks: &StateManager<B>, identity: Option<&Keypair>) -> NodeInfo {
+    let latest = blocks.get_latest_block_id().and_then(|id| blocks.get_state_record(&id));
+    let info = NodeInfo {
+        identity: String::new(),
+        genesis_hash: blocks.get_state_record(&Block::get_id(1)).map(|genesis| hex::encode(genesis.hash())),
+        latest_block: latest.as_ref().map(|block| block.block_number).unwrap_or_default(),
+        latest_block_hash: latest.map(|block| hex::encode(block.hash())),
+        node_version: NODE_VERSION.to_string(),
+        schema_version: SCHEMA_VERSION,
+        protocol_versions: PROTOCOL_VERSIONS.to_vec(),
+        api_versions: API_VERSIONS.iter().map(|version| version.to_string()).collect(),
+        signature: None,
+    };
+    match identity {
+        Some(identity) => info.sign(identity),
+        None => info,
+    }
+}
+
+/// Fetches the info of the node at `peer_url` and runs the handshake against it, see
+/// `state::node_info::handshake`. Returns the protocol version to sync with.
+pub async fn handshake_with_peer(peer_url: &str, local: &NodeInfo) -> Result<u32, String> {
+    let url = format!("{}/{}/node-info", peer_url.trim_end_matches('/'), API_VERSION);
+    let peer: NodeInfo = http_client::shared_client()
+        .get(&url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|error| format!("Failed to reach {}: {}", url, error))?
+        .json()
+        .await
+        .map_err(|error| format!("Invalid node info from {}: {}", url, error))?;
+    let protocol_version = handshake(local, &peer)?;
+    info!("Handshake with node {} at {} succeeded, protocol version {}, its latest block {}",
+        peer.identity, peer_url, protocol_version, peer.latest_block);
+    Ok(protocol_version)
+}
@@ -0,0 +1,162 @@
+//! Per-client rate limit of the submission routes, so one client can't fill the transaction pool
+//! and starve everyone else of the engine. Every client has a token bucket that refills at a
+//! steady rate, a submission takes a token per transaction and is refused with 429 when there
+//! are not enough. Clients are told apart by address, see `client_ip::attribution`.
+
+use log::debug;
+use serde_derive::{Deserialize, Serialize};
+use server::client_ip::attribution;
+use server::error::{ApiError, ErrorCode};
+use server::http::client_ip;
+use state::config::TrollupConfig;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use warp::{Filter, Rejection};
+
+/// Buckets kept at most, the bucket of the client seen least recently makes room for a new one.
+const MAX_TRACKED_CLIENTS: usize = 100_000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitSettings {
+    /// Submissions a client can make per second on average, 0 turns the limit off
+    pub per_second: u32,
+    /// Submissions a client can make at once after being idle, at least 1
+    pub burst: u32,
+}
+
+impl RateLimitSettings {
+    pub fn from_config(config: &TrollupConfig) -> Self {
+        RateLimitSettings {
+            per_second: config.submission_rate_limit,
+            burst: config.submission_rate_burst,
+        }
+    }
+
+    fn capacity(&self) -> f64 {
+        self.burst.max(1) as f64
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Position in `Buckets::recency`
+    last_used: u64,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant, settings: &RateLimitSettings) {
+        let refilled = now.saturating_duration_since(self.updated).as_secs_f64() * settings.per_second as f64;
+        self.tokens = (self.tokens + refilled).min(settings.capacity());
+        self.updated = now;
+    }
+}
+
+/// The buckets of the clients seen most recently, in least recently used order.
+#[derive(Default)]
+struct Buckets {
+    by_client: HashMap<IpAddr, Bucket>,
+    recency: BTreeMap<u64, IpAddr>,
+    uses: u64,
+}
+
+impl Buckets {
+    /// The bucket of `client`, a full one for a client not tracked. Marks it most recently used
+    /// and drops the least recently used bucket when there are `capacity` of them.
+    fn touch(&mut self, client: IpAddr, now: Instant, tokens: f64, capacity: usize) -> &mut Bucket {
+        self.uses += 1;
+        let last_used = self.uses;
+        match self.by_client.get_mut(&client) {
+            Some(bucket) => {
+                self.recency.remove(&bucket.last_used);
+                bucket.last_used = last_used;
+            }
+            None => {
+                if self.by_client.len() >= capacity {
+                    if let Some((_, evicted)) = self.recency.pop_first() {
+                        self.by_client.remove(&evicted);
+                    }
+                }
+                self.by_client.insert(client, Bucket { tokens, updated: now, last_used });
+            }
+        }
+        self.recency.insert(last_used, client);
+        self.by_client.get_mut(&client).expect("Bucket was just inserted")
+    }
+}
+
+/// The token buckets of every client, shared by the submission routes. Kept in memory, they start
+/// over with the process.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings) -> Self {
+        RateLimiter { settings, ..Default::default() }
+    }
+
+    pub fn from_config(config: &TrollupConfig) -> Self {
+        Self::new(RateLimitSettings::from_config(config))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings.per_second > 0
+    }
+
+    /// Takes a token from the bucket of `ip`, or returns how long until the next one.
+    pub fn acquire(&self, ip: IpAddr) -> Result<(), Duration> {
+        self.acquire_many(ip, 1)
+    }
+
+    /// Takes a token per transaction from the bucket of `ip`, or none and returns how long until
+    /// there are enough. More transactions than the burst take a full bucket.
+    pub fn acquire_many(&self, ip: IpAddr, transactions: usize) -> Result<(), Duration> {
+        if !self.is_enabled() || transactions == 0 {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let capacity = self.settings.capacity();
+        let cost = (transactions as f64).min(capacity);
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.touch(attribution(ip), now, capacity, MAX_TRACKED_CLIENTS);
+        bucket.refill(now, &self.settings);
+        if bucket.tokens >= cost {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((cost - bucket.tokens) / self.settings.per_second as f64))
+        }
+    }
+}
+
+/// Takes the tokens of `transactions` from the bucket of `client`, a too many requests rejection
+/// when there are not enough. Requests without a client address pass.
+pub fn charge(limiter: &RateLimiter, client: Option<IpAddr>, transactions: usize) -> Result<(), Rejection> {
+    match client.map(|ip| (ip, limiter.acquire_many(ip, transactions))) {
+        Some((ip, Err(wait))) => {
+            debug!("Rate limited submission of {} transactions from {}", transactions, ip);
+            let message = format!("Too many submissions, retry in {} ms", wait.as_millis().max(1));
+            Err(warp::reject::custom(ApiError::new(ErrorCode::TooManyRequests, message)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Rejects with too many requests once the client used up its bucket, before the body is read.
+/// Takes the token of one transaction, routes taking several charge the rest with `charge` once
+/// they read them. Requests without a client address pass.
+pub fn limit_submissions(limiter: RateLimiter) -> impl Filter<Extract=(), Error=Rejection> + Clone {
+    client_ip()
+        .and_then(move |client: Option<IpAddr>| {
+            let limiter = limiter.clone();
+            async move { charge(&limiter, client, 1) }
+        })
+        .untuple_one()
+}
+
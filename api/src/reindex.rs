@@ -0,0 +1,101 @@
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use state::block::Block;
+use state::config::TrollupConfig;
+use state_commitment::{merkle_index, transaction_index};
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::StateManager;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref PROGRESS: Mutex<ReindexProgress> = Mutex::new(ReindexProgress::default());
+}
+
+/// Body of `/admin/reindex`, the state of the current or last rebuild.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReindexProgress {
+    pub running: bool,
+    pub blocks_done: u64,
+    pub blocks_total: u64,
+    /// Unix timestamps of the last run
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub error: Option<String>,
+}
+
+pub fn progress() -> ReindexProgress {
+    PROGRESS.lock().unwrap().clone()
+}
+
+/// Starts rebuilding the secondary indexes from the stored blocks in the background. Returns
+/// `false` without starting anything when a rebuild is already running.
+pub fn start(block_state_manager: Arc<StateManager<SledStateManagement<Block>>>) -> bool {
+    let blocks_total = block_state_manager
+        .get_latest_block_id()
+        .and_then(|id| block_state_manager.get_state_record(&id))
+        .map(|block| block.block_number)
+        .unwrap_or_default();
+    {
+        let mut progress = PROGRESS.lock().unwrap();
+        if progress.running {
+            return false;
+        }
+        *progress = ReindexProgress {
+            running: true,
+            blocks_total,
+            started_at: Some(unix_now()),
+            ..Default::default()
+        };
+    }
+
+    tokio::spawn(async move {
+        info!("Rebuilding the transaction and Merkle indexes from {} blocks", blocks_total);
+        let error = rebuild(&block_state_manager, blocks_total).await.err();
+        if let Some(error) = &error {
+            warn!("Rebuilding the indexes failed: {}", error);
+        }
+        let mut progress = PROGRESS.lock().unwrap();
+        progress.running = false;
+        progress.finished_at = Some(unix_now());
+        progress.error = error;
+    });
+    true
+}
+
+/// Indexes blocks 1 to `blocks_total`, sleeping as needed to stay under
+/// `REINDEX_BLOCKS_PER_SECOND` so the rebuild shares the databases with live traffic.
+async fn rebuild(block_state_manager: &StateManager<SledStateManagement<Block>>, blocks_total: u64) -> Result<(), String> {
+    let blocks_per_second = CONFIG.reindex_blocks_per_second.max(1);
+    let started = Instant::now();
+    transaction_index::clear();
+    merkle_index::clear();
+
+    for block_number in 1..=blocks_total {
+        let block = block_state_manager
+            .get_state_record(&Block::get_id(block_number))
+            .ok_or_else(|| format!("Block {} is missing", block_number))?;
+        transaction_index::record_block(&block);
+        merkle_index::record_block(&block);
+        PROGRESS.lock().unwrap().blocks_done = block_number;
+
+        if block_number % blocks_per_second == 0 {
+            transaction_index::commit();
+            merkle_index::commit();
+        }
+        let due = Duration::from_secs_f64(block_number as f64 / blocks_per_second as f64);
+        if let Some(ahead) = due.checked_sub(started.elapsed()) {
+            tokio::time::sleep(ahead).await;
+        }
+    }
+    transaction_index::commit();
+    merkle_index::commit();
+    Ok(())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
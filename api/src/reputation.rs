@@ -0,0 +1,294 @@
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::Transaction;
+use server::client_ip;
+use server::error::{ApiError, ErrorCode};
+use state::config::TrollupConfig;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use warp::reply::Response;
+use warp::Reply;
+
+/// Sources tracked before idle ones without a ban are dropped.
+const MAX_TRACKED_SOURCES: usize = 100_000;
+/// Signatures remembered to recognize resubmissions.
+const MAX_RECENT_SIGNATURES: usize = 100_000;
+
+/// Who a submission is attributed to. A submission counts against each of its sources, so a
+/// payer hopping between addresses is caught as well as an address cycling payers.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum Source {
+    /// The client address, IPv6 ones reduced to their /64, see `client_ip::attribution`
+    Ip(IpAddr),
+    /// Short SHA-256 fingerprint of the `X-API-Key` header, the key itself is never kept
+    ApiKey(String),
+    Payer(Pubkey),
+}
+
+impl Source {
+    pub fn ip(ip: IpAddr) -> Self {
+        Source::Ip(client_ip::attribution(ip))
+    }
+
+    pub fn api_key(key: &str) -> Self {
+        Source::ApiKey(hex::encode(&Sha256::digest(key.as_bytes())[..8]))
+    }
+}
+
+/// `ip:<address>`, `apiKey:<fingerprint>` or `payer:<base58 pubkey>`, the form the admin routes
+/// take.
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Ip(ip) => write!(f, "ip:{}", ip),
+            Source::ApiKey(fingerprint) => write!(f, "apiKey:{}", fingerprint),
+            Source::Payer(payer) => write!(f, "payer:{}", payer),
+        }
+    }
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid source {:?}, expected ip:<address>, apiKey:<fingerprint> or payer:<pubkey>", value);
+        match value.split_once(':').ok_or_else(invalid)? {
+            ("ip", ip) => ip.parse().map(Source::ip).map_err(|_| invalid()),
+            ("apiKey", fingerprint) if !fingerprint.is_empty() => Ok(Source::ApiKey(fingerprint.to_string())),
+            ("payer", payer) => Pubkey::from_str(payer).map(Source::Payer).map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Offence {
+    /// Unsigned, malformed or over the compute limit
+    InvalidSubmission,
+    /// A simulation that ended in a transaction error
+    FailedSimulation,
+    /// A transaction that was already submitted
+    Resubmission,
+}
+
+/// Limits for automatic bans. A source collecting `strike_threshold` offences within
+/// `window_secs` is banned, for `ban_base_secs` the first time and twice as long for every
+/// further ban, up to `ban_max_secs`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ReputationSettings {
+    /// `0` tracks offences without banning automatically
+    pub strike_threshold: u32,
+    pub window_secs: u64,
+    pub ban_base_secs: u64,
+    pub ban_max_secs: u64,
+}
+
+impl ReputationSettings {
+    pub fn from_config(config: &TrollupConfig) -> Self {
+        ReputationSettings {
+            strike_threshold: config.reputation_strike_threshold,
+            window_secs: config.reputation_window_secs,
+            ban_base_secs: config.reputation_ban_base_secs,
+            ban_max_secs: config.reputation_ban_max_secs,
+        }
+    }
+
+    fn ban_duration(&self, previous_bans: u32) -> u64 {
+        let duration = self.ban_base_secs.saturating_mul(1u64.checked_shl(previous_bans).unwrap_or(u64::MAX));
+        duration.min(self.ban_max_secs.max(self.ban_base_secs))
+    }
+}
+
+/// Offences and bans of one source, the body of `/admin/bans`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceRecord {
+    pub source: String,
+    pub invalid_submissions: u64,
+    pub failed_simulations: u64,
+    pub resubmissions: u64,
+    /// Offences in the current window
+    pub strikes: u32,
+    pub window_started_at: u64,
+    pub bans: u32,
+    /// Unix timestamp the current ban ends at
+    pub banned_until: Option<u64>,
+    pub ban_reason: Option<String>,
+}
+
+impl SourceRecord {
+    fn is_banned(&self, now: u64) -> bool {
+        self.banned_until.is_some_and(|until| until > now)
+    }
+}
+
+/// Offence tracking and temporary bans of submitters, shared by the routes that accept
+/// transactions. Everything is in memory and starts over with the process.
+#[derive(Clone, Default)]
+pub struct Reputation {
+    settings: ReputationSettings,
+    records: Arc<Mutex<HashMap<Source, SourceRecord>>>,
+    recent_signatures: Arc<Mutex<HashMap<[u8; 64], u64>>>,
+}
+
+impl Reputation {
+    pub fn new(settings: ReputationSettings) -> Self {
+        Reputation { settings, ..Default::default() }
+    }
+
+    pub fn from_config(config: &TrollupConfig) -> Self {
+        Self::new(ReputationSettings::from_config(config))
+    }
+
+    /// The ban of the first banned source, as the message to reject the request with.
+    pub fn check(&self, sources: &[Source]) -> Result<(), String> {
+        let now = unix_now();
+        let records = self.records.lock().unwrap();
+        for source in sources {
+            if let Some(record) = records.get(source).filter(|record| record.is_banned(now)) {
+                let remaining = record.banned_until.unwrap_or_default() - now;
+                return Err(format!("{} is banned for another {} seconds", source, remaining));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts `offence` against every source, banning the ones that reach the strike threshold.
+    pub fn record(&self, sources: &[Source], offence: Offence) {
+        let now = unix_now();
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= MAX_TRACKED_SOURCES {
+            let window_secs = self.settings.window_secs;
+            records.retain(|_, record| record.is_banned(now) || now.saturating_sub(record.window_started_at) < window_secs);
+        }
+        for source in sources {
+            let record = records.entry(source.clone()).or_insert_with(|| SourceRecord { source: source.to_string(), ..Default::default() });
+            match offence {
+                Offence::InvalidSubmission => record.invalid_submissions += 1,
+                Offence::FailedSimulation => record.failed_simulations += 1,
+                Offence::Resubmission => record.resubmissions += 1,
+            }
+            if now.saturating_sub(record.window_started_at) >= self.settings.window_secs {
+                record.window_started_at = now;
+                record.strikes = 0;
+            }
+            record.strikes += 1;
+            if self.settings.strike_threshold > 0 && record.strikes >= self.settings.strike_threshold && !record.is_banned(now) {
+                let duration = self.settings.ban_duration(record.bans);
+                let reason = format!("{} offences within {} seconds, the last {:?}", record.strikes, self.settings.window_secs, offence);
+                warn!(target: "audit", "Banned {} for {} seconds: {}", source, duration, reason);
+                ban(record, now, duration, reason);
+            }
+        }
+    }
+
+    /// Records a resubmission when `transaction`'s signature was already seen within the window.
+    pub fn record_resubmission(&self, sources: &[Source], transaction: &Transaction) {
+        let Some(signature) = transaction.signatures.first() else {
+            return;
+        };
+        let now = unix_now();
+        let seen = {
+            let mut recent = self.recent_signatures.lock().unwrap();
+            if recent.len() >= MAX_RECENT_SIGNATURES {
+                let window_secs = self.settings.window_secs;
+                recent.retain(|_, seen_at| now.saturating_sub(*seen_at) < window_secs);
+            }
+            recent
+                .insert(<[u8; 64]>::from(*signature), now)
+                .is_some_and(|seen_at| now.saturating_sub(seen_at) < self.settings.window_secs)
+        };
+        if seen {
+            self.record(sources, Offence::Resubmission);
+        }
+    }
+
+    /// Bans `source` for `duration_secs` from now, replacing any current ban.
+    pub fn ban(&self, source: &Source, duration_secs: u64, reason: &str) -> SourceRecord {
+        let mut records = self.records.lock().unwrap();
+        let record = records.entry(source.clone()).or_insert_with(|| SourceRecord { source: source.to_string(), ..Default::default() });
+        info!(target: "audit", "Banned {} for {} seconds by an operator: {}", source, duration_secs, reason);
+        ban(record, unix_now(), duration_secs, reason.to_string());
+        record.clone()
+    }
+
+    /// Lifts the ban of `source`, `false` when it wasn't banned. Its offence history is kept.
+    pub fn unban(&self, source: &Source) -> bool {
+        let now = unix_now();
+        let mut records = self.records.lock().unwrap();
+        let Some(record) = records.get_mut(source).filter(|record| record.is_banned(now)) else {
+            return false;
+        };
+        record.banned_until = None;
+        record.ban_reason = None;
+        record.strikes = 0;
+        info!(target: "audit", "Lifted the ban of {}", source);
+        true
+    }
+
+    /// Every tracked source, banned ones first.
+    pub fn snapshot(&self) -> Vec<SourceRecord> {
+        let now = unix_now();
+        let mut records: Vec<SourceRecord> = self.records.lock().unwrap().values().cloned().collect();
+        records.sort_by(|a, b| b.is_banned(now).cmp(&a.is_banned(now)).then_with(|| a.source.cmp(&b.source)));
+        records
+    }
+}
+
+fn ban(record: &mut SourceRecord, now: u64, duration_secs: u64, reason: String) {
+    record.banned_until = Some(now.saturating_add(duration_secs));
+    record.ban_reason = Some(reason);
+    record.bans += 1;
+    record.strikes = 0;
+}
+
+/// The sources of one request, with the reputation they are checked and recorded against.
+#[derive(Clone)]
+pub struct Submitter {
+    reputation: Reputation,
+    sources: Vec<Source>,
+}
+
+impl Submitter {
+    pub fn new(reputation: Reputation, client_ip: Option<IpAddr>, api_key: Option<String>) -> Self {
+        let sources = client_ip
+            .map(Source::ip)
+            .into_iter()
+            .chain(api_key.as_deref().map(Source::api_key))
+            .collect();
+        Submitter { reputation, sources }
+    }
+
+    fn sources_with_payer(&self, transaction: &Transaction) -> Vec<Source> {
+        let mut sources = self.sources.clone();
+        sources.extend(transaction.message.account_keys.first().map(|payer| Source::Payer(*payer)));
+        sources
+    }
+
+    /// 403 when any source of the request, or the transaction's payer, is banned. Otherwise
+    /// counts a resubmission of a transaction seen before.
+    pub fn admit(&self, transaction: &Transaction) -> Result<(), Response> {
+        let sources = self.sources_with_payer(transaction);
+        self.reputation
+            .check(&sources)
+            .map_err(|message| ApiError::new(ErrorCode::Forbidden, message).into_response())?;
+        self.reputation.record_resubmission(&sources, transaction);
+        Ok(())
+    }
+
+    pub fn offence(&self, transaction: &Transaction, offence: Offence) {
+        self.reputation.record(&self.sources_with_payer(transaction), offence);
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
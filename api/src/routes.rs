@@ -0,0 +1,1139 @@
+use crate::account_handler::{AccountHandler, AccountQuery};
+use crate::auth::{self, AdminToken, ApiKeys};
+use crate::blob_handler::BlobHandler;
+use crate::challenge_handler::{ChallengeHandler, PostChallengeRequest};
+use crate::block_handler::{AccountDataProofQuery, AccountProofQuery, BlockHandler, GetBlockQuery};
+use crate::block_verification::BlockVerifier;
+use crate::cross_rollup_handler::CrossRollupHandler;
+use crate::decode_handler::{self, DecodeTransactionRequest};
+use crate::export::ExportQuery;
+use crate::governance_handler::{self, ApproveProposalRequest, ParametersQuery, ProposeParametersRequest};
+use crate::handler::Handler;
+use crate::health::HealthEvents;
+use crate::load_shedding::SheddingThresholds;
+use crate::metrics;
+use crate::rate_limit::{self, RateLimiter};
+use crate::optimistic_handler::{self, OptimisticHandler};
+use crate::reindex;
+use crate::reputation::{Reputation, Source, Submitter};
+use crate::scheduler::SchedulerStatus;
+use crate::sequencing_feed::{self, SequenceFeedQuery};
+use crate::simulation_handler::{SimulationCache, SimulationHandler};
+use crate::sponsorship_handler::{SponsoredTransactionRequest, SponsorshipHandler};
+use crate::transaction_handler::TransactionHandler;
+use crate::ws;
+use execution::transaction_pool::TransactionPool;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use server::access_log::{self, AccessLog, API_KEY_HEADER};
+use server::error::{handle_rejection, ApiError};
+use server::http::client_ip;
+use server::request_id::request_id;
+use server::swagger;
+use solana_sdk::transaction::Transaction;
+use state::account_state::AccountState;
+use state::blob::{Blob, BlobReferences};
+use state::block::Block;
+use state::config::OperationMode;
+use state::cross_rollup::{CrossRollupClaim, DeliveredMessage};
+use state::sponsorship::SponsorSpend;
+use state::state_record::StateCommitmentPackage;
+use state::transaction::TrollupTransaction;
+use state::views::{BlockView, InstructionView, TransactionView};
+use state_commitment::checkpoints;
+use state_commitment::load_failures;
+use state_commitment::optimistic_commitments::{self, Resolution};
+use state_management::blob_store::BlobStore;
+use state_management::compression;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::StateManager;
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use utoipa::OpenApi;
+use utoipa_gen::ToSchema;
+use warp::body::json;
+use warp::http::header::{HeaderName, HeaderValue, LINK};
+use warp::http::StatusCode;
+use warp::path::FullPath;
+use warp::{Filter, Rejection, Reply};
+
+/// Version prefix of the current API routes.
+pub const API_VERSION: &str = "v1";
+/// Versions served by this node, oldest first.
+pub const API_VERSIONS: &[&str] = &[API_VERSION];
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Trollup API",
+        description = "The Trollup API provides functionality to submit transactions and query rollup state",
+        version = "0.0.1"
+    ),
+    paths(send_transaction_route, get_transaction_route, get_block_route, get_latest_block_route),
+        components(
+            schemas(TransactionSchema, TransactionView, InstructionView, BlockView)
+        ),
+    tags(
+    (name = "handler", description = "Trollup API endpoints")
+    )
+)]
+struct ApiDoc;
+
+/// Shared state the API routes are built from. The binary creates the state managers and pools
+/// once and hands them to `routes`.
+#[derive(Clone)]
+pub struct ApiState {
+    pub transaction_pool: Arc<Mutex<TransactionPool>>,
+    pub account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>,
+    pub transaction_state_manager: Arc<StateManager<SledStateManagement<TrollupTransaction>>>,
+    pub block_state_manager: Arc<StateManager<SledStateManagement<Block>>>,
+    pub optimistic_commitment_state_management: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>,
+    pub sponsor_state_manager: Arc<StateManager<SledStateManagement<SponsorSpend>>>,
+    pub inbox_state_manager: Arc<StateManager<SledStateManagement<DeliveredMessage>>>,
+    pub blob_store: Arc<BlobStore<SledStateManagement<Blob>, SledStateManagement<BlobReferences>>>,
+    pub scheduler_status: SchedulerStatus,
+    pub access_log: AccessLog,
+    pub simulation_cache: SimulationCache,
+    pub operation_mode: OperationMode,
+    /// Serving a data directory without the engine, the routes that write are left out
+    pub read_only: bool,
+    pub shedding: SheddingThresholds,
+    pub block_verifier: BlockVerifier,
+    pub health_events: HealthEvents,
+    pub versioning: ApiVersioning,
+    pub reputation: Reputation,
+    /// Keys the submission routes ask for, see `auth::require_api_key`
+    pub api_keys: ApiKeys,
+    /// Per-client limit of the submission routes
+    pub rate_limiter: RateLimiter,
+    /// Operator token of the admin routes that change the node, see `auth::require_admin_token`
+    pub admin_token: AdminToken,
+}
+
+/// How the unversioned routes from before `/v1` are served.
+#[derive(Clone, Debug, Default)]
+pub struct ApiVersioning {
+    /// Also serve every route without the version prefix
+    pub legacy_routes_enabled: bool,
+    /// HTTP date sent in the `Sunset` header of legacy responses, empty to leave it out
+    pub legacy_routes_sunset: String,
+}
+
+/// The complete API router, including the admin and documentation routes.
+///
+/// Every API route is served under `/v1`. With legacy routes enabled they are served without the
+/// prefix as well, marked deprecated and pointing at their versioned successor. `/health`,
+/// `/versions`, `/metrics`, the `/ws` pubsub endpoint and the documentation routes are never
+/// versioned. Every response is recorded in the metrics, see `metrics::record_request`.
+/// Requests no route answers get an `ApiError` too, see `server::error::handle_rejection`.
+pub fn routes(state: ApiState) -> impl Filter<Extract=impl Reply, Error=Infallible> + Clone {
+    let ApiVersioning { legacy_routes_enabled, legacy_routes_sunset } = state.versioning.clone();
+    let health = health_route(Arc::clone(&state.transaction_pool), state.health_events.clone());
+    let metrics = metrics_route(Arc::clone(&state.transaction_pool));
+    let history = ws::BlockHistory {
+        blocks: Arc::clone(&state.block_state_manager),
+        transactions: Arc::clone(&state.transaction_state_manager),
+        accounts: Arc::clone(&state.account_state_manager),
+    };
+    let api = api_routes(state);
+
+    let versioned = warp::path(API_VERSION).and(api.clone());
+    let legacy = warp::any()
+        .and_then(move || async move {
+            if legacy_routes_enabled {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+        .and(warp::path::full())
+        .and(api)
+        .map(move |path: FullPath, reply| deprecated(reply, &path, &legacy_routes_sunset));
+
+    get_versions_route()
+        .or(health)
+        .or(metrics)
+        .or(ws_route(history))
+        .or(versioned)
+        .or(legacy)
+        .or(swagger::routes(ApiDoc::openapi()))
+        .recover(handle_rejection)
+        .with(warp::log::custom(metrics::record_request))
+}
+
+/// Marks a legacy response as deprecated and links it to the versioned route.
+fn deprecated(reply: impl Reply, path: &FullPath, sunset: &str) -> warp::reply::Response {
+    let mut response = reply.into_response();
+    let headers = response.headers_mut();
+    headers.insert(HeaderName::from_static("deprecation"), HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&format!("</{}{}>; rel=\"successor-version\"", API_VERSION, path.as_str())) {
+        headers.insert(LINK, link);
+    }
+    if !sunset.is_empty() {
+        if let Ok(sunset) = HeaderValue::from_str(sunset) {
+            headers.insert(HeaderName::from_static("sunset"), sunset);
+        }
+    }
+    response
+}
+
+/// The routes served under each version prefix.
+fn api_routes(state: ApiState) -> impl Filter<Extract=(impl Reply,), Error=Rejection> + Clone {
+    let pool = state.transaction_pool;
+    let optimistic = state.operation_mode.accepts_optimistic();
+    let writable = !state.read_only;
+
+    health_route(Arc::clone(&pool), state.health_events)
+        .or(enabled_if(writable).and(send_transaction_route(Arc::clone(&pool), state.reputation.clone(), state.api_keys.clone(), state.rate_limiter.clone())))
+        .or(enabled_if(writable && optimistic).and(send_transaction_optimistic_route(Arc::clone(&pool), state.reputation.clone(), state.api_keys.clone(), state.rate_limiter.clone())))
+        .or(enabled_if(writable).and(send_transaction_bundle_route(Arc::clone(&pool), state.reputation.clone(), state.api_keys.clone(), state.rate_limiter.clone())))
+        .or(get_bundle_status_route(Arc::clone(&pool)))
+        .or(enabled_if(writable).and(send_system_transaction_route(Arc::clone(&pool))))
+        .or(enabled_if(writable).and(send_sponsored_transaction_route(Arc::clone(&pool), Arc::clone(&state.sponsor_state_manager), state.api_keys.clone(), state.rate_limiter.clone())))
+        .or(simulate_transaction_route(Arc::clone(&state.account_state_manager), Arc::clone(&state.block_state_manager), state.simulation_cache, state.reputation.clone()))
+        .or(decode_transaction_route())
+        .or(get_transaction_route(Arc::clone(&state.transaction_state_manager)))
+        .or(get_all_transaction_route(Arc::clone(&state.transaction_state_manager)))
+        .or(get_transaction_status_route(Arc::clone(&state.transaction_state_manager), Arc::clone(&state.block_state_manager), Arc::clone(&pool)))
+        .or(enabled_if(optimistic).and(get_all_pending_commitments_route(Arc::clone(&state.optimistic_commitment_state_management), Arc::clone(&state.blob_store))))
+        .or(enabled_if(optimistic).and(get_pending_commitment_route(Arc::clone(&state.optimistic_commitment_state_management), Arc::clone(&state.blob_store))))
+        .or(enabled_if(optimistic).and(get_pending_commitment_diff_route(Arc::clone(&state.optimistic_commitment_state_management), Arc::clone(&state.blob_store), Arc::clone(&state.account_state_manager))))
+        .or(enabled_if(writable && optimistic).and(post_challenge_route(Arc::clone(&pool), Arc::clone(&state.optimistic_commitment_state_management), state.rate_limiter.clone())))
+        .or(enabled_if(writable && optimistic).and(claim_challenge_route(Arc::clone(&pool), Arc::clone(&state.optimistic_commitment_state_management), state.rate_limiter.clone())))
+        .or(enabled_if(optimistic).and(get_challenges_route(Arc::clone(&pool), Arc::clone(&state.optimistic_commitment_state_management))))
+        .or(enabled_if(optimistic).and(get_stuck_commitments_route()))
+        .or(enabled_if(optimistic).and(get_commitment_status_route()))
+        .or(enabled_if(writable && optimistic).and(resolve_commitment_route(state.admin_token.clone())))
+        .or(get_account_route(Arc::clone(&state.account_state_manager)))
+        .or(get_all_accounts_route(Arc::clone(&state.account_state_manager)))
+        .or(export_accounts_route(Arc::clone(&state.account_state_manager)))
+        .or(export_transactions_route(Arc::clone(&state.transaction_state_manager)))
+        .or(get_all_blocks_route(Arc::clone(&state.block_state_manager)))
+        .or(get_block_public_inputs_route(Arc::clone(&state.block_state_manager), Arc::clone(&state.account_state_manager), state.block_verifier.clone()))
+        .or(get_block_route(Arc::clone(&state.block_state_manager), Arc::clone(&state.account_state_manager), state.block_verifier))
+        .or(get_latest_block_route(Arc::clone(&state.block_state_manager)))
+        .or(get_node_info_route(Arc::clone(&state.block_state_manager)))
+        .or(get_l1_anchor_route(Arc::clone(&state.block_state_manager)))
+        .or(get_account_proof_route(Arc::clone(&state.block_state_manager)))
+        .or(get_account_data_proof_route(Arc::clone(&state.block_state_manager), Arc::clone(&state.account_state_manager)))
+        .or(get_checkpoint_route())
+        .or(get_latest_checkpoint_route())
+        .or(get_parameters_route())
+        .or(get_parameters_by_hash_route())
+        .or(get_proposals_route())
+        .or(enabled_if(writable).and(propose_parameters_route()))
+        .or(enabled_if(writable).and(approve_proposal_route()))
+        .or(get_blob_route(Arc::clone(&state.blob_store)))
+        .or(get_sequence_feed_route())
+        .or(access_log::admin_route(state.access_log, state.admin_token.clone()))
+        .or(get_scheduler_status_route(state.scheduler_status))
+        .or(get_storage_stats_route())
+        .or(get_load_failures_route())
+        .or(get_stats_route(Arc::clone(&pool), state.shedding))
+        .or(enabled_if(writable).and(reindex_route(Arc::clone(&state.block_state_manager), state.admin_token.clone())))
+        .or(get_reindex_progress_route())
+        .or(get_pipeline_route(Arc::clone(&pool)))
+        .or(get_bans_route(state.reputation.clone()))
+        .or(ban_route(state.reputation.clone(), state.admin_token.clone()))
+        .or(unban_route(state.reputation, state.admin_token.clone()))
+        .or(get_all_sponsors_route(Arc::clone(&pool), Arc::clone(&state.sponsor_state_manager)))
+        .or(get_outbox_claim_route(Arc::clone(&pool), Arc::clone(&state.block_state_manager), Arc::clone(&state.inbox_state_manager)))
+        .or(enabled_if(writable).and(receive_claim_route(Arc::clone(&pool), Arc::clone(&state.block_state_manager), Arc::clone(&state.inbox_state_manager), state.rate_limiter.clone())))
+        .or(get_all_delivered_route(Arc::clone(&pool), Arc::clone(&state.block_state_manager), Arc::clone(&state.inbox_state_manager)))
+}
+
+/// Solana RPC style pubsub, see `ws::handle_connection`.
+fn ws_route(history: ws::BlockHistory) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("ws")
+        .and(warp::ws())
+        .map(move |upgrade: warp::ws::Ws| {
+            let history = history.clone();
+            upgrade.on_upgrade(move |socket| ws::handle_connection(socket, history))
+        })
+}
+
+/// Lets clients pick the newest version both sides support.
+fn get_versions_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("versions")
+        .and(warp::get())
+        .map(|| warp::reply::json(&serde_json::json!({
+            "current": API_VERSION,
+            "supported": API_VERSIONS,
+        })))
+}
+
+/// Query parameters that fall back to their defaults when the query string is missing or
+/// can't be parsed.
+fn optional_query<T: DeserializeOwned + Default + Send + 'static>() -> impl Filter<Extract=(T,), Error=Infallible> + Clone {
+    warp::query::<T>()
+        .or(warp::any().map(T::default))
+        .unify()
+}
+
+/// Rejects with not found unless `enabled`, for routes that only exist in some operation modes.
+fn enabled_if(enabled: bool) -> impl Filter<Extract=(), Error=Rejection> + Clone {
+    warp::any()
+        .and_then(move || async move {
+            if enabled {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+}
+
+fn with_pool(
+    pool: Arc<Mutex<TransactionPool>>,
+) -> impl Filter<Extract=(Arc<Mutex<TransactionPool>>,), Error=std::convert::Infallible> + Clone {
+    warp::any().map(move || Arc::clone(&pool))
+}
+
+fn health_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    health_events: HealthEvents,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("health")
+        .and(with_pool(pool))
+        .and(warp::any().map(move || health_events.clone()))
+        .and_then(|pool: Arc<Mutex<TransactionPool>>, health_events: HealthEvents| async move {
+            let handler = Handler::new(pool);
+            handler.health_handler(&health_events).await
+        })
+}
+
+/// Prometheus metrics, see `metrics`.
+fn metrics_route(pool: Arc<Mutex<TransactionPool>>) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("metrics")
+        .and(warp::get())
+        .and(with_pool(pool))
+        .and_then(metrics::metrics_handler)
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct TransactionSchema(Transaction);
+
+#[utoipa::path(
+        post,
+        path = "/v1/send-transaction",
+        request_body = Transaction,
+        responses(
+            (status = 200, description = "Transaction submitted successfully", body = String),
+            (status = 400, description = "Invalid transaction")
+        ),
+        tag = "transactions"
+)]
+fn send_transaction_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    reputation: Reputation,
+    api_keys: ApiKeys,
+    rate_limiter: RateLimiter,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("send-transaction")
+        .and(auth::require_api_key(api_keys))
+        .and(rate_limit::limit_submissions(rate_limiter))
+        .and(with_pool(pool))
+        .and(json())
+        .and(request_id())
+        .and(submitter(reputation))
+        .and_then(|pool: Arc<Mutex<TransactionPool>>, transaction: Transaction, request_id: String, submitter: Submitter| async move {
+            let handler = Handler::new(pool);
+            handler.send_transaction_handler(transaction, &request_id, &submitter).await
+        })
+}
+
+fn send_transaction_optimistic_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    reputation: Reputation,
+    api_keys: ApiKeys,
+    rate_limiter: RateLimiter,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("send-transaction-optimistic")
+        .and(auth::require_api_key(api_keys))
+        .and(rate_limit::limit_submissions(rate_limiter))
+        .and(with_pool(pool))
+        .and(json())
+        .and(request_id())
+        .and(submitter(reputation))
+        .and_then(|pool: Arc<Mutex<TransactionPool>>, transaction: Transaction, request_id: String, submitter: Submitter| async move {
+            let handler = Handler::new(pool);
+            handler.send_transaction_optimistic_handler(transaction, &request_id, &submitter).await
+        })
+}
+
+/// Transactions that all make it into a block or none does, see `Handler::send_transaction_bundle_handler`.
+fn send_transaction_bundle_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    reputation: Reputation,
+    api_keys: ApiKeys,
+    rate_limiter: RateLimiter,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("send-transaction-bundle")
+        .and(warp::post())
+        .and(auth::require_api_key(api_keys))
+        .and(rate_limit::limit_submissions(rate_limiter.clone()))
+        .and(with_pool(pool))
+        .and(json())
+        .and(request_id())
+        .and(submitter(reputation))
+        .and(client_ip())
+        .and_then(move |pool: Arc<Mutex<TransactionPool>>, transactions: Vec<Transaction>, request_id: String, submitter: Submitter, client: Option<IpAddr>| {
+            let rate_limiter = rate_limiter.clone();
+            async move {
+                // The first transaction was charged before the body was read
+                rate_limit::charge(&rate_limiter, client, transactions.len().saturating_sub(1))?;
+                let handler = Handler::new(pool);
+                handler.send_transaction_bundle_handler(transactions, &request_id, &submitter).await
+            }
+        })
+}
+
+fn get_bundle_status_route(
+    pool: Arc<Mutex<TransactionPool>>,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("get-bundle-status" / String)
+        .and(with_pool(pool))
+        .and_then(|bundle_id: String, pool: Arc<Mutex<TransactionPool>>| async move {
+            let handler = Handler::new(pool);
+            handler.get_bundle_status_handler(&bundle_id).await
+        })
+}
+
+/// The client's address and API key, to check and record submissions against.
+fn submitter(reputation: Reputation) -> impl Filter<Extract=(Submitter,), Error=Infallible> + Clone {
+    client_ip()
+        .and(warp::header::optional::<String>(API_KEY_HEADER))
+        .map(move |client_ip, api_key| Submitter::new(reputation.clone(), client_ip, api_key))
+}
+
+/// Operator maintenance transactions, authenticated with `SYSTEM_LANE_TOKEN`.
+fn send_system_transaction_route(
+    pool: Arc<Mutex<TransactionPool>>,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "system-transaction")
+        .and(warp::post())
+        .and(with_pool(pool))
+        .and(json())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(request_id())
+        .and_then(|pool: Arc<Mutex<TransactionPool>>, transaction: Transaction, authorization: Option<String>, request_id: String| async move {
+            let handler = Handler::new(pool);
+            handler.send_system_transaction_handler(transaction, authorization, &request_id).await
+        })
+}
+
+fn simulate_transaction_route(
+    account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>,
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>,
+    simulation_cache: SimulationCache,
+    reputation: Reputation,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("simulate-transaction")
+        .and(warp::post())
+        .and(warp::any().map(move || SimulationHandler::new(Arc::clone(&account_state_manager), Arc::clone(&block_state_manager), simulation_cache.clone())))
+        .and(json())
+        .and(submitter(reputation))
+        .and_then(|handler: SimulationHandler, transaction: Transaction, submitter: Submitter| async move {
+            handler.simulate_transaction(transaction, &submitter).await
+        })
+}
+
+/// Runs a payload through the submission pipeline without submitting it, see
+/// `decode_handler::decode_transaction`.
+fn decode_transaction_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("decode-transaction")
+        .and(warp::post())
+        .and(json())
+        .and_then(|request: DecodeTransactionRequest| async move {
+            decode_handler::decode_transaction_handler(request).await
+        })
+}
+
+fn create_sponsorship_handler_filter(
+    pool: Arc<Mutex<TransactionPool>>,
+    state_manager: Arc<StateManager<SledStateManagement<SponsorSpend>>>
+) -> impl Filter<Extract=(SponsorshipHandler<SledStateManagement<SponsorSpend>>,), Error=Infallible> + Clone {
+    warp::any().map(move || SponsorshipHandler::new(Arc::clone(&pool), Arc::clone(&state_manager)))
+}
+
+fn send_sponsored_transaction_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    sponsor_state_manager: Arc<StateManager<SledStateManagement<SponsorSpend>>>,
+    api_keys: ApiKeys,
+    rate_limiter: RateLimiter,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("send-sponsored-transaction")
+        .and(warp::post())
+        .and(auth::require_api_key(api_keys))
+        .and(rate_limit::limit_submissions(rate_limiter))
+        .and(create_sponsorship_handler_filter(pool, sponsor_state_manager))
+        .and(json())
+        .and(request_id())
+        .and_then(|handler: SponsorshipHandler<SledStateManagement<SponsorSpend>>, request: SponsoredTransactionRequest, request_id: String| async move {
+            handler.send_sponsored_transaction(request, &request_id).await
+        })
+}
+
+fn get_all_sponsors_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    sponsor_state_manager: Arc<StateManager<SledStateManagement<SponsorSpend>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "sponsors")
+        .and(warp::get())
+        .and(create_sponsorship_handler_filter(pool, sponsor_state_manager))
+        .and_then(|handler: SponsorshipHandler<SledStateManagement<SponsorSpend>>| async move {
+            handler.get_all_sponsors().await
+        })
+}
+
+type CrossRollupSledHandler = CrossRollupHandler<SledStateManagement<Block>, SledStateManagement<DeliveredMessage>>;
+
+fn create_cross_rollup_handler_filter(
+    pool: Arc<Mutex<TransactionPool>>,
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>,
+    inbox_state_manager: Arc<StateManager<SledStateManagement<DeliveredMessage>>>
+) -> impl Filter<Extract=(CrossRollupSledHandler,), Error=Infallible> + Clone {
+    warp::any().map(move || CrossRollupHandler::new(Arc::clone(&pool), Arc::clone(&block_state_manager), Arc::clone(&inbox_state_manager)))
+}
+
+fn get_outbox_claim_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>,
+    inbox_state_manager: Arc<StateManager<SledStateManagement<DeliveredMessage>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("cross-rollup" / "outbox" / u64 / usize)
+        .and(warp::get())
+        .and(create_cross_rollup_handler_filter(pool, block_state_manager, inbox_state_manager))
+        .and_then(|block_number: u64, index: usize, handler: CrossRollupSledHandler| async move {
+            handler.get_outbox_claim(block_number, index).await
+        })
+}
+
+fn receive_claim_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>,
+    inbox_state_manager: Arc<StateManager<SledStateManagement<DeliveredMessage>>>,
+    rate_limiter: RateLimiter,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("cross-rollup" / "inbox")
+        .and(warp::post())
+        .and(rate_limit::limit_submissions(rate_limiter))
+        .and(create_cross_rollup_handler_filter(pool, block_state_manager, inbox_state_manager))
+        .and(json())
+        .and(request_id())
+        .and_then(|handler: CrossRollupSledHandler, claim: CrossRollupClaim, request_id: String| async move {
+            handler.receive_claim(claim, &request_id).await
+        })
+}
+
+fn get_all_delivered_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>,
+    inbox_state_manager: Arc<StateManager<SledStateManagement<DeliveredMessage>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("cross-rollup" / "inbox")
+        .and(warp::get())
+        .and(create_cross_rollup_handler_filter(pool, block_state_manager, inbox_state_manager))
+        .and_then(|handler: CrossRollupSledHandler| async move {
+            handler.get_all_delivered().await
+        })
+}
+
+fn get_account_route(
+    account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-account")
+        .and(warp::path::param())
+        .and(optional_query::<AccountQuery>())
+        .and(create_account_handler_filter(account_state_manager))
+        .and_then(|account_id: String, query: AccountQuery, handler: AccountHandler<SledStateManagement<AccountState>>| async move {
+            handler.get_account(&account_id, query).await
+        })
+}
+
+fn get_all_accounts_route(
+    account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-all-accounts")
+        .and(create_account_handler_filter(account_state_manager))
+        .and_then(|handler: AccountHandler<SledStateManagement<AccountState>>| async move {
+            handler.get_all_accounts().await
+        })
+}
+
+/// Streams every account, see `export::stream`.
+fn export_accounts_route(
+    account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("export" / "accounts")
+        .and(warp::get())
+        .and(optional_query::<ExportQuery>())
+        .and(create_account_handler_filter(account_state_manager))
+        .and_then(|query: ExportQuery, handler: AccountHandler<SledStateManagement<AccountState>>| async move {
+            handler.export_accounts(query.format).await
+        })
+}
+
+fn create_account_handler_filter(
+    state_manager: Arc<StateManager<SledStateManagement<AccountState>>>
+) -> impl Filter<Extract=(AccountHandler<SledStateManagement<AccountState>>,), Error=Infallible> + Clone {
+    let handler_filter = warp::any().map(move || AccountHandler::new(Arc::clone(&state_manager)));
+    handler_filter
+}
+
+#[utoipa::path(
+        get,
+        path = "/v1/get-transaction/{signature}",
+        params(("signature" = String, Path, description = "Base58 transaction signature")),
+        responses(
+            (status = 200, description = "The transaction, or a message when it isn't found", body = TransactionView)
+        ),
+        tag = "transactions"
+)]
+fn get_transaction_route(
+    transaction_state_manager: Arc<StateManager<SledStateManagement<TrollupTransaction>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-transaction")
+        .and(warp::path::param())
+        .and(create_transaction_handler_filter(transaction_state_manager))
+        .and_then(|signature: String, handler: TransactionHandler<SledStateManagement<TrollupTransaction>>| async move {
+            handler.get_transaction(&signature).await
+        })
+}
+
+fn get_transaction_status_route(
+    transaction_state_manager: Arc<StateManager<SledStateManagement<TrollupTransaction>>>,
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>,
+    pool: Arc<Mutex<TransactionPool>>,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("get-transaction-status" / String)
+        .and(create_transaction_handler_filter(transaction_state_manager))
+        .and(warp::any().map(move || Arc::clone(&block_state_manager)))
+        .and(with_pool(pool))
+        .and_then(|signature: String, handler: TransactionHandler<SledStateManagement<TrollupTransaction>>, block_state_manager: Arc<StateManager<SledStateManagement<Block>>>, pool: Arc<Mutex<TransactionPool>>| async move {
+            handler.get_transaction_status(&signature, &block_state_manager, &pool).await
+        })
+}
+
+fn get_all_transaction_route(
+    transaction_state_manager: Arc<StateManager<SledStateManagement<TrollupTransaction>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-all-transactions")
+        .and(create_transaction_handler_filter(transaction_state_manager))
+        .and_then(|handler: TransactionHandler<SledStateManagement<TrollupTransaction>>| async move {
+            handler.get_all_transactions().await
+        })
+}
+
+fn export_transactions_route(
+    transaction_state_manager: Arc<StateManager<SledStateManagement<TrollupTransaction>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("export" / "transactions")
+        .and(warp::get())
+        .and(optional_query::<ExportQuery>())
+        .and(create_transaction_handler_filter(transaction_state_manager))
+        .and_then(|query: ExportQuery, handler: TransactionHandler<SledStateManagement<TrollupTransaction>>| async move {
+            handler.export_transactions(query.format).await
+        })
+}
+
+fn create_transaction_handler_filter(
+    state_manager: Arc<StateManager<SledStateManagement<TrollupTransaction>>>
+) -> impl Filter<Extract=(TransactionHandler<SledStateManagement<TrollupTransaction>>,), Error=Infallible> + Clone {
+    let handler_filter = warp::any().map(move || TransactionHandler::new(Arc::clone(&state_manager)));
+    handler_filter
+}
+
+#[utoipa::path(
+        get,
+        path = "/v1/get-block/{block_number}",
+        params(
+            ("block_number" = u64, Path, description = "Rollup block number"),
+            ("verify" = Option<bool>, Query, description = "Verify the block's proof, wrapping the block with the result")
+        ),
+        responses(
+            (status = 200, description = "The block, or a message when it isn't found", body = BlockView)
+        ),
+        tag = "blocks"
+)]
+fn get_block_route(
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>,
+    account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>,
+    block_verifier: BlockVerifier,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-block")
+        .and(warp::path::param())
+        .and(optional_query::<GetBlockQuery>())
+        .and(create_block_handler_filter(block_state_manager))
+        .and(warp::any().map(move || (Arc::clone(&account_state_manager), block_verifier.clone())))
+        .and_then(|block_id: u64, query: GetBlockQuery, handler: BlockHandler<SledStateManagement<Block>>, (account_state_manager, verifier): (Arc<StateManager<SledStateManagement<AccountState>>>, BlockVerifier)| async move {
+            if query.verify {
+                handler.get_verified_block(block_id, &account_state_manager, &verifier).await.map(|reply| reply.into_response())
+            } else {
+                handler.get_block(block_id).await.map(|reply| reply.into_response())
+            }
+        })
+}
+
+#[utoipa::path(
+        get,
+        path = "/v1/get-latest-block",
+        responses(
+            (status = 200, description = "The latest finalized block", body = BlockView)
+        ),
+        tag = "blocks"
+)]
+fn get_latest_block_route(
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-latest-block")
+        .and(create_block_handler_filter(block_state_manager))
+        .and_then(|handler: BlockHandler<SledStateManagement<Block>>| async move {
+            handler.get_latest_block().await
+        })
+}
+
+fn get_node_info_route(
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("node-info")
+        .and(warp::get())
+        .and(create_block_handler_filter(block_state_manager))
+        .and_then(|handler: BlockHandler<SledStateManagement<Block>>| async move {
+            handler.get_node_info().await
+        })
+}
+
+fn get_l1_anchor_route(
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-l1-anchor")
+        .and(warp::path::param())
+        .and(create_block_handler_filter(block_state_manager))
+        .and_then(|block_id: u64, handler: BlockHandler<SledStateManagement<Block>>| async move {
+            handler.get_l1_anchor(block_id).await
+        })
+}
+
+fn get_account_proof_route(
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-account-proof")
+        .and(warp::path::param())
+        .and(optional_query::<AccountProofQuery>())
+        .and(create_block_handler_filter(block_state_manager))
+        .and_then(|account_id: String, query: AccountProofQuery, handler: BlockHandler<SledStateManagement<Block>>| async move {
+            handler.get_account_proof(&account_id, query).await
+        })
+}
+
+fn get_account_data_proof_route(
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>,
+    account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-account-data-proof")
+        .and(warp::path::param())
+        .and(optional_query::<AccountDataProofQuery>())
+        .and(create_block_handler_filter(block_state_manager))
+        .and(warp::any().map(move || Arc::clone(&account_state_manager)))
+        .and_then(|account_id: String, query: AccountDataProofQuery, handler: BlockHandler<SledStateManagement<Block>>, account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>| async move {
+            handler.get_account_data_proof(&account_id, query, &account_state_manager).await
+        })
+}
+
+fn get_block_public_inputs_route(
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>,
+    account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>,
+    block_verifier: BlockVerifier,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-block-public-inputs")
+        .and(warp::path::param())
+        .and(create_block_handler_filter(block_state_manager))
+        .and(warp::any().map(move || (Arc::clone(&account_state_manager), block_verifier.clone())))
+        .and_then(|block_id: u64, handler: BlockHandler<SledStateManagement<Block>>, (account_state_manager, verifier): (Arc<StateManager<SledStateManagement<AccountState>>>, BlockVerifier)| async move {
+            handler.get_block_public_inputs(block_id, &account_state_manager, &verifier).await
+        })
+}
+
+fn get_all_blocks_route(
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-all-blocks")
+        .and(create_block_handler_filter(block_state_manager))
+        .and_then(|handler: BlockHandler<SledStateManagement<Block>>| async move {
+            handler.get_all_blocks().await
+        })
+}
+
+fn create_block_handler_filter(
+    state_manager: Arc<StateManager<SledStateManagement<Block>>>
+) -> impl Filter<Extract=(BlockHandler<SledStateManagement<Block>>,), Error=Infallible> + Clone {
+    let handler_filter = warp::any().map(move || BlockHandler::new(Arc::clone(&state_manager)));
+    handler_filter
+}
+
+fn create_optimistic_handler_filter(
+    state_manager: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>,
+    blob_store: Arc<BlobStore<SledStateManagement<Blob>, SledStateManagement<BlobReferences>>>
+) -> impl Filter<Extract=(OptimisticHandler<SledStateManagement<StateCommitmentPackage<AccountState>>, SledStateManagement<Blob>, SledStateManagement<BlobReferences>>,), Error=Infallible> + Clone {
+    let handler_filter = warp::any().map(move || OptimisticHandler::new(Arc::clone(&state_manager), Arc::clone(&blob_store)));
+    handler_filter
+}
+
+fn get_all_pending_commitments_route(
+    optimistic_commit_state_manager: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>,
+    blob_store: Arc<BlobStore<SledStateManagement<Blob>, SledStateManagement<BlobReferences>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-all-pending-commitments")
+        .and(create_optimistic_handler_filter(optimistic_commit_state_manager, blob_store))
+        .and_then(|handler: OptimisticHandler<SledStateManagement<StateCommitmentPackage<AccountState>>, SledStateManagement<Blob>, SledStateManagement<BlobReferences>>| async move {
+            handler.get_all_transactions().await
+        })
+}
+
+fn get_pending_commitment_route(
+    optimistic_commit_state_manager: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>,
+    blob_store: Arc<BlobStore<SledStateManagement<Blob>, SledStateManagement<BlobReferences>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-pending-commitments")
+        .and(warp::path::param())
+        .and(create_optimistic_handler_filter(optimistic_commit_state_manager, blob_store))
+        .and_then(|state_root: String, handler: OptimisticHandler<SledStateManagement<StateCommitmentPackage<AccountState>>, SledStateManagement<Blob>, SledStateManagement<BlobReferences>>| async move {
+            handler.get_pending_transaction_batch(&state_root).await
+        })
+}
+
+fn get_pending_commitment_diff_route(
+    optimistic_commit_state_manager: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>,
+    blob_store: Arc<BlobStore<SledStateManagement<Blob>, SledStateManagement<BlobReferences>>>,
+    account_state_manager: Arc<StateManager<SledStateManagement<AccountState>>>,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-pending-commitment-diff")
+        .and(warp::path::param())
+        .and(create_optimistic_handler_filter(optimistic_commit_state_manager, blob_store))
+        .and_then(move |state_root: String, handler: OptimisticHandler<SledStateManagement<StateCommitmentPackage<AccountState>>, SledStateManagement<Blob>, SledStateManagement<BlobReferences>>| {
+            let account_state_manager = Arc::clone(&account_state_manager);
+            async move { handler.get_pending_commitment_diff(&state_root, &account_state_manager).await }
+        })
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StuckCommitmentsQuery {
+    /// Defaults to `OPTIMISTIC_TIMEOUT`
+    pub older_than_secs: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolveCommitmentRequest {
+    pub resolution: Resolution,
+}
+
+fn parse_state_root(state_root: &str) -> Result<[u8; 32], String> {
+    hex::decode(state_root)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("Invalid state root: {}", state_root))
+}
+
+/// Optimistic commitments pending for longer than they should, oldest first.
+fn get_stuck_commitments_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "optimistic-commitments")
+        .and(warp::get())
+        .and(optional_query::<StuckCommitmentsQuery>())
+        .map(|query: StuckCommitmentsQuery| warp::reply::json(&optimistic_handler::stuck_commitments(query.older_than_secs)))
+}
+
+fn get_commitment_status_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "optimistic-commitments" / String)
+        .and(warp::get())
+        .map(|state_root: String| match parse_state_root(&state_root) {
+            Ok(state_root) => match optimistic_commitments::status(&state_root) {
+                Some(record) => warp::reply::json(&record).into_response(),
+                None => ApiError::not_found(format!("No commitment {}", hex::encode(state_root))).into_response(),
+            },
+            Err(message) => ApiError::invalid_request(message).into_response(),
+        })
+}
+
+/// Settles a stuck commitment, proving it now or rolling it back. The committer carries the
+/// resolution out shortly after the request is accepted. Needs the operator token.
+fn resolve_commitment_route(admin_token: AdminToken) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "optimistic-commitments" / String / "resolve")
+        .and(warp::post())
+        .and(auth::require_admin_token(admin_token))
+        .and(json())
+        .map(|state_root: String, request: ResolveCommitmentRequest| match parse_state_root(&state_root) {
+            Ok(state_root) => match optimistic_commitments::request_resolution(&state_root, request.resolution) {
+                Ok(record) => warp::reply::with_status(warp::reply::json(&record), StatusCode::ACCEPTED).into_response(),
+                Err(message) => ApiError::not_found(message).into_response(),
+            },
+            Err(message) => ApiError::invalid_request(message).into_response(),
+        })
+}
+
+fn create_challenge_handler_filter(
+    pool: Arc<Mutex<TransactionPool>>,
+    optimistic_commit_state_manager: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>
+) -> impl Filter<Extract=(ChallengeHandler<SledStateManagement<StateCommitmentPackage<AccountState>>>,), Error=Infallible> + Clone {
+    warp::any().map(move || ChallengeHandler::new(Arc::clone(&pool), Arc::clone(&optimistic_commit_state_manager)))
+}
+
+fn post_challenge_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    optimistic_commit_state_manager: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>,
+    rate_limiter: RateLimiter,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("post-challenge")
+        .and(warp::post())
+        .and(rate_limit::limit_submissions(rate_limiter))
+        .and(create_challenge_handler_filter(pool, optimistic_commit_state_manager))
+        .and(json())
+        .and(request_id())
+        .and_then(|handler: ChallengeHandler<SledStateManagement<StateCommitmentPackage<AccountState>>>, request: PostChallengeRequest, request_id: String| async move {
+            handler.post_challenge(request, &request_id).await
+        })
+}
+
+fn claim_challenge_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    optimistic_commit_state_manager: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>,
+    rate_limiter: RateLimiter,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("claim-challenge")
+        .and(warp::path::param())
+        .and(warp::post())
+        .and(rate_limit::limit_submissions(rate_limiter))
+        .and(create_challenge_handler_filter(pool, optimistic_commit_state_manager))
+        .and_then(|id: String, handler: ChallengeHandler<SledStateManagement<StateCommitmentPackage<AccountState>>>| async move {
+            handler.claim_challenge(&id).await
+        })
+}
+
+fn get_challenges_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    optimistic_commit_state_manager: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-challenges")
+        .and(warp::get())
+        .and(create_challenge_handler_filter(pool, optimistic_commit_state_manager))
+        .and_then(|handler: ChallengeHandler<SledStateManagement<StateCommitmentPackage<AccountState>>>| async move {
+            handler.get_challenges().await
+        })
+}
+
+fn get_blob_route(
+    blob_store: Arc<BlobStore<SledStateManagement<Blob>, SledStateManagement<BlobReferences>>>
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("get-blob")
+        .and(warp::path::param())
+        .and(warp::get())
+        .and(warp::any().map(move || BlobHandler::new(Arc::clone(&blob_store))))
+        .and_then(|hash: String, handler: BlobHandler<SledStateManagement<Blob>, SledStateManagement<BlobReferences>>| async move {
+            handler.get_blob(&hash).await
+        })
+}
+
+fn get_sequence_feed_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path("sequence-feed")
+        .and(warp::get())
+        .and(optional_query::<SequenceFeedQuery>())
+        .and_then(sequencing_feed::get_sequence_feed)
+}
+
+fn get_scheduler_status_route(
+    scheduler_status: SchedulerStatus
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "scheduler")
+        .and(warp::get())
+        .map(move || warp::reply::json(&scheduler_status.snapshot()))
+}
+
+fn get_pipeline_route(
+    pool: Arc<Mutex<TransactionPool>>,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "pipeline")
+        .and(warp::get())
+        .and(with_pool(pool))
+        .and_then(|pool: Arc<Mutex<TransactionPool>>| async move {
+            let handler = Handler::new(pool);
+            handler.pipeline_handler().await
+        })
+}
+
+/// Offences and bans of every tracked submitter, see `reputation`.
+fn get_bans_route(reputation: Reputation) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "bans")
+        .and(warp::get())
+        .map(move || warp::reply::json(&reputation.snapshot()))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BanRequest {
+    /// `ip:<address>`, `apiKey:<fingerprint>` or `payer:<pubkey>`
+    pub source: String,
+    pub duration_secs: u64,
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// Bans a source by hand. Needs the operator token, like lifting a ban.
+fn ban_route(reputation: Reputation, admin_token: AdminToken) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "bans")
+        .and(warp::post())
+        .and(auth::require_admin_token(admin_token))
+        .and(json())
+        .map(move |request: BanRequest| match request.source.parse::<Source>() {
+            Ok(source) => warp::reply::json(&reputation.ban(&source, request.duration_secs, &request.reason)).into_response(),
+            Err(message) => ApiError::invalid_request(message).into_response(),
+        })
+}
+
+fn unban_route(reputation: Reputation, admin_token: AdminToken) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "bans" / String)
+        .and(warp::delete())
+        .and(auth::require_admin_token(admin_token))
+        .map(move |source: String| match source.parse::<Source>() {
+            Ok(source) => warp::reply::json(&reputation.unban(&source)).into_response(),
+            Err(message) => ApiError::invalid_request(message).into_response(),
+        })
+}
+
+/// Starts rebuilding the secondary indexes in the background, answering with its progress. Needs
+/// the operator token.
+fn reindex_route(
+    block_state_manager: Arc<StateManager<SledStateManagement<Block>>>,
+    admin_token: AdminToken,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "reindex")
+        .and(warp::post())
+        .and(auth::require_admin_token(admin_token))
+        .map(move || {
+            let started = reindex::start(Arc::clone(&block_state_manager));
+            warp::reply::json(&serde_json::json!({
+                "started": started,
+                "message": if started { "Reindex started" } else { "A reindex is already running" },
+                "progress": reindex::progress(),
+            }))
+        })
+}
+
+fn get_reindex_progress_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "reindex")
+        .and(warp::get())
+        .map(|| warp::reply::json(&reindex::progress()))
+}
+
+/// Signed summary of an epoch, see `checkpoints::produce`.
+fn get_checkpoint_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("get-checkpoint" / u64)
+        .and(warp::get())
+        .map(|epoch: u64| match checkpoints::get(epoch) {
+            Some(checkpoint) => warp::reply::json(&checkpoint).into_response(),
+            None => ApiError::not_found(format!("No checkpoint found for epoch: {}", epoch)).into_response(),
+        })
+}
+
+fn get_latest_checkpoint_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("get-latest-checkpoint")
+        .and(warp::get())
+        .map(|| match checkpoints::latest() {
+            Some(checkpoint) => warp::reply::json(&checkpoint).into_response(),
+            None => ApiError::not_found("No checkpoints exist").into_response(),
+        })
+}
+
+/// The governed parameters in force at `?block=`, or for the next block.
+fn get_parameters_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("get-parameters")
+        .and(warp::get())
+        .and(optional_query::<ParametersQuery>())
+        .and_then(governance_handler::get_parameters_handler)
+}
+
+/// A parameter set by the hex hash a block header records.
+fn get_parameters_by_hash_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("get-parameters" / String)
+        .and(warp::get())
+        .and_then(governance_handler::get_parameters_by_hash_handler)
+}
+
+fn get_proposals_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "parameters" / "proposals")
+        .and(warp::get())
+        .and_then(governance_handler::get_proposals_handler)
+}
+
+/// Proposes a parameter set for a future block, signed by a governance signer.
+fn propose_parameters_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "parameters" / "proposals")
+        .and(warp::post())
+        .and(json())
+        .and_then(|request: ProposeParametersRequest| async move {
+            governance_handler::propose_parameters_handler(request).await
+        })
+}
+
+fn approve_proposal_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "parameters" / "proposals" / String / "approve")
+        .and(warp::post())
+        .and(json())
+        .and_then(|id: String, request: ApproveProposalRequest| async move {
+            governance_handler::approve_proposal_handler(id, request).await
+        })
+}
+
+/// Pool size, proof backlog and the load shedding level it puts the API at.
+fn get_stats_route(
+    pool: Arc<Mutex<TransactionPool>>,
+    thresholds: SheddingThresholds,
+) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("stats")
+        .and(warp::get())
+        .and(with_pool(pool))
+        .and_then(move |pool: Arc<Mutex<TransactionPool>>| async move {
+            let transaction_pool = pool.lock().await.pool_size();
+            Ok::<_, Rejection>(warp::reply::json(&serde_json::json!({
+                "transactionPool": transaction_pool,
+                "shedding": thresholds.status(),
+            })))
+        })
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadFailuresQuery {
+    /// Defaults to an hour, failures are kept for a day
+    pub window_secs: Option<u64>,
+}
+
+/// Why the SVM failed to load transactions within the window, most common cause first.
+fn get_load_failures_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "load-failures")
+        .and(warp::get())
+        .and(optional_query::<LoadFailuresQuery>())
+        .map(|query: LoadFailuresQuery| {
+            let window_secs = query.window_secs.unwrap_or(3600);
+            warp::reply::json(&serde_json::json!({
+                "windowSecs": window_secs,
+                "causes": load_failures::most_common(std::time::Duration::from_secs(window_secs)),
+            }))
+        })
+}
+
+fn get_storage_stats_route() -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    warp::path!("admin" / "storage")
+        .and(warp::get())
+        .map(|| {
+            let stats = compression::stats();
+            warp::reply::json(&serde_json::json!({
+                "compression": stats,
+                "bytesSaved": stats.bytes_saved(),
+            }))
+        })
+}
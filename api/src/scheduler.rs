@@ -0,0 +1,143 @@
+use async_trait::async_trait;
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::{interval, MissedTickBehavior};
+
+/// A periodic maintenance task driven by the `Scheduler`.
+#[async_trait]
+pub trait ScheduledJob: Send + Sync {
+    /// Unique name of the job. This is the key used for the job in the `SCHEDULER_JOBS` config
+    /// and in the `/admin/scheduler` output.
+    fn name(&self) -> &'static str;
+
+    async fn run(&self) -> anyhow::Result<()>;
+}
+
+/// Last run information for a scheduled job.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatus {
+    pub name: String,
+    pub enabled: bool,
+    pub interval_secs: u64,
+    pub running: bool,
+    pub run_count: u64,
+    pub failure_count: u64,
+    pub last_started_at: Option<u64>,
+    pub last_finished_at: Option<u64>,
+    pub last_duration_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+/// Shared, read-only view over the job statuses for the admin API.
+#[derive(Clone, Default)]
+pub struct SchedulerStatus {
+    jobs: Arc<RwLock<BTreeMap<String, JobStatus>>>,
+}
+
+impl SchedulerStatus {
+    pub fn snapshot(&self) -> Vec<JobStatus> {
+        self.jobs.read().unwrap().values().cloned().collect()
+    }
+
+    fn update(&self, name: &str, update: impl FnOnce(&mut JobStatus)) {
+        if let Some(status) = self.jobs.write().unwrap().get_mut(name) {
+            update(status);
+        }
+    }
+}
+
+/// A small cron-like scheduler for the node's periodic maintenance jobs.
+///
+/// Jobs are registered with a default interval which can be overridden per job through the
+/// `SCHEDULER_JOBS` config map (job name to interval in seconds, `0` disables the job). Each job
+/// runs on its own task, and a run that is still in progress when the next tick fires is skipped
+/// rather than overlapped.
+///
+/// Checkpoints are not a job, the committer writes them at the end of an epoch while the account
+/// set is the one they describe. Pruning and snapshots will be registered here once the node
+/// has them.
+pub struct Scheduler {
+    intervals: HashMap<String, u64>,
+    jobs: Vec<(Arc<dyn ScheduledJob>, Duration)>,
+    status: SchedulerStatus,
+}
+
+impl Scheduler {
+    pub fn new(intervals: HashMap<String, u64>) -> Self {
+        Scheduler {
+            intervals,
+            jobs: Vec::new(),
+            status: SchedulerStatus::default(),
+        }
+    }
+
+    pub fn register(&mut self, job: Arc<dyn ScheduledJob>, default_interval: Duration) {
+        let interval_secs = self.intervals.get(job.name()).copied().unwrap_or(default_interval.as_secs());
+        self.status.jobs.write().unwrap().insert(job.name().to_string(), JobStatus {
+            name: job.name().to_string(),
+            enabled: interval_secs > 0,
+            interval_secs,
+            ..Default::default()
+        });
+        if interval_secs > 0 {
+            self.jobs.push((job, Duration::from_secs(interval_secs)));
+        } else {
+            info!("Scheduled job {} is disabled", job.name());
+        }
+    }
+
+    pub fn status(&self) -> SchedulerStatus {
+        self.status.clone()
+    }
+
+    /// Spawns every enabled job onto the current tokio runtime.
+    pub fn start(self) -> SchedulerStatus {
+        for (job, period) in self.jobs {
+            let status = self.status.clone();
+            tokio::spawn(async move {
+                let mut ticker = interval(period);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                // The first tick completes immediately, let the node finish starting up first.
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    run_job(job.as_ref(), &status).await;
+                }
+            });
+        }
+        info!("Scheduler started");
+        self.status
+    }
+}
+
+async fn run_job(job: &dyn ScheduledJob, status: &SchedulerStatus) {
+    let started = Instant::now();
+    status.update(job.name(), |s| {
+        s.running = true;
+        s.last_started_at = Some(unix_timestamp());
+    });
+
+    let result = job.run().await;
+    if let Err(error) = &result {
+        warn!("Scheduled job {} failed: {:?}", job.name(), error);
+    }
+
+    status.update(job.name(), |s| {
+        s.running = false;
+        s.run_count += 1;
+        s.last_finished_at = Some(unix_timestamp());
+        s.last_duration_ms = Some(started.elapsed().as_millis() as u64);
+        s.last_error = result.err().map(|error| error.to_string());
+        if s.last_error.is_some() {
+            s.failure_count += 1;
+        }
+    });
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
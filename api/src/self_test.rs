@@ -0,0 +1,218 @@
+use base64::{engine::general_purpose, Engine as _};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use state::account_state::AccountState;
+use state::blob::{Blob, BlobReferences};
+use state::block::Block;
+use state::config::TrollupConfig;
+use state::sponsorship::SponsorSpend;
+use state::state_record::{StateCommitmentPackage, StateRecord};
+use state::transaction::TrollupTransaction;
+use state_commitment::http_client::build_client;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::StateManager;
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use trollup_zk::fixtures::dummy_accounts;
+use trollup_zk::prove::{generate_proof, generate_proof_load_keys, setup};
+use trollup_zk::verify::verify_proof_package;
+
+/// Result of a single self-test check.
+pub struct CheckResult {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+    pub elapsed: Duration,
+}
+
+/// Outcome of `--self-test`. Every check runs even if an earlier one failed so the report shows
+/// everything that is wrong with the deployment at once.
+#[derive(Default)]
+pub struct SelfTestReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    pub fn print(&self) {
+        println!("Trollup self-test");
+        for check in &self.checks {
+            println!(
+                "  [{}] {:<24} {:>6}ms  {}",
+                if check.passed { " OK " } else { "FAIL" },
+                check.name,
+                check.elapsed.as_millis(),
+                check.detail
+            );
+        }
+        let failed = self.checks.iter().filter(|check| !check.passed).count();
+        if failed == 0 {
+            println!("All {} checks passed", self.checks.len());
+        } else {
+            println!("{} of {} checks failed", failed, self.checks.len());
+        }
+    }
+
+    fn record(&mut self, name: &'static str, started: Instant, result: Result<String, String>) {
+        let (passed, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        self.checks.push(CheckResult { name, passed, detail, elapsed: started.elapsed() });
+    }
+}
+
+/// Runs the startup checks: configuration and keys, every state database, a proof round trip for
+/// a dummy batch, the validator, the L1 RPC and the on-chain state PDAs.
+pub async fn run() -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+
+    let started = Instant::now();
+    let config = match quietly(TrollupConfig::build) {
+        Ok(Ok(config)) => config,
+        Ok(Err(error)) => {
+            report.record("config", started, Err(error.to_string()));
+            return report;
+        }
+        Err(error) => {
+            report.record("config", started, Err(error));
+            return report;
+        }
+    };
+    report.record("config", started, Ok(format!("environment {}", config.solana_environment)));
+
+    let started = Instant::now();
+    report.record("mode", started, config.validate_mode().map(|_| config.mode.to_string()));
+
+    let started = Instant::now();
+    report.record("keys", started, check_keys(&config));
+
+    let started = Instant::now();
+    report.record("databases", started, check_databases(&config));
+
+    let started = Instant::now();
+    report.record("proof", started, check_proof());
+
+    let started = Instant::now();
+    report.record("validator", started, check_validator(&config).await);
+
+    let rpc_client = config.rpc_urls.get(&config.solana_environment).map(|url| RpcClient::new(url.to_string()));
+
+    let started = Instant::now();
+    let rpc_result = match &rpc_client {
+        Some(client) => client.get_version().await
+            .map(|version| format!("solana-core {}", version.solana_core))
+            .map_err(|e| e.to_string()),
+        None => Err(format!("No RPC url configured for {}", config.solana_environment)),
+    };
+    report.record("l1-rpc", started, rpc_result);
+
+    for (name, program_id) in [
+        ("proof-verifier-pda", config.proof_verifier_program_id_current_env()),
+        ("signature-verifier-pda", config.signature_verifier_program_id_current_env()),
+    ] {
+        let started = Instant::now();
+        let result = match &rpc_client {
+            Some(client) => check_state_pda(client, program_id).await,
+            None => Err("L1 RPC unavailable".to_string()),
+        };
+        report.record(name, started, result);
+    }
+
+    report
+}
+
+fn check_keys(config: &TrollupConfig) -> Result<String, String> {
+    Keypair::from_bytes(&config.trollup_api_keypair).map_err(|e| format!("API keypair: {}", e))?;
+    Keypair::from_bytes(&config.trollup_validator_keypair).map_err(|e| format!("Validator keypair: {}", e))?;
+    let proving_keys = Path::new("pk.bin").exists() && Path::new("vk.bin").exists();
+    Ok(format!("API and validator keypairs loaded, proving keys {}", if proving_keys { "present" } else { "not generated yet" }))
+}
+
+fn check_databases(config: &TrollupConfig) -> Result<String, String> {
+    let mut opened = Vec::new();
+    open::<AccountState>("accounts", &config.account_state_manager_db_path, &mut opened)?;
+    open::<Block>("blocks", &config.block_state_manager_db_path, &mut opened)?;
+    open::<TrollupTransaction>("transactions", &config.transaction_state_manager_db_path, &mut opened)?;
+    open::<StateCommitmentPackage<AccountState>>("optimistic commitments", &config.optimistic_commitment_state_manager_db_path, &mut opened)?;
+    open::<SponsorSpend>("sponsors", &config.sponsor_state_manager_db_path, &mut opened)?;
+    open::<Blob>("blobs", &config.blob_state_manager_db_path, &mut opened)?;
+    open::<BlobReferences>("blob references", &config.blob_references_db_path, &mut opened)?;
+    Ok(format!("opened {}", opened.join(", ")))
+}
+
+fn open<S: StateRecord>(name: &'static str, path: &str, opened: &mut Vec<String>) -> Result<(), String> {
+    quietly(|| StateManager::<SledStateManagement<S>>::new(path))
+        .map_err(|error| format!("{} ({}): {}", name, if path.is_empty() { "temporary" } else { path }, error))?;
+    opened.push(name.to_string());
+    Ok(())
+}
+
+/// Uses the persisted proving keys when present so the keys the node will run with are the ones
+/// being tested, otherwise runs a throwaway setup.
+fn check_proof() -> Result<String, String> {
+    let use_saved_keys = Path::new("pk.bin").exists() && Path::new("vk.bin").exists();
+    let (_, _, proof_package) = quietly(|| {
+        if use_saved_keys {
+            generate_proof_load_keys(dummy_accounts(), &[0u8; 32], &[0u8; 32], &[0u8; 32])
+        } else {
+            let (proving_key, verifying_key) = setup(false);
+            generate_proof(&proving_key, &verifying_key, dummy_accounts(), &[0u8; 32], &[0u8; 32], &[0u8; 32])
+        }
+    })?;
+
+    if verify_proof_package(&proof_package) {
+        Ok(format!("proof for dummy batch verified with {} keys", if use_saved_keys { "saved" } else { "fresh" }))
+    } else {
+        Err("proof for dummy batch did not verify".to_string())
+    }
+}
+
+async fn check_validator(config: &TrollupConfig) -> Result<String, String> {
+    let url = format!("{}/health", config.validator_url_current_env());
+    let client = build_client(config).map_err(|e| format!("HTTP client: {}", e))?;
+    let response = client
+        .get(&url)
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .map_err(|e| format!("{}: {}", url, e))?;
+    if response.status().is_success() {
+        Ok(format!("{} responded {}", url, response.status()))
+    } else {
+        Err(format!("{} responded {}", url, response.status()))
+    }
+}
+
+async fn check_state_pda(client: &RpcClient, program_id: &str) -> Result<String, String> {
+    let program_id = Pubkey::from_str(program_id).map_err(|e| format!("Invalid program id {:?}: {}", program_id, e))?;
+    let (pda, _) = Pubkey::find_program_address(&[b"state"], &program_id);
+    let data = client.get_account_data(&pda).await.map_err(|e| format!("{}: {}", pda, e))?;
+    if data.len() < 32 {
+        return Err(format!("{} holds {} bytes, expected a 32 byte root", pda, data.len()));
+    }
+    Ok(format!("{} root {}", pda, general_purpose::URL_SAFE.encode(&data[..32])))
+}
+
+/// Runs `f`, turning a panic into an error so one broken dependency doesn't abort the report.
+fn quietly<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+    let result = catch_unwind(AssertUnwindSafe(f));
+    std::panic::set_hook(hook);
+    result.map_err(|panic| panic_message(panic.as_ref()))
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    panic.downcast_ref::<String>()
+        .cloned()
+        .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+        .unwrap_or_else(|| "panicked".to_string())
+}
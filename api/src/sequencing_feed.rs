@@ -0,0 +1,165 @@
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde_derive::{Deserialize, Serialize};
+use server::error::ApiError;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use state::canonical_json;
+use state::config::TrollupConfig;
+use state::sequence_entry::SequenceEntry;
+use state::transaction::TrollupTransaction;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::StateManager;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use warp::http::header::CONTENT_TYPE;
+use warp::reply::with_header;
+use warp::{Rejection, Reply};
+
+type Result<T> = std::result::Result<T, Rejection>;
+
+/// Entries returned by `/sequence-feed` when no limit is given, and the most it returns.
+const DEFAULT_PAGE_SIZE: u64 = 100;
+const MAX_PAGE_SIZE: u64 = 1000;
+/// Entries buffered per WebSocket subscriber.
+const SUBSCRIBER_CAPACITY: usize = 1024;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref FEED: Option<SequencingFeed> = SequencingFeed::from_config(&CONFIG);
+}
+
+/// Append-only log of signed sequencing entries, one per admitted transaction.
+struct SequencingFeed {
+    store: StateManager<SledStateManagement<SequenceEntry>>,
+    sequencer: Keypair,
+    next_index: Mutex<u64>,
+    sender: broadcast::Sender<SequenceEntry>,
+}
+
+impl SequencingFeed {
+    /// Disabled when `SEQUENCE_FEED_DB_PATH` is empty.
+    fn from_config(config: &TrollupConfig) -> Option<Self> {
+        if config.sequence_feed_db_path.is_empty() {
+            return None;
+        }
+        let sequencer = match Keypair::from_bytes(&config.trollup_api_keypair) {
+            Ok(keypair) => keypair,
+            Err(error) => {
+                error!("Sequencing feed disabled, invalid API keypair: {}", error);
+                return None;
+            }
+        };
+        let store = StateManager::<SledStateManagement<SequenceEntry>>::new(&config.sequence_feed_db_path);
+        let next_index = store
+            .get_latest_block_id()
+            .and_then(|key| store.get_state_record(&key))
+            .map(|entry| entry.index + 1)
+            .unwrap_or(0);
+        info!("Sequencing feed signed by {} continues at index {}", sequencer.pubkey(), next_index);
+        Some(SequencingFeed {
+            store,
+            sequencer,
+            next_index: Mutex::new(next_index),
+            sender: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+        })
+    }
+
+    fn append(&self, transaction: &TrollupTransaction) -> SequenceEntry {
+        let mut next_index = self.next_index.lock().unwrap();
+        let transaction_hash = SequenceEntry::transaction_hash(transaction);
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default();
+        let message = SequenceEntry::signing_message(*next_index, &transaction_hash, timestamp);
+        let entry = SequenceEntry {
+            index: *next_index,
+            transaction_hash,
+            timestamp,
+            signature: self.sequencer.sign_message(&message).as_ref().to_vec(),
+        };
+        self.store.set_state_record(&entry);
+        self.store.set_latest_block_id(&entry.get_key());
+        self.store.commit();
+        *next_index += 1;
+        let _ = self.sender.send(entry.clone());
+        entry
+    }
+
+    fn entries(&self, from: u64, limit: u64) -> Vec<SequenceEntry> {
+        (from..from.saturating_add(limit))
+            .map_while(|index| self.store.get_state_record(&SequenceEntry::get_id(index)))
+            .collect()
+    }
+}
+
+/// Signs and records the admission of a transaction. Call it while holding the transaction pool
+/// lock, so the feed order is the pool order. Returns `None` when the feed is disabled.
+pub fn append(transaction: &TrollupTransaction) -> Option<SequenceEntry> {
+    FEED.as_ref().map(|feed| feed.append(transaction))
+}
+
+/// Up to `limit` entries starting at index `from`.
+pub fn entries(from: u64, limit: u64) -> Vec<SequenceEntry> {
+    FEED.as_ref().map(|feed| feed.entries(from, limit)).unwrap_or_default()
+}
+
+/// Entries appended from now on, `None` when the feed is disabled.
+pub fn subscribe() -> Option<broadcast::Receiver<SequenceEntry>> {
+    FEED.as_ref().map(|feed| feed.sender.subscribe())
+}
+
+/// JSON form of a `SequenceEntry`. The signature verifies against `sequencer` over
+/// `SequenceEntry::signing_message`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SequenceEntryView {
+    pub index: u64,
+    /// Hex encoded
+    pub transaction_hash: String,
+    pub timestamp: u64,
+    /// Base58 encoded
+    pub signature: String,
+}
+
+impl From<&SequenceEntry> for SequenceEntryView {
+    fn from(entry: &SequenceEntry) -> Self {
+        SequenceEntryView {
+            index: entry.index,
+            transaction_hash: hex::encode(entry.transaction_hash),
+            timestamp: entry.timestamp,
+            signature: Signature::try_from(entry.signature.as_slice()).map(|signature| signature.to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct SequenceFeedQuery {
+    #[serde(default)]
+    pub from: u64,
+    pub limit: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SequenceFeedResponse {
+    pub sequencer: String,
+    pub entries: Vec<SequenceEntryView>,
+}
+
+/// Served as canonical JSON, so clients in any language can hash a page as received.
+pub async fn get_sequence_feed(query: SequenceFeedQuery) -> Result<impl Reply> {
+    let Some(feed) = FEED.as_ref() else {
+        return Ok(ApiError::unavailable("The sequencing feed is disabled").into_response());
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    let response = SequenceFeedResponse {
+        sequencer: feed.sequencer.pubkey().to_string(),
+        entries: feed.entries(query.from, limit).iter().map(SequenceEntryView::from).collect(),
+    };
+    match canonical_json::to_string(&response) {
+        Ok(body) => Ok(with_header(body, CONTENT_TYPE, "application/json").into_response()),
+        Err(error) => {
+            error!("Failed to encode sequencing feed: {}", error);
+            Ok(ApiError::internal("Failed to encode the sequencing feed").into_response())
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::reputation::{Offence, Submitter};
+use execution::execution_engine::{simulate_transaction, SimulationResult};
+use execution::sysvars::SysvarProvider;
+use lazy_static::lazy_static;
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+use server::error::ApiError;
+use sha2::{Digest, Sha256};
+use solana_sdk::transaction::Transaction;
+use state::account_state::AccountState;
+use state::block::Block;
+use state::config::TrollupConfig;
+use state::transaction::convert_to_trollup_transaction;
+use state_commitment::block_feed;
+use state_commitment::governance;
+use state_commitment::rollup_status;
+use state_management::account_loader::TrollupAccountLoader;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::{ManageState, StateManager};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use warp::{reply::json, reply::Response, Rejection, Reply};
+
+type Result<T> = std::result::Result<T, Rejection>;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationResponse {
+    /// Hex account root of the finalized block the transaction was simulated against
+    pub state_root: String,
+    /// Whether the result was served from the simulation cache
+    pub cached: bool,
+    #[serde(flatten)]
+    pub result: SimulationResult,
+}
+
+/// Results of recent simulations keyed by (transaction hash, state root). Entries live for
+/// `SIMULATION_CACHE_TTL_MS` and are all dropped when a block is finalized, after which the same
+/// transaction can simulate differently.
+#[derive(Clone)]
+pub struct SimulationCache {
+    entries: Arc<Mutex<HashMap<([u8; 32], [u8; 32]), (Instant, SimulationResult)>>>,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+impl SimulationCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        SimulationCache {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+            max_entries,
+        }
+    }
+
+    pub fn from_config(config: &TrollupConfig) -> Self {
+        Self::new(Duration::from_millis(config.simulation_cache_ttl_ms), config.simulation_cache_max_entries)
+    }
+
+    pub fn get(&self, transaction_hash: &[u8; 32], state_root: &[u8; 32]) -> Option<SimulationResult> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&(*transaction_hash, *state_root))
+            .filter(|(cached_at, _)| cached_at.elapsed() < self.ttl)
+            .map(|(_, result)| result.clone())
+    }
+
+    /// Caches `result` unless the cache is disabled, or still full after dropping expired entries.
+    pub fn insert(&self, transaction_hash: [u8; 32], state_root: [u8; 32], result: SimulationResult) {
+        if self.ttl.is_zero() || self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries {
+            entries.retain(|_, (cached_at, _)| cached_at.elapsed() < self.ttl);
+        }
+        if entries.len() < self.max_entries {
+            entries.insert((transaction_hash, state_root), (Instant::now(), result));
+        }
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Clears the cache whenever a block is finalized. Must be called from within a Tokio runtime.
+    pub fn invalidate_on_finalize(&self) {
+        let cache = self.clone();
+        let mut blocks = block_feed::subscribe();
+        tokio::spawn(async move {
+            loop {
+                match blocks.recv().await {
+                    Ok(_) | Err(RecvError::Lagged(_)) => cache.clear(),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+pub struct SimulationHandler {
+    account_state_management: Arc<StateManager<SledStateManagement<AccountState>>>,
+    block_state_management: Arc<StateManager<SledStateManagement<Block>>>,
+    cache: SimulationCache,
+}
+
+impl SimulationHandler {
+    pub fn new(account_state_management: Arc<StateManager<SledStateManagement<AccountState>>>, block_state_management: Arc<StateManager<SledStateManagement<Block>>>, cache: SimulationCache) -> Self {
+        SimulationHandler { account_state_management, block_state_management, cache }
+    }
+
+    /// Executes `transaction` on top of the latest finalized block without committing it.
+    /// Failed simulations count against the submitter, see `reputation`.
+    pub async fn simulate_transaction(&self, transaction: Transaction, submitter: &Submitter) -> Result<Response> {
+        if let Err(banned) = submitter.admit(&transaction) {
+            return Ok(banned);
+        }
+        let trollup_transaction = match convert_to_trollup_transaction(transaction.clone()) {
+            Ok(trollup_transaction) => trollup_transaction,
+            Err(error) => {
+                submitter.offence(&transaction, Offence::InvalidSubmission);
+                return Ok(ApiError::invalid_transaction(format!("Invalid transaction: {}", error)).into_response());
+            }
+        };
+        let transaction_hash: [u8; 32] = Sha256::digest(borsh::to_vec(&trollup_transaction).unwrap()).into();
+
+        let latest_block = self.block_state_management
+            .get_latest_block_id()
+            .and_then(|id| self.block_state_management.get_state_record(&id));
+        let state_root = latest_block.as_ref().map(|block| *block.accounts_merkle_root).unwrap_or_default();
+        let next_block_number = latest_block.as_ref().map(|block| block.block_number + 1).unwrap_or(1);
+
+        if let Some(result) = self.cache.get(&transaction_hash, &state_root) {
+            if result.err.is_some() {
+                submitter.offence(&transaction, Offence::FailedSimulation);
+            }
+            return Ok(json(&SimulationResponse { state_root: hex::encode(state_root), cached: true, result }).into_response());
+        }
+
+        let account_state_management = Arc::clone(&self.account_state_management);
+        let simulation = tokio::task::spawn_blocking(move || {
+            let account_loader = TrollupAccountLoader::new(&account_state_management);
+            let mut sysvars = SysvarProvider::from_config(&CONFIG, next_block_number);
+            sysvars.set_metadata(rollup_status::metadata(&CONFIG, &governance::current()));
+            simulate_transaction(&account_loader, &sysvars, &trollup_transaction)
+        }).await;
+
+        let result = match simulation {
+            Ok(Ok(result)) => result,
+            Ok(Err(message)) => {
+                submitter.offence(&transaction, Offence::InvalidSubmission);
+                return Ok(ApiError::invalid_transaction(format!("Invalid transaction: {}", message)).into_response());
+            }
+            Err(error) => {
+                warn!("Simulation failed: {}", error);
+                return Ok(ApiError::internal("Simulation failed").into_response());
+            }
+        };
+        if result.err.is_some() {
+            submitter.offence(&transaction, Offence::FailedSimulation);
+        }
+        self.cache.insert(transaction_hash, state_root, result.clone());
+        Ok(json(&SimulationResponse { state_root: hex::encode(state_root), cached: false, result }).into_response())
+    }
+}
@@ -0,0 +1,166 @@
+use crate::handler::overloaded;
+use crate::load_shedding::SheddingThresholds;
+use crate::sequencing_feed::{self, SequenceEntryView};
+use execution::cost_model::CostModel;
+use execution::transaction_pool::TransactionPool;
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+use server::error::ApiError;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use state::config::TrollupConfig;
+use state::sponsorship::{co_sign, SponsorSpend, SponsorshipVoucher};
+use state::transaction::convert_to_trollup_transaction;
+use state_commitment::governance;
+use state_management::state_management::{ManageState, StateManager};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use warp::{reply::json, reply::Response, Rejection, Reply};
+
+type Result<T> = std::result::Result<T, Rejection>;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref COST_MODEL: CostModel = CostModel::from_config(&CONFIG);
+    static ref SHEDDING: SheddingThresholds = SheddingThresholds::from_config(&CONFIG);
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SponsoredTransactionRequest {
+    pub transaction: Transaction,
+    /// Required unless every instruction targets a program in `SPONSORED_PROGRAM_IDS`
+    #[serde(default)]
+    pub voucher: Option<SponsorshipVoucher>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SponsoredTransactionResponse {
+    pub sponsor: String,
+    pub fee_lamports: u64,
+    /// The sequencer's signature, use this to look the transaction up
+    pub signature: String,
+    /// The sequencer's signed admission, when the sequencing feed is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<SequenceEntryView>,
+}
+
+pub struct SponsorshipHandler<S: ManageState<Record=SponsorSpend>> {
+    transaction_pool: Arc<Mutex<TransactionPool>>,
+    sponsor_state_management: Arc<StateManager<S>>,
+}
+
+impl<S: ManageState<Record=SponsorSpend>> SponsorshipHandler<S> {
+    pub fn new(transaction_pool: Arc<Mutex<TransactionPool>>, sponsor_state_management: Arc<StateManager<S>>) -> Self {
+        SponsorshipHandler { transaction_pool, sponsor_state_management }
+    }
+
+    /// Validates the sponsorship, co-signs the transaction as its fee payer and charges the fee
+    /// against the sponsor's budget before the transaction is added to the pool. The client
+    /// builds the transaction with the sequencer as fee payer, see `co_sign`.
+    pub async fn send_sponsored_transaction(&self, request: SponsoredTransactionRequest, request_id: &str) -> Result<Response> {
+        let sequencer = match Keypair::from_bytes(&CONFIG.trollup_api_keypair) {
+            Ok(keypair) => keypair,
+            Err(_) => return Ok(ApiError::unavailable("Sponsorship is not available").into_response()),
+        };
+
+        let sponsor = match self.sponsor_for(&request, &sequencer.pubkey()) {
+            Ok(sponsor) => sponsor,
+            Err(message) => return Ok(ApiError::invalid_request(message).into_response()),
+        };
+
+        let sponsored = match co_sign(&request.transaction, &sequencer) {
+            Ok(transaction) => transaction,
+            Err(error) => return Ok(ApiError::invalid_transaction(format!("Invalid transaction: {}", error)).into_response()),
+        };
+        let fee_lamports = governance::current().lamports_per_signature * sponsored.signatures.len() as u64;
+
+        if let Some(voucher) = &request.voucher {
+            if fee_lamports > voucher.max_lamports {
+                return Ok(ApiError::unprocessable(format!("Fee of {} lamports exceeds the voucher limit", fee_lamports)).into_response());
+            }
+        }
+
+        // The pool lock serializes sponsored submissions so concurrent requests can't overspend a budget
+        let mut pool = self.transaction_pool.lock().await;
+        let budget = CONFIG.sponsor_budgets.get(&sponsor.to_string()).copied().unwrap_or_default();
+        let mut spend = self.sponsor_state_management
+            .get_state_record(&sponsor.to_bytes())
+            .unwrap_or(SponsorSpend { sponsor, ..Default::default() });
+        if spend.spent_lamports.saturating_add(fee_lamports) > budget {
+            return Ok(ApiError::unprocessable(format!("Sponsor budget exhausted for: {}", sponsor)).into_response());
+        }
+
+        let signature = sponsored.signatures[0].to_string();
+        let trollup_transaction = match convert_to_trollup_transaction(sponsored) {
+            Ok(trollup_transaction) => trollup_transaction,
+            Err(error) => return Ok(ApiError::invalid_transaction(format!("Invalid transaction: {}", error)).into_response()),
+        };
+        // Checked before charging the sponsor, a rejected transaction costs nothing
+        if let Err(message) = COST_MODEL.check(&trollup_transaction) {
+            return Ok(ApiError::invalid_transaction(message).into_response());
+        }
+        if let Err(message) = SHEDDING.admit(&trollup_transaction) {
+            return Ok(overloaded(&message));
+        }
+
+        spend.spent_lamports += fee_lamports;
+        spend.transaction_count += 1;
+        self.sponsor_state_management.set_state_record(&spend);
+        let sequence = sequencing_feed::append(&trollup_transaction);
+        pool.add_transaction_for_request(trollup_transaction, request_id);
+
+        Ok(json(&SponsoredTransactionResponse {
+            sponsor: sponsor.to_string(),
+            fee_lamports,
+            signature,
+            sequence: sequence.as_ref().map(SequenceEntryView::from),
+        }).into_response())
+    }
+
+    pub async fn get_all_sponsors(&self) -> Result<impl Reply> {
+        let spends: Vec<SponsorSpend> = self.sponsor_state_management
+            .get_all_entries()
+            .into_iter()
+            .map(|(_, spend)| spend)
+            .collect();
+        Ok(json(&spends))
+    }
+
+    /// Programs on the whitelist are sponsored by the sequencer itself, anything else needs a
+    /// valid voucher from a sponsor with a configured budget.
+    fn sponsor_for(&self, request: &SponsoredTransactionRequest, sequencer: &Pubkey) -> std::result::Result<Pubkey, String> {
+        match &request.voucher {
+            Some(voucher) => {
+                if !CONFIG.sponsor_budgets.contains_key(&voucher.sponsor.to_string()) {
+                    return Err(format!("Unknown sponsor: {}", voucher.sponsor));
+                }
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+                if voucher.expires_at < now {
+                    return Err("Sponsorship voucher has expired".to_string());
+                }
+                if !voucher.verify(&request.transaction) {
+                    return Err("Invalid sponsorship voucher".to_string());
+                }
+                Ok(voucher.sponsor)
+            }
+            None => {
+                let message = &request.transaction.message;
+                let whitelisted = !message.instructions.is_empty() && message.instructions.iter().all(|ix| {
+                    message.account_keys
+                        .get(ix.program_id_index as usize)
+                        .map(|program_id| CONFIG.sponsored_program_ids.iter().any(|id| Pubkey::from_str(id).ok().as_ref() == Some(program_id)))
+                        .unwrap_or(false)
+                });
+                if whitelisted {
+                    Ok(*sequencer)
+                } else {
+                    Err("Transaction requires a sponsorship voucher".to_string())
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::scheduler::ScheduledJob;
+use async_trait::async_trait;
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+use server::access_log::{AccessLog, RequestCounts};
+use state::config::OperationMode;
+use state_commitment::http_client;
+use state_commitment::rollup_status;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Operational stats of a node, what `TelemetryJob` reports. Nothing in it identifies the node,
+/// its operator or its users: no keys, addresses, URLs or transaction contents, and the
+/// instance ID is random and changes on every restart.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryReport {
+    pub instance_id: String,
+    pub node_version: String,
+    pub mode: String,
+    pub block_height: u64,
+    pub uptime_secs: u64,
+    /// Transactions finalized per second since the previous report
+    pub transactions_per_second: f64,
+    /// Requests served since the previous report
+    pub requests: u64,
+    /// Share of those requests answered with a server error
+    pub server_error_rate: f64,
+}
+
+/// Counters at the previous report.
+struct Previous {
+    at: Instant,
+    finalized_transactions: u64,
+    requests: RequestCounts,
+}
+
+/// Reports anonymized node stats to `TELEMETRY_ENDPOINT`. Only registered with
+/// `TELEMETRY_ENABLED`, every report is written to the `audit` log target as sent.
+pub struct TelemetryJob {
+    endpoint: String,
+    instance_id: String,
+    mode: OperationMode,
+    access_log: AccessLog,
+    started: Instant,
+    previous: Mutex<Previous>,
+}
+
+impl TelemetryJob {
+    pub fn new(endpoint: String, mode: OperationMode, access_log: AccessLog) -> Self {
+        let started = Instant::now();
+        TelemetryJob {
+            endpoint,
+            instance_id: hex::encode(rand::random::<[u8; 16]>()),
+            mode,
+            previous: Mutex::new(Previous {
+                at: started,
+                finalized_transactions: rollup_status::finalized_transactions(),
+                requests: access_log.counts(),
+            }),
+            access_log,
+            started,
+        }
+    }
+
+    /// Stats since the previous report, which this becomes.
+    fn report(&self) -> TelemetryReport {
+        let now = Instant::now();
+        let finalized_transactions = rollup_status::finalized_transactions();
+        let requests = self.access_log.counts();
+        let mut previous = self.previous.lock().unwrap();
+        let elapsed = now.duration_since(previous.at).as_secs_f64();
+        let transactions = finalized_transactions - previous.finalized_transactions;
+        let served = requests.requests - previous.requests.requests;
+        let server_errors = requests.server_errors - previous.requests.server_errors;
+        *previous = Previous { at: now, finalized_transactions, requests };
+        TelemetryReport {
+            instance_id: self.instance_id.clone(),
+            node_version: rollup_status::NODE_VERSION.to_string(),
+            mode: self.mode.to_string(),
+            block_height: rollup_status::finalized_block_number(),
+            uptime_secs: self.started.elapsed().as_secs(),
+            transactions_per_second: if elapsed > 0.0 { transactions as f64 / elapsed } else { 0.0 },
+            requests: served,
+            server_error_rate: if served > 0 { server_errors as f64 / served as f64 } else { 0.0 },
+        }
+    }
+}
+
+#[async_trait]
+impl ScheduledJob for TelemetryJob {
+    fn name(&self) -> &'static str {
+        "telemetry"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let report = self.report();
+        info!(target: "audit", "Sending telemetry to {}: {}", self.endpoint, serde_json::to_string(&report)?);
+        http_client::json_body(http_client::shared_client().post(&self.endpoint), &report)?
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -1,13 +1,28 @@
 use lazy_static::lazy_static;
 use sha2::{Digest, Sha256};
+use server::error::ApiError;
+use solana_sdk::signature::Signature;
+use state::block::Block;
 use state::transaction::{convert_to_solana_transaction, TrollupTransaction};
+use state::transaction_status::{CommitmentLevel, TransactionLifecycle, TransactionStatus};
+use state::views::TransactionView;
+use execution::transaction_pool::TransactionPool;
+use state_commitment::pipeline::{self, Stage};
+use state_commitment::load_failures;
+use state_commitment::transaction_index;
+use std::str::FromStr;
 use state_management::state_management::{ManageState, StateManager};
 use std::sync::Arc;
+use tokio::sync::Mutex;
 use warp::{reply::json, Rejection, Reply};
 use state::config::TrollupConfig;
+use crate::export::{self, ExportFormat};
 
 type Result<T> = std::result::Result<T, Rejection>;
 
+/// Blocks searched back from the latest one for the block holding a committed transaction.
+const MAX_STATUS_BLOCK_SEARCH: u64 = 1000;
+
 lazy_static! {
     static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
 }
@@ -22,18 +37,94 @@ impl <T: ManageState<Record=TrollupTransaction>> TransactionHandler<T> {
     }
 
     pub async fn get_transaction(&self, signature: &str) -> Result<impl Reply> {
-        let hash: [u8; 32] = Sha256::digest(signature.as_bytes()).into();
+        let Ok(parsed) = Signature::from_str(signature) else {
+            return Ok(ApiError::invalid_request(format!("Invalid signature: {}", signature)).into_response());
+        };
+        // Transactions are keyed by the SHA-256 of their first signature's bytes
+        let hash: [u8; 32] = Sha256::digest(parsed.as_ref()).into();
         let option = self.transaction_state_management.get_state_record(&hash);
         match option {
             None => {
-                Ok(json(&format!("No transaction found for: {:?}", signature)))
+                Ok(ApiError::not_found(format!("No transaction found for: {:?}", signature)).into_response())
             }
             Some(transaction) => {
-                Ok(json(&format!("Transaction details: {:?}", transaction)))
+                Ok(json(&TransactionView::from(&transaction)).into_response())
             }
         }
     }
 
+    /// How far the transaction with `signature` has made it. Blocks are searched back from the
+    /// latest one, a transaction committed longer ago is reported without its block. Transactions
+    /// that aren't stored yet are looked up in the pipeline, then in `pool`.
+    pub async fn get_transaction_status<B: ManageState<Record=Block>>(&self, signature: &str, block_state_management: &StateManager<B>, pool: &Mutex<TransactionPool>) -> Result<impl Reply> {
+        let signature = match Signature::from_str(signature) {
+            Ok(signature) => signature,
+            Err(_) => return Ok(ApiError::invalid_request(format!("Invalid signature: {}", signature)).into_response()),
+        };
+        let key: [u8; 32] = Sha256::digest(signature.as_ref()).into();
+
+        if self.transaction_state_management.get_state_record(&key).is_none() {
+            let status = match pipeline::batch_of(&key) {
+                Some(batch) if batch.stage == Stage::Executing => TransactionStatus {
+                    lifecycle: TransactionLifecycle::Executing,
+                    ..Default::default()
+                },
+                Some(batch) => TransactionStatus {
+                    commitment: Some(CommitmentLevel::Executed),
+                    lifecycle: if batch.stage == Stage::Optimistic { TransactionLifecycle::PendingOptimistic } else { TransactionLifecycle::Executed },
+                    optimistic_state_root: batch.state_root.filter(|_| batch.stage == Stage::Optimistic),
+                    ..Default::default()
+                },
+                // A transaction dropped before is waiting again once it was resubmitted
+                None if pool.lock().await.contains(&key) => TransactionStatus {
+                    lifecycle: TransactionLifecycle::Pooled,
+                    ..Default::default()
+                },
+                None => {
+                    let load_failure = load_failures::cause_of(&key);
+                    TransactionStatus {
+                        lifecycle: if load_failure.is_some() { TransactionLifecycle::Dropped } else { TransactionLifecycle::Unknown },
+                        load_failure,
+                        ..Default::default()
+                    }
+                }
+            };
+            return Ok(json(&status).into_response());
+        }
+
+        let indexed_block = transaction_index::locate(&key)
+            .and_then(|location| block_state_management.get_state_record(&Block::get_id(location.block_number)));
+        // Blocks finalized before the index existed are found by walking back from the latest one
+        let latest_block = || block_state_management
+            .get_latest_block_id()
+            .and_then(|id| block_state_management.get_state_record(&id));
+        let block = indexed_block.or_else(|| latest_block().and_then(|latest| {
+            let oldest = latest.block_number.saturating_sub(MAX_STATUS_BLOCK_SEARCH);
+            std::iter::successors(Some(latest), |block| {
+                (block.block_number > oldest.max(1))
+                    .then(|| block_state_management.get_state_record(&Block::get_id(block.block_number - 1)))
+                    .flatten()
+            })
+            .find(|block| block.transactions.contains(&key))
+        }));
+
+        Ok(json(&match block {
+            Some(block) => TransactionStatus {
+                commitment: Some(if block.l1_anchor.is_resolved() { CommitmentLevel::FinalizedOnChain } else { CommitmentLevel::Committed }),
+                block_number: Some(block.block_number),
+                l1_slot: block.l1_anchor.slot,
+                l1_signature: block.l1_anchor.signature,
+                lifecycle: TransactionLifecycle::Finalized,
+                ..Default::default()
+            },
+            None => TransactionStatus {
+                commitment: Some(CommitmentLevel::Committed),
+                lifecycle: TransactionLifecycle::Finalized,
+                ..Default::default()
+            },
+        }).into_response())
+    }
+
     pub async fn get_all_transactions(&self) -> Result<impl Reply> {
         let transactions = self.transaction_state_management.get_all_entries();
         let mut solana_txs = Vec::with_capacity(transactions.len());
@@ -42,5 +133,16 @@ impl <T: ManageState<Record=TrollupTransaction>> TransactionHandler<T> {
         }
         Ok(json(&solana_txs))
     }
+
+    /// JSON lines are Solana transactions like `/get-all-transactions`, borsh records are the
+    /// stored `TrollupTransaction`s.
+    pub async fn export_transactions(&self, format: ExportFormat) -> Result<impl Reply>
+    where
+        T: Send + Sync + 'static,
+    {
+        Ok(export::stream(Arc::clone(&self.transaction_state_management), format, |transaction| {
+            convert_to_solana_transaction(transaction).ok()
+        }))
+    }
     
 }
@@ -0,0 +1,79 @@
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use state::account_state::AccountState;
+use state::block::Block;
+use state::config::TrollupConfig;
+use state_management::account_loader::TrollupAccountLoader;
+use state_management::state_management::{ManageState, StateManager};
+use std::collections::HashMap;
+use std::time::Instant;
+use trollup_zk::prove;
+
+/// What the warmup loaded before the node started executing.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WarmupReport {
+    /// `PROGRAM_IDS_TO_LOAD` programs loaded
+    pub programs: usize,
+    /// Hot accounts read
+    pub accounts: usize,
+    pub proving_keys: bool,
+    pub elapsed_ms: u64,
+}
+
+/// Loads what the first blocks would otherwise wait for: the `PROGRAM_IDS_TO_LOAD` programs, the
+/// `WARMUP_HOT_ACCOUNTS` accounts the last `WARMUP_RECENT_BLOCKS` blocks wrote most often, and
+/// the proving keys saved by the last setup.
+pub fn warm_up<A, B>(accounts: &StateManager<A>, blocks: &StateManager<B>, config: &TrollupConfig) -> WarmupReport
+where
+    A: ManageState<Record = AccountState>,
+    B: ManageState<Record = Block>,
+{
+    let started = Instant::now();
+    let programs = TrollupAccountLoader::new(accounts).preload_programs();
+    // Reading them pulls their pages into the database cache
+    let hot = hottest_accounts(blocks, config.warmup_recent_blocks, config.warmup_hot_accounts);
+    let accounts = hot.iter().filter(|address| accounts.get_state_record(address).is_some()).count();
+    let proving_keys = match prove::proving_keys() {
+        Ok(_) => true,
+        Err(error) => {
+            warn!("No proving keys to preload: {}", error);
+            false
+        }
+    };
+    let report = WarmupReport {
+        programs,
+        accounts,
+        proving_keys,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    };
+    info!("Warmed up in {} ms: {} programs, {} accounts, proving keys {}",
+        report.elapsed_ms, report.programs, report.accounts, if report.proving_keys { "loaded" } else { "missing" });
+    report
+}
+
+/// Up to `count` addresses the last `recent_blocks` finalized blocks wrote most often, most
+/// written first and by address between equal counts. Blocks are persisted, so the ranking
+/// survives restarts.
+pub fn hottest_accounts<B: ManageState<Record = Block>>(blocks: &StateManager<B>, recent_blocks: u64, count: usize) -> Vec<[u8; 32]> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let latest = blocks
+        .get_latest_block_id()
+        .and_then(|id| blocks.get_state_record(&id))
+        .map(|block| block.block_number)
+        .unwrap_or_default();
+    let mut writes: HashMap<[u8; 32], u64> = HashMap::new();
+    for block_number in (1..=latest).rev().take(recent_blocks as usize) {
+        let Some(block) = blocks.get_state_record(&Block::get_id(block_number)) else {
+            continue;
+        };
+        for address in block.accounts {
+            *writes.entry(address).or_default() += 1;
+        }
+    }
+    let mut ranked: Vec<([u8; 32], u64)> = writes.into_iter().collect();
+    ranked.sort_by(|(a, a_writes), (b, b_writes)| b_writes.cmp(a_writes).then(a.cmp(b)));
+    ranked.into_iter().take(count).map(|(address, _)| address).collect()
+}
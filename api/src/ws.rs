@@ -0,0 +1,404 @@
+use crate::sequencing_feed::{self, SequenceEntryView};
+use base64::{engine::general_purpose, Engine as _};
+use futures_util::{SinkExt, StreamExt};
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use state::account_state::AccountState;
+use state::block::Block;
+use state::canonical_json;
+use state::config::TrollupConfig;
+use state::sequence_entry::SequenceEntry;
+use state::transaction::TrollupTransaction;
+use state::views::{AccountView, BlockView, FinalizedBlockView, TransactionView};
+use state_commitment::block_feed::{self, FinalizedBlock};
+use state_commitment::vault_reconciliation::is_withdrawal_instruction;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::StateManager;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use warp::ws::{Message, WebSocket};
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+}
+
+/// Subscriptions a single connection may hold at once.
+const MAX_SUBSCRIPTIONS_PER_CONNECTION: usize = 100;
+/// Historical entries sent when a sequence subscription starts at an earlier index.
+const MAX_SEQUENCE_BACKFILL: u64 = 1000;
+/// Stored blocks sent when a block subscription starts at an earlier block. A subscriber further
+/// behind sees the next live block skip ahead and subscribes again from where it got to.
+const MAX_BLOCK_BACKFILL: u64 = 100;
+
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+enum Subscription {
+    /// `accountSubscribe`. Only base64 encoding is supported, which is what the Solana web3
+    /// libraries request.
+    Account { pubkey: Pubkey },
+    /// `sequenceSubscribe`, the signed sequencing feed. `next_index` skips live entries that
+    /// were already sent as backfill.
+    Sequence { next_index: u64 },
+    /// `blockSubscribe`, every finalized block the filter matches with its transactions and
+    /// accounts. `next_block` skips live blocks that were already sent as backfill.
+    Block { next_block: u64, filter: BlockFilter },
+}
+
+/// Which blocks a block subscription is notified of, from the subscription config. Every
+/// condition given has to hold, without any every block is sent.
+#[derive(Default)]
+struct BlockFilter {
+    /// `mentionsAccountOrProgram`, a base58 address the block wrote or one of its transactions
+    /// references. Program IDs are account keys of the transactions calling them
+    mentions: Option<String>,
+    /// `withdrawals`, only blocks moving value off the rollup, i.e. with a transaction paying out
+    /// or unlocking withdrawals through the vault program
+    withdrawals: bool,
+    /// `VAULT_PROGRAM_ID`, without it no block has withdrawals
+    vault_program_id: Option<String>,
+}
+
+impl BlockFilter {
+    fn from_config(config: Option<&Value>) -> Result<Self, &'static str> {
+        let mentions = match config.and_then(|config| config.get("mentionsAccountOrProgram")) {
+            None | Some(Value::Null) => None,
+            Some(address) => {
+                let pubkey = address.as_str().and_then(|address| Pubkey::from_str(address).ok());
+                Some(pubkey.ok_or("Invalid param: mentionsAccountOrProgram must be a base58 encoded pubkey")?.to_string())
+            }
+        };
+        let withdrawals = match config.and_then(|config| config.get("withdrawals")) {
+            None | Some(Value::Null) => false,
+            Some(withdrawals) => withdrawals.as_bool().ok_or("Invalid param: withdrawals must be a boolean")?,
+        };
+        let vault_program_id = Some(CONFIG.vault_program_id.clone()).filter(|id| !id.is_empty());
+        Ok(BlockFilter { mentions, withdrawals, vault_program_id })
+    }
+
+    /// Evaluated against the block's account index and the account keys of its transactions.
+    fn matches(&self, block: &FinalizedBlockView) -> bool {
+        if self.withdrawals && !block.transactions.iter().any(|transaction| self.withdraws(transaction)) {
+            return false;
+        }
+        match &self.mentions {
+            Some(address) => block.block.accounts.contains(address) || block.transactions.iter().any(|transaction| transaction.account_keys.contains(address)),
+            None => true,
+        }
+    }
+
+    /// Whether one of the transaction's instructions is a vault withdrawal, payout or root unlock.
+    fn withdraws(&self, transaction: &TransactionView) -> bool {
+        let Some(vault_program_id) = &self.vault_program_id else {
+            return false;
+        };
+        transaction.instructions.iter().any(|instruction| {
+            &instruction.program_id == vault_program_id
+                && general_purpose::STANDARD.decode(&instruction.data).is_ok_and(|data| is_withdrawal_instruction(&data))
+        })
+    }
+}
+
+/// The stores a block subscription's backfill is read from.
+#[derive(Clone)]
+pub struct BlockHistory {
+    pub blocks: Arc<StateManager<SledStateManagement<Block>>>,
+    pub transactions: Arc<StateManager<SledStateManagement<TrollupTransaction>>>,
+    pub accounts: Arc<StateManager<SledStateManagement<AccountState>>>,
+}
+
+impl BlockHistory {
+    /// Up to `limit` stored blocks from `from` on. Stores only keep the latest state of an
+    /// account, so a block is sent with the accounts it wrote as they are now.
+    fn blocks_from(&self, from: u64, limit: u64) -> Vec<FinalizedBlockView> {
+        let latest = self.blocks
+            .get_latest_block_id()
+            .and_then(|id| self.blocks.get_state_record(&id))
+            .map(|block| block.block_number)
+            .unwrap_or_default();
+        (from.max(1)..=latest)
+            .take(limit as usize)
+            .map_while(|block_number| self.blocks.get_state_record(&Block::get_id(block_number)))
+            .map(|block| FinalizedBlockView {
+                block: BlockView::from(&block),
+                transactions: block.transactions
+                    .iter()
+                    .filter_map(|key| self.transactions.get_state_record(key))
+                    .map(|transaction| TransactionView::from(&transaction))
+                    .collect(),
+                accounts: block.accounts
+                    .iter()
+                    .filter_map(|address| self.accounts.get_state_record(address))
+                    .map(|account| AccountView::from(&account))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// Serves Solana RPC style pubsub on one WebSocket connection. Every subscription is notified
+/// from finalized blocks only, so `commitment` in the request config is accepted but ignored.
+pub async fn handle_connection(socket: WebSocket, history: BlockHistory) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut blocks = block_feed::subscribe();
+    let mut sequence_entries: Option<broadcast::Receiver<SequenceEntry>> = None;
+    let mut subscriptions: HashMap<u64, Subscription> = HashMap::new();
+    let mut next_subscription_id = 0u64;
+
+    loop {
+        tokio::select! {
+            message = receiver.next() => {
+                let Some(Ok(message)) = message else {
+                    break;
+                };
+                if message.is_close() {
+                    break;
+                }
+                let Ok(text) = message.to_str() else {
+                    continue;
+                };
+                let (response, backfill) = handle_request(text, &mut subscriptions, &mut next_subscription_id, &mut sequence_entries, &history);
+                if sender.send(Message::text(response.to_string())).await.is_err() {
+                    break;
+                }
+                for notification in backfill.unwrap_or_default() {
+                    if sender.send(Message::text(canonical_json::to_string(&notification).unwrap_or_default())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            entry = next_sequence_entry(&mut sequence_entries) => match entry {
+                Ok(entry) => {
+                    for notification in sequence_notifications(&entry, &mut subscriptions) {
+                        if sender.send(Message::text(canonical_json::to_string(&notification).unwrap_or_default())).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => warn!("WebSocket subscriber skipped {} sequence entries", skipped),
+                Err(RecvError::Closed) => sequence_entries = None,
+            },
+            block = blocks.recv() => match block {
+                Ok(block) => {
+                    let mut notifications = account_notifications(&block, &subscriptions);
+                    notifications.extend(block_notifications(&block, &mut subscriptions));
+                    for notification in notifications {
+                        if sender.send(Message::text(canonical_json::to_string(&notification).unwrap_or_default())).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => warn!("WebSocket subscriber skipped {} blocks", skipped),
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+    debug!("WebSocket connection closed with {} subscriptions", subscriptions.len());
+}
+
+/// Never resolves while the connection has no sequence subscription.
+async fn next_sequence_entry(receiver: &mut Option<broadcast::Receiver<SequenceEntry>>) -> Result<SequenceEntry, RecvError> {
+    match receiver {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Returns the response, and for a new sequence or block subscription the notifications of the
+/// entries or blocks it starts with.
+fn handle_request(
+    text: &str,
+    subscriptions: &mut HashMap<u64, Subscription>,
+    next_subscription_id: &mut u64,
+    sequence_entries: &mut Option<broadcast::Receiver<SequenceEntry>>,
+    history: &BlockHistory,
+) -> (Value, Option<Vec<Value>>) {
+    let Ok(request) = serde_json::from_str::<Value>(text) else {
+        return (error_response(Value::Null, INVALID_REQUEST, "Invalid request"), None);
+    };
+    match request.get("method").and_then(Value::as_str) {
+        Some("sequenceSubscribe") => sequence_subscribe(&request, subscriptions, next_subscription_id, sequence_entries),
+        Some("blockSubscribe") => block_subscribe(&request, subscriptions, next_subscription_id, history),
+        _ => (handle_account_request(&request, subscriptions, next_subscription_id), None),
+    }
+}
+
+/// The connection is subscribed to the block feed from the start, so a block finalized while the
+/// backfill is read is either in it or sent live.
+fn block_subscribe(
+    request: &Value,
+    subscriptions: &mut HashMap<u64, Subscription>,
+    next_subscription_id: &mut u64,
+    history: &BlockHistory,
+) -> (Value, Option<Vec<Value>>) {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    if subscriptions.len() >= MAX_SUBSCRIPTIONS_PER_CONNECTION {
+        return (error_response(id, INVALID_REQUEST, "Too many subscriptions"), None);
+    }
+    let config = request.get("params").and_then(|params| params.get(0));
+    let filter = match BlockFilter::from_config(config) {
+        Ok(filter) => filter,
+        Err(message) => return (error_response(id, INVALID_PARAMS, message), None),
+    };
+    let from = config.and_then(|config| config.get("from")).and_then(Value::as_u64);
+
+    let subscription_id = *next_subscription_id;
+    *next_subscription_id += 1;
+    let backfill = from.map(|from| history.blocks_from(from, MAX_BLOCK_BACKFILL)).unwrap_or_default();
+    let next_block = backfill.last().map(|block| block.block.block_number + 1).or(from).unwrap_or(0);
+    let notifications = backfill
+        .iter()
+        .filter(|block| filter.matches(block))
+        .map(|block| block_notification(block, subscription_id))
+        .collect();
+    subscriptions.insert(subscription_id, Subscription::Block { next_block, filter });
+    (json!({ "jsonrpc": "2.0", "result": subscription_id, "id": id }), Some(notifications))
+}
+
+fn sequence_subscribe(
+    request: &Value,
+    subscriptions: &mut HashMap<u64, Subscription>,
+    next_subscription_id: &mut u64,
+    sequence_entries: &mut Option<broadcast::Receiver<SequenceEntry>>,
+) -> (Value, Option<Vec<Value>>) {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    if subscriptions.len() >= MAX_SUBSCRIPTIONS_PER_CONNECTION {
+        return (error_response(id, INVALID_REQUEST, "Too many subscriptions"), None);
+    }
+    // Subscribe before reading the backfill so no entry falls between the two.
+    if sequence_entries.is_none() {
+        *sequence_entries = sequencing_feed::subscribe();
+    }
+    if sequence_entries.is_none() {
+        return (error_response(id, INVALID_REQUEST, "The sequencing feed is disabled"), None);
+    }
+    let from = request.get("params").and_then(|params| params.get(0)).and_then(|config| config.get("from")).and_then(Value::as_u64);
+
+    let subscription_id = *next_subscription_id;
+    *next_subscription_id += 1;
+    let backfill = from.map(|from| sequencing_feed::entries(from, MAX_SEQUENCE_BACKFILL)).unwrap_or_default();
+    let next_index = backfill.last().map(|entry| entry.index + 1).or(from).unwrap_or(0);
+    subscriptions.insert(subscription_id, Subscription::Sequence { next_index });
+    let notifications = backfill.iter().map(|entry| sequence_notification(entry, subscription_id)).collect();
+    (json!({ "jsonrpc": "2.0", "result": subscription_id, "id": id }), Some(notifications))
+}
+
+fn handle_account_request(request: &Value, subscriptions: &mut HashMap<u64, Subscription>, next_subscription_id: &mut u64) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let params = request.get("params").and_then(Value::as_array).cloned().unwrap_or_default();
+
+    match request.get("method").and_then(Value::as_str) {
+        Some("accountSubscribe") => {
+            let Some(pubkey) = params.first().and_then(Value::as_str).and_then(|pubkey| Pubkey::from_str(pubkey).ok()) else {
+                return error_response(id, INVALID_PARAMS, "Invalid param: expected a base58 encoded pubkey");
+            };
+            let encoding = params.get(1).and_then(|config| config.get("encoding")).and_then(Value::as_str).unwrap_or("base64");
+            if encoding != "base64" {
+                return error_response(id, INVALID_PARAMS, "Unsupported encoding, only base64 is supported");
+            }
+            if subscriptions.len() >= MAX_SUBSCRIPTIONS_PER_CONNECTION {
+                return error_response(id, INVALID_REQUEST, "Too many subscriptions");
+            }
+            let subscription_id = *next_subscription_id;
+            *next_subscription_id += 1;
+            subscriptions.insert(subscription_id, Subscription::Account { pubkey });
+            json!({ "jsonrpc": "2.0", "result": subscription_id, "id": id })
+        }
+        Some("accountUnsubscribe") | Some("sequenceUnsubscribe") | Some("blockUnsubscribe") => {
+            let Some(subscription_id) = params.first().and_then(Value::as_u64) else {
+                return error_response(id, INVALID_PARAMS, "Invalid param: expected a subscription id");
+            };
+            json!({ "jsonrpc": "2.0", "result": subscriptions.remove(&subscription_id).is_some(), "id": id })
+        }
+        _ => error_response(id, METHOD_NOT_FOUND, "Method not found"),
+    }
+}
+
+/// `accountNotification` messages for the subscribed accounts a block changed.
+fn account_notifications(block: &FinalizedBlock, subscriptions: &HashMap<u64, Subscription>) -> Vec<Value> {
+    if subscriptions.is_empty() {
+        return Vec::new();
+    }
+    let accounts: HashMap<&Pubkey, &AccountState> = block.accounts.iter().map(|account| (&account.address, account)).collect();
+    subscriptions
+        .iter()
+        .filter_map(|(subscription_id, subscription)| {
+            let Subscription::Account { pubkey } = subscription else {
+                return None;
+            };
+            let account = accounts.get(pubkey)?;
+            Some(json!({
+                "jsonrpc": "2.0",
+                "method": "accountNotification",
+                "params": {
+                    "result": {
+                        "context": { "slot": block.block.block_number },
+                        "value": {
+                            "lamports": account.lamports,
+                            "data": [general_purpose::STANDARD.encode(&account.data), "base64"],
+                            "owner": account.owner.to_string(),
+                            "executable": account.executable,
+                            "rentEpoch": account.rent_epoch,
+                            "space": account.data.len(),
+                        },
+                    },
+                    "subscription": subscription_id,
+                },
+            }))
+        })
+        .collect()
+}
+
+fn sequence_notifications(entry: &SequenceEntry, subscriptions: &mut HashMap<u64, Subscription>) -> Vec<Value> {
+    subscriptions
+        .iter_mut()
+        .filter_map(|(subscription_id, subscription)| match subscription {
+            Subscription::Sequence { next_index } if entry.index >= *next_index => {
+                *next_index = entry.index + 1;
+                Some(sequence_notification(entry, *subscription_id))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn sequence_notification(entry: &SequenceEntry, subscription_id: u64) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "sequenceNotification",
+        "params": { "result": SequenceEntryView::from(entry), "subscription": subscription_id },
+    })
+}
+
+fn block_notifications(block: &FinalizedBlock, subscriptions: &mut HashMap<u64, Subscription>) -> Vec<Value> {
+    let block_number = block.block.block_number;
+    let mut view = None;
+    subscriptions
+        .iter_mut()
+        .filter_map(|(subscription_id, subscription)| match subscription {
+            Subscription::Block { next_block, filter } if block_number >= *next_block => {
+                *next_block = block_number + 1;
+                let view = view.get_or_insert_with(|| FinalizedBlockView::from(block));
+                filter.matches(view).then(|| block_notification(view, *subscription_id))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn block_notification(block: &FinalizedBlockView, subscription_id: u64) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "blockNotification",
+        "params": { "result": block, "subscription": subscription_id },
+    })
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "error": { "code": code, "message": message }, "id": id })
+}
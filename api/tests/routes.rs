@@ -0,0 +1,1189 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use execution::transaction_pool::TransactionPool;
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use server::error::{ApiError, ErrorCode};
+use server::access_log::{AccessLog, AccessLogSettings};
+use server::body::MAX_BODY_SIZE;
+use solana_sdk::bs58;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{write_keypair_file, Signature, Signer};
+use solana_sdk::transaction::Transaction;
+use state::account_data::DataRangeProof;
+use state::account_state::AccountState;
+use state::block::{Block, L1Anchor};
+use state::config::OperationMode;
+use state::merkle::parallel_root;
+use state::node_info::{handshake, NodeInfo};
+use state::parameters::{GovernedParameters, ParameterProposal};
+use state::state_record::{StateCommitmentPackage, StateCommitmentPackageUI, StateRecord};
+use state::test_fixtures::{keypair, transfer_transaction};
+use state::transaction::{convert_to_trollup_transaction, TrollupTransaction};
+use state::transaction_status::{CommitmentLevel, LoadFailureCause, TransactionLifecycle, TransactionStatus};
+use state::views::{AccountView, BlockView, FinalizedBlockView, TransactionView};
+use state::witness::account_leaf;
+use state_commitment::bundles::{BundleState, BundleStatus};
+use state_commitment::load_failures::{self, LoadFailureCount};
+use state_commitment::merkle_index;
+use state_commitment::optimistic_commitments::{self, CommitmentRecord, CommitmentState, Resolution};
+use state_commitment::pipeline::{self, Stage};
+use state_management::blob_store::BlobStore;
+use state_management::state_management::StateManager;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Once};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use trollup_api::account_handler::AccountWithPending;
+use trollup_api::auth::{AdminToken, ApiKeys};
+use trollup_api::block_handler::{AccountDataProofResponse, AccountProofResponse, BlockPublicInputsResponse, L1AnchorResponse, VerifiedBlockResponse};
+use trollup_api::block_verification::{BlockVerification, BlockVerifier};
+use trollup_api::decode_handler::{DecodeStage, DecodeTransactionResponse};
+use trollup_api::governance_handler::{ParametersView, ProposalView};
+use trollup_api::handler::{BundleSubmission, PipelineSnapshot};
+use trollup_api::health::HealthEvents;
+use trollup_api::load_shedding::SheddingThresholds;
+use trollup_api::rate_limit::{RateLimitSettings, RateLimiter};
+use trollup_api::reindex::ReindexProgress;
+use trollup_api::reputation::{Reputation, ReputationSettings, SourceRecord};
+use trollup_api::routes::{routes, ApiState, ApiVersioning};
+use trollup_api::scheduler::{JobStatus, ScheduledJob, Scheduler};
+use trollup_api::simulation_handler::SimulationCache;
+use trollup_zk::public_inputs::compute_public_inputs;
+use warp::http::StatusCode;
+use warp::test::{request, WsClient};
+
+const SYSTEM_LANE_TOKEN: &str = "routes-test-token";
+const ADMIN_TOKEN: &str = "routes-test-admin-token";
+
+static CONFIGURE: Once = Once::new();
+static PAYERS: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    /// The Merkle and transaction indexes are process wide, tests using them take turns.
+    static ref INDEXES: Mutex<()> = Mutex::new(());
+}
+
+/// Handlers read the process configuration, which only needs keypairs on top of the defaults.
+/// Every database path is empty, so each state manager is a temporary sled database.
+fn configure() {
+    CONFIGURE.call_once(|| {
+        let directory = std::env::temp_dir().join(format!("trollup-api-routes-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let keypair_path = directory.join("keypair.json");
+        write_keypair_file(&keypair("sequencer"), &keypair_path).unwrap();
+        std::env::set_var("TROLLUP_API_KEYPAIR_PATH", &keypair_path);
+        std::env::set_var("TROLLUP_VALIDATOR_KEYPAIR_PATH", &keypair_path);
+        std::env::set_var("SYSTEM_LANE_TOKEN", SYSTEM_LANE_TOKEN);
+        let governance_signers: Vec<String> = (0..2).map(|index| keypair(&format!("governance/{}", index)).pubkey().to_string()).collect();
+        std::env::set_var("GOVERNANCE_SIGNERS", governance_signers.join(","));
+        std::env::set_var("GOVERNANCE_THRESHOLD", "2");
+    });
+}
+
+fn api_state(operation_mode: OperationMode, read_only: bool) -> ApiState {
+    configure();
+    ApiState {
+        transaction_pool: Arc::new(Mutex::new(TransactionPool::new())),
+        account_state_manager: Arc::new(StateManager::new("")),
+        transaction_state_manager: Arc::new(StateManager::new("")),
+        block_state_manager: Arc::new(StateManager::new("")),
+        optimistic_commitment_state_management: Arc::new(StateManager::new("")),
+        sponsor_state_manager: Arc::new(StateManager::new("")),
+        inbox_state_manager: Arc::new(StateManager::new("")),
+        blob_store: Arc::new(BlobStore::new(Arc::new(StateManager::new("")), Arc::new(StateManager::new("")))),
+        scheduler_status: Scheduler::new(HashMap::new()).status(),
+        access_log: AccessLog::new("trollup-api", AccessLogSettings::default()),
+        simulation_cache: SimulationCache::new(Duration::from_secs(60), 16),
+        operation_mode,
+        read_only,
+        shedding: SheddingThresholds {
+            reject_optimistic: 0,
+            minimum_fee: 0,
+            reject_all: 0,
+            minimum_compute_unit_price: 0,
+            mode: operation_mode,
+        },
+        block_verifier: BlockVerifier::load(),
+        health_events: HealthEvents::default(),
+        versioning: ApiVersioning::default(),
+        reputation: Reputation::default(),
+        api_keys: ApiKeys::default(),
+        rate_limiter: RateLimiter::default(),
+        admin_token: AdminToken::new(ADMIN_TOKEN.to_string()),
+    }
+}
+
+async fn get(state: &ApiState, path: &str) -> (StatusCode, Vec<u8>) {
+    let response = request().method("GET").path(path).reply(&routes(state.clone())).await;
+    (response.status(), response.body().to_vec())
+}
+
+async fn post(state: &ApiState, path: &str, body: &Value) -> (StatusCode, Vec<u8>) {
+    let response = request().method("POST").path(path).json(body).reply(&routes(state.clone())).await;
+    (response.status(), response.body().to_vec())
+}
+
+/// A request to an admin route that changes the node, with the operator token unless it's left out.
+async fn admin(state: &ApiState, method: &str, path: &str, body: &Value, token: Option<&str>) -> (StatusCode, Vec<u8>) {
+    let mut builder = request().method(method).path(path).json(body);
+    if let Some(token) = token {
+        builder = builder.header("authorization", format!("Bearer {}", token));
+    }
+    let response = builder.reply(&routes(state.clone())).await;
+    (response.status(), response.body().to_vec())
+}
+
+/// The body of a successful GET, deserialized into the type clients read it as.
+async fn get_ok<T: DeserializeOwned>(state: &ApiState, path: &str) -> T {
+    let (status, body) = get(state, path).await;
+    assert_eq!(status, StatusCode::OK, "GET {}", path);
+    serde_json::from_slice(&body).unwrap_or_else(|error| panic!("GET {}: {} in {}", path, error, String::from_utf8_lossy(&body)))
+}
+
+async fn get_message(state: &ApiState, path: &str) -> String {
+    get_ok::<String>(state, path).await
+}
+
+/// The `ApiError` of a failed GET, checked to come with the status of its code.
+async fn get_error(state: &ApiState, path: &str) -> ApiError {
+    let (status, body) = get(state, path).await;
+    let error: ApiError = serde_json::from_slice(&body).unwrap_or_else(|error| panic!("GET {}: {} in {}", path, error, String::from_utf8_lossy(&body)));
+    assert_eq!(status, error.status(), "GET {}", path);
+    error
+}
+
+fn account(lamports: u64, data: Vec<u8>) -> AccountState {
+    AccountState {
+        address: Pubkey::new_unique(),
+        lamports,
+        data,
+        owner: Pubkey::new_unique(),
+        executable: false,
+        rent_epoch: 0,
+        data_root: None,
+    }
+}
+
+/// A transfer from a payer no other call of the process uses.
+fn transfer() -> Transaction {
+    let payer = keypair(&format!("payer/{}", PAYERS.fetch_add(1, Ordering::Relaxed)));
+    transfer_transaction(&payer, &Pubkey::new_unique(), 1_000, Hash::new_unique())
+}
+
+/// A block writing `accounts`, with the account leaves the Merkle index is built from.
+fn block_writing(block_number: u64, accounts: &[AccountState], transactions: &[TrollupTransaction]) -> Block {
+    let leaves: Vec<[u8; 32]> = accounts.iter().map(account_leaf).collect();
+    let root = parallel_root(&leaves).unwrap_or_default();
+    let mut block = Block::new(
+        block_number,
+        Block::get_id(block_number - 1),
+        Box::new([1u8; 32]),
+        Box::new(root),
+        vec![],
+        vec![],
+        [0u8; 32],
+        transactions.iter().map(StateRecord::get_key).collect(),
+        accounts.iter().map(|account| account.address.to_bytes()).collect(),
+    );
+    block.account_leaves = leaves;
+    block
+}
+
+fn store_block(state: &ApiState, block: &Block) {
+    state.block_state_manager.set_state_record(block);
+    state.block_state_manager.set_latest_block_id(&block.get_key());
+}
+
+#[tokio::test]
+async fn test_versions_and_health() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let versions: Value = get_ok(&state, "/versions").await;
+    assert_eq!(versions["current"], "v1");
+    assert_eq!(get_message(&state, "/health").await, "ok");
+    assert_eq!(get_message(&state, "/v1/health").await, "ok");
+    assert_eq!(get_error(&state, "/v1/no-such-route").await.code, ErrorCode::NotFound);
+}
+
+#[tokio::test]
+async fn test_account_routes() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let stored = account(42, vec![1, 2, 3]);
+    state.account_state_manager.set_state_record(&stored);
+
+    let fetched: AccountState = get_ok(&state, &format!("/v1/get-account/{}", stored.address)).await;
+    assert_eq!(fetched.address, stored.address);
+    assert_eq!(fetched.lamports, 42);
+    assert_eq!(fetched.data, vec![1, 2, 3]);
+    assert_eq!(fetched.owner, stored.owner);
+
+    let all: Vec<([u8; 32], AccountState)> = get_ok(&state, "/v1/get-all-accounts").await;
+    assert_eq!(all.len(), 1);
+    assert_eq!(all[0].0, stored.address.to_bytes());
+
+    assert_eq!(get_error(&state, "/v1/get-account/not-a-pubkey").await, ApiError::invalid_request("Invalid account id: \"not-a-pubkey\""));
+    let missing = Pubkey::new_unique().to_string();
+    assert_eq!(get_error(&state, &format!("/v1/get-account/{}", missing)).await, ApiError::not_found(format!("No account found for: {:?}", missing)));
+}
+
+#[tokio::test]
+async fn test_account_route_overlays_pending_writes() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let stored = account(42, vec![]);
+    state.account_state_manager.set_state_record(&stored);
+    let created = account(5, vec![]);
+    let path = format!("/v1/get-account/{}?include_pending=true", stored.address);
+
+    let fetched: AccountWithPending = get_ok(&state, &path).await;
+    assert_eq!(fetched.account.unwrap().lamports, 42);
+    assert!(fetched.pending.is_none());
+
+    let batch = vec![convert_to_trollup_transaction(transfer()).unwrap()];
+    pipeline::enter(&batch, Stage::AwaitingProof, &[], None);
+    pipeline::record_writes(&batch, &[AccountState { lamports: 40, ..stored.clone() }, created.clone()]);
+    pipeline::enter(&batch, Stage::Optimistic, &[], None);
+
+    let fetched: AccountWithPending = get_ok(&state, &path).await;
+    assert_eq!(fetched.account.unwrap().lamports, 42);
+    let pending = fetched.pending.unwrap();
+    assert_eq!(pending.stage, Stage::Optimistic);
+    assert_eq!(pending.account.lamports, 40);
+    let fetched: AccountWithPending = get_ok(&state, &format!("/v1/get-account/{}?include_pending=true", created.address)).await;
+    assert!(fetched.account.is_none());
+    assert_eq!(fetched.pending.unwrap().account.lamports, 5);
+
+    // Without the flag only the finalized state is returned
+    let finalized: AccountState = get_ok(&state, &format!("/v1/get-account/{}", stored.address)).await;
+    assert_eq!(finalized.lamports, 42);
+    assert_eq!(get_error(&state, &format!("/v1/get-account/{}", created.address)).await, ApiError::not_found(format!("No account found for: {:?}", created.address.to_string())));
+
+    pipeline::leave(&batch);
+    let fetched: AccountWithPending = get_ok(&state, &path).await;
+    assert!(fetched.pending.is_none());
+}
+
+#[tokio::test]
+async fn test_legacy_routes_follow_the_versioning_settings() {
+    let mut state = api_state(OperationMode::Hybrid, false);
+    let stored = account(7, vec![]);
+    state.account_state_manager.set_state_record(&stored);
+    let legacy_path = format!("/get-account/{}", stored.address);
+    assert_eq!(get(&state, &legacy_path).await.0, StatusCode::NOT_FOUND);
+
+    state.versioning = ApiVersioning { legacy_routes_enabled: true, legacy_routes_sunset: String::new() };
+    let response = request().method("GET").path(&legacy_path).reply(&routes(state.clone())).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["deprecation"], "true");
+    let fetched: AccountState = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(fetched.lamports, 7);
+}
+
+#[tokio::test]
+async fn test_transaction_routes() {
+    let _indexes = INDEXES.lock().await;
+    let state = api_state(OperationMode::Hybrid, false);
+    let transaction = transfer();
+    let signature = transaction.signatures[0].to_string();
+    let trollup_transaction = convert_to_trollup_transaction(transaction).unwrap();
+    state.transaction_state_manager.set_state_record(&trollup_transaction);
+
+    let view: TransactionView = get_ok(&state, &format!("/v1/get-transaction/{}", signature)).await;
+    assert_eq!(view, TransactionView::from(&trollup_transaction));
+    let all: Vec<Transaction> = get_ok(&state, "/v1/get-all-transactions").await;
+    assert_eq!(all.len(), 1);
+    assert_eq!(all[0].signatures[0].to_string(), signature);
+
+    assert_eq!(get_error(&state, "/v1/get-transaction/bad").await, ApiError::invalid_request("Invalid signature: bad"));
+    let unknown = transfer().signatures[0].to_string();
+    assert_eq!(get_error(&state, &format!("/v1/get-transaction/{}", unknown)).await, ApiError::not_found(format!("No transaction found for: {:?}", unknown)));
+
+    // Stored but in no block yet, then in an anchored block
+    let status: TransactionStatus = get_ok(&state, &format!("/v1/get-transaction-status/{}", signature)).await;
+    assert_eq!(status.commitment, Some(CommitmentLevel::Committed));
+    assert_eq!(status.block_number, None);
+    let mut block = block_writing(1, &[], std::slice::from_ref(&trollup_transaction));
+    block.l1_anchor = L1Anchor { slot: Some(9), signature: Some("anchor".to_string()) };
+    store_block(&state, &block);
+    let status: TransactionStatus = get_ok(&state, &format!("/v1/get-transaction-status/{}", signature)).await;
+    assert_eq!(status.commitment, Some(CommitmentLevel::FinalizedOnChain));
+    assert_eq!(status.block_number, Some(1));
+    assert_eq!(status.l1_slot, Some(9));
+
+    let status: TransactionStatus = get_ok(&state, &format!("/v1/get-transaction-status/{}", unknown)).await;
+    assert_eq!(status, TransactionStatus::default());
+}
+
+#[tokio::test]
+async fn test_metrics_route() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let (status, _) = post(&state, "/v1/send-transaction", &serde_json::to_value(transfer()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    let unknown = transfer().signatures[0].to_string();
+    assert_eq!(get(&state, &format!("/v1/get-transaction/{}", unknown)).await.0, StatusCode::NOT_FOUND);
+
+    let response = request().method("GET").path("/metrics").reply(&routes(state.clone())).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+    let body = String::from_utf8(response.body().to_vec()).unwrap();
+    assert!(body.contains("trollup_api_transaction_pool_depth 1"), "{}", body);
+    // Path parameters are left out of the route label
+    let not_found = body.lines().find(|line| line.starts_with("trollup_api_requests_total") && line.contains("route=\"get-transaction\"") && line.contains("status=\"404\""));
+    assert!(not_found.is_some(), "{}", body);
+    assert!(!body.contains(&unknown));
+    assert!(body.contains("trollup_api_request_duration_seconds_count{route=\"send-transaction\"}"), "{}", body);
+}
+
+#[tokio::test]
+async fn test_transaction_lifecycle() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let transaction = transfer();
+    let signature = transaction.signatures[0].to_string();
+    let path = format!("/v1/get-transaction-status/{}", signature);
+    let status: TransactionStatus = get_ok(&state, &path).await;
+    assert_eq!(status.lifecycle, TransactionLifecycle::Unknown);
+
+    let (response_status, _) = post(&state, "/v1/send-transaction-optimistic", &serde_json::to_value(&transaction).unwrap()).await;
+    assert_eq!(response_status, StatusCode::OK);
+    let status: TransactionStatus = get_ok(&state, &path).await;
+    assert_eq!((status.lifecycle, status.commitment), (TransactionLifecycle::Pooled, None));
+
+    let batch = state.transaction_pool.lock().await.get_next_transactions(1);
+    pipeline::enter(&batch, Stage::Executing, &[], None);
+    let status: TransactionStatus = get_ok(&state, &path).await;
+    assert_eq!(status.lifecycle, TransactionLifecycle::Executing);
+    pipeline::enter(&batch, Stage::Optimistic, &[], Some([0xd1; 32]));
+    let status: TransactionStatus = get_ok(&state, &path).await;
+    assert_eq!((status.lifecycle, status.commitment), (TransactionLifecycle::PendingOptimistic, Some(CommitmentLevel::Executed)));
+    assert_eq!(status.optimistic_state_root, Some(hex::encode([0xd1; 32])));
+    pipeline::leave(&batch);
+
+    state.transaction_state_manager.set_state_record(&batch[0]);
+    let status: TransactionStatus = get_ok(&state, &path).await;
+    assert_eq!(status.lifecycle, TransactionLifecycle::Finalized);
+}
+
+#[tokio::test]
+async fn test_load_failure_routes() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let signature = Signature::new_unique();
+    load_failures::record(Sha256::digest(signature.as_ref()).into(), LoadFailureCause::RentViolation);
+
+    let status: TransactionStatus = get_ok(&state, &format!("/v1/get-transaction-status/{}", signature)).await;
+    assert_eq!(status.commitment, None);
+    assert_eq!(status.lifecycle, TransactionLifecycle::Dropped);
+    assert_eq!(status.load_failure, Some(LoadFailureCause::RentViolation));
+
+    let failures: Value = get_ok(&state, "/v1/admin/load-failures?windowSecs=60").await;
+    assert_eq!(failures["windowSecs"], 60);
+    let causes: Vec<LoadFailureCount> = serde_json::from_value(failures["causes"].clone()).unwrap();
+    assert!(causes.iter().any(|count| count.cause == LoadFailureCause::RentViolation && count.count >= 1));
+}
+
+#[tokio::test]
+async fn test_send_transaction_routes() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let transaction = serde_json::to_value(transfer()).unwrap();
+    let (status, body) = post(&state, "/v1/send-transaction", &transaction).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(serde_json::from_slice::<String>(&body).unwrap(), "Transaction submitted successfully");
+
+    let (status, body) = post(&state, "/v1/send-transaction-optimistic", &serde_json::to_value(transfer()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(serde_json::from_slice::<String>(&body).unwrap(), "Optimistic transaction submitted successfully");
+    let stats: Value = get_ok(&state, "/v1/stats").await;
+    assert_eq!(stats["transactionPool"], 2);
+
+    let mut unsigned = transfer();
+    unsigned.signatures.clear();
+    let (status, body) = post(&state, "/v1/send-transaction", &serde_json::to_value(unsigned).unwrap()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(serde_json::from_slice::<ApiError>(&body).unwrap(), ApiError::invalid_transaction("Transaction must be signed"));
+
+    let (status, body) = post(&state, "/v1/send-transaction", &json!({ "not": "a transaction" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(serde_json::from_slice::<ApiError>(&body).unwrap().code, ErrorCode::InvalidBody);
+}
+
+#[tokio::test]
+async fn test_submissions_over_the_compute_limit_are_rejected() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let payer = keypair("compute-limit/payer");
+    // Programs without a configured cost are charged the default of 200k units each
+    let instructions: Vec<Instruction> = (0..7).map(|_| Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![])).collect();
+    let transaction = Transaction::new_signed_with_payer(&instructions, Some(&payer.pubkey()), &[&payer], Hash::new_unique());
+
+    for path in ["/v1/send-transaction", "/v1/send-transaction-optimistic"] {
+        let (status, body) = post(&state, path, &serde_json::to_value(&transaction).unwrap()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let expected = ApiError::invalid_transaction("Transaction is estimated at 1400720 compute units, the limit is 1400000");
+        assert_eq!(serde_json::from_slice::<ApiError>(&body).unwrap(), expected);
+    }
+    let stats: Value = get_ok(&state, "/v1/stats").await;
+    assert_eq!(stats["transactionPool"], 0);
+}
+
+#[tokio::test]
+async fn test_bundle_routes() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let bundle = json!([transfer(), transfer()]);
+    let (status, body) = post(&state, "/v1/send-transaction-bundle", &bundle).await;
+    assert_eq!(status, StatusCode::OK);
+    let submission: BundleSubmission = serde_json::from_slice(&body).unwrap();
+    let bundle_status: BundleStatus = get_ok(&state, &format!("/v1/get-bundle-status/{}", submission.bundle_id)).await;
+    assert_eq!(bundle_status.state, BundleState::Pending);
+    assert_eq!(bundle_status.signatures.len(), 2);
+
+    // The same transactions can't be bundled again while the bundle is pending
+    assert_eq!(post(&state, "/v1/send-transaction-bundle", &bundle).await.0, StatusCode::CONFLICT);
+    assert_eq!(post(&state, "/v1/send-transaction-bundle", &json!([])).await.0, StatusCode::BAD_REQUEST);
+    let duplicate = transfer();
+    assert_eq!(post(&state, "/v1/send-transaction-bundle", &json!([duplicate, duplicate])).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(get(&state, &format!("/v1/get-bundle-status/{}", hex::encode([0u8; 32]))).await.0, StatusCode::NOT_FOUND);
+    let stats: Value = get_ok(&state, "/v1/stats").await;
+    assert_eq!(stats["transactionPool"], 2);
+}
+
+#[tokio::test]
+async fn test_write_routes_depend_on_the_mode() {
+    let proved = api_state(OperationMode::Proved, false);
+    let transaction = serde_json::to_value(transfer()).unwrap();
+    assert_eq!(post(&proved, "/v1/send-transaction-optimistic", &transaction).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(get(&proved, "/v1/get-all-pending-commitments").await.0, StatusCode::NOT_FOUND);
+    assert_eq!(post(&proved, "/v1/send-transaction", &transaction).await.0, StatusCode::OK);
+
+    let follower = api_state(OperationMode::Hybrid, true);
+    assert_eq!(post(&follower, "/v1/send-transaction", &transaction).await.0, StatusCode::NOT_FOUND);
+    // Only the progress route is left at the reindex path
+    assert_eq!(post(&follower, "/v1/admin/reindex", &json!({})).await.0, StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(admin(&follower, "POST", "/v1/admin/reindex", &json!({}), Some(ADMIN_TOKEN)).await.0, StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(get(&follower, "/v1/get-all-accounts").await.0, StatusCode::OK);
+}
+
+async fn send_system(state: &ApiState, transaction: &Value, token: Option<&str>) -> (StatusCode, Vec<u8>) {
+    let mut builder = request().method("POST").path("/v1/admin/system-transaction").json(transaction);
+    if let Some(token) = token {
+        builder = builder.header("authorization", format!("Bearer {}", token));
+    }
+    let response = builder.reply(&routes(state.clone())).await;
+    (response.status(), response.body().to_vec())
+}
+
+#[tokio::test]
+async fn test_system_transaction_requires_the_token() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let transaction = serde_json::to_value(transfer()).unwrap();
+    assert_eq!(send_system(&state, &transaction, None).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(send_system(&state, &transaction, Some("wrong")).await.0, StatusCode::UNAUTHORIZED);
+    let (status, body) = send_system(&state, &transaction, Some(SYSTEM_LANE_TOKEN)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(serde_json::from_slice::<String>(&body).unwrap(), "System transaction submitted successfully");
+    let pipeline: PipelineSnapshot = get_ok(&state, "/v1/admin/pipeline").await;
+    assert_eq!(pipeline.system_lane, 1);
+}
+
+async fn send_with_key(state: &ApiState, path: &str, header: Option<(&str, &str)>) -> StatusCode {
+    let mut builder = request().method("POST").path(path).json(&transfer());
+    if let Some((name, value)) = header {
+        builder = builder.header(name, value);
+    }
+    builder.reply(&routes(state.clone())).await.status()
+}
+
+#[tokio::test]
+async fn test_submission_routes_require_an_api_key() {
+    let mut state = api_state(OperationMode::Hybrid, false);
+    state.api_keys = ApiKeys::new(vec!["first-key".to_string(), "second-key".to_string()]);
+
+    for path in ["/v1/send-transaction", "/v1/send-transaction-optimistic"] {
+        assert_eq!(send_with_key(&state, path, None).await, StatusCode::UNAUTHORIZED, "{}", path);
+        assert_eq!(send_with_key(&state, path, Some(("x-api-key", "wrong-key"))).await, StatusCode::UNAUTHORIZED, "{}", path);
+        assert_eq!(send_with_key(&state, path, Some(("authorization", "Bearer wrong-key"))).await, StatusCode::UNAUTHORIZED, "{}", path);
+        assert_eq!(send_with_key(&state, path, Some(("x-api-key", "first-key"))).await, StatusCode::OK, "{}", path);
+        assert_eq!(send_with_key(&state, path, Some(("authorization", "Bearer second-key"))).await, StatusCode::OK, "{}", path);
+    }
+    let error: ApiError = serde_json::from_slice(&post(&state, "/v1/send-transaction", &serde_json::to_value(transfer()).unwrap()).await.1).unwrap();
+    assert_eq!(error.code, ErrorCode::Unauthorized);
+
+    // The key is checked before the sponsored request is read
+    let sponsored = "/v1/send-sponsored-transaction";
+    assert_eq!(send_with_key(&state, sponsored, None).await, StatusCode::UNAUTHORIZED);
+    assert_ne!(send_with_key(&state, sponsored, Some(("x-api-key", "first-key"))).await, StatusCode::UNAUTHORIZED);
+
+    // Reads stay public
+    assert_eq!(get(&state, "/v1/get-all-accounts").await.0, StatusCode::OK);
+    assert_eq!(get(&state, "/health").await.0, StatusCode::OK);
+
+    // Without keys the submission routes are open
+    let open = api_state(OperationMode::Hybrid, false);
+    assert_eq!(send_with_key(&open, "/v1/send-transaction", None).await, StatusCode::OK);
+}
+
+async fn decode(state: &ApiState, body: Value) -> DecodeTransactionResponse {
+    let (status, body) = post(state, "/v1/decode-transaction", &body).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_slice(&body).unwrap()
+}
+
+fn failed_stage(response: &DecodeTransactionResponse) -> Option<DecodeStage> {
+    match response {
+        DecodeTransactionResponse::Failed { stage, .. } => Some(*stage),
+        DecodeTransactionResponse::Decoded { .. } => None,
+    }
+}
+
+#[tokio::test]
+async fn test_decode_transaction_route() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let transaction = transfer();
+    let wire = bincode::serialize(&transaction).unwrap();
+    let expected = TransactionView::from(&convert_to_trollup_transaction(transaction.clone()).unwrap());
+
+    let decoded = decode(&state, json!({ "encoded": general_purpose::STANDARD.encode(&wire) })).await;
+    let DecodeTransactionResponse::Decoded { transaction: view, estimated_compute_units } = decoded else {
+        panic!("Expected the transfer to decode: {:?}", decoded);
+    };
+    assert_eq!(view, expected);
+    assert!(estimated_compute_units > 0);
+    let borsh = borsh::to_vec(&convert_to_trollup_transaction(transaction.clone()).unwrap()).unwrap();
+    let decoded = decode(&state, json!({ "encoded": bs58::encode(&borsh).into_string(), "encoding": "base58", "format": "borsh" })).await;
+    assert_eq!(failed_stage(&decoded), None);
+    let decoded = decode(&state, json!({ "transaction": transaction })).await;
+    assert_eq!(failed_stage(&decoded), None);
+
+    assert_eq!(failed_stage(&decode(&state, json!({ "encoded": "not base64!" })).await), Some(DecodeStage::Decode));
+    assert_eq!(failed_stage(&decode(&state, json!({})).await), Some(DecodeStage::Decode));
+    assert_eq!(failed_stage(&decode(&state, json!({ "encoded": general_purpose::STANDARD.encode([1, 2, 3]) })).await), Some(DecodeStage::Deserialize));
+    assert_eq!(failed_stage(&decode(&state, json!({ "transaction": { "not": "a transaction" } })).await), Some(DecodeStage::Deserialize));
+
+    let mut unsigned = transaction.clone();
+    unsigned.signatures.clear();
+    assert_eq!(failed_stage(&decode(&state, json!({ "transaction": unsigned })).await), Some(DecodeStage::Signatures));
+    let mut out_of_bounds = transaction.clone();
+    out_of_bounds.message.instructions[0].program_id_index = 9;
+    let decoded = decode(&state, json!({ "transaction": out_of_bounds })).await;
+    assert_eq!(failed_stage(&decoded), Some(DecodeStage::Sanitize));
+    let DecodeTransactionResponse::Failed { transaction: partial, .. } = decoded else { unreachable!() };
+    assert!(partial.is_some());
+    let mut forged = transaction;
+    forged.signatures[0] = Signature::new_unique();
+    let decoded = decode(&state, json!({ "transaction": forged })).await;
+    assert_eq!(failed_stage(&decoded), Some(DecodeStage::Verify));
+}
+
+async fn submit(state: &ApiState, transaction: &Transaction, address: &str) -> StatusCode {
+    let response = request()
+        .method("POST")
+        .path("/v1/send-transaction")
+        .remote_addr(address.parse().unwrap())
+        .json(transaction)
+        .reply(&routes(state.clone()))
+        .await;
+    response.status()
+}
+
+async fn submit_bundle(state: &ApiState, transactions: &[Transaction], address: &str) -> StatusCode {
+    let response = request()
+        .method("POST")
+        .path("/v1/send-transaction-bundle")
+        .remote_addr(address.parse().unwrap())
+        .json(&transactions)
+        .reply(&routes(state.clone()))
+        .await;
+    response.status()
+}
+
+#[tokio::test]
+async fn test_repeat_offenders_are_banned() {
+    let mut state = api_state(OperationMode::Hybrid, false);
+    state.reputation = Reputation::new(ReputationSettings { strike_threshold: 2, window_secs: 60, ban_base_secs: 30, ban_max_secs: 120 });
+    let mut unsigned = transfer();
+    unsigned.signatures.clear();
+    assert_eq!(submit(&state, &unsigned, "10.0.0.1:1000").await, StatusCode::BAD_REQUEST);
+    assert_eq!(submit(&state, &unsigned, "10.0.0.1:1001").await, StatusCode::BAD_REQUEST);
+    // Banned by address, and by payer from any other address
+    assert_eq!(submit(&state, &transfer(), "10.0.0.1:1002").await, StatusCode::FORBIDDEN);
+    let mut same_payer = unsigned.clone();
+    same_payer.signatures = vec![Signature::new_unique()];
+    assert_eq!(submit(&state, &same_payer, "10.0.0.2:1000").await, StatusCode::FORBIDDEN);
+    assert_eq!(submit(&state, &transfer(), "10.0.0.2:1001").await, StatusCode::OK);
+
+    let bans: Vec<SourceRecord> = get_ok(&state, "/v1/admin/bans").await;
+    let banned: Vec<&str> = bans.iter().filter(|record| record.banned_until.is_some()).map(|record| record.source.as_str()).collect();
+    assert_eq!(banned.len(), 2);
+    assert!(banned.contains(&"ip:10.0.0.1"));
+    assert_eq!(bans[0].invalid_submissions, 2);
+
+    // Lifting and imposing bans takes the operator token
+    let unban = "/v1/admin/bans/ip:10.0.0.1";
+    assert_eq!(admin(&state, "DELETE", unban, &json!({}), None).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(admin(&state, "DELETE", unban, &json!({}), Some("wrong")).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(submit(&state, &transfer(), "10.0.0.1:1003").await, StatusCode::FORBIDDEN);
+    let (status, body) = admin(&state, "DELETE", unban, &json!({}), Some(ADMIN_TOKEN)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(serde_json::from_slice::<bool>(&body).unwrap());
+    assert_eq!(submit(&state, &transfer(), "10.0.0.1:1004").await, StatusCode::OK);
+
+    let ban = json!({ "source": "ip:10.0.0.3", "durationSecs": 600, "reason": "spam" });
+    assert_eq!(post(&state, "/v1/admin/bans", &ban).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(submit(&state, &transfer(), "10.0.0.3:1000").await, StatusCode::OK);
+    let (status, body) = admin(&state, "POST", "/v1/admin/bans", &ban, Some(ADMIN_TOKEN)).await;
+    assert_eq!(status, StatusCode::OK);
+    let record: SourceRecord = serde_json::from_slice(&body).unwrap();
+    assert_eq!(record.bans, 1);
+    assert_eq!(submit(&state, &transfer(), "10.0.0.3:1001").await, StatusCode::FORBIDDEN);
+    let nobody = json!({ "source": "nobody", "durationSecs": 1 });
+    assert_eq!(admin(&state, "POST", "/v1/admin/bans", &nobody, Some(ADMIN_TOKEN)).await.0, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_access_log_settings_need_the_admin_token() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let quiet = json!({ "enabled": false, "samplePercent": 10 });
+
+    assert_eq!(admin(&state, "POST", "/v1/admin/access-log", &quiet, None).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(admin(&state, "POST", "/v1/admin/access-log", &quiet, Some("wrong")).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(get(&state, "/v1/admin/access-log").await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(admin(&state, "GET", "/v1/admin/access-log", &json!({}), Some("wrong")).await.0, StatusCode::UNAUTHORIZED);
+    let (status, body) = admin(&state, "GET", "/v1/admin/access-log", &json!({}), Some(ADMIN_TOKEN)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(serde_json::from_slice::<AccessLogSettings>(&body).unwrap(), AccessLogSettings::default());
+
+    let (status, body) = admin(&state, "POST", "/v1/admin/access-log", &quiet, Some(ADMIN_TOKEN)).await;
+    assert_eq!(status, StatusCode::OK);
+    let updated = AccessLogSettings { enabled: false, sample_percent: 10 };
+    assert_eq!(serde_json::from_slice::<AccessLogSettings>(&body).unwrap(), updated);
+    let (_, body) = admin(&state, "GET", "/v1/admin/access-log", &json!({}), Some(ADMIN_TOKEN)).await;
+    assert_eq!(serde_json::from_slice::<AccessLogSettings>(&body).unwrap(), updated);
+
+    // Refused from its Content-Length, before the body is read
+    let oversized = request()
+        .method("POST")
+        .path("/v1/admin/access-log")
+        .header("authorization", format!("Bearer {}", ADMIN_TOKEN))
+        .body(vec![b' '; MAX_BODY_SIZE as usize + 1])
+        .reply(&routes(state.clone()))
+        .await;
+    assert_eq!(oversized.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_ipv6_clients_are_attributed_by_network() {
+    let mut state = api_state(OperationMode::Hybrid, false);
+    state.reputation = Reputation::new(ReputationSettings { strike_threshold: 1, window_secs: 60, ban_base_secs: 30, ban_max_secs: 120 });
+    let mut unsigned = transfer();
+    unsigned.signatures.clear();
+    assert_eq!(submit(&state, &unsigned, "[2001:db8:0:1::1]:1000").await, StatusCode::BAD_REQUEST);
+    // Any address of the same /64 is the same client
+    assert_eq!(submit(&state, &transfer(), "[2001:db8:0:1::2]:1000").await, StatusCode::FORBIDDEN);
+    assert_eq!(submit(&state, &transfer(), "[2001:db8:0:2::1]:1000").await, StatusCode::OK);
+
+    // Without a trusted proxy in between, X-Forwarded-For doesn't change who the client is
+    let response = request()
+        .method("POST")
+        .path("/v1/send-transaction")
+        .remote_addr("[2001:db8:0:1::3]:1000".parse().unwrap())
+        .header("x-forwarded-for", "192.0.2.1")
+        .json(&transfer())
+        .reply(&routes(state.clone()))
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let bans: Vec<SourceRecord> = get_ok(&state, "/v1/admin/bans").await;
+    assert!(bans.iter().any(|record| record.source == "ip:2001:db8:0:1::"));
+}
+
+#[tokio::test]
+async fn test_submissions_are_rate_limited_per_client() {
+    let mut state = api_state(OperationMode::Hybrid, false);
+    state.rate_limiter = RateLimiter::new(RateLimitSettings { per_second: 1, burst: 2 });
+    assert_eq!(submit(&state, &transfer(), "10.0.1.1:1000").await, StatusCode::OK);
+    assert_eq!(submit(&state, &transfer(), "10.0.1.1:1001").await, StatusCode::OK);
+    assert_eq!(submit(&state, &transfer(), "10.0.1.1:1002").await, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(submit(&state, &transfer(), "10.0.1.2:1000").await, StatusCode::OK);
+
+    let response = request()
+        .method("POST")
+        .path("/v1/send-transaction-optimistic")
+        .remote_addr("10.0.1.1:1003".parse().unwrap())
+        .json(&transfer())
+        .reply(&routes(state.clone()))
+        .await;
+    let error: ApiError = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(error.code, ErrorCode::TooManyRequests);
+}
+
+#[tokio::test]
+async fn test_every_route_feeding_the_pool_is_rate_limited() {
+    let mut state = api_state(OperationMode::Hybrid, false);
+    state.rate_limiter = RateLimiter::new(RateLimitSettings { per_second: 1, burst: 1 });
+    let paths = ["/v1/send-sponsored-transaction", "/v1/post-challenge", "/v1/claim-challenge/unknown", "/v1/cross-rollup/inbox"];
+    for (client, path) in paths.into_iter().enumerate() {
+        // Rejected requests still take their token
+        assert_ne!(post_from(&state, path, &format!("10.0.4.{}:1000", client + 1)).await, StatusCode::TOO_MANY_REQUESTS, "{}", path);
+        assert_eq!(post_from(&state, path, &format!("10.0.4.{}:1001", client + 1)).await, StatusCode::TOO_MANY_REQUESTS, "{}", path);
+    }
+}
+
+async fn post_from(state: &ApiState, path: &str, address: &str) -> StatusCode {
+    let response = request()
+        .method("POST")
+        .path(path)
+        .remote_addr(address.parse().unwrap())
+        .json(&json!({}))
+        .reply(&routes(state.clone()))
+        .await;
+    response.status()
+}
+
+#[tokio::test]
+async fn test_bundles_are_charged_per_transaction() {
+    let mut state = api_state(OperationMode::Hybrid, false);
+    state.rate_limiter = RateLimiter::new(RateLimitSettings { per_second: 1, burst: 3 });
+    assert_eq!(submit_bundle(&state, &[transfer(), transfer()], "10.0.2.1:1000").await, StatusCode::OK);
+    // One token is left, the bundle needs two
+    assert_eq!(submit_bundle(&state, &[transfer(), transfer()], "10.0.2.1:1001").await, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(submit(&state, &transfer(), "10.0.2.2:1000").await, StatusCode::OK);
+}
+
+#[test]
+fn test_rate_limited_clients_are_tracked_up_to_a_cap() {
+    let limiter = RateLimiter::new(RateLimitSettings { per_second: 1, burst: 1 });
+    let drained: IpAddr = "10.0.3.1".parse().unwrap();
+    assert!(limiter.acquire(drained).is_ok());
+    assert!(limiter.acquire(drained).is_err());
+    // Every other client is seen after it, its bucket is the least recently used one to go
+    for client in 0..100_000u32 {
+        assert!(limiter.acquire(IpAddr::from((0x0b00_0000 + client).to_be_bytes())).is_ok());
+    }
+    assert!(limiter.acquire(drained).is_ok());
+}
+
+#[tokio::test]
+async fn test_block_routes() {
+    let state = api_state(OperationMode::Hybrid, false);
+    assert_eq!(get_error(&state, "/v1/get-latest-block").await, ApiError::not_found("No blocks exist"));
+
+    let mut block = block_writing(1, &[account(1, vec![])], &[]);
+    block.l1_anchor = L1Anchor { slot: Some(5), signature: None };
+    store_block(&state, &block);
+    let heartbeat = block_writing(2, &[], &[]);
+    store_block(&state, &heartbeat);
+
+    let view: BlockView = get_ok(&state, "/v1/get-block/1").await;
+    assert_eq!(view, BlockView::from(&block));
+    let latest: BlockView = get_ok(&state, "/v1/get-latest-block").await;
+    assert_eq!(latest, BlockView::from(&heartbeat));
+    let all: Vec<([u8; 32], Block)> = get_ok(&state, "/v1/get-all-blocks").await;
+    assert_eq!(all.len(), 2);
+
+    let verified: VerifiedBlockResponse = get_ok(&state, "/v1/get-block/2?verify=true").await;
+    assert_eq!(verified.verification, BlockVerification::Verified);
+    let anchor: L1AnchorResponse = get_ok(&state, "/v1/get-l1-anchor/1").await;
+    assert_eq!(anchor.block_number, 1);
+    assert_eq!(anchor.l1_anchor.slot, Some(5));
+
+    assert_eq!(get_error(&state, "/v1/get-block/99").await, ApiError::not_found("No block found for: 99"));
+    assert_eq!(get_error(&state, "/v1/get-l1-anchor/99").await, ApiError::not_found("No block found for: 99"));
+    assert_eq!(get(&state, "/v1/get-block/not-a-number").await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_node_info_route() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let empty: NodeInfo = get_ok(&state, "/v1/node-info").await;
+    assert_eq!((empty.genesis_hash, empty.latest_block), (None, 0));
+    assert_eq!(empty.verify().unwrap(), keypair("sequencer").pubkey());
+
+    let genesis = block_writing(1, &[account(1, vec![])], &[]);
+    store_block(&state, &genesis);
+    store_block(&state, &block_writing(2, &[], &[]));
+    let info: NodeInfo = get_ok(&state, "/v1/node-info").await;
+    assert_eq!(info.genesis_hash, Some(hex::encode(genesis.hash())));
+    assert_eq!(info.latest_block, 2);
+    assert_eq!(info.api_versions, vec!["v1".to_string()]);
+    // A follower on the same chain accepts it, one on another chain refuses
+    let follower = NodeInfo { latest_block: 1, ..info.clone() };
+    assert_eq!(handshake(&follower, &info), Ok(1));
+    let other_chain = NodeInfo { genesis_hash: Some(hex::encode([7u8; 32])), ..follower };
+    assert!(handshake(&other_chain, &info).is_err());
+}
+
+#[tokio::test]
+async fn test_account_proof_routes() {
+    let _indexes = INDEXES.lock().await;
+    let state = api_state(OperationMode::Hybrid, false);
+    let plain = account(10, vec![5; 16]);
+    let mut large = account(20, (0..200).map(|i| i as u8).collect());
+    large.commit_data(100);
+    state.account_state_manager.set_state_record(&plain);
+    state.account_state_manager.set_state_record(&large);
+    let block = block_writing(1, &[plain.clone(), large.clone()], &[]);
+    store_block(&state, &block);
+    merkle_index::record_block(&block);
+    merkle_index::commit();
+
+    let proof: AccountProofResponse = get_ok(&state, &format!("/v1/get-account-proof/{}", plain.address)).await;
+    assert_eq!(proof.block_number, 1);
+    assert_eq!(proof.root, hex::encode(*block.accounts_merkle_root));
+    assert_eq!(proof.leaf, hex::encode(account_leaf(&plain)));
+    assert_eq!(proof.leaf_count, 2);
+
+    let data_proof: AccountDataProofResponse = get_ok(&state, &format!("/v1/get-account-data-proof/{}?offset=70&length=8", large.address)).await;
+    assert!(data_proof.account.data.is_empty());
+    assert_eq!(account_leaf(&data_proof.account), account_leaf(&large));
+    let range = DataRangeProof {
+        data_len: data_proof.data_len,
+        first_chunk: data_proof.first_chunk,
+        chunks: data_proof.chunks.iter().map(|chunk| hex::decode(chunk).unwrap()).collect(),
+        proof: data_proof.chunk_proof.iter().map(|hash| hex::decode(hash).unwrap().try_into().unwrap()).collect(),
+    };
+    assert!(range.verify(&large.data_root.unwrap()));
+    assert_eq!(range.bytes(70, 8).unwrap(), large.data[70..78]);
+
+    let error = get_error(&state, &format!("/v1/get-account-data-proof/{}?offset=0&length=4", plain.address)).await;
+    assert_eq!(error.code, ErrorCode::Unprocessable);
+    assert!(error.message.contains("has no data commitment"), "{}", error);
+    let error = get_error(&state, &format!("/v1/get-account-data-proof/{}?offset=190&length=20", large.address)).await;
+    assert_eq!(error.code, ErrorCode::InvalidRequest);
+    assert!(error.message.contains("is outside the 200 bytes"), "{}", error);
+    let unknown = Pubkey::new_unique().to_string();
+    assert_eq!(get_error(&state, &format!("/v1/get-account-proof/{}", unknown)).await, ApiError::not_found(format!("No block wrote account: {:?}", unknown)));
+    assert_eq!(get_error(&state, &format!("/v1/get-account-proof/{}?block=7", plain.address)).await, ApiError::not_found("Block 7 is not in the Merkle index"));
+}
+
+#[tokio::test]
+async fn test_block_public_inputs_are_recomputed_from_the_accounts() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let parent = block_writing(1, &[account(1, vec![])], &[]);
+    let written = vec![account(5, vec![1, 2]), account(7, vec![])];
+    let transactions = vec![convert_to_trollup_transaction(transfer()).unwrap()];
+    let block = block_writing(2, &written, &transactions);
+    store_block(&state, &parent);
+    store_block(&state, &block);
+    state.account_state_manager.set_state_records(&written);
+
+    let response: BlockPublicInputsResponse = get_ok(&state, "/v1/get-block-public-inputs/2").await;
+    let expected = compute_public_inputs(&written, &parent.accounts_merkle_root, &block.transactions_merkle_root, &block.accounts_merkle_root);
+    assert_eq!(response.parent_root, hex::encode(*parent.accounts_merkle_root));
+    assert_eq!(response.public_inputs, Some(expected.to_bytes().iter().map(hex::encode).collect()));
+    assert_eq!(response.lamports_sum, Some(12));
+    assert!(response.changed_accounts.is_empty());
+    assert_eq!(response.matches, response.prepared.as_ref().map(|prepared| *prepared == response.stored));
+    // Verification checks the proof against the recomputed inputs, never the stored ones
+    if state.block_verifier.prepared_verifying_key().is_some() {
+        let mut tampered = block.clone();
+        tampered.accounts_zk_public_inputs = vec![7; 64];
+        store_block(&state, &tampered);
+        let verified: VerifiedBlockResponse = get_ok(&state, "/v1/get-block/2?verify=true").await;
+        assert_eq!(verified.verification, BlockVerification::Invalid("Stored public inputs don't match the block's roots and accounts".to_string()));
+        store_block(&state, &block);
+    }
+    let verified: VerifiedBlockResponse = get_ok(&state, "/v1/get-block/2?verify=true").await;
+    assert_ne!(verified.verification, BlockVerification::Verified);
+
+    // The block's state of a changed account is gone, the inputs can't be recomputed anymore
+    state.account_state_manager.set_state_record(&AccountState { lamports: 6, ..written[0].clone() });
+    let response: BlockPublicInputsResponse = get_ok(&state, "/v1/get-block-public-inputs/2").await;
+    assert!(response.public_inputs.is_none());
+    assert_eq!(response.changed_accounts, vec![written[0].address.to_string()]);
+    let verified: VerifiedBlockResponse = get_ok(&state, "/v1/get-block/2?verify=true").await;
+    assert_eq!(
+        verified.verification,
+        BlockVerification::Unverifiable(format!("Public inputs can't be recomputed, accounts changed since: {}", written[0].address)),
+    );
+
+    assert_eq!(get_error(&state, "/v1/get-block-public-inputs/1").await, ApiError::not_found("Block 1 is a heartbeat block and has no proof"));
+    assert_eq!(get_error(&state, "/v1/get-block-public-inputs/3").await, ApiError::not_found("No block found for: 3"));
+}
+
+#[tokio::test]
+async fn test_optimistic_routes() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let pending: Vec<StateCommitmentPackageUI<AccountState>> = get_ok(&state, "/v1/get-all-pending-commitments").await;
+    assert!(pending.is_empty());
+
+    let committed = account(1, vec![]);
+    state.account_state_manager.set_state_record(&committed);
+    let mut changed = committed.clone();
+    changed.lamports = 2;
+    let created = account(3, vec![]);
+    let mut package = StateCommitmentPackage::new(true, vec![changed, created], vec![], vec![]);
+    package.state_root = Some([7u8; 32]);
+    state.optimistic_commitment_state_management.set_state_record(&package);
+    let state_root = general_purpose::URL_SAFE.encode([7u8; 32]);
+
+    let pending: Vec<StateCommitmentPackageUI<AccountState>> = get_ok(&state, "/v1/get-all-pending-commitments").await;
+    assert_eq!(pending.len(), 1);
+    let fetched: StateCommitmentPackageUI<AccountState> = get_ok(&state, &format!("/v1/get-pending-commitments/{}", state_root)).await;
+    assert_eq!(fetched.state_root, Some([7u8; 32]));
+    assert!(fetched.optimistic);
+    assert_eq!(fetched.state_records.len(), 2);
+
+    let diff: Value = get_ok(&state, &format!("/v1/get-pending-commitment-diff/{}", state_root)).await;
+    assert_eq!(diff["accounts"][0]["changed"], json!(["lamports"]));
+    assert_eq!(diff["accounts"][1]["changed"], json!(["created"]));
+
+    assert_eq!(get_error(&state, "/v1/get-pending-commitments/invalid").await, ApiError::invalid_request("Invalid state root: \"invalid\""));
+    let unknown = general_purpose::URL_SAFE.encode([8u8; 32]);
+    let error = get_error(&state, &format!("/v1/get-pending-commitment-diff/{}", unknown)).await;
+    assert_eq!(error, ApiError::not_found(format!("No pending batches found for: {:?}", unknown)));
+    let challenges: Value = get_ok(&state, "/v1/get-challenges").await;
+    assert!(challenges.is_array());
+}
+
+#[tokio::test]
+async fn test_stuck_optimistic_commitment_routes() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let mut package = StateCommitmentPackage::new(true, vec![account(1, vec![])], vec![], vec![]);
+    package.state_root = Some([0x5a; 32]);
+    optimistic_commitments::pending(&package);
+    let state_root = hex::encode([0x5a; 32]);
+
+    let stuck: Vec<CommitmentRecord> = get_ok(&state, "/v1/admin/optimistic-commitments?olderThanSecs=0").await;
+    assert!(stuck.iter().any(|record| record.state_root == state_root));
+    let stuck: Vec<CommitmentRecord> = get_ok(&state, "/v1/admin/optimistic-commitments?olderThanSecs=3600").await;
+    assert!(!stuck.iter().any(|record| record.state_root == state_root));
+
+    let path = format!("/v1/admin/optimistic-commitments/{}/resolve", state_root);
+    let roll_back = json!({ "resolution": "rollBack" });
+    assert_eq!(post(&state, &path, &roll_back).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(admin(&state, "POST", &path, &roll_back, Some("wrong")).await.0, StatusCode::UNAUTHORIZED);
+    let unconfigured = ApiState { admin_token: AdminToken::default(), ..state.clone() };
+    assert_eq!(admin(&unconfigured, "POST", &path, &roll_back, Some(ADMIN_TOKEN)).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(optimistic_commitments::status(&[0x5a; 32]).unwrap().requested_resolution, None);
+
+    let (status, body) = admin(&state, "POST", &path, &roll_back, Some(ADMIN_TOKEN)).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let requested: CommitmentRecord = serde_json::from_slice(&body).unwrap();
+    assert_eq!(requested.requested_resolution, Some(Resolution::RollBack));
+    assert!(optimistic_commitments::take_resolutions().contains(&([0x5a; 32], Resolution::RollBack)));
+
+    optimistic_commitments::finish(&[0x5a; 32], CommitmentState::RolledBack, Some("an operator rolled it back".to_string()));
+    let settled: CommitmentRecord = get_ok(&state, &format!("/v1/admin/optimistic-commitments/{}", state_root)).await;
+    assert_eq!(settled.state, CommitmentState::RolledBack);
+    let prove = json!({ "resolution": "prove" });
+    assert_eq!(admin(&state, "POST", &path, &prove, Some(ADMIN_TOKEN)).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(admin(&state, "POST", "/v1/admin/optimistic-commitments/zz/resolve", &prove, Some(ADMIN_TOKEN)).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(get(&state, &format!("/v1/admin/optimistic-commitments/{}", hex::encode([0x5b; 32]))).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_admin_routes() {
+    let _indexes = INDEXES.lock().await;
+    let state = api_state(OperationMode::Hybrid, false);
+    let jobs: Vec<JobStatus> = get_ok(&state, "/v1/admin/scheduler").await;
+    assert!(jobs.is_empty());
+    let pipeline: PipelineSnapshot = get_ok(&state, "/v1/admin/pipeline").await;
+    assert_eq!(pipeline.transaction_pool.count, 0);
+    let storage: Value = get_ok(&state, "/v1/admin/storage").await;
+    assert!(storage.get("bytesSaved").is_some());
+    let sponsors: Vec<Value> = get_ok(&state, "/v1/admin/sponsors").await;
+    assert!(sponsors.is_empty());
+
+    store_block(&state, &block_writing(1, &[account(1, vec![])], &[]));
+    assert_eq!(post(&state, "/v1/admin/reindex", &json!({})).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(admin(&state, "POST", "/v1/admin/reindex", &json!({}), Some("wrong")).await.0, StatusCode::UNAUTHORIZED);
+    let (status, body) = admin(&state, "POST", "/v1/admin/reindex", &json!({}), Some(ADMIN_TOKEN)).await;
+    assert_eq!(status, StatusCode::OK);
+    let started: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(started["progress"]["blocksTotal"], 1);
+    // Let the rebuild finish before releasing the indexes
+    let mut progress: ReindexProgress = get_ok(&state, "/v1/admin/reindex").await;
+    while progress.running {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        progress = get_ok(&state, "/v1/admin/reindex").await;
+    }
+    assert_eq!(progress.error, None);
+    assert_eq!(progress.blocks_done, 1);
+    assert!(merkle_index::get(1).is_some());
+}
+
+/// A scheduled job that only counts as run, or fails every time.
+struct TestJob {
+    name: &'static str,
+    fails: bool,
+}
+
+#[async_trait]
+impl ScheduledJob for TestJob {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        if self.fails {
+            anyhow::bail!("{} failed", self.name);
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_scheduler_reports_job_runs() {
+    let mut scheduler = Scheduler::new(HashMap::from([("disabled".to_string(), 0), ("failing".to_string(), 1)]));
+    scheduler.register(Arc::new(TestJob { name: "succeeding", fails: false }), Duration::from_secs(1));
+    scheduler.register(Arc::new(TestJob { name: "failing", fails: true }), Duration::from_secs(60));
+    scheduler.register(Arc::new(TestJob { name: "disabled", fails: false }), Duration::from_secs(1));
+    let mut state = api_state(OperationMode::Hybrid, false);
+    state.scheduler_status = scheduler.start();
+
+    // Jobs skip the tick at startup, wait for the first run of both
+    let mut jobs: Vec<JobStatus> = get_ok(&state, "/v1/admin/scheduler").await;
+    for _ in 0..50 {
+        if jobs.iter().filter(|job| job.enabled).all(|job| job.run_count > 0 && !job.running) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        jobs = get_ok(&state, "/v1/admin/scheduler").await;
+    }
+
+    let names: Vec<&str> = jobs.iter().map(|job| job.name.as_str()).collect();
+    assert_eq!(names, vec!["disabled", "failing", "succeeding"]);
+    let (disabled, failing, succeeding) = (&jobs[0], &jobs[1], &jobs[2]);
+    assert!(!disabled.enabled);
+    assert_eq!((disabled.interval_secs, disabled.run_count), (0, 0));
+    // The configured interval overrides the default
+    assert_eq!(failing.interval_secs, 1);
+    assert!(failing.run_count > 0);
+    assert_eq!(failing.failure_count, failing.run_count);
+    assert_eq!(failing.last_error.as_deref(), Some("failing failed"));
+    assert!(succeeding.run_count > 0);
+    assert_eq!(succeeding.failure_count, 0);
+    assert_eq!(succeeding.last_error, None);
+    assert!(succeeding.last_finished_at.is_some());
+}
+
+/// Sends one JSON-RPC request and reads its response.
+async fn exchange(client: &mut WsClient, request: String) -> Value {
+    client.send_text(request).await;
+    let message = client.recv().await.expect("WebSocket response");
+    serde_json::from_str(message.to_str().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn test_websocket_subscriptions() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let mut client = warp::test::ws().path("/ws").handshake(routes(state)).await.expect("WebSocket handshake");
+
+    let pubkey = Pubkey::new_unique().to_string();
+    let subscribed = exchange(&mut client, json!({ "jsonrpc": "2.0", "id": 1, "method": "accountSubscribe", "params": [pubkey] }).to_string()).await;
+    assert_eq!(subscribed["result"], 0);
+    assert_eq!(subscribed["id"], 1);
+    let unsubscribed = exchange(&mut client, json!({ "jsonrpc": "2.0", "id": 2, "method": "accountUnsubscribe", "params": [0] }).to_string()).await;
+    assert_eq!(unsubscribed["result"], true);
+    let unsubscribed = exchange(&mut client, json!({ "jsonrpc": "2.0", "id": 3, "method": "accountUnsubscribe", "params": [0] }).to_string()).await;
+    assert_eq!(unsubscribed["result"], false);
+
+    let bad_pubkey = exchange(&mut client, json!({ "jsonrpc": "2.0", "id": 4, "method": "accountSubscribe", "params": ["nope"] }).to_string()).await;
+    assert_eq!(bad_pubkey["error"]["code"], -32602);
+    let unknown_method = exchange(&mut client, json!({ "jsonrpc": "2.0", "id": 5, "method": "slotSubscribe" }).to_string()).await;
+    assert_eq!(unknown_method["error"]["code"], -32601);
+    let not_json = exchange(&mut client, "not json".to_string()).await;
+    assert_eq!(not_json["error"]["code"], -32600);
+    let sequence = exchange(&mut client, json!({ "jsonrpc": "2.0", "id": 6, "method": "sequenceSubscribe" }).to_string()).await;
+    assert_eq!(sequence["error"]["message"], "The sequencing feed is disabled");
+}
+
+#[tokio::test]
+async fn test_block_subscription_backfill() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let transaction = convert_to_trollup_transaction(transfer()).unwrap();
+    let account = account(9, vec![1, 2, 3]);
+    state.transaction_state_manager.set_state_record(&transaction);
+    state.account_state_manager.set_state_record(&account);
+    store_block(&state, &block_writing(1, std::slice::from_ref(&account), std::slice::from_ref(&transaction)));
+    store_block(&state, &block_writing(2, &[], &[]));
+    let mut client = warp::test::ws().path("/ws").handshake(routes(state)).await.expect("WebSocket handshake");
+
+    let subscribed = exchange(&mut client, json!({ "jsonrpc": "2.0", "id": 1, "method": "blockSubscribe", "params": [{ "from": 1 }] }).to_string()).await;
+    assert_eq!(subscribed["result"], 0);
+    let mut backfill = Vec::new();
+    for _ in 0..2 {
+        let message: Value = serde_json::from_str(client.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(message["method"], "blockNotification");
+        backfill.push(serde_json::from_value::<FinalizedBlockView>(message["params"]["result"].clone()).unwrap());
+    }
+    assert_eq!(backfill.iter().map(|block| block.block.block_number).collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(backfill[0].transactions, vec![TransactionView::from(&transaction)]);
+    assert_eq!(backfill[0].accounts, vec![AccountView::from(&account)]);
+    assert!(backfill[1].transactions.is_empty());
+
+    let unsubscribed = exchange(&mut client, json!({ "jsonrpc": "2.0", "id": 2, "method": "blockUnsubscribe", "params": [0] }).to_string()).await;
+    assert_eq!(unsubscribed["result"], true);
+
+    // Filtered subscriptions only get the blocks that match, block 2 mentions neither address
+    let program = TransactionView::from(&transaction).instructions[0].program_id.clone();
+    for (id, address) in [(3, program), (4, account.address.to_string())] {
+        let subscribed = exchange(&mut client, json!({ "jsonrpc": "2.0", "id": id, "method": "blockSubscribe", "params": [{ "from": 1, "mentionsAccountOrProgram": address }] }).to_string()).await;
+        assert_eq!(subscribed["id"], id);
+        let message: Value = serde_json::from_str(client.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(message["params"]["result"]["block"]["blockNumber"], 1);
+    }
+    // Neither block has outbox messages, so the next message answers the next request
+    let withdrawals = exchange(&mut client, json!({ "jsonrpc": "2.0", "id": 5, "method": "blockSubscribe", "params": [{ "from": 1, "withdrawals": true }] }).to_string()).await;
+    assert_eq!(withdrawals["result"], 3);
+    let invalid = exchange(&mut client, json!({ "jsonrpc": "2.0", "id": 6, "method": "blockSubscribe", "params": [{ "mentionsAccountOrProgram": "nope" }] }).to_string()).await;
+    assert_eq!(invalid["error"]["code"], -32602);
+}
+
+#[tokio::test]
+async fn test_parameter_proposals_take_effect_at_their_block() {
+    let state = api_state(OperationMode::Hybrid, false);
+    let genesis: ParametersView = get_ok(&state, "/v1/get-parameters").await;
+    let parameters = GovernedParameters {
+        lamports_per_signature: genesis.parameters.lamports_per_signature * 2,
+        ..genesis.parameters.clone()
+    };
+    let signers = [keypair("governance/0"), keypair("governance/1")];
+    let proposal = |effective_block: u64, signer: &str| {
+        let id = ParameterProposal::id_for(&parameters, effective_block);
+        json!({
+            "parameters": parameters,
+            "effectiveBlock": effective_block,
+            "signer": keypair(signer).pubkey().to_string(),
+            "signature": keypair(signer).sign_message(&id).to_string(),
+        })
+    };
+
+    // Only governance signers propose, and only past the minimum delay
+    assert_eq!(post(&state, "/v1/admin/parameters/proposals", &proposal(500, "outsider")).await.0, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(post(&state, "/v1/admin/parameters/proposals", &proposal(1, "governance/0")).await.0, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, body) = post(&state, "/v1/admin/parameters/proposals", &proposal(500, "governance/0")).await;
+    assert_eq!(status, StatusCode::OK);
+    let proposed: ProposalView = serde_json::from_slice(&body).unwrap();
+    assert_eq!(proposed.id, hex::encode(ParameterProposal::id_for(&parameters, 500)));
+    assert_eq!(proposed.scheduled_at, None);
+    let pending: ParametersView = get_ok(&state, "/v1/get-parameters?block=500").await;
+    assert_eq!(pending.hash, genesis.hash);
+
+    let approve_path = format!("/v1/admin/parameters/proposals/{}/approve", proposed.id);
+    let approval = |signer: &str| json!({
+        "signer": keypair(signer).pubkey().to_string(),
+        "signature": keypair(signer).sign_message(&ParameterProposal::id_for(&parameters, 500)).to_string(),
+    });
+    let (status, body) = post(&state, &approve_path, &approval("governance/1")).await;
+    assert_eq!(status, StatusCode::OK);
+    let scheduled: ProposalView = serde_json::from_slice(&body).unwrap();
+    assert!(scheduled.scheduled_at.is_some());
+    assert_eq!(scheduled.approvals.len(), 2);
+    assert_eq!(post(&state, &approve_path, &approval("governance/0")).await.0, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // The new set is in force from its effective block on and stays retrievable by its hash
+    let before: ParametersView = get_ok(&state, "/v1/get-parameters?block=499").await;
+    assert_eq!(before.hash, genesis.hash);
+    let from: ParametersView = get_ok(&state, "/v1/get-parameters?block=500").await;
+    assert_eq!(from.hash, hex::encode(parameters.hash()));
+    let by_hash: ParametersView = get_ok(&state, &format!("/v1/get-parameters/{}", from.hash)).await;
+    assert_eq!(by_hash.parameters, parameters);
+    assert_eq!(get_error(&state, &format!("/v1/get-parameters/{}", hex::encode([0u8; 32]))).await.code, ErrorCode::NotFound);
+
+    let proposals: Vec<ProposalView> = get_ok(&state, "/v1/admin/parameters/proposals").await;
+    assert!(proposals.iter().any(|proposal| proposal.id == proposed.id && proposal.scheduled_at.is_some()));
+}
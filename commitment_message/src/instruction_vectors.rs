@@ -0,0 +1,243 @@
+//! Golden encodings of the on-chain program instructions. The example, the validator and the
+//! program tests each build the fixtures with their own instruction types and check the Borsh
+//! bytes against `instruction_vectors.json`, so an encoder can't drift from the program's
+//! decoder unnoticed. The file is written from the program layouts, not generated: a change of
+//! an encoding is a change of the programs, update the `data` of the affected vectors with it.
+
+use crate::{BlockProducer, NodeVersion};
+use serde::{Deserialize, Serialize};
+
+pub const INSTRUCTION_VECTORS_JSON: &str = include_str!("../instruction_vectors.json");
+
+/// Variants of the programs' `ProgramInstruction`. `initialize` is the first variant of both
+/// programs, `verifyProof` and `setVerifyingKey` belong to the proof verifier and the others to
+/// the signature verifier.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Instruction {
+    Initialize,
+    VerifySig,
+    VerifySigBatch,
+    VerifyEd25519,
+    VerifyProof,
+    SetVerifyingKey,
+    SetValidator,
+    Migrate,
+}
+
+/// A `ZkProofCommitment`, `ChainedCommitment` or `Ed25519Commitment`, bytes hex encoded.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitmentFixture {
+    /// Not set for ed25519 commitments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verifier_signature: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery_id: Option<u8>,
+    /// 65 bytes for secp256k1 commitments, 32 for ed25519
+    pub public_key: String,
+    /// Only set for chained commitments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_state_root: Option<String>,
+    pub new_state_root: String,
+    pub sequence: u64,
+    pub outbox_root: String,
+    pub sequencer: String,
+    /// `major.minor.patch`
+    pub node_version: String,
+}
+
+impl CommitmentFixture {
+    pub fn verifier_signature(&self) -> [u8; 64] {
+        bytes(self.verifier_signature.as_deref().expect("Secp256k1 fixtures have a verifier signature"))
+    }
+
+    pub fn recovery_id(&self) -> u8 {
+        self.recovery_id.expect("Secp256k1 fixtures have a recovery ID")
+    }
+
+    pub fn public_key<const N: usize>(&self) -> [u8; N] {
+        bytes(&self.public_key)
+    }
+
+    pub fn parent_state_root(&self) -> [u8; 32] {
+        bytes(self.parent_state_root.as_deref().expect("Chained fixtures have a parent state root"))
+    }
+
+    pub fn new_state_root(&self) -> [u8; 32] {
+        bytes(&self.new_state_root)
+    }
+
+    pub fn outbox_root(&self) -> [u8; 32] {
+        bytes(&self.outbox_root)
+    }
+
+    pub fn producer(&self) -> BlockProducer {
+        BlockProducer {
+            sequencer: bytes(&self.sequencer),
+            version: self.node_version.parse::<NodeVersion>().expect("Fixture node versions are major.minor.patch"),
+        }
+    }
+}
+
+/// A `ProofCommitmentPackage`, points and field elements in the big-endian layout of the
+/// alt_bn128 syscalls.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofFixture {
+    pub proof_a: String,
+    pub proof_b: String,
+    pub proof_c: String,
+    /// Account hash and lamports sum
+    pub batch_inputs: Vec<String>,
+    pub state_root: String,
+}
+
+/// The verifying key `setVerifyingKey` stores, in the layout of `ProofFixture`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyingKeyFixture {
+    pub vk_alpha_g1: String,
+    pub vk_beta_g2: String,
+    pub vk_gamma_g2: String,
+    pub vk_delta_g2: String,
+    /// One point per public input plus one
+    pub vk_ic: Vec<String>,
+}
+
+impl ProofFixture {
+    pub fn proof_a(&self) -> [u8; 64] {
+        bytes(&self.proof_a)
+    }
+
+    pub fn proof_b(&self) -> [u8; 128] {
+        bytes(&self.proof_b)
+    }
+
+    pub fn proof_c(&self) -> [u8; 64] {
+        bytes(&self.proof_c)
+    }
+
+    pub fn batch_inputs<const N: usize>(&self) -> [[u8; 32]; N] {
+        array(&self.batch_inputs)
+    }
+
+    pub fn state_root(&self) -> [u8; 32] {
+        bytes(&self.state_root)
+    }
+}
+
+impl VerifyingKeyFixture {
+    pub fn vk_alpha_g1(&self) -> [u8; 64] {
+        bytes(&self.vk_alpha_g1)
+    }
+
+    pub fn vk_beta_g2(&self) -> [u8; 128] {
+        bytes(&self.vk_beta_g2)
+    }
+
+    pub fn vk_gamma_g2(&self) -> [u8; 128] {
+        bytes(&self.vk_gamma_g2)
+    }
+
+    pub fn vk_delta_g2(&self) -> [u8; 128] {
+        bytes(&self.vk_delta_g2)
+    }
+
+    pub fn vk_ic<const N: usize>(&self) -> [[u8; 64]; N] {
+        array(&self.vk_ic)
+    }
+}
+
+/// The validator keys `setValidator` pins, hex encoded.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidatorFixture {
+    /// 65 bytes, uncompressed
+    pub secp256k1: String,
+    pub ed25519: String,
+}
+
+impl ValidatorFixture {
+    pub fn secp256k1(&self) -> [u8; 65] {
+        bytes(&self.secp256k1)
+    }
+
+    pub fn ed25519(&self) -> [u8; 32] {
+        bytes(&self.ed25519)
+    }
+}
+
+/// One instruction with its fixture and the bytes it must encode to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InstructionVector {
+    pub name: String,
+    pub instruction: Instruction,
+    /// One for `verifySig` and `verifyEd25519`, in order for `verifySigBatch`, none otherwise
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commitments: Vec<CommitmentFixture>,
+    /// Only set for `verifyProof`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<ProofFixture>,
+    /// Only set for `setVerifyingKey`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verifying_key: Option<VerifyingKeyFixture>,
+    /// Only set for `setValidator`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validator: Option<ValidatorFixture>,
+    /// Borsh encoding of the instruction, the program's instruction data
+    pub data: String,
+}
+
+impl InstructionVector {
+    /// The only commitment of a `verifySig` or `verifyEd25519` vector.
+    pub fn commitment(&self) -> &CommitmentFixture {
+        match &self.commitments[..] {
+            [commitment] => commitment,
+            _ => panic!("{} doesn't have exactly one commitment", self.name),
+        }
+    }
+
+    pub fn proof(&self) -> &ProofFixture {
+        self.proof.as_ref().unwrap_or_else(|| panic!("{} doesn't have a proof", self.name))
+    }
+
+    pub fn verifying_key(&self) -> &VerifyingKeyFixture {
+        self.verifying_key.as_ref().unwrap_or_else(|| panic!("{} doesn't have a verifying key", self.name))
+    }
+
+    pub fn validator(&self) -> &ValidatorFixture {
+        self.validator.as_ref().unwrap_or_else(|| panic!("{} doesn't have validator keys", self.name))
+    }
+
+    pub fn data(&self) -> Vec<u8> {
+        hex::decode(&self.data).expect("Instruction vector data is hex")
+    }
+
+    /// The encoding of the variant's payload, the data without the variant index.
+    pub fn payload(&self) -> Vec<u8> {
+        self.data()[1..].to_vec()
+    }
+}
+
+fn bytes<const N: usize>(hex: &str) -> [u8; N] {
+    hex::decode(hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .unwrap_or_else(|| panic!("Fixture field isn't {} hex encoded bytes", N))
+}
+
+fn array<const N: usize, const M: usize>(hexes: &[String]) -> [[u8; N]; M] {
+    hexes
+        .iter()
+        .map(|hex| bytes::<N>(hex))
+        .collect::<Vec<_>>()
+        .try_into()
+        .unwrap_or_else(|_| panic!("Fixture field doesn't have {} entries", M))
+}
+
+/// The vectors of `instruction_vectors.json`.
+pub fn instruction_vectors() -> Vec<InstructionVector> {
+    serde_json::from_str(INSTRUCTION_VECTORS_JSON).expect("instruction_vectors.json is valid")
+}
@@ -0,0 +1,252 @@
+//! The messages the validator signs to commit a state root on L1. The validator and the signature
+//! verifier program both build them here, so they always hash the same bytes, and
+//! `test_vectors.json` pins the bytes down for every other implementation.
+//! `instruction_vectors.json` does the same for the instructions carrying the commitments.
+
+use solana_program::{hash, keccak};
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(any(test, feature = "test-vectors"))]
+pub mod test_vectors;
+#[cfg(any(test, feature = "test-vectors"))]
+pub mod instruction_vectors;
+
+/// Prefix of the message of a single commitment. The tags keep a signature over one kind of
+/// message from being accepted as the other.
+pub const COMMITMENT_DOMAIN: &[u8] = b"trollup:commitment:v2";
+/// Prefix of the message of a commitment signed as part of a batch
+pub const CHAINED_COMMITMENT_DOMAIN: &[u8] = b"trollup:chained-commitment:v2";
+/// Length of an encoded `BlockProducer`
+pub const BLOCK_PRODUCER_SIZE: usize = 32 + 6;
+
+/// Hash applied to a message before it is signed. Picked per deployment, by
+/// `COMMITMENT_MESSAGE_HASH` on the validator and the `sha256-commitments` feature of the
+/// signature verifier program.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(any(test, feature = "serde"), serde(rename_all = "camelCase"))]
+pub enum MessageHash {
+    /// What Ethereum tooling expects of secp256k1 signatures
+    #[default]
+    Keccak256,
+    Sha256,
+}
+
+impl MessageHash {
+    pub fn hash(&self, message: &[u8]) -> [u8; 32] {
+        match self {
+            MessageHash::Keccak256 => keccak::hash(message).to_bytes(),
+            MessageHash::Sha256 => hash::hash(message).to_bytes(),
+        }
+    }
+}
+
+impl fmt::Display for MessageHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MessageHash::Keccak256 => write!(f, "keccak256"),
+            MessageHash::Sha256 => write!(f, "sha256"),
+        }
+    }
+}
+
+impl FromStr for MessageHash {
+    type Err = String;
+
+    fn from_str(hash: &str) -> Result<Self, Self::Err> {
+        match hash.trim().to_ascii_lowercase().as_str() {
+            "keccak256" | "keccak" | "" => Ok(MessageHash::Keccak256),
+            "sha256" => Ok(MessageHash::Sha256),
+            other => Err(format!("Unknown COMMITMENT_MESSAGE_HASH: {}, expected keccak256 or sha256", other)),
+        }
+    }
+}
+
+/// Semantic version of the node software that produced a block.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize, borsh::BorshDeserialize))]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl NodeVersion {
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        NodeVersion { major, minor, patch }
+    }
+
+    /// Major, minor and patch as little-endian bytes, in that order.
+    pub fn to_le_bytes(&self) -> [u8; 6] {
+        let mut bytes = [0u8; 6];
+        bytes[..2].copy_from_slice(&self.major.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.minor.to_le_bytes());
+        bytes[4..].copy_from_slice(&self.patch.to_le_bytes());
+        bytes
+    }
+}
+
+impl fmt::Display for NodeVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for NodeVersion {
+    type Err = String;
+
+    /// Parses `major.minor.patch`. Pre-release and build metadata aren't signed and are dropped.
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        let core = version.trim().split(['-', '+']).next().unwrap_or_default();
+        let parts = core
+            .split('.')
+            .map(|part| part.parse::<u16>().map_err(|_| format!("Invalid node version: {}, expected major.minor.patch", version)))
+            .collect::<Result<Vec<_>, _>>()?;
+        match parts[..] {
+            [major, minor, patch] => Ok(NodeVersion { major, minor, patch }),
+            _ => Err(format!("Invalid node version: {}, expected major.minor.patch", version)),
+        }
+    }
+}
+
+/// The sequencer that produced a block and the version of its node software. Signed into every
+/// commitment, so a root on L1 can be traced back to the node and release that produced it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize, borsh::BorshDeserialize))]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockProducer {
+    /// Public key of the sequencer's keypair
+    pub sequencer: [u8; 32],
+    pub version: NodeVersion,
+}
+
+impl BlockProducer {
+    /// The sequencer key followed by the version.
+    pub fn to_bytes(&self) -> [u8; BLOCK_PRODUCER_SIZE] {
+        let mut bytes = [0u8; BLOCK_PRODUCER_SIZE];
+        bytes[..32].copy_from_slice(&self.sequencer);
+        bytes[32..].copy_from_slice(&self.version.to_le_bytes());
+        bytes
+    }
+}
+
+/// The domain tag, the root the commitment stores, its sequence as little-endian bytes so a
+/// signature can't be replayed at another position, and the outbox root so other rollups can
+/// trust the block's messages, followed by the block's producer.
+pub fn commitment_message(new_state_root: &[u8; 32], sequence: u64, outbox_root: &[u8; 32], producer: &BlockProducer) -> Vec<u8> {
+    [COMMITMENT_DOMAIN, &new_state_root[..], &sequence.to_le_bytes()[..], &outbox_root[..], &producer.to_bytes()[..]].concat()
+}
+
+/// The domain tag and the root the commitment extends, followed by the fields of
+/// `commitment_message`.
+pub fn chained_commitment_message(parent_state_root: &[u8; 32], new_state_root: &[u8; 32], sequence: u64, outbox_root: &[u8; 32], producer: &BlockProducer) -> Vec<u8> {
+    [CHAINED_COMMITMENT_DOMAIN, &parent_state_root[..], &new_state_root[..], &sequence.to_le_bytes()[..], &outbox_root[..], &producer.to_bytes()[..]].concat()
+}
+
+/// The digest signed for a single commitment.
+pub fn commitment_message_hash(message_hash: MessageHash, new_state_root: &[u8; 32], sequence: u64, outbox_root: &[u8; 32], producer: &BlockProducer) -> [u8; 32] {
+    message_hash.hash(&commitment_message(new_state_root, sequence, outbox_root, producer))
+}
+
+/// The digest signed for a commitment of a batch.
+pub fn chained_commitment_message_hash(message_hash: MessageHash, parent_state_root: &[u8; 32], new_state_root: &[u8; 32], sequence: u64, outbox_root: &[u8; 32], producer: &BlockProducer) -> [u8; 32] {
+    message_hash.hash(&chained_commitment_message(parent_state_root, new_state_root, sequence, outbox_root, producer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vectors::{generate, test_vectors};
+
+    #[test]
+    fn test_vectors_match_the_implementation() {
+        // Rewrites the file after an intended change of the messages
+        if std::env::var("REGENERATE_TEST_VECTORS").is_ok() {
+            let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_vectors.json");
+            std::fs::write(path, serde_json::to_string_pretty(&generate()).unwrap() + "\n").unwrap();
+            return;
+        }
+        assert_eq!(test_vectors(), generate());
+    }
+
+    #[test]
+    fn instruction_vectors_are_well_formed() {
+        use crate::instruction_vectors::{instruction_vectors, Instruction};
+
+        let vectors = instruction_vectors();
+        assert!(!vectors.is_empty());
+        for vector in vectors {
+            let (variant, commitments) = match vector.instruction {
+                Instruction::Initialize => (0, Some(0)),
+                Instruction::VerifySig => (1, Some(1)),
+                Instruction::VerifySigBatch => (2, None),
+                Instruction::VerifyEd25519 => (3, Some(1)),
+                Instruction::VerifyProof => (1, Some(0)),
+                Instruction::SetVerifyingKey => (2, Some(0)),
+                Instruction::SetValidator => (4, Some(0)),
+                Instruction::Migrate => (5, Some(0)),
+            };
+            assert_eq!(vector.data()[0], variant, "{}", vector.name);
+            if let Some(count) = commitments {
+                assert_eq!(vector.commitments.len(), count, "{}", vector.name);
+            }
+            assert_eq!(vector.proof.is_some(), vector.instruction == Instruction::VerifyProof, "{}", vector.name);
+            assert_eq!(vector.verifying_key.is_some(), vector.instruction == Instruction::SetVerifyingKey, "{}", vector.name);
+            assert_eq!(vector.validator.is_some(), vector.instruction == Instruction::SetValidator, "{}", vector.name);
+        }
+    }
+
+    const PRODUCER: BlockProducer = BlockProducer { sequencer: [4u8; 32], version: NodeVersion::new(1, 2, 3) };
+
+    #[test]
+    fn messages_are_tagged_by_kind() {
+        let single = commitment_message(&[2u8; 32], 7, &[3u8; 32], &PRODUCER);
+        let chained = chained_commitment_message(&[1u8; 32], &[2u8; 32], 7, &[3u8; 32], &PRODUCER);
+
+        assert!(single.starts_with(COMMITMENT_DOMAIN));
+        assert!(chained.starts_with(CHAINED_COMMITMENT_DOMAIN));
+        assert_eq!(single.len(), COMMITMENT_DOMAIN.len() + 72 + BLOCK_PRODUCER_SIZE);
+        assert_eq!(chained.len(), CHAINED_COMMITMENT_DOMAIN.len() + 104 + BLOCK_PRODUCER_SIZE);
+        assert_eq!(&chained[CHAINED_COMMITMENT_DOMAIN.len() + 32..], &single[COMMITMENT_DOMAIN.len()..]);
+    }
+
+    #[test]
+    fn hashes_differ_for_the_same_message() {
+        let keccak = commitment_message_hash(MessageHash::Keccak256, &[2u8; 32], 7, &[3u8; 32], &PRODUCER);
+        let sha256 = commitment_message_hash(MessageHash::Sha256, &[2u8; 32], 7, &[3u8; 32], &PRODUCER);
+
+        assert_ne!(keccak, sha256);
+        assert_eq!(sha256, hash::hashv(&[COMMITMENT_DOMAIN, &[2u8; 32], &7u64.to_le_bytes(), &[3u8; 32], &[4u8; 32], &[1, 0, 2, 0, 3, 0]]).to_bytes());
+    }
+
+    #[test]
+    fn producer_is_signed() {
+        let other_sequencer = BlockProducer { sequencer: [5u8; 32], ..PRODUCER };
+        let other_version = BlockProducer { version: NodeVersion::new(1, 2, 4), ..PRODUCER };
+        let digest = commitment_message_hash(MessageHash::Keccak256, &[2u8; 32], 7, &[3u8; 32], &PRODUCER);
+
+        assert_ne!(digest, commitment_message_hash(MessageHash::Keccak256, &[2u8; 32], 7, &[3u8; 32], &other_sequencer));
+        assert_ne!(digest, commitment_message_hash(MessageHash::Keccak256, &[2u8; 32], 7, &[3u8; 32], &other_version));
+    }
+
+    #[test]
+    fn node_version_parses_semantic_versions() {
+        assert_eq!("0.1.0".parse(), Ok(NodeVersion::new(0, 1, 0)));
+        assert_eq!(" 1.22.333-rc.1+build.5 ".parse(), Ok(NodeVersion::new(1, 22, 333)));
+        assert!("1.2".parse::<NodeVersion>().is_err());
+        assert!("1.2.3.4".parse::<NodeVersion>().is_err());
+        assert!("1.2.70000".parse::<NodeVersion>().is_err());
+        assert_eq!(NodeVersion::new(4, 5, 6).to_string().parse(), Ok(NodeVersion::new(4, 5, 6)));
+    }
+
+    #[test]
+    fn message_hash_parses_config_values() {
+        assert_eq!("".parse(), Ok(MessageHash::Keccak256));
+        assert_eq!(" Keccak256 ".parse(), Ok(MessageHash::Keccak256));
+        assert_eq!("sha256".parse(), Ok(MessageHash::Sha256));
+        assert!("blake3".parse::<MessageHash>().is_err());
+        assert_eq!(MessageHash::Sha256.to_string().parse(), Ok(MessageHash::Sha256));
+    }
+}
@@ -0,0 +1,111 @@
+//! Known answers for the commitment messages, read by the tests of every crate that builds or
+//! checks them. After an intended change of the messages regenerate the file with
+//! `REGENERATE_TEST_VECTORS=1 cargo test -p trollup-commitment-message`.
+
+use crate::{chained_commitment_message, commitment_message, BlockProducer, MessageHash, NodeVersion};
+use serde::{Deserialize, Serialize};
+
+pub const TEST_VECTORS_JSON: &str = include_str!("../test_vectors.json");
+
+/// One commitment with its message and digest, roots and bytes hex encoded.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TestVector {
+    pub name: String,
+    pub hash: MessageHash,
+    /// Only set for chained commitments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_state_root: Option<String>,
+    pub new_state_root: String,
+    pub sequence: u64,
+    pub outbox_root: String,
+    pub sequencer: String,
+    /// `major.minor.patch`
+    pub node_version: String,
+    /// The full message, domain tag included
+    pub message: String,
+    /// What the validator signs
+    pub digest: String,
+}
+
+impl TestVector {
+    fn build(name: &str, hash: MessageHash, parent_state_root: Option<[u8; 32]>, new_state_root: [u8; 32], sequence: u64, outbox_root: [u8; 32], producer: BlockProducer) -> Self {
+        let message = match &parent_state_root {
+            Some(parent_state_root) => chained_commitment_message(parent_state_root, &new_state_root, sequence, &outbox_root, &producer),
+            None => commitment_message(&new_state_root, sequence, &outbox_root, &producer),
+        };
+        TestVector {
+            name: format!("{}/{}", hash, name),
+            hash,
+            parent_state_root: parent_state_root.map(hex::encode),
+            new_state_root: hex::encode(new_state_root),
+            sequence,
+            outbox_root: hex::encode(outbox_root),
+            sequencer: hex::encode(producer.sequencer),
+            node_version: producer.version.to_string(),
+            digest: hex::encode(hash.hash(&message)),
+            message: hex::encode(message),
+        }
+    }
+
+    pub fn parent_state_root(&self) -> Option<[u8; 32]> {
+        self.parent_state_root.as_deref().map(root)
+    }
+
+    pub fn new_state_root(&self) -> [u8; 32] {
+        root(&self.new_state_root)
+    }
+
+    pub fn outbox_root(&self) -> [u8; 32] {
+        root(&self.outbox_root)
+    }
+
+    pub fn producer(&self) -> BlockProducer {
+        BlockProducer {
+            sequencer: root(&self.sequencer),
+            version: self.node_version.parse().expect("Test vector node versions are major.minor.patch"),
+        }
+    }
+
+    pub fn message(&self) -> Vec<u8> {
+        hex::decode(&self.message).expect("Test vector message is hex")
+    }
+
+    pub fn digest(&self) -> [u8; 32] {
+        root(&self.digest)
+    }
+}
+
+fn root(hex: &str) -> [u8; 32] {
+    hex::decode(hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .expect("Test vector roots are 32 hex encoded bytes")
+}
+
+/// The vectors of `test_vectors.json`.
+pub fn test_vectors() -> Vec<TestVector> {
+    serde_json::from_str(TEST_VECTORS_JSON).expect("test_vectors.json is valid")
+}
+
+/// The vectors of `test_vectors.json`, computed by this crate.
+pub fn generate() -> Vec<TestVector> {
+    let mut counting = [0u8; 32];
+    for (index, byte) in counting.iter_mut().enumerate() {
+        *byte = index as u8;
+    }
+    let producer = BlockProducer { sequencer: [4u8; 32], version: NodeVersion::new(0, 1, 0) };
+    let latest = BlockProducer { sequencer: counting, version: NodeVersion::new(u16::MAX, 0x0102, 3) };
+    [MessageHash::Keccak256, MessageHash::Sha256]
+        .into_iter()
+        .flat_map(|hash| {
+            [
+                TestVector::build("commitment/zeros", hash, None, [0u8; 32], 0, [0u8; 32], BlockProducer::default()),
+                TestVector::build("commitment/no-messages", hash, None, [2u8; 32], 7, [0u8; 32], producer),
+                TestVector::build("commitment/max-sequence", hash, None, counting, u64::MAX, [0xffu8; 32], latest),
+                TestVector::build("chained/first", hash, Some([1u8; 32]), [2u8; 32], 8, [3u8; 32], producer),
+                TestVector::build("chained/extending", hash, Some([2u8; 32]), counting, 0x0102030405060708, [5u8; 32], latest),
+            ]
+        })
+        .collect()
+}
@@ -1,32 +1,47 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use ark_bn254::Bn254;
-use ark_groth16::{Proof, VerifyingKey};
-use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Compress};
+use ark_groth16::Proof;
+use ark_serialize::CanonicalDeserialize;
+use base64::{engine::general_purpose, Engine as _};
 use borsh::to_vec;
 use borsh_derive::{BorshDeserialize, BorshSerialize};
+use futures_util::{stream, Stream, StreamExt};
 use lazy_static::lazy_static;
 use log::info;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_program::alt_bn128::compression::prelude::convert_endianness;
 use solana_program::hash::Hash;
-use solana_program::instruction::{AccountMeta, CompiledInstruction, Instruction};
-use solana_program::message::{Message, MessageHeader};
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::message::Message;
 use solana_program::pubkey::Pubkey;
-use solana_program::system_instruction;
 use solana_sdk::commitment_config::CommitmentConfig;
-use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::signature::{Signature, Signer};
 use solana_sdk::transaction::Transaction;
 use state::account_state::AccountState;
+use state::challenge::ChallengeStatus;
 use state::config::TrollupConfig;
 use state::state_record::{StateCommitmentPackage, StateCommitmentPackageUI};
-use std::ops::Neg;
+use state::test_fixtures;
+use state::transaction_status::{CommitmentLevel, TransactionStatus};
+use state::views::{BlockView, TransactionView};
+use state::witness::AccountInclusion;
+use state_commitment::fee_payer;
+use state_commitment::http_client::shared_client;
+use state_commitment::l1_divergence::{self, L1Root};
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::fs;
-use trollup_zk::verify_lite::{convert_arkworks_vk_to_solana_example, Groth16VerifierPrepared, Groth16VerifyingKeyPrepared, ProofCommitmentPackage};
+use tokio::sync::OnceCell;
+use trollup_zk::fixtures::build_proof_commitment_package;
+use trollup_zk::public_inputs::compute_public_inputs;
+use trollup_zk::verify_lite::ProofCommitmentPackage;
 
 const BASE_URL: &str = "http://localhost:27182";
+/// API versions this client understands, newest first.
+const SUPPORTED_API_VERSIONS: &[&str] = &["v1"];
 
 lazy_static! {
     static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
@@ -38,17 +53,154 @@ pub enum ProgramInstruction {
     VerifyProof(ProofCommitmentPackage),
 }
 
+#[derive(Deserialize)]
+struct ApiVersions {
+    supported: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct VerifiedBlock {
+    block: BlockView,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccountProof {
+    block_number: u64,
+    root: String,
+    leaf_index: u64,
+    leaf_count: u64,
+    proof: Vec<String>,
+}
+
+/// An account state checked against the root the signature verifier committed on L1, without
+/// trusting the API for anything but the data it was checked against.
+#[derive(Debug)]
+struct L1AccountVerification {
+    account: AccountState,
+    l1_root: L1Root,
+    inclusion: AccountInclusion,
+}
+
+/// How `send_and_confirm_with_options` waits for a transaction.
+struct ConfirmOptions {
+    /// Give up once the transaction hasn't reached the level after this long
+    timeout: Duration,
+    poll_interval: Duration,
+}
+
+impl Default for ConfirmOptions {
+    fn default() -> Self {
+        ConfirmOptions {
+            timeout: Duration::from_secs(120),
+            poll_interval: Duration::from_millis(500),
+        }
+    }
+}
+
+/// How `send_optimistic_with_fallback` watches an optimistic submission.
+struct FallbackOptions {
+    /// Resubmit through the proved path once the transaction hasn't reached the level this long
+    /// after it was submitted, leave room for the node's `OPTIMISTIC_TIMEOUT`
+    fallback_after: Duration,
+    /// How the proved resubmission is waited for. Its poll interval is used throughout
+    proved: ConfirmOptions,
+}
+
+impl Default for FallbackOptions {
+    fn default() -> Self {
+        FallbackOptions {
+            // Past the 10 minute OPTIMISTIC_TIMEOUT of the bundled configs
+            fallback_after: Duration::from_secs(15 * 60),
+            proved: ConfirmOptions::default(),
+        }
+    }
+}
+
+/// The path a transaction sent with `send_optimistic_with_fallback` made it through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SubmissionPath {
+    Optimistic,
+    Proved,
+}
+
+#[derive(Debug)]
+struct OptimisticOutcome {
+    path: SubmissionPath,
+    /// Why the transaction was resubmitted through the proved path
+    fallback_reason: Option<String>,
+    status: TransactionStatus,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChallengeSummary {
+    /// URL safe base64
+    state_root: String,
+    status: ChallengeStatus,
+}
+
 struct TrollupClient {
     client: Client,
+    api_base_url: OnceCell<String>,
+}
+
+/// Splits a newline-delimited JSON body into documents as its chunks arrive. The body is only
+/// read as fast as the stream is polled, which is what holds the node's export back.
+fn ndjson_stream<T: DeserializeOwned, B: AsRef<[u8]>>(
+    body: impl Stream<Item=reqwest::Result<B>> + Unpin,
+) -> impl Stream<Item=Result<T>> {
+    stream::unfold((body, Vec::new(), false), |(mut body, mut buffer, mut finished)| async move {
+        loop {
+            if let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let document = serde_json::from_slice(&line[..end]).map_err(Into::into);
+                return Some((document, (body, buffer, finished)));
+            }
+            if finished {
+                // A last document without a trailing newline
+                if buffer.iter().all(u8::is_ascii_whitespace) {
+                    return None;
+                }
+                let document = serde_json::from_slice(&std::mem::take(&mut buffer)).map_err(Into::into);
+                return Some((document, (body, buffer, finished)));
+            }
+            match body.next().await {
+                Some(Ok(chunk)) => buffer.extend_from_slice(chunk.as_ref()),
+                Some(Err(error)) => {
+                    buffer.clear();
+                    return Some((Err(error.into()), (body, buffer, true)));
+                }
+                None => finished = true,
+            }
+        }
+    })
 }
 
 impl TrollupClient {
     fn new() -> Self {
         TrollupClient {
-            client: Client::new(),
+            client: shared_client(),
+            api_base_url: OnceCell::new(),
         }
     }
 
+    /// Negotiates the API version on first use. Nodes that predate versioning don't serve
+    /// `/versions` and are called without a version prefix.
+    async fn url(&self, path: &str) -> String {
+        let base_url = self.api_base_url.get_or_init(|| async {
+            let versions = match self.client.get(format!("{}/versions", BASE_URL)).send().await {
+                Ok(response) if response.status().is_success() => response.json::<ApiVersions>().await.ok(),
+                _ => None,
+            };
+            versions
+                .and_then(|versions| SUPPORTED_API_VERSIONS.iter().find(|version| versions.supported.iter().any(|supported| supported == *version)).copied())
+                .map(|version| format!("{}/{}", BASE_URL, version))
+                .unwrap_or_else(|| BASE_URL.to_string())
+        }).await;
+        format!("{}{}", base_url, path)
+    }
+
     async fn health_check(&self) -> Result<String> {
         let response = self.client
             .get(format!("{}/health", BASE_URL))
@@ -60,7 +212,7 @@ impl TrollupClient {
 
     async fn send_transaction(&self, transaction: &Transaction) -> Result<String> {
         let response = self.client
-            .post(format!("{}/send-transaction", BASE_URL))
+            .post(self.url("/send-transaction").await)
             .json(transaction)
             .send()
             .await?;
@@ -70,7 +222,7 @@ impl TrollupClient {
 
     async fn send_transaction_optimistic(&self, transaction: &Transaction) -> Result<String> {
         let response = self.client
-            .post(format!("{}/send-transaction-optimistic", BASE_URL))
+            .post(self.url("/send-transaction-optimistic").await)
             .json(transaction)
             .send()
             .await?;
@@ -78,45 +230,235 @@ impl TrollupClient {
         Ok(response.text().await?)
     }
 
-    async fn get_transaction(&self, signature: &str) -> Result<String> {
+    async fn get_transaction_status(&self, signature: &Signature) -> Result<TransactionStatus> {
         let response = self.client
-            .get(format!("{}/get-transaction/{}", BASE_URL, signature))
+            .get(self.url(&format!("/get-transaction-status/{}", signature)).await)
             .send()
             .await?;
 
-        Ok(response.text().await?)
+        Ok(response.json::<TransactionStatus>().await?)
+    }
+
+    /// Sends `transaction` and waits until it reaches `level`, the rollup counterpart of
+    /// `send_and_confirm_transaction` in solana-client.
+    async fn send_and_confirm(&self, transaction: &Transaction, level: CommitmentLevel) -> Result<TransactionStatus> {
+        self.send_and_confirm_with_options(transaction, level, ConfirmOptions::default(), |_| {}).await
+    }
+
+    /// Like `send_and_confirm`, calling `on_progress` every time the transaction moves to a new
+    /// level. Fails if the API rejects the transaction or it doesn't reach `level` in time.
+    async fn send_and_confirm_with_options(
+        &self,
+        transaction: &Transaction,
+        level: CommitmentLevel,
+        options: ConfirmOptions,
+        mut on_progress: impl FnMut(&TransactionStatus),
+    ) -> Result<TransactionStatus> {
+        let signature = *transaction.signatures.first().ok_or_else(|| anyhow!("Transaction must be signed"))?;
+        let response = self.send_transaction(transaction).await?;
+        // Rejections come back as a message with a success status
+        if !response.contains("submitted successfully") {
+            bail!("Transaction {} was rejected: {}", signature, response);
+        }
+
+        let started = Instant::now();
+        let mut last_level = None;
+        loop {
+            let status = self.get_transaction_status(&signature).await?;
+            if status.commitment != last_level {
+                last_level = status.commitment;
+                on_progress(&status);
+            }
+            if status.has_reached(level) {
+                return Ok(status);
+            }
+            if started.elapsed() >= options.timeout {
+                bail!("Transaction {} did not reach {:?} within {:?}, last status: {:?}", signature, level, options.timeout, status.commitment);
+            }
+            tokio::time::sleep(options.poll_interval).await;
+        }
+    }
+
+    /// Sends `transaction` optimistically and waits until it reaches `level`. Falls back to
+    /// resubmitting it through the proved path when the node doesn't take it optimistically,
+    /// when the pending commitment holding it is challenged, or when it doesn't reach `level`
+    /// within `options.fallback_after`. The outcome tells which path it made it through.
+    ///
+    /// The node queues the transactions of a rolled back commitment again, a resubmitted copy
+    /// that meets them in the pool is dropped there.
+    async fn send_optimistic_with_fallback(&self, transaction: &Transaction, level: CommitmentLevel, options: FallbackOptions) -> Result<OptimisticOutcome> {
+        let signature = *transaction.signatures.first().ok_or_else(|| anyhow!("Transaction must be signed"))?;
+        let response = self.client
+            .post(self.url("/send-transaction-optimistic").await)
+            .json(transaction)
+            .send()
+            .await?;
+
+        let reason = match response.status() {
+            status if status.is_success() => match self.watch_optimistic(&signature, level, &options).await? {
+                Ok(status) => return Ok(OptimisticOutcome { path: SubmissionPath::Optimistic, fallback_reason: None, status }),
+                Err(reason) => reason,
+            },
+            // The node runs in proved mode, or is shedding load
+            StatusCode::NOT_FOUND | StatusCode::SERVICE_UNAVAILABLE => {
+                format!("the node didn't take it optimistically: {}", response.text().await?)
+            }
+            _ => bail!("Transaction {} was rejected: {}", signature, response.text().await?),
+        };
+        info!("Resubmitting transaction {} through the proved path, {}", signature, reason);
+        let status = self.send_and_confirm_with_options(transaction, level, options.proved, |_| {}).await?;
+        Ok(OptimisticOutcome { path: SubmissionPath::Proved, fallback_reason: Some(reason), status })
+    }
+
+    /// Polls the optimistic transaction `signature` until it reaches `level`, or returns why it
+    /// should be resubmitted through the proved path.
+    async fn watch_optimistic(&self, signature: &Signature, level: CommitmentLevel, options: &FallbackOptions) -> Result<std::result::Result<TransactionStatus, String>> {
+        let started = Instant::now();
+        let mut state_root = None;
+        loop {
+            let status = self.get_transaction_status(signature).await?;
+            if status.has_reached(level) {
+                return Ok(Ok(status));
+            }
+            if state_root.is_none() {
+                state_root = status.optimistic_state_root.as_deref().and_then(|root| hex::decode(root).ok()?.try_into().ok());
+            }
+            if let Some(state_root) = &state_root {
+                if self.is_challenged(state_root).await? {
+                    return Ok(Err(format!("its commitment {} was challenged", hex::encode(state_root))));
+                }
+            }
+            if started.elapsed() >= options.fallback_after {
+                return Ok(Err(format!("it didn't reach {:?} within {:?}, last status: {:?}", level, options.fallback_after, status.commitment)));
+            }
+            tokio::time::sleep(options.proved.poll_interval).await;
+        }
+    }
+
+    /// Whether a challenge against `state_root` is open or was upheld.
+    async fn is_challenged(&self, state_root: &[u8; 32]) -> Result<bool> {
+        let challenges: Vec<ChallengeSummary> = self.client
+            .get(self.url("/get-challenges").await)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let state_root = general_purpose::URL_SAFE.encode(state_root);
+        Ok(challenges.iter().any(|challenge| {
+            challenge.state_root == state_root && matches!(challenge.status, ChallengeStatus::Open | ChallengeStatus::Upheld)
+        }))
+    }
+
+    /// The transaction with `signature`, `None` when the node doesn't have it.
+    async fn get_transaction(&self, signature: &str) -> Result<Option<TransactionView>> {
+        let response = self.client
+            .get(self.url(&format!("/get-transaction/{}", signature)).await)
+            .send()
+            .await?;
+
+        // Transactions that aren't found come back as a message
+        Ok(serde_json::from_str(&response.text().await?).ok())
     }
 
     async fn get_account(&self, account_id: &str) -> Result<String> {
         let response = self.client
-            .get(format!("{}/get-account/{}", BASE_URL, account_id))
+            .get(self.url(&format!("/get-account/{}", account_id)).await)
             .send()
             .await?;
 
         Ok(response.text().await?)
     }
 
-    async fn get_latest_block(&self) -> Result<String> {
+    async fn get_account_state(&self, pubkey: &Pubkey) -> Result<AccountState> {
         let response = self.client
-            .get(format!("{}/get-latest-block/", BASE_URL))
+            .get(self.url(&format!("/get-account/{}", pubkey)).await)
             .send()
             .await?;
 
-        Ok(response.text().await?)
+        response.json::<AccountState>().await.map_err(|_| anyhow!("No account found for {}", pubkey))
     }
 
-    async fn get_block(&self, block_id: u64) -> Result<String> {
+    async fn get_account_proof(&self, pubkey: &Pubkey, block_number: u64) -> Result<AccountInclusion> {
         let response = self.client
-            .get(format!("{}/get-block/{}", BASE_URL, block_id))
+            .get(self.url(&format!("/get-account-proof/{}?block={}", pubkey, block_number)).await)
             .send()
             .await?;
+        let text = response.text().await?;
+        let proof: AccountProof = serde_json::from_str(&text).map_err(|_| anyhow!("No proof for {}: {}", pubkey, text))?;
 
-        Ok(response.text().await?)
+        let decode = |hash: &str| -> Result<[u8; 32]> {
+            hex::decode(hash)?.try_into().map_err(|_| anyhow!("Proof hash {} is not 32 bytes", hash))
+        };
+        Ok(AccountInclusion {
+            block_number: proof.block_number,
+            root: decode(&proof.root)?,
+            leaf_index: proof.leaf_index,
+            leaf_count: proof.leaf_count,
+            proof: proof.proof.iter().map(|hash| decode(hash)).collect::<Result<_>>()?,
+        })
+    }
+
+    /// Checks the API's state of `pubkey` against L1 in one call: reads the root committed to the
+    /// signature verifier's state account through Solana RPC, checks the API's block with that
+    /// number has the same account root, and verifies the account's inclusion proof against the
+    /// L1 root locally.
+    ///
+    /// Only the latest committed root is on L1, and each block's tree holds the accounts its
+    /// batch wrote, so this fails for accounts the L1-committed block didn't write or that were
+    /// written again since.
+    async fn verify_account_against_l1(&self, pubkey: &Pubkey) -> Result<L1AccountVerification> {
+        let program_id = Pubkey::from_str(CONFIG.signature_verifier_program_id_current_env())
+            .map_err(|_| anyhow!("SIGNATURE_VERIFIER_PROGRAM_ID is not set"))?;
+        let rpc_client = RpcClient::new_with_commitment(CONFIG.rpc_url_current_env().to_string(), CommitmentConfig::finalized());
+        let l1_root = l1_divergence::fetch_l1_root(&rpc_client, &program_id)
+            .await?
+            .ok_or_else(|| anyhow!("No root committed on L1 yet"))?;
+
+        let response = self.client
+            .get(self.url(&format!("/get-block/{}?verify=true", l1_root.sequence)).await)
+            .send()
+            .await?;
+        let block = response
+            .json::<VerifiedBlock>()
+            .await
+            .map_err(|_| anyhow!("The API has no block {}, the block L1 committed", l1_root.sequence))?
+            .block;
+        if block.accounts_merkle_root != hex::encode(l1_root.root) {
+            bail!("The API's block {} has account root {} but L1 committed {}", l1_root.sequence, block.accounts_merkle_root, hex::encode(l1_root.root));
+        }
+
+        let account = self.get_account_state(pubkey).await?;
+        let inclusion = self.get_account_proof(pubkey, l1_root.sequence).await?;
+        // Checked against the L1 root, not the one the API sent along with the proof
+        let inclusion = AccountInclusion { root: l1_root.root, ..inclusion };
+        if !inclusion.verify(&account) {
+            bail!("The API's state of {} is not the one committed on L1 in block {}", pubkey, l1_root.sequence);
+        }
+        Ok(L1AccountVerification { account, l1_root, inclusion })
+    }
+
+    async fn get_latest_block(&self) -> Result<Option<BlockView>> {
+        let response = self.client
+            .get(self.url("/get-latest-block/").await)
+            .send()
+            .await?;
+
+        Ok(serde_json::from_str(&response.text().await?).ok())
+    }
+
+    /// Block `block_id`, `None` when the node doesn't have it.
+    async fn get_block(&self, block_id: u64) -> Result<Option<BlockView>> {
+        let response = self.client
+            .get(self.url(&format!("/get-block/{}", block_id)).await)
+            .send()
+            .await?;
+
+        Ok(serde_json::from_str(&response.text().await?).ok())
     }
 
     async fn get_all_transactions(&self) -> Result<String> {
         let response = self.client
-            .get(format!("{}/get-all-transactions/", BASE_URL))
+            .get(self.url("/get-all-transactions/").await)
             .send()
             .await?;
 
@@ -125,16 +467,35 @@ impl TrollupClient {
 
     async fn get_all_accounts(&self) -> Result<String> {
         let response = self.client
-            .get(format!("{}/get-all-accounts/", BASE_URL))
+            .get(self.url("/get-all-accounts/").await)
             .send()
             .await?;
 
         Ok(response.text().await?)
     }
 
+    /// Every account on the node, decoded as the node streams them rather than buffered whole.
+    async fn export_accounts(&self) -> Result<impl Stream<Item=Result<AccountState>>> {
+        self.export("/export/accounts").await
+    }
+
+    async fn export_transactions(&self) -> Result<impl Stream<Item=Result<Transaction>>> {
+        self.export("/export/transactions").await
+    }
+
+    async fn export<T: DeserializeOwned>(&self, path: &str) -> Result<impl Stream<Item=Result<T>>> {
+        let response = self.client
+            .get(self.url(path).await)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(ndjson_stream(Box::pin(response.bytes_stream())))
+    }
+
     async fn get_all_pending_commits(&self) -> Result<String> {
         let response = self.client
-            .get(format!("{}/get-all-pending-commitments/", BASE_URL))
+            .get(self.url("/get-all-pending-commitments/").await)
             .send()
             .await?;
 
@@ -148,7 +509,7 @@ impl TrollupClient {
 
     async fn get_all_pending_commits_full(&self) -> Result<Vec<StateCommitmentPackageUI<AccountState>>> {
         let response = self.client
-            .get(format!("{}/get-all-pending-commitments/", BASE_URL))
+            .get(self.url("/get-all-pending-commitments/").await)
             .send()
             .await?;
 
@@ -162,7 +523,7 @@ impl TrollupClient {
 
     async fn get_all_blocks(&self) -> Result<String> {
         let response = self.client
-            .get(format!("{}/get-all-blocks/", BASE_URL))
+            .get(self.url("/get-all-blocks/").await)
             .send()
             .await?;
 
@@ -180,39 +541,11 @@ async fn main() -> Result<()> {
     let health_status = client.health_check().await?;
     info!("Health status: {}", health_status);
 
-    let sender = Keypair::new();
-
-    // Specify the recipient's public key
+    // The same sender on every run, so its account can be funded once
+    let sender = test_fixtures::keypair("example/sender");
     let recipient = Pubkey::new_unique();
-
-    // Amount to transfer (in lamports)
     let amount = 1_000_000; // 0.001 SOL
-
-    // Create the transfer instruction
-    let instruction = system_instruction::transfer(
-        &sender.pubkey(),
-        &recipient,
-        amount,
-    );
-
-    // Create a TrollupTransaction
-    let transaction = Transaction {
-        signatures: vec![sender.sign_message(&[0u8; 32]).into()], // Placeholder signature
-        message: Message {
-            header: MessageHeader {
-                num_required_signatures: 1,
-                num_readonly_signed_accounts: 0,
-                num_readonly_unsigned_accounts: 1,
-            },
-            account_keys: vec![sender.pubkey(), recipient, solana_sdk::system_program::id()],
-            recent_blockhash: Hash::default(),
-            instructions: vec![CompiledInstruction {
-                program_id_index: 2, // Index of the system program in account_keys
-                accounts: vec![0, 1], // Indices of sender and recipient in account_keys
-                data: instruction.data.clone(),
-            }],
-        },
-    };
+    let transaction = test_fixtures::transfer_transaction(&sender, &recipient, amount, Hash::default());
 
     let send_result = client.send_transaction_optimistic(&transaction).await?;
     println!("Send optimistic transaction result: {}", send_result);
@@ -220,7 +553,7 @@ async fn main() -> Result<()> {
     // Get transaction details
     let signature = "your_transaction_signature_here";
     let transaction_details = client.get_transaction(signature).await?;
-    println!("Transaction details: {}", transaction_details);
+    println!("Transaction details: {:?}", transaction_details);
     
     let account = client.get_all_accounts().await?;
     // println!("Account details: {}", account);
@@ -230,6 +563,9 @@ async fn main() -> Result<()> {
     
     let transactions = client.get_all_transactions().await?;
     // println!("Transactions details: {}", transactions);
+
+    let exported_accounts = client.export_accounts().await?.filter(|account| std::future::ready(account.is_ok())).count().await;
+    println!("Exported accounts: {}", exported_accounts);
     
     let pending_commits = client.get_all_pending_commits().await?;
     println!("Pending commits: {}", pending_commits);
@@ -240,7 +576,7 @@ async fn main() -> Result<()> {
     
     let commitment_packages = client.get_all_pending_commits_full().await.expect("TODO: panic message");
     
-    let payer = Keypair::from_bytes(&CONFIG.trollup_api_keypair)?;
+    let payer = CONFIG.commitment_fee_payer().map_err(anyhow::Error::msg)?;
     // let payer = Keypair::new();
     // let airdrop_amount = 1_000_000; // 1 SOL in lamports
     // match request_airdrop(&rpc_client, &payer.pubkey(), airdrop_amount).await {
@@ -249,32 +585,39 @@ async fn main() -> Result<()> {
     // }
     
     for commitment_package in commitment_packages {
-        let verifier_prepared = build_verifier(commitment_package.proof, commitment_package.public_inputs, commitment_package.verifying_key);
-        let proof_commitment_package = ProofCommitmentPackage {
-            groth16_verifier_prepared: verifier_prepared,
-            state_root: commitment_package.state_root.unwrap(),
-        };
+        let proof_commitment_package = build_package(&commitment_package);
         // Serialize and encode the proof package
         // let serialized_proof = to_vec(&proof_commitment_package).unwrap();
-        let program_id = Pubkey::from_str(&CONFIG.proof_verifier_program_id)?;
+        let program_id = Pubkey::from_str(CONFIG.proof_verifier_program_id_current_env())?;
         let instruction_data = to_vec(&ProgramInstruction::VerifyProof(proof_commitment_package)).unwrap();
         let (pda, bump_seed) = Pubkey::find_program_address(&[b"state"], &program_id);
+        let (verifying_key, _) = Pubkey::find_program_address(&[b"verifying_key"], &program_id);
+        let mut accounts = vec![
+            AccountMeta::new(pda, false),  // PDA account (writable, not signer)
+            AccountMeta::new_readonly(verifying_key, false),
+        ];
+        // A verifier built with `vault-cpi` unlocks the root in the vault when these are passed
+        if !CONFIG.vault_program_id.is_empty() {
+            let vault_program_id = Pubkey::from_str(&CONFIG.vault_program_id)?;
+            let (vault, _) = Pubkey::find_program_address(&[b"vault"], &vault_program_id);
+            accounts.push(AccountMeta::new_readonly(vault_program_id, false));
+            accounts.push(AccountMeta::new(vault, false));
+        }
         let instruction = Instruction::new_with_bytes(
             program_id,
             instruction_data.as_slice(),
-            vec![
-                AccountMeta::new(pda, false),  // PDA account (writable, not signer)
-            ],
+            accounts,
         );
     
-        // Create and send the transaction
+        // Create and send the transaction, unless the fee payer can't cover it
         let recent_blockhash = rpc_client.get_latest_blockhash().await.unwrap();
-        let transaction = Transaction::new_signed_with_payer(
-            &[instruction],
-            Some(&payer.pubkey()),
-            &[&payer],
-            recent_blockhash,
-        );
+        let compute_unit_limit = ComputeBudgetInstruction::set_compute_unit_limit(CONFIG.proof_verify_compute_unit_limit);
+        let message = Message::new_with_blockhash(&[compute_unit_limit, instruction], Some(&payer.pubkey()), &recent_blockhash);
+        if let Err(err) = fee_payer::ensure_can_pay(&rpc_client, &message, 0, CONFIG.fee_payer_low_balance_lamports).await {
+            println!("Not sending the proof package: {}", err);
+            continue;
+        }
+        let transaction = Transaction::new(&[&payer], message, recent_blockhash);
 
         println!("Sending proof package to on-chain verifier...");
 
@@ -289,41 +632,18 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-fn build_verifier(proof_bytes: Vec<u8>, public_inputs: Vec<u8>, verifying_key: Vec<u8>) -> Groth16VerifierPrepared {
-    let proof = Proof::<Bn254>::deserialize_uncompressed_unchecked(proof_bytes.as_slice()).expect("Error deserializing proof");
-
-    let proof_with_neg_a = Proof::<Bn254> {
-        a: proof.a.neg(),
-        b: proof.b,
-        c: proof.c,
-    };
-    let mut proof_bytes = Vec::with_capacity(proof_with_neg_a.serialized_size(Compress::No));
-    proof_with_neg_a.serialize_uncompressed(&mut proof_bytes).expect("Error serializing proof");
-
-    let proof_a: [u8; 64] = convert_endianness::<32, 64>(proof_bytes[0..64].try_into().unwrap());
-    let proof_b: [u8; 128] = convert_endianness::<64, 128>(proof_bytes[64..192].try_into().unwrap());
-    let proof_c: [u8; 64] = convert_endianness::<32, 64>(proof_bytes[192..256].try_into().unwrap());
-    
-    let prepared_public_input = convert_endianness::<32, 64>(<&[u8; 64]>::try_from(public_inputs.as_slice()).unwrap());
-
-    let vk = VerifyingKey::<Bn254>::deserialize_uncompressed_unchecked(verifying_key.as_slice()).expect("Error deserializing verifying key");
-
-    let groth_vk = convert_arkworks_vk_to_solana_example(&vk);
-    let groth_vk_prepared = Groth16VerifyingKeyPrepared {
-        vk_alpha_g1: groth_vk.vk_alpha_g1,
-        vk_beta_g2: groth_vk.vk_beta_g2,
-        vk_gamma_g2: groth_vk.vk_gamma_g2,
-        vk_delta_g2: groth_vk.vk_delta_g2,
-    };
-
-    let verifier: Groth16VerifierPrepared = Groth16VerifierPrepared::new(
-        proof_a,
-        proof_b,
-        proof_c,
-        prepared_public_input,
-        Box::new(groth_vk_prepared),
-    ).unwrap();
-    verifier
+/// The `VerifyProof` payload of a pending commitment. The proof verifier checks it against the
+/// verifying key it stores and derives the state root inputs itself, only the account hash and
+/// lamports sum are recomputed here.
+fn build_package(commitment_package: &StateCommitmentPackageUI<AccountState>) -> ProofCommitmentPackage {
+    let proof = Proof::<Bn254>::deserialize_uncompressed_unchecked(commitment_package.proof.as_slice()).expect("Error deserializing proof");
+    let public_inputs = compute_public_inputs(
+        &commitment_package.state_records,
+        &commitment_package.parent_state_root.unwrap_or_default(),
+        &commitment_package.transactions_root,
+        &commitment_package.state_root.expect("Pending commitments have a state root"),
+    );
+    build_proof_commitment_package(&proof, &public_inputs)
 }
 
 async fn request_airdrop(client: &RpcClient, pubkey: &Pubkey, amount: u64) -> std::result::Result<(), Box<dyn std::error::Error>> {
@@ -351,14 +671,18 @@ mod test {
     use solana_program::system_instruction;
     use solana_sdk::signature::{Keypair, Signer};
     use solana_sdk::transaction::Transaction;
-    use solana_program::message::{Message, MessageHeader};
+    use solana_program::message::Message;
     use solana_program::hash::Hash;
-    use solana_program::instruction::{AccountMeta, CompiledInstruction, Instruction};
+    use solana_program::instruction::{AccountMeta, Instruction};
     use solana_sdk::commitment_config::CommitmentConfig;
+    use solana_sdk::compute_budget::ComputeBudgetInstruction;
     use tokio::test;
     use state::config::TrollupConfig;
+    use state::test_fixtures;
     use trollup_zk::verify_lite::ProofCommitmentPackage;
-    use crate::{build_verifier, ProgramInstruction, TrollupClient};
+    use crate::{build_package, ConfirmOptions, FallbackOptions, ProgramInstruction, SubmissionPath, TrollupClient};
+    use futures_util::StreamExt;
+    use state::transaction_status::CommitmentLevel;
     // Assuming TrollupClient and other necessary imports are available
 
     #[tokio::test]
@@ -372,36 +696,80 @@ mod test {
     #[tokio::test]
     async fn test_send_transaction() -> Result<()> {
         let client = TrollupClient::new();
-        let sender = Keypair::new();
+        let sender = test_fixtures::keypair("example/sender");
         let recipient = Pubkey::new_unique();
         let amount = 1_000_000; // 0.001 SOL
+        let transaction = test_fixtures::transfer_transaction(&sender, &recipient, amount, Hash::default());
 
-        let instruction = system_instruction::transfer(
-            &sender.pubkey(),
-            &recipient,
-            amount,
-        );
+        let send_result = client.send_transaction(&transaction).await?;
+        assert!(!send_result.is_empty(), "Send transaction should return a non-empty result");
+        Ok(())
+    }
 
-        let transaction = Transaction {
-            signatures: vec![sender.sign_message(&[0u8; 32]).into()],
-            message: Message {
-                header: MessageHeader {
-                    num_required_signatures: 1,
-                    num_readonly_signed_accounts: 0,
-                    num_readonly_unsigned_accounts: 1,
-                },
-                account_keys: vec![sender.pubkey(), recipient, solana_sdk::system_program::id()],
-                recent_blockhash: Hash::default(),
-                instructions: vec![CompiledInstruction {
-                    program_id_index: 2,
-                    accounts: vec![0, 1],
-                    data: instruction.data.clone(),
-                }],
-            },
+    #[tokio::test]
+    async fn test_send_and_confirm() -> Result<()> {
+        env::set_var("TROLLUP_CONFIG_PATH", "./config/local/trollup-api-config.json");
+        let _ = TrollupConfig::load();
+        let config = TrollupConfig::build().unwrap();
+
+        let client = TrollupClient::new();
+        // The sequencer key is funded in the local rollup state
+        let sender = Keypair::from_bytes(&config.trollup_api_keypair)?;
+        let instruction = system_instruction::transfer(&sender.pubkey(), &Pubkey::new_unique(), 1_000);
+        let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&sender.pubkey()), &[&sender], Hash::new_unique());
+
+        let mut levels = Vec::new();
+        let options = ConfirmOptions { timeout: Duration::from_secs(300), ..Default::default() };
+        let status = client
+            .send_and_confirm_with_options(&transaction, CommitmentLevel::Committed, options, |status| levels.push(status.commitment))
+            .await?;
+        assert!(status.has_reached(CommitmentLevel::Committed));
+        assert!(status.block_number.is_some(), "A committed transaction should report its block");
+        assert_eq!(levels.last().copied().flatten(), status.commitment);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_optimistic_with_fallback() -> Result<()> {
+        env::set_var("TROLLUP_CONFIG_PATH", "./config/local/trollup-api-config.json");
+        let _ = TrollupConfig::load();
+        let config = TrollupConfig::build().unwrap();
+
+        let client = TrollupClient::new();
+        let sender = Keypair::from_bytes(&config.trollup_api_keypair)?;
+        let instruction = system_instruction::transfer(&sender.pubkey(), &Pubkey::new_unique(), 1_000);
+        let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&sender.pubkey()), &[&sender], Hash::new_unique());
+
+        // Falls back long before the optimistic window ends, whichever path it takes it commits
+        let options = FallbackOptions {
+            fallback_after: Duration::from_secs(5),
+            proved: ConfirmOptions { timeout: Duration::from_secs(300), ..Default::default() },
         };
+        let outcome = client.send_optimistic_with_fallback(&transaction, CommitmentLevel::Committed, options).await?;
+        assert!(outcome.status.has_reached(CommitmentLevel::Committed));
+        assert_eq!(outcome.fallback_reason.is_some(), outcome.path == SubmissionPath::Proved);
+        Ok(())
+    }
 
-        let send_result = client.send_transaction(&transaction).await?;
-        assert!(!send_result.is_empty(), "Send transaction should return a non-empty result");
+    #[tokio::test]
+    async fn test_verify_account_against_l1() -> Result<()> {
+        env::set_var("TROLLUP_CONFIG_PATH", "./config/local/trollup-api-config.json");
+        let _ = TrollupConfig::load();
+        let config = TrollupConfig::build().unwrap();
+
+        let client = TrollupClient::new();
+        let sender = Keypair::from_bytes(&config.trollup_api_keypair)?;
+        let recipient = Pubkey::new_unique();
+        let instruction = system_instruction::transfer(&sender.pubkey(), &recipient, 1_000);
+        let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&sender.pubkey()), &[&sender], Hash::new_unique());
+        let options = ConfirmOptions { timeout: Duration::from_secs(300), ..Default::default() };
+        let status = client.send_and_confirm_with_options(&transaction, CommitmentLevel::FinalizedOnChain, options, |_| {}).await?;
+
+        // Only holds while the recipient's block is still the latest one committed on L1
+        let verification = client.verify_account_against_l1(&recipient).await?;
+        assert_eq!(Some(verification.l1_root.sequence), status.block_number);
+        assert_eq!(verification.account.lamports, 1_000);
+        assert_eq!(verification.inclusion.root, verification.l1_root.root);
         Ok(())
     }
 
@@ -412,33 +780,10 @@ mod test {
         let CONFIG = TrollupConfig::build().unwrap();
 
         let client = TrollupClient::new();
-        let sender = Keypair::new();
+        let sender = test_fixtures::keypair("example/sender");
         let recipient = Pubkey::new_unique();
         let amount = 1_000_000; // 0.001 SOL
-
-        let instruction = system_instruction::transfer(
-            &sender.pubkey(),
-            &recipient,
-            amount,
-        );
-
-        let transaction = Transaction {
-            signatures: vec![sender.sign_message(&[0u8; 32]).into()],
-            message: Message {
-                header: MessageHeader {
-                    num_required_signatures: 1,
-                    num_readonly_signed_accounts: 0,
-                    num_readonly_unsigned_accounts: 1,
-                },
-                account_keys: vec![sender.pubkey(), recipient, solana_sdk::system_program::id()],
-                recent_blockhash: Hash::default(),
-                instructions: vec![CompiledInstruction {
-                    program_id_index: 2,
-                    accounts: vec![0, 1],
-                    data: instruction.data.clone(),
-                }],
-            },
-        };
+        let transaction = test_fixtures::transfer_transaction(&sender, &recipient, amount, Hash::default());
 
         let send_result = client.send_transaction_optimistic(&transaction).await?;
         println!("Send transaction result: {}", send_result);
@@ -446,7 +791,7 @@ mod test {
         // Get transaction details
         let signature = "your_transaction_signature_here";
         let transaction_details = client.get_transaction(signature).await?;
-        println!("Transaction details: {}", transaction_details);
+        println!("Transaction details: {:?}", transaction_details);
 
         tokio::time::sleep(Duration::from_secs(3)).await;
 
@@ -466,7 +811,7 @@ mod test {
 
         let commitment_packages = client.get_all_pending_commits_full().await.expect("TODO: panic message");
 
-        let payer = Keypair::from_bytes(&CONFIG.trollup_api_keypair)?;
+        let payer = CONFIG.commitment_fee_payer().map_err(anyhow::Error::msg)?;
         // let payer = Keypair::new();
         // let airdrop_amount = 1_000_000; // 1 SOL in lamports
         // match request_airdrop(&rpc_client, &payer.pubkey(), airdrop_amount).await {
@@ -475,32 +820,31 @@ mod test {
         // }
 
         for commitment_package in commitment_packages {
-            let verifier_prepared = build_verifier(commitment_package.proof, commitment_package.public_inputs, commitment_package.verifying_key);
-            let proof_commitment_package = ProofCommitmentPackage {
-                groth16_verifier_prepared: verifier_prepared,
-                state_root: commitment_package.state_root.unwrap(),
-            };
+            let proof_commitment_package = build_package(&commitment_package);
             // Serialize and encode the proof package
             // let serialized_proof = to_vec(&proof_commitment_package).unwrap();
-            let program_id = Pubkey::from_str(&CONFIG.proof_verifier_program_id)?;
+            let program_id = Pubkey::from_str(CONFIG.proof_verifier_program_id_current_env())?;
             let instruction_data = to_vec(&ProgramInstruction::VerifyProof(proof_commitment_package)).unwrap();
             let (pda, bump_seed) = Pubkey::find_program_address(&[b"state"], &program_id);
+            let (verifying_key, _) = Pubkey::find_program_address(&[b"verifying_key"], &program_id);
             let instruction = Instruction::new_with_bytes(
                 program_id,
                 instruction_data.as_slice(),
                 vec![
                     AccountMeta::new(pda, false),  // PDA account (writable, not signer)
+                    AccountMeta::new_readonly(verifying_key, false),
                 ],
             );
 
-            // Create and send the transaction
+            // Create and send the transaction, unless the fee payer can't cover it
             let recent_blockhash = rpc_client.get_latest_blockhash().await.unwrap();
-            let transaction = Transaction::new_signed_with_payer(
-                &[instruction],
-                Some(&payer.pubkey()),
-                &[&payer],
-                recent_blockhash,
-            );
+            let compute_unit_limit = ComputeBudgetInstruction::set_compute_unit_limit(CONFIG.proof_verify_compute_unit_limit);
+            let message = Message::new_with_blockhash(&[compute_unit_limit, instruction], Some(&payer.pubkey()), &recent_blockhash);
+            if let Err(err) = fee_payer::ensure_can_pay(&rpc_client, &message, 0, CONFIG.fee_payer_low_balance_lamports).await {
+                println!("Not sending the proof package: {}", err);
+                continue;
+            }
+            let transaction = Transaction::new(&[&payer], message, recent_blockhash);
 
             // Send and confirm transaction
             match rpc_client.send_and_confirm_transaction_with_spinner(&transaction).await {
@@ -514,10 +858,22 @@ mod test {
 
     #[tokio::test]
     async fn test_get_transaction() -> Result<()> {
+        env::set_var("TROLLUP_CONFIG_PATH", "./config/local/trollup-api-config.json");
+        let _ = TrollupConfig::load();
+        let config = TrollupConfig::build().unwrap();
+
         let client = TrollupClient::new();
-        let signature = "your_transaction_signature_here";
-        let transaction_details = client.get_transaction(signature).await?;
-        assert!(!transaction_details.is_empty(), "Transaction details should not be empty");
+        let sender = Keypair::from_bytes(&config.trollup_api_keypair)?;
+        let instruction = system_instruction::transfer(&sender.pubkey(), &Pubkey::new_unique(), 1_000);
+        let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&sender.pubkey()), &[&sender], Hash::new_unique());
+        let options = ConfirmOptions { timeout: Duration::from_secs(300), ..Default::default() };
+        client.send_and_confirm_with_options(&transaction, CommitmentLevel::Committed, options, |_| {}).await?;
+
+        let transaction_details = client.get_transaction(&transaction.signatures[0].to_string()).await?;
+        let transaction_details = transaction_details.expect("Transaction details should be found");
+        assert_eq!(transaction_details.signatures[0], transaction.signatures[0].to_string());
+        assert_eq!(transaction_details.instructions[0].parsed.as_ref().and_then(|parsed| parsed["lamports"].as_u64()), Some(1_000));
+        assert!(client.get_transaction("your_transaction_signature_here").await?.is_none());
         Ok(())
     }
 
@@ -529,6 +885,28 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_export_accounts() -> Result<()> {
+        let client = TrollupClient::new();
+        let mut accounts = client.export_accounts().await?;
+        let mut exported = 0;
+        while let Some(account) = accounts.next().await {
+            account?;
+            exported += 1;
+        }
+        assert!(exported > 0, "The account export should not be empty");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_export_transactions() -> Result<()> {
+        let client = TrollupClient::new();
+        let mut transactions = client.export_transactions().await?;
+        let transaction = transactions.next().await.expect("The transaction export should not be empty")?;
+        assert!(!transaction.signatures.is_empty());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_get_all_blocks() -> Result<()> {
         let client = TrollupClient::new();
@@ -553,6 +931,26 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_instructions_match_the_instruction_vectors() -> Result<()> {
+        use trollup_commitment_message::instruction_vectors::{instruction_vectors, Instruction};
+        use trollup_zk::verify_lite::Groth16Proof;
+
+        let vectors: Vec<_> = instruction_vectors().into_iter().filter(|vector| vector.instruction == Instruction::VerifyProof).collect();
+        assert!(!vectors.is_empty());
+        for vector in vectors {
+            let proof = vector.proof();
+            let package = ProofCommitmentPackage {
+                proof: Groth16Proof { proof_a: proof.proof_a(), proof_b: proof.proof_b(), proof_c: proof.proof_c() },
+                batch_inputs: proof.batch_inputs(),
+                state_root: proof.state_root(),
+            };
+            assert_eq!(borsh::to_vec(&ProgramInstruction::VerifyProof(package))?, vector.data(), "{}", vector.name);
+        }
+        assert_eq!(borsh::to_vec(&ProgramInstruction::Initialize)?, [0u8]);
+        Ok(())
+    }
+
     // Additional test for get_all_pending_commits_full if needed
     // #[tokio::test]
     // async fn test_get_all_pending_commits_full() -> Result<()> {
@@ -0,0 +1,183 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction::SystemInstruction;
+use solana_sdk::system_program;
+use solana_sdk::transaction::SanitizedTransaction;
+use state::account_state::AccountState;
+use state::config::TrollupConfig;
+use std::collections::HashSet;
+use std::fmt;
+
+/// One-time price of the storage of a new account, charged to the fee payer of the transaction
+/// creating it on top of the transaction fee. Rollup accounts don't pay rent over time.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AccountCreationPricing {
+    pub base_lamports: u64,
+    pub lamports_per_byte: u64,
+}
+
+impl AccountCreationPricing {
+    pub fn from_config(config: &TrollupConfig) -> Self {
+        AccountCreationPricing {
+            base_lamports: config.account_creation_base_lamports,
+            lamports_per_byte: config.account_creation_lamports_per_byte,
+        }
+    }
+
+    pub fn price(&self, data_len: usize) -> u64 {
+        self.base_lamports.saturating_add(self.lamports_per_byte.saturating_mul(data_len as u64))
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum AccountCreationError {
+    /// The account came into existence without being created, e.g. by a transfer to a new key
+    Implicit(Pubkey),
+    InsufficientFunds { fee_payer: Pubkey, required: u64, available: u64 },
+}
+
+impl fmt::Display for AccountCreationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AccountCreationError::Implicit(address) => write!(f, "account {} is materialized without a system CreateAccount or Allocate", address),
+            AccountCreationError::InsufficientFunds { fee_payer, required, available } => write!(
+                f,
+                "fee payer {} has {} lamports but the new accounts cost {}",
+                fee_payer, available, required,
+            ),
+        }
+    }
+}
+
+/// Accounts the transaction's own system instructions create or allocate.
+pub fn explicitly_created(transaction: &SanitizedTransaction) -> HashSet<Pubkey> {
+    let message = transaction.message();
+    let account_keys = message.account_keys();
+    message
+        .instructions()
+        .iter()
+        .filter(|instruction| account_keys.get(instruction.program_id_index as usize) == Some(&system_program::id()))
+        .filter_map(|instruction| {
+            let position = match bincode::deserialize(&instruction.data).ok()? {
+                SystemInstruction::CreateAccount { .. } | SystemInstruction::CreateAccountWithSeed { .. } => 1,
+                SystemInstruction::Allocate { .. } | SystemInstruction::AllocateWithSeed { .. } => 0,
+                _ => return None,
+            };
+            let index = *instruction.accounts.get(position)?;
+            account_keys.get(index as usize).copied()
+        })
+        .collect()
+}
+
+/// Settles the accounts `transaction` brought into existence. `accounts` are its loaded accounts
+/// as left by execution and `existed` tells whether an account existed before it.
+///
+/// A new account has to be created explicitly, by one of the transaction's system instructions or
+/// by a program, which can only own a new account once the system program created it for the
+/// program. Outside of `allow_implicit` anything else, such as lamports sent to a key that
+/// doesn't exist, fails the transaction. The fee payer is debited the storage price of every new
+/// account, which is returned.
+pub fn settle(
+    transaction: &SanitizedTransaction,
+    accounts: &mut [AccountState],
+    existed: impl Fn(&Pubkey) -> bool,
+    pricing: &AccountCreationPricing,
+    allow_implicit: bool,
+) -> Result<u64, AccountCreationError> {
+    let explicit = explicitly_created(transaction);
+    let mut price = 0u64;
+    for account in accounts.iter() {
+        if existed(&account.address) || (account.lamports == 0 && account.data.is_empty()) {
+            continue;
+        }
+        let program_owned = account.owner != system_program::id();
+        if !allow_implicit && !program_owned && !explicit.contains(&account.address) {
+            return Err(AccountCreationError::Implicit(account.address));
+        }
+        price = price.saturating_add(pricing.price(account.data.len()));
+    }
+    if price == 0 {
+        return Ok(0);
+    }
+
+    let fee_payer_address = *transaction.message().fee_payer();
+    let fee_payer = accounts
+        .iter_mut()
+        .find(|account| account.address == fee_payer_address)
+        .filter(|fee_payer| fee_payer.lamports >= price);
+    match fee_payer {
+        Some(fee_payer) => {
+            fee_payer.lamports -= price;
+            Ok(price)
+        }
+        None => Err(AccountCreationError::InsufficientFunds {
+            fee_payer: fee_payer_address,
+            required: price,
+            available: accounts.iter().find(|account| account.address == fee_payer_address).map_or(0, |account| account.lamports),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::Transaction;
+    use state::test_fixtures::keypair;
+    use state::transaction::{convert_to_sanitized_transaction, TrollupTransaction};
+
+    const PRICING: AccountCreationPricing = AccountCreationPricing { base_lamports: 1_000, lamports_per_byte: 10 };
+
+    fn sanitized(payer: &Keypair, instruction: solana_sdk::instruction::Instruction) -> SanitizedTransaction {
+        let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[payer], Default::default());
+        convert_to_sanitized_transaction(&TrollupTransaction::from(&transaction)).unwrap()
+    }
+
+    fn account(address: Pubkey, lamports: u64, space: usize, owner: Pubkey) -> AccountState {
+        AccountState { address, lamports, data: vec![0; space], owner, executable: false, rent_epoch: 0, data_root: None }
+    }
+
+    #[test]
+    fn test_create_account_is_charged_to_the_fee_payer() {
+        let payer = keypair("payer");
+        let new_account = keypair("new-account");
+        let owner = Pubkey::new_unique();
+        let transaction = sanitized(&payer, system_instruction::create_account(&payer.pubkey(), &new_account.pubkey(), 500, 64, &owner));
+        let mut accounts = vec![
+            account(payer.pubkey(), 10_000, 0, system_program::id()),
+            account(new_account.pubkey(), 500, 64, owner),
+        ];
+
+        let price = settle(&transaction, &mut accounts, |address| *address == payer.pubkey(), &PRICING, false).unwrap();
+
+        assert_eq!(price, 1_000 + 64 * 10);
+        assert_eq!(accounts[0].lamports, 10_000 - price);
+        assert_eq!(explicitly_created(&transaction), HashSet::from([new_account.pubkey()]));
+    }
+
+    #[test]
+    fn test_transfer_to_new_key_is_implicit() {
+        let payer = keypair("payer");
+        let recipient = Pubkey::new_unique();
+        let transaction = sanitized(&payer, system_instruction::transfer(&payer.pubkey(), &recipient, 500));
+        let accounts = vec![
+            account(payer.pubkey(), 10_000, 0, system_program::id()),
+            account(recipient, 500, 0, system_program::id()),
+        ];
+        let existed = |address: &Pubkey| *address == payer.pubkey();
+
+        assert_eq!(
+            settle(&transaction, &mut accounts.clone(), existed, &PRICING, false),
+            Err(AccountCreationError::Implicit(recipient)),
+        );
+        // Allowed in dev mode, and still priced
+        assert_eq!(settle(&transaction, &mut accounts.clone(), existed, &PRICING, true), Ok(1_000));
+
+        let mut broke = accounts.clone();
+        broke[0].lamports = 999;
+        assert_eq!(
+            settle(&transaction, &mut broke, existed, &PRICING, true),
+            Err(AccountCreationError::InsufficientFunds { fee_payer: payer.pubkey(), required: 1_000, available: 999 }),
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use state::config::TrollupConfig;
+use std::time::Duration;
+
+/// When the execution engine produces blocks.
+///
+/// With an interval the engine drains the pool once per interval, so blocks follow a steady
+/// cadence of at most `TRANSACTION_BATCH_AMOUNT` transactions each. Intervals without
+/// transactions produce nothing, unless heartbeats are enabled: after `heartbeat_intervals` idle
+/// intervals in a row the engine produces an empty heartbeat block, which the committer
+/// finalizes without a proof. Without an interval blocks are produced as soon as transactions
+/// are pending and there are no heartbeats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockProductionPolicy {
+    pub interval: Option<Duration>,
+    /// Idle intervals before a heartbeat block, `0` disables heartbeats
+    pub heartbeat_intervals: u64,
+}
+
+impl BlockProductionPolicy {
+    pub fn from_config(config: &TrollupConfig) -> Self {
+        BlockProductionPolicy {
+            interval: (config.block_interval_ms > 0).then(|| Duration::from_millis(config.block_interval_ms)),
+            heartbeat_intervals: config.heartbeat_intervals,
+        }
+    }
+
+    pub fn cadence(&self) -> BlockCadence {
+        BlockCadence {
+            heartbeat_intervals: if self.interval.is_some() { self.heartbeat_intervals } else { 0 },
+            idle_intervals: 0,
+        }
+    }
+}
+
+/// Counts the idle intervals since the last block.
+#[derive(Debug)]
+pub struct BlockCadence {
+    heartbeat_intervals: u64,
+    idle_intervals: u64,
+}
+
+impl BlockCadence {
+    /// Records the end of an interval, `produced` telling whether it produced a block. Returns
+    /// whether a heartbeat block is due.
+    pub fn record_interval(&mut self, produced: bool) -> bool {
+        if produced {
+            self.idle_intervals = 0;
+            return false;
+        }
+        self.idle_intervals += 1;
+        if self.heartbeat_intervals == 0 || self.idle_intervals < self.heartbeat_intervals {
+            return false;
+        }
+        self.idle_intervals = 0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_after_idle_intervals() {
+        let policy = BlockProductionPolicy { interval: Some(Duration::from_millis(400)), heartbeat_intervals: 3 };
+        let mut cadence = policy.cadence();
+
+        assert!(!cadence.record_interval(false));
+        assert!(!cadence.record_interval(false));
+        // A block resets the count
+        assert!(!cadence.record_interval(true));
+        assert!(!cadence.record_interval(false));
+        assert!(!cadence.record_interval(false));
+        assert!(cadence.record_interval(false));
+        // The heartbeat starts the next count
+        assert!(!cadence.record_interval(false));
+    }
+
+    #[test]
+    fn test_no_heartbeats_without_interval() {
+        let policy = BlockProductionPolicy { interval: None, heartbeat_intervals: 1 };
+        let mut cadence = policy.cadence();
+        assert!((0..10).all(|_| !cadence.record_interval(false)));
+
+        let policy = BlockProductionPolicy { interval: Some(Duration::from_millis(400)), heartbeat_intervals: 0 };
+        let mut cadence = policy.cadence();
+        assert!((0..10).all(|_| !cadence.record_interval(false)));
+    }
+}
@@ -0,0 +1,134 @@
+use solana_sdk::pubkey::Pubkey;
+use state::config::TrollupConfig;
+use state::transaction::TrollupTransaction;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Static estimate of the compute units a transaction will consume, cheap enough to run at
+/// admission. Transactions estimated above the per-transaction limit are rejected before they
+/// take a slot in a batch.
+///
+/// The estimate adds a cost per signature, per account referenced by an instruction and per
+/// byte of instruction data to the cost of every instruction's program. Programs without a
+/// configured cost are charged the default, which matches the SVM's default per-instruction
+/// budget so unknown programs are estimated pessimistically.
+#[derive(Clone, Debug)]
+pub struct CostModel {
+    pub signature_units: u64,
+    pub account_units: u64,
+    pub data_byte_units: u64,
+    pub default_program_units: u64,
+    pub program_units: HashMap<Pubkey, u64>,
+    pub max_transaction_units: u64,
+}
+
+impl CostModel {
+    pub fn from_config(config: &TrollupConfig) -> Self {
+        CostModel {
+            signature_units: config.cost_model_signature_units,
+            account_units: config.cost_model_account_units,
+            data_byte_units: config.cost_model_data_byte_units,
+            default_program_units: config.cost_model_default_program_units,
+            program_units: config.cost_model_program_units
+                .iter()
+                .filter_map(|(program_id, units)| Pubkey::from_str(program_id).ok().map(|program_id| (program_id, *units)))
+                .collect(),
+            max_transaction_units: config.max_transaction_compute_units,
+        }
+    }
+
+    pub fn estimate(&self, transaction: &TrollupTransaction) -> u64 {
+        let message = &transaction.message;
+        let instructions = message.instructions.iter().map(|instruction| {
+            let program_units = message.account_keys
+                .get(instruction.program_id_index as usize)
+                .and_then(|program_id| self.program_units.get(&Pubkey::from(*program_id)))
+                .copied()
+                .unwrap_or(self.default_program_units);
+            program_units
+                .saturating_add(self.account_units.saturating_mul(instruction.accounts.len() as u64))
+                .saturating_add(self.data_byte_units.saturating_mul(instruction.data.len() as u64))
+        });
+        instructions.fold(
+            self.signature_units.saturating_mul(transaction.signatures.len() as u64),
+            u64::saturating_add,
+        )
+    }
+
+    /// The estimate, or an error describing why the transaction is rejected.
+    pub fn check(&self, transaction: &TrollupTransaction) -> Result<u64, String> {
+        let estimate = self.estimate(transaction);
+        if estimate > self.max_transaction_units {
+            return Err(format!(
+                "Transaction is estimated at {} compute units, the limit is {}",
+                estimate, self.max_transaction_units
+            ));
+        }
+        Ok(estimate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::Instruction;
+    use solana_sdk::signature::Signer;
+    use solana_sdk::system_program;
+    use solana_sdk::transaction::Transaction;
+    use state::test_fixtures::{keypair, transfer_transaction};
+    use state::transaction::convert_to_trollup_transaction;
+
+    fn model(max_transaction_units: u64) -> CostModel {
+        CostModel {
+            signature_units: 1_000,
+            account_units: 100,
+            data_byte_units: 10,
+            default_program_units: 50_000,
+            program_units: HashMap::from([(system_program::id(), 150)]),
+            max_transaction_units,
+        }
+    }
+
+    fn transfer() -> TrollupTransaction {
+        convert_to_trollup_transaction(transfer_transaction(&keypair("cost-model/payer"), &Pubkey::new_unique(), 1, Hash::default())).unwrap()
+    }
+
+    #[test]
+    fn test_estimate_adds_signatures_programs_accounts_and_data() {
+        // One signature, a system transfer of two accounts and 12 bytes of data
+        assert_eq!(model(u64::MAX).estimate(&transfer()), 1_000 + 150 + 2 * 100 + 12 * 10);
+    }
+
+    #[test]
+    fn test_programs_without_a_cost_are_charged_the_default() {
+        let payer = keypair("cost-model/payer");
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[1, 2], vec![]);
+        let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], Hash::default());
+
+        assert_eq!(model(u64::MAX).estimate(&convert_to_trollup_transaction(transaction).unwrap()), 1_000 + 50_000 + 2 * 10);
+    }
+
+    #[test]
+    fn test_check_rejects_estimates_over_the_limit() {
+        let estimate = model(u64::MAX).estimate(&transfer());
+
+        assert_eq!(model(estimate).check(&transfer()), Ok(estimate));
+        let error = model(estimate - 1).check(&transfer()).unwrap_err();
+        assert_eq!(error, format!("Transaction is estimated at {} compute units, the limit is {}", estimate, estimate - 1));
+    }
+
+    #[test]
+    fn test_from_config_skips_invalid_program_ids() {
+        let mut config = TrollupConfig::build().unwrap();
+        config.cost_model_program_units = HashMap::from([
+            (system_program::id().to_string(), 150),
+            ("not a program id".to_string(), 1),
+        ]);
+
+        let model = CostModel::from_config(&config);
+
+        assert_eq!(model.program_units, HashMap::from([(system_program::id(), 150)]));
+        assert_eq!(model.max_transaction_units, config.max_transaction_compute_units);
+    }
+}
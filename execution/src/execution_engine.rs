@@ -1,4 +1,11 @@
+use crate::account_creation::{self, AccountCreationError, AccountCreationPricing};
+use crate::block_production::BlockProductionPolicy;
+use crate::cost_model::CostModel;
+use crate::ordering::{self, OrderingPolicy};
 use crate::processor::{create_transaction_batch_processor, get_transaction_check_results};
+use crate::program_limits::ProgramLimits;
+use crate::signature_verification::retain_valid_signatures;
+use crate::sysvars::SysvarProvider;
 use crate::transaction_pool::TransactionPool;
 use solana_compute_budget::compute_budget::ComputeBudget;
 use solana_sdk::account::ReadableAccount;
@@ -6,28 +13,48 @@ use solana_sdk::feature_set::FeatureSet;
 use solana_sdk::fee::FeeStructure;
 use solana_sdk::hash::Hash;
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::rent_collector::RentCollector;
+use solana_sdk::sysvar;
 use solana_sdk::transaction::SanitizedTransaction;
 use solana_svm::account_loader::{LoadedTransaction, TransactionLoadResult};
-use solana_svm::transaction_processor::{LoadAndExecuteSanitizedTransactionsOutput, TransactionProcessingConfig, TransactionProcessingEnvironment};
+use solana_svm::transaction_processing_callback::TransactionProcessingCallback;
+use solana_svm::transaction_processor::{ExecutionRecordingConfig, LoadAndExecuteSanitizedTransactionsOutput, TransactionProcessingConfig, TransactionProcessingEnvironment};
 use solana_svm::transaction_results::TransactionExecutionResult;
+use serde_derive::{Deserialize, Serialize};
 use state::account_state::AccountState;
+use state::receipt::{InstructionWriteSet, TransactionReceipt};
+use state::rollup_metadata::RollupMetadata;
 use state::state_record::{StateCommitmentPackage, StateRecord};
 use state::transaction::TrollupTransaction;
+use state::witness::{AccountWitness, WitnessBundle};
+use state_commitment::bundles;
+use state_commitment::challenges;
+use state_commitment::governance;
+use state_commitment::load_failures;
+use state_commitment::pipeline::{self, Stage};
+use state_commitment::rollup_status;
 use state_commitment::state_commitment_pool::{StateCommitmentPool, StatePool};
 use state_management::account_loader::TrollupAccountLoader;
 use state_management::state_management::{ManageState, StateManager};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc};
+use base64::Engine as _;
 use lazy_static::lazy_static;
-use solana_program_runtime::log_collector::log::info;
+use solana_program_runtime::log_collector::log::{info, warn};
 use tokio::sync::Mutex;
+use tokio::time::MissedTickBehavior;
 use state::config::TrollupConfig;
+use std::time::Duration;
 
 lazy_static! {
     static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref COST_MODEL: CostModel = CostModel::from_config(&CONFIG);
+    static ref CREATION_PRICING: AccountCreationPricing = AccountCreationPricing::from_config(&CONFIG);
+    static ref PROGRAM_LIMITS: ProgramLimits = ProgramLimits::from_config(&CONFIG);
 }
 
+/// How often a halted engine checks whether commitments resumed.
+const HALT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(PartialEq, Eq, Debug)]
 enum EngineState {
     Running,
@@ -49,11 +76,15 @@ enum EngineState {
 /// - `transaction_pool`: a `TransactionPool` instance for managing the pool of unprocessed transactions.
 /// - `account_state_commitment`: a `StateCommitmentLayer` instance for committing the state changes of accounts.
 /// - `transaction_state_commitment`: a `StateCommitmentLayer` instance for committing the state changes of transactions.
+/// - `sysvars`: a `SysvarProvider` supplying the Clock, Rent and EpochSchedule sysvars of the block being executed.
 pub struct ExecutionEngine<'a, A: ManageState<Record=AccountState>> {
     account_state_management: &'a StateManager<A>,
     transaction_pool: Arc<Mutex<TransactionPool>>,
     commitment_pool: Arc<Mutex<StateCommitmentPool<AccountState>>>,
+    sysvars: SysvarProvider,
     engine_state: EngineState,
+    ordering_policy: Box<dyn OrderingPolicy>,
+    block_production: BlockProductionPolicy,
 }
 
 impl<'a, A: ManageState<Record=AccountState>> ExecutionEngine<'a, A> {
@@ -63,33 +94,67 @@ impl<'a, A: ManageState<Record=AccountState>> ExecutionEngine<'a, A> {
     /// - `account_state_management`: A reference to a `StateManager` instance for managing the state of accounts.
     /// - `transaction_pool`: An atomic reference counter to a thread-safe `TransactionPool` instance for managing the pool of unprocessed transactions.
     /// - `commitment_pool`: An atomic reference counter to a thread-safe `StateCommitmentPool` instance for committing the state changes of accounts.
+    /// - `sysvars`: A `SysvarProvider` positioned at the last finalized block, advanced once per executed block.
     ///
     /// # Returns
     /// A new `ExecutionEngine` instance initialized with the provided `StateManager`, `TransactionPool`, and `StateCommitmentPool`.
-    pub fn new(account_state_management: &'a StateManager<A>, transaction_pool: Arc<Mutex<TransactionPool>>, commitment_pool: Arc<Mutex<StateCommitmentPool<AccountState>>>) -> Self {
+    pub fn new(account_state_management: &'a StateManager<A>, transaction_pool: Arc<Mutex<TransactionPool>>, commitment_pool: Arc<Mutex<StateCommitmentPool<AccountState>>>, sysvars: SysvarProvider) -> Self {
         Self {
             account_state_management,
             transaction_pool,
             commitment_pool,
+            sysvars,
             engine_state: EngineState::Initialized,
+            ordering_policy: ordering::builtin(CONFIG.ordering_policy),
+            block_production: BlockProductionPolicy::from_config(&CONFIG),
         }
     }
 
-    /// Starts the execution loop.
-    ///
-    /// This method runs an infinite loop until a break condition is met.
-    /// It checks if the transaction pool size is greater than or equal to 4.
-    /// If it is, it calls the `execute_block` method.
-    /// If the pool size is less than 4, it breaks out of the loop.
+    /// Replaces the `ORDERING_POLICY` the pool is drained with, e.g. by a custom policy.
+    pub fn with_ordering_policy(mut self, ordering_policy: Box<dyn OrderingPolicy>) -> Self {
+        self.ordering_policy = ordering_policy;
+        self
+    }
+
+    /// Replaces the `BLOCK_INTERVAL_MS` and `HEARTBEAT_INTERVALS` policy.
+    pub fn with_block_production(mut self, block_production: BlockProductionPolicy) -> Self {
+        self.block_production = block_production;
+        self
+    }
+
+    /// Starts the execution loop, producing blocks as the `BlockProductionPolicy` says until the
+    /// engine is stopped.
     pub async fn start(&mut self) {
         self.engine_state = EngineState::Running;
         info!("Execution Engine started.");
+        let mut ticker = self.block_production.interval.map(|interval| {
+            let mut ticker = tokio::time::interval(interval);
+            // A slow block pushes the following ones back instead of producing a burst to catch up
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            ticker
+        });
+        let mut cadence = self.block_production.cadence();
+        let mut halted = false;
         loop {
             if self.engine_state == EngineState::Stopped {
                 info!("Execution Engine stopped.");
                 break;
-            } else {
-                self.execute_block().await;
+            }
+            // The local chain may be rolled back to L1, nothing is executed on state about to change
+            if rollup_status::commitments_halted() {
+                halted = true;
+                tokio::time::sleep(HALT_POLL_INTERVAL).await;
+                continue;
+            }
+            if std::mem::take(&mut halted) {
+                self.sysvars.resync(rollup_status::finalized_block_number() + 1);
+            }
+            if let Some(ticker) = ticker.as_mut() {
+                ticker.tick().await;
+            }
+            let produced = self.execute_block().await;
+            if cadence.record_interval(produced) {
+                self.produce_heartbeat().await;
             }
         }
     }
@@ -99,40 +164,70 @@ impl<'a, A: ManageState<Record=AccountState>> ExecutionEngine<'a, A> {
         self.engine_state = EngineState::Stopped;
     }
 
-    /// Executes a block by processing a set of transactions.
-    pub async fn execute_block(&mut self) {
+    /// Executes a block by processing a set of transactions. Returns whether the block had
+    /// successful transactions, nothing is produced otherwise.
+    pub async fn execute_block(&mut self) -> bool {
+        let parameters = governance::current();
         let mut tx_pool = self.transaction_pool.lock().await;
-        let transactions = tx_pool.get_next_transactions(CONFIG.transaction_batch_amount);
+        // The block's trees have to stay within MERKLE_MAX_DEPTH
+        let batch_amount = (parameters.transaction_batch_amount as u64).min(CONFIG.merkle_parameters().max_block_transactions()) as u32;
+        let transactions = tx_pool.take_ordered(self.ordering_policy.as_ref(), batch_amount);
+        let request_ids: HashMap<[u8; 32], String> = transactions
+            .iter()
+            .map(StateRecord::get_key)
+            .zip(tx_pool.take_request_ids(&transactions))
+            .filter(|(_, request_id)| !request_id.is_empty())
+            .collect();
         drop(tx_pool);
+        let transactions = retain_valid_signatures(transactions);
         if transactions.is_empty() {
-            return;
+            return false;
         }
-
-        let sanitized_txs = batch_sanitize_transactions(&transactions);
-
-        // Create a mapping of signatures to transactions
-        let tx_map: HashMap<[u8; 32], TrollupTransaction> = transactions
+        let executing_request_ids: Vec<String> = transactions
             .iter()
-            .map(|tx| (tx.get_key(), tx.clone()))
+            .filter_map(|tx| request_ids.get(&tx.get_key()).cloned())
             .collect();
+        pipeline::enter(&transactions, Stage::Executing, &executing_request_ids, None);
 
-        let results = self.execute_svm_transactions(sanitized_txs.clone());
-        let loaded_txs = results.loaded_transactions;
-
-        let exec_results = results.execution_results;
-
-        let successful_outcomes = extract_successful_transactions(tx_map, &loaded_txs, &exec_results);
+        self.sysvars.advance();
+        self.sysvars.set_metadata(rollup_status::metadata(&CONFIG, &parameters));
+        let mut witness = witness_bundle(self.account_state_management, &self.sysvars, &transactions);
+        let account_loader = TrollupAccountLoader::new(self.account_state_management);
+        let successful_outcomes = execute_batch(&account_loader, &self.sysvars, &transactions);
+        pipeline::leave(&transactions);
+        let successful_outcomes = settle_bundles(&transactions, successful_outcomes, &mut witness);
 
+        let produced = !successful_outcomes.is_empty();
         let mut successful_txs: Vec<TrollupTransaction> = Vec::new();
         let mut successful_optimistic_txs: Vec<TrollupTransaction> = Vec::new();
         let mut transaction_ids = Vec::with_capacity(successful_outcomes.len());
         let mut account_states: Vec<AccountState> = Vec::new();
-        for outcome in successful_outcomes {
-            transaction_ids.push(outcome.trollup_transaction.get_key());
+        let mut batch_request_ids: Vec<String> = Vec::new();
+        let mut optimistic_request_ids: Vec<String> = Vec::new();
+        let mut receipts: Vec<TransactionReceipt> = Vec::new();
+        let mut optimistic_receipts: Vec<TransactionReceipt> = Vec::new();
+        for mut outcome in successful_outcomes {
+            record_cost(&outcome);
+            challenges::bond_executed(&outcome.trollup_transaction, outcome.error.as_deref());
+            outcome.trollup_transaction.optimistic = CONFIG.mode.is_optimistic(outcome.trollup_transaction.optimistic);
+            let transaction_id = outcome.trollup_transaction.get_key();
+            transaction_ids.push(transaction_id);
+            let receipt = outcome.receipt();
             account_states.extend(outcome.accounts);
+            let request_id = request_ids.get(&transaction_id).cloned();
+            if let Some(request_id) = &request_id {
+                info!("Executed transaction {} for request {}", signature_string(&outcome.trollup_transaction), request_id);
+            }
+            if outcome.trollup_transaction.system {
+                info!(target: "audit", "Executed system transaction {}", signature_string(&outcome.trollup_transaction));
+            }
             if outcome.trollup_transaction.optimistic {
+                optimistic_receipts.push(receipt);
+                optimistic_request_ids.extend(request_id);
                 successful_optimistic_txs.push(outcome.trollup_transaction)
             } else {
+                receipts.push(receipt);
+                batch_request_ids.extend(request_id);
                 successful_txs.push(outcome.trollup_transaction);
             }
         }
@@ -147,8 +242,17 @@ impl<'a, A: ManageState<Record=AccountState>> ExecutionEngine<'a, A> {
                 state_records: account_states.clone(),
                 transactions: successful_txs,
                 transaction_ids: transaction_ids.clone(),
+                verifying_key_hash: None,
+                parent_state_root: None,
+                witness: Some(witness.clone()),
+                request_ids: batch_request_ids,
+                receipts,
+                parameters_hash: parameters.hash(),
+                program_data_hashes: vec![],
             };
 
+            pipeline::enter(&commitment_package.transactions, Stage::AwaitingProof, &commitment_package.request_ids, None);
+            pipeline::record_writes(&commitment_package.transactions, &commitment_package.state_records);
             let mut commit_pool = self.commitment_pool.lock().await;
             commit_pool.add(commitment_package);
         }
@@ -163,61 +267,300 @@ impl<'a, A: ManageState<Record=AccountState>> ExecutionEngine<'a, A> {
                 state_records: account_states,
                 transactions: successful_optimistic_txs,
                 transaction_ids,
+                verifying_key_hash: None,
+                parent_state_root: None,
+                witness: Some(witness),
+                request_ids: optimistic_request_ids,
+                receipts: optimistic_receipts,
+                parameters_hash: parameters.hash(),
+                program_data_hashes: vec![],
             };
 
+            pipeline::enter(&commitment_package.transactions, Stage::AwaitingProof, &commitment_package.request_ids, None);
+            pipeline::record_writes(&commitment_package.transactions, &commitment_package.state_records);
             let mut commit_pool = self.commitment_pool.lock().await;
             commit_pool.add(commitment_package);
         }
 
+        produced
     }
 
+    /// Queues an empty package, which the committer turns into a heartbeat block. The Clock
+    /// advances like for any other block, so slots keep matching block numbers.
+    async fn produce_heartbeat(&mut self) {
+        self.sysvars.advance();
+        info!("Producing heartbeat block after {} idle intervals", self.block_production.heartbeat_intervals);
+        self.commitment_pool.lock().await.add(StateCommitmentPackage::new(false, vec![], vec![], vec![]));
+    }
 
-    pub fn execute_svm_transactions(&self, transactions: Vec<SanitizedTransaction>) -> LoadAndExecuteSanitizedTransactionsOutput {
-        let compute_budget = ComputeBudget::default();
-        let feature_set = FeatureSet::all_enabled();
-        let fee_structure = FeeStructure::default();
-        let lamports_per_signature = fee_structure.lamports_per_signature;
-        let rent_collector = RentCollector::default();
 
+    pub fn execute_svm_transactions(&self, transactions: Vec<SanitizedTransaction>) -> LoadAndExecuteSanitizedTransactionsOutput {
         let account_loader = TrollupAccountLoader::new(self.account_state_management);
+        load_and_execute(&account_loader, &self.sysvars, &transactions, &vec![false; transactions.len()], ExecutionRecordingConfig::default())
+    }
+}
 
-        let (processor, _fork_graph) =
-            create_transaction_batch_processor(&account_loader, &feature_set, &compute_budget);
-
-        let processing_environment = TransactionProcessingEnvironment {
-            blockhash: Hash::default(),
-            epoch_total_stake: None,
-            epoch_vote_accounts: None,
-            feature_set: Arc::new(feature_set),
-            fee_structure: Some(&fee_structure),
-            lamports_per_signature,
-            rent_collector: Some(&rent_collector),
-        };
+/// Executes `transactions` in order against the accounts `account_loader` serves, with the
+/// sysvars of the current block. Returns the transactions that executed along with the accounts
+/// they loaded, in execution order. Transactions that fail to sanitize are skipped.
+///
+/// The sequencer and a validator re-executing a witness bundle both go through here, so they
+/// derive the same account states from the same inputs.
+pub fn execute_batch<A: ManageState<Record=AccountState>>(
+    account_loader: &TrollupAccountLoader<A>,
+    sysvars: &SysvarProvider,
+    transactions: &[TrollupTransaction],
+) -> Vec<ExecutionOutcome> {
+    let (transactions, sanitized_txs): (Vec<TrollupTransaction>, Vec<SanitizedTransaction>) = transactions
+        .iter()
+        .filter_map(|tx| {
+            state::transaction::convert_to_sanitized_transaction(tx)
+                .map_err(|e| warn!("Failed to sanitize transaction: {:?}", e))
+                .ok()
+                .filter(|sanitized| !writes_metadata_account(sanitized))
+                .map(|sanitized| (tx.clone(), sanitized))
+        })
+        .unzip();
 
-        let processing_config = TransactionProcessingConfig {
-            compute_budget: Some(compute_budget),
-            ..Default::default()
-        };
+    let fee_free: Vec<bool> = transactions.iter().map(|tx| tx.system).collect();
+    let results = load_and_execute(account_loader, sysvars, &sanitized_txs, &fee_free, ExecutionRecordingConfig::default());
+    extract_successful_transactions(account_loader, transactions, &sanitized_txs, &results.loaded_transactions, &results.execution_results)
+}
+
+/// Outcome of simulating a single transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationResult {
+    /// Why the transaction failed, `None` when it succeeded
+    pub err: Option<String>,
+    pub logs: Vec<String>,
+    pub units_consumed: u64,
+    /// Base64 return data of the last program that set it
+    pub return_data: Option<String>,
+    /// State every loaded account would be left in, sysvars excluded
+    pub accounts: Vec<AccountState>,
+}
+
+/// Executes `transaction` against the accounts `account_loader` serves without committing
+/// anything, recording the program logs and return data. Signatures are not checked, the same
+/// as Solana's `simulateTransaction`.
+pub fn simulate_transaction<A: ManageState<Record=AccountState>>(
+    account_loader: &TrollupAccountLoader<A>,
+    sysvars: &SysvarProvider,
+    transaction: &TrollupTransaction,
+) -> Result<SimulationResult, String> {
+    let sanitized = state::transaction::convert_to_sanitized_transaction(transaction).map_err(|e| e.to_string())?;
+    if writes_metadata_account(&sanitized) {
+        return Err("Transaction writes a rollup metadata account".to_string());
+    }
 
-        let results = processor.load_and_execute_sanitized_transactions(
-            &account_loader,
-            &transactions,
-            get_transaction_check_results(transactions.len(), lamports_per_signature),
-            &processing_environment,
-            &processing_config,
-        );
+    let results = load_and_execute(account_loader, sysvars, std::slice::from_ref(&sanitized), &[transaction.system], ExecutionRecordingConfig::new_single_setting(true));
+    let mut accounts = match &results.loaded_transactions[0] {
+        Ok(loaded_tx) => extract_accounts(loaded_tx),
+        Err(_) => Vec::new(),
+    };
+    let settlement = settle_new_accounts(account_loader, &sanitized, &mut accounts);
+    Ok(match &results.execution_results[0] {
+        TransactionExecutionResult::Executed { details, .. } => SimulationResult {
+            err: details.status.as_ref().err().map(ToString::to_string).or(settlement.err().map(|error| error.to_string())),
+            logs: details.log_messages.clone().unwrap_or_default(),
+            units_consumed: details.executed_units,
+            return_data: details.return_data.as_ref().map(|return_data| base64::engine::general_purpose::STANDARD.encode(&return_data.data)),
+            accounts,
+        },
+        TransactionExecutionResult::NotExecuted(error) => SimulationResult {
+            err: Some(error.to_string()),
+            logs: Vec::new(),
+            units_consumed: 0,
+            return_data: None,
+            accounts: Vec::new(),
+        },
+    })
+}
 
-        results
+/// Metadata accounts are read-only, like sysvars on Solana. Transactions that write-lock one are
+/// skipped rather than executed.
+fn writes_metadata_account(transaction: &SanitizedTransaction) -> bool {
+    let message = transaction.message();
+    let writes_metadata = message
+        .account_keys()
+        .iter()
+        .enumerate()
+        .any(|(index, key)| message.is_writable(index) && RollupMetadata::is_metadata_account(key));
+    if writes_metadata {
+        warn!("Skipping transaction {} that writes a rollup metadata account", transaction.signature());
+    }
+    writes_metadata
+}
+
+/// The pre-state of the stored accounts `transactions` reference, for re-execution by a
+/// validator. Inclusion proofs are added by the committer once the batch is committed.
+pub fn witness_bundle<A: ManageState<Record=AccountState>>(
+    account_state_management: &StateManager<A>,
+    sysvars: &SysvarProvider,
+    transactions: &[TrollupTransaction],
+) -> WitnessBundle {
+    let mut seen = HashSet::new();
+    let accounts = transactions
+        .iter()
+        .flat_map(|tx| tx.message.account_keys.iter())
+        .filter(|key| seen.insert(**key) && !is_provided_account(&Pubkey::from(**key)))
+        .filter_map(|key| account_state_management.get_state_record(key))
+        .map(|account| AccountWitness { account, inclusion: None })
+        .collect();
+    WitnessBundle {
+        parent_state_root: None,
+        slot: sysvars.clock().slot,
+        unix_timestamp: sysvars.clock().unix_timestamp,
+        epoch_start_timestamp: sysvars.clock().epoch_start_timestamp,
+        rollup_metadata: sysvars.metadata().clone(),
+        transactions: transactions.to_vec(),
+        accounts,
     }
 }
 
+/// Executes `transactions` with the budgets `PROGRAM_LIMITS` gives them. The SVM takes one
+/// budget per call, so each run of consecutive transactions with the same budget is executed on
+/// its own, in order.
+fn load_and_execute<A: ManageState<Record=AccountState>>(
+    account_loader: &TrollupAccountLoader<A>,
+    sysvars: &SysvarProvider,
+    transactions: &[SanitizedTransaction],
+    fee_free: &[bool],
+    recording_config: ExecutionRecordingConfig,
+) -> LoadAndExecuteSanitizedTransactionsOutput {
+    let compute_budgets: Vec<ComputeBudget> = transactions.iter().map(|tx| PROGRAM_LIMITS.compute_budget(tx)).collect();
+    let mut start = 0;
+    let mut output: Option<LoadAndExecuteSanitizedTransactionsOutput> = None;
+    while start < transactions.len() || output.is_none() {
+        let end = start + compute_budgets[start..].iter().take_while(|budget| **budget == compute_budgets[start]).count();
+        let compute_budget = compute_budgets.get(start).copied().unwrap_or_default();
+        let run = load_and_execute_with_budget(account_loader, sysvars, &transactions[start..end], &fee_free[start..end], recording_config, compute_budget);
+        match output.as_mut() {
+            None => output = Some(run),
+            Some(output) => {
+                output.error_metrics.accumulate(&run.error_metrics);
+                output.execute_timings.accumulate(&run.execute_timings);
+                output.loaded_transactions.extend(run.loaded_transactions);
+                output.execution_results.extend(run.execution_results);
+            }
+        }
+        start = end;
+    }
+    output.expect("At least one run is executed")
+}
+
+fn load_and_execute_with_budget<A: ManageState<Record=AccountState>>(
+    account_loader: &TrollupAccountLoader<A>,
+    sysvars: &SysvarProvider,
+    transactions: &[SanitizedTransaction],
+    fee_free: &[bool],
+    recording_config: ExecutionRecordingConfig,
+    compute_budget: ComputeBudget,
+) -> LoadAndExecuteSanitizedTransactionsOutput {
+    let feature_set = FeatureSet::all_enabled();
+    // The governed fee schedule the batch is executed under, witnessed for the validator
+    let lamports_per_signature = sysvars.metadata().fee_schedule.lamports_per_signature;
+    let fee_structure = FeeStructure { lamports_per_signature, ..FeeStructure::default() };
+    let rent_collector = sysvars.rent_collector();
+
+    account_loader.add_accounts(sysvars.accounts());
+
+    let (processor, _fork_graph) =
+        create_transaction_batch_processor(account_loader, &feature_set, &compute_budget);
+    processor.fill_missing_sysvar_cache_entries(account_loader);
+
+    let processing_environment = TransactionProcessingEnvironment {
+        blockhash: Hash::default(),
+        epoch_total_stake: None,
+        epoch_vote_accounts: None,
+        feature_set: Arc::new(feature_set),
+        fee_structure: Some(&fee_structure),
+        lamports_per_signature,
+        rent_collector: Some(&rent_collector),
+    };
+
+    let processing_config = TransactionProcessingConfig {
+        compute_budget: Some(compute_budget),
+        recording_config,
+        ..Default::default()
+    };
+
+    let results = processor.load_and_execute_sanitized_transactions(
+        account_loader,
+        transactions,
+        get_transaction_check_results(fee_free, lamports_per_signature),
+        &processing_environment,
+        &processing_config,
+    );
+
+    results
+}
+
+/// Logs the cost model's estimate next to the compute units actually consumed, under the
+/// `cost_model` target, for calibrating the model parameters.
+/// Keeps bundles all-or-nothing. A bundle in `transactions` with a transaction that failed or
+/// wasn't executed is excluded: the outcomes of all its transactions are dropped, and they are
+/// removed from `witness` so a validator re-executes the same batch the block is built from.
+fn settle_bundles(transactions: &[TrollupTransaction], outcomes: Vec<ExecutionOutcome>, witness: &mut WitnessBundle) -> Vec<ExecutionOutcome> {
+    let errors: HashMap<[u8; 32], Option<String>> = outcomes
+        .iter()
+        .map(|outcome| (outcome.trollup_transaction.get_key(), outcome.error.clone()))
+        .collect();
+    let mut settled = HashSet::new();
+    let mut excluded: HashSet<[u8; 32]> = HashSet::new();
+    for transaction in transactions {
+        let Some((bundle_id, members)) = bundles::bundle_of(&transaction.get_key()) else {
+            continue;
+        };
+        if !settled.insert(bundle_id) {
+            continue;
+        }
+        let failure = members.iter().enumerate().find_map(|(position, key)| match errors.get(key) {
+            Some(None) => None,
+            Some(Some(error)) => Some(format!("Transaction {} of the bundle failed: {}", position + 1, error)),
+            None => Some(format!("Transaction {} of the bundle wasn't executed", position + 1)),
+        });
+        match failure {
+            Some(reason) => {
+                warn!("Excluding bundle {}: {}", hex::encode(bundle_id), reason);
+                bundles::exclude(&bundle_id, reason);
+                excluded.extend(members);
+            }
+            None => bundles::executed(&bundle_id),
+        }
+    }
+    if excluded.is_empty() {
+        return outcomes;
+    }
+    witness.transactions.retain(|transaction| !excluded.contains(&transaction.get_key()));
+    outcomes
+        .into_iter()
+        .filter(|outcome| !excluded.contains(&outcome.trollup_transaction.get_key()))
+        .collect()
+}
+
+fn record_cost(outcome: &ExecutionOutcome) {
+    info!(
+        target: "cost_model",
+        "transaction={} estimated_units={} consumed_units={}",
+        signature_string(&outcome.trollup_transaction),
+        COST_MODEL.estimate(&outcome.trollup_transaction),
+        outcome.compute_units,
+    );
+}
+
+fn signature_string(transaction: &TrollupTransaction) -> String {
+    solana_sdk::signature::Signature::from(transaction.signatures.first().copied().unwrap_or([0u8; 64])).to_string()
+}
+
 pub fn batch_sanitize_transactions(transactions: &Vec<TrollupTransaction>) -> Vec<SanitizedTransaction> {
     transactions
         .into_iter()
         .filter_map(|tx| {
             state::transaction::convert_to_sanitized_transaction(tx)
                 .map_err(|e| {
-                    eprintln!("Failed to sanitize transaction: {:?}", e);
+                    warn!("Failed to sanitize transaction: {:?}", e);
                     e
                 })
                 .ok()
@@ -225,25 +568,71 @@ pub fn batch_sanitize_transactions(transactions: &Vec<TrollupTransaction>) -> Ve
         .collect()
 }
 
-struct ExecutionOutcome {
-    trollup_transaction: TrollupTransaction,
-    accounts: Vec<AccountState>,
+pub struct ExecutionOutcome {
+    pub trollup_transaction: TrollupTransaction,
+    /// Every account the transaction loaded, sysvars excluded, as left by the batch
+    pub accounts: Vec<AccountState>,
+    /// Compute units the transaction consumed
+    pub compute_units: u64,
+    /// Accounts each instruction may have written, in instruction order
+    pub instruction_write_sets: Vec<InstructionWriteSet>,
+    /// Lamports charged to the fee payer: the transaction fee plus the storage price of the
+    /// accounts it created
+    pub fee: u64,
+    /// Why the transaction failed, its fee is charged all the same
+    pub error: Option<String>,
+}
+
+impl ExecutionOutcome {
+    pub fn receipt(&self) -> TransactionReceipt {
+        TransactionReceipt {
+            transaction_id: self.trollup_transaction.get_key(),
+            compute_units: self.compute_units,
+            system: self.trollup_transaction.system,
+            instruction_write_sets: self.instruction_write_sets.clone(),
+            fee: self.fee,
+        }
+    }
 }
 
-fn extract_successful_transactions(
-    tx_map: HashMap<[u8; 32], TrollupTransaction>,
+/// `transactions` and `sanitized_txs` must be in the order they were passed to the SVM.
+/// Transactions the SVM couldn't load are dropped and counted by cause, see `load_failures`.
+fn extract_successful_transactions<A: ManageState<Record=AccountState>>(
+    account_loader: &TrollupAccountLoader<A>,
+    transactions: Vec<TrollupTransaction>,
+    sanitized_txs: &[SanitizedTransaction],
     loaded_txs: &[TransactionLoadResult],
     exec_results: &[TransactionExecutionResult],
 ) -> Vec<ExecutionOutcome> {
     let mut execution_outcomes = Vec::new();
-    for (i, (_key, value)) in tx_map.iter().enumerate() {
-        let loaded_tx = &loaded_txs[i].clone().unwrap();
+    for (i, value) in transactions.iter().enumerate() {
+        let loaded_tx = match &loaded_txs[i] {
+            Ok(loaded_tx) => loaded_tx,
+            Err(error) => {
+                let cause = load_failures::classify(error);
+                warn!("Dropping transaction {} that failed to load, {}: {}", signature_string(value), cause, error);
+                load_failures::record(value.get_key(), cause);
+                continue;
+            }
+        };
         let x1 = &exec_results[i];
         match x1 {
-            TransactionExecutionResult::Executed { .. } => {
+            TransactionExecutionResult::Executed { details, .. } => {
+                let mut accounts = extract_accounts(&loaded_tx.clone());
+                let storage_fee = match settle_new_accounts(account_loader, &sanitized_txs[i], &mut accounts) {
+                    Ok(storage_fee) => storage_fee,
+                    Err(error) => {
+                        warn!("Dropping transaction {}: {}", signature_string(value), error);
+                        continue;
+                    }
+                };
                 execution_outcomes.push(ExecutionOutcome {
                     trollup_transaction: value.clone(),
-                    accounts: extract_accounts(&loaded_tx.clone()),
+                    instruction_write_sets: instruction_write_sets(account_loader, &sanitized_txs[i], &accounts),
+                    accounts,
+                    compute_units: details.executed_units,
+                    fee: loaded_tx.fee_details.total_fee() + storage_fee,
+                    error: details.status.as_ref().err().map(ToString::to_string),
                 });
             }
             TransactionExecutionResult::NotExecuted(_) => {}
@@ -252,20 +641,157 @@ fn extract_successful_transactions(
     execution_outcomes
 }
 
+/// Charges the fee payer for the accounts the transaction created, see `account_creation::settle`.
+/// The loader serves accounts as they were before the batch, see `ACCOUNT_LOADER_STRICTNESS` for
+/// how it treats unknown ones.
+fn settle_new_accounts<A: ManageState<Record=AccountState>>(
+    account_loader: &TrollupAccountLoader<A>,
+    transaction: &SanitizedTransaction,
+    accounts: &mut [AccountState],
+) -> Result<u64, AccountCreationError> {
+    account_creation::settle(
+        transaction,
+        accounts,
+        |address| account_loader.get_account_shared_data(address).is_some(),
+        &CREATION_PRICING,
+        CONFIG.dev_mode,
+    )
+}
+
+fn is_provided_account(address: &Pubkey) -> bool {
+    sysvar::is_sysvar_id(address) || RollupMetadata::is_metadata_account(address)
+}
+
+/// Attributes the accounts `transaction` changed to the instructions that were passed them as
+/// writable. `accounts` are the transaction's loaded accounts after execution; the loader still
+/// serves them as they were before, it is never updated with execution results.
+fn instruction_write_sets<A: ManageState<Record=AccountState>>(
+    account_loader: &TrollupAccountLoader<A>,
+    transaction: &SanitizedTransaction,
+    accounts: &[AccountState],
+) -> Vec<InstructionWriteSet> {
+    let changed: HashSet<Pubkey> = accounts
+        .iter()
+        .filter(|account| {
+            account_loader
+                .get_account_shared_data(&account.address)
+                .map_or(true, |before| {
+                    before.lamports() != account.lamports
+                        || before.data() != account.data.as_slice()
+                        || before.owner() != &account.owner
+                        || before.executable() != account.executable
+                })
+        })
+        .map(|account| account.address)
+        .collect();
+
+    let message = transaction.message();
+    let account_keys = message.account_keys();
+    message
+        .instructions()
+        .iter()
+        .enumerate()
+        .map(|(index, instruction)| {
+            let mut written: Vec<Pubkey> = Vec::new();
+            for account_index in instruction.accounts.iter().map(|index| *index as usize) {
+                let Some(key) = account_keys.get(account_index) else {
+                    continue;
+                };
+                if message.is_writable(account_index) && changed.contains(key) && !written.contains(key) {
+                    written.push(*key);
+                }
+            }
+            InstructionWriteSet {
+                instruction_index: index as u8,
+                program_id: account_keys.get(instruction.program_id_index as usize).copied().unwrap_or_default(),
+                accounts: written,
+            }
+        })
+        .collect()
+}
+
 fn extract_accounts(loaded_tx: &LoadedTransaction) -> Vec<AccountState> {
     loaded_tx.accounts
         .iter()
+        // Sysvars and rollup metadata are provided per block and never stored as rollup accounts
+        .filter(|account| !is_provided_account(&account.0))
         .map(|account| {
-            AccountState {
+            let mut account_state = AccountState {
                 address: Pubkey::from(account.0.to_bytes()),
                 lamports: account.1.lamports(),
                 data: account.1.data().to_vec(),
                 owner: *account.1.owner(),
                 executable: account.1.executable(),
                 rent_epoch: account.1.rent_epoch(),
-            }
+                data_root: None,
+            };
+            account_state.commit_data(CONFIG.account_data_commitment_min_bytes);
+            account_state
         })
         .collect()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signer;
+    use state::rollup_metadata::FeeSchedule;
+    use state::test_fixtures::{funded_accounts, keypair, transfer_transaction, FUNDED_LAMPORTS};
+    use state_management::sled_state_management::SledStateManagement;
+
+    const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+    /// Executes a transfer between two funded accounts with a fee schedule, from the system lane
+    /// when `system` is set.
+    fn execute_transfer(system: bool) -> (ExecutionOutcome, [u8; 32]) {
+        let (payer, recipient) = (keypair("system-lane/payer"), keypair("system-lane/recipient"));
+        let accounts = StateManager::<SledStateManagement<AccountState>>::new("");
+        accounts.set_state_records(&funded_accounts(&[payer.insecure_clone(), recipient.insecure_clone()]));
+        let mut sysvars = SysvarProvider::from_config(&TrollupConfig::build().unwrap(), 1);
+        sysvars.set_metadata(RollupMetadata {
+            fee_schedule: FeeSchedule { lamports_per_signature: LAMPORTS_PER_SIGNATURE, ..FeeSchedule::default() },
+            ..RollupMetadata::default()
+        });
+        let transaction = TrollupTransaction { system, ..TrollupTransaction::from(&transfer_transaction(&payer, &recipient.pubkey(), 10, Hash::default())) };
+
+        let mut outcomes = execute_batch(&TrollupAccountLoader::new(&accounts), &sysvars, &[transaction]);
+        assert_eq!(outcomes.len(), 1);
+        (outcomes.remove(0), payer.pubkey().to_bytes())
+    }
+
+    fn balance(outcome: &ExecutionOutcome, address: &[u8; 32]) -> u64 {
+        outcome.accounts.iter().find(|account| account.address.to_bytes() == *address).unwrap().lamports
+    }
+
+    #[test]
+    fn test_system_transactions_execute_without_fees() {
+        let (user, payer) = execute_transfer(false);
+        assert_eq!(user.error, None);
+        assert_eq!(user.fee, LAMPORTS_PER_SIGNATURE);
+        assert_eq!(balance(&user, &payer), FUNDED_LAMPORTS - 10 - LAMPORTS_PER_SIGNATURE);
+        assert!(!user.receipt().system);
+
+        let (system, payer) = execute_transfer(true);
+        assert_eq!(system.error, None);
+        assert_eq!(system.fee, 0);
+        assert_eq!(balance(&system, &payer), FUNDED_LAMPORTS - 10);
+        assert!(system.receipt().system);
+    }
 
+    #[test]
+    fn test_requeued_system_transactions_return_to_their_lane() {
+        let payer = keypair("system-lane/payer");
+        let transfer = |lamports| TrollupTransaction::from(&transfer_transaction(&payer, &Pubkey::new_unique(), lamports, Hash::default()));
+        let mut pool = TransactionPool::new().with_system_lane_capacity(1);
+        pool.add_transaction(transfer(1));
+        pool.add_system_transaction(transfer(2), "").unwrap();
+        assert!(pool.add_system_transaction(transfer(3), "").is_err());
+
+        let batch = pool.take_ordered(&ordering::Fifo, 2);
+        assert!(batch[0].system && !batch[1].system);
+        pool.requeue_transactions(batch);
+        assert_eq!(pool.system_lane_size(), 1);
+        assert!(pool.get_next_transaction().unwrap().system);
+        assert!(!pool.get_next_transaction().unwrap().system);
+    }
+}
@@ -1,3 +1,11 @@
 pub mod transaction_pool;
 pub mod execution_engine;
 pub mod processor;
+pub mod sysvars;
+pub mod signature_verification;
+pub mod cost_model;
+pub mod ordering;pub mod account_creation;
+pub mod block_production;
+pub mod replay;
+pub mod program_limits;
+pub mod outbox_program;
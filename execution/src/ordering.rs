@@ -0,0 +1,234 @@
+use solana_sdk::compute_budget;
+use solana_sdk::pubkey::Pubkey;
+use state::config::OrderingPolicyKind;
+use state::transaction::TrollupTransaction;
+use std::collections::HashMap;
+
+/// `ComputeBudgetInstruction::SetComputeUnitPrice` tag, followed by the price as a LE u64.
+const SET_COMPUTE_UNIT_PRICE: u8 = 3;
+
+/// Decides which pooled transactions the engine executes next and in what order.
+///
+/// Implementations must be deterministic: the same snapshot and limit always give the same
+/// order, so a block can be reproduced from the pool it was drained from. The system lane is
+/// drained before the policy is consulted and never reordered.
+pub trait OrderingPolicy: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Indices into `pending`, oldest first, of at most `limit` transactions in execution order.
+    /// Indices out of range or repeated are ignored.
+    fn select(&self, pending: &[&TrollupTransaction], limit: usize) -> Vec<usize>;
+}
+
+/// Arrival order.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fifo;
+
+impl OrderingPolicy for Fifo {
+    fn name(&self) -> &str {
+        "fifo"
+    }
+
+    fn select(&self, pending: &[&TrollupTransaction], limit: usize) -> Vec<usize> {
+        (0..pending.len().min(limit)).collect()
+    }
+}
+
+/// Highest compute unit price first, arrival order between equal prices.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FeePriority;
+
+impl OrderingPolicy for FeePriority {
+    fn name(&self) -> &str {
+        "fee-priority"
+    }
+
+    fn select(&self, pending: &[&TrollupTransaction], limit: usize) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..pending.len()).collect();
+        // Stable, so equal prices keep their arrival order
+        indices.sort_by_key(|index| std::cmp::Reverse(compute_unit_price(pending[*index])));
+        indices.truncate(limit);
+        indices
+    }
+}
+
+/// One transaction per fee payer in turn, payers in the order their first transaction arrived,
+/// so a single payer flooding the pool can't take a whole batch.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RoundRobinByPayer;
+
+impl OrderingPolicy for RoundRobinByPayer {
+    fn name(&self) -> &str {
+        "round-robin"
+    }
+
+    fn select(&self, pending: &[&TrollupTransaction], limit: usize) -> Vec<usize> {
+        let mut payers: Vec<Vec<usize>> = Vec::new();
+        let mut payer_slots: HashMap<Option<[u8; 32]>, usize> = HashMap::new();
+        for (index, transaction) in pending.iter().enumerate() {
+            let payer = transaction.message.account_keys.first().copied();
+            let slot = *payer_slots.entry(payer).or_insert_with(|| {
+                payers.push(Vec::new());
+                payers.len() - 1
+            });
+            payers[slot].push(index);
+        }
+
+        let mut selected = Vec::with_capacity(pending.len().min(limit));
+        let mut round = 0;
+        while selected.len() < limit {
+            let before = selected.len();
+            selected.extend(payers.iter().filter_map(|indices| indices.get(round)).take(limit - before));
+            if selected.len() == before {
+                break;
+            }
+            round += 1;
+        }
+        selected
+    }
+}
+
+/// The built-in policy `ORDERING_POLICY` selects.
+pub fn builtin(kind: OrderingPolicyKind) -> Box<dyn OrderingPolicy> {
+    match kind {
+        OrderingPolicyKind::Fifo => Box::new(Fifo),
+        OrderingPolicyKind::FeePriority => Box::new(FeePriority),
+        OrderingPolicyKind::RoundRobin => Box::new(RoundRobinByPayer),
+    }
+}
+
+/// Micro-lamports per compute unit the transaction offers through the compute budget program,
+/// 0 when it doesn't set a price.
+pub fn compute_unit_price(transaction: &TrollupTransaction) -> u64 {
+    let message = &transaction.message;
+    message.instructions
+        .iter()
+        .filter(|instruction| {
+            message.account_keys
+                .get(instruction.program_id_index as usize)
+                .is_some_and(|program_id| Pubkey::from(*program_id) == compute_budget::id())
+        })
+        .filter_map(|instruction| match instruction.data.as_slice() {
+            [SET_COMPUTE_UNIT_PRICE, price @ ..] => price.try_into().ok().map(u64::from_le_bytes),
+            _ => None,
+        })
+        .last()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction_pool::TransactionPool;
+    use solana_sdk::compute_budget::ComputeBudgetInstruction;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::Transaction;
+    use state::state_record::StateRecord;
+    use state::test_fixtures::keypair;
+
+    fn transaction(payer: &Keypair, lamports: u64, price: Option<u64>) -> TrollupTransaction {
+        let mut instructions = vec![system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), lamports)];
+        if let Some(price) = price {
+            instructions.insert(0, ComputeBudgetInstruction::set_compute_unit_price(price));
+        }
+        let transaction = Transaction::new_signed_with_payer(&instructions, Some(&payer.pubkey()), &[payer], Default::default());
+        TrollupTransaction::from(&transaction)
+    }
+
+    fn snapshot() -> Vec<TrollupTransaction> {
+        let (alice, bob, carol) = (keypair("alice"), keypair("bob"), keypair("carol"));
+        vec![
+            transaction(&alice, 1, None),
+            transaction(&alice, 2, Some(10)),
+            transaction(&alice, 3, Some(50)),
+            transaction(&bob, 4, Some(50)),
+            transaction(&carol, 5, Some(5)),
+            transaction(&bob, 6, None),
+        ]
+    }
+
+    #[test]
+    fn test_fifo_keeps_arrival_order() {
+        let pending = snapshot();
+        let pending: Vec<&TrollupTransaction> = pending.iter().collect();
+        assert_eq!(Fifo.select(&pending, 4), vec![0, 1, 2, 3]);
+        assert_eq!(Fifo.select(&pending, 10), vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_fee_priority_orders_by_price_then_arrival() {
+        let pending = snapshot();
+        let pending: Vec<&TrollupTransaction> = pending.iter().collect();
+        assert_eq!(compute_unit_price(pending[2]), 50);
+        assert_eq!(FeePriority.select(&pending, 10), vec![2, 3, 1, 4, 0, 5]);
+        assert_eq!(FeePriority.select(&pending, 3), vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn test_round_robin_alternates_payers() {
+        let pending = snapshot();
+        let pending: Vec<&TrollupTransaction> = pending.iter().collect();
+        assert_eq!(RoundRobinByPayer.select(&pending, 10), vec![0, 3, 4, 1, 5, 2]);
+        assert_eq!(RoundRobinByPayer.select(&pending, 4), vec![0, 3, 4, 1]);
+    }
+
+    #[test]
+    fn test_policies_are_deterministic() {
+        let pending = snapshot();
+        let pending: Vec<&TrollupTransaction> = pending.iter().collect();
+        for kind in [OrderingPolicyKind::Fifo, OrderingPolicyKind::FeePriority, OrderingPolicyKind::RoundRobin] {
+            let policy = builtin(kind);
+            assert_eq!(policy.select(&pending, 5), policy.select(&pending, 5), "policy {}", policy.name());
+        }
+    }
+
+    #[test]
+    fn test_pool_drains_system_lane_then_policy_order() {
+        let transactions = snapshot();
+        let system = transaction(&keypair("system"), 7, None);
+        let drain = || {
+            let mut pool = TransactionPool::new();
+            for transaction in &transactions {
+                pool.add_transaction(transaction.clone());
+            }
+            pool.add_system_transaction(system.clone(), "").unwrap();
+            let batch = pool.take_ordered(&FeePriority, 4);
+            (batch, pool.pool_size())
+        };
+
+        let (batch, remaining) = drain();
+        let keys: Vec<[u8; 32]> = batch.iter().map(StateRecord::get_key).collect();
+        let expected: Vec<[u8; 32]> = [&system, &transactions[2], &transactions[3], &transactions[1]]
+            .into_iter()
+            .map(StateRecord::get_key)
+            .collect();
+        assert_eq!(keys, expected);
+        assert!(batch[0].system);
+        assert_eq!(remaining, 3);
+        assert_eq!(drain().0.iter().map(StateRecord::get_key).collect::<Vec<_>>(), keys);
+    }
+
+    #[test]
+    fn test_pool_takes_bundles_whole() {
+        let (alice, bob) = (keypair("alice"), keypair("bob"));
+        let mut pool = TransactionPool::new();
+        pool.add_transaction(transaction(&alice, 1, Some(1)));
+        let bundle = vec![transaction(&bob, 2, None), transaction(&bob, 3, Some(100))];
+        let pricier = transaction(&alice, 4, Some(50));
+        pool.add_bundle(bundle.clone(), "").unwrap();
+        pool.add_transaction(pricier.clone());
+
+        // The bundle's second transaction pays the most, the first comes along ahead of it
+        let batch = pool.take_ordered(&FeePriority, 3);
+        let keys: Vec<[u8; 32]> = batch.iter().map(StateRecord::get_key).collect();
+        assert_eq!(keys, vec![bundle[0].get_key(), bundle[1].get_key(), pricier.get_key()]);
+        assert_eq!(pool.pool_size(), 1);
+
+        // A bundle that doesn't fit in the batch stays in the pool
+        let mut pool = TransactionPool::new();
+        pool.add_bundle(vec![transaction(&bob, 5, Some(100)), transaction(&bob, 6, Some(100))], "").unwrap();
+        assert!(pool.take_ordered(&FeePriority, 1).is_empty());
+        assert_eq!(pool.take_ordered(&FeePriority, 2).len(), 2);
+    }
+}
@@ -0,0 +1,137 @@
+//! The outbox builtin at `outbox::id()`. It writes a cross-rollup message into a message account
+//! the same transaction created for it, which is how messages enter a block's outbox, see
+//! `state::cross_rollup::outbox_messages`. Only the owner may write an account's data, so the
+//! runtime already limits the builtin to accounts assigned to the outbox.
+
+use borsh::BorshDeserialize;
+use solana_program_runtime::declare_process_instruction;
+use solana_sdk::instruction::InstructionError;
+use state::cross_rollup::{outbox, OutboxInstruction};
+
+/// Compute units of a message, the same as a system program instruction.
+pub const DEFAULT_COMPUTE_UNITS: u64 = 150;
+
+declare_process_instruction!(Entrypoint, DEFAULT_COMPUTE_UNITS, |invoke_context| {
+    let transaction_context = &invoke_context.transaction_context;
+    let instruction_context = transaction_context.get_current_instruction_context()?;
+    let OutboxInstruction::Send(message) = OutboxInstruction::try_from_slice(instruction_context.get_instruction_data())
+        .map_err(|_| InstructionError::InvalidInstructionData)?;
+    if message.destination.is_empty() {
+        return Err(InstructionError::InvalidInstructionData);
+    }
+
+    let mut account = instruction_context.try_borrow_instruction_account(transaction_context, 0)?;
+    if !account.is_signer() {
+        return Err(InstructionError::MissingRequiredSignature);
+    }
+    if *account.get_owner() != outbox::id() {
+        return Err(InstructionError::InvalidAccountOwner);
+    }
+    if account.get_data().iter().any(|byte| *byte != 0) {
+        return Err(InstructionError::AccountAlreadyInitialized);
+    }
+    let data = borsh::to_vec(&message).map_err(|_| InstructionError::InvalidInstructionData)?;
+    if account.get_data().len() != data.len() {
+        return Err(InstructionError::InvalidAccountData);
+    }
+    account.set_data_from_slice(&data)
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::execution_engine::{execute_batch, ExecutionOutcome};
+    use crate::sysvars::SysvarProvider;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::Instruction;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::transaction::Transaction;
+    use state::account_state::AccountState;
+    use state::config::TrollupConfig;
+    use state::cross_rollup::{outbox, outbox_messages, send_message, CrossRollupMessage};
+    use state::test_fixtures::{funded_accounts, keypair};
+    use state::transaction::TrollupTransaction;
+    use state_management::account_loader::TrollupAccountLoader;
+    use state_management::sled_state_management::SledStateManagement;
+    use state_management::state_management::{ManageState, StateManager};
+
+    fn message() -> CrossRollupMessage {
+        CrossRollupMessage { destination: "destination".to_string(), recipient: Pubkey::new_unique(), payload: b"hello".to_vec() }
+    }
+
+    /// Executes `instructions` paid for by `sender` and signed by `signers` as well, against a
+    /// store holding the funded sender.
+    fn execute(sender: &Keypair, signers: &[&Keypair], instructions: &[Instruction]) -> (ExecutionOutcome, StateManager<SledStateManagement<AccountState>>) {
+        let accounts = StateManager::<SledStateManagement<AccountState>>::new("");
+        accounts.set_state_records(&funded_accounts(std::slice::from_ref(sender)));
+        let mut all_signers = vec![sender];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(instructions, Some(&sender.pubkey()), &all_signers, Hash::default());
+        let sysvars = SysvarProvider::from_config(&TrollupConfig::build().unwrap(), 1);
+        let mut outcomes = execute_batch(&TrollupAccountLoader::new(&accounts), &sysvars, &[TrollupTransaction::from(&transaction)]);
+        assert_eq!(outcomes.len(), 1);
+        (outcomes.remove(0), accounts)
+    }
+
+    fn rent_exempt(message: &CrossRollupMessage) -> u64 {
+        SysvarProvider::from_config(&TrollupConfig::build().unwrap(), 1).rent().minimum_balance(borsh::to_vec(message).unwrap().len())
+    }
+
+    #[test]
+    fn test_sent_message_is_in_the_outbox() {
+        let sender = keypair("outbox/sender");
+        let message_account = keypair("outbox/message");
+        let message = message();
+        let lamports = rent_exempt(&message);
+
+        let (outcome, accounts) = execute(&sender, &[&message_account], &send_message(&sender.pubkey(), &message_account.pubkey(), lamports, &message));
+
+        assert_eq!(outcome.error, None);
+        let written = outcome.accounts.iter().find(|account| account.address == message_account.pubkey()).unwrap();
+        assert_eq!(written.owner, outbox::id());
+        assert_eq!(borsh::from_slice::<CrossRollupMessage>(&written.data).unwrap(), message);
+        let sent = outbox_messages(&outcome.accounts, |address| accounts.get_state_record(&address.to_bytes()));
+        assert_eq!(sent.len(), 1);
+        assert_eq!((sent[0].address, sent[0].lamports), (message_account.pubkey(), lamports));
+        assert_eq!((&sent[0].destination, sent[0].recipient, &sent[0].payload), (&message.destination, message.recipient, &message.payload));
+    }
+
+    #[test]
+    fn test_message_is_written_once() {
+        let sender = keypair("outbox/sender");
+        let message_account = keypair("outbox/message");
+        let message = message();
+        let mut instructions = send_message(&sender.pubkey(), &message_account.pubkey(), rent_exempt(&message), &message);
+        instructions.push(instructions[1].clone());
+
+        let (outcome, _) = execute(&sender, &[&message_account], &instructions);
+
+        assert!(outcome.error.unwrap().contains("requires an uninitialized account"));
+    }
+
+    #[test]
+    fn test_message_must_fill_the_account() {
+        let sender = keypair("outbox/sender");
+        let message_account = keypair("outbox/message");
+        let message = message();
+        let mut longer = message.clone();
+        longer.payload.push(0);
+        let mut instructions = send_message(&sender.pubkey(), &message_account.pubkey(), rent_exempt(&longer), &longer);
+        instructions[1] = send_message(&sender.pubkey(), &message_account.pubkey(), 0, &message).remove(1);
+
+        let (outcome, _) = execute(&sender, &[&message_account], &instructions);
+
+        assert!(outcome.error.unwrap().contains("invalid account data"));
+    }
+
+    #[test]
+    fn test_message_needs_a_destination() {
+        let sender = keypair("outbox/sender");
+        let message_account = keypair("outbox/message");
+        let message = CrossRollupMessage { destination: String::new(), ..message() };
+
+        let (outcome, _) = execute(&sender, &[&message_account], &send_message(&sender.pubkey(), &message_account.pubkey(), rent_exempt(&message), &message));
+
+        assert!(outcome.error.unwrap().contains("invalid instruction data"));
+    }
+}
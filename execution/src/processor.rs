@@ -1,4 +1,5 @@
 use {
+    crate::outbox_program,
     solana_bpf_loader_program::syscalls::create_program_runtime_environment_v1,
     solana_compute_budget::compute_budget::ComputeBudget,
     solana_program_runtime::loaded_programs::{
@@ -11,6 +12,7 @@ use {
         transaction_processor::TransactionBatchProcessor,
     },
     solana_system_program::system_processor,
+    state::cross_rollup::outbox,
     std::sync::{Arc, RwLock},
 };
 
@@ -99,18 +101,32 @@ pub(crate) fn create_transaction_batch_processor<CB: TransactionProcessingCallba
         ),
     );
 
+    // Add the outbox builtin, for cross-rollup messages.
+    processor.add_builtin(
+        callbacks,
+        outbox::id(),
+        "trollup_outbox_program",
+        ProgramCacheEntry::new_builtin(
+            0,
+            b"trollup_outbox_program".len(),
+            outbox_program::Entrypoint::vm,
+        ),
+    );
+
     (processor, fork_graph)
 }
 
+/// One check result per transaction. Fee-free transactions are charged nothing, the SVM skips
+/// the fee when `lamports_per_signature` is zero.
 pub(crate) fn get_transaction_check_results(
-    len: usize,
+    fee_free: &[bool],
     lamports_per_signature: u64,
 ) -> Vec<transaction::Result<CheckedTransactionDetails>> {
-    vec![
-        Ok(CheckedTransactionDetails {
+    fee_free
+        .iter()
+        .map(|fee_free| Ok(CheckedTransactionDetails {
             nonce: None,
-            lamports_per_signature,
-        });
-        len
-    ]
+            lamports_per_signature: if *fee_free { 0 } else { lamports_per_signature },
+        }))
+        .collect()
 }
\ No newline at end of file
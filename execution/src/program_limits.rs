@@ -0,0 +1,104 @@
+use solana_compute_budget::compute_budget::ComputeBudget;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::SanitizedTransaction;
+use state::config::TrollupConfig;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Smallest and largest heap a transaction can be given, as on Solana.
+const MIN_HEAP_FRAME_BYTES: u32 = 32 * 1024;
+const MAX_HEAP_FRAME_BYTES: u32 = 256 * 1024;
+
+/// Per program overrides of the compute budget, so one program can't take a batch's compute.
+///
+/// A transaction runs with the default budget, 1.4M compute units and a 32 KiB heap, unless it
+/// invokes a program with an override. Then it gets the lowest compute unit limit and the
+/// smallest heap among the overrides of the programs it invokes.
+#[derive(Clone, Debug, Default)]
+pub struct ProgramLimits {
+    pub compute_unit_limits: HashMap<Pubkey, u64>,
+    /// Bytes, a multiple of 1 KiB between 32 KiB and 256 KiB
+    pub heap_sizes: HashMap<Pubkey, u32>,
+}
+
+impl ProgramLimits {
+    /// `PROGRAM_COMPUTE_UNIT_LIMITS` and `PROGRAM_HEAP_SIZES`, keyed by program ID. Invalid program
+    /// IDs are skipped, heap sizes are rounded down to a KiB and clamped to what the SVM supports.
+    pub fn from_config(config: &TrollupConfig) -> Self {
+        ProgramLimits {
+            compute_unit_limits: config.program_compute_unit_limits
+                .iter()
+                .filter_map(|(program_id, units)| Pubkey::from_str(program_id).ok().map(|program_id| (program_id, *units)))
+                .collect(),
+            heap_sizes: config.program_heap_sizes
+                .iter()
+                .filter_map(|(program_id, bytes)| Pubkey::from_str(program_id).ok().map(|program_id| (program_id, heap_frame(*bytes))))
+                .collect(),
+        }
+    }
+
+    /// The budget `transaction` executes with.
+    pub fn compute_budget(&self, transaction: &SanitizedTransaction) -> ComputeBudget {
+        let mut compute_budget = ComputeBudget::default();
+        let mut heap_size = None;
+        for (program_id, _) in transaction.message().program_instructions_iter() {
+            if let Some(units) = self.compute_unit_limits.get(program_id) {
+                compute_budget.compute_unit_limit = compute_budget.compute_unit_limit.min(*units);
+            }
+            if let Some(bytes) = self.heap_sizes.get(program_id) {
+                heap_size = Some(heap_size.map_or(*bytes, |size: u32| size.min(*bytes)));
+            }
+        }
+        if let Some(heap_size) = heap_size {
+            compute_budget.heap_size = heap_size;
+        }
+        compute_budget
+    }
+}
+
+fn heap_frame(bytes: u32) -> u32 {
+    (bytes / 1024 * 1024).clamp(MIN_HEAP_FRAME_BYTES, MAX_HEAP_FRAME_BYTES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::Instruction;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::transaction::Transaction;
+    use std::collections::HashSet;
+
+    fn invoking(programs: &[Pubkey]) -> SanitizedTransaction {
+        let payer = Keypair::new();
+        let instructions: Vec<Instruction> = programs
+            .iter()
+            .map(|program_id| Instruction::new_with_bytes(*program_id, &[], vec![]))
+            .collect();
+        let transaction = Transaction::new_signed_with_payer(&instructions, Some(&payer.pubkey()), &[&payer], Hash::default());
+        SanitizedTransaction::try_from_legacy_transaction(transaction, &HashSet::new()).unwrap()
+    }
+
+    #[test]
+    fn test_strictest_override_applies() {
+        let (greedy, heavy, other) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let limits = ProgramLimits {
+            compute_unit_limits: HashMap::from([(greedy, 50_000), (heavy, 400_000)]),
+            heap_sizes: HashMap::from([(heavy, heap_frame(128 * 1024)), (greedy, heap_frame(64 * 1024 + 100))]),
+        };
+
+        let unlisted = limits.compute_budget(&invoking(&[other]));
+        assert_eq!(unlisted, ComputeBudget::default());
+        let heavy_only = limits.compute_budget(&invoking(&[heavy, other]));
+        assert_eq!((heavy_only.compute_unit_limit, heavy_only.heap_size), (400_000, 128 * 1024));
+        let both = limits.compute_budget(&invoking(&[heavy, greedy]));
+        assert_eq!((both.compute_unit_limit, both.heap_size), (50_000, 64 * 1024));
+    }
+
+    #[test]
+    fn test_heap_sizes_are_clamped() {
+        assert_eq!(heap_frame(1), MIN_HEAP_FRAME_BYTES);
+        assert_eq!(heap_frame(u32::MAX), MAX_HEAP_FRAME_BYTES);
+        assert_eq!(heap_frame(100 * 1024 + 1023), 100 * 1024);
+    }
+}
@@ -0,0 +1,25 @@
+use crate::execution_engine::execute_batch;
+use crate::sysvars::SysvarProvider;
+use state::account_state::AccountState;
+use state::config::TrollupConfig;
+use state::witness::WitnessBundle;
+use state_management::account_loader::TrollupAccountLoader;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::{ManageState, StateManager};
+
+/// Re-executes the batch in `witness` with the clock and metadata it was executed with and
+/// returns the post-state, in the order the block's account tree is built from. The witnessed
+/// accounts are the whole account store as far as the batch is concerned.
+pub fn replay_witness(config: &TrollupConfig, witness: &WitnessBundle) -> Vec<AccountState> {
+    let pre_state = StateManager::<SledStateManagement<AccountState>>::new("");
+    pre_state.set_state_records(&witness.accounts.iter().map(|account_witness| account_witness.account.clone()).collect());
+    let account_loader = TrollupAccountLoader::new(&pre_state);
+    let mut sysvars = SysvarProvider::from_config(config, witness.slot);
+    sysvars.restore_clock(witness.slot, witness.unix_timestamp, witness.epoch_start_timestamp);
+    sysvars.set_metadata(witness.rollup_metadata.clone());
+
+    execute_batch(&account_loader, &sysvars, &witness.transactions)
+        .into_iter()
+        .flat_map(|outcome| outcome.accounts)
+        .collect()
+}
@@ -0,0 +1,150 @@
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use log::warn;
+use state::transaction::{convert_to_solana_transaction, TrollupTransaction};
+
+/// The message bytes and the signer keys of a transaction, in signature order.
+struct SignedMessage {
+    message: Vec<u8>,
+    public_keys: Vec<PublicKey>,
+    signatures: Vec<Signature>,
+}
+
+impl SignedMessage {
+    /// `None` when the signatures can't possibly be valid, e.g. a signature is missing or a
+    /// signer key isn't a curve point.
+    fn new(transaction: &TrollupTransaction) -> Option<Self> {
+        let required_signatures = transaction.message.header[0] as usize;
+        if transaction.signatures.len() != required_signatures || transaction.message.account_keys.len() < required_signatures {
+            return None;
+        }
+        let public_keys = transaction.message.account_keys[..required_signatures]
+            .iter()
+            .map(|key| PublicKey::from_bytes(key).ok())
+            .collect::<Option<Vec<_>>>()?;
+        let signatures = transaction.signatures
+            .iter()
+            .map(|signature| Signature::try_from(&signature[..]).ok())
+            .collect::<Option<Vec<_>>>()?;
+        let message = convert_to_solana_transaction(transaction.clone()).ok()?.message_data();
+        Some(SignedMessage { message, public_keys, signatures })
+    }
+
+    fn verify(&self) -> bool {
+        self.public_keys
+            .iter()
+            .zip(&self.signatures)
+            .all(|(public_key, signature)| public_key.verify(&self.message, signature).is_ok())
+    }
+}
+
+/// Drops the transactions that aren't signed by all of their signers.
+///
+/// All signatures are checked with a single ed25519 batch verification. Only when the batch
+/// fails is every transaction verified on its own, to find the ones that caused it.
+pub fn retain_valid_signatures(transactions: Vec<TrollupTransaction>) -> Vec<TrollupTransaction> {
+    let signed_messages: Vec<Option<SignedMessage>> = transactions.iter().map(SignedMessage::new).collect();
+
+    let well_formed = signed_messages.iter().all(Option::is_some);
+    if well_formed && verify_batch(signed_messages.iter().flatten()) {
+        return transactions;
+    }
+
+    transactions
+        .into_iter()
+        .zip(signed_messages)
+        .filter_map(|(transaction, signed_message)| match signed_message {
+            Some(signed_message) if signed_message.verify() => Some(transaction),
+            _ => {
+                warn!("Dropping transaction {:?} with an invalid signature", transaction.signatures.first().map(signature_string));
+                None
+            }
+        })
+        .collect()
+}
+
+fn verify_batch<'a>(signed_messages: impl Iterator<Item = &'a SignedMessage>) -> bool {
+    let mut messages: Vec<&[u8]> = Vec::new();
+    let mut signatures = Vec::new();
+    let mut public_keys = Vec::new();
+    for signed_message in signed_messages {
+        for (public_key, signature) in signed_message.public_keys.iter().zip(&signed_message.signatures) {
+            messages.push(&signed_message.message);
+            signatures.push(*signature);
+            public_keys.push(*public_key);
+        }
+    }
+    messages.is_empty() || ed25519_dalek::verify_batch(&messages, &signatures, &public_keys).is_ok()
+}
+
+fn signature_string(signature: &[u8; 64]) -> String {
+    solana_sdk::signature::Signature::from(*signature).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::Transaction;
+    use state::test_fixtures::{keypair, transfer_transaction};
+
+    fn transfer(seed: &str) -> TrollupTransaction {
+        TrollupTransaction::from(&transfer_transaction(&keypair(seed), &Pubkey::new_unique(), 1, Hash::default()))
+    }
+
+    /// A transfer out of `owner`'s account with the fee paid by `payer`, signed by both.
+    fn sponsored(payer: &Keypair, owner: &Keypair) -> TrollupTransaction {
+        let instruction = system_instruction::transfer(&owner.pubkey(), &Pubkey::new_unique(), 1);
+        TrollupTransaction::from(&Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[payer, owner], Hash::default()))
+    }
+
+    fn signatures(transactions: &[TrollupTransaction]) -> Vec<[u8; 64]> {
+        transactions.iter().map(|transaction| transaction.signatures[0]).collect()
+    }
+
+    #[test]
+    fn test_valid_transactions_are_kept() {
+        let transactions = vec![transfer("signatures/a"), transfer("signatures/b")];
+
+        assert_eq!(signatures(&retain_valid_signatures(transactions.clone())), signatures(&transactions));
+    }
+
+    #[test]
+    fn test_tampered_transaction_is_dropped() {
+        let valid = transfer("signatures/a");
+        let mut tampered = transfer("signatures/b");
+        tampered.message.recent_blockhash = [1; 32];
+
+        assert_eq!(signatures(&retain_valid_signatures(vec![tampered, valid.clone()])), signatures(&[valid]));
+    }
+
+    #[test]
+    fn test_transaction_missing_a_signature_is_dropped() {
+        let valid = transfer("signatures/a");
+        let mut unsigned = sponsored(&keypair("signatures/payer"), &keypair("signatures/owner"));
+        unsigned.signatures.pop();
+
+        assert_eq!(signatures(&retain_valid_signatures(vec![unsigned, valid.clone()])), signatures(&[valid]));
+    }
+
+    #[test]
+    fn test_every_signer_is_verified() {
+        let payer = keypair("signatures/payer");
+        let owner = keypair("signatures/owner");
+        let valid = sponsored(&payer, &owner);
+        assert_eq!(valid.signatures.len(), 2);
+        // Signed by the payer, but the owner's signature is someone else's
+        let mut forged = valid.clone();
+        forged.signatures[1] = sponsored(&payer, &keypair("signatures/forger")).signatures[1];
+        // Both signatures are genuine but in the wrong order
+        let mut swapped = valid.clone();
+        swapped.signatures.swap(0, 1);
+
+        let kept = retain_valid_signatures(vec![forged, valid.clone(), swapped]);
+
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].signatures, valid.signatures);
+    }
+}
@@ -0,0 +1,145 @@
+use solana_sdk::account::{create_account_shared_data_with_fields, Account, AccountSharedData};
+use solana_sdk::clock::{Clock, Slot, UnixTimestamp};
+use solana_sdk::epoch_schedule::{EpochSchedule, MINIMUM_SLOTS_PER_EPOCH};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use solana_sdk::rent_collector::RentCollector;
+use solana_sdk::sysvar::{self, Sysvar};
+use state::config::TrollupConfig;
+use state::rollup_metadata::{self, RollupMetadata};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Supplies the Clock, Rent and EpochSchedule sysvars programs see during execution, along with
+/// the rollup metadata accounts.
+///
+/// Every executed batch becomes a rollup block, so the Clock slot is the number of the block
+/// being executed and its timestamp is taken when execution of the block starts. Rent and the
+/// epoch schedule come from the configuration and don't change at runtime. The metadata is
+/// refreshed from the committer before each block.
+#[derive(Clone, Debug)]
+pub struct SysvarProvider {
+    clock: Clock,
+    rent: Rent,
+    epoch_schedule: EpochSchedule,
+    metadata: RollupMetadata,
+}
+
+impl SysvarProvider {
+    /// `next_block_number` is the number of the first block this provider will be advanced to.
+    pub fn new(next_block_number: u64, rent: Rent, epoch_schedule: EpochSchedule) -> Self {
+        let slot = next_block_number.saturating_sub(1);
+        let clock = Clock {
+            slot,
+            epoch_start_timestamp: 0,
+            epoch: epoch_schedule.get_epoch(slot),
+            leader_schedule_epoch: epoch_schedule.get_leader_schedule_epoch(slot),
+            unix_timestamp: 0,
+        };
+        SysvarProvider { clock, rent, epoch_schedule, metadata: RollupMetadata::default() }
+    }
+
+    pub fn from_config(config: &TrollupConfig, next_block_number: u64) -> Self {
+        let rent = Rent {
+            lamports_per_byte_year: config.rent_lamports_per_byte_year,
+            exemption_threshold: config.rent_exemption_threshold_years as f64,
+            burn_percent: config.rent_burn_percent,
+        };
+        let slots_per_epoch = config.slots_per_epoch.max(MINIMUM_SLOTS_PER_EPOCH);
+        let epoch_schedule = EpochSchedule::custom(slots_per_epoch, slots_per_epoch, false);
+        Self::new(next_block_number, rent, epoch_schedule)
+    }
+
+    /// Moves the Clock to the next block, stamped with the current time.
+    pub fn advance(&mut self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as UnixTimestamp)
+            .unwrap_or_default();
+        self.advance_to(self.clock.slot + 1, now);
+    }
+
+    /// Moves the Clock so the next block, `next_block_number`, gets its number as slot again
+    /// after the chain was rolled back to L1. The timestamp stays.
+    pub fn resync(&mut self, next_block_number: u64) {
+        self.advance_to(next_block_number.saturating_sub(1), self.clock.unix_timestamp);
+    }
+
+    /// Moves the Clock to `slot`. Timestamps never go backwards, a clock adjustment on the host
+    /// repeats the previous timestamp instead.
+    pub fn advance_to(&mut self, slot: Slot, unix_timestamp: UnixTimestamp) {
+        let unix_timestamp = unix_timestamp.max(self.clock.unix_timestamp);
+        let epoch = self.epoch_schedule.get_epoch(slot);
+        if epoch != self.clock.epoch || self.clock.epoch_start_timestamp == 0 {
+            self.clock.epoch_start_timestamp = unix_timestamp;
+        }
+        self.clock.slot = slot;
+        self.clock.epoch = epoch;
+        self.clock.leader_schedule_epoch = self.epoch_schedule.get_leader_schedule_epoch(slot);
+        self.clock.unix_timestamp = unix_timestamp;
+    }
+
+    /// Sets the Clock to exactly what another node executed a block with, for re-executing it.
+    pub fn restore_clock(&mut self, slot: Slot, unix_timestamp: UnixTimestamp, epoch_start_timestamp: UnixTimestamp) {
+        self.clock.slot = slot;
+        self.clock.epoch = self.epoch_schedule.get_epoch(slot);
+        self.clock.leader_schedule_epoch = self.epoch_schedule.get_leader_schedule_epoch(slot);
+        self.clock.unix_timestamp = unix_timestamp;
+        self.clock.epoch_start_timestamp = epoch_start_timestamp;
+    }
+
+    pub fn set_metadata(&mut self, metadata: RollupMetadata) {
+        self.metadata = metadata;
+    }
+
+    pub fn metadata(&self) -> &RollupMetadata {
+        &self.metadata
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    pub fn rent(&self) -> &Rent {
+        &self.rent
+    }
+
+    pub fn epoch_schedule(&self) -> &EpochSchedule {
+        &self.epoch_schedule
+    }
+
+    /// Rent collector for the processing environment, using the configured rent.
+    pub fn rent_collector(&self) -> RentCollector {
+        RentCollector {
+            epoch: self.clock.epoch,
+            epoch_schedule: self.epoch_schedule.clone(),
+            rent: self.rent.clone(),
+            ..RentCollector::default()
+        }
+    }
+
+    /// The sysvar and rollup metadata accounts for the current block, served by the account
+    /// loader. The sysvars also fill the SVM sysvar cache.
+    pub fn accounts(&self) -> Vec<(Pubkey, AccountSharedData)> {
+        let mut accounts = vec![
+            (sysvar::clock::id(), self.account(&self.clock)),
+            (sysvar::rent::id(), self.account(&self.rent)),
+            (sysvar::epoch_schedule::id(), self.account(&self.epoch_schedule)),
+        ];
+        accounts.extend(self.metadata.account_data().into_iter().map(|(address, data)| {
+            let account = Account {
+                lamports: self.rent.minimum_balance(data.len()).max(1),
+                data,
+                owner: rollup_metadata::owner::id(),
+                executable: false,
+                rent_epoch: 0,
+            };
+            (address, AccountSharedData::from(account))
+        }));
+        accounts
+    }
+
+    fn account<S: Sysvar>(&self, sysvar: &S) -> AccountSharedData {
+        let lamports = self.rent.minimum_balance(S::size_of()).max(1);
+        create_account_shared_data_with_fields(sysvar, (lamports, 0))
+    }
+}
@@ -1,5 +1,12 @@
+use crate::ordering::OrderingPolicy;
+use state::state_record::StateRecord;
 use state::transaction::TrollupTransaction;
-use std::collections::VecDeque;
+use state_commitment::bundles;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+/// System lane size of a pool created with `new`.
+pub const DEFAULT_SYSTEM_LANE_CAPACITY: usize = 64;
 
 /// TransactionPool is a struct that represents a pool of transactions.
 ///
@@ -18,26 +25,161 @@ use std::collections::VecDeque;
 /// - `state_management`: A reference to the StateManager that manages the state of the transactions.
 #[derive(Debug, Clone)]
 pub struct TransactionPool {
-    pool: VecDeque<TrollupTransaction>,
+    /// Transactions in execution order, with the time they were added to the pool
+    pool: VecDeque<(TrollupTransaction, Instant)>,
+    /// ID of the API request that submitted a transaction, by transaction key
+    request_ids: HashMap<[u8; 32], String>,
+    /// Operator maintenance transactions, executed ahead of the public pool
+    system_lane: VecDeque<(TrollupTransaction, Instant)>,
+    system_lane_capacity: usize,
 }
 
 impl TransactionPool {
     pub fn new() -> Self {
         Self {
-            pool: VecDeque::new()
+            pool: VecDeque::new(),
+            request_ids: HashMap::new(),
+            system_lane: VecDeque::new(),
+            system_lane_capacity: DEFAULT_SYSTEM_LANE_CAPACITY,
         }
     }
 
+    pub fn with_system_lane_capacity(mut self, capacity: usize) -> Self {
+        self.system_lane_capacity = capacity;
+        self
+    }
+
     pub fn add_transaction(&mut self, tx: TrollupTransaction) {
-        self.pool.push_back(tx);
+        self.pool.push_back((tx, Instant::now()));
+    }
+
+    /// Adds a transaction and remembers the request that submitted it, so the execution and
+    /// commitment of the transaction can be logged with the same request ID.
+    pub fn add_transaction_for_request(&mut self, tx: TrollupTransaction, request_id: &str) {
+        if !request_id.is_empty() {
+            self.request_ids.insert(tx.get_key(), request_id.to_string());
+        }
+        self.add_transaction(tx);
+    }
+
+    /// Adds the transactions of a bundle one after the other, all submitted by `request_id`.
+    /// They are only ever taken together, see `take_ordered`. Returns the bundle ID.
+    pub fn add_bundle(&mut self, transactions: Vec<TrollupTransaction>, request_id: &str) -> Result<[u8; 32], String> {
+        let bundle_id = bundles::register(&transactions)?;
+        for tx in transactions {
+            self.add_transaction_for_request(tx, request_id);
+        }
+        Ok(bundle_id)
+    }
+
+    /// Removes and returns the request IDs of `transactions`, in the same order. Transactions not
+    /// submitted through a request have an empty ID.
+    pub fn take_request_ids(&mut self, transactions: &[TrollupTransaction]) -> Vec<String> {
+        transactions
+            .iter()
+            .map(|tx| self.request_ids.remove(&tx.get_key()).unwrap_or_default())
+            .collect()
+    }
+
+    /// Adds an operator transaction to the system lane, marking it as a system transaction.
+    /// Fails when the lane is full, the lane is bounded separately so operator traffic can
+    /// neither be crowded out by users nor crowd them out.
+    pub fn add_system_transaction(&mut self, mut tx: TrollupTransaction, request_id: &str) -> Result<(), String> {
+        if self.system_lane.len() >= self.system_lane_capacity {
+            return Err(format!("System lane is full ({} transactions)", self.system_lane_capacity));
+        }
+        tx.system = true;
+        if !request_id.is_empty() {
+            self.request_ids.insert(tx.get_key(), request_id.to_string());
+        }
+        self.system_lane.push_back((tx, Instant::now()));
+        Ok(())
+    }
+
+    /// Puts transactions back at the front of the pool, keeping their order, so they are executed
+    /// again before anything submitted after them. Their age starts over. System transactions go
+    /// back to the system lane.
+    pub fn requeue_transactions(&mut self, transactions: Vec<TrollupTransaction>) {
+        let now = Instant::now();
+        for tx in transactions.into_iter().rev() {
+            if tx.system {
+                self.system_lane.push_front((tx, now));
+            } else {
+                self.pool.push_front((tx, now));
+            }
+        }
     }
 
     pub fn get_next_transaction(&mut self) -> Option<TrollupTransaction> {
-        self.pool.pop_front()
+        self.system_lane.pop_front().or_else(|| self.pool.pop_front()).map(|(tx, _)| tx)
     }
 
     pub fn pool_size(&self) -> usize {
-        self.pool.len()
+        self.pool.len() + self.system_lane.len()
+    }
+
+    /// Whether the transaction with key `key` is waiting in either lane.
+    pub fn contains(&self, key: &[u8; 32]) -> bool {
+        self.system_lane.iter().chain(&self.pool).any(|(tx, _)| tx.get_key() == *key)
+    }
+
+    pub fn system_lane_size(&self) -> usize {
+        self.system_lane.len()
+    }
+
+    /// How long the longest waiting transaction in either lane has been waiting.
+    pub fn oldest_age(&self) -> Option<Duration> {
+        self.pool.front().into_iter()
+            .chain(self.system_lane.front())
+            .map(|(_, added_at)| added_at.elapsed())
+            .max()
+    }
+
+    /// Drops every transaction whose key was already seen earlier in the pool, keeping the first
+    /// submission. Returns the number of transactions removed.
+    pub fn remove_duplicates(&mut self) -> usize {
+        let before = self.pool_size();
+        let mut seen = HashSet::with_capacity(before);
+        self.system_lane.retain(|(tx, _)| seen.insert(tx.get_key()));
+        self.pool.retain(|(tx, _)| seen.insert(tx.get_key()));
+        before - self.pool_size()
+    }
+
+    /// Removes up to `chunk` transactions in execution order: the system lane first, then the
+    /// public pool in the order `policy` selects from a snapshot of it. Selecting a transaction of
+    /// a bundle takes the whole bundle, in bundle order, or nothing when it doesn't fit.
+    pub fn take_ordered(&mut self, policy: &dyn OrderingPolicy, chunk: u32) -> Vec<TrollupTransaction> {
+        let chunk = chunk as usize;
+        let system_count = self.system_lane.len().min(chunk);
+        let mut transactions: Vec<TrollupTransaction> = self.system_lane.drain(..system_count).map(|(tx, _)| tx).collect();
+
+        let pending: Vec<&TrollupTransaction> = self.pool.iter().map(|(tx, _)| tx).collect();
+        let positions: HashMap<[u8; 32], usize> = pending.iter().enumerate().map(|(index, tx)| (tx.get_key(), index)).collect();
+        let room = chunk - system_count;
+        let mut taken = HashSet::new();
+        let mut selected: Vec<usize> = Vec::new();
+        for index in policy.select(&pending, room) {
+            if index >= pending.len() || taken.contains(&index) {
+                continue;
+            }
+            let group: Vec<usize> = match bundles::bundle_of(&pending[index].get_key()) {
+                Some((_, members)) => members.iter().filter_map(|key| positions.get(key).copied()).collect(),
+                None => vec![index],
+            };
+            if selected.len() + group.len() > room {
+                continue;
+            }
+            taken.extend(group.iter().copied());
+            selected.extend(group);
+        }
+        transactions.extend(selected.iter().map(|index| pending[*index].clone()));
+
+        let mut index = 0;
+        self.pool.retain(|_| {
+            index += 1;
+            !taken.contains(&(index - 1))
+        });
+        transactions
     }
 
     pub fn get_next_transactions(&mut self, chunk: u32) -> Vec<TrollupTransaction> {
@@ -48,7 +190,7 @@ impl TransactionPool {
 
         let to = chunk.min(self.pool_size() as u32);
         for _ in 0..to {
-            if let Some(transaction) = self.pool.pop_front() {
+            if let Some(transaction) = self.get_next_transaction() {
                 transactions.push(transaction);
             } else {
                 break;
@@ -0,0 +1,10 @@
+#![no_main]
+
+use borsh::BorshDeserialize;
+use libfuzzer_sys::fuzz_target;
+
+// Instruction data is fully attacker controlled, decoding it must never panic in either program.
+fuzz_target!(|data: &[u8]| {
+    let _ = trollup_proof_verifier::ProgramInstruction::try_from_slice(data);
+    let _ = validator_signature_verify::ProgramInstruction::try_from_slice(data);
+});
@@ -0,0 +1,23 @@
+#![no_main]
+
+use borsh::BorshDeserialize;
+use libfuzzer_sys::fuzz_target;
+use trollup_zk::prove::{ProofPackage, ProofPackagePrepared};
+use trollup_zk::verify::verify_proof_package;
+
+fn check(package: ProofPackagePrepared) {
+    if let Ok(proof_package) = ProofPackage::try_from(package) {
+        let _ = verify_proof_package(&proof_package);
+    }
+}
+
+// The validator accepts ProofPackagePrepared as JSON over HTTP, the borsh encoding is fuzzed as well
+// since it reaches the same conversion with fewer bytes spent on framing.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(package) = serde_json::from_slice::<ProofPackagePrepared>(data) {
+        check(package);
+    }
+    if let Ok(package) = ProofPackagePrepared::try_from_slice(data) {
+        check(package);
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use borsh::BorshDeserialize;
+use libfuzzer_sys::fuzz_target;
+use state::state_record::StateRecord;
+use state::transaction::{convert_to_sanitized_transaction, deserialize_transaction, TrollupTransaction};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = deserialize_transaction(data);
+
+    // Anything that decodes has to survive being keyed and sanitized, same as in the execution engine
+    if let Ok(transaction) = TrollupTransaction::try_from_slice(data) {
+        let _ = transaction.get_key();
+        let _ = convert_to_sanitized_transaction(&transaction);
+    }
+});
@@ -0,0 +1,65 @@
+//! A `blockSubscribe` subscription on a node's `/ws` endpoint.
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use state::views::FinalizedBlockView;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+/// ID of the subscribe request, the only request the feed sends.
+const SUBSCRIBE_REQUEST_ID: u64 = 1;
+
+pub struct BlockFeed {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl BlockFeed {
+    /// Subscribes to the finalized blocks from `from` on. The node sends the stored blocks from
+    /// `from` first, up to its backfill limit, then every block it finalizes.
+    pub async fn subscribe(url: &str, from: u64) -> Result<Self, String> {
+        let (mut socket, _) = connect_async(url).await.map_err(|error| format!("Failed to connect to {}: {}", url, error))?;
+        let request = json!({ "jsonrpc": "2.0", "id": SUBSCRIBE_REQUEST_ID, "method": "blockSubscribe", "params": [{ "from": from }] });
+        socket.send(Message::text(request.to_string())).await.map_err(feed_error)?;
+
+        let mut feed = BlockFeed { socket };
+        let response = feed.next_message().await?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("{} refused the block subscription: {}", url, error));
+        }
+        if response.get("id").and_then(Value::as_u64) != Some(SUBSCRIBE_REQUEST_ID) {
+            return Err(format!("Unexpected response to the block subscription: {}", response));
+        }
+        Ok(feed)
+    }
+
+    /// Waits for the next block, failing once the connection is lost.
+    pub async fn next_block(&mut self) -> Result<FinalizedBlockView, String> {
+        loop {
+            let message = self.next_message().await?;
+            if message.get("method").and_then(Value::as_str) != Some("blockNotification") {
+                continue;
+            }
+            let result = message.get("params").and_then(|params| params.get("result")).cloned().unwrap_or_default();
+            return serde_json::from_value(result).map_err(|error| format!("Invalid block notification: {}", error));
+        }
+    }
+
+    /// The next JSON message, pings are answered by the socket itself.
+    async fn next_message(&mut self) -> Result<Value, String> {
+        loop {
+            match self.socket.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    return serde_json::from_str(&text).map_err(|error| format!("Invalid message from the feed: {}", error));
+                }
+                Some(Ok(Message::Close(_))) | None => return Err("The feed closed the connection".to_string()),
+                Some(Ok(_)) => continue,
+                Some(Err(error)) => return Err(feed_error(error)),
+            }
+        }
+    }
+}
+
+fn feed_error(error: tokio_tungstenite::tungstenite::Error) -> String {
+    format!("Block feed error: {}", error)
+}
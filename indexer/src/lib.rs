@@ -0,0 +1,31 @@
+pub mod feed;
+pub mod projection;
+pub mod store;
+
+use crate::feed::BlockFeed;
+use crate::projection::project;
+use crate::store::Store;
+use log::info;
+
+/// Follows the feed at `feed_url` from the block after the checkpoint, writing every block to
+/// `store`, until the feed or the database fails.
+pub async fn follow(store: &mut Store, feed_url: &str) -> Result<(), String> {
+    let mut next_block = store.checkpoint().await?.map_or(1, |block_number| block_number + 1);
+    info!("Indexing blocks from {} on, following {}", next_block, feed_url);
+    let mut feed = BlockFeed::subscribe(feed_url, next_block).await?;
+    loop {
+        let block = feed.next_block().await?;
+        let block_number = block.block.block_number;
+        if block_number < next_block {
+            continue;
+        }
+        if block_number > next_block {
+            // The node's backfill ended before the live block, pick up from where it stopped
+            info!("Feed skipped from block {} to {}, subscribing again", next_block, block_number);
+            feed = BlockFeed::subscribe(feed_url, next_block).await?;
+            continue;
+        }
+        store.apply(&project(&block)).await?;
+        next_block += 1;
+    }
+}
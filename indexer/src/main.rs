@@ -0,0 +1,40 @@
+use lazy_static::lazy_static;
+use log::{error, warn};
+use server::logging::{self, LogSettings};
+use state::config::TrollupConfig;
+use trollup_indexer::follow;
+use trollup_indexer::store::Store;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+}
+
+#[tokio::main]
+async fn main() {
+    let _ = TrollupConfig::load();
+
+    let _log_guard = logging::init(&LogSettings {
+        level: CONFIG.log_level.clone(),
+        file_path: CONFIG.log_file_path.clone(),
+        rotation: CONFIG.log_rotation.clone(),
+        max_file_size: CONFIG.log_max_file_size,
+        max_files: CONFIG.log_max_files,
+        format: CONFIG.log_format.clone(),
+    });
+
+    if CONFIG.indexer_database_url.is_empty() {
+        error!("INDEXER_DATABASE_URL is not set, there is no database to index into");
+        std::process::exit(1);
+    }
+    loop {
+        if let Err(error) = run().await {
+            warn!("Indexer stopped: {}, restarting in {:?}", error, CONFIG.indexer_reconnect_interval);
+        }
+        tokio::time::sleep(CONFIG.indexer_reconnect_interval).await;
+    }
+}
+
+async fn run() -> Result<(), String> {
+    let mut store = Store::connect(&CONFIG.indexer_database_url).await?;
+    follow(&mut store, &CONFIG.indexer_feed_url).await
+}
@@ -0,0 +1,234 @@
+//! The rows a finalized block becomes in the SQL projections, kept apart from the database so
+//! the mapping can be checked without one.
+
+use lazy_static::lazy_static;
+use serde_json::Value;
+use state::views::{FinalizedBlockView, InstructionView, TransactionView};
+
+/// Decoded spl-token instructions that move tokens.
+const TOKEN_TRANSFER_KINDS: [&str; 4] = ["transfer", "transferChecked", "mintTo", "burn"];
+
+lazy_static! {
+    static ref TOKEN_PROGRAM_ID: String = spl_token::id().to_string();
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockRow {
+    pub block_number: u64,
+    /// Hex
+    pub block_hash: String,
+    pub previous_block: String,
+    pub accounts_merkle_root: String,
+    pub transaction_count: u32,
+    pub account_count: u32,
+    pub fees: u64,
+    pub sequencer: String,
+    pub heartbeat: bool,
+    pub l1_slot: Option<u64>,
+    pub l1_signature: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionRow {
+    /// Base58 first signature
+    pub signature: String,
+    pub block_number: u64,
+    /// Position of the transaction in the block
+    pub position: u32,
+    pub fee_payer: String,
+    pub optimistic: bool,
+    pub system: bool,
+    /// The transaction as `/get-transaction` returns it
+    pub body: Value,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BalanceRow {
+    pub address: String,
+    pub lamports: u64,
+    pub owner: String,
+    /// Block that wrote this balance
+    pub block_number: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenTransferRow {
+    pub signature: String,
+    pub instruction_index: u32,
+    pub block_number: u64,
+    /// The decoded instruction type, `transfer`, `transferChecked`, `mintTo` or `burn`
+    pub kind: String,
+    /// Token accounts, no source for a mint and no destination for a burn
+    pub source: Option<String>,
+    pub destination: Option<String>,
+    /// Only known for checked transfers, mints and burns
+    pub mint: Option<String>,
+    pub authority: Option<String>,
+    pub amount: u64,
+}
+
+/// Everything the indexer writes for one block, in one database transaction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Projection {
+    pub block: BlockRow,
+    pub transactions: Vec<TransactionRow>,
+    pub balances: Vec<BalanceRow>,
+    pub token_transfers: Vec<TokenTransferRow>,
+}
+
+pub fn project(finalized: &FinalizedBlockView) -> Projection {
+    let block = &finalized.block;
+    let block_number = block.block_number;
+    let transactions: Vec<TransactionRow> = finalized.transactions
+        .iter()
+        .enumerate()
+        .filter_map(|(position, transaction)| transaction_row(transaction, block_number, position as u32))
+        .collect();
+    let token_transfers = finalized.transactions
+        .iter()
+        .zip(&transactions)
+        .flat_map(|(transaction, row)| {
+            transaction.instructions
+                .iter()
+                .enumerate()
+                .filter_map(|(index, instruction)| token_transfer_row(instruction, &row.signature, index as u32, block_number))
+        })
+        .collect();
+
+    Projection {
+        block: BlockRow {
+            block_number,
+            block_hash: block.block_hash.clone(),
+            previous_block: block.previous_block.clone(),
+            accounts_merkle_root: block.accounts_merkle_root.clone(),
+            transaction_count: block.transactions.len() as u32,
+            account_count: block.accounts.len() as u32,
+            fees: block.fees,
+            sequencer: block.sequencer.clone(),
+            heartbeat: block.heartbeat,
+            l1_slot: block.l1_slot,
+            l1_signature: block.l1_signature.clone(),
+        },
+        transactions,
+        balances: finalized.accounts
+            .iter()
+            .map(|account| BalanceRow {
+                address: account.address.clone(),
+                lamports: account.lamports,
+                owner: account.owner.clone(),
+                block_number,
+            })
+            .collect(),
+        token_transfers,
+    }
+}
+
+/// `None` for an unsigned transaction, which a block never holds.
+fn transaction_row(transaction: &TransactionView, block_number: u64, position: u32) -> Option<TransactionRow> {
+    Some(TransactionRow {
+        signature: transaction.signatures.first()?.clone(),
+        block_number,
+        position,
+        fee_payer: transaction.account_keys.first().cloned().unwrap_or_default(),
+        optimistic: transaction.optimistic,
+        system: transaction.system,
+        body: serde_json::to_value(transaction).ok()?,
+    })
+}
+
+fn token_transfer_row(instruction: &InstructionView, signature: &str, instruction_index: u32, block_number: u64) -> Option<TokenTransferRow> {
+    if instruction.program_id != *TOKEN_PROGRAM_ID {
+        return None;
+    }
+    let parsed = instruction.parsed.as_ref()?;
+    let kind = parsed.get("type")?.as_str()?;
+    if !TOKEN_TRANSFER_KINDS.contains(&kind) {
+        return None;
+    }
+    let field = |name: &str| parsed.get(name).and_then(Value::as_str).map(str::to_string);
+    let (source, destination) = match kind {
+        "mintTo" => (None, field("account")),
+        "burn" => (field("account"), None),
+        _ => (field("source"), field("destination")),
+    };
+    Some(TokenTransferRow {
+        signature: signature.to_string(),
+        instruction_index,
+        block_number,
+        kind: kind.to_string(),
+        source,
+        destination,
+        mint: field("mint"),
+        authority: field("authority"),
+        amount: parsed.get("amount")?.as_u64()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Signer;
+    use state::account_state::AccountState;
+    use state::block::Block;
+    use state::test_fixtures::{keypair, system_account, token_transfer_transaction, transfer_transaction, FixtureMint};
+    use state::transaction::convert_to_trollup_transaction;
+    use state::views::{AccountView, BlockView};
+
+    fn finalized(transactions: &[TransactionView], accounts: &[AccountState]) -> FinalizedBlockView {
+        let block = Block::new(7, Block::get_id(6), Box::new([1u8; 32]), Box::new([2u8; 32]), vec![], vec![], [0u8; 32], vec![], vec![]);
+        FinalizedBlockView {
+            block: BlockView::from(&block),
+            transactions: transactions.to_vec(),
+            accounts: accounts.iter().map(AccountView::from).collect(),
+        }
+    }
+
+    fn view(transaction: solana_sdk::transaction::Transaction) -> TransactionView {
+        TransactionView::from(&convert_to_trollup_transaction(transaction).unwrap())
+    }
+
+    #[test]
+    fn test_projects_transactions_and_balances() {
+        let payer = keypair("indexer/payer");
+        let transfer = view(transfer_transaction(&payer, &Pubkey::new_unique(), 1_000, Hash::new_unique()));
+        let account = system_account(payer.pubkey(), 42);
+
+        let projection = project(&finalized(std::slice::from_ref(&transfer), std::slice::from_ref(&account)));
+        assert_eq!(projection.block.block_number, 7);
+        assert_eq!(projection.transactions.len(), 1);
+        assert_eq!(projection.transactions[0].signature, transfer.signatures[0]);
+        assert_eq!(projection.transactions[0].fee_payer, payer.pubkey().to_string());
+        assert_eq!(projection.transactions[0].body, serde_json::to_value(&transfer).unwrap());
+        assert_eq!(projection.balances, vec![BalanceRow {
+            address: payer.pubkey().to_string(),
+            lamports: 42,
+            owner: account.owner.to_string(),
+            block_number: 7,
+        }]);
+        // A system transfer moves lamports, not tokens
+        assert!(projection.token_transfers.is_empty());
+    }
+
+    #[test]
+    fn test_projects_token_transfers() {
+        let owner = keypair("indexer/owner");
+        let mint = FixtureMint::new("indexer", 6);
+        let to = Pubkey::new_unique();
+        let transfer = view(token_transfer_transaction(&owner, &mint, &to, 250, Hash::new_unique()));
+
+        let projection = project(&finalized(std::slice::from_ref(&transfer), &[]));
+        assert_eq!(projection.token_transfers, vec![TokenTransferRow {
+            signature: transfer.signatures[0].clone(),
+            instruction_index: 0,
+            block_number: 7,
+            kind: "transferChecked".to_string(),
+            source: Some(mint.token_account_address(&owner.pubkey()).to_string()),
+            destination: Some(mint.token_account_address(&to).to_string()),
+            mint: Some(mint.address.to_string()),
+            authority: Some(owner.pubkey().to_string()),
+            amount: 250,
+        }]);
+    }
+}
@@ -0,0 +1,153 @@
+//! The Postgres projections. Every block is written in one transaction together with the
+//! checkpoint, and every write is an upsert, so a block written twice after a crash or a
+//! reconnect leaves the same rows.
+
+use crate::projection::Projection;
+use log::warn;
+use tokio_postgres::{Client, NoTls};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS indexer_checkpoint (
+    id SMALLINT PRIMARY KEY CHECK (id = 1),
+    block_number BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE TABLE IF NOT EXISTS blocks (
+    block_number BIGINT PRIMARY KEY,
+    block_hash TEXT NOT NULL,
+    previous_block TEXT NOT NULL,
+    accounts_merkle_root TEXT NOT NULL,
+    transaction_count INTEGER NOT NULL,
+    account_count INTEGER NOT NULL,
+    fees BIGINT NOT NULL,
+    sequencer TEXT NOT NULL,
+    heartbeat BOOLEAN NOT NULL,
+    l1_slot BIGINT,
+    l1_signature TEXT
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    signature TEXT PRIMARY KEY,
+    block_number BIGINT NOT NULL,
+    position INTEGER NOT NULL,
+    fee_payer TEXT NOT NULL,
+    optimistic BOOLEAN NOT NULL,
+    system BOOLEAN NOT NULL,
+    body JSONB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS transactions_block_number ON transactions (block_number);
+CREATE INDEX IF NOT EXISTS transactions_fee_payer ON transactions (fee_payer);
+CREATE TABLE IF NOT EXISTS account_balances (
+    address TEXT PRIMARY KEY,
+    lamports BIGINT NOT NULL,
+    owner TEXT NOT NULL,
+    block_number BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS account_balances_owner ON account_balances (owner);
+CREATE TABLE IF NOT EXISTS token_transfers (
+    signature TEXT NOT NULL,
+    instruction_index INTEGER NOT NULL,
+    block_number BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    source TEXT,
+    destination TEXT,
+    mint TEXT,
+    authority TEXT,
+    amount NUMERIC(20, 0) NOT NULL,
+    PRIMARY KEY (signature, instruction_index)
+);
+CREATE INDEX IF NOT EXISTS token_transfers_source ON token_transfers (source);
+CREATE INDEX IF NOT EXISTS token_transfers_destination ON token_transfers (destination);
+CREATE INDEX IF NOT EXISTS token_transfers_mint ON token_transfers (mint);
+";
+
+pub struct Store {
+    client: Client,
+}
+
+impl Store {
+    /// Connects to the database at `url` and creates the tables it doesn't have yet.
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        let (client, connection) = tokio_postgres::connect(url, NoTls)
+            .await
+            .map_err(|error| format!("Failed to connect to the indexer database: {}", error))?;
+        tokio::spawn(async move {
+            if let Err(error) = connection.await {
+                warn!("Indexer database connection closed: {}", error);
+            }
+        });
+        client.batch_execute(SCHEMA).await.map_err(|error| format!("Failed to create the indexer tables: {}", error))?;
+        Ok(Store { client })
+    }
+
+    /// The last block written, `None` before the first one.
+    pub async fn checkpoint(&self) -> Result<Option<u64>, String> {
+        let row = self.client
+            .query_opt("SELECT block_number FROM indexer_checkpoint WHERE id = 1", &[])
+            .await
+            .map_err(|error| format!("Failed to read the indexer checkpoint: {}", error))?;
+        Ok(row.map(|row| row.get::<_, i64>(0) as u64))
+    }
+
+    /// Writes the rows of a block and moves the checkpoint to it. A balance is only replaced by
+    /// one written by the same or a later block, and the checkpoint never moves back.
+    pub async fn apply(&mut self, projection: &Projection) -> Result<(), String> {
+        let block_number = projection.block.block_number as i64;
+        let transaction = self.client.transaction().await.map_err(database_error)?;
+
+        let block = &projection.block;
+        transaction.execute(
+            "INSERT INTO blocks (block_number, block_hash, previous_block, accounts_merkle_root, transaction_count, account_count, fees, sequencer, heartbeat, l1_slot, l1_signature)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+             ON CONFLICT (block_number) DO UPDATE SET block_hash = EXCLUDED.block_hash, previous_block = EXCLUDED.previous_block,
+                 accounts_merkle_root = EXCLUDED.accounts_merkle_root, transaction_count = EXCLUDED.transaction_count,
+                 account_count = EXCLUDED.account_count, fees = EXCLUDED.fees, sequencer = EXCLUDED.sequencer,
+                 heartbeat = EXCLUDED.heartbeat, l1_slot = EXCLUDED.l1_slot, l1_signature = EXCLUDED.l1_signature",
+            &[&block_number, &block.block_hash, &block.previous_block, &block.accounts_merkle_root,
+                &(block.transaction_count as i32), &(block.account_count as i32), &(block.fees as i64), &block.sequencer,
+                &block.heartbeat, &block.l1_slot.map(|slot| slot as i64), &block.l1_signature],
+        ).await.map_err(database_error)?;
+
+        for row in &projection.transactions {
+            transaction.execute(
+                "INSERT INTO transactions (signature, block_number, position, fee_payer, optimistic, system, body)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT (signature) DO UPDATE SET block_number = EXCLUDED.block_number, position = EXCLUDED.position,
+                     fee_payer = EXCLUDED.fee_payer, optimistic = EXCLUDED.optimistic, system = EXCLUDED.system, body = EXCLUDED.body",
+                &[&row.signature, &block_number, &(row.position as i32), &row.fee_payer, &row.optimistic, &row.system, &row.body],
+            ).await.map_err(database_error)?;
+        }
+
+        for row in &projection.balances {
+            transaction.execute(
+                "INSERT INTO account_balances (address, lamports, owner, block_number) VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (address) DO UPDATE SET lamports = EXCLUDED.lamports, owner = EXCLUDED.owner, block_number = EXCLUDED.block_number
+                 WHERE account_balances.block_number <= EXCLUDED.block_number",
+                &[&row.address, &(row.lamports as i64), &row.owner, &block_number],
+            ).await.map_err(database_error)?;
+        }
+
+        for row in &projection.token_transfers {
+            // Amounts are u64, bound as text so the ones past i64 fit the NUMERIC column
+            transaction.execute(
+                "INSERT INTO token_transfers (signature, instruction_index, block_number, kind, source, destination, mint, authority, amount)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::TEXT::NUMERIC)
+                 ON CONFLICT (signature, instruction_index) DO NOTHING",
+                &[&row.signature, &(row.instruction_index as i32), &block_number, &row.kind, &row.source, &row.destination,
+                    &row.mint, &row.authority, &row.amount.to_string()],
+            ).await.map_err(database_error)?;
+        }
+
+        transaction.execute(
+            "INSERT INTO indexer_checkpoint (id, block_number, block_hash) VALUES (1, $1, $2)
+             ON CONFLICT (id) DO UPDATE SET block_number = EXCLUDED.block_number, block_hash = EXCLUDED.block_hash, updated_at = now()
+             WHERE indexer_checkpoint.block_number < EXCLUDED.block_number",
+            &[&block_number, &block.block_hash],
+        ).await.map_err(database_error)?;
+        transaction.commit().await.map_err(database_error)
+    }
+}
+
+fn database_error(error: tokio_postgres::Error) -> String {
+    format!("Indexer database error: {}", error)
+}
@@ -0,0 +1,252 @@
+use crate::auth::{require_admin_token, AdminToken};
+use crate::body;
+use crate::client_ip::ClientAddr;
+use crate::request_id::REQUEST_ID_HEADER;
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use warp::http::header::{AUTHORIZATION, CONTENT_LENGTH, USER_AGENT};
+use warp::http::{HeaderMap, Request, Response, StatusCode};
+use warp::hyper::body::HttpBody;
+use warp::hyper::Body;
+use warp::{Filter, Rejection, Reply};
+
+/// Path segments whose following segment is replaced with `<redacted>` in the access log,
+/// e.g. `/get-transaction/<signature>` and `/prove/<state root>`. Every route taking a
+/// signature, bundle ID or state root in its path is listed.
+const REDACTED_PATH_SEGMENTS: [&str; 7] = [
+    "get-transaction",
+    "get-transaction-status",
+    "get-bundle-status",
+    "get-pending-commitments",
+    "get-pending-commitment-diff",
+    "optimistic-commitments",
+    "prove",
+];
+
+/// Query parameters whose values are never written to the access log.
+const REDACTED_QUERY_PARAMS: [&str; 4] = ["api_key", "apikey", "token", "signature"];
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Runtime settings for the access log. These can be changed through `/admin/access-log`
+/// without restarting the server.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessLogSettings {
+    pub enabled: bool,
+    /// Percentage (0-100) of successful requests that are logged. Requests resulting in a server
+    /// error are always logged.
+    pub sample_percent: u8,
+}
+
+impl Default for AccessLogSettings {
+    fn default() -> Self {
+        AccessLogSettings {
+            enabled: true,
+            sample_percent: 100,
+        }
+    }
+}
+
+/// Structured access logging shared by the api and validator servers.
+///
+/// Each request is logged under the `access_log` target with its ID, method, redacted path, status,
+/// latency, request and response sizes and the caller identity. Signatures, state roots, API keys
+/// and authorization headers are never written out.
+#[derive(Clone)]
+pub struct AccessLog {
+    service: &'static str,
+    settings: Arc<RwLock<AccessLogSettings>>,
+    counts: Arc<Counters>,
+}
+
+/// Responses served since the server started, counted whether or not they are logged.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestCounts {
+    pub requests: u64,
+    pub server_errors: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    requests: AtomicU64,
+    server_errors: AtomicU64,
+}
+
+/// Request details captured before the request is handed to the routes.
+pub struct AccessLogEntry {
+    service: &'static str,
+    sample_percent: u8,
+    started: Instant,
+    request_id: String,
+    method: String,
+    path: String,
+    caller: String,
+    request_bytes: u64,
+}
+
+impl AccessLog {
+    pub fn new(service: &'static str, settings: AccessLogSettings) -> Self {
+        AccessLog {
+            service,
+            settings: Arc::new(RwLock::new(settings)),
+            counts: Arc::default(),
+        }
+    }
+
+    /// Counts a response, called by `serve` for every request.
+    pub fn count(&self, status: StatusCode) {
+        self.counts.requests.fetch_add(1, Ordering::Relaxed);
+        if status.is_server_error() {
+            self.counts.server_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn counts(&self) -> RequestCounts {
+        RequestCounts {
+            requests: self.counts.requests.load(Ordering::Relaxed),
+            server_errors: self.counts.server_errors.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn settings(&self) -> AccessLogSettings {
+        *self.settings.read().unwrap()
+    }
+
+    pub fn update(&self, mut settings: AccessLogSettings) -> AccessLogSettings {
+        settings.sample_percent = settings.sample_percent.min(100);
+        *self.settings.write().unwrap() = settings;
+        info!("Access log settings updated for {}: {:?}", self.service, settings);
+        settings
+    }
+
+    /// Captures the request details, or returns `None` when access logging is disabled.
+    pub fn start(&self, request: &Request<Body>, client: ClientAddr) -> Option<AccessLogEntry> {
+        let settings = self.settings();
+        if !settings.enabled {
+            return None;
+        }
+
+        Some(AccessLogEntry {
+            service: self.service,
+            sample_percent: settings.sample_percent,
+            started: Instant::now(),
+            request_id: request.headers().get(REQUEST_ID_HEADER).and_then(|value| value.to_str().ok()).unwrap_or("-").to_string(),
+            method: request.method().to_string(),
+            path: redact_path(request.uri().path(), request.uri().query()),
+            caller: caller_identity(request.headers(), client),
+            request_bytes: content_length(request.headers()).unwrap_or_else(|| request.body().size_hint().lower()),
+        })
+    }
+}
+
+impl AccessLogEntry {
+    pub fn finish(self, response: &Response<Body>) {
+        let status = response.status();
+        if !status.is_server_error() && rand::random::<u8>() % 100 >= self.sample_percent {
+            return;
+        }
+
+        let response_bytes = content_length(response.headers())
+            .or_else(|| response.body().size_hint().exact())
+            .map(|size| size.to_string())
+            .unwrap_or_else(|| "-".to_string());
+
+        info!(
+            target: "access_log",
+            "service={} request_id={} method={} path={} status={} latency_ms={:.3} request_bytes={} response_bytes={} caller={}",
+            self.service,
+            self.request_id,
+            self.method,
+            self.path,
+            status.as_u16(),
+            self.started.elapsed().as_secs_f64() * 1000.0,
+            self.request_bytes,
+            response_bytes,
+            self.caller,
+        );
+    }
+}
+
+/// `GET /admin/access-log` returns the current settings, `POST /admin/access-log` replaces them.
+/// Both need the operator token.
+pub fn admin_route(access_log: AccessLog, admin_token: AdminToken) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    let get_access_log = access_log.clone();
+    let get = warp::path!("admin" / "access-log")
+        .and(warp::get())
+        .and(require_admin_token(admin_token.clone()))
+        .map(move || warp::reply::json(&get_access_log.settings()));
+
+    let update = warp::path!("admin" / "access-log")
+        .and(warp::post())
+        .and(require_admin_token(admin_token))
+        .and(body::json())
+        .map(move |settings: AccessLogSettings| warp::reply::json(&access_log.update(settings)));
+
+    get.or(update)
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+/// Identifies the caller by client address and user agent, with the proxy it came through.
+/// Credentials are reduced to a short fingerprint so callers can be told apart without the key
+/// itself ending up in the logs.
+fn caller_identity(headers: &HeaderMap, client: ClientAddr) -> String {
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("-");
+
+    let credential = headers
+        .get(API_KEY_HEADER)
+        .or_else(|| headers.get(AUTHORIZATION))
+        .map(|value| {
+            let digest = Sha256::digest(value.as_bytes());
+            format!(" key={}", hex::encode(&digest[..4]))
+        })
+        .unwrap_or_default();
+
+    let address = if client.is_proxied() {
+        format!("{} via={}", client.ip, client.peer)
+    } else {
+        client.peer.to_string()
+    };
+    format!("{} agent={:?}{}", address, user_agent, credential)
+}
+
+fn redact_path(path: &str, query: Option<&str>) -> String {
+    let mut redact_next = false;
+    let segments: Vec<&str> = path
+        .split('/')
+        .map(|segment| {
+            let segment = if redact_next && !segment.is_empty() { "<redacted>" } else { segment };
+            redact_next = REDACTED_PATH_SEGMENTS.contains(&segment);
+            segment
+        })
+        .collect();
+    let mut redacted = segments.join("/");
+
+    if let Some(query) = query {
+        let params: Vec<String> = query
+            .split('&')
+            .map(|param| match param.split_once('=') {
+                Some((key, _)) if REDACTED_QUERY_PARAMS.contains(&key.to_ascii_lowercase().as_str()) => format!("{}=<redacted>", key),
+                _ => param.to_string(),
+            })
+            .collect();
+        redacted.push('?');
+        redacted.push_str(&params.join("&"));
+    }
+
+    redacted
+}
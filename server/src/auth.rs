@@ -0,0 +1,54 @@
+//! The operator token of the admin routes that change a node, shared by the api and validator
+//! servers.
+
+use crate::error::{ApiError, ErrorCode};
+use log::warn;
+use std::sync::Arc;
+use warp::path::FullPath;
+use warp::{Filter, Rejection};
+
+/// The operator token the admin routes that change the node ask for, `ADMIN_TOKEN`. Without one
+/// configured they refuse every request.
+#[derive(Clone, Default)]
+pub struct AdminToken {
+    token: Arc<String>,
+}
+
+impl AdminToken {
+    pub fn new(token: String) -> Self {
+        AdminToken { token: Arc::new(token) }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.token.is_empty()
+    }
+}
+
+/// Rejects with unauthorized unless the request carries `token` as `Authorization: Bearer
+/// <token>`. Fails closed, with no token configured nothing gets through.
+pub fn require_admin_token(token: AdminToken) -> impl Filter<Extract=(), Error=Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::path::full())
+        .and_then(move |authorization: Option<String>, path: FullPath| {
+            let token = token.clone();
+            async move {
+                if !token.is_enabled() {
+                    return Err(warp::reject::custom(ApiError::new(ErrorCode::Unauthorized, "Admin routes are disabled, no ADMIN_TOKEN is configured")));
+                }
+                match authorization.as_deref().and_then(|header| header.strip_prefix("Bearer ")) {
+                    Some(presented) if constant_time_eq(presented.as_bytes(), token.token.as_bytes()) => Ok(()),
+                    Some(_) => {
+                        warn!(target: "audit", "Rejected {} with an invalid admin token", path.as_str());
+                        Err(warp::reject::custom(ApiError::new(ErrorCode::Unauthorized, "Invalid admin token")))
+                    }
+                    None => Err(warp::reject::custom(ApiError::new(ErrorCode::Unauthorized, "Admin token required"))),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// Compares without returning early, so the time taken doesn't reveal how much of a token matched.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |difference, (a, b)| difference | (a ^ b)) == 0
+}
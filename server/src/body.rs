@@ -0,0 +1,76 @@
+use flate2::read::GzDecoder;
+use futures_util::{pin_mut, Stream, TryStreamExt};
+use serde::de::DeserializeOwned;
+use std::io::Read;
+use warp::hyper::body::Buf;
+use warp::reject::Reject;
+use warp::{Filter, Rejection};
+
+/// Upper bound on a decoded request body, protects against compressed payloads that expand to
+/// something much larger than what was sent.
+pub const MAX_DECODED_BODY_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Upper bound on a request body as sent. A compressed body is never larger than what it
+/// decodes to, so it is the same as `MAX_DECODED_BODY_SIZE`.
+pub const MAX_BODY_SIZE: u64 = MAX_DECODED_BODY_SIZE;
+
+#[derive(Debug)]
+pub struct InvalidBody(pub String);
+
+impl Reject for InvalidBody {}
+
+/// Like `warp::body::json`, but also accepts bodies sent with `Content-Encoding: gzip` and chunked
+/// bodies without a `Content-Length`. Bodies over `MAX_BODY_SIZE` are refused with payload too
+/// large, see `limited_body`.
+pub fn json<T: DeserializeOwned + Send>() -> impl Filter<Extract=(T,), Error=Rejection> + Clone {
+    warp::header::optional::<String>("content-encoding")
+        .and(warp::header::optional::<u64>("content-length"))
+        .and(warp::body::stream())
+        .and_then(|content_encoding: Option<String>, content_length: Option<u64>, body| async move {
+            let body = limited_body(content_length, body).await?;
+            decode(content_encoding.as_deref(), &body)
+                .and_then(|body| serde_json::from_slice(&body).map_err(|e| e.to_string()))
+                .map_err(|message| warp::reject::custom(InvalidBody(message)))
+        })
+}
+
+/// Reads the body, refusing it before reading anything when its `Content-Length` is over
+/// `MAX_BODY_SIZE` and as soon as a chunked body grows past it.
+async fn limited_body<B: Buf>(content_length: Option<u64>, body: impl Stream<Item=Result<B, warp::Error>>) -> Result<Vec<u8>, Rejection> {
+    let too_large = || warp::reject::custom(InvalidBody("Request body too large".to_string()));
+    if content_length.is_some_and(|length| length > MAX_BODY_SIZE) {
+        return Err(too_large());
+    }
+    pin_mut!(body);
+    let mut bytes = Vec::new();
+    while let Some(mut chunk) = body.try_next().await.map_err(|e| warp::reject::custom(InvalidBody(e.to_string())))? {
+        if (bytes.len() + chunk.remaining()) as u64 > MAX_BODY_SIZE {
+            return Err(too_large());
+        }
+        while chunk.has_remaining() {
+            let part = chunk.chunk();
+            bytes.extend_from_slice(part);
+            let read = part.len();
+            chunk.advance(read);
+        }
+    }
+    Ok(bytes)
+}
+
+fn decode(content_encoding: Option<&str>, body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::new();
+    match content_encoding.map(str::trim) {
+        None | Some("identity") => decoded.extend_from_slice(body),
+        Some(encoding) if encoding.eq_ignore_ascii_case("gzip") => {
+            GzDecoder::new(body)
+                .take(MAX_DECODED_BODY_SIZE + 1)
+                .read_to_end(&mut decoded)
+                .map_err(|e| format!("Invalid gzip body: {}", e))?;
+            if decoded.len() as u64 > MAX_DECODED_BODY_SIZE {
+                return Err("Request body too large".to_string());
+            }
+        }
+        Some(encoding) => return Err(format!("Unsupported content encoding: {}", encoding)),
+    }
+    Ok(decoded)
+}
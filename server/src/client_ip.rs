@@ -0,0 +1,206 @@
+//! Attribution of requests to clients behind load balancers. The peer of a connection is only the
+//! client when nothing sits in between. Peers listed as trusted proxies can name the client, with
+//! a PROXY protocol header at the start of the connection or with `X-Forwarded-For`, and anyone
+//! else's claims are ignored.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use warp::http::HeaderMap;
+
+pub const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Bits of an IPv6 address that identify a client. Hosts usually get a whole /64, so anything
+/// keyed on the full address could be dodged by hopping between addresses of the same network.
+pub const IPV6_CLIENT_PREFIX: u8 = 64;
+
+/// How long a trusted proxy has to send the PROXY header once connected.
+pub const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+const PROXY_V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+/// Longest v1 header the specification allows, `\r\n` included.
+const PROXY_V1_MAX_LENGTH: usize = 107;
+
+/// Addresses and networks allowed to name the client of a request, `TRUSTED_PROXIES`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>,
+    /// Whether trusted proxies open every connection with a PROXY protocol header
+    proxy_protocol: bool,
+}
+
+impl TrustedProxies {
+    /// Takes addresses and CIDR networks, `10.0.0.0/8`, `fd00::/8` or `127.0.0.1`.
+    pub fn new(networks: &[String], proxy_protocol: bool) -> Result<Self, String> {
+        let networks = networks.iter().map(|network| parse_network(network)).collect::<Result<_, _>>()?;
+        Ok(TrustedProxies { networks, proxy_protocol })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.networks.is_empty()
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.networks.iter().any(|(network, prefix)| mask(ip, *prefix) == Some(*network))
+    }
+
+    /// Whether the connection from `peer` starts with a PROXY header.
+    pub fn expects_proxy_header(&self, peer: SocketAddr) -> bool {
+        self.proxy_protocol && self.contains(peer.ip())
+    }
+
+    /// The client of a request on a connection from `remote`, the peer or the source its PROXY
+    /// header named. `X-Forwarded-For` is read from the right, every hop a trusted proxy added
+    /// is believed and the first address not trusted is the client. A malformed entry stops the
+    /// walk at the proxy that added it.
+    pub fn client(&self, remote: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = remote.to_canonical();
+        let forwarded: Vec<&str> = headers
+            .get_all(FORWARDED_FOR_HEADER)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        for hop in forwarded.into_iter().rev() {
+            if !self.contains(client) {
+                break;
+            }
+            match parse_hop(hop) {
+                Some(ip) => client = ip,
+                None => break,
+            }
+        }
+        client
+    }
+}
+
+/// Who a request came from, set on the request by `serve`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientAddr {
+    /// The client, as far as trusted proxies vouch for it
+    pub ip: IpAddr,
+    /// The other end of the connection
+    pub peer: SocketAddr,
+}
+
+impl ClientAddr {
+    pub fn is_proxied(&self) -> bool {
+        self.ip != self.peer.ip().to_canonical()
+    }
+}
+
+/// The part of `ip` rate limits, bans and logs attribute requests to: IPv4 addresses as they are,
+/// IPv6 addresses reduced to their /64. IPv4-mapped IPv6 addresses count as IPv4.
+pub fn attribution(ip: IpAddr) -> IpAddr {
+    let ip = ip.to_canonical();
+    mask(ip, if ip.is_ipv4() { 32 } else { IPV6_CLIENT_PREFIX }).unwrap_or(ip)
+}
+
+fn parse_network(network: &str) -> Result<(IpAddr, u8), String> {
+    let invalid = || format!("Invalid trusted proxy {:?}, expected an address or a CIDR network", network);
+    let (address, prefix) = match network.trim().split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix.parse::<u8>().map_err(|_| invalid())?)),
+        None => (network.trim(), None),
+    };
+    let address = IpAddr::from_str(address).map_err(|_| invalid())?.to_canonical();
+    let prefix = prefix.unwrap_or(if address.is_ipv4() { 32 } else { 128 });
+    mask(address, prefix).map(|address| (address, prefix)).ok_or_else(invalid)
+}
+
+/// `ip` with everything past `prefix` bits cleared, `None` for a prefix longer than the address.
+fn mask(ip: IpAddr, prefix: u8) -> Option<IpAddr> {
+    match ip {
+        IpAddr::V4(ip) if prefix <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            Some(IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask)))
+        }
+        IpAddr::V6(ip) if prefix <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            Some(IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask)))
+        }
+        _ => None,
+    }
+}
+
+/// An `X-Forwarded-For` entry: an address, with or without port, IPv6 ones possibly in brackets.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim().trim_matches('"');
+    IpAddr::from_str(hop)
+        .ok()
+        .or_else(|| SocketAddr::from_str(hop).ok().map(|addr| addr.ip()))
+        .or_else(|| hop.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+        .map(IpAddr::to_canonical)
+}
+
+/// Reads the PROXY protocol header, v1 or v2, at the start of a connection, leaving the stream at
+/// the first byte after it. Returns the source it names, `None` for `UNKNOWN` and `LOCAL`
+/// connections such as the proxy's health checks.
+pub async fn read_proxy_header<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<Option<SocketAddr>> {
+    let mut signature = [0u8; 12];
+    stream.read_exact(&mut signature).await?;
+    if signature == PROXY_V2_SIGNATURE {
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header).await?;
+        let mut addresses = vec![0u8; u16::from_be_bytes([header[2], header[3]]) as usize];
+        stream.read_exact(&mut addresses).await?;
+        return parse_proxy_v2(header[0], header[1], &addresses);
+    }
+    if !signature.starts_with(b"PROXY ") {
+        return Err(invalid_header("missing PROXY header"));
+    }
+    let mut line = signature.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= PROXY_V1_MAX_LENGTH {
+            return Err(invalid_header("PROXY header too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+    let line = std::str::from_utf8(&line).map_err(|_| invalid_header("PROXY header isn't text"))?;
+    parse_proxy_v1(line.trim_end())
+}
+
+/// `PROXY TCP4 <source> <destination> <source port> <destination port>`, or `PROXY UNKNOWN ...`.
+fn parse_proxy_v1(line: &str) -> io::Result<Option<SocketAddr>> {
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", protocol @ ("TCP4" | "TCP6"), source, _, source_port, _] => {
+            let ip = IpAddr::from_str(source).map_err(|_| invalid_header("bad source address"))?;
+            if ip.is_ipv4() != (*protocol == "TCP4") {
+                return Err(invalid_header("source address doesn't match the protocol"));
+            }
+            let port = source_port.parse().map_err(|_| invalid_header("bad source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid_header("malformed v1 header")),
+    }
+}
+
+/// The v2 address block after the 16 byte header, only TCP and UDP over IPv4 and IPv6 carry a
+/// source.
+fn parse_proxy_v2(version_command: u8, family: u8, addresses: &[u8]) -> io::Result<Option<SocketAddr>> {
+    match version_command {
+        0x20 => return Ok(None),
+        0x21 => {}
+        _ => return Err(invalid_header("unsupported v2 version or command")),
+    }
+    match family {
+        0x11 | 0x12 if addresses.len() >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), u16::from_be_bytes([addresses[8], addresses[9]]))))
+        }
+        0x21 | 0x22 if addresses.len() >= 36 => {
+            let ip: [u8; 16] = addresses[..16].try_into().expect("16 bytes");
+            Ok(Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), u16::from_be_bytes([addresses[32], addresses[33]]))))
+        }
+        0x11 | 0x12 | 0x21 | 0x22 => Err(invalid_header("truncated v2 addresses")),
+        _ => Ok(None),
+    }
+}
+
+fn invalid_header(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid PROXY protocol header: {}", reason))
+}
@@ -0,0 +1,148 @@
+use crate::body::InvalidBody;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::fmt;
+use warp::filters::body::BodyDeserializeError;
+use warp::http::StatusCode;
+use warp::reject::{InvalidQuery, MethodNotAllowed, MissingHeader, PayloadTooLarge, Reject, UnsupportedMediaType};
+use warp::reply::Response;
+use warp::{Rejection, Reply};
+
+/// What went wrong, for clients to branch on. Each code answers with one HTTP status.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    /// A path or query parameter can't be parsed, or the request asks for something impossible
+    InvalidRequest,
+    /// The body isn't the JSON the route expects, or is sent in an unsupported encoding
+    InvalidBody,
+    /// The transaction can't be decoded, isn't signed or fails the cost model
+    InvalidTransaction,
+    Unauthorized,
+    /// The caller, or the payer of the transaction, is banned
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    /// The request clashes with something submitted or settled before
+    Conflict,
+    PayloadTooLarge,
+    /// The caller exceeded its rate limit, retrying later succeeds
+    TooManyRequests,
+    /// Well formed, but the rollup can't act on it in its current state
+    Unprocessable,
+    /// The node is shedding load or the feature is turned off, retrying later may succeed
+    Unavailable,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidRequest | ErrorCode::InvalidBody | ErrorCode::InvalidTransaction => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::Unprocessable => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// The body of every error response, `{"code": "notFound", "message": "..."}` with the status of
+/// the code. Handlers reply with it directly, filters reject with it and `handle_rejection`
+/// turns the rejection into the same response.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    /// For people, clients should only rely on the code
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ApiError { code, message: message.into() }
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidRequest, message)
+    }
+
+    pub fn invalid_transaction(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidTransaction, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Conflict, message)
+    }
+
+    pub fn unprocessable(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Unprocessable, message)
+    }
+
+    pub fn unavailable(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Unavailable, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.code.status()
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+impl Reject for ApiError {}
+
+impl Reply for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        warp::reply::with_status(warp::reply::json(&self), status).into_response()
+    }
+}
+
+/// Answers every rejection with an `ApiError`, for `Filter::recover` on the complete router.
+/// Rejections warp produces itself get the code closest to their status.
+pub async fn handle_rejection(rejection: Rejection) -> Result<Response, Infallible> {
+    let api_error = if let Some(api_error) = rejection.find::<ApiError>() {
+        api_error.clone()
+    } else if rejection.is_not_found() {
+        ApiError::not_found("Not found")
+    } else if let Some(InvalidBody(message)) = rejection.find::<InvalidBody>() {
+        let code = if message.contains("too large") { ErrorCode::PayloadTooLarge } else { ErrorCode::InvalidBody };
+        ApiError::new(code, message.clone())
+    } else if let Some(error) = rejection.find::<BodyDeserializeError>() {
+        ApiError::new(ErrorCode::InvalidBody, error.to_string())
+    } else if let Some(error) = rejection.find::<UnsupportedMediaType>() {
+        ApiError::new(ErrorCode::InvalidBody, error.to_string())
+    } else if let Some(error) = rejection.find::<PayloadTooLarge>() {
+        ApiError::new(ErrorCode::PayloadTooLarge, error.to_string())
+    } else if let Some(error) = rejection.find::<InvalidQuery>() {
+        ApiError::invalid_request(error.to_string())
+    } else if let Some(error) = rejection.find::<MissingHeader>() {
+        ApiError::invalid_request(error.to_string())
+    } else if let Some(error) = rejection.find::<MethodNotAllowed>() {
+        ApiError::new(ErrorCode::MethodNotAllowed, error.to_string())
+    } else {
+        error!("Unhandled rejection: {:?}", rejection);
+        ApiError::internal("Internal server error")
+    };
+    Ok(api_error.into_response())
+}
+
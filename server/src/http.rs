@@ -0,0 +1,155 @@
+use crate::access_log::AccessLog;
+use crate::client_ip::{read_proxy_header, ClientAddr, TrustedProxies, PROXY_HEADER_TIMEOUT};
+use crate::request_id::{self, REQUEST_ID_HEADER};
+use log::{error, info, warn};
+use std::convert::Infallible;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use warp::http::{HeaderValue, Request};
+use warp::hyper::server::accept;
+use warp::hyper::service::{make_service_fn, service_fn, Service};
+use warp::hyper::{Body, Server};
+use warp::{Filter, Reply};
+
+/// Accepted connections waiting for the server to pick them up.
+const ACCEPT_BACKLOG: usize = 1024;
+
+/// Extracts the client's address, from `serve` or, for routes served by warp itself, from warp.
+pub fn client_ip() -> impl Filter<Extract=(Option<IpAddr>,), Error=Infallible> + Clone {
+    warp::ext::optional::<ClientAddr>()
+        .and(warp::addr::remote())
+        .map(|served: Option<ClientAddr>, remote: Option<SocketAddr>| {
+            served.map(|client| client.ip).or(remote.map(|addr| addr.ip().to_canonical()))
+        })
+}
+
+/// Serves `routes` on `addr`, running every request through the access log.
+///
+/// Every request gets an ID, the caller's `X-Request-Id` or a generated one. It is set on the
+/// request for the routes, returned in the `X-Request-Id` response header and attached to every
+/// log line written while the request is handled.
+///
+/// The client of every request is resolved once, see `TrustedProxies::client`, and set on the
+/// request as `ClientAddr` for the routes, the access log and the request's log lines.
+///
+/// This replaces `warp::serve` so the access log sees the final response, including the ones warp
+/// produces for rejected requests.
+pub async fn serve<F>(routes: F, addr: impl Into<SocketAddr>, access_log: AccessLog, proxies: TrustedProxies)
+where
+    F: Filter + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let addr = addr.into();
+    let service = warp::service(routes);
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(error) => {
+            error!("Server error: {}", error);
+            return;
+        }
+    };
+    let (connections, mut accepted) = mpsc::channel(ACCEPT_BACKLOG);
+    tokio::spawn(accept_connections(listener, proxies.clone(), connections));
+    let incoming = accept::poll_fn(move |cx| accepted.poll_recv(cx).map(|connection| connection.map(Ok::<_, io::Error>)));
+
+    let make_service = make_service_fn(move |connection: &Connection| {
+        let peer = connection.peer;
+        let remote = connection.source.map(|source| source.ip()).unwrap_or(peer.ip());
+        let service = service.clone();
+        let access_log = access_log.clone();
+        let proxies = proxies.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |mut request: Request<Body>| {
+                let mut service = service.clone();
+                let request_id = request_id::from_headers(request.headers());
+                let header_value = HeaderValue::from_str(&request_id).expect("Request IDs are valid header values");
+                request.headers_mut().insert(REQUEST_ID_HEADER, header_value.clone());
+                let client = ClientAddr { ip: proxies.client(remote, request.headers()), peer };
+                request.extensions_mut().insert(client);
+                let entry = access_log.start(&request, client);
+                let counter = access_log.clone();
+                async move {
+                    let mut response = service.call(request).await?;
+                    response.headers_mut().insert(REQUEST_ID_HEADER, header_value);
+                    counter.count(response.status());
+                    if let Some(entry) = entry {
+                        entry.finish(&response);
+                    }
+                    Ok::<_, Infallible>(response)
+                }
+                .instrument(tracing::info_span!("request", id = %request_id, client = %client.ip))
+            }))
+        }
+    });
+
+    info!("Listening on {}", addr);
+    if let Err(error) = Server::builder(incoming).serve(make_service).await {
+        error!("Server error: {}", error);
+    }
+}
+
+/// A connection, with the source its PROXY header named when it came from a trusted proxy.
+struct Connection {
+    stream: TcpStream,
+    peer: SocketAddr,
+    source: Option<SocketAddr>,
+}
+
+/// Hands accepted connections to the server. Connections from trusted proxies are read up to the
+/// end of their PROXY header first, each in its own task so a slow proxy doesn't hold up others.
+async fn accept_connections(listener: TcpListener, proxies: TrustedProxies, connections: mpsc::Sender<Connection>) {
+    loop {
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(error) => {
+                // Usually out of file descriptors, give connections time to close
+                warn!("Failed to accept a connection: {}", error);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        if !proxies.expects_proxy_header(peer) {
+            if connections.send(Connection { stream, peer, source: None }).await.is_err() {
+                return;
+            }
+            continue;
+        }
+        let connections = connections.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(PROXY_HEADER_TIMEOUT, read_proxy_header(&mut stream)).await {
+                Ok(Ok(source)) => {
+                    let _ = connections.send(Connection { stream, peer, source }).await;
+                }
+                Ok(Err(error)) => warn!("Dropped connection from {}: {}", peer, error),
+                Err(_) => warn!("Dropped connection from {}, no PROXY header within {:?}", peer, PROXY_HEADER_TIMEOUT),
+            }
+        });
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
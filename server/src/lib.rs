@@ -0,0 +1,9 @@
+pub mod access_log;
+pub mod auth;
+pub mod body;
+pub mod client_ip;
+pub mod error;
+pub mod http;
+pub mod logging;
+pub mod request_id;
+pub mod swagger;
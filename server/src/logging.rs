@@ -0,0 +1,144 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer, Registry};
+
+/// Node log output. Logs always go to stderr and, with `file_path` set, also to a rotated file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogSettings {
+    /// Filter directives in `RUST_LOG` syntax, e.g. `info,state_commitment=debug,access_log=warn`
+    pub level: String,
+    pub file_path: String,
+    /// `minutely`, `hourly`, `daily` or `never`, ignored when `max_file_size` is set
+    pub rotation: String,
+    /// Rotate the file once it reaches this many bytes instead of by time, 0 to rotate by time
+    pub max_file_size: u64,
+    /// Rotated files kept next to the current one, older files are deleted
+    pub max_files: usize,
+    /// `text` or `json`
+    pub format: String,
+}
+
+/// Flushes buffered file output when dropped, keep it alive for the lifetime of the process.
+pub struct LogGuard(Option<WorkerGuard>);
+
+/// Installs the global logger. `log` records from dependencies are routed through it as well.
+pub fn init(settings: &LogSettings) -> LogGuard {
+    let filter = EnvFilter::try_new(&settings.level).unwrap_or_else(|_| EnvFilter::new("info"));
+    let json = settings.format.eq_ignore_ascii_case("json");
+
+    let mut layers = vec![format_layer(json, io::stderr, true)];
+    let mut guard = None;
+    let mut file_error = None;
+    if !settings.file_path.is_empty() {
+        match file_writer(settings) {
+            Ok(writer) => {
+                let (writer, worker_guard) = tracing_appender::non_blocking(writer);
+                layers.push(format_layer(json, writer, false));
+                guard = Some(worker_guard);
+            }
+            Err(error) => file_error = Some(error),
+        }
+    }
+
+    if let Err(error) = Registry::default().with(layers).with(filter).try_init() {
+        eprintln!("Logger already initialized: {}", error);
+    }
+    if let Some(error) = file_error {
+        log::error!("Logging to stderr only, can't open log file {}: {}", settings.file_path, error);
+    }
+    LogGuard(guard)
+}
+
+fn format_layer<W>(json: bool, writer: W, ansi: bool) -> Box<dyn Layer<Registry> + Send + Sync>
+where
+    W: for<'writer> fmt::MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer).with_ansi(ansi);
+    if json {
+        layer.json().boxed()
+    } else {
+        layer.boxed()
+    }
+}
+
+fn file_writer(settings: &LogSettings) -> io::Result<Box<dyn Write + Send>> {
+    let path = Path::new(&settings.file_path);
+    if settings.max_file_size > 0 {
+        return Ok(Box::new(SizeRotatingFile::open(path.to_path_buf(), settings.max_file_size, settings.max_files)?));
+    }
+
+    let rotation = match settings.rotation.to_ascii_lowercase().as_str() {
+        "minutely" => Rotation::MINUTELY,
+        "hourly" => Rotation::HOURLY,
+        "never" => Rotation::NEVER,
+        _ => Rotation::DAILY,
+    };
+    let directory = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("trollup.log");
+    let appender = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(file_name)
+        .max_log_files(settings.max_files.max(1))
+        .build(directory)
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+    Ok(Box::new(appender))
+}
+
+/// Appends to `path` and rotates it once it reaches `max_size` bytes: `path` is renamed to
+/// `path.1`, `path.1` to `path.2` and so on, and files beyond `max_files` are removed.
+struct SizeRotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl SizeRotatingFile {
+    fn open(path: PathBuf, max_size: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(SizeRotatingFile { path, max_size, max_files, file, size })
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let _ = fs::remove_file(self.rotated(self.max_files.max(1)));
+        for index in (1..self.max_files.max(1)).rev() {
+            let _ = fs::rename(self.rotated(index), self.rotated(index + 1));
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -0,0 +1,35 @@
+use std::convert::Infallible;
+use warp::http::HeaderMap;
+use warp::Filter;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest incoming `X-Request-Id` that is honored, longer ones are replaced.
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+/// The caller's `X-Request-Id` when it is short and only uses characters that are safe to log
+/// and echo back, otherwise a new random ID.
+pub fn from_headers(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(generate)
+}
+
+pub fn generate() -> String {
+    hex::encode(rand::random::<[u8; 16]>())
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id.bytes().all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b':'))
+}
+
+/// Extracts the ID of the current request. `serve` sets the header on every request before it
+/// reaches the routes, so this is only empty for routes served some other way.
+pub fn request_id() -> impl Filter<Extract=(String,), Error=Infallible> + Clone {
+    warp::header::optional::<String>(REQUEST_ID_HEADER).map(|id: Option<String>| id.unwrap_or_default())
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use utoipa::openapi::OpenApi;
+use utoipa_swagger_ui::Config as SwaggerConfig;
+use warp::{
+    http::Uri,
+    hyper::{Response, StatusCode},
+    path::{FullPath, Tail},
+    Filter, Rejection, Reply,
+};
+
+/// Serves `openapi` at `/api-doc.json` and the Swagger UI for it at `/swagger-ui`.
+pub fn routes(openapi: OpenApi) -> impl Filter<Extract=impl Reply, Error=Rejection> + Clone {
+    let openapi = Arc::new(openapi);
+    let api_doc_config = Arc::new(SwaggerConfig::from("/api-doc.json"));
+
+    let api_doc = warp::path("api-doc.json")
+        .and(warp::get())
+        .map(move || warp::reply::json(openapi.as_ref()));
+
+    let swagger_ui = warp::path("swagger-ui")
+        .and(warp::get())
+        .and(warp::path::full())
+        .and(warp::path::tail())
+        .and(warp::any().map(move || api_doc_config.clone()))
+        .and_then(serve_swagger);
+
+    api_doc.or(swagger_ui)
+}
+
+async fn serve_swagger(
+    full_path: FullPath,
+    tail: Tail,
+    config: Arc<SwaggerConfig<'static>>,
+) -> Result<Box<dyn Reply + 'static>, Rejection> {
+    if full_path.as_str() == "/swagger-ui" {
+        return Ok(Box::new(warp::redirect::found(Uri::from_static(
+            "/swagger-ui/",
+        ))));
+    }
+
+    let path = tail.as_str();
+    match utoipa_swagger_ui::serve(path, config) {
+        Ok(file) => {
+            if let Some(file) = file {
+                Ok(Box::new(
+                    Response::builder()
+                        .header("Content-Type", file.content_type)
+                        .body(file.bytes),
+                ))
+            } else {
+                Ok(Box::new(StatusCode::NOT_FOUND))
+            }
+        }
+        Err(error) => Ok(Box::new(
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(error.to_string()),
+        )),
+    }
+}
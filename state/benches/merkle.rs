@@ -0,0 +1,44 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rs_merkle::algorithms::Sha256;
+use rs_merkle::MerkleTree;
+use solana_sdk::pubkey::Pubkey;
+use state::account_state::AccountState;
+use state::merkle::{hash_leaves, parallel_root};
+use state::witness::account_leaf;
+
+fn accounts(count: usize) -> Vec<AccountState> {
+    (0..count)
+        .map(|i| AccountState {
+            address: Pubkey::new_unique(),
+            lamports: i as u64,
+            data: vec![7; 165],
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+            data_root: None,
+        })
+        .collect()
+}
+
+/// Account tree of a batch, hashing the leaves and building the tree serially as the committer
+/// used to, against the rayon version.
+fn account_tree(c: &mut Criterion) {
+    let mut group = c.benchmark_group("account_tree");
+    group.sample_size(10);
+    for count in [1_000, 10_000, 100_000] {
+        let batch = accounts(count);
+        group.bench_with_input(BenchmarkId::new("serial", count), &batch, |b, batch| {
+            b.iter(|| {
+                let leaves: Vec<[u8; 32]> = batch.iter().map(account_leaf).collect();
+                MerkleTree::<Sha256>::from_leaves(&leaves).root()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", count), &batch, |b, batch| {
+            b.iter(|| parallel_root(&hash_leaves(batch, account_leaf)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, account_tree);
+criterion_main!(benches);
@@ -0,0 +1,123 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use rs_merkle::algorithms::Sha256;
+use rs_merkle::{Hasher, MerkleProof, MerkleTree};
+use serde_derive::{Deserialize, Serialize};
+
+/// Bytes of account data per leaf of the data tree, the last chunk may be shorter.
+pub const DATA_CHUNK_SIZE: usize = 64;
+
+/// Root committing to an account's data: the SHA-256 of the data length followed by the root of
+/// the tree over its chunks. The length fixes the number of leaves a range proof is checked
+/// against.
+pub fn data_root(data: &[u8]) -> [u8; 32] {
+    let leaves: Vec<[u8; 32]> = data.chunks(DATA_CHUNK_SIZE).map(Sha256::hash).collect();
+    let tree_root = MerkleTree::<Sha256>::from_leaves(&leaves).root().unwrap_or_default();
+    bind_length(data.len() as u64, &tree_root)
+}
+
+fn bind_length(data_len: u64, tree_root: &[u8; 32]) -> [u8; 32] {
+    let mut preimage = data_len.to_le_bytes().to_vec();
+    preimage.extend_from_slice(tree_root);
+    Sha256::hash(&preimage)
+}
+
+/// The chunks covering a byte range of an account's data with their proof against its
+/// `data_root`, so a field can be proven without the rest of the data.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataRangeProof {
+    pub data_len: u64,
+    /// Index of the first chunk, its data starts at `first_chunk * DATA_CHUNK_SIZE`
+    pub first_chunk: u64,
+    pub chunks: Vec<Vec<u8>>,
+    /// Multi-proof of the chunk leaves, as produced by rs_merkle
+    pub proof: Vec<[u8; 32]>,
+}
+
+impl DataRangeProof {
+    /// Proof of `length` bytes of `data` from `offset`, `None` when the range is empty or runs
+    /// past the end of the data.
+    pub fn build(data: &[u8], offset: u64, length: u64) -> Option<Self> {
+        let end = offset.checked_add(length)?;
+        if length == 0 || end > data.len() as u64 {
+            return None;
+        }
+        let first_chunk = offset as usize / DATA_CHUNK_SIZE;
+        let last_chunk = (end as usize - 1) / DATA_CHUNK_SIZE;
+        let chunks: Vec<&[u8]> = data.chunks(DATA_CHUNK_SIZE).collect();
+        let leaves: Vec<[u8; 32]> = chunks.iter().map(|chunk| Sha256::hash(chunk)).collect();
+        let indices: Vec<usize> = (first_chunk..=last_chunk).collect();
+        Some(DataRangeProof {
+            data_len: data.len() as u64,
+            first_chunk: first_chunk as u64,
+            chunks: chunks[first_chunk..=last_chunk].iter().map(|chunk| chunk.to_vec()).collect(),
+            proof: MerkleTree::<Sha256>::from_leaves(&leaves).proof(&indices).proof_hashes().to_vec(),
+        })
+    }
+
+    pub fn verify(&self, data_root: &[u8; 32]) -> bool {
+        let leaf_count = (self.data_len as usize).div_ceil(DATA_CHUNK_SIZE);
+        let indices: Vec<usize> = (self.first_chunk as usize..self.first_chunk as usize + self.chunks.len()).collect();
+        if self.chunks.is_empty() || indices.last().is_some_and(|last| *last >= leaf_count) {
+            return false;
+        }
+        let leaves: Vec<[u8; 32]> = self.chunks.iter().map(|chunk| Sha256::hash(chunk)).collect();
+        MerkleProof::<Sha256>::new(self.proof.clone())
+            .root(&indices, &leaves, leaf_count)
+            .is_ok_and(|tree_root| bind_length(self.data_len, &tree_root) == *data_root)
+    }
+
+    /// The proven bytes `offset..offset + length`, `None` when the chunks don't cover them.
+    pub fn bytes(&self, offset: u64, length: u64) -> Option<Vec<u8>> {
+        let start = (offset as usize).checked_sub(self.first_chunk as usize * DATA_CHUNK_SIZE)?;
+        let covered: Vec<u8> = self.chunks.concat();
+        covered.get(start..start.checked_add(length as usize)?).map(<[u8]>::to_vec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_range_proof_verifies_against_the_data_root() {
+        let data = data(1000);
+        let root = data_root(&data);
+        for (offset, length) in [(0, 1), (60, 10), (128, 64), (999, 1), (0, 1000)] {
+            let proof = DataRangeProof::build(&data, offset, length).unwrap();
+            assert!(proof.verify(&root), "range {}+{}", offset, length);
+            assert_eq!(proof.bytes(offset, length).unwrap(), data[offset as usize..(offset + length) as usize]);
+        }
+    }
+
+    #[test]
+    fn test_range_proof_rejects_tampered_chunks_and_lengths() {
+        let data = data(300);
+        let root = data_root(&data);
+        let proof = DataRangeProof::build(&data, 70, 20).unwrap();
+
+        let mut tampered = proof.clone();
+        tampered.chunks[0][0] ^= 1;
+        assert!(!tampered.verify(&root));
+
+        let mut wrong_length = proof.clone();
+        wrong_length.data_len = 320;
+        assert!(!wrong_length.verify(&root));
+
+        let mut shifted = proof;
+        shifted.first_chunk += 1;
+        assert!(!shifted.verify(&root));
+    }
+
+    #[test]
+    fn test_range_outside_the_data_has_no_proof() {
+        let data = data(100);
+        assert!(DataRangeProof::build(&data, 90, 11).is_none());
+        assert!(DataRangeProof::build(&data, 10, 0).is_none());
+        assert!(DataRangeProof::build(&data, u64::MAX, 2).is_none());
+    }
+}
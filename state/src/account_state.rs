@@ -1,3 +1,4 @@
+use crate::account_data::data_root;
 use crate::state_record::StateRecord;
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
@@ -19,12 +20,81 @@ pub struct AccountState {
     pub executable: bool,
     /// the epoch at which this account will next owe rent
     pub rent_epoch: Epoch,
+    /// Root of the chunk tree over `data`, set for accounts with at least
+    /// `ACCOUNT_DATA_COMMITMENT_MIN_BYTES` of data. The account's leaf then commits to the data
+    /// through it, so a range of the data can be proven on its own.
+    #[serde(default)]
+    pub data_root: Option<[u8; 32]>,
+}
+
+impl AccountState {
+    /// Sets `data_root` when the data is at least `min_bytes` long, 0 disables data commitments.
+    pub fn commit_data(&mut self, min_bytes: usize) {
+        self.data_root = (min_bytes > 0 && self.data.len() >= min_bytes).then(|| data_root(&self.data));
+    }
+
+    /// The account with its data left out, what the leaf of an account with a `data_root` hashes.
+    pub fn without_data(&self) -> AccountState {
+        AccountState { data: Vec::new(), ..self.clone() }
+    }
+}
+
+impl AccountState {
+    /// What the account's leaf hashes, see `witness::account_leaf`: the account as it was encoded
+    /// before `data_root`, which the vault's `RollupAccount` mirrors, followed by the data root
+    /// in place of the data when there is one. Accounts without a data root keep their old leaf.
+    pub fn leaf_bytes(&self) -> Vec<u8> {
+        let data: &[u8] = if self.data_root.is_some() { &[] } else { &self.data };
+        let mut bytes = Vec::with_capacity(32 + 8 + 4 + data.len() + 32 + 1 + 8 + 32);
+        BorshSerialize::serialize(&self.address, &mut bytes).unwrap();
+        BorshSerialize::serialize(&self.lamports, &mut bytes).unwrap();
+        BorshSerialize::serialize(&data, &mut bytes).unwrap();
+        BorshSerialize::serialize(&self.owner, &mut bytes).unwrap();
+        BorshSerialize::serialize(&self.executable, &mut bytes).unwrap();
+        BorshSerialize::serialize(&self.rent_epoch, &mut bytes).unwrap();
+        if let Some(data_root) = &self.data_root {
+            bytes.extend_from_slice(data_root);
+        }
+        bytes
+    }
+}
+
+/// `AccountState` as stored before `data_root`, encoding version 0.
+#[derive(BorshDeserialize)]
+struct AccountStateV0 {
+    address: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+    owner: Pubkey,
+    executable: bool,
+    rent_epoch: Epoch,
 }
 
 impl StateRecord for AccountState {
+    /// 1 added `data_root`
+    const ENCODING_VERSION: u32 = 1;
+
     fn get_key(&self) -> [u8; 32] {
         self.address.to_bytes()
     }
+
+    fn upgrade(version: u32, bytes: &[u8]) -> Option<Self> {
+        match version {
+            0 => {
+                let account = borsh::from_slice::<AccountStateV0>(bytes).ok()?;
+                Some(AccountState {
+                    address: account.address,
+                    lamports: account.lamports,
+                    data: account.data,
+                    owner: account.owner,
+                    executable: account.executable,
+                    rent_epoch: account.rent_epoch,
+                    data_root: None,
+                })
+            }
+            _ => None,
+        }
+    }
 }
 
 impl From<AccountSharedData> for AccountState {
@@ -37,6 +107,7 @@ impl From<AccountSharedData> for AccountState {
             owner: account.owner,
             executable: account.executable,
             rent_epoch: account.rent_epoch,
+            data_root: None,
         }
     }
 }
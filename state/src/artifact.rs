@@ -0,0 +1,90 @@
+//! Detached signatures over the artifacts a node mirrors to object stores, DA layers or CDNs,
+//! such as the batch data of finalized blocks. The sequencer signs each object with its key and
+//! publishes the signature next to it as `<name>.sig`, so whoever reads a mirror can check an
+//! object came from the sequencer before trusting it.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use std::str::FromStr;
+
+/// Separates artifact signatures from every other message the sequencer key signs.
+pub const ARTIFACT_DOMAIN: &[u8] = b"trollup:artifact:v1";
+
+/// A detached signature, stored as JSON next to the object it signs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactSignature {
+    /// Base58 public key of the signer
+    pub signer: String,
+    /// Base58 ed25519 signature over `artifact_digest`
+    pub signature: String,
+}
+
+/// What the signer signs: SHA-256 over the domain, the object name and its bytes. The name is
+/// bound in, so a mirror can't serve one block's object, signature included, as another's.
+pub fn artifact_digest(name: &str, bytes: &[u8]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(ARTIFACT_DOMAIN)
+        .chain_update((name.len() as u32).to_le_bytes())
+        .chain_update(name.as_bytes())
+        .chain_update(bytes)
+        .finalize()
+        .into()
+}
+
+/// Name of the signature object of the object `name`.
+pub fn signature_name(name: &str) -> String {
+    format!("{}.sig", name)
+}
+
+impl ArtifactSignature {
+    pub fn sign(name: &str, bytes: &[u8], signer: &Keypair) -> Self {
+        ArtifactSignature {
+            signer: signer.pubkey().to_string(),
+            signature: signer.sign_message(&artifact_digest(name, bytes)).to_string(),
+        }
+    }
+
+    /// Checks the signature covers `bytes` under `name` and was made by `expected_signer`.
+    pub fn verify(&self, name: &str, bytes: &[u8], expected_signer: &Pubkey) -> Result<(), String> {
+        let signer = Pubkey::from_str(&self.signer).map_err(|_| format!("Invalid signer {}", self.signer))?;
+        if signer != *expected_signer {
+            return Err(format!("{} is signed by {}, not {}", name, signer, expected_signer));
+        }
+        let signature = Signature::from_str(&self.signature).map_err(|_| format!("Invalid signature of {}", name))?;
+        if !signature.verify(signer.as_ref(), &artifact_digest(name, bytes)) {
+            return Err(format!("The signature of {} doesn't match its content", name));
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("ArtifactSignature serializes to JSON")
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(bytes).map_err(|error| format!("Invalid artifact signature: {}", error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_binds_signer_name_and_content() {
+        let sequencer = Keypair::new();
+        let signature = ArtifactSignature::sign("00000000000000000007.borsh", b"batch", &sequencer);
+        assert!(signature.verify("00000000000000000007.borsh", b"batch", &sequencer.pubkey()).is_ok());
+
+        assert!(signature.verify("00000000000000000007.borsh", b"batcH", &sequencer.pubkey()).is_err());
+        assert!(signature.verify("00000000000000000008.borsh", b"batch", &sequencer.pubkey()).is_err());
+        assert!(signature.verify("00000000000000000007.borsh", b"batch", &Pubkey::new_unique()).is_err());
+
+        let decoded = ArtifactSignature::from_json(&signature.to_json()).unwrap();
+        assert_eq!(decoded, signature);
+        assert!(ArtifactSignature::from_json(b"not json").is_err());
+    }
+}
@@ -0,0 +1,21 @@
+use crate::parameters::GovernedParameters;
+use crate::transaction::TrollupTransaction;
+use crate::witness::WitnessBundle;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// What it takes to rebuild a finalized block by replaying it, published to the batch data
+/// store under the block number.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct BatchData {
+    pub block_number: u64,
+    pub account_state_root: [u8; 32],
+    /// The block's transactions. When optimistic and proved transactions were executed in the
+    /// same batch each block only has its own, while the witness has them all.
+    pub transactions: Vec<TrollupTransaction>,
+    pub transaction_ids: Vec<[u8; 32]>,
+    pub fees: u64,
+    /// `None` for heartbeat blocks, which change nothing
+    pub witness: Option<WitnessBundle>,
+    /// The governed parameters the block was produced under, its header records their hash
+    pub parameters: GovernedParameters,
+}
@@ -0,0 +1,41 @@
+use crate::state_record::StateRecord;
+use borsh::{BorshDeserialize, BorshSerialize};
+use sha2::{Digest, Sha256};
+
+/// An immutable artifact (verifying key, program, ...) stored once under the hash of its contents.
+/// It is written when first stored and never again, references to it are counted in
+/// `BlobReferences`.
+#[derive(Debug, Clone, Default, BorshSerialize, BorshDeserialize)]
+pub struct Blob {
+    pub hash: [u8; 32],
+    pub data: Vec<u8>,
+    /// References of a blob stored before they were counted in `BlobReferences`, moved there
+    /// when the store is opened. Zero for every other blob
+    pub ref_count: u64,
+}
+
+impl Blob {
+    pub fn content_hash(data: &[u8]) -> [u8; 32] {
+        Sha256::digest(data).into()
+    }
+}
+
+impl StateRecord for Blob {
+    fn get_key(&self) -> [u8; 32] {
+        self.hash
+    }
+}
+
+/// Number of records referencing the blob `hash`, it can be pruned once this drops to zero. Kept
+/// apart from the blob so taking a reference doesn't rewrite its contents.
+#[derive(Debug, Clone, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct BlobReferences {
+    pub hash: [u8; 32],
+    pub count: u64,
+}
+
+impl StateRecord for BlobReferences {
+    fn get_key(&self) -> [u8; 32] {
+        self.hash
+    }
+}
@@ -1,7 +1,26 @@
+use crate::challenge::ChallengeOutcome;
+use crate::cross_rollup::OutboxMessage;
 use crate::state_record::StateRecord;
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use trollup_commitment_message::BlockProducer;
+
+/// Where a block's state root landed on Solana. Filled in once the commitment transaction is
+/// known, either slot or signature can be missing until the confirmation tracker resolved it.
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct L1Anchor {
+    pub slot: Option<u64>,
+    /// Base58 signature of the transaction that committed the state root
+    pub signature: Option<String>,
+}
+
+impl L1Anchor {
+    pub fn is_resolved(&self) -> bool {
+        self.slot.is_some() && self.signature.is_some()
+    }
+}
 
 // TODO add transaction proof?
 #[derive(Debug, BorshDeserialize, BorshSerialize, Clone, Default, Serialize, Deserialize)]
@@ -14,14 +33,37 @@ pub struct Block {
     pub accounts_merkle_root: Box<[u8; 32]>,
     pub accounts_zk_proof: Vec<u8>,
     pub transactions: Vec<[u8; 32]>,
-    pub accounts: Vec<[u8; 32]>
+    pub accounts: Vec<[u8; 32]>,
+    /// Public inputs of `accounts_zk_proof`, prepared against the verifying key (uncompressed G1)
+    pub accounts_zk_public_inputs: Vec<u8>,
+    /// Blob store hash of the verifying key the proof was generated for
+    pub verifying_key_hash: [u8; 32],
+    pub l1_anchor: L1Anchor,
+    /// Leaves of the account tree in order, so inclusion proofs can be built after the block's
+    /// accounts changed again
+    pub account_leaves: Vec<[u8; 32]>,
+    /// Root of the block's cross-rollup outbox, signed into the L1 commitment. All zeros when the
+    /// block sent no messages
+    pub outbox_root: [u8; 32],
+    /// Messages of the outbox tree in leaf order, for building inclusion proofs
+    pub outbox_messages: Vec<OutboxMessage>,
+    /// Lamports charged in fees by the block's transactions
+    pub fees: u64,
+    /// Challenges resolved since the previous block
+    pub challenge_outcomes: Vec<ChallengeOutcome>,
+    /// Sequencer and node version that produced the block, part of the block hash and signed
+    /// into the L1 commitment. Set with `set_producer`
+    producer: BlockProducer,
+    /// Hash of the governed parameters the block was produced under, part of the block hash. Set
+    /// with `set_parameters_hash`
+    parameters_hash: [u8; 32],
 }
 
 impl Block {
-    pub fn new(block_number: u64, previous_block: [u8; 32], transactions_merkle_root: Box<[u8; 32]>, accounts_merkle_root: Box<[u8; 32]>, accounts_zk_proof: Vec<u8>, transactions: Vec<[u8;32]>, accounts: Vec<[u8; 32]>) -> Self {
+    pub fn new(block_number: u64, previous_block: [u8; 32], transactions_merkle_root: Box<[u8; 32]>, accounts_merkle_root: Box<[u8; 32]>, accounts_zk_proof: Vec<u8>, accounts_zk_public_inputs: Vec<u8>, verifying_key_hash: [u8; 32], transactions: Vec<[u8;32]>, accounts: Vec<[u8; 32]>) -> Self {
         Block {
             id: Self::get_id(block_number),
-            block_hash: Self::block_hash(&transactions_merkle_root, &accounts_merkle_root),
+            block_hash: Self::block_hash(&transactions_merkle_root, &accounts_merkle_root, &BlockProducer::default(), &[0u8; 32]),
             previous_block,
             block_number,
             transactions_merkle_root,
@@ -29,9 +71,60 @@ impl Block {
             accounts_zk_proof,
             transactions,
             accounts,
+            accounts_zk_public_inputs,
+            verifying_key_hash,
+            l1_anchor: L1Anchor::default(),
+            account_leaves: Vec::new(),
+            outbox_root: [0u8; 32],
+            outbox_messages: Vec::new(),
+            fees: 0,
+            challenge_outcomes: Vec::new(),
+            producer: BlockProducer::default(),
+            parameters_hash: [0u8; 32],
         }
     }
 
+    pub fn producer(&self) -> &BlockProducer {
+        &self.producer
+    }
+
+    /// Records the block's producer and updates the block hash to cover it.
+    pub fn set_producer(&mut self, producer: BlockProducer) {
+        self.producer = producer;
+        self.block_hash = Self::block_hash(&self.transactions_merkle_root, &self.accounts_merkle_root, &self.producer, &self.parameters_hash);
+    }
+
+    pub fn parameters_hash(&self) -> &[u8; 32] {
+        &self.parameters_hash
+    }
+
+    /// Records the hash of the governed parameters the block was produced under and updates the
+    /// block hash to cover it.
+    pub fn set_parameters_hash(&mut self, parameters_hash: [u8; 32]) {
+        self.parameters_hash = parameters_hash;
+        self.block_hash = Self::block_hash(&self.transactions_merkle_root, &self.accounts_merkle_root, &self.producer, &self.parameters_hash);
+    }
+
+    /// Checks that the block id and hash match the block number and roots stored in the block.
+    /// Blocks stored before producers and parameters were recorded hash their roots alone.
+    pub fn has_consistent_hash(&self) -> bool {
+        let legacy = self.producer == BlockProducer::default()
+            && self.parameters_hash == [0u8; 32]
+            && self.block_hash == Self::legacy_block_hash(&self.transactions_merkle_root, &self.accounts_merkle_root);
+        self.id == Self::get_id(self.block_number)
+            && (legacy || self.block_hash == Self::block_hash(&self.transactions_merkle_root, &self.accounts_merkle_root, &self.producer, &self.parameters_hash))
+    }
+
+    /// Heartbeat blocks keep the cadence while the pool is idle. They change no state and carry
+    /// no proof, the accounts root is the parent's.
+    pub fn is_heartbeat(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        self.block_hash
+    }
+
     pub fn get_id(block_number: u64) -> [u8; 32] {
         Self::hash_id(block_number)
     }
@@ -44,18 +137,64 @@ impl Block {
         hash
     }
     
-    fn block_hash(transactions_root: &[u8; 32], accounts_root: &[u8; 32]) -> [u8; 32] {
+    fn block_hash(transactions_root: &[u8; 32], accounts_root: &[u8; 32], producer: &BlockProducer, parameters_hash: &[u8; 32]) -> [u8; 32] {
         let mut hasher = Sha256::new();
         hasher.update(transactions_root);
         hasher.update(accounts_root);
+        hasher.update(producer.to_bytes());
+        hasher.update(parameters_hash);
         let hash: [u8; 32] = hasher.finalize().into();
         hash
     }
+
+    /// Hash of blocks of encoding version 0.
+    fn legacy_block_hash(transactions_root: &[u8; 32], accounts_root: &[u8; 32]) -> [u8; 32] {
+        Sha256::new().chain_update(transactions_root).chain_update(accounts_root).finalize().into()
+    }
+}
+
+/// `Block` as stored before public inputs, anchors, outboxes, fees, challenges, producers and
+/// parameters were recorded, encoding version 0.
+#[derive(BorshDeserialize)]
+struct BlockV0 {
+    id: [u8; 32],
+    block_hash: [u8; 32],
+    previous_block: [u8; 32],
+    block_number: u64,
+    transactions_merkle_root: Box<[u8; 32]>,
+    accounts_merkle_root: Box<[u8; 32]>,
+    accounts_zk_proof: Vec<u8>,
+    transactions: Vec<[u8; 32]>,
+    accounts: Vec<[u8; 32]>,
 }
 
 impl StateRecord for Block {
+    /// 1 added everything after `accounts`, from `accounts_zk_public_inputs` to `parameters_hash`
+    const ENCODING_VERSION: u32 = 1;
+
     fn get_key(&self) -> [u8; 32] {
         self.id
     }
 
+    fn upgrade(version: u32, bytes: &[u8]) -> Option<Self> {
+        match version {
+            0 => {
+                let block = borsh::from_slice::<BlockV0>(bytes).ok()?;
+                Some(Block {
+                    id: block.id,
+                    block_hash: block.block_hash,
+                    previous_block: block.previous_block,
+                    block_number: block.block_number,
+                    transactions_merkle_root: block.transactions_merkle_root,
+                    accounts_merkle_root: block.accounts_merkle_root,
+                    accounts_zk_proof: block.accounts_zk_proof,
+                    transactions: block.transactions,
+                    accounts: block.accounts,
+                    ..Block::default()
+                })
+            }
+            _ => None,
+        }
+    }
+
 }
@@ -0,0 +1,148 @@
+//! Canonical JSON in the style of RFC 8785 (JCS), for payloads that are signed or hashed and
+//! must hash the same in every language.
+//!
+//! Object members are sorted by the UTF-16 code units of their names, no whitespace is written,
+//! strings use the shortest JSON escapes and floats use the ECMAScript number format. Unlike
+//! RFC 8785, integers are written exactly rather than through an IEEE 754 double, so lamports and
+//! slots above 2^53 survive; consumers have to parse them as big integers before re-serializing.
+//! `test-vectors/canonical_json.json` holds input and output pairs for checking other
+//! implementations.
+
+use serde::Serialize;
+use serde_json::{Number, Value};
+use std::fmt::Write;
+
+/// Canonical JSON encoding of `value`.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<Vec<u8>> {
+    to_string(value).map(String::into_bytes)
+}
+
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    let mut output = String::new();
+    write_value(&mut output, &serde_json::to_value(value)?)?;
+    Ok(output)
+}
+
+fn write_value(output: &mut String, value: &Value) -> serde_json::Result<()> {
+    match value {
+        Value::Null => output.push_str("null"),
+        Value::Bool(value) => output.push_str(if *value { "true" } else { "false" }),
+        Value::Number(number) => write_number(output, number),
+        Value::String(string) => output.push_str(&serde_json::to_string(string)?),
+        Value::Array(values) => {
+            output.push('[');
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+                write_value(output, value)?;
+            }
+            output.push(']');
+        }
+        Value::Object(members) => {
+            let mut members: Vec<(&String, &Value)> = members.iter().collect();
+            members.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            output.push('{');
+            for (index, (name, value)) in members.into_iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+                output.push_str(&serde_json::to_string(name)?);
+                output.push(':');
+                write_value(output, value)?;
+            }
+            output.push('}');
+        }
+    }
+    Ok(())
+}
+
+fn write_number(output: &mut String, number: &Number) {
+    if let Some(integer) = number.as_u64() {
+        let _ = write!(output, "{}", integer);
+    } else if let Some(integer) = number.as_i64() {
+        let _ = write!(output, "{}", integer);
+    } else if let Some(float) = number.as_f64() {
+        write_float(output, float);
+    }
+}
+
+/// Writes a finite float the way ECMAScript's `Number.prototype.toString` does. serde_json
+/// never holds NaN or infinities.
+fn write_float(output: &mut String, value: f64) {
+    if value == 0.0 {
+        output.push('0');
+        return;
+    }
+    if value < 0.0 {
+        output.push('-');
+    }
+    // Rust's exponent format yields the shortest digits that round-trip, e.g. "1.25e-7"
+    let formatted = format!("{:e}", value.abs());
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let exponent: i32 = exponent.parse().unwrap_or_default();
+    let digit_count = digits.len() as i32;
+    // Position of the decimal point relative to the start of the digits
+    let point = exponent + 1;
+
+    if digit_count <= point && point <= 21 {
+        output.push_str(&digits);
+        output.extend(std::iter::repeat('0').take((point - digit_count) as usize));
+    } else if 0 < point && point <= 21 {
+        output.push_str(&digits[..point as usize]);
+        output.push('.');
+        output.push_str(&digits[point as usize..]);
+    } else if -6 < point && point <= 0 {
+        output.push_str("0.");
+        output.extend(std::iter::repeat('0').take((-point) as usize));
+        output.push_str(&digits);
+    } else {
+        output.push_str(&digits[..1]);
+        if digit_count > 1 {
+            output.push('.');
+            output.push_str(&digits[1..]);
+        }
+        let _ = write!(output, "e{}{}", if point - 1 >= 0 { "+" } else { "-" }, (point - 1).abs());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Deserialize)]
+    struct Vector {
+        name: String,
+        input: Value,
+        canonical: String,
+    }
+
+    #[test]
+    fn test_vectors() {
+        let vectors: Vec<Vector> = serde_json::from_str(include_str!("../test-vectors/canonical_json.json")).unwrap();
+        for vector in vectors {
+            assert_eq!(to_string(&vector.input).unwrap(), vector.canonical, "vector {}", vector.name);
+        }
+    }
+
+    #[test]
+    fn test_floats_use_ecmascript_format() {
+        let cases = [
+            (1.0, "1"),
+            (-1.5, "-1.5"),
+            (0.000001, "0.000001"),
+            (0.0000001, "1e-7"),
+            (123456789012345680000.0, "123456789012345680000"),
+            (1e21, "1e+21"),
+            (4.35, "4.35"),
+            (5e-324, "5e-324"),
+            (1.7976931348623157e308, "1.7976931348623157e+308"),
+        ];
+        for (value, expected) in cases {
+            let mut output = String::new();
+            write_float(&mut output, value);
+            assert_eq!(output, expected);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use crate::state_record::StateRecord;
+use crate::transaction::TrollupTransaction;
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction::SystemInstruction;
+use solana_sdk::system_program;
+use solana_sdk::transaction::Transaction;
+
+/// How a challenge against a pending optimistic commitment ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChallengeStatus {
+    /// The commitment is still pending
+    Open,
+    /// The validator refused the commitment's proof. The challenger gets the bond back plus a
+    /// reward from the sequencer's bond
+    Upheld,
+    /// The commitment was finalized, the bond is slashed to the sequencer
+    Rejected,
+    /// The commitment was dropped without a verdict, e.g. rolled back for a conflict. The bond is
+    /// refunded without a reward
+    Void,
+}
+
+/// A challenger's bond against the state root of a pending commitment.
+///
+/// The bond is posted with a rollup transaction transferring it to the sequencer's account, which
+/// holds the sequencer's own bond too. Payouts are transfers from that account back to the
+/// challenger, made once the challenge is claimed.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Challenge {
+    pub id: [u8; 32],
+    pub state_root: [u8; 32],
+    pub challenger: Pubkey,
+    pub bond_lamports: u64,
+    /// Signature of the transaction that posted the bond
+    pub bond_signature: String,
+    /// Unix timestamp (seconds) of the post
+    pub posted_at: u64,
+    pub status: ChallengeStatus,
+    /// Reward owed on top of the bond, set when the challenge is upheld
+    pub reward_lamports: u64,
+    /// Block the outcome was recorded in, `None` while open or until the next block
+    pub resolved_in_block: Option<u64>,
+    /// Signature of the transaction that paid the challenger out, set once claimed
+    pub payout_signature: Option<String>,
+}
+
+impl Challenge {
+    pub fn new(state_root: [u8; 32], challenger: Pubkey, bond_lamports: u64, bond_signature: String, posted_at: u64) -> Self {
+        Challenge {
+            id: Self::id_for(&state_root, &challenger),
+            state_root,
+            challenger,
+            bond_lamports,
+            bond_signature,
+            posted_at,
+            status: ChallengeStatus::Open,
+            reward_lamports: 0,
+            resolved_in_block: None,
+            payout_signature: None,
+        }
+    }
+
+    /// A challenger can challenge a state root once.
+    pub fn id_for(state_root: &[u8; 32], challenger: &Pubkey) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update("challenge_");
+        hasher.update(state_root);
+        hasher.update(challenger);
+        hasher.finalize().into()
+    }
+
+    /// Lamports owed to the challenger: the bond and reward when upheld, the bond when void and
+    /// nothing otherwise.
+    pub fn payout(&self) -> u64 {
+        match self.status {
+            ChallengeStatus::Upheld => self.bond_lamports.saturating_add(self.reward_lamports),
+            ChallengeStatus::Void => self.bond_lamports,
+            ChallengeStatus::Open | ChallengeStatus::Rejected => 0,
+        }
+    }
+
+    /// Whether the challenger can claim a payout now.
+    pub fn is_claimable(&self) -> bool {
+        self.payout() > 0 && self.payout_signature.is_none()
+    }
+
+    pub fn outcome(&self) -> ChallengeOutcome {
+        ChallengeOutcome {
+            id: self.id,
+            state_root: self.state_root,
+            challenger: self.challenger,
+            status: self.status,
+            bond_lamports: self.bond_lamports,
+            payout_lamports: self.payout(),
+        }
+    }
+}
+
+impl StateRecord for Challenge {
+    fn get_key(&self) -> [u8; 32] {
+        self.id
+    }
+}
+
+/// A resolved challenge as recorded in the block following its resolution.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengeOutcome {
+    pub id: [u8; 32],
+    pub state_root: [u8; 32],
+    pub challenger: Pubkey,
+    pub status: ChallengeStatus,
+    pub bond_lamports: u64,
+    /// Lamports the challenger can claim, zero for a slashed bond
+    pub payout_lamports: u64,
+}
+
+/// Where the transaction that posted a challenge's bond is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BondState {
+    /// Not executed yet
+    Pending,
+    /// Executed and the transfer went through for the bonded amount
+    Posted,
+    /// Executed but the transfer failed, or moved a different amount. Failed transactions still
+    /// make it into blocks, so being finalized isn't enough to have posted the bond
+    Failed,
+}
+
+/// The transaction a challenge's bond was posted with. Keyed by the transaction, so a transfer
+/// bonds one challenge only.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bond {
+    pub transaction_id: [u8; 32],
+    pub challenge_id: [u8; 32],
+    pub lamports: u64,
+    pub state: BondState,
+}
+
+impl Bond {
+    pub fn new(transaction_id: [u8; 32], challenge: &Challenge) -> Self {
+        Bond {
+            transaction_id,
+            challenge_id: challenge.id,
+            lamports: challenge.bond_lamports,
+            state: BondState::Pending,
+        }
+    }
+
+    /// Records the execution of the bond transaction, `error` as the SVM reported it.
+    pub fn executed(&mut self, transaction: &TrollupTransaction, error: Option<&str>) {
+        self.state = match (error, transferred_lamports(transaction)) {
+            (None, Some(lamports)) if lamports == self.lamports => BondState::Posted,
+            _ => BondState::Failed,
+        };
+    }
+}
+
+impl StateRecord for Bond {
+    fn get_key(&self) -> [u8; 32] {
+        self.transaction_id
+    }
+}
+
+/// Lamports moved by `transaction` when it is a single system transfer.
+fn transferred_lamports(transaction: &TrollupTransaction) -> Option<u64> {
+    let [instruction] = transaction.message.instructions.as_slice() else {
+        return None;
+    };
+    let program_id = transaction.message.account_keys.get(instruction.program_id_index as usize)?;
+    if *program_id != system_program::id().to_bytes() {
+        return None;
+    }
+    match bincode::deserialize(&instruction.data) {
+        Ok(SystemInstruction::Transfer { lamports }) => Some(lamports),
+        _ => None,
+    }
+}
+
+/// Checks `transaction` posts a bond of at least `bond_lamports`: a single system transfer to
+/// `sequencer`. Returns the challenger, the sender of the transfer, and the amount posted.
+pub fn bond_transfer(transaction: &Transaction, sequencer: &Pubkey, bond_lamports: u64) -> Result<(Pubkey, u64), String> {
+    let message = &transaction.message;
+    let [instruction] = message.instructions.as_slice() else {
+        return Err("The bond transaction must contain exactly one instruction".to_string());
+    };
+    let key = |index: u8| message.account_keys.get(index as usize).copied();
+    if key(instruction.program_id_index) != Some(system_program::id()) {
+        return Err("The bond must be a system transfer".to_string());
+    }
+    let Ok(SystemInstruction::Transfer { lamports }) = bincode::deserialize(&instruction.data) else {
+        return Err("The bond must be a system transfer".to_string());
+    };
+    let (Some(challenger), Some(recipient)) = (
+        instruction.accounts.first().and_then(|index| key(*index)),
+        instruction.accounts.get(1).and_then(|index| key(*index)),
+    ) else {
+        return Err("The bond transfer is missing accounts".to_string());
+    };
+    if recipient != *sequencer {
+        return Err(format!("The bond must be sent to the sequencer: {}", sequencer));
+    }
+    if lamports < bond_lamports {
+        return Err(format!("The bond is {} lamports, {} were sent", bond_lamports, lamports));
+    }
+    Ok((challenger, lamports))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::system_instruction;
+
+    #[test]
+    fn test_bond_transfer() {
+        let challenger = Keypair::new();
+        let sequencer = Pubkey::new_unique();
+        let transfer = |to: &Pubkey, lamports| Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(&challenger.pubkey(), to, lamports)],
+            Some(&challenger.pubkey()),
+            &[&challenger],
+            Default::default(),
+        );
+
+        assert_eq!(bond_transfer(&transfer(&sequencer, 1_500), &sequencer, 1_000), Ok((challenger.pubkey(), 1_500)));
+        assert!(bond_transfer(&transfer(&sequencer, 999), &sequencer, 1_000).is_err());
+        assert!(bond_transfer(&transfer(&Pubkey::new_unique(), 1_000), &sequencer, 1_000).is_err());
+    }
+
+    #[test]
+    fn test_bond_is_posted_by_a_successful_transfer_of_the_amount() {
+        let challenger = Keypair::new();
+        let sequencer = Pubkey::new_unique();
+        let transfer = |lamports| TrollupTransaction::from(&Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(&challenger.pubkey(), &sequencer, lamports)],
+            Some(&challenger.pubkey()),
+            &[&challenger],
+            Default::default(),
+        ));
+        let challenge = Challenge::new([1; 32], challenger.pubkey(), 1_000, String::new(), 0);
+        let mut bond = Bond::new([2; 32], &challenge);
+        assert_eq!(bond.state, BondState::Pending);
+
+        bond.executed(&transfer(1_000), None);
+        assert_eq!(bond.state, BondState::Posted);
+        bond.executed(&transfer(1_000), Some("Error processing Instruction 0: custom program error: 0x1"));
+        assert_eq!(bond.state, BondState::Failed);
+        bond.executed(&transfer(999), None);
+        assert_eq!(bond.state, BondState::Failed);
+    }
+
+    #[test]
+    fn test_payout_by_status() {
+        let mut challenge = Challenge::new([1; 32], Pubkey::new_unique(), 1_000, String::new(), 0);
+        assert!(!challenge.is_claimable());
+
+        challenge.status = ChallengeStatus::Rejected;
+        assert_eq!(challenge.payout(), 0);
+
+        challenge.status = ChallengeStatus::Void;
+        assert_eq!(challenge.payout(), 1_000);
+
+        challenge.status = ChallengeStatus::Upheld;
+        challenge.reward_lamports = 500;
+        assert_eq!(challenge.outcome().payout_lamports, 1_500);
+        assert!(challenge.is_claimable());
+
+        challenge.payout_signature = Some("paid".to_string());
+        assert!(!challenge.is_claimable());
+    }
+}
@@ -0,0 +1,157 @@
+use crate::account_state::AccountState;
+use crate::merkle;
+use crate::state_record::StateRecord;
+use crate::witness::{account_leaf, AccountInclusion};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+
+/// Summary of an epoch of `CHECKPOINT_INTERVAL_BLOCKS` blocks, signed by the sequencer. Light
+/// clients sync from checkpoint to checkpoint, following `previous_checkpoint`, instead of
+/// replaying every block header.
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Checkpoint {
+    /// Epochs start at 1, epoch `n` ends with block `n * interval`
+    pub epoch: u64,
+    pub first_block: u64,
+    pub last_block: u64,
+    pub last_block_hash: [u8; 32],
+    /// Root over every rollup account after `last_block`, leaves ordered by address
+    pub account_set_root: [u8; 32],
+    pub account_count: u64,
+    pub epoch_transactions: u64,
+    pub epoch_fees: u64,
+    pub cumulative_transactions: u64,
+    pub cumulative_fees: u64,
+    /// Digest of the previous epoch's checkpoint, all zeros for the first epoch
+    pub previous_checkpoint: [u8; 32],
+    pub sequencer: Pubkey,
+    /// Sequencer's ed25519 signature over `digest`, empty until signed
+    pub signature: Vec<u8>,
+}
+
+impl Checkpoint {
+    pub fn get_id(epoch: u64) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update("checkpoint_");
+        hasher.update(epoch.to_be_bytes());
+        hasher.finalize().into()
+    }
+
+    /// SHA-256 of the Borsh encoding of every field but the signature, what the sequencer signs
+    /// and the next checkpoint links to.
+    pub fn digest(&self) -> [u8; 32] {
+        let unsigned = Checkpoint { signature: Vec::new(), ..self.clone() };
+        Sha256::digest(borsh::to_vec(&unsigned).unwrap()).into()
+    }
+
+    pub fn sign(&mut self, sequencer: &Keypair) {
+        self.sequencer = sequencer.pubkey();
+        self.signature = sequencer.sign_message(&self.digest()).as_ref().to_vec();
+    }
+
+    pub fn verify_signature(&self) -> bool {
+        Signature::try_from(self.signature.as_slice())
+            .map(|signature| signature.verify(self.sequencer.as_ref(), &self.digest()))
+            .unwrap_or(false)
+    }
+}
+
+impl StateRecord for Checkpoint {
+    fn get_key(&self) -> [u8; 32] {
+        Self::get_id(self.epoch)
+    }
+}
+
+/// Root of the account set, independent of the order `accounts` come in. All zeros for no
+/// accounts.
+pub fn account_set_root(accounts: &[AccountState]) -> [u8; 32] {
+    let mut accounts: Vec<&AccountState> = accounts.iter().collect();
+    accounts.sort_by_key(|account| account.address);
+    let leaves = merkle::hash_leaves(&accounts, |account| account_leaf(account));
+    merkle::parallel_root(&leaves).unwrap_or_default()
+}
+
+/// Inclusion proof of the account at `address` against `account_set_root(accounts)`, what the
+/// vault's emergency withdrawals are proven with. `block_number` is the block the account set
+/// is the state after.
+pub fn account_set_inclusion(block_number: u64, accounts: &[AccountState], address: &Pubkey) -> Option<AccountInclusion> {
+    let mut accounts: Vec<&AccountState> = accounts.iter().collect();
+    accounts.sort_by_key(|account| account.address);
+    let addresses: Vec<[u8; 32]> = accounts.iter().map(|account| account.address.to_bytes()).collect();
+    let leaves = merkle::hash_leaves(&accounts, |account| account_leaf(account));
+    merkle::BlockMerkleIndex::build(block_number, &addresses, leaves).inclusion(&address.to_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(lamports: u64) -> AccountState {
+        AccountState {
+            address: Pubkey::new_unique(),
+            lamports,
+            data: vec![],
+            owner: Pubkey::default(),
+            executable: false,
+            rent_epoch: 0,
+            data_root: None,
+        }
+    }
+
+    fn checkpoint() -> Checkpoint {
+        Checkpoint {
+            epoch: 2,
+            first_block: 101,
+            last_block: 200,
+            last_block_hash: [4u8; 32],
+            account_set_root: [5u8; 32],
+            account_count: 12,
+            epoch_transactions: 40,
+            epoch_fees: 200_000,
+            cumulative_transactions: 90,
+            cumulative_fees: 450_000,
+            previous_checkpoint: [6u8; 32],
+            sequencer: Pubkey::default(),
+            signature: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_signature_covers_every_field() {
+        let sequencer = Keypair::new();
+        let mut signed = checkpoint();
+        signed.sign(&sequencer);
+        assert!(signed.verify_signature());
+        assert_eq!(signed.sequencer, sequencer.pubkey());
+
+        let mut tampered = signed.clone();
+        tampered.cumulative_fees += 1;
+        assert!(!tampered.verify_signature());
+        assert!(!checkpoint().verify_signature());
+    }
+
+    #[test]
+    fn test_account_set_root_ignores_order() {
+        let accounts = vec![account(1), account(2), account(3)];
+        let reversed: Vec<AccountState> = accounts.iter().rev().cloned().collect();
+        assert_eq!(account_set_root(&accounts), account_set_root(&reversed));
+        assert_ne!(account_set_root(&accounts), account_set_root(&accounts[..2]));
+        assert_eq!(account_set_root(&[]), [0u8; 32]);
+    }
+
+    #[test]
+    fn test_account_set_inclusion_proves_against_the_account_set_root() {
+        let accounts = vec![account(1), account(2), account(3), account(4), account(5)];
+        for account in &accounts {
+            let inclusion = account_set_inclusion(9, &accounts, &account.address).unwrap();
+            assert_eq!(inclusion.root, account_set_root(&accounts));
+            assert!(inclusion.verify(account));
+            assert!(!inclusion.verify(&AccountState { lamports: account.lamports + 1, ..account.clone() }));
+        }
+        assert!(account_set_inclusion(9, &accounts, &Pubkey::new_unique()).is_none());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::account_state::AccountState;
+use crate::state_record::StateRecord;
+use borsh::{BorshDeserialize, BorshSerialize};
+use rs_merkle::algorithms::Sha256;
+use rs_merkle::{Hasher, MerkleProof, MerkleTree};
+use serde::{Deserialize, Serialize};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction;
+use std::collections::{HashMap, HashSet};
+
+/// The outbox builtin and owner of outbox message accounts. A transaction sends a message by
+/// creating an account owned by the builtin and having it write a Borsh [`CrossRollupMessage`]
+/// into it, see [`send_message`]. The account's lamports are locked on this rollup and paid out
+/// to the recipient on the destination.
+pub mod outbox {
+    solana_sdk::declare_id!("Tro11upMessageBox11111111111111111111111111");
+}
+
+/// Data of an outbox message account.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct CrossRollupMessage {
+    /// `ROLLUP_ID` of the receiving deployment
+    pub destination: String,
+    pub recipient: Pubkey,
+    pub payload: Vec<u8>,
+}
+
+/// Instructions of the outbox builtin.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum OutboxInstruction {
+    /// Writes the message into a new message account, once.
+    ///
+    /// Accounts: `[writable, signer]` the message account, owned by the outbox, zeroed and
+    /// exactly as long as the encoded message
+    Send(CrossRollupMessage),
+}
+
+/// Instructions sending `message` from a new account at `message_account`, funded with
+/// `lamports` by `payer`. Both sign the transaction.
+pub fn send_message(payer: &Pubkey, message_account: &Pubkey, lamports: u64, message: &CrossRollupMessage) -> Vec<Instruction> {
+    let space = borsh::to_vec(message).unwrap().len() as u64;
+    vec![
+        system_instruction::create_account(payer, message_account, lamports, space, &outbox::id()),
+        Instruction::new_with_bytes(
+            outbox::id(),
+            &borsh::to_vec(&OutboxInstruction::Send(message.clone())).unwrap(),
+            vec![AccountMeta::new(*message_account, true)],
+        ),
+    ]
+}
+
+/// A message as committed in a block's outbox tree.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxMessage {
+    /// Address of the message account, unique per source rollup
+    pub address: Pubkey,
+    pub lamports: u64,
+    pub destination: String,
+    pub recipient: Pubkey,
+    pub payload: Vec<u8>,
+}
+
+impl OutboxMessage {
+    pub fn leaf(&self) -> [u8; 32] {
+        Sha256::hash(&borsh::to_vec(self).unwrap())
+    }
+}
+
+/// The messages sent by a batch: accounts in `records` that became outbox message accounts.
+/// `previous` returns the state of an account before the batch, accounts that were already
+/// messages are not sent again. Each address is sent at most once, in the order the batch first
+/// touched it.
+pub fn outbox_messages(records: &[AccountState], previous: impl Fn(&Pubkey) -> Option<AccountState>) -> Vec<OutboxMessage> {
+    let latest: HashMap<Pubkey, &AccountState> = records.iter().map(|record| (record.address, record)).collect();
+    let mut seen = HashSet::new();
+    records
+        .iter()
+        .filter(|record| seen.insert(record.address))
+        .filter_map(|record| {
+            let account = latest[&record.address];
+            if account.owner != outbox::id() {
+                return None;
+            }
+            if previous(&account.address).is_some_and(|previous| previous.owner == outbox::id()) {
+                return None;
+            }
+            let message = CrossRollupMessage::try_from_slice(&account.data).ok()?;
+            Some(OutboxMessage {
+                address: account.address,
+                lamports: account.lamports,
+                destination: message.destination,
+                recipient: message.recipient,
+                payload: message.payload,
+            })
+        })
+        .collect()
+}
+
+/// Root of the outbox tree over `messages`, all zeros for a block without messages.
+pub fn outbox_root(messages: &[OutboxMessage]) -> [u8; 32] {
+    let leaves: Vec<[u8; 32]> = messages.iter().map(OutboxMessage::leaf).collect();
+    MerkleTree::<Sha256>::from_leaves(&leaves).root().unwrap_or_default()
+}
+
+/// Proof that a message is in the outbox of a source block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxInclusion {
+    pub block_number: u64,
+    pub outbox_root: [u8; 32],
+    pub leaf_index: u64,
+    pub leaf_count: u64,
+    pub proof: Vec<[u8; 32]>,
+}
+
+impl OutboxInclusion {
+    /// Inclusion proof of the message at `index` in `messages`, the outbox of block `block_number`.
+    pub fn new(block_number: u64, messages: &[OutboxMessage], index: usize) -> Option<Self> {
+        if index >= messages.len() {
+            return None;
+        }
+        let leaves: Vec<[u8; 32]> = messages.iter().map(OutboxMessage::leaf).collect();
+        let tree = MerkleTree::<Sha256>::from_leaves(&leaves);
+        Some(OutboxInclusion {
+            block_number,
+            outbox_root: tree.root()?,
+            leaf_index: index as u64,
+            leaf_count: leaves.len() as u64,
+            proof: tree.proof(&[index]).proof_hashes().to_vec(),
+        })
+    }
+
+    pub fn verify(&self, message: &OutboxMessage) -> bool {
+        MerkleProof::<Sha256>::new(self.proof.clone()).verify(
+            self.outbox_root,
+            &[self.leaf_index as usize],
+            &[message.leaf()],
+            self.leaf_count as usize,
+        )
+    }
+}
+
+/// What a destination rollup's inbox needs to accept a message: the message, its inclusion in
+/// a source block's outbox and the L1 transaction that committed that block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrossRollupClaim {
+    /// `ROLLUP_ID` of the sending deployment
+    pub source: String,
+    pub message: OutboxMessage,
+    pub inclusion: OutboxInclusion,
+    /// Base58 signature of the source's signature verifier transaction for the block
+    pub l1_signature: String,
+}
+
+/// A message delivered by the inbox, kept so the same message can't be claimed twice.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveredMessage {
+    pub source: String,
+    pub address: Pubkey,
+    pub block_number: u64,
+    /// Base58 signature of the rollup transaction that paid out the message
+    pub delivery_signature: String,
+    pub payload: Vec<u8>,
+}
+
+impl DeliveredMessage {
+    pub fn key(source: &str, address: &Pubkey) -> [u8; 32] {
+        Sha256::hash(&[source.as_bytes(), &[0u8], address.as_ref()].concat())
+    }
+}
+
+impl StateRecord for DeliveredMessage {
+    fn get_key(&self) -> [u8; 32] {
+        Self::key(&self.source, &self.address)
+    }
+}
@@ -0,0 +1,33 @@
+use crate::state_record::StateRecord;
+use borsh::{BorshDeserialize, BorshSerialize};
+use rs_merkle::algorithms::Sha256;
+use rs_merkle::Hasher;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+/// A lamport deposit into the L1 vault the sequencer credited on the rollup, kept so a deposit is
+/// only credited once.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreditedDeposit {
+    /// Base58 signature of the L1 transaction that made the deposit
+    pub l1_signature: String,
+    /// Index of the vault's `Deposit` instruction in that transaction
+    pub instruction_index: u32,
+    pub rollup_recipient: Pubkey,
+    pub lamports: u64,
+    /// Base58 signature of the rollup transaction that credited the deposit
+    pub credit_signature: String,
+}
+
+impl CreditedDeposit {
+    pub fn key(l1_signature: &str, instruction_index: u32) -> [u8; 32] {
+        Sha256::hash(&[l1_signature.as_bytes(), &[0u8], &instruction_index.to_le_bytes()].concat())
+    }
+}
+
+impl StateRecord for CreditedDeposit {
+    fn get_key(&self) -> [u8; 32] {
+        Self::key(&self.l1_signature, self.instruction_index)
+    }
+}
@@ -0,0 +1,203 @@
+//! Decoders rendering instructions of well known programs as JSON for API responses and
+//! published receipts. Every decoded instruction has a `type`, plus its amounts and the parties
+//! involved keyed by their role; instructions of other programs are left to their raw data.
+
+use serde_json::{json, Value};
+use solana_sdk::compute_budget::{self, ComputeBudgetInstruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction::SystemInstruction;
+use solana_sdk::system_program;
+use spl_token::instruction::TokenInstruction;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Decodes the instructions of one program.
+pub trait InstructionDecoder: Send + Sync {
+    fn program_id(&self) -> Pubkey;
+
+    /// The instruction as JSON, `None` when `data` isn't an instruction of the program.
+    /// `accounts` are the instruction's accounts, in instruction order.
+    fn decode(&self, accounts: &[Pubkey], data: &[u8]) -> Option<Value>;
+}
+
+/// Decoders by program id. `Default` has the system, spl-token and compute budget decoders.
+pub struct DecoderRegistry {
+    decoders: HashMap<Pubkey, Box<dyn InstructionDecoder>>,
+}
+
+impl DecoderRegistry {
+    /// A registry without any decoder.
+    pub fn empty() -> Self {
+        DecoderRegistry { decoders: HashMap::new() }
+    }
+
+    /// Adds `decoder`, replacing the one registered for the same program.
+    pub fn register(&mut self, decoder: impl InstructionDecoder + 'static) {
+        self.decoders.insert(decoder.program_id(), Box::new(decoder));
+    }
+
+    pub fn decode(&self, program_id: &Pubkey, accounts: &[Pubkey], data: &[u8]) -> Option<Value> {
+        self.decoders.get(program_id)?.decode(accounts, data)
+    }
+}
+
+impl Default for DecoderRegistry {
+    fn default() -> Self {
+        let mut registry = DecoderRegistry::empty();
+        registry.register(SystemDecoder);
+        registry.register(TokenDecoder);
+        registry.register(ComputeBudgetDecoder);
+        registry
+    }
+}
+
+/// The registry used for API views and receipt messages.
+pub fn registry() -> &'static DecoderRegistry {
+    static REGISTRY: OnceLock<DecoderRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(DecoderRegistry::default)
+}
+
+/// Decodes the instruction with the default registry, `None` for programs without a decoder and
+/// data that doesn't decode.
+pub fn decode_instruction(program_id: &Pubkey, accounts: &[Pubkey], data: &[u8]) -> Option<Value> {
+    registry().decode(program_id, accounts, data)
+}
+
+fn account(accounts: &[Pubkey], index: usize) -> Option<String> {
+    accounts.get(index).map(Pubkey::to_string)
+}
+
+pub struct SystemDecoder;
+
+impl InstructionDecoder for SystemDecoder {
+    fn program_id(&self) -> Pubkey {
+        system_program::id()
+    }
+
+    fn decode(&self, accounts: &[Pubkey], data: &[u8]) -> Option<Value> {
+        let account = |index| account(accounts, index);
+        let instruction: SystemInstruction = bincode::deserialize(data).ok()?;
+        Some(match instruction {
+            SystemInstruction::Transfer { lamports } => json!({
+                "type": "transfer", "source": account(0), "destination": account(1), "lamports": lamports,
+            }),
+            SystemInstruction::CreateAccount { lamports, space, owner } => json!({
+                "type": "createAccount", "source": account(0), "newAccount": account(1),
+                "lamports": lamports, "space": space, "owner": owner.to_string(),
+            }),
+            SystemInstruction::Assign { owner } => json!({ "type": "assign", "account": account(0), "owner": owner.to_string() }),
+            SystemInstruction::Allocate { space } => json!({ "type": "allocate", "account": account(0), "space": space }),
+            other => json!({ "type": variant_name(&other) }),
+        })
+    }
+}
+
+pub struct TokenDecoder;
+
+impl InstructionDecoder for TokenDecoder {
+    fn program_id(&self) -> Pubkey {
+        spl_token::id()
+    }
+
+    fn decode(&self, accounts: &[Pubkey], data: &[u8]) -> Option<Value> {
+        let account = |index| account(accounts, index);
+        let instruction = TokenInstruction::unpack(data).ok()?;
+        Some(match instruction {
+            TokenInstruction::Transfer { amount } => json!({
+                "type": "transfer", "source": account(0), "destination": account(1), "authority": account(2), "amount": amount,
+            }),
+            TokenInstruction::TransferChecked { amount, decimals } => json!({
+                "type": "transferChecked", "source": account(0), "mint": account(1), "destination": account(2),
+                "authority": account(3), "amount": amount, "decimals": decimals,
+            }),
+            TokenInstruction::MintTo { amount } => json!({
+                "type": "mintTo", "mint": account(0), "account": account(1), "authority": account(2), "amount": amount,
+            }),
+            TokenInstruction::Burn { amount } => json!({
+                "type": "burn", "account": account(0), "mint": account(1), "authority": account(2), "amount": amount,
+            }),
+            TokenInstruction::Approve { amount } => json!({
+                "type": "approve", "source": account(0), "delegate": account(1), "owner": account(2), "amount": amount,
+            }),
+            TokenInstruction::CloseAccount => json!({
+                "type": "closeAccount", "account": account(0), "destination": account(1), "owner": account(2),
+            }),
+            other => json!({ "type": variant_name(&other) }),
+        })
+    }
+}
+
+/// Compute budget instructions take no accounts, only their limit or price.
+pub struct ComputeBudgetDecoder;
+
+impl InstructionDecoder for ComputeBudgetDecoder {
+    fn program_id(&self) -> Pubkey {
+        compute_budget::id()
+    }
+
+    fn decode(&self, _accounts: &[Pubkey], data: &[u8]) -> Option<Value> {
+        let instruction: ComputeBudgetInstruction = borsh::from_slice(data).ok()?;
+        Some(match instruction {
+            ComputeBudgetInstruction::RequestHeapFrame(bytes) => json!({ "type": "requestHeapFrame", "bytes": bytes }),
+            ComputeBudgetInstruction::SetComputeUnitLimit(units) => json!({ "type": "setComputeUnitLimit", "units": units }),
+            ComputeBudgetInstruction::SetComputeUnitPrice(micro_lamports) => json!({
+                "type": "setComputeUnitPrice", "microLamports": micro_lamports,
+            }),
+            ComputeBudgetInstruction::SetLoadedAccountsDataSizeLimit(bytes) => json!({
+                "type": "setLoadedAccountsDataSizeLimit", "bytes": bytes,
+            }),
+            other => json!({ "type": variant_name(&other) }),
+        })
+    }
+}
+
+/// `InitializeMint { .. }` as `initializeMint`.
+fn variant_name(instruction: &impl std::fmt::Debug) -> String {
+    let debug = format!("{:?}", instruction);
+    let name: String = debug.chars().take_while(|c| c.is_alphanumeric()).collect();
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|first| first.to_ascii_lowercase().to_string() + chars.as_str())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decodes_compute_budget_instructions() {
+        let price = ComputeBudgetInstruction::set_compute_unit_price(5_000);
+        assert_eq!(
+            decode_instruction(&price.program_id, &[], &price.data),
+            Some(json!({ "type": "setComputeUnitPrice", "microLamports": 5_000 })),
+        );
+        let limit = ComputeBudgetInstruction::set_compute_unit_limit(200_000);
+        assert_eq!(
+            decode_instruction(&limit.program_id, &[], &limit.data),
+            Some(json!({ "type": "setComputeUnitLimit", "units": 200_000 })),
+        );
+    }
+
+    #[test]
+    fn test_registered_decoder_replaces_default() {
+        struct Memo;
+        impl InstructionDecoder for Memo {
+            fn program_id(&self) -> Pubkey {
+                system_program::id()
+            }
+            fn decode(&self, _accounts: &[Pubkey], data: &[u8]) -> Option<Value> {
+                Some(json!({ "type": "memo", "text": String::from_utf8_lossy(data) }))
+            }
+        }
+
+        let mut registry = DecoderRegistry::default();
+        registry.register(Memo);
+        assert_eq!(
+            registry.decode(&system_program::id(), &[], b"hello"),
+            Some(json!({ "type": "memo", "text": "hello" })),
+        );
+        assert_eq!(DecoderRegistry::empty().decode(&compute_budget::id(), &[], &[3, 0, 0, 0, 0, 0, 0, 0, 0]), None);
+    }
+}
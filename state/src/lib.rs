@@ -1,5 +1,29 @@
 pub mod account_state;
+pub mod account_data;
 pub mod state_record;
 pub mod transaction;
 pub mod block;
-pub mod config;
\ No newline at end of file
+pub mod config;
+pub mod sponsorship;
+pub mod outbox;
+pub mod blob;
+pub mod sequence_entry;
+pub mod witness;
+pub mod rollup_metadata;
+pub mod cross_rollup;
+pub mod deposit;
+pub mod transaction_status;
+pub mod receipt;
+pub mod merkle;
+pub mod canonical_json;
+pub mod checkpoint;
+pub mod views;
+pub mod instruction_decoder;
+pub mod challenge;
+pub mod parameters;
+pub mod batch_data;
+pub mod artifact;
+pub mod node_info;
+pub mod units;
+#[cfg(any(test, feature = "test-fixtures"))]
+pub mod test_fixtures;
@@ -0,0 +1,310 @@
+use crate::block::Block;
+use crate::state_record::StateRecord;
+use crate::transaction::TrollupTransaction;
+use crate::witness::AccountInclusion;
+use borsh::{BorshDeserialize, BorshSerialize};
+use rayon::prelude::*;
+use rs_merkle::algorithms::Sha256;
+use rs_merkle::{Hasher, MerkleTree};
+use serde_derive::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Leaves per subtree built on one thread. A power of two, so every full subtree's root sits
+/// exactly on a layer of the whole tree.
+const SUBTREE_LEAVES: usize = 1024;
+
+/// Most accounts a transaction can write, its message indexes account keys with a `u8`.
+pub const MAX_TRANSACTION_ACCOUNTS: u64 = 256;
+
+/// Smallest `MERKLE_MAX_DEPTH`, a block of a single transaction has to fit.
+pub const MIN_MERKLE_DEPTH: u8 = 8;
+
+/// Number of children per node of a Merkle tree.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum MerkleArity {
+    /// One sibling per level at twice the depth of a quaternary tree, the layout of the current
+    /// rs_merkle trees
+    #[default]
+    Binary,
+    /// Half the depth with three siblings per level, fewer hashes to constrain in a circuit
+    Quaternary,
+}
+
+impl MerkleArity {
+    pub fn children(&self) -> u64 {
+        match self {
+            MerkleArity::Binary => 2,
+            MerkleArity::Quaternary => 4,
+        }
+    }
+}
+
+impl std::fmt::Display for MerkleArity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MerkleArity::Binary => write!(f, "binary"),
+            MerkleArity::Quaternary => write!(f, "quaternary"),
+        }
+    }
+}
+
+impl FromStr for MerkleArity {
+    type Err = String;
+
+    fn from_str(arity: &str) -> Result<Self, Self::Err> {
+        match arity.trim().to_ascii_lowercase().as_str() {
+            "binary" | "2" | "" => Ok(MerkleArity::Binary),
+            "quaternary" | "4" => Ok(MerkleArity::Quaternary),
+            other => Err(format!("Unknown MERKLE_ARITY: {}, expected binary or quaternary", other)),
+        }
+    }
+}
+
+/// Shape of the state tree. Both sides of a proof have to agree on it: the prover to build the
+/// tree and the circuit and verifiers to check paths through it.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MerkleParameters {
+    pub arity: MerkleArity,
+    /// Levels below the root, bounding the number of leaves to `arity ^ max_depth`
+    pub max_depth: u8,
+}
+
+impl MerkleParameters {
+    /// Most leaves a tree of these parameters holds, saturating at `u64::MAX`.
+    pub fn capacity(&self) -> u64 {
+        self.arity.children().checked_pow(self.max_depth as u32).unwrap_or(u64::MAX)
+    }
+
+    /// Levels needed for `leaf_count` leaves.
+    pub fn depth_for(&self, leaf_count: u64) -> u32 {
+        let mut depth = 0;
+        let mut capacity = 1u64;
+        while capacity < leaf_count {
+            capacity = capacity.saturating_mul(self.arity.children());
+            depth += 1;
+        }
+        depth
+    }
+
+    /// Checks that `leaf_count` leaves of the `tree` fit within `max_depth`.
+    pub fn check_leaves(&self, tree: &str, leaf_count: u64) -> Result<(), String> {
+        if leaf_count > self.capacity() {
+            return Err(format!(
+                "The {} tree needs depth {} for {} leaves, MERKLE_MAX_DEPTH is {}",
+                tree,
+                self.depth_for(leaf_count),
+                leaf_count,
+                self.max_depth,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Most transactions a block can hold so its transaction and account trees stay within
+    /// `max_depth`, every transaction writing at most `MAX_TRANSACTION_ACCOUNTS` accounts.
+    pub fn max_block_transactions(&self) -> u64 {
+        self.capacity() / MAX_TRANSACTION_ACCOUNTS
+    }
+}
+
+/// Hashes `items` into leaves on the rayon pool, keeping their order.
+pub fn hash_leaves<T: Sync>(items: &[T], leaf: impl Fn(&T) -> [u8; 32] + Sync + Send) -> Vec<[u8; 32]> {
+    items.par_iter().map(leaf).collect()
+}
+
+/// Leaf of a transaction in a block's transaction tree, the SHA-256 of its Borsh encoding.
+pub fn transaction_leaf(transaction: &TrollupTransaction) -> [u8; 32] {
+    Sha256::hash(&borsh::to_vec(transaction).unwrap())
+}
+
+/// Root of the transaction tree over `transactions`, all zeros without transactions. What blocks
+/// store as `transactions_merkle_root` and batch proofs are bound to.
+pub fn transactions_root(transactions: &[TrollupTransaction]) -> [u8; 32] {
+    parallel_root(&hash_leaves(transactions, transaction_leaf)).unwrap_or_default()
+}
+
+/// Root of `MerkleTree::<Sha256>::from_leaves(leaves)`, with the subtrees built concurrently.
+///
+/// rs_merkle pairs adjacent nodes layer by layer and carries an odd last node up unhashed, so
+/// the roots of consecutive power-of-two subtrees, treated as leaves, give the same root as the
+/// whole tree. A short last subtree's root is carried up to the same layer as the others.
+pub fn parallel_root(leaves: &[[u8; 32]]) -> Option<[u8; 32]> {
+    if leaves.len() <= SUBTREE_LEAVES {
+        return MerkleTree::<Sha256>::from_leaves(leaves).root();
+    }
+    let subtree_roots: Vec<[u8; 32]> = leaves
+        .par_chunks(SUBTREE_LEAVES)
+        .filter_map(|subtree| MerkleTree::<Sha256>::from_leaves(subtree).root())
+        .collect();
+    MerkleTree::<Sha256>::from_leaves(&subtree_roots).root()
+}
+
+/// The account tree of a finalized block with every layer kept, so an inclusion proof is read
+/// off in O(log n) instead of rebuilding the tree. Stored keyed by the block's ID.
+#[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct BlockMerkleIndex {
+    pub block_number: u64,
+    /// Address and leaf index of every account the block wrote, sorted by address. The last
+    /// write wins when a block wrote an account more than once.
+    pub leaf_indices: Vec<([u8; 32], u64)>,
+    /// Leaves first, root last, laid out as rs_merkle builds them
+    pub layers: Vec<Vec<[u8; 32]>>,
+}
+
+impl BlockMerkleIndex {
+    /// Index of the tree over `leaves`, where `accounts[i]` is the address of `leaves[i]`.
+    pub fn build(block_number: u64, accounts: &[[u8; 32]], leaves: Vec<[u8; 32]>) -> Self {
+        let mut leaf_indices: Vec<([u8; 32], u64)> = accounts
+            .iter()
+            .enumerate()
+            .map(|(index, address)| (*address, index as u64))
+            .collect();
+        // Stable, so the last write of an address ends up last among its duplicates
+        leaf_indices.sort_by_key(|(address, _)| *address);
+        leaf_indices.reverse();
+        leaf_indices.dedup_by_key(|(address, _)| *address);
+        leaf_indices.reverse();
+
+        let mut layers = vec![leaves];
+        while layers.last().is_some_and(|layer| layer.len() > 1) {
+            let next = layers
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => Sha256::concat_and_hash(left, Some(right)),
+                    // rs_merkle carries an odd last node up unhashed
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(next);
+        }
+        BlockMerkleIndex { block_number, leaf_indices, layers }
+    }
+
+    pub fn root(&self) -> Option<[u8; 32]> {
+        self.layers.last().and_then(|layer| layer.first()).copied()
+    }
+
+    pub fn leaf_count(&self) -> u64 {
+        self.layers.first().map(Vec::len).unwrap_or_default() as u64
+    }
+
+    pub fn leaf_index(&self, address: &[u8; 32]) -> Option<u64> {
+        self.leaf_indices
+            .binary_search_by_key(address, |(candidate, _)| *candidate)
+            .ok()
+            .map(|position| self.leaf_indices[position].1)
+    }
+
+    /// Sibling hashes from the leaf up, the same as `MerkleTree::proof(&[leaf_index])`.
+    pub fn proof(&self, leaf_index: u64) -> Vec<[u8; 32]> {
+        let mut index = leaf_index as usize;
+        let mut proof = Vec::with_capacity(self.layers.len());
+        for layer in &self.layers[..self.layers.len().saturating_sub(1)] {
+            if let Some(sibling) = layer.get(index ^ 1) {
+                proof.push(*sibling);
+            }
+            index /= 2;
+        }
+        proof
+    }
+
+    /// Inclusion proof of the account at `address`, `None` when the block didn't write it.
+    pub fn inclusion(&self, address: &[u8; 32]) -> Option<AccountInclusion> {
+        let leaf_index = self.leaf_index(address)?;
+        Some(AccountInclusion {
+            block_number: self.block_number,
+            root: self.root()?,
+            leaf_index,
+            leaf_count: self.leaf_count(),
+            proof: self.proof(leaf_index),
+        })
+    }
+}
+
+impl StateRecord for BlockMerkleIndex {
+    fn get_key(&self) -> [u8; 32] {
+        Block::get_id(self.block_number)
+    }
+}
+
+/// The latest finalized block that wrote an account, keyed by the account's address. Finds the
+/// block to prove an account against without walking the chain.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AccountWriter {
+    pub address: [u8; 32],
+    pub block_number: u64,
+}
+
+impl StateRecord for AccountWriter {
+    fn get_key(&self) -> [u8; 32] {
+        self.address
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_depth_bounds_the_leaves() {
+        let parameters = MerkleParameters { arity: MerkleArity::Binary, max_depth: 10 };
+        assert_eq!(parameters.capacity(), 1024);
+        assert!(parameters.check_leaves("account", 1024).is_ok());
+        let error = parameters.check_leaves("account", 1025).unwrap_err();
+        assert!(error.contains("depth 11"), "{}", error);
+        assert_eq!(parameters.max_block_transactions(), 4);
+        let deepest = MerkleParameters { arity: MerkleArity::Binary, max_depth: 64 };
+        assert_eq!(deepest.capacity(), u64::MAX);
+        assert!(deepest.check_leaves("account", u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_parallel_root_matches_serial_tree() {
+        for leaf_count in [0, 1, 2, 1023, 1024, 1025, 2048, 3000, 4097] {
+            let leaves: Vec<[u8; 32]> = (0..leaf_count as u64).map(|i| Sha256::hash(&i.to_le_bytes())).collect();
+            assert_eq!(
+                parallel_root(&leaves),
+                MerkleTree::<Sha256>::from_leaves(&leaves).root(),
+                "{} leaves", leaf_count,
+            );
+        }
+    }
+
+    #[test]
+    fn test_hash_leaves_keeps_order() {
+        let items: Vec<u64> = (0..5000).collect();
+        let leaves = hash_leaves(&items, |i| Sha256::hash(&i.to_le_bytes()));
+        assert_eq!(leaves.len(), items.len());
+        assert_eq!(leaves[4321], Sha256::hash(&4321u64.to_le_bytes()));
+    }
+
+    #[test]
+    fn test_block_merkle_index_proofs_match_rs_merkle() {
+        for leaf_count in [1u64, 2, 3, 7, 8, 13, 64, 100] {
+            let accounts: Vec<[u8; 32]> = (0..leaf_count).map(|i| Sha256::hash(&i.to_be_bytes())).collect();
+            let leaves: Vec<[u8; 32]> = (0..leaf_count).map(|i| Sha256::hash(&i.to_le_bytes())).collect();
+            let tree = MerkleTree::<Sha256>::from_leaves(&leaves);
+            let index = BlockMerkleIndex::build(9, &accounts, leaves);
+            assert_eq!(index.root(), tree.root(), "{} leaves", leaf_count);
+            for (leaf_index, address) in accounts.iter().enumerate() {
+                assert_eq!(index.leaf_index(address), Some(leaf_index as u64));
+                assert_eq!(index.proof(leaf_index as u64), tree.proof(&[leaf_index]).proof_hashes().to_vec());
+            }
+        }
+    }
+
+    #[test]
+    fn test_block_merkle_index_keeps_last_write() {
+        let (first, second) = ([1u8; 32], [2u8; 32]);
+        let leaves: Vec<[u8; 32]> = (0..4u64).map(|i| Sha256::hash(&i.to_le_bytes())).collect();
+        let index = BlockMerkleIndex::build(1, &[second, first, second, first], leaves);
+        assert_eq!(index.leaf_index(&first), Some(3));
+        assert_eq!(index.leaf_index(&second), Some(2));
+        assert_eq!(index.leaf_index(&[3u8; 32]), None);
+    }
+}
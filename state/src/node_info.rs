@@ -0,0 +1,135 @@
+//! Who a node is and what chain and formats it serves, exchanged before two nodes sync. A node
+//! signs its info with its identity keypair, and a follower only syncs from a peer on the same
+//! chain whose stored records it can read.
+
+use crate::canonical_json;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use std::str::FromStr;
+
+/// Separates node info signatures from every other message the identity key signs.
+pub const NODE_INFO_DOMAIN: &[u8] = b"trollup:node-info:v1";
+/// Version of the block, transaction and account encodings a node stores and serves. Bumped on
+/// every change a node of the previous version can't read.
+pub const SCHEMA_VERSION: u32 = 1;
+/// Versions of the sync protocol this node speaks, oldest first.
+pub const PROTOCOL_VERSIONS: &[u32] = &[1];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfo {
+    /// Base58 public key of the node's identity keypair
+    pub identity: String,
+    /// Hex hash of block 1, `None` before the node produced or synced it
+    pub genesis_hash: Option<String>,
+    pub latest_block: u64,
+    /// Hex hash of `latest_block`
+    pub latest_block_hash: Option<String>,
+    pub node_version: String,
+    pub schema_version: u32,
+    pub protocol_versions: Vec<u32>,
+    /// API versions served, oldest first
+    pub api_versions: Vec<String>,
+    /// Base58 signature of the identity over `NodeInfo::digest`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl NodeInfo {
+    /// SHA-256 over the domain and the canonical JSON of the info without its signature.
+    pub fn digest(&self) -> [u8; 32] {
+        let unsigned = NodeInfo { signature: None, ..self.clone() };
+        let json = canonical_json::to_vec(&unsigned).expect("NodeInfo serializes to JSON");
+        Sha256::new().chain_update(NODE_INFO_DOMAIN).chain_update(json).finalize().into()
+    }
+
+    /// Sets `identity` to `keypair`'s public key and signs the info with it.
+    pub fn sign(mut self, keypair: &Keypair) -> Self {
+        self.identity = keypair.pubkey().to_string();
+        self.signature = Some(keypair.sign_message(&self.digest()).to_string());
+        self
+    }
+
+    /// Checks the info is signed by its identity.
+    pub fn verify(&self) -> Result<Pubkey, String> {
+        let identity = Pubkey::from_str(&self.identity).map_err(|_| format!("Invalid node identity {}", self.identity))?;
+        let signature = self.signature.as_deref().ok_or_else(|| format!("Node {} sent unsigned info", identity))?;
+        let signature = Signature::from_str(signature).map_err(|_| format!("Invalid signature from node {}", identity))?;
+        if !signature.verify(identity.as_ref(), &self.digest()) {
+            return Err(format!("The info of node {} doesn't match its signature", identity));
+        }
+        Ok(identity)
+    }
+}
+
+/// The handshake a follower runs against the peer it syncs from, `local` being its own info.
+/// Fails when the peer's info isn't signed by its identity, when it stores another schema
+/// version, when the two don't share a protocol version, or when the peer is on another chain:
+/// a different genesis block, or none while the follower already has one. Returns the newest
+/// protocol version both speak.
+pub fn handshake(local: &NodeInfo, peer: &NodeInfo) -> Result<u32, String> {
+    let identity = peer.verify()?;
+    if peer.schema_version != local.schema_version {
+        return Err(format!("Node {} stores schema version {}, this node reads {}", identity, peer.schema_version, local.schema_version));
+    }
+    match (&local.genesis_hash, &peer.genesis_hash) {
+        (Some(local_genesis), Some(peer_genesis)) if local_genesis != peer_genesis => {
+            return Err(format!("Node {} has genesis block {}, this node {}", identity, peer_genesis, local_genesis));
+        }
+        (Some(local_genesis), None) => {
+            return Err(format!("Node {} has no blocks, this node already has genesis block {}", identity, local_genesis));
+        }
+        _ => {}
+    }
+    local.protocol_versions
+        .iter()
+        .filter(|version| peer.protocol_versions.contains(version))
+        .max()
+        .copied()
+        .ok_or_else(|| format!("Node {} speaks protocol versions {:?}, this node {:?}", identity, peer.protocol_versions, local.protocol_versions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(genesis_hash: Option<&str>) -> NodeInfo {
+        NodeInfo {
+            identity: String::new(),
+            genesis_hash: genesis_hash.map(str::to_string),
+            latest_block: 3,
+            latest_block_hash: None,
+            node_version: "0.1.0".to_string(),
+            schema_version: SCHEMA_VERSION,
+            protocol_versions: PROTOCOL_VERSIONS.to_vec(),
+            api_versions: vec!["v1".to_string()],
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn test_handshake_accepts_peers_on_the_same_chain() {
+        let peer = info(Some("aa")).sign(&Keypair::new());
+        assert_eq!(handshake(&info(Some("aa")), &peer), Ok(1));
+        // A fresh follower syncs from any chain
+        assert_eq!(handshake(&info(None), &peer), Ok(1));
+    }
+
+    #[test]
+    fn test_handshake_rejects_mismatched_peers() {
+        let local = info(Some("aa"));
+        let peer = Keypair::new();
+        assert!(handshake(&local, &info(Some("bb")).sign(&peer)).is_err());
+        assert!(handshake(&local, &info(None).sign(&peer)).is_err());
+        assert!(handshake(&local, &NodeInfo { schema_version: SCHEMA_VERSION + 1, ..info(Some("aa")) }.sign(&peer)).is_err());
+        assert!(handshake(&local, &NodeInfo { protocol_versions: vec![99], ..info(Some("aa")) }.sign(&peer)).is_err());
+
+        // Unsigned or tampered info
+        assert!(handshake(&local, &info(Some("aa"))).is_err());
+        let mut tampered = info(Some("bb")).sign(&peer);
+        tampered.genesis_hash = Some("aa".to_string());
+        assert!(handshake(&local, &tampered).is_err());
+    }
+}
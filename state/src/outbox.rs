@@ -0,0 +1,35 @@
+use crate::state_record::StateRecord;
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+/// A message waiting to be published to the message bus.
+///
+/// Entries are written when a block is finalized and only deleted once the broker acknowledged
+/// them, so a crash or broker outage delays messages instead of losing them.
+#[derive(Debug, Clone, Default, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxEntry {
+    /// Block number in the upper 32 bits, position within the block in the lower 32 bits
+    pub sequence: u64,
+    pub topic: String,
+    /// Partitioning key, e.g. the block number, signature or account address
+    pub key: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+impl OutboxEntry {
+    /// Unique id of the entry, used by brokers that deduplicate redelivered messages.
+    pub fn message_id(&self) -> String {
+        format!("{:016x}", self.sequence)
+    }
+}
+
+impl StateRecord for OutboxEntry {
+    /// The big-endian sequence leads the key so iterating the store yields entries in publishing
+    /// order.
+    fn get_key(&self) -> [u8; 32] {
+        let mut key = [0u8; 32];
+        key[..8].copy_from_slice(&self.sequence.to_be_bytes());
+        key
+    }
+}
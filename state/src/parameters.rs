@@ -0,0 +1,206 @@
+//! Rollup parameters under governance: the fee schedule, the batch limits and the challenge
+//! window. Changes are proposed for a future block and take effect once enough governance
+//! signers approved them. Every block records the hash of the set it was produced under, so a
+//! historical block is checked against the rules in force at the time.
+
+use crate::state_record::StateRecord;
+use borsh::{to_vec, BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Separates parameter set hashes from every other hash over the same bytes.
+pub const PARAMETERS_DOMAIN: &[u8] = b"trollup:parameters:v1";
+/// Separates proposal IDs, which governance signers sign, from every other message.
+pub const PROPOSAL_DOMAIN: &[u8] = b"trollup:parameter-proposal:v1";
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GovernedParameters {
+    /// Fee charged per signature, system transactions excepted
+    pub lamports_per_signature: u64,
+    /// Most transactions the engine takes from the pool for one batch
+    pub transaction_batch_amount: u32,
+    /// Most transactions a bundle can hold, never more than `transaction_batch_amount`
+    pub max_bundle_transactions: u32,
+    /// How long a pending optimistic commitment can be challenged before it is proved, in seconds
+    pub challenge_window_secs: u64,
+}
+
+impl GovernedParameters {
+    /// SHA-256 over the domain and the Borsh encoding, the hash blocks record.
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::new()
+            .chain_update(PARAMETERS_DOMAIN)
+            .chain_update(to_vec(self).expect("Parameters serialize to Borsh"))
+            .finalize()
+            .into()
+    }
+
+    pub fn challenge_window(&self) -> Duration {
+        Duration::from_secs(self.challenge_window_secs)
+    }
+
+    /// Fails for a set the rollup can't run with.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.transaction_batch_amount == 0 {
+            return Err("transactionBatchAmount must be at least 1".to_string());
+        }
+        if self.max_bundle_transactions == 0 || self.max_bundle_transactions > self.transaction_batch_amount {
+            return Err(format!("maxBundleTransactions must be between 1 and transactionBatchAmount ({})", self.transaction_batch_amount));
+        }
+        if self.challenge_window_secs == 0 {
+            return Err("challengeWindowSecs must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A governance signer's approval of a proposal.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterApproval {
+    pub signer: Pubkey,
+    /// Base58 ed25519 signature of the signer over the proposal ID
+    pub signature: String,
+}
+
+/// A parameter set proposed to take effect from `effective_block` on.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterProposal {
+    /// What the signers sign, see `ParameterProposal::id_for`
+    pub id: [u8; 32],
+    pub parameters: GovernedParameters,
+    pub effective_block: u64,
+    pub approvals: Vec<ParameterApproval>,
+    /// Unix timestamp (seconds) of the proposal
+    pub proposed_at: u64,
+    /// Latest finalized block when the proposal reached the approval threshold, `None` while it
+    /// is collecting approvals. Only scheduled proposals ever take effect
+    pub scheduled_at: Option<u64>,
+}
+
+impl ParameterProposal {
+    pub fn new(parameters: GovernedParameters, effective_block: u64, proposed_at: u64) -> Self {
+        ParameterProposal {
+            id: Self::id_for(&parameters, effective_block),
+            parameters,
+            effective_block,
+            approvals: Vec::new(),
+            proposed_at,
+            scheduled_at: None,
+        }
+    }
+
+    /// The set a chain starts with, in force from the first block until a proposal replaces it.
+    pub fn genesis(parameters: GovernedParameters) -> Self {
+        ParameterProposal { scheduled_at: Some(0), ..Self::new(parameters, 0, 0) }
+    }
+
+    /// The same set can be proposed for a block once.
+    pub fn id_for(parameters: &GovernedParameters, effective_block: u64) -> [u8; 32] {
+        Sha256::new()
+            .chain_update(PROPOSAL_DOMAIN)
+            .chain_update(parameters.hash())
+            .chain_update(effective_block.to_be_bytes())
+            .finalize()
+            .into()
+    }
+
+    /// Adds `signer`'s approval after checking it is one of `signers` and its signature over the
+    /// proposal ID.
+    pub fn approve(&mut self, signer: &Pubkey, signature: &str, signers: &[Pubkey]) -> Result<(), String> {
+        if !signers.contains(signer) {
+            return Err(format!("{} is not a governance signer", signer));
+        }
+        if self.approvals.iter().any(|approval| approval.signer == *signer) {
+            return Err(format!("{} already approved the proposal", signer));
+        }
+        let parsed = Signature::from_str(signature).map_err(|_| format!("Invalid signature from {}", signer))?;
+        if !parsed.verify(signer.as_ref(), &self.id) {
+            return Err(format!("The signature of {} doesn't match the proposal", signer));
+        }
+        self.approvals.push(ParameterApproval { signer: *signer, signature: signature.to_string() });
+        Ok(())
+    }
+
+    pub fn is_scheduled(&self) -> bool {
+        self.scheduled_at.is_some()
+    }
+}
+
+impl StateRecord for ParameterProposal {
+    fn get_key(&self) -> [u8; 32] {
+        self.id
+    }
+}
+
+/// The scheduled proposal in force at `block_number`: the one with the latest effective block
+/// not after it, the one scheduled last among proposals for the same block.
+pub fn effective_at(proposals: &[ParameterProposal], block_number: u64) -> Option<&ParameterProposal> {
+    proposals
+        .iter()
+        .filter(|proposal| proposal.effective_block <= block_number)
+        .filter_map(|proposal| proposal.scheduled_at.map(|scheduled_at| (proposal, scheduled_at)))
+        .max_by_key(|(proposal, scheduled_at)| (proposal.effective_block, *scheduled_at))
+        .map(|(proposal, _)| proposal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    fn parameters(lamports_per_signature: u64) -> GovernedParameters {
+        GovernedParameters {
+            lamports_per_signature,
+            transaction_batch_amount: 10,
+            max_bundle_transactions: 5,
+            challenge_window_secs: 60,
+        }
+    }
+
+    #[test]
+    fn test_approvals_are_checked() {
+        let signers = [Keypair::new(), Keypair::new()];
+        let keys: Vec<Pubkey> = signers.iter().map(Keypair::pubkey).collect();
+        let mut proposal = ParameterProposal::new(parameters(10_000), 50, 0);
+
+        let outsider = Keypair::new();
+        let outsider_signature = outsider.sign_message(&proposal.id).to_string();
+        assert!(proposal.approve(&outsider.pubkey(), &outsider_signature, &keys).is_err());
+        let wrong_message = signers[0].sign_message(&ParameterProposal::id_for(&parameters(1), 50)).to_string();
+        assert!(proposal.approve(&keys[0], &wrong_message, &keys).is_err());
+
+        let signature = signers[0].sign_message(&proposal.id).to_string();
+        assert!(proposal.approve(&keys[0], &signature, &keys).is_ok());
+        assert!(proposal.approve(&keys[0], &signature, &keys).is_err());
+        assert_eq!(proposal.approvals.len(), 1);
+    }
+
+    #[test]
+    fn test_effective_at_follows_the_schedule() {
+        let genesis = ParameterProposal::genesis(parameters(5_000));
+        let scheduled = ParameterProposal { scheduled_at: Some(3), ..ParameterProposal::new(parameters(10_000), 20, 0) };
+        let pending = ParameterProposal::new(parameters(1), 10, 0);
+        let proposals = vec![genesis.clone(), scheduled.clone(), pending];
+
+        assert_eq!(effective_at(&proposals, 1), Some(&genesis));
+        assert_eq!(effective_at(&proposals, 19), Some(&genesis));
+        assert_eq!(effective_at(&proposals, 20), Some(&scheduled));
+        assert_eq!(effective_at(&proposals, 1_000), Some(&scheduled));
+        assert_ne!(genesis.parameters.hash(), scheduled.parameters.hash());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(parameters(5_000).validate().is_ok());
+        assert!(GovernedParameters { max_bundle_transactions: 11, ..parameters(5_000) }.validate().is_err());
+        assert!(GovernedParameters { challenge_window_secs: 0, ..parameters(5_000) }.validate().is_err());
+        assert!(GovernedParameters { transaction_batch_amount: 0, ..parameters(5_000) }.validate().is_err());
+    }
+}
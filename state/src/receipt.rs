@@ -0,0 +1,39 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+/// Accounts an instruction may have written: the writable accounts it was passed that the
+/// transaction changed. Programs can only write accounts passed to them, also through CPI, so an
+/// account changed by the transaction is in the write set of every instruction that could have
+/// changed it.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InstructionWriteSet {
+    pub instruction_index: u8,
+    pub program_id: Pubkey,
+    pub accounts: Vec<Pubkey>,
+}
+
+/// What executing a transaction did, recorded by the engine and carried with its batch.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionReceipt {
+    /// Key of the transaction, the SHA-256 of its first signature
+    pub transaction_id: [u8; 32],
+    pub compute_units: u64,
+    /// Submitted by the operator through the system lane rather than by a user
+    pub system: bool,
+    /// One entry per instruction, in instruction order
+    pub instruction_write_sets: Vec<InstructionWriteSet>,
+    /// Lamports charged to the fee payer, 0 for system transactions
+    pub fee: u64,
+}
+
+impl TransactionReceipt {
+    /// Instructions that may have written `account`.
+    pub fn writers_of<'a>(&'a self, account: &'a Pubkey) -> impl Iterator<Item = &'a InstructionWriteSet> {
+        self.instruction_write_sets
+            .iter()
+            .filter(move |write_set| write_set.accounts.contains(account))
+    }
+}
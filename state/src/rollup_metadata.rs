@@ -0,0 +1,61 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::pubkey::Pubkey;
+
+/// Owner of the rollup metadata accounts, the rollup counterpart of the `Sysvar1111...` owner.
+pub mod owner {
+    solana_sdk::declare_id!("Tro11upSysvar111111111111111111111111111111");
+}
+
+/// Borsh `u64`, number of the latest finalized rollup block, 0 before the first one.
+pub mod block_number {
+    solana_sdk::declare_id!("Tro11upB1ockNumber1111111111111111111111111");
+}
+
+/// Borsh [`FeeSchedule`].
+pub mod fee_schedule {
+    solana_sdk::declare_id!("Tro11upFeeSchedu1e1111111111111111111111111");
+}
+
+/// The 32 byte pubkey of the sequencer, all zeros when the node has no sequencer key.
+pub mod sequencer {
+    solana_sdk::declare_id!("Tro11upSequencer111111111111111111111111111");
+}
+
+/// Borsh `bool`, set while the committer is not committing new blocks.
+pub mod paused {
+    solana_sdk::declare_id!("Tro11upPaused111111111111111111111111111111");
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct FeeSchedule {
+    pub lamports_per_signature: u64,
+    pub max_transaction_compute_units: u64,
+}
+
+/// Rollup parameters exposed to programs as read-only accounts, the way Solana exposes sysvars.
+/// Each field is served as its own account, see the id modules above for the layouts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct RollupMetadata {
+    pub finalized_block_number: u64,
+    pub fee_schedule: FeeSchedule,
+    pub sequencer: Pubkey,
+    pub paused: bool,
+}
+
+impl RollupMetadata {
+    /// Address and account data of each metadata account.
+    pub fn account_data(&self) -> Vec<(Pubkey, Vec<u8>)> {
+        vec![
+            (block_number::id(), borsh::to_vec(&self.finalized_block_number).unwrap()),
+            (fee_schedule::id(), borsh::to_vec(&self.fee_schedule).unwrap()),
+            (sequencer::id(), self.sequencer.to_bytes().to_vec()),
+            (paused::id(), borsh::to_vec(&self.paused).unwrap()),
+        ]
+    }
+
+    /// Whether `address` is one of the metadata accounts, which transactions may read but not
+    /// write.
+    pub fn is_metadata_account(address: &Pubkey) -> bool {
+        [block_number::id(), fee_schedule::id(), sequencer::id(), paused::id()].contains(address)
+    }
+}
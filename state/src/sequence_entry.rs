@@ -0,0 +1,52 @@
+use crate::state_record::StateRecord;
+use crate::transaction::TrollupTransaction;
+use borsh::{BorshDeserialize, BorshSerialize};
+use sha2::{Digest, Sha256};
+
+/// Domain separator of the signed sequencing message.
+const SEQUENCE_DOMAIN: &[u8] = b"trollup-sequence";
+
+/// The sequencer's signed statement that it admitted a transaction at a position in the order.
+///
+/// Entries are append-only. A transaction with a signed entry that never shows up in a block, or
+/// shows up after transactions with a higher index, is evidence of censorship or reordering.
+#[derive(Debug, Clone, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct SequenceEntry {
+    pub index: u64,
+    /// SHA-256 of the Borsh encoded transaction
+    pub transaction_hash: [u8; 32],
+    /// Admission time in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Ed25519 signature of the sequencer over `signing_message`
+    pub signature: Vec<u8>,
+}
+
+impl SequenceEntry {
+    pub fn get_id(index: u64) -> [u8; 32] {
+        let mut key = [0u8; 32];
+        key[..8].copy_from_slice(&index.to_be_bytes());
+        key
+    }
+
+    pub fn transaction_hash(transaction: &TrollupTransaction) -> [u8; 32] {
+        Sha256::digest(borsh::to_vec(transaction).unwrap_or_default()).into()
+    }
+
+    /// The bytes the sequencer signs: the domain, the little-endian index, the transaction hash
+    /// and the little-endian timestamp.
+    pub fn signing_message(index: u64, transaction_hash: &[u8; 32], timestamp: u64) -> Vec<u8> {
+        let mut message = Vec::with_capacity(SEQUENCE_DOMAIN.len() + 48);
+        message.extend_from_slice(SEQUENCE_DOMAIN);
+        message.extend_from_slice(&index.to_le_bytes());
+        message.extend_from_slice(transaction_hash);
+        message.extend_from_slice(&timestamp.to_le_bytes());
+        message
+    }
+}
+
+impl StateRecord for SequenceEntry {
+    /// The big-endian index leads the key so iterating the store yields entries in order.
+    fn get_key(&self) -> [u8; 32] {
+        Self::get_id(self.index)
+    }
+}
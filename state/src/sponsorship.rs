@@ -0,0 +1,161 @@
+use crate::state_record::StateRecord;
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::sanitize::Sanitize;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::transaction::Transaction;
+
+const VOUCHER_DOMAIN: &[u8] = b"trollup-sponsorship-voucher";
+
+/// A sponsor's signed promise to pay the execution fees of one transaction.
+///
+/// The sponsor signs the voucher terms together with the hash of the transaction message, so a
+/// voucher can't be reused for a different transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SponsorshipVoucher {
+    pub sponsor: Pubkey,
+    /// Upper bound on the fee the sponsor agrees to pay for the transaction
+    pub max_lamports: u64,
+    /// Unix timestamp (seconds) after which the voucher is no longer accepted
+    pub expires_at: u64,
+    pub message_hash: [u8; 32],
+    pub signature: Signature,
+}
+
+impl SponsorshipVoucher {
+    /// Creates a voucher for `transaction` signed by `sponsor`.
+    pub fn new(sponsor: &Keypair, transaction: &Transaction, max_lamports: u64, expires_at: u64) -> Self {
+        let message_hash = message_hash(transaction);
+        let signature = sponsor.sign_message(&signing_bytes(&sponsor.pubkey(), max_lamports, expires_at, &message_hash));
+        SponsorshipVoucher {
+            sponsor: sponsor.pubkey(),
+            max_lamports,
+            expires_at,
+            message_hash,
+            signature,
+        }
+    }
+
+    /// Verifies the sponsor's signature and that the voucher was issued for `transaction`.
+    pub fn verify(&self, transaction: &Transaction) -> bool {
+        self.message_hash == message_hash(transaction)
+            && self.signature.verify(self.sponsor.as_ref(), &signing_bytes(&self.sponsor, self.max_lamports, self.expires_at, &self.message_hash))
+    }
+}
+
+fn signing_bytes(sponsor: &Pubkey, max_lamports: u64, expires_at: u64, message_hash: &[u8; 32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(VOUCHER_DOMAIN.len() + 80);
+    bytes.extend_from_slice(VOUCHER_DOMAIN);
+    bytes.extend_from_slice(sponsor.as_ref());
+    bytes.extend_from_slice(&max_lamports.to_le_bytes());
+    bytes.extend_from_slice(&expires_at.to_le_bytes());
+    bytes.extend_from_slice(message_hash);
+    bytes
+}
+
+fn message_hash(transaction: &Transaction) -> [u8; 32] {
+    Sha256::digest(transaction.message_data()).into()
+}
+
+/// Running total of the fees paid on behalf of a sponsor.
+#[derive(Debug, Clone, Default, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SponsorSpend {
+    pub sponsor: Pubkey,
+    pub spent_lamports: u64,
+    pub transaction_count: u64,
+}
+
+impl StateRecord for SponsorSpend {
+    fn get_key(&self) -> [u8; 32] {
+        self.sponsor.to_bytes()
+    }
+}
+
+/// Adds `fee_payer`'s signature to a transaction the client built with it as the fee payer.
+///
+/// The client compiles its message with the sequencer as the first account and signs it with
+/// every other signer, leaving the fee payer's signature empty. The message is left as it is, so
+/// the client's signatures still verify. Instructions may not use the fee payer's account, the
+/// sequencer would otherwise sign away its own lamports.
+pub fn co_sign(transaction: &Transaction, fee_payer: &Keypair) -> Result<Transaction, String> {
+    transaction.sanitize().map_err(|e| e.to_string())?;
+
+    let message = &transaction.message;
+    if message.account_keys.first() != Some(&fee_payer.pubkey()) {
+        return Err(format!("The fee payer must be the sequencer: {}", fee_payer.pubkey()));
+    }
+    let uses_fee_payer = message.instructions
+        .iter()
+        .any(|ix| ix.program_id_index == 0 || ix.accounts.contains(&0));
+    if uses_fee_payer {
+        return Err("Instructions may not use the fee payer's account".to_string());
+    }
+
+    let mut sponsored = transaction.clone();
+    sponsored.signatures[0] = fee_payer.sign_message(&sponsored.message_data());
+    sponsored.verify().map_err(|_| "Invalid signature of a transaction signer".to_string())?;
+    Ok(sponsored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::Message;
+    use solana_sdk::system_instruction;
+
+    /// A transfer `client` signs with `sequencer` as the fee payer, leaving its signature out.
+    fn client_transaction(client: &Keypair, sequencer: &Pubkey) -> Transaction {
+        let instruction = system_instruction::transfer(&client.pubkey(), &Pubkey::new_unique(), 1_000);
+        let blockhash = Hash::new_unique();
+        let message = Message::new_with_blockhash(&[instruction], Some(sequencer), &blockhash);
+        let mut transaction = Transaction::new_unsigned(message);
+        transaction.partial_sign(&[client], blockhash);
+        transaction
+    }
+
+    #[test]
+    fn test_co_signed_transaction_verifies() {
+        let (client, sequencer) = (Keypair::new(), Keypair::new());
+        let transaction = client_transaction(&client, &sequencer.pubkey());
+        let voucher = SponsorshipVoucher::new(&Keypair::new(), &transaction, 10_000, u64::MAX);
+
+        let sponsored = co_sign(&transaction, &sequencer).unwrap();
+        assert!(sponsored.verify().is_ok());
+        assert_eq!(sponsored.message, transaction.message);
+        assert_eq!(sponsored.signatures[1], transaction.signatures[1]);
+        assert!(voucher.verify(&sponsored));
+    }
+
+    #[test]
+    fn test_co_sign_requires_the_sequencer_as_fee_payer() {
+        let (client, sequencer) = (Keypair::new(), Keypair::new());
+        let transaction = client_transaction(&client, &client.pubkey());
+        assert!(co_sign(&transaction, &sequencer).is_err());
+    }
+
+    #[test]
+    fn test_co_sign_rejects_missing_or_tampered_signatures() {
+        let (client, sequencer) = (Keypair::new(), Keypair::new());
+        let mut unsigned = client_transaction(&client, &sequencer.pubkey());
+        unsigned.signatures[1] = Signature::default();
+        assert!(co_sign(&unsigned, &sequencer).is_err());
+
+        let mut tampered = client_transaction(&client, &sequencer.pubkey());
+        tampered.message.instructions[0].data[4] ^= 1;
+        assert!(co_sign(&tampered, &sequencer).is_err());
+    }
+
+    #[test]
+    fn test_co_sign_rejects_instructions_using_the_fee_payer() {
+        let sequencer = Keypair::new();
+        let instruction = system_instruction::transfer(&sequencer.pubkey(), &Pubkey::new_unique(), 1_000);
+        let message = Message::new_with_blockhash(&[instruction], Some(&sequencer.pubkey()), &Hash::new_unique());
+        let transaction = Transaction::new_unsigned(message);
+        assert_eq!(co_sign(&transaction, &sequencer), Err("Instructions may not use the fee payer's account".to_string()));
+    }
+}
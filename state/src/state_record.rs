@@ -3,14 +3,27 @@ use borsh::{to_vec, BorshDeserialize, BorshSerialize};
 use serde_derive::{Deserialize, Serialize};
 use sha2::Digest;
 use solana_sdk::transaction::Transaction;
+use crate::receipt::TransactionReceipt;
 use crate::transaction::{convert_to_solana_transaction, TrollupTransaction};
+use crate::witness::WitnessBundle;
+use trollup_commitment_message::BlockProducer;
 
 /// This trait represents a state record that can be serialized to and deserialized from
 /// bytes using the Borsh encoding format. It also provides a method to retrieve the key
 /// associated with the state record. A state record is a struct that will be used in a key value
 /// store.
 pub trait StateRecord: BorshSerialize + BorshDeserialize + Clone {
+    /// Version of the record's Borsh encoding, bumped whenever it changes. Stores written with an
+    /// older version are migrated when they are opened, see `upgrade`.
+    const ENCODING_VERSION: u32 = 0;
+
     fn get_key(&self) -> [u8; 32];
+
+    /// Decodes a record written with `version`, an encoding older than `ENCODING_VERSION`.
+    /// Record types that bump their version read every older encoding here.
+    fn upgrade(_version: u32, _bytes: &[u8]) -> Option<Self> {
+        None
+    }
 }
 
 // This struct represents the commitment to a ZK proof verification
@@ -21,6 +34,36 @@ pub struct ZkProofCommitment {
     pub recovery_id: u8,
     pub public_key: [u8; 65],
     pub new_state_root: [u8; 32],
+    /// Rollup block number of the commitment, the signature verifier only accepts increasing values
+    pub sequence: u64,
+    /// Root of the block's cross-rollup outbox, signed along with the state root
+    pub outbox_root: [u8; 32],
+    /// Sequencer and node version that produced the block, signed along with the state root
+    pub producer: BlockProducer,
+}
+
+/// A `ZkProofCommitment` submitted in a batch, signed along with the root it extends.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ChainedCommitment {
+    pub verifier_signature: [u8; 64],
+    pub recovery_id: u8,
+    pub public_key: [u8; 65],
+    pub parent_state_root: [u8; 32],
+    pub new_state_root: [u8; 32],
+    pub sequence: u64,
+    pub outbox_root: [u8; 32],
+    pub producer: BlockProducer,
+}
+
+/// A commitment signed with an ed25519 key, whose signature is in the ed25519 program
+/// instruction before it.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Ed25519Commitment {
+    pub public_key: [u8; 32],
+    pub new_state_root: [u8; 32],
+    pub sequence: u64,
+    pub outbox_root: [u8; 32],
+    pub producer: BlockProducer,
 }
 
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize)]
@@ -33,13 +76,77 @@ pub struct StateCommitmentPackage<S: StateRecord> {
     pub state_records: Vec<S>,
     pub transactions: Vec<TrollupTransaction>,
     pub transaction_ids: Vec<[u8; 32]>,
+    /// Set when the verifying key was moved to the blob store, `verifying_key` is empty then
+    pub verifying_key_hash: Option<[u8; 32]>,
+    /// Account root of the finalized block the batch was executed against, `None` before the first block
+    pub parent_state_root: Option<[u8; 32]>,
+    /// Inputs for re-executing the batch, sent to the validator with the proof
+    pub witness: Option<WitnessBundle>,
+    /// IDs of the API requests that submitted the transactions, for correlating commitment logs
+    pub request_ids: Vec<String>,
+    /// Receipts of the transactions, in transaction order
+    pub receipts: Vec<TransactionReceipt>,
+    /// Hash of the governed parameters the batch was executed under, recorded in its block. All
+    /// zeros for heartbeats
+    pub parameters_hash: [u8; 32],
+    /// Set when the data of the executable accounts was moved to the blob store, the hash of
+    /// each one's data, state records first and then witness accounts. Their data is empty then
+    pub program_data_hashes: Vec<[u8; 32]>,
+}
+
+/// `StateCommitmentPackage` as stored before `program_data_hashes`, encoding version 0.
+#[derive(BorshDeserialize)]
+struct StateCommitmentPackageV0<S: StateRecord> {
+    optimistic: bool,
+    proof: Vec<u8>,
+    public_inputs: Vec<u8>,
+    verifying_key: Vec<u8>,
+    state_root: Option<[u8; 32]>,
+    state_records: Vec<S>,
+    transactions: Vec<TrollupTransaction>,
+    transaction_ids: Vec<[u8; 32]>,
+    verifying_key_hash: Option<[u8; 32]>,
+    parent_state_root: Option<[u8; 32]>,
+    witness: Option<WitnessBundle>,
+    request_ids: Vec<String>,
+    receipts: Vec<TransactionReceipt>,
+    parameters_hash: [u8; 32],
 }
 
 impl<S: StateRecord> StateRecord for StateCommitmentPackage<S> {
+    /// 1 added `program_data_hashes`
+    const ENCODING_VERSION: u32 = 1;
+
     fn get_key(&self) -> [u8; 32] {
         self.state_root
             .expect("No state_root set for this record. The state_root is the key for this record.")
     }
+
+    fn upgrade(version: u32, bytes: &[u8]) -> Option<Self> {
+        match version {
+            0 => {
+                let package = borsh::from_slice::<StateCommitmentPackageV0<S>>(bytes).ok()?;
+                Some(StateCommitmentPackage {
+                    optimistic: package.optimistic,
+                    proof: package.proof,
+                    public_inputs: package.public_inputs,
+                    verifying_key: package.verifying_key,
+                    state_root: package.state_root,
+                    state_records: package.state_records,
+                    transactions: package.transactions,
+                    transaction_ids: package.transaction_ids,
+                    verifying_key_hash: package.verifying_key_hash,
+                    parent_state_root: package.parent_state_root,
+                    witness: package.witness,
+                    request_ids: package.request_ids,
+                    receipts: package.receipts,
+                    parameters_hash: package.parameters_hash,
+                    program_data_hashes: vec![],
+                })
+            }
+            _ => None,
+        }
+    }
 }
 
 impl<S: StateRecord> StateCommitmentPackage<S> {
@@ -64,9 +171,21 @@ impl<S: StateRecord> StateCommitmentPackage<S> {
             state_records,
             transactions,
             transaction_ids,
+            verifying_key_hash: None,
+            parent_state_root: None,
+            witness: None,
+            request_ids: vec![],
+            receipts: vec![],
+            parameters_hash: [0u8; 32],
+            program_data_hashes: vec![],
         }
     }
 
+    /// Packages without transactions only advance the chain, every executed batch has some.
+    pub fn is_heartbeat(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
     pub fn hash(state_records: Vec<S>) -> [u8; 32] {
         let mut hasher = sha2::Sha256::new();
 
@@ -85,9 +204,14 @@ pub struct StateCommitmentPackageUI<S: StateRecord> {
     pub public_inputs: Vec<u8>,
     pub verifying_key: Vec<u8>,
     pub state_root: Option<[u8; 32]>,
+    pub parent_state_root: Option<[u8; 32]>,
     pub state_records: Vec<S>,
     pub transactions: Vec<Transaction>,
     pub transaction_ids: Vec<[u8; 32]>,
+    /// `merkle::transactions_root` of the batch, which the proof is bound to. The transactions
+    /// above lost the lane flags that go into it.
+    #[serde(default)]
+    pub transactions_root: [u8; 32],
 }
 
 impl <S: StateRecord> From<&StateCommitmentPackage<S>> for StateCommitmentPackageUI<S> {
@@ -102,9 +226,11 @@ impl <S: StateRecord> From<&StateCommitmentPackage<S>> for StateCommitmentPackag
             public_inputs: state_commitment_package.public_inputs.clone(),
             verifying_key: state_commitment_package.verifying_key.clone(),
             state_root: state_commitment_package.state_root,
+            parent_state_root: state_commitment_package.parent_state_root,
             state_records: state_commitment_package.state_records.clone(),
             transactions: converted_txs,
             transaction_ids: state_commitment_package.transaction_ids.clone(),
+            transactions_root: crate::merkle::transactions_root(&state_commitment_package.transactions),
         }
     }
 }
\ No newline at end of file
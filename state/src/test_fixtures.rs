@@ -0,0 +1,192 @@
+//! Deterministic keypairs, funded accounts, SPL mints and transactions for tests, benchmarks,
+//! load generators and the example client. Everything is derived from a seed string, so the
+//! same seed names the same key in every run and every process.
+
+use crate::account_state::AccountState;
+use sha2::{Digest, Sha256};
+use solana_sdk::hash::Hash;
+use solana_sdk::program_option::COption;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{keypair_from_seed, Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::system_program;
+use solana_sdk::transaction::Transaction;
+use spl_token::state::{Account as TokenAccount, AccountState as TokenAccountState, Mint};
+
+/// Lamports of every account `funded_accounts` returns
+pub const FUNDED_LAMPORTS: u64 = 1_000_000_000;
+/// Decimals of the canned mints
+pub const MINT_DECIMALS: u8 = 6;
+/// Supply of the canned mints, in base units
+pub const MINT_SUPPLY: u64 = 1_000_000_000_000;
+
+/// The keypair derived from `seed`.
+pub fn keypair(seed: &str) -> Keypair {
+    let secret = Sha256::digest(format!("trollup:test-fixture:{}", seed));
+    keypair_from_seed(&secret).expect("A SHA-256 digest is a valid keypair seed")
+}
+
+/// `count` keypairs with the seeds `<prefix>/0`, `<prefix>/1`, ...
+pub fn keypairs(prefix: &str, count: usize) -> Vec<Keypair> {
+    (0..count).map(|index| keypair(&format!("{}/{}", prefix, index))).collect()
+}
+
+/// A system account holding `lamports`.
+pub fn system_account(address: Pubkey, lamports: u64) -> AccountState {
+    AccountState {
+        address,
+        lamports,
+        data: Vec::new(),
+        owner: system_program::id(),
+        executable: false,
+        rent_epoch: 0,
+        data_root: None,
+    }
+}
+
+/// A system account with `FUNDED_LAMPORTS` for each of `keypairs`, to write to the account
+/// store before submitting their transactions.
+pub fn funded_accounts(keypairs: &[Keypair]) -> Vec<AccountState> {
+    keypairs.iter().map(|keypair| system_account(keypair.pubkey(), FUNDED_LAMPORTS)).collect()
+}
+
+/// `count` funded keypairs with the seeds `genesis/<index>`, and their accounts.
+pub fn genesis_accounts(count: usize) -> (Vec<Keypair>, Vec<AccountState>) {
+    let keypairs = keypairs("genesis", count);
+    let accounts = funded_accounts(&keypairs);
+    (keypairs, accounts)
+}
+
+/// An SPL token mint whose address and authority are derived from its name.
+pub struct FixtureMint {
+    pub name: String,
+    pub address: Pubkey,
+    pub authority: Keypair,
+    pub decimals: u8,
+}
+
+impl FixtureMint {
+    pub fn new(name: &str, decimals: u8) -> Self {
+        FixtureMint {
+            name: name.to_string(),
+            address: keypair(&format!("mint/{}", name)).pubkey(),
+            authority: keypair(&format!("mint/{}/authority", name)),
+            decimals,
+        }
+    }
+
+    /// The initialized mint account with `MINT_SUPPLY` issued.
+    pub fn account(&self) -> AccountState {
+        let mint = Mint {
+            mint_authority: COption::Some(self.authority.pubkey()),
+            supply: MINT_SUPPLY,
+            decimals: self.decimals,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        };
+        let mut data = vec![0u8; Mint::LEN];
+        Mint::pack(mint, &mut data).expect("The buffer is Mint::LEN long");
+        token_program_account(self.address, data)
+    }
+
+    /// Address of `owner`'s token account for the mint.
+    pub fn token_account_address(&self, owner: &Pubkey) -> Pubkey {
+        keypair(&format!("mint/{}/token-account/{}", self.name, owner)).pubkey()
+    }
+
+    /// `owner`'s initialized token account holding `amount` base units.
+    pub fn token_account(&self, owner: &Pubkey, amount: u64) -> AccountState {
+        let account = TokenAccount {
+            mint: self.address,
+            owner: *owner,
+            amount,
+            delegate: COption::None,
+            state: TokenAccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        };
+        let mut data = vec![0u8; TokenAccount::LEN];
+        TokenAccount::pack(account, &mut data).expect("The buffer is Account::LEN long");
+        token_program_account(self.token_account_address(owner), data)
+    }
+}
+
+/// The canned mints, `USDC` and `BONK`, both with `MINT_DECIMALS`.
+pub fn mints() -> Vec<FixtureMint> {
+    ["USDC", "BONK"].into_iter().map(|name| FixtureMint::new(name, MINT_DECIMALS)).collect()
+}
+
+fn token_program_account(address: Pubkey, data: Vec<u8>) -> AccountState {
+    AccountState {
+        address,
+        lamports: 2_039_280,
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: 0,
+        data_root: None,
+    }
+}
+
+/// A system transfer of `lamports` from `from` to `to`, signed by `from`.
+pub fn transfer_transaction(from: &Keypair, to: &Pubkey, lamports: u64, recent_blockhash: Hash) -> Transaction {
+    let instruction = system_instruction::transfer(&from.pubkey(), to, lamports);
+    Transaction::new_signed_with_payer(&[instruction], Some(&from.pubkey()), &[from], recent_blockhash)
+}
+
+/// A checked transfer of `amount` base units of `mint` from `owner`'s token account to `to`'s,
+/// signed and paid for by `owner`.
+pub fn token_transfer_transaction(owner: &Keypair, mint: &FixtureMint, to: &Pubkey, amount: u64, recent_blockhash: Hash) -> Transaction {
+    let instruction = spl_token::instruction::transfer_checked(
+        &spl_token::id(),
+        &mint.token_account_address(&owner.pubkey()),
+        &mint.address,
+        &mint.token_account_address(to),
+        &owner.pubkey(),
+        &[],
+        amount,
+        mint.decimals,
+    ).expect("The token program id is spl_token's");
+    Transaction::new_signed_with_payer(&[instruction], Some(&owner.pubkey()), &[owner], recent_blockhash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixtures_are_deterministic() {
+        assert_eq!(keypair("alice").to_bytes(), keypair("alice").to_bytes());
+        assert_ne!(keypair("alice").pubkey(), keypair("bob").pubkey());
+        let (keypairs, accounts) = genesis_accounts(3);
+        assert_eq!(keypairs[2].pubkey(), keypair("genesis/2").pubkey());
+        assert!(accounts.iter().all(|account| account.lamports == FUNDED_LAMPORTS));
+        assert_eq!(mints()[0].address, FixtureMint::new("USDC", MINT_DECIMALS).address);
+    }
+
+    #[test]
+    fn test_token_accounts_unpack() {
+        let mint = &mints()[0];
+        let owner = keypair("alice").pubkey();
+
+        let mint_state = Mint::unpack(&mint.account().data).unwrap();
+        assert_eq!(mint_state.mint_authority, COption::Some(mint.authority.pubkey()));
+        assert_eq!(mint_state.decimals, MINT_DECIMALS);
+        let token_account = mint.token_account(&owner, 500);
+        assert_eq!(token_account.address, mint.token_account_address(&owner));
+        let token_state = TokenAccount::unpack(&token_account.data).unwrap();
+        assert_eq!((token_state.mint, token_state.owner, token_state.amount), (mint.address, owner, 500));
+    }
+
+    #[test]
+    fn test_transactions_are_signed() {
+        let alice = keypair("alice");
+        let transfer = transfer_transaction(&alice, &keypair("bob").pubkey(), 10, Hash::default());
+        assert!(transfer.verify().is_ok());
+        let token_transfer = token_transfer_transaction(&alice, &mints()[0], &keypair("bob").pubkey(), 10, Hash::default());
+        assert!(token_transfer.verify().is_ok());
+        assert_eq!(token_transfer.message.account_keys[0], alice.pubkey());
+    }
+}
@@ -7,20 +7,53 @@ use solana_sdk::instruction::CompiledInstruction;
 use solana_sdk::message::{Message, MessageHeader};
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signature;
-use solana_sdk::transaction::{SanitizedTransaction, Transaction};
+use solana_sdk::transaction::{SanitizedTransaction, Transaction, TransactionError};
 use std::io::{Error, ErrorKind};
 
 impl StateRecord for TrollupTransaction {
+    /// 1 added `system`
+    const ENCODING_VERSION: u32 = 1;
+
     fn get_key(&self) -> [u8; 32] {
-        let hash: [u8; 32] = Sha256::digest(&self.signatures[0]).into();
+        // Unsigned transactions never make it through the API, but deserialized bytes can still
+        // carry an empty signature list, so fall back to the message rather than indexing.
+        let hash: [u8; 32] = match self.signatures.first() {
+            Some(signature) => Sha256::digest(signature).into(),
+            None => Sha256::digest(to_vec(&self.message).unwrap_or_default()).into(),
+        };
         hash
     }
+
+    fn upgrade(version: u32, bytes: &[u8]) -> Option<Self> {
+        match version {
+            0 => {
+                let transaction = borsh::from_slice::<TrollupTransactionV0>(bytes).ok()?;
+                Some(TrollupTransaction {
+                    optimistic: transaction.optimistic,
+                    system: false,
+                    signatures: transaction.signatures,
+                    message: transaction.message,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// `TrollupTransaction` before the system lane.
+#[derive(BorshDeserialize)]
+struct TrollupTransactionV0 {
+    optimistic: bool,
+    signatures: Vec<[u8; 64]>,
+    message: TrollupMessage,
 }
 
 // Wrapper structures for Borsh serialization
 #[derive(Debug, BorshSerialize, BorshDeserialize, Clone)]
 pub struct TrollupTransaction {
     pub optimistic: bool,
+    /// Operator maintenance transaction from the system lane, executed without fees
+    pub system: bool,
     pub signatures: Vec<[u8; 64]>,
     pub message: TrollupMessage,
 }
@@ -67,6 +100,7 @@ impl From<&Transaction> for TrollupTransaction {
         }
         TrollupTransaction {
             optimistic: false,
+            system: false,
             signatures: sigs,
             message: (&tx.message).into(),
         }
@@ -195,12 +229,13 @@ pub fn convert_to_trollup_transaction(tx: Transaction) -> Result<TrollupTransact
     // Create and return the Solana Transaction
     Ok(TrollupTransaction {
         optimistic: false,
+        system: false,
         signatures,
         message,
     })
 }
 
 pub fn convert_to_sanitized_transaction(tx: &TrollupTransaction) -> solana_sdk::transaction::Result<SanitizedTransaction> {
-    let transaction = convert_to_solana_transaction(tx.clone()).expect("Error converting TrollupTransaction to Solana Transaction");
+    let transaction = convert_to_solana_transaction(tx.clone()).map_err(|_| TransactionError::SanitizeFailure)?;
     SanitizedTransaction::try_from_legacy_transaction(transaction, &HashSet::new())
 }
@@ -0,0 +1,120 @@
+use crate::state_record::StateRecord;
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+/// How far a transaction has made it, in the order it gets there.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum CommitmentLevel {
+    /// Executed by the engine, its batch is on its way to a block
+    Executed,
+    /// Part of a finalized rollup block
+    Committed,
+    /// The block's state root is committed on L1
+    FinalizedOnChain,
+}
+
+/// Where a transaction is in the node, so a transaction still waiting can be told apart from one
+/// the node never saw.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionLifecycle {
+    /// Not in the pool, in no tracked batch and not stored, the node doesn't know of it
+    #[default]
+    Unknown,
+    /// Waiting in the transaction pool
+    Pooled,
+    /// Drained from the pool, the engine is executing its batch
+    Executing,
+    /// Executed, its batch is on its way to a block through the proved path
+    Executed,
+    /// Executed into a pending optimistic commitment, waiting for its root on L1 or for its
+    /// window to end
+    PendingOptimistic,
+    /// Stored with a finalized block
+    Finalized,
+    /// Dropped without being executed because the SVM couldn't load it, see `load_failure`
+    Dropped,
+}
+
+/// Body of `/get-transaction-status`.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionStatus {
+    /// `None` while the node doesn't know of the transaction having been executed
+    pub commitment: Option<CommitmentLevel>,
+    pub block_number: Option<u64>,
+    pub l1_slot: Option<u64>,
+    /// Base58 signature of the L1 transaction that committed the block
+    pub l1_signature: Option<String>,
+    #[serde(default)]
+    pub lifecycle: TransactionLifecycle,
+    /// Hex state root of the pending optimistic commitment holding the transaction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub optimistic_state_root: Option<String>,
+    /// Why the SVM couldn't load the transaction, which was dropped without being executed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_failure: Option<LoadFailureCause>,
+}
+
+/// Why the SVM failed to load a transaction's accounts before executing it.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum LoadFailureCause {
+    /// An account, usually the fee payer, doesn't exist
+    MissingAccount,
+    /// An invoked program doesn't exist
+    MissingProgram,
+    /// An invoked account isn't an executable program
+    InvalidProgram,
+    /// An account is owned by a program that doesn't allow its use, e.g. a fee payer not owned
+    /// by the system program
+    InvalidOwner,
+    /// The fee payer can't pay the fee
+    InsufficientFunds,
+    /// The transaction would leave an account below the rent exempt minimum
+    RentViolation,
+    /// An account is locked twice, or too many accounts are locked
+    AccountLocks,
+    /// The loaded account data exceeds the transaction's limit
+    DataSizeLimit,
+    Other,
+}
+
+impl std::fmt::Display for LoadFailureCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadFailureCause::MissingAccount => write!(f, "missing_account"),
+            LoadFailureCause::MissingProgram => write!(f, "missing_program"),
+            LoadFailureCause::InvalidProgram => write!(f, "invalid_program"),
+            LoadFailureCause::InvalidOwner => write!(f, "invalid_owner"),
+            LoadFailureCause::InsufficientFunds => write!(f, "insufficient_funds"),
+            LoadFailureCause::RentViolation => write!(f, "rent_violation"),
+            LoadFailureCause::AccountLocks => write!(f, "account_locks"),
+            LoadFailureCause::DataSizeLimit => write!(f, "data_size_limit"),
+            LoadFailureCause::Other => write!(f, "other"),
+        }
+    }
+}
+
+impl TransactionStatus {
+    pub fn has_reached(&self, level: CommitmentLevel) -> bool {
+        self.commitment.is_some_and(|commitment| commitment >= level)
+    }
+}
+
+/// Entry of the transaction index, where a finalized transaction ended up.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionLocation {
+    pub transaction_id: [u8; 32],
+    pub block_number: u64,
+    /// Position of the transaction in the block
+    pub index: u32,
+}
+
+impl StateRecord for TransactionLocation {
+    fn get_key(&self) -> [u8; 32] {
+        self.transaction_id
+    }
+}
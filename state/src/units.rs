@@ -0,0 +1,132 @@
+//! Durations and sizes as operators write them in the configuration, e.g. `30s`, `1h30m`,
+//! `500ms`, `10MB` or `512KiB`. A bare number keeps the unit the setting always had, so existing
+//! configurations read the same.
+
+use std::time::Duration;
+
+const DURATION_UNITS: [(&str, Duration); 6] = [
+    ("ms", Duration::from_millis(1)),
+    ("s", Duration::from_secs(1)),
+    ("m", Duration::from_secs(60)),
+    ("h", Duration::from_secs(3600)),
+    ("d", Duration::from_secs(86_400)),
+    ("w", Duration::from_secs(604_800)),
+];
+
+const SIZE_UNITS: [(&str, u64); 7] = [
+    ("b", 1),
+    ("kb", 1000),
+    ("mb", 1000 * 1000),
+    ("gb", 1000 * 1000 * 1000),
+    ("kib", 1024),
+    ("mib", 1024 * 1024),
+    ("gib", 1024 * 1024 * 1024),
+];
+
+/// Parses a duration of one or more `<number><unit>` parts, `ms`, `s`, `m`, `h`, `d` or `w`, or a
+/// bare number of `bare_unit`.
+pub fn parse_duration(value: &str, bare_unit: Duration) -> Result<Duration, String> {
+    let value = value.trim();
+    if let Ok(count) = value.parse::<u64>() {
+        return multiply(bare_unit, count).ok_or_else(|| format!("{} is too long", value));
+    }
+    let parts = split_parts(value).ok_or_else(|| format!("{} isn't a duration, expected e.g. 500ms, 30s, 5m or 1h30m", value))?;
+    parts.into_iter().try_fold(Duration::ZERO, |total, (count, unit)| {
+        let (_, unit) = DURATION_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .ok_or_else(|| format!("Unknown duration unit {} in {}, expected ms, s, m, h, d or w", unit, value))?;
+        multiply(*unit, count)
+            .and_then(|part| total.checked_add(part))
+            .ok_or_else(|| format!("{} is too long", value))
+    })
+}
+
+/// Parses a size in bytes, a bare number or a number with a unit: `B`, `KB`, `MB`, `GB` or their
+/// binary `KiB`, `MiB` and `GiB`. Units are case insensitive.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    if let Ok(bytes) = value.parse::<u64>() {
+        return Ok(bytes);
+    }
+    let (count, unit) = match split_parts(value).as_deref() {
+        Some([(count, unit)]) => (*count, unit.to_ascii_lowercase()),
+        _ => return Err(format!("{} isn't a size, expected e.g. 512, 64KiB or 10MB", value)),
+    };
+    let (_, unit) = SIZE_UNITS
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .ok_or_else(|| format!("Unknown size unit {} in {}, expected B, KB, MB, GB, KiB, MiB or GiB", unit, value))?;
+    count.checked_mul(*unit).ok_or_else(|| format!("{} is too large", value))
+}
+
+/// Whole `unit`s in `duration`, for settings kept as a count of seconds or milliseconds.
+pub fn whole_units(duration: Duration, unit: Duration) -> Result<u64, String> {
+    if duration.as_nanos() % unit.as_nanos() != 0 {
+        return Err(format!("{:?} isn't a whole number of {:?}", duration, unit));
+    }
+    u64::try_from(duration.as_nanos() / unit.as_nanos()).map_err(|_| format!("{:?} is too long", duration))
+}
+
+/// `10MB` into `[(10, "MB")]`, `1h 30m` into `[(1, "h"), (30, "m")]`. `None` when a part misses
+/// its number or its unit.
+fn split_parts(value: &str) -> Option<Vec<(u64, &str)>> {
+    let mut parts = Vec::new();
+    let mut rest = value.trim_start();
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let count = rest[..digits].parse().ok()?;
+        rest = rest[digits..].trim_start();
+        let letters = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        if letters == 0 {
+            return None;
+        }
+        parts.push((count, &rest[..letters]));
+        rest = rest[letters..].trim_start();
+    }
+    (!parts.is_empty()).then_some(parts)
+}
+
+fn multiply(unit: Duration, count: u64) -> Option<Duration> {
+    unit.checked_mul(u32::try_from(count).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_durations() {
+        let seconds = Duration::from_secs(1);
+        assert_eq!(parse_duration("60", seconds), Ok(Duration::from_secs(60)));
+        assert_eq!(parse_duration("60", Duration::from_millis(1)), Ok(Duration::from_millis(60)));
+        assert_eq!(parse_duration("500ms", seconds), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration(" 5m ", seconds), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h30m", seconds), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1h 30m", seconds), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2d", seconds), Ok(Duration::from_secs(172_800)));
+        assert!(parse_duration("5 minutes", seconds).is_err());
+        assert!(parse_duration("1.5h", seconds).is_err());
+        assert!(parse_duration("s", seconds).is_err());
+        assert!(parse_duration("", seconds).is_err());
+    }
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("10MB"), Ok(10_000_000));
+        assert_eq!(parse_size("10 mb"), Ok(10_000_000));
+        assert_eq!(parse_size("64KiB"), Ok(65_536));
+        assert_eq!(parse_size("1GiB"), Ok(1 << 30));
+        assert!(parse_size("10MiB 4KiB").is_err());
+        assert!(parse_size("10XB").is_err());
+        assert!(parse_size("-1").is_err());
+    }
+
+    #[test]
+    fn test_whole_units() {
+        assert_eq!(whole_units(Duration::from_secs(120), Duration::from_secs(1)), Ok(120));
+        assert_eq!(whole_units(Duration::from_secs(2), Duration::from_millis(1)), Ok(2000));
+        assert!(whole_units(Duration::from_millis(1500), Duration::from_secs(1)).is_err());
+    }
+}
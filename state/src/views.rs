@@ -0,0 +1,244 @@
+//! JSON views of rollup records for API responses. Keys and signatures are base58 like in
+//! Solana's JSON RPC, hashes and roots hex, and instructions of well known programs are decoded
+//! next to their raw data, which stays the only content for programs without a decoder.
+
+use crate::account_state::AccountState;
+use crate::block::Block;
+use crate::instruction_decoder::decode_instruction;
+use crate::state_record::StateRecord;
+use crate::transaction::{TrollupCompileInstruction, TrollupTransaction};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionView {
+    /// Base58 signatures, the first one identifies the transaction
+    pub signatures: Vec<String>,
+    pub optimistic: bool,
+    pub system: bool,
+    pub num_required_signatures: u8,
+    pub num_readonly_signed_accounts: u8,
+    pub num_readonly_unsigned_accounts: u8,
+    pub account_keys: Vec<String>,
+    pub recent_blockhash: String,
+    pub instructions: Vec<InstructionView>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct InstructionView {
+    pub program_id: String,
+    pub accounts: Vec<String>,
+    /// Base64 instruction data
+    pub data: String,
+    /// The instruction decoded, for programs with a decoder in the `instruction_decoder` registry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<Object>))]
+    pub parsed: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct BlockView {
+    pub block_number: u64,
+    /// Hex encoded, like every hash and root of the block
+    pub id: String,
+    pub block_hash: String,
+    pub previous_block: String,
+    pub transactions_merkle_root: String,
+    pub accounts_merkle_root: String,
+    pub outbox_root: String,
+    pub verifying_key_hash: String,
+    /// Keys of the block's transactions
+    pub transactions: Vec<String>,
+    /// Base58 addresses of the accounts the block wrote, in leaf order
+    pub accounts: Vec<String>,
+    pub fees: u64,
+    /// Base58 key of the sequencer that produced the block
+    pub sequencer: String,
+    /// Version of the node software that produced the block, `major.minor.patch`
+    pub node_version: String,
+    /// Hash of the governed parameters the block was produced under, see `get-parameters`
+    #[serde(default)]
+    pub parameters_hash: String,
+    pub outbox_message_count: u64,
+    pub challenge_outcome_count: u64,
+    /// An empty block produced to keep the cadence, see `Block::is_heartbeat`
+    pub heartbeat: bool,
+    pub l1_slot: Option<u64>,
+    pub l1_signature: Option<String>,
+    /// Base64 Groth16 proof and public inputs
+    pub accounts_zk_proof: String,
+    pub accounts_zk_public_inputs: String,
+}
+
+/// An account as a block wrote it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountView {
+    pub address: String,
+    pub lamports: u64,
+    pub owner: String,
+    pub executable: bool,
+    pub rent_epoch: u64,
+    /// Base64
+    pub data: String,
+}
+
+/// A finalized block with its transactions and the accounts it wrote, the result of a
+/// `blockNotification`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FinalizedBlockView {
+    pub block: BlockView,
+    /// In block order
+    pub transactions: Vec<TransactionView>,
+    pub accounts: Vec<AccountView>,
+}
+
+impl From<&AccountState> for AccountView {
+    fn from(account: &AccountState) -> Self {
+        AccountView {
+            address: account.address.to_string(),
+            lamports: account.lamports,
+            owner: account.owner.to_string(),
+            executable: account.executable,
+            rent_epoch: account.rent_epoch,
+            data: general_purpose::STANDARD.encode(&account.data),
+        }
+    }
+}
+
+impl From<&TrollupTransaction> for TransactionView {
+    fn from(transaction: &TrollupTransaction) -> Self {
+        let message = &transaction.message;
+        let account_keys: Vec<Pubkey> = message.account_keys.iter().map(|key| Pubkey::from(*key)).collect();
+        TransactionView {
+            signatures: transaction.signatures.iter().map(|signature| Signature::from(*signature).to_string()).collect(),
+            optimistic: transaction.optimistic,
+            system: transaction.system,
+            num_required_signatures: message.header[0],
+            num_readonly_signed_accounts: message.header[1],
+            num_readonly_unsigned_accounts: message.header[2],
+            account_keys: account_keys.iter().map(Pubkey::to_string).collect(),
+            recent_blockhash: Hash::new_from_array(message.recent_blockhash).to_string(),
+            instructions: message.instructions
+                .iter()
+                .map(|instruction| InstructionView::new(instruction, &account_keys))
+                .collect(),
+        }
+    }
+}
+
+impl InstructionView {
+    /// Resolves the instruction's program and accounts against the transaction's `account_keys`.
+    pub fn new(instruction: &TrollupCompileInstruction, account_keys: &[Pubkey]) -> Self {
+        let program_id = account_keys.get(instruction.program_id_index as usize).copied().unwrap_or_default();
+        let accounts: Vec<Pubkey> = instruction.accounts
+            .iter()
+            .map(|index| account_keys.get(*index as usize).copied().unwrap_or_default())
+            .collect();
+        InstructionView {
+            program_id: program_id.to_string(),
+            accounts: accounts.iter().map(Pubkey::to_string).collect(),
+            data: general_purpose::STANDARD.encode(&instruction.data),
+            parsed: decode_instruction(&program_id, &accounts, &instruction.data),
+        }
+    }
+}
+
+impl From<&Block> for BlockView {
+    fn from(block: &Block) -> Self {
+        BlockView {
+            block_number: block.block_number,
+            id: hex::encode(block.get_key()),
+            block_hash: hex::encode(block.hash()),
+            previous_block: hex::encode(block.previous_block),
+            transactions_merkle_root: hex::encode(*block.transactions_merkle_root),
+            accounts_merkle_root: hex::encode(*block.accounts_merkle_root),
+            outbox_root: hex::encode(block.outbox_root),
+            verifying_key_hash: hex::encode(block.verifying_key_hash),
+            transactions: block.transactions.iter().map(hex::encode).collect(),
+            accounts: block.accounts.iter().map(|address| Pubkey::from(*address).to_string()).collect(),
+            fees: block.fees,
+            sequencer: Pubkey::from(block.producer().sequencer).to_string(),
+            node_version: block.producer().version.to_string(),
+            parameters_hash: hex::encode(block.parameters_hash()),
+            outbox_message_count: block.outbox_messages.len() as u64,
+            challenge_outcome_count: block.challenge_outcomes.len() as u64,
+            heartbeat: block.is_heartbeat(),
+            l1_slot: block.l1_anchor.slot,
+            l1_signature: block.l1_anchor.signature.clone(),
+            accounts_zk_proof: general_purpose::STANDARD.encode(&block.accounts_zk_proof),
+            accounts_zk_public_inputs: general_purpose::STANDARD.encode(&block.accounts_zk_public_inputs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::system_instruction::{self, SystemInstruction};
+    use solana_sdk::system_program;
+    use solana_sdk::transaction::Transaction;
+    use trollup_commitment_message::{BlockProducer, NodeVersion};
+
+    #[test]
+    fn test_transaction_view_decodes_system_transfer() {
+        let payer = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        let transaction = Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(&payer.pubkey(), &recipient, 42)],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Default::default(),
+        );
+        let view = TransactionView::from(&TrollupTransaction::from(&transaction));
+
+        assert_eq!(view.signatures, vec![transaction.signatures[0].to_string()]);
+        assert_eq!(view.account_keys[0], payer.pubkey().to_string());
+        let instruction = &view.instructions[0];
+        assert_eq!(instruction.program_id, system_program::id().to_string());
+        assert_eq!(instruction.parsed, Some(json!({
+            "type": "transfer",
+            "source": payer.pubkey().to_string(),
+            "destination": recipient.to_string(),
+            "lamports": 42,
+        })));
+    }
+
+    #[test]
+    fn test_unknown_program_is_not_decoded() {
+        let accounts = [Pubkey::new_unique()];
+        assert_eq!(decode_instruction(&Pubkey::new_unique(), &accounts, &[1, 2, 3]), None);
+        assert_eq!(
+            decode_instruction(&system_program::id(), &accounts, &bincode::serialize(&SystemInstruction::AdvanceNonceAccount).unwrap()),
+            Some(json!({ "type": "advanceNonceAccount" })),
+        );
+    }
+
+    #[test]
+    fn test_block_view_reports_the_producer() {
+        let sequencer = Pubkey::new_unique();
+        let mut block = Block::new(1, Block::get_id(0), Box::new([1u8; 32]), Box::new([2u8; 32]), vec![], vec![], [0u8; 32], vec![[3u8; 32]], vec![]);
+        let unproduced_hash = block.hash();
+        block.set_producer(BlockProducer { sequencer: sequencer.to_bytes(), version: NodeVersion::new(0, 4, 1) });
+
+        let view = BlockView::from(&block);
+        assert_eq!(view.sequencer, sequencer.to_string());
+        assert_eq!(view.node_version, "0.4.1");
+        // The producer is part of the block hash
+        assert_ne!(block.hash(), unproduced_hash);
+        assert!(block.has_consistent_hash());
+    }
+}
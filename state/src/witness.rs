@@ -0,0 +1,63 @@
+use crate::account_state::AccountState;
+use crate::rollup_metadata::RollupMetadata;
+use crate::transaction::TrollupTransaction;
+use borsh::{BorshDeserialize, BorshSerialize};
+use rs_merkle::algorithms::Sha256;
+use rs_merkle::{Hasher, MerkleProof};
+
+/// Leaf of an account in a block's account tree, the SHA-256 of `AccountState::leaf_bytes`. The
+/// data of an account with a `data_root` is left out, the root commits to it.
+pub fn account_leaf(account: &AccountState) -> [u8; 32] {
+    Sha256::hash(&account.leaf_bytes())
+}
+
+/// Proof that an account state is a leaf of the account tree of the block that last wrote it.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct AccountInclusion {
+    pub block_number: u64,
+    /// Account root of the block
+    pub root: [u8; 32],
+    pub leaf_index: u64,
+    pub leaf_count: u64,
+    /// Sibling hashes from the leaf up, as produced by rs_merkle
+    pub proof: Vec<[u8; 32]>,
+}
+
+impl AccountInclusion {
+    pub fn verify(&self, account: &AccountState) -> bool {
+        MerkleProof::<Sha256>::new(self.proof.clone()).verify(
+            self.root,
+            &[self.leaf_index as usize],
+            &[account_leaf(account)],
+            self.leaf_count as usize,
+        )
+    }
+}
+
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct AccountWitness {
+    /// State of the account before the batch
+    pub account: AccountState,
+    /// Filled in by the committer, `None` while the batch is still in the commitment pool
+    pub inclusion: Option<AccountInclusion>,
+}
+
+/// What a validator needs to re-execute a batch without the account store: the pre-state of
+/// every stored account the batch touches, the transactions in execution order and the Clock
+/// and rollup metadata they were executed with.
+///
+/// Accounts the batch touches that have no witness did not exist before it, the validator
+/// executes against the same default accounts the sequencer's loader creates.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct WitnessBundle {
+    /// Account root of the block the batch was executed on, `None` for the first block
+    pub parent_state_root: Option<[u8; 32]>,
+    /// Clock the batch was executed with
+    pub slot: u64,
+    pub unix_timestamp: i64,
+    pub epoch_start_timestamp: i64,
+    /// Rollup metadata accounts the batch was executed with
+    pub rollup_metadata: RollupMetadata,
+    pub transactions: Vec<TrollupTransaction>,
+    pub accounts: Vec<AccountWitness>,
+}
@@ -0,0 +1,161 @@
+use async_trait::async_trait;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{write_keypair_file, Signature};
+use state::account_state::AccountState;
+use state::blob::{Blob, BlobReferences};
+use state::block::{Block, L1Anchor};
+use state::state_record::StateCommitmentPackage;
+use state::test_fixtures::{keypair, transfer_transaction};
+use state::transaction::{convert_to_trollup_transaction, TrollupTransaction};
+use state::witness::WitnessBundle;
+use state_commitment::l1_confirmation::L1Confirmer;
+use state_commitment::state_commitment_layer::{CommitmentClients, PdaListenerMessage, PdaSource, StateCommitment, StateCommitter};
+use state_commitment::state_commitment_pool::{StateCommitmentPool, StatePool};
+use state_commitment::validator_client::{ApiResponse, BatchEntry, Validator};
+use state_management::blob_store::BlobStore;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::{ManageState, StateManager};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::{self, Sender};
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Instant};
+use trollup_zk::fixtures::accounts_fixture;
+use trollup_zk::prove::ProofPackagePrepared;
+
+/// Blocks committed per iteration
+const BLOCKS: u64 = 6;
+/// Time the validator takes to verify a proof and send the commitment transaction
+const VALIDATOR_LATENCY: Duration = Duration::from_millis(150);
+/// Time a commitment transaction takes to finalize
+const L1_LATENCY: Duration = Duration::from_millis(400);
+
+struct SlowValidator;
+
+#[async_trait]
+impl Validator for SlowValidator {
+    async fn prove(&self, _proof_package: ProofPackagePrepared, _witness: Option<&WitnessBundle>, _new_state_root: &[u8; 32], _sequence: u64, _outbox_root: &[u8; 32]) -> anyhow::Result<ApiResponse> {
+        sleep(VALIDATOR_LATENCY).await;
+        Ok(ApiResponse { success: true, signature: Signature::new_unique() })
+    }
+
+    async fn prove_batch(&self, _entries: &[BatchEntry]) -> anyhow::Result<ApiResponse> {
+        sleep(VALIDATOR_LATENCY).await;
+        Ok(ApiResponse { success: true, signature: Signature::new_unique() })
+    }
+}
+
+struct SlowConfirmer;
+
+#[async_trait]
+impl L1Confirmer for SlowConfirmer {
+    async fn confirm(&self, signature: &Signature) -> anyhow::Result<Option<L1Anchor>> {
+        sleep(L1_LATENCY).await;
+        Ok(Some(L1Anchor { slot: Some(0), signature: Some(signature.to_string()) }))
+    }
+}
+
+struct NoPdaUpdates;
+
+#[async_trait]
+impl PdaSource for NoPdaUpdates {
+    async fn listen(&self, _pda_sender: Sender<PdaListenerMessage>) {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Keypairs for the configuration in a scratch directory, which becomes the working directory
+/// the proving keys are written to.
+fn configure() {
+    let directory = std::env::temp_dir().join(format!("trollup-commit-pipeline-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let keypair_path = directory.join("keypair.json");
+    write_keypair_file(&keypair("sequencer"), &keypair_path).unwrap();
+    std::env::set_var("TROLLUP_API_KEYPAIR_PATH", &keypair_path);
+    std::env::set_var("TROLLUP_VALIDATOR_KEYPAIR_PATH", &keypair_path);
+    std::env::set_current_dir(&directory).unwrap();
+}
+
+fn package(seed: u64) -> StateCommitmentPackage<AccountState> {
+    let accounts: Vec<AccountState> = accounts_fixture(2)
+        .into_iter()
+        .map(|account| AccountState { lamports: account.lamports + seed, ..account })
+        .collect();
+    let transfer = transfer_transaction(&keypair("payer"), &Pubkey::new_unique(), seed + 1, Hash::default());
+    let transaction = convert_to_trollup_transaction(transfer).unwrap();
+    StateCommitmentPackage::new(false, accounts, vec![transaction], vec![[seed as u8; 32]])
+}
+
+/// Commits `BLOCKS` queued blocks with up to `pipeline_depth` in flight. Returns the time from
+/// the first finalized block to the last, leaving out the proving key setup and the latency of
+/// the first block.
+async fn commit_blocks(pipeline_depth: usize) -> Duration {
+    let accounts = StateManager::<SledStateManagement<AccountState>>::new("");
+    let blocks = StateManager::<SledStateManagement<Block>>::new("");
+    let transactions = StateManager::<SledStateManagement<TrollupTransaction>>::new("");
+    let optimistic = Arc::new(StateManager::<SledStateManagement<StateCommitmentPackage<AccountState>>>::new(""));
+    let blob_store = Arc::new(BlobStore::new(
+        Arc::new(StateManager::<SledStateManagement<Blob>>::new("")),
+        Arc::new(StateManager::<SledStateManagement<BlobReferences>>::new("")),
+    ));
+    let pool = Arc::new(Mutex::new(StateCommitmentPool::new()));
+    for seed in 0..BLOCKS {
+        pool.lock().await.add(package(seed));
+    }
+    let (requeue_sender, _requeue_receiver) = mpsc::unbounded_channel();
+    let clients = CommitmentClients {
+        validator: Arc::new(SlowValidator),
+        l1_confirmer: Arc::new(SlowConfirmer),
+        pda_source: Arc::new(NoPdaUpdates),
+        l1_confirmation_timeout: Duration::from_secs(60),
+    };
+    let mut committer = StateCommitment::new(&accounts, pool, &blocks, &transactions, optimistic, blob_store, requeue_sender)
+        .with_clients(clients)
+        .with_pipeline_depth(pipeline_depth);
+
+    let finalized = async {
+        let mut first_finalized = None;
+        loop {
+            let block_number = blocks
+                .get_latest_block_id()
+                .and_then(|id| blocks.get_state_record(&id))
+                .map(|block| block.block_number)
+                .unwrap_or_default();
+            if block_number >= 1 && first_finalized.is_none() {
+                first_finalized = Some(Instant::now());
+            }
+            if block_number >= BLOCKS {
+                return first_finalized.unwrap().elapsed();
+            }
+            sleep(Duration::from_millis(5)).await;
+        }
+    };
+    tokio::select! {
+        _ = committer.start() => unreachable!("the committer runs until it is stopped"),
+        elapsed = finalized => elapsed,
+    }
+}
+
+/// Blocks finalized per second with a slow validator and L1, committing one block at a time
+/// against proving and sending the next blocks while one awaits L1.
+fn commit_pipeline(c: &mut Criterion) {
+    configure();
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("commit_pipeline");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(BLOCKS - 1));
+    for pipeline_depth in [1, 2, 4] {
+        group.bench_with_input(BenchmarkId::new("depth", pipeline_depth), &pipeline_depth, |b, &pipeline_depth| {
+            b.iter_custom(|iterations| {
+                (0..iterations).map(|_| runtime.block_on(commit_blocks(pipeline_depth))).sum()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, commit_pipeline);
+criterion_main!(benches);
@@ -0,0 +1,274 @@
+use crate::http_client::shared_client;
+use async_trait::async_trait;
+use borsh::BorshDeserialize;
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use reqwest::{Client, StatusCode};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use state::artifact::{signature_name, ArtifactSignature};
+use state::batch_data::BatchData;
+use state::block::Block;
+use state::config::TrollupConfig;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref STORE: Option<Box<dyn BatchDataStore>> = store_from_config(&CONFIG.batch_data_location);
+}
+
+/// Where finalized blocks' batch data is published, so a node that lost its history can rebuild
+/// it. Objects are Borsh `BatchData`, named after the block number, each with its detached
+/// signature as `<name>.sig`.
+#[async_trait]
+pub trait BatchDataStore: Send + Sync {
+    async fn put(&self, name: &str, bytes: &[u8]) -> anyhow::Result<()>;
+    /// `None` when there is no object `name`
+    async fn get(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>>;
+}
+
+/// Zero padded, so the objects list in block order.
+fn object_name(block_number: u64) -> String {
+    format!("{:020}.borsh", block_number)
+}
+
+/// A directory, local or a mounted bucket.
+pub struct DirectoryStore {
+    directory: PathBuf,
+}
+
+#[async_trait]
+impl BatchDataStore for DirectoryStore {
+    async fn put(&self, name: &str, bytes: &[u8]) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.directory)?;
+        // Written under a temporary name first, a reader never sees half an object
+        let path = self.directory.join(name);
+        let partial = self.directory.join(format!("{}.partial", name));
+        std::fs::write(&partial, bytes)?;
+        std::fs::rename(&partial, &path)?;
+        Ok(())
+    }
+
+    async fn get(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.directory.join(name)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+/// An HTTP object store, objects are `PUT` and `GET` below the base URL.
+pub struct HttpStore {
+    client: Client,
+    base_url: String,
+}
+
+#[async_trait]
+impl BatchDataStore for HttpStore {
+    async fn put(&self, name: &str, bytes: &[u8]) -> anyhow::Result<()> {
+        self.client
+            .put(format!("{}/{}", self.base_url, name))
+            .body(bytes.to_vec())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn get(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let response = self.client
+            .get(format!("{}/{}", self.base_url, name))
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.bytes().await?.to_vec()))
+    }
+}
+
+/// `BATCH_DATA_LOCATION` is empty (disabled), an `http://` or `https://` base URL, or a
+/// directory.
+pub fn store_from_config(location: &str) -> Option<Box<dyn BatchDataStore>> {
+    match location {
+        "" => None,
+        url if url.starts_with("http://") || url.starts_with("https://") => Some(Box::new(HttpStore {
+            client: shared_client(),
+            base_url: url.trim_end_matches('/').to_string(),
+        })),
+        directory => Some(Box::new(DirectoryStore { directory: PathBuf::from(directory) })),
+    }
+}
+
+/// Key batch data has to be signed by: `BATCH_DATA_SIGNER`, or the API keypair's when it isn't
+/// set.
+pub fn trusted_signer(config: &TrollupConfig) -> Result<Pubkey, String> {
+    if config.batch_data_signer.is_empty() {
+        return Keypair::from_bytes(&config.trollup_api_keypair)
+            .map(|keypair| keypair.pubkey())
+            .map_err(|error| format!("API keypair: {}", error));
+    }
+    Pubkey::from_str(&config.batch_data_signer).map_err(|_| format!("Invalid BATCH_DATA_SIGNER {}", config.batch_data_signer))
+}
+
+/// Writes `batch` and, when there is a `signer`, its signature. The signature goes first, so a
+/// reader that finds the object also finds what vouches for it.
+pub async fn put(store: &dyn BatchDataStore, batch: &BatchData, signer: Option<&Keypair>) -> anyhow::Result<()> {
+    let name = object_name(batch.block_number);
+    let bytes = borsh::to_vec(batch)?;
+    if let Some(signer) = signer {
+        let signature = ArtifactSignature::sign(&name, &bytes, signer);
+        store.put(&signature_name(&name), &signature.to_json()).await?;
+    }
+    store.put(&name, &bytes).await
+}
+
+/// Fetches the batch data of `block_number`, `None` when nothing was published for it. The
+/// object has to be signed by `signer`; unsigned objects, published before batch data was
+/// signed, are only accepted with `allow_unsigned`. A signature that is there is always checked.
+pub async fn fetch(store: &dyn BatchDataStore, block_number: u64, signer: &Pubkey, allow_unsigned: bool) -> Result<Option<BatchData>, String> {
+    let name = object_name(block_number);
+    let Some(bytes) = store.get(&name).await.map_err(|error| format!("Failed to fetch {}: {}", name, error))? else {
+        return Ok(None);
+    };
+    let signature = store
+        .get(&signature_name(&name))
+        .await
+        .map_err(|error| format!("Failed to fetch the signature of {}: {}", name, error))?;
+    match signature {
+        Some(signature) => ArtifactSignature::from_json(&signature)?.verify(&name, &bytes, signer)?,
+        None if allow_unsigned => warn!("{} is unsigned, trusting it since unsigned batch data is allowed", name),
+        None => return Err(format!("{} has no signature", name)),
+    }
+    BatchData::try_from_slice(&bytes).map(Some).map_err(|error| format!("Invalid batch data in {}: {}", name, error))
+}
+
+/// Re-reads the batch data published for `block` and checks it is still there, signed and
+/// describes the block: its account state root, transactions, fees and governed parameters.
+pub async fn verify_published(store: &dyn BatchDataStore, block: &Block, signer: &Pubkey, allow_unsigned: bool) -> Result<(), String> {
+    let batch = fetch(store, block.block_number, signer, allow_unsigned)
+        .await?
+        .ok_or_else(|| format!("No batch data was published for block {}", block.block_number))?;
+    if batch.block_number != block.block_number {
+        return Err(format!("The batch data of block {} is for block {}", block.block_number, batch.block_number));
+    }
+    if batch.account_state_root != *block.accounts_merkle_root {
+        return Err(format!(
+            "The batch data of block {} has account state root {} but the block has {}",
+            block.block_number,
+            hex::encode(batch.account_state_root),
+            hex::encode(*block.accounts_merkle_root),
+        ));
+    }
+    if batch.transaction_ids != block.transactions || batch.fees != block.fees {
+        return Err(format!("The batch data of block {} has different transactions than the block", block.block_number));
+    }
+    if batch.parameters.hash() != *block.parameters_hash() {
+        return Err(format!("The batch data of block {} has different parameters than the block", block.block_number));
+    }
+    Ok(())
+}
+
+/// Publishes the batch data of a block that was just finalized, signed by the sequencer. A
+/// failure is logged, the block stays finalized but can't be rebuilt from the store.
+pub async fn publish(batch: BatchData) {
+    let Some(store) = STORE.as_ref() else {
+        return;
+    };
+    let signer = Keypair::from_bytes(&CONFIG.trollup_api_keypair).ok();
+    if signer.is_none() {
+        warn!("No sequencer keypair, the batch data of block {} is unsigned", batch.block_number);
+    }
+    match put(store.as_ref(), &batch, signer.as_ref()).await {
+        Ok(()) => info!("Published batch data of block {}", batch.block_number),
+        Err(error) => error!("Failed to publish batch data of block {}: {}", batch.block_number, error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use state::parameters::GovernedParameters;
+
+    fn batch(block_number: u64) -> BatchData {
+        BatchData {
+            block_number,
+            account_state_root: [3u8; 32],
+            transactions: vec![],
+            transaction_ids: vec![],
+            fees: 0,
+            witness: None,
+            parameters: GovernedParameters {
+                lamports_per_signature: 5_000,
+                transaction_batch_amount: 10,
+                max_bundle_transactions: 5,
+                challenge_window_secs: 60,
+            },
+        }
+    }
+
+    fn directory_store(name: &str) -> DirectoryStore {
+        let directory = std::env::temp_dir().join(format!("trollup-batch-data-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        DirectoryStore { directory }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_checks_the_signature() {
+        let store = directory_store("signed");
+        let sequencer = Keypair::new();
+        put(&store, &batch(1), Some(&sequencer)).await.unwrap();
+
+        let fetched = fetch(&store, 1, &sequencer.pubkey(), false).await.unwrap().unwrap();
+        assert_eq!(fetched.account_state_root, [3u8; 32]);
+        assert!(fetch(&store, 1, &Pubkey::new_unique(), true).await.is_err());
+        assert!(fetch(&store, 2, &sequencer.pubkey(), false).await.unwrap().is_none());
+
+        // A mirror swapping in different content under the same name
+        store.put(&object_name(1), &borsh::to_vec(&batch(2)).unwrap()).await.unwrap();
+        assert!(fetch(&store, 1, &sequencer.pubkey(), true).await.is_err());
+    }
+
+    /// The block `batch` was published for.
+    fn block_of(batch: &BatchData) -> Block {
+        let mut block = Block::new(batch.block_number, Block::get_id(batch.block_number - 1), Box::new([0u8; 32]), Box::new(batch.account_state_root), vec![], vec![], [0u8; 32], batch.transaction_ids.clone(), vec![]);
+        block.fees = batch.fees;
+        block.set_parameters_hash(batch.parameters.hash());
+        block
+    }
+
+    #[tokio::test]
+    async fn test_verify_published_matches_the_block() {
+        let store = directory_store("verify");
+        let sequencer = Keypair::new();
+        let published = BatchData { transaction_ids: vec![[1u8; 32]], fees: 5_000, ..batch(1) };
+        put(&store, &published, Some(&sequencer)).await.unwrap();
+        let block = block_of(&published);
+
+        assert_eq!(verify_published(&store, &block, &sequencer.pubkey(), false).await, Ok(()));
+        assert!(verify_published(&store, &block, &Pubkey::new_unique(), false).await.is_err());
+        assert!(verify_published(&store, &block_of(&batch(2)), &sequencer.pubkey(), false).await.unwrap_err().contains("No batch data"));
+
+        let mut other_root = block.clone();
+        other_root.accounts_merkle_root = Box::new([4u8; 32]);
+        assert!(verify_published(&store, &other_root, &sequencer.pubkey(), false).await.unwrap_err().contains("account state root"));
+        let mut other_transactions = block.clone();
+        other_transactions.transactions.push([2u8; 32]);
+        assert!(verify_published(&store, &other_transactions, &sequencer.pubkey(), false).await.unwrap_err().contains("transactions"));
+        let mut other_parameters = block.clone();
+        other_parameters.set_parameters_hash([5u8; 32]);
+        assert!(verify_published(&store, &other_parameters, &sequencer.pubkey(), false).await.unwrap_err().contains("parameters"));
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_batch_data_needs_opting_in() {
+        let store = directory_store("unsigned");
+        let sequencer = Keypair::new();
+        put(&store, &batch(1), None).await.unwrap();
+
+        assert!(fetch(&store, 1, &sequencer.pubkey(), false).await.is_err());
+        assert!(fetch(&store, 1, &sequencer.pubkey(), true).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,46 @@
+use lazy_static::lazy_static;
+use state::account_state::AccountState;
+use state::block::Block;
+use state::transaction::TrollupTransaction;
+use state::views::{AccountView, BlockView, FinalizedBlockView, TransactionView};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Finalized blocks buffered per subscriber. A subscriber that falls further behind misses the
+/// oldest blocks and is told how many it skipped.
+const FEED_CAPACITY: usize = 256;
+
+lazy_static! {
+    static ref FEED: broadcast::Sender<Arc<FinalizedBlock>> = broadcast::channel(FEED_CAPACITY).0;
+}
+
+/// A block as it was finalized, together with the records it changed.
+#[derive(Clone, Debug)]
+pub struct FinalizedBlock {
+    pub block: Block,
+    /// New state of every account the block changed
+    pub accounts: Vec<AccountState>,
+    pub transactions: Vec<TrollupTransaction>,
+}
+
+impl From<&FinalizedBlock> for FinalizedBlockView {
+    fn from(block: &FinalizedBlock) -> Self {
+        FinalizedBlockView {
+            block: BlockView::from(&block.block),
+            transactions: block.transactions.iter().map(TransactionView::from).collect(),
+            accounts: block.accounts.iter().map(AccountView::from).collect(),
+        }
+    }
+}
+
+/// Publishes a finalized block to every current subscriber. Blocks finalized while nobody is
+/// subscribed are dropped.
+pub fn publish(block: FinalizedBlock) {
+    let _ = FEED.send(Arc::new(block));
+}
+
+/// Receives the blocks finalized from now on. The feed is process wide, so the API can subscribe
+/// to blocks finalized by the commitment thread's runtime.
+pub fn subscribe() -> broadcast::Receiver<Arc<FinalizedBlock>> {
+    FEED.subscribe()
+}
@@ -0,0 +1,213 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::signature::Signature;
+use state::state_record::StateRecord;
+use state::transaction::TrollupTransaction;
+use std::collections::{HashMap, VecDeque};
+use std::sync::RwLock;
+
+/// Bundles that were excluded or finalized whose status is kept, older ones are forgotten.
+const MAX_FINISHED_BUNDLES: usize = 10_000;
+
+lazy_static! {
+    static ref BUNDLES: RwLock<Bundles> = RwLock::new(Bundles::default());
+}
+
+#[derive(Default)]
+struct Bundles {
+    by_id: HashMap<[u8; 32], BundleStatus>,
+    /// Bundle of every transaction of a bundle that isn't finished yet
+    of_transaction: HashMap<[u8; 32], [u8; 32]>,
+    finished: VecDeque<[u8; 32]>,
+}
+
+/// Where a bundle is. Its transactions execute in the same batch, and either all of them make it
+/// into a block or none does.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum BundleState {
+    /// Waiting in the transaction pool
+    Pending,
+    /// Every transaction executed, the batch is on its way to a block
+    Executed,
+    /// A transaction failed, none of them was kept
+    Excluded,
+    /// In a finalized block
+    Finalized,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleStatus {
+    /// Hex hash of the bundle's transaction keys
+    pub bundle_id: String,
+    pub state: BundleState,
+    /// First signature of every transaction, in execution order
+    pub signatures: Vec<String>,
+    /// Why the bundle was excluded
+    pub reason: Option<String>,
+    pub block_number: Option<u64>,
+    #[serde(skip)]
+    transaction_keys: Vec<[u8; 32]>,
+}
+
+/// Identifies a bundle by its transactions, in order.
+pub fn bundle_id(transactions: &[TrollupTransaction]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"trollup:bundle");
+    for transaction in transactions {
+        hasher.update(transaction.get_key());
+    }
+    hasher.finalize().into()
+}
+
+/// Starts tracking the bundle of `transactions` as pending. Fails when one of them already is
+/// in a bundle that isn't finished.
+pub fn register(transactions: &[TrollupTransaction]) -> Result<[u8; 32], String> {
+    let id = bundle_id(transactions);
+    let mut bundles = BUNDLES.write().unwrap();
+    if transactions.iter().any(|transaction| bundles.of_transaction.contains_key(&transaction.get_key())) {
+        return Err("A transaction of the bundle is already in a pending bundle".to_string());
+    }
+    let transaction_keys: Vec<[u8; 32]> = transactions.iter().map(StateRecord::get_key).collect();
+    for key in &transaction_keys {
+        bundles.of_transaction.insert(*key, id);
+    }
+    bundles.by_id.insert(id, BundleStatus {
+        bundle_id: hex::encode(id),
+        state: BundleState::Pending,
+        signatures: transactions
+            .iter()
+            .map(|transaction| Signature::from(transaction.signatures.first().copied().unwrap_or([0u8; 64])).to_string())
+            .collect(),
+        reason: None,
+        block_number: None,
+        transaction_keys,
+    });
+    Ok(id)
+}
+
+/// The bundle the transaction with key `transaction_key` is in, with the keys of all its
+/// transactions in order. `None` once the bundle is finished.
+pub fn bundle_of(transaction_key: &[u8; 32]) -> Option<([u8; 32], Vec<[u8; 32]>)> {
+    let bundles = BUNDLES.read().unwrap();
+    let id = bundles.of_transaction.get(transaction_key)?;
+    bundles.by_id.get(id).map(|status| (*id, status.transaction_keys.clone()))
+}
+
+/// Every transaction of bundle `id` executed.
+pub fn executed(id: &[u8; 32]) {
+    if let Some(status) = BUNDLES.write().unwrap().by_id.get_mut(id) {
+        status.state = BundleState::Executed;
+    }
+}
+
+/// The bundles of `transactions` are back in the pool, their commitment was rolled back.
+pub fn requeue(transactions: &[TrollupTransaction]) {
+    let mut bundles = BUNDLES.write().unwrap();
+    for transaction in transactions {
+        let Some(id) = bundles.of_transaction.get(&transaction.get_key()).copied() else {
+            continue;
+        };
+        if let Some(status) = bundles.by_id.get_mut(&id) {
+            status.state = BundleState::Pending;
+        }
+    }
+}
+
+/// None of bundle `id`'s transactions is kept.
+pub fn exclude(id: &[u8; 32], reason: String) {
+    finish(id, BundleState::Excluded, Some(reason), None);
+}
+
+/// Finishes the bundles whose transactions are in `block_number`, `transaction_keys` being the
+/// block's.
+pub fn finalize(transaction_keys: &[[u8; 32]], block_number: u64) {
+    let ids: Vec<[u8; 32]> = {
+        let bundles = BUNDLES.read().unwrap();
+        let mut ids: Vec<[u8; 32]> = transaction_keys.iter().filter_map(|key| bundles.of_transaction.get(key)).copied().collect();
+        ids.dedup();
+        ids
+    };
+    for id in ids {
+        finish(&id, BundleState::Finalized, None, Some(block_number));
+    }
+}
+
+fn finish(id: &[u8; 32], state: BundleState, reason: Option<String>, block_number: Option<u64>) {
+    let mut bundles = BUNDLES.write().unwrap();
+    let Some(status) = bundles.by_id.get_mut(id) else {
+        return;
+    };
+    if matches!(status.state, BundleState::Excluded | BundleState::Finalized) {
+        return;
+    }
+    status.state = state;
+    status.reason = reason;
+    status.block_number = block_number;
+    let transaction_keys = status.transaction_keys.clone();
+    for key in &transaction_keys {
+        bundles.of_transaction.remove(key);
+    }
+    bundles.finished.push_back(*id);
+    if bundles.finished.len() > MAX_FINISHED_BUNDLES {
+        if let Some(oldest) = bundles.finished.pop_front() {
+            bundles.by_id.remove(&oldest);
+        }
+    }
+}
+
+pub fn status(id: &[u8; 32]) -> Option<BundleStatus> {
+    BUNDLES.read().unwrap().by_id.get(id).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::pubkey::Pubkey;
+    use state::test_fixtures::{keypair, transfer_transaction};
+    use state::transaction::convert_to_trollup_transaction;
+
+    fn bundle(seed: &str) -> Vec<TrollupTransaction> {
+        (0..2)
+            .map(|lamports| transfer_transaction(&keypair(seed), &Pubkey::new_unique(), lamports + 1, Hash::default()))
+            .map(|transaction| convert_to_trollup_transaction(transaction).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_bundle_lifecycle() {
+        let transactions = bundle("bundle-lifecycle");
+        let id = register(&transactions).unwrap();
+        assert!(register(&transactions[1..]).is_err());
+        let (found, keys) = bundle_of(&transactions[1].get_key()).unwrap();
+        assert_eq!((found, keys.len()), (id, 2));
+        assert_eq!(status(&id).unwrap().state, BundleState::Pending);
+
+        executed(&id);
+        assert_eq!(status(&id).unwrap().state, BundleState::Executed);
+        requeue(&transactions);
+        assert_eq!(status(&id).unwrap().state, BundleState::Pending);
+        executed(&id);
+        finalize(&keys, 7);
+        let finalized = status(&id).unwrap();
+        assert_eq!((finalized.state, finalized.block_number), (BundleState::Finalized, Some(7)));
+        assert!(bundle_of(&transactions[0].get_key()).is_none());
+        // Finished bundles stay finished
+        exclude(&id, "too late".to_string());
+        assert_eq!(status(&id).unwrap().state, BundleState::Finalized);
+    }
+
+    #[test]
+    fn test_excluded_bundles_release_their_transactions() {
+        let transactions = bundle("bundle-excluded");
+        let id = register(&transactions).unwrap();
+        exclude(&id, "Transaction failed".to_string());
+        let excluded = status(&id).unwrap();
+        assert_eq!((excluded.state, excluded.reason.as_deref()), (BundleState::Excluded, Some("Transaction failed")));
+        assert!(bundle_of(&transactions[0].get_key()).is_none());
+        assert!(register(&transactions).is_ok());
+    }
+}
@@ -0,0 +1,116 @@
+use lazy_static::lazy_static;
+use log::warn;
+use state::challenge::{Bond, BondState, Challenge, ChallengeOutcome, ChallengeStatus};
+use state::transaction::TrollupTransaction;
+use state::config::TrollupConfig;
+use state::state_record::StateRecord;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::{ManageState, StateManager};
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref CHALLENGES: StateManager<SledStateManagement<Challenge>> = StateManager::new(&CONFIG.challenge_state_manager_db_path);
+    static ref BONDS: StateManager<SledStateManagement<Bond>> = StateManager::new(&CONFIG.challenge_bonds_db_path);
+    /// Serializes read-modify-write updates, the API posts and claims while the committer resolves
+    static ref UPDATES: Mutex<()> = Mutex::new(());
+}
+
+/// Records a new challenge bonded by the transaction `bond_transaction_id`. Fails when the
+/// challenger already challenged the state root, or the transaction already bonds a challenge.
+pub fn post(challenge: &Challenge, bond_transaction_id: &[u8; 32]) -> Result<(), String> {
+    let _guard = UPDATES.lock().unwrap();
+    if CHALLENGES.get_state_record(&challenge.id).is_some() {
+        return Err("The state root was already challenged by this challenger".to_string());
+    }
+    if BONDS.get_state_record(bond_transaction_id).is_some() {
+        return Err("The bond transaction already bonds a challenge".to_string());
+    }
+    BONDS.set_state_record(&Bond::new(*bond_transaction_id, challenge));
+    BONDS.commit();
+    CHALLENGES.set_state_record(challenge);
+    CHALLENGES.commit();
+    Ok(())
+}
+
+/// Records how the engine executed `transaction` if it posts a bond, a no-op for any other
+/// transaction. A transaction executed again after a rollback overwrites the earlier result.
+pub fn bond_executed(transaction: &TrollupTransaction, error: Option<&str>) {
+    let Some(mut bond) = BONDS.get_state_record(&transaction.get_key()) else {
+        return;
+    };
+    bond.executed(transaction, error);
+    if bond.state == BondState::Failed {
+        warn!("Bond transaction {} of challenge {} failed", hex::encode(bond.transaction_id), hex::encode(bond.challenge_id));
+    }
+    BONDS.set_state_record(&bond);
+    BONDS.commit();
+}
+
+pub fn bond(transaction_id: &[u8; 32]) -> Option<Bond> {
+    BONDS.get_state_record(transaction_id)
+}
+
+pub fn get(id: &[u8; 32]) -> Option<Challenge> {
+    CHALLENGES.get_state_record(id)
+}
+
+pub fn all() -> Vec<Challenge> {
+    CHALLENGES.get_all_entries().into_iter().map(|(_, challenge)| challenge).collect()
+}
+
+/// Resolves the open challenges against `state_root`. Called by the committer whenever a pending
+/// commitment is finalized, refused or dropped; roots nobody challenged are a no-op.
+pub fn resolve(state_root: &[u8; 32], status: ChallengeStatus) {
+    let _guard = UPDATES.lock().unwrap();
+    let resolved: Vec<Challenge> = all()
+        .into_iter()
+        .filter(|challenge| challenge.state_root == *state_root && challenge.status == ChallengeStatus::Open)
+        .map(|mut challenge| {
+            challenge.status = status;
+            if status == ChallengeStatus::Upheld {
+                challenge.reward_lamports = CONFIG.challenge_reward_lamports;
+            }
+            challenge
+        })
+        .collect();
+    if resolved.is_empty() {
+        return;
+    }
+    CHALLENGES.set_state_records(&resolved);
+    CHALLENGES.commit();
+}
+
+/// Outcomes resolved since the last block, marked as recorded in `block_number`.
+pub fn take_unrecorded(block_number: u64) -> Vec<ChallengeOutcome> {
+    let _guard = UPDATES.lock().unwrap();
+    let mut recorded: Vec<Challenge> = all()
+        .into_iter()
+        .filter(|challenge| challenge.status != ChallengeStatus::Open && challenge.resolved_in_block.is_none())
+        .collect();
+    recorded.sort_by_key(|challenge| (challenge.posted_at, challenge.id));
+    for challenge in recorded.iter_mut() {
+        challenge.resolved_in_block = Some(block_number);
+    }
+    CHALLENGES.set_state_records(&recorded);
+    CHALLENGES.commit();
+    recorded.iter().map(Challenge::outcome).collect()
+}
+
+/// Marks a claimable challenge as paid by the transaction with `payout_signature`. The check and
+/// the update happen under one lock, so a challenge is paid out once.
+pub fn claim(id: &[u8; 32], payout_signature: impl FnOnce(&Challenge) -> Result<String, String>) -> Result<Challenge, String> {
+    let _guard = UPDATES.lock().unwrap();
+    let mut challenge = CHALLENGES.get_state_record(id).ok_or_else(|| "Unknown challenge".to_string())?;
+    if !challenge.is_claimable() {
+        return Err(match (challenge.status, &challenge.payout_signature) {
+            (_, Some(signature)) => format!("The challenge was already paid out by {}", signature),
+            (ChallengeStatus::Open, _) => "The challenge is still open".to_string(),
+            _ => "The bond was slashed, nothing to claim".to_string(),
+        });
+    }
+    challenge.payout_signature = Some(payout_signature(&challenge)?);
+    CHALLENGES.set_state_record(&challenge);
+    CHALLENGES.commit();
+    Ok(challenge)
+}
@@ -0,0 +1,132 @@
+use crate::{fee_payer, vault_reconciliation};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::Message;
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use state::account_state::AccountState;
+use state::block::Block;
+use state::checkpoint::{self, Checkpoint};
+use state::config::TrollupConfig;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::StateManager;
+use std::str::FromStr;
+
+/// SPL Memo program, checkpoints are bridged as memos since nothing on L1 consumes them yet
+const MEMO_PROGRAM_ID: Pubkey = pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref CHECKPOINTS: StateManager<SledStateManagement<Checkpoint>> = StateManager::new(&CONFIG.checkpoint_db_path);
+}
+
+/// Epoch `block_number` completes, `None` when checkpoints are disabled or the block is inside
+/// an epoch.
+pub fn completed_epoch(block_number: u64) -> Option<u64> {
+    let interval = CONFIG.checkpoint_interval_blocks;
+    (interval > 0 && block_number > 0 && block_number % interval == 0).then(|| block_number / interval)
+}
+
+/// Builds, signs and stores the checkpoint of the epoch `last_block` completes. `block` looks up
+/// finalized blocks by number and `accounts` is every rollup account after `last_block`.
+pub fn produce(last_block: &Block, block: impl Fn(u64) -> Option<Block>, accounts: &[AccountState]) -> Option<Checkpoint> {
+    let epoch = completed_epoch(last_block.block_number)?;
+    if let Err(error) = CONFIG.merkle_parameters().check_leaves("account set", accounts.len() as u64) {
+        error!("Skipping the checkpoint of epoch {}: {}", epoch, error);
+        return None;
+    }
+    let first_block = last_block.block_number - CONFIG.checkpoint_interval_blocks + 1;
+    let previous = epoch.checked_sub(1).and_then(get);
+
+    let (mut epoch_transactions, mut epoch_fees) = (0u64, 0u64);
+    for block_number in first_block..=last_block.block_number {
+        let Some(block) = block(block_number) else {
+            warn!("Block {} is missing, skipping the checkpoint of epoch {}", block_number, epoch);
+            return None;
+        };
+        epoch_transactions += block.transactions.len() as u64;
+        epoch_fees += block.fees;
+    }
+
+    let mut checkpoint = Checkpoint {
+        epoch,
+        first_block,
+        last_block: last_block.block_number,
+        last_block_hash: last_block.hash(),
+        account_set_root: checkpoint::account_set_root(accounts),
+        account_count: accounts.len() as u64,
+        epoch_transactions,
+        epoch_fees,
+        cumulative_transactions: previous.as_ref().map(|previous| previous.cumulative_transactions).unwrap_or_default() + epoch_transactions,
+        cumulative_fees: previous.as_ref().map(|previous| previous.cumulative_fees).unwrap_or_default() + epoch_fees,
+        previous_checkpoint: previous.as_ref().map(Checkpoint::digest).unwrap_or_default(),
+        sequencer: Pubkey::default(),
+        signature: Vec::new(),
+    };
+    match Keypair::from_bytes(&CONFIG.trollup_api_keypair) {
+        Ok(sequencer) => checkpoint.sign(&sequencer),
+        Err(_) => warn!("No sequencer keypair, the checkpoint of epoch {} is unsigned", epoch),
+    }
+    CHECKPOINTS.set_state_record(&checkpoint);
+    CHECKPOINTS.commit();
+    info!("Checkpoint of epoch {} at block {}, account set root {:?}", epoch, checkpoint.last_block, checkpoint.account_set_root);
+    Some(checkpoint)
+}
+
+pub fn get(epoch: u64) -> Option<Checkpoint> {
+    CHECKPOINTS.get_state_record(&Checkpoint::get_id(epoch))
+}
+
+pub fn latest() -> Option<Checkpoint> {
+    CHECKPOINTS
+        .get_all_entries()
+        .into_iter()
+        .map(|(_, checkpoint)| checkpoint)
+        .max_by_key(|checkpoint| checkpoint.epoch)
+}
+
+/// Posts the checkpoint's epoch and digest to L1 as a memo paid by the commitment fee payer, when
+/// `CHECKPOINT_BRIDGE_TO_L1` is set. Returns the signature of the memo transaction.
+pub async fn bridge(checkpoint: &Checkpoint) -> anyhow::Result<Option<String>> {
+    if !CONFIG.checkpoint_bridge_to_l1 {
+        return Ok(None);
+    }
+    let fee_payer = CONFIG.commitment_fee_payer().map_err(anyhow::Error::msg)?;
+    let memo = format!("trollup-checkpoint:{}:{}", checkpoint.epoch, hex::encode(checkpoint.digest()));
+    let instruction = Instruction::new_with_bytes(MEMO_PROGRAM_ID, memo.as_bytes(), vec![]);
+    let rpc_client = RpcClient::new_with_commitment(CONFIG.rpc_url_current_env().to_string(), CommitmentConfig::confirmed());
+    let blockhash = rpc_client.get_latest_blockhash().await?;
+    let message = Message::new_with_blockhash(&[instruction], Some(&fee_payer.pubkey()), &blockhash);
+    fee_payer::ensure_can_pay(&rpc_client, &message, 0, CONFIG.fee_payer_low_balance_lamports).await?;
+    let transaction = Transaction::new(&[&fee_payer], message, blockhash);
+    let signature = rpc_client.send_and_confirm_transaction(&transaction).await?;
+    Ok(Some(signature.to_string()))
+}
+
+/// Posts the checkpoint's account set root to the vault when `VAULT_PROGRAM_ID` is set, as the
+/// root emergency withdrawals are proven against. Signed by the sequencer, the key the vault was
+/// initialized with, and paid by the commitment fee payer. Returns the transaction's signature.
+pub async fn post_to_vault(checkpoint: &Checkpoint) -> anyhow::Result<Option<String>> {
+    let Ok(vault_program_id) = Pubkey::from_str(&CONFIG.vault_program_id) else {
+        return Ok(None);
+    };
+    let sequencer = Keypair::from_bytes(&CONFIG.trollup_api_keypair).map_err(|error| anyhow::anyhow!("API keypair: {}", error))?;
+    let fee_payer = CONFIG.commitment_fee_payer().map_err(anyhow::Error::msg)?;
+    let instruction = vault_reconciliation::post_account_set_root(&vault_program_id, &sequencer.pubkey(), checkpoint.epoch, checkpoint.account_set_root);
+    let rpc_client = RpcClient::new_with_commitment(CONFIG.rpc_url_current_env().to_string(), CommitmentConfig::confirmed());
+    let blockhash = rpc_client.get_latest_blockhash().await?;
+    let message = Message::new_with_blockhash(&[instruction], Some(&fee_payer.pubkey()), &blockhash);
+    fee_payer::ensure_can_pay(&rpc_client, &message, 0, CONFIG.fee_payer_low_balance_lamports).await?;
+    let transaction = if fee_payer.pubkey() == sequencer.pubkey() {
+        Transaction::new(&[&fee_payer], message, blockhash)
+    } else {
+        Transaction::new(&[&fee_payer, &sequencer], message, blockhash)
+    };
+    let signature = rpc_client.send_and_confirm_transaction(&transaction).await?;
+    Ok(Some(signature.to_string()))
+}
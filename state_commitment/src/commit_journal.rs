@@ -0,0 +1,278 @@
+//! Write-ahead journal of finalized blocks. A block's accounts, transactions, the block itself and
+//! its message bus outbox entries live in separate databases that are flushed one after the
+//! other, so a crash between two flushes leaves accounts without the block that wrote them, or a
+//! block whose messages are never published. The committer journals the whole
+//! block before writing any store and clears the entry once every store is flushed; on startup
+//! `recover` applies whatever the journal still holds.
+
+use crate::merkle_index;
+use crate::message_bus;
+use crate::transaction_index;
+use borsh::{BorshDeserialize, BorshSerialize};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use state::account_state::AccountState;
+use state::block::Block;
+use state::config::TrollupConfig;
+use state::outbox::OutboxEntry;
+use state::state_record::StateRecord;
+use state::transaction::TrollupTransaction;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::{ManageState, StateManager};
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref JOURNAL: CommitJournal = CommitJournal::new(&CONFIG.commit_journal_db_path);
+}
+
+/// Everything a finalized block writes, journaled before the first store is touched.
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+pub struct PendingCommit {
+    pub block: Block,
+    pub accounts: Vec<AccountState>,
+    pub transactions: Vec<TrollupTransaction>,
+    /// Message bus entries of the block, empty when the message bus is disabled
+    pub outbox: Vec<OutboxEntry>,
+}
+
+/// `PendingCommit` before it carried the outbox entries.
+#[derive(BorshDeserialize)]
+struct PendingCommitV0 {
+    block: Block,
+    accounts: Vec<AccountState>,
+    transactions: Vec<TrollupTransaction>,
+}
+
+impl StateRecord for PendingCommit {
+    /// 1 added `outbox`
+    const ENCODING_VERSION: u32 = 1;
+
+    fn get_key(&self) -> [u8; 32] {
+        self.block.get_key()
+    }
+
+    fn upgrade(version: u32, bytes: &[u8]) -> Option<Self> {
+        match version {
+            0 => {
+                let pending = borsh::from_slice::<PendingCommitV0>(bytes).ok()?;
+                Some(PendingCommit {
+                    block: pending.block,
+                    accounts: pending.accounts,
+                    transactions: pending.transactions,
+                    outbox: vec![],
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The outbox a journaled commit writes its message bus entries to.
+pub type Outbox = StateManager<SledStateManagement<OutboxEntry>>;
+
+pub struct CommitJournal {
+    entries: StateManager<SledStateManagement<PendingCommit>>,
+}
+
+impl CommitJournal {
+    pub fn new(path: &str) -> Self {
+        CommitJournal { entries: StateManager::new(path) }
+    }
+
+    /// Records the intent to commit `pending`, durable before this returns.
+    pub fn prepare(&self, pending: &PendingCommit) {
+        self.entries.set_state_record(pending);
+        self.entries.commit();
+    }
+
+    /// Clears the entry of `block` once every store holds it.
+    pub fn complete(&self, block: &Block) {
+        self.entries.delete_state_record(&block.get_key());
+        self.entries.commit();
+    }
+
+    /// Entries left by commits that didn't complete, oldest block first.
+    pub fn pending(&self) -> Vec<PendingCommit> {
+        let mut pending: Vec<PendingCommit> = self.entries.iter_entries().map(|(_, entry)| entry).collect();
+        pending.sort_by_key(|entry| entry.block.block_number);
+        pending
+    }
+}
+
+/// The journal at `COMMIT_JOURNAL_DB_PATH`.
+pub fn journal() -> &'static CommitJournal {
+    &JOURNAL
+}
+
+/// Writes `pending` to every store: accounts and transactions first, then the block, then the
+/// indexes derived from it and the outbox entries. Each write is idempotent, so a commit can be
+/// applied again after a crash at any point.
+pub fn apply<A, B, T>(pending: &PendingCommit, accounts: &StateManager<A>, blocks: &StateManager<B>, transactions: &StateManager<T>, outbox: Option<&Outbox>)
+where
+    A: ManageState<Record = AccountState>,
+    B: ManageState<Record = Block>,
+    T: ManageState<Record = TrollupTransaction>,
+{
+    accounts.set_state_records(&pending.accounts);
+    transactions.set_state_records(&pending.transactions);
+    accounts.commit();
+    transactions.commit();
+    blocks.set_latest_block_id(&pending.block.get_key());
+    blocks.set_state_record(&pending.block);
+    blocks.commit();
+    record_derived(pending, outbox);
+}
+
+/// The indexes and outbox entries derived from a written block.
+fn record_derived(pending: &PendingCommit, outbox: Option<&Outbox>) {
+    transaction_index::record_block(&pending.block);
+    transaction_index::commit();
+    merkle_index::record_block(&pending.block);
+    merkle_index::commit();
+    if let Some(outbox) = outbox {
+        message_bus::write_outbox(outbox, &pending.outbox);
+    }
+}
+
+/// Repairs the commits `journal` still holds, returning the numbers of the blocks it repaired.
+/// A journaled block is only ever one the L1 already confirmed, so torn commits are rolled
+/// forward. Entries whose block was written only lost their index or outbox writes or the
+/// clearing of the entry, their accounts may since have been overwritten and are left alone.
+/// Their outbox entries are written again, which may publish them twice, delivery is
+/// at-least-once.
+pub fn recover<A, B, T>(journal: &CommitJournal, accounts: &StateManager<A>, blocks: &StateManager<B>, transactions: &StateManager<T>, outbox: Option<&Outbox>) -> Vec<u64>
+where
+    A: ManageState<Record = AccountState>,
+    B: ManageState<Record = Block>,
+    T: ManageState<Record = TrollupTransaction>,
+{
+    let mut repaired = Vec::new();
+    for pending in journal.pending() {
+        if blocks.get_state_record(&pending.block.get_key()).is_some() {
+            record_derived(&pending, outbox);
+        } else {
+            warn!("Block {} was finalized but not fully committed, applying it again", pending.block.block_number);
+            apply(&pending, accounts, blocks, transactions, outbox);
+            repaired.push(pending.block.block_number);
+        }
+        journal.complete(&pending.block);
+    }
+    if !repaired.is_empty() {
+        info!("Repaired {} torn block commits", repaired.len());
+    }
+    repaired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use state::test_fixtures::{funded_accounts, keypairs, transfer_transaction};
+    use state::transaction::convert_to_trollup_transaction;
+
+    /// Far above the blocks the committer tests finalize, the indexes are process wide
+    const BLOCK_NUMBER: u64 = 1_000_000;
+
+    fn pending_commit(block_number: u64) -> PendingCommit {
+        let keypairs = keypairs("commit-journal", 2);
+        let accounts = funded_accounts(&keypairs);
+        let transfer = transfer_transaction(&keypairs[0], &accounts[1].address, 10, Hash::default());
+        let transaction = convert_to_trollup_transaction(transfer).unwrap();
+        let block = Block::new(
+            block_number,
+            Block::get_id(block_number - 1),
+            Box::new([1u8; 32]),
+            Box::new([2u8; 32]),
+            vec![],
+            vec![],
+            [0u8; 32],
+            vec![transaction.get_key()],
+            accounts.iter().map(|account| account.address.to_bytes()).collect(),
+        );
+        let outbox = (0..2)
+            .map(|index| OutboxEntry { sequence: (block_number << 32) | index, topic: "trollup.blocks".to_string(), key: vec![], payload: vec![index as u8] })
+            .collect();
+        PendingCommit { block, accounts, transactions: vec![transaction], outbox }
+    }
+
+    fn outbox_sequences(outbox: &Outbox) -> Vec<u64> {
+        outbox.iter_entries().map(|(_, entry)| entry.sequence).collect()
+    }
+
+    #[test]
+    fn test_recover_rolls_a_torn_commit_forward() {
+        let journal = CommitJournal::new("");
+        let accounts = StateManager::<SledStateManagement<AccountState>>::new("");
+        let blocks = StateManager::<SledStateManagement<Block>>::new("");
+        let transactions = StateManager::<SledStateManagement<TrollupTransaction>>::new("");
+        let outbox = Outbox::new("");
+        let pending = pending_commit(BLOCK_NUMBER);
+
+        // Crash after the accounts were flushed, before the block was written
+        journal.prepare(&pending);
+        accounts.set_state_records(&pending.accounts);
+        accounts.commit();
+        assert!(blocks.get_latest_block_id().is_none());
+
+        assert_eq!(recover(&journal, &accounts, &blocks, &transactions, Some(&outbox)), vec![BLOCK_NUMBER]);
+        assert_eq!(blocks.get_latest_block_id(), Some(pending.block.get_key()));
+        assert_eq!(blocks.get_state_record(&pending.block.get_key()).unwrap().hash(), pending.block.hash());
+        assert!(transactions.get_state_record(&pending.transactions[0].get_key()).is_some());
+        assert_eq!(transaction_index::locate(&pending.transactions[0].get_key()).unwrap().block_number, BLOCK_NUMBER);
+        assert_eq!(outbox_sequences(&outbox), pending.outbox.iter().map(|entry| entry.sequence).collect::<Vec<_>>());
+        assert!(journal.pending().is_empty());
+    }
+
+    #[test]
+    fn test_recover_leaves_written_blocks_alone() {
+        let journal = CommitJournal::new("");
+        let accounts = StateManager::<SledStateManagement<AccountState>>::new("");
+        let blocks = StateManager::<SledStateManagement<Block>>::new("");
+        let transactions = StateManager::<SledStateManagement<TrollupTransaction>>::new("");
+        let outbox = Outbox::new("");
+        let pending = pending_commit(BLOCK_NUMBER + 1);
+
+        // Crash after the block was written, before the entry was cleared
+        journal.prepare(&pending);
+        apply(&pending, &accounts, &blocks, &transactions, Some(&outbox));
+        let spent = AccountState { lamports: 1, ..pending.accounts[0].clone() };
+        accounts.set_state_record(&spent);
+
+        assert!(recover(&journal, &accounts, &blocks, &transactions, Some(&outbox)).is_empty());
+        assert_eq!(accounts.get_state_record(&spent.address.to_bytes()).unwrap().lamports, 1);
+        assert!(journal.pending().is_empty());
+        // Nothing left to repair on the next start
+        assert!(recover(&journal, &accounts, &blocks, &transactions, Some(&outbox)).is_empty());
+    }
+
+    #[test]
+    fn test_recover_writes_the_outbox_of_a_written_block() {
+        let journal = CommitJournal::new("");
+        let accounts = StateManager::<SledStateManagement<AccountState>>::new("");
+        let blocks = StateManager::<SledStateManagement<Block>>::new("");
+        let transactions = StateManager::<SledStateManagement<TrollupTransaction>>::new("");
+        let outbox = Outbox::new("");
+        let pending = pending_commit(BLOCK_NUMBER + 2);
+
+        // Crash after the block was written, before its messages reached the outbox
+        journal.prepare(&pending);
+        apply(&pending, &accounts, &blocks, &transactions, None);
+        assert!(outbox_sequences(&outbox).is_empty());
+
+        assert!(recover(&journal, &accounts, &blocks, &transactions, Some(&outbox)).is_empty());
+        assert_eq!(outbox_sequences(&outbox), pending.outbox.iter().map(|entry| entry.sequence).collect::<Vec<_>>());
+        assert!(journal.pending().is_empty());
+    }
+
+    #[test]
+    fn test_entries_journaled_without_an_outbox_are_upgraded() {
+        let pending = pending_commit(BLOCK_NUMBER + 3);
+        let bytes = borsh::to_vec(&(&pending.block, &pending.accounts, &pending.transactions)).unwrap();
+
+        let upgraded = PendingCommit::upgrade(0, &bytes).unwrap();
+
+        assert_eq!(upgraded.block.hash(), pending.block.hash());
+        assert_eq!(upgraded.transactions.len(), 1);
+        assert!(upgraded.outbox.is_empty());
+    }
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static LAST_BALANCE: RwLock<Option<FeePayerBalance>> = RwLock::new(None);
+/// Fee plus rent of the last L1 transaction the fee payer was asked to pay for.
+static LAST_REQUIRED: AtomicU64 = AtomicU64::new(0);
+
+/// Balance of the keypair paying for L1 transactions, as last read from L1.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FeePayerBalance {
+    pub fee_payer: String,
+    pub lamports: u64,
+    /// Fee plus rent of the last L1 transaction, the balance is refused below it
+    pub required_lamports: u64,
+    pub low_balance_threshold: u64,
+    pub checked_at: u64,
+}
+
+impl FeePayerBalance {
+    pub fn is_low(&self) -> bool {
+        self.lamports < self.low_balance_threshold
+    }
+
+    /// Whether L1 transactions like the last one are refused until the fee payer is topped up.
+    pub fn is_refusing(&self) -> bool {
+        self.lamports < self.required_lamports
+    }
+}
+
+impl fmt::Display for FeePayerBalance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "fee payer {} has {} lamports (low balance threshold {}, last L1 transaction needed {})",
+            self.fee_payer, self.lamports, self.low_balance_threshold, self.required_lamports,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct InsufficientBalance(pub FeePayerBalance);
+
+impl fmt::Display for InsufficientBalance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Refusing the L1 transaction: {}", self.0)
+    }
+}
+
+impl std::error::Error for InsufficientBalance {}
+
+/// Reads the fee payer's balance from L1 and records it for `last_balance`.
+pub async fn check_balance(rpc_client: &RpcClient, fee_payer: &Pubkey, low_balance_threshold: u64) -> anyhow::Result<FeePayerBalance> {
+    let lamports = rpc_client.get_balance(fee_payer).await?;
+    let balance = FeePayerBalance {
+        fee_payer: fee_payer.to_string(),
+        lamports,
+        required_lamports: LAST_REQUIRED.load(Ordering::SeqCst),
+        low_balance_threshold,
+        checked_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+    };
+    *LAST_BALANCE.write().unwrap() = Some(balance.clone());
+    Ok(balance)
+}
+
+/// Checks the fee payer of `message` can pay its fee plus `rent_lamports` for accounts it
+/// creates, failing with `InsufficientBalance` rather than sending a transaction that can't land.
+pub async fn ensure_can_pay(rpc_client: &RpcClient, message: &Message, rent_lamports: u64, low_balance_threshold: u64) -> anyhow::Result<FeePayerBalance> {
+    let fee_payer = message.account_keys.first().copied().unwrap_or_default();
+    let fee = rpc_client.get_fee_for_message(message).await?;
+    LAST_REQUIRED.store(fee.saturating_add(rent_lamports), Ordering::SeqCst);
+    let balance = check_balance(rpc_client, &fee_payer, low_balance_threshold).await?;
+    if balance.is_refusing() {
+        return Err(InsufficientBalance(balance).into());
+    }
+    Ok(balance)
+}
+
+/// The balance read by the last check, `None` before the first one.
+pub fn last_balance() -> Option<FeePayerBalance> {
+    LAST_BALANCE.read().unwrap().clone()
+}
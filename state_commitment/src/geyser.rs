@@ -0,0 +1,290 @@
+use base64::{engine::general_purpose, Engine as _};
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use solana_sdk::hash::Hash;
+use solana_sdk::signature::Signature;
+use state::account_state::AccountState;
+use state::block::Block;
+use state::config::TrollupConfig;
+use state::transaction::TrollupTransaction;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref SINK: Option<Box<dyn GeyserSink>> = sink_from_config(&CONFIG.geyser_output);
+}
+
+/// Account update, mirrors `ReplicaAccountInfoV3` from the Geyser plugin interface.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GeyserAccountInfo {
+    pub pubkey: String,
+    pub lamports: u64,
+    pub owner: String,
+    pub executable: bool,
+    pub rent_epoch: u64,
+    /// Base64 encoded account data
+    pub data: String,
+    pub write_version: u64,
+}
+
+/// Block metadata, mirrors `ReplicaBlockInfoV3`. Rollup block numbers are used as slots.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GeyserBlockInfo {
+    pub parent_slot: u64,
+    pub parent_blockhash: String,
+    pub slot: u64,
+    pub blockhash: String,
+    pub block_time: Option<i64>,
+    pub block_height: Option<u64>,
+    pub executed_transaction_count: u64,
+    pub entry_count: u64,
+}
+
+/// Transaction notification, mirrors `ReplicaTransactionInfoV2`. The transaction itself is the
+/// base64 encoded Borsh `TrollupTransaction`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GeyserTransactionInfo {
+    pub signature: String,
+    pub is_vote: bool,
+    pub index: usize,
+    pub transaction: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum GeyserSlotStatus {
+    Processed,
+    Rooted,
+    Confirmed,
+}
+
+/// One Geyser callback, serialized with the callback name as `type`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum GeyserMessage {
+    UpdateAccount { account: GeyserAccountInfo, slot: u64, is_startup: bool },
+    NotifyTransaction { transaction: GeyserTransactionInfo, slot: u64 },
+    NotifyBlockMetadata { block: GeyserBlockInfo },
+    UpdateSlotStatus { slot: u64, parent: Option<u64>, status: GeyserSlotStatus },
+}
+
+/// Destination for Geyser messages.
+pub trait GeyserSink: Send + Sync {
+    fn send(&self, message: &GeyserMessage) -> anyhow::Result<()>;
+}
+
+/// Writes one JSON message per line.
+pub struct JsonLinesSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonLinesSink {
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        JsonLinesSink { writer: Mutex::new(writer) }
+    }
+}
+
+impl GeyserSink for JsonLinesSink {
+    fn send(&self, message: &GeyserMessage) -> anyhow::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        serde_json::to_writer(&mut *writer, message)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// `GEYSER_OUTPUT` is empty (disabled), `stdout`, or a file the messages are appended to.
+fn sink_from_config(output: &str) -> Option<Box<dyn GeyserSink>> {
+    match output {
+        "" => None,
+        "stdout" => Some(Box::new(JsonLinesSink::new(Box::new(std::io::stdout())))),
+        path => match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => {
+                info!("Writing Geyser output to {}", path);
+                Some(Box::new(JsonLinesSink::new(Box::new(BufWriter::new(file)))))
+            }
+            Err(error) => {
+                error!("Geyser output disabled, failed to open {}: {}", path, error);
+                None
+            }
+        },
+    }
+}
+
+/// Builds the messages a Geyser plugin would receive for a finalized block, in the order the
+/// validator calls them: account updates, transactions, block metadata and the slot status.
+pub fn block_messages(block: &Block, parent: Option<&Block>, accounts: &[AccountState], transactions: &[TrollupTransaction]) -> Vec<GeyserMessage> {
+    let slot = block.block_number;
+    let mut messages = Vec::with_capacity(accounts.len() + transactions.len() + 2);
+
+    for (index, account) in accounts.iter().enumerate() {
+        messages.push(GeyserMessage::UpdateAccount {
+            account: GeyserAccountInfo {
+                pubkey: account.address.to_string(),
+                lamports: account.lamports,
+                owner: account.owner.to_string(),
+                executable: account.executable,
+                rent_epoch: account.rent_epoch,
+                data: general_purpose::STANDARD.encode(&account.data),
+                // Monotonic across blocks as long as a block updates fewer than 2^32 accounts
+                write_version: (slot << 32) | index as u64,
+            },
+            slot,
+            is_startup: false,
+        });
+    }
+
+    for (index, transaction) in transactions.iter().enumerate() {
+        messages.push(GeyserMessage::NotifyTransaction {
+            transaction: GeyserTransactionInfo {
+                signature: transaction.signatures.first().map(|signature| Signature::from(*signature).to_string()).unwrap_or_default(),
+                is_vote: false,
+                index,
+                transaction: general_purpose::STANDARD.encode(borsh::to_vec(transaction).unwrap_or_default()),
+            },
+            slot,
+        });
+    }
+
+    let parent_slot = slot.saturating_sub(1);
+    messages.push(GeyserMessage::NotifyBlockMetadata {
+        block: GeyserBlockInfo {
+            parent_slot,
+            parent_blockhash: parent.map(|parent| Hash::new_from_array(parent.hash()).to_string()).unwrap_or_default(),
+            slot,
+            blockhash: Hash::new_from_array(block.hash()).to_string(),
+            block_time: SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs() as i64),
+            block_height: Some(slot),
+            executed_transaction_count: transactions.len() as u64,
+            entry_count: 1,
+        },
+    });
+    messages.push(GeyserMessage::UpdateSlotStatus {
+        slot,
+        parent: parent.map(|_| parent_slot),
+        status: GeyserSlotStatus::Rooted,
+    });
+
+    messages
+}
+
+/// Sends a finalized block to the configured Geyser output, if any. Output errors are logged and
+/// never fail the block.
+pub fn notify_block(block: &Block, parent: Option<&Block>, accounts: &[AccountState], transactions: &[TrollupTransaction]) {
+    let Some(sink) = SINK.as_ref() else {
+        return;
+    };
+    for message in block_messages(block, parent, accounts, transactions) {
+        if let Err(error) = sink.send(&message) {
+            error!("Failed to write Geyser output for block {}: {}", block.block_number, error);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+    use state::test_fixtures::{keypair, transfer_transaction};
+    use state::transaction::convert_to_trollup_transaction;
+    use std::io::{BufRead, BufReader};
+    use trollup_zk::fixtures::accounts_fixture;
+
+    fn block(block_number: u64) -> Block {
+        let root = Box::new([block_number as u8; 32]);
+        Block::new(block_number, Block::get_id(block_number - 1), root.clone(), root, vec![], vec![], [0u8; 32], vec![], vec![])
+    }
+
+    fn transaction() -> TrollupTransaction {
+        convert_to_trollup_transaction(transfer_transaction(&keypair("geyser/payer"), &Pubkey::new_unique(), 1, Hash::default())).unwrap()
+    }
+
+    #[test]
+    fn test_block_messages_follow_the_callback_order() {
+        let parent = block(1);
+        let block = block(2);
+        let accounts = accounts_fixture(2);
+        let transaction = transaction();
+
+        let messages = block_messages(&block, Some(&parent), &accounts, std::slice::from_ref(&transaction));
+
+        assert_eq!(messages.len(), 5);
+        let mut write_versions = Vec::new();
+        for (message, expected) in messages[..2].iter().zip(&accounts) {
+            let GeyserMessage::UpdateAccount { account, slot, is_startup } = message else {
+                panic!("Expected an account update, got {:?}", message);
+            };
+            assert_eq!((*slot, *is_startup), (2, false));
+            assert_eq!((&account.pubkey, account.lamports), (&expected.address.to_string(), expected.lamports));
+            assert_eq!(general_purpose::STANDARD.decode(&account.data).unwrap(), expected.data);
+            write_versions.push(account.write_version);
+        }
+        // Above every write version of the parent block
+        assert_eq!(write_versions, [2 << 32, (2 << 32) | 1]);
+
+        let GeyserMessage::NotifyTransaction { transaction: notified, slot } = &messages[2] else {
+            panic!("Expected a transaction, got {:?}", messages[2]);
+        };
+        assert_eq!((*slot, notified.index, notified.is_vote), (2, 0, false));
+        assert_eq!(notified.signature, Signature::from(transaction.signatures[0]).to_string());
+        assert_eq!(general_purpose::STANDARD.decode(&notified.transaction).unwrap(), borsh::to_vec(&transaction).unwrap());
+
+        let GeyserMessage::NotifyBlockMetadata { block: metadata } = &messages[3] else {
+            panic!("Expected block metadata, got {:?}", messages[3]);
+        };
+        assert_eq!((metadata.slot, metadata.parent_slot, metadata.executed_transaction_count), (2, 1, 1));
+        assert_eq!(metadata.blockhash, Hash::new_from_array(block.hash()).to_string());
+        assert_eq!(metadata.parent_blockhash, Hash::new_from_array(parent.hash()).to_string());
+
+        let GeyserMessage::UpdateSlotStatus { slot, parent, status } = &messages[4] else {
+            panic!("Expected a slot status, got {:?}", messages[4]);
+        };
+        assert_eq!((*slot, *parent, *status), (2, Some(1), GeyserSlotStatus::Rooted));
+    }
+
+    #[test]
+    fn test_first_block_has_no_parent() {
+        let messages = block_messages(&block(1), None, &[], &[]);
+
+        assert_eq!(messages.len(), 2);
+        let GeyserMessage::NotifyBlockMetadata { block: metadata } = &messages[0] else {
+            panic!("Expected block metadata, got {:?}", messages[0]);
+        };
+        assert_eq!((metadata.parent_slot, metadata.parent_blockhash.as_str(), metadata.executed_transaction_count), (0, "", 0));
+        assert!(matches!(messages[1], GeyserMessage::UpdateSlotStatus { slot: 1, parent: None, .. }));
+    }
+
+    #[test]
+    fn test_file_output_has_a_message_per_line() {
+        let path = std::env::temp_dir().join(format!("trollup-geyser-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sink = sink_from_config(path.to_str().unwrap()).unwrap();
+
+        for message in block_messages(&block(1), None, &accounts_fixture(1), &[transaction()]) {
+            sink.send(&message).unwrap();
+        }
+
+        let lines: Vec<serde_json::Value> = BufReader::new(std::fs::File::open(&path).unwrap())
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect();
+        let types: Vec<&str> = lines.iter().map(|line| line["type"].as_str().unwrap()).collect();
+        assert_eq!(types, ["updateAccount", "notifyTransaction", "notifyBlockMetadata", "updateSlotStatus"]);
+        assert_eq!(lines[3]["status"], "rooted");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_output_is_disabled_by_default() {
+        assert!(sink_from_config("").is_none());
+    }
+}
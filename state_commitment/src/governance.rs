@@ -0,0 +1,144 @@
+//! The schedule of governed parameters, see `state::parameters`. Proposals are kept in
+//! `PARAMETERS_STATE_MANAGER_DB_PATH` together with the set the chain started with, so the set in
+//! force at any block can still be looked up after the configuration changed.
+//!
+//! The engine executes every batch under the set in force at the block after the latest
+//! finalized one, and the block records the hash of that set. A batch executed once the block
+//! before a proposal's effective block is finalized can't end up in an earlier block, so a change
+//! never applies before its effective block.
+
+use crate::rollup_status;
+use lazy_static::lazy_static;
+use log::info;
+use solana_sdk::fee::FeeStructure;
+use solana_sdk::pubkey::Pubkey;
+use state::config::TrollupConfig;
+use state::parameters::{self, GovernedParameters, ParameterProposal};
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::{ManageState, StateManager};
+use std::str::FromStr;
+use std::sync::{Mutex, RwLock};
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref PROPOSALS: StateManager<SledStateManagement<ParameterProposal>> = StateManager::new(&CONFIG.parameters_state_manager_db_path);
+    /// Scheduled proposals, read for every batch and block
+    static ref SCHEDULE: RwLock<Vec<ParameterProposal>> = RwLock::new(load_schedule());
+    /// Serializes read-modify-write updates of proposals
+    static ref UPDATES: Mutex<()> = Mutex::new(());
+}
+
+/// The set a chain starts with, from the configuration.
+pub fn genesis_parameters(config: &TrollupConfig) -> GovernedParameters {
+    GovernedParameters {
+        lamports_per_signature: FeeStructure::default().lamports_per_signature,
+        transaction_batch_amount: config.transaction_batch_amount,
+        max_bundle_transactions: config.max_bundle_transactions.min(config.transaction_batch_amount as usize) as u32,
+        challenge_window_secs: config.optimistic_timeout.as_secs().max(1),
+    }
+}
+
+/// The scheduled proposals, storing the genesis set on first use.
+fn load_schedule() -> Vec<ParameterProposal> {
+    let mut scheduled: Vec<ParameterProposal> = PROPOSALS
+        .get_all_entries()
+        .into_iter()
+        .map(|(_, proposal)| proposal)
+        .filter(ParameterProposal::is_scheduled)
+        .collect();
+    if !scheduled.iter().any(|proposal| proposal.effective_block == 0) {
+        let genesis = ParameterProposal::genesis(genesis_parameters(&CONFIG));
+        PROPOSALS.set_state_record(&genesis);
+        PROPOSALS.commit();
+        scheduled.push(genesis);
+    }
+    scheduled
+}
+
+/// The set in force at `block_number`.
+pub fn effective(block_number: u64) -> GovernedParameters {
+    let schedule = SCHEDULE.read().unwrap();
+    parameters::effective_at(&schedule, block_number)
+        .map(|proposal| proposal.parameters.clone())
+        .expect("The schedule holds the genesis set")
+}
+
+/// The set the next block is produced under.
+pub fn current() -> GovernedParameters {
+    effective(rollup_status::finalized_block_number() + 1)
+}
+
+/// A set that was or will be in force, by the hash blocks record.
+pub fn by_hash(hash: &[u8; 32]) -> Option<GovernedParameters> {
+    SCHEDULE.read().unwrap()
+        .iter()
+        .map(|proposal| &proposal.parameters)
+        .find(|parameters| parameters.hash() == *hash)
+        .cloned()
+}
+
+/// A proposal by its ID.
+pub fn get(id: &[u8; 32]) -> Option<ParameterProposal> {
+    PROPOSALS.get_state_record(id)
+}
+
+/// Every proposal, scheduled or still collecting approvals, by effective block.
+pub fn proposals() -> Vec<ParameterProposal> {
+    let mut proposals: Vec<ParameterProposal> = PROPOSALS.get_all_entries().into_iter().map(|(_, proposal)| proposal).collect();
+    proposals.sort_by_key(|proposal| (proposal.effective_block, proposal.proposed_at, proposal.id));
+    proposals
+}
+
+/// `GOVERNANCE_SIGNERS`, entries that aren't public keys are ignored.
+pub fn signers() -> Vec<Pubkey> {
+    CONFIG.governance_signers.iter().filter_map(|signer| Pubkey::from_str(signer).ok()).collect()
+}
+
+/// Records a proposal of `parameters` from `effective_block` on, approved by the proposing
+/// signer. The effective block has to be `GOVERNANCE_MIN_DELAY_BLOCKS` past the latest
+/// finalized block.
+pub fn propose(parameters: GovernedParameters, effective_block: u64, signer: &Pubkey, signature: &str, proposed_at: u64) -> Result<ParameterProposal, String> {
+    parameters.validate()?;
+    if !CONFIG.optimistic_commitment_max_age.is_zero() && parameters.challenge_window() >= CONFIG.optimistic_commitment_max_age {
+        return Err("challengeWindowSecs must be shorter than OPTIMISTIC_COMMITMENT_MAX_AGE".to_string());
+    }
+    let earliest = rollup_status::finalized_block_number() + CONFIG.governance_min_delay_blocks.max(1);
+    if effective_block < earliest {
+        return Err(format!("The effective block must be {} or later", earliest));
+    }
+    let _guard = UPDATES.lock().unwrap();
+    let mut proposal = ParameterProposal::new(parameters, effective_block, proposed_at);
+    if PROPOSALS.get_state_record(&proposal.id).is_some() {
+        return Err("The same parameters were already proposed for this block".to_string());
+    }
+    proposal.approve(signer, signature, &signers())?;
+    store(&mut proposal);
+    Ok(proposal)
+}
+
+/// Adds `signer`'s approval to the proposal, scheduling it once it has `GOVERNANCE_THRESHOLD`
+/// approvals. Fails once the proposal is scheduled or its effective block is finalized.
+pub fn approve(id: &[u8; 32], signer: &Pubkey, signature: &str) -> Result<ParameterProposal, String> {
+    let _guard = UPDATES.lock().unwrap();
+    let mut proposal = PROPOSALS.get_state_record(id).ok_or_else(|| "Unknown proposal".to_string())?;
+    if proposal.is_scheduled() {
+        return Err("The proposal is already scheduled".to_string());
+    }
+    if proposal.effective_block <= rollup_status::finalized_block_number() {
+        return Err(format!("Block {} is already finalized", proposal.effective_block));
+    }
+    proposal.approve(signer, signature, &signers())?;
+    store(&mut proposal);
+    Ok(proposal)
+}
+
+/// Stores the proposal, scheduled first when it has enough approvals.
+fn store(proposal: &mut ParameterProposal) {
+    if proposal.approvals.len() >= CONFIG.governance_threshold.max(1) {
+        proposal.scheduled_at = Some(rollup_status::finalized_block_number());
+        info!(target: "audit", "Scheduled parameters {} from block {} on", hex::encode(proposal.parameters.hash()), proposal.effective_block);
+        SCHEDULE.write().unwrap().push(proposal.clone());
+    }
+    PROPOSALS.set_state_record(proposal);
+    PROPOSALS.commit();
+}
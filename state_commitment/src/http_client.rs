@@ -0,0 +1,84 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use lazy_static::lazy_static;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use reqwest::{Body, Client, RequestBuilder};
+use serde::Serialize;
+use state::canonical_json;
+use state::config::TrollupConfig;
+use std::io::Write;
+
+/// Bodies smaller than this are sent as is, compressing them costs more than it saves.
+const COMPRESSION_THRESHOLD: usize = 16 * 1024;
+
+/// Bodies larger than this are streamed to the connection in chunks of this size instead of being
+/// handed to the connection as one buffer.
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref SHARED_CLIENT: Client = build_client(&CONFIG).expect("Error building HTTP client");
+}
+
+/// The process wide client. `Client` is a handle to a connection pool, sharing it means requests
+/// to the same host reuse warm connections instead of opening a new one per client struct.
+pub fn shared_client() -> Client {
+    SHARED_CLIENT.clone()
+}
+
+/// Builds a client with the pooling, keep-alive and HTTP/2 settings from `config`.
+///
+/// With `HTTP2_PRIOR_KNOWLEDGE` the client speaks HTTP/2 over plain TCP (h2c), which both Trollup
+/// servers accept. Leave it off when a proxy that only speaks HTTP/1.1 sits in between.
+pub fn build_client(config: &TrollupConfig) -> reqwest::Result<Client> {
+    let mut builder = Client::builder()
+        .pool_idle_timeout(config.http_pool_idle_timeout)
+        .pool_max_idle_per_host(config.http_pool_max_idle_per_host)
+        .tcp_keepalive(config.http_tcp_keepalive)
+        .tcp_nodelay(true)
+        .http2_adaptive_window(true);
+
+    if config.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+
+    builder.build()
+}
+
+/// Sets `value` as the JSON body of `request`.
+///
+/// Large bodies are gzip compressed when `HTTP_COMPRESS_REQUESTS` is enabled and streamed in
+/// chunks. The receiving route has to accept `Content-Encoding: gzip`, see `server::body::json`.
+pub fn json_body<T: Serialize>(request: RequestBuilder, value: &T) -> anyhow::Result<RequestBuilder> {
+    encoded_body(request, serde_json::to_vec(value)?)
+}
+
+/// Like `json_body`, with `value` in canonical JSON so receivers can hash or verify it as sent.
+pub fn canonical_json_body<T: Serialize>(request: RequestBuilder, value: &T) -> anyhow::Result<RequestBuilder> {
+    encoded_body(request, canonical_json::to_vec(value)?)
+}
+
+fn encoded_body(request: RequestBuilder, mut payload: Vec<u8>) -> anyhow::Result<RequestBuilder> {
+    let mut request = request.header(CONTENT_TYPE, "application/json");
+
+    if CONFIG.http_compress_requests && payload.len() >= COMPRESSION_THRESHOLD {
+        let mut encoder = GzEncoder::new(Vec::with_capacity(payload.len() / 2), Compression::fast());
+        encoder.write_all(&payload)?;
+        payload = encoder.finish()?;
+        request = request.header(CONTENT_ENCODING, "gzip");
+    }
+
+    Ok(request.body(streamed(payload)))
+}
+
+fn streamed(payload: Vec<u8>) -> Body {
+    if payload.len() <= STREAM_CHUNK_SIZE {
+        return Body::from(payload);
+    }
+
+    let chunks: Vec<std::io::Result<Vec<u8>>> = payload
+        .chunks(STREAM_CHUNK_SIZE)
+        .map(|chunk| Ok(chunk.to_vec()))
+        .collect();
+    Body::wrap_stream(futures_util::stream::iter(chunks))
+}
@@ -0,0 +1,110 @@
+use log::info;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use state::block::{Block, L1Anchor};
+use state_management::state_management::{ManageState, StateManager};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Number of most recent blocks checked for unresolved anchors per run.
+const ANCHOR_LOOKBACK_BLOCKS: u64 = 256;
+/// Pages of state PDA signatures searched for the transaction of a slot-only anchor.
+const MAX_SIGNATURE_PAGES: usize = 10;
+
+/// Completes the L1 anchors of recent blocks.
+///
+/// Blocks committed through the validator know their commitment signature, blocks finalized
+/// optimistically only know the slot at which the state PDA changed. The tracker looks up the
+/// missing half on the L1 RPC and writes it back to the block.
+pub struct L1AnchorTracker<B: ManageState<Record=Block>> {
+    block_state_management: Arc<StateManager<B>>,
+    rpc_client: RpcClient,
+    state_pda: Pubkey,
+}
+
+impl<B: ManageState<Record=Block>> L1AnchorTracker<B> {
+    pub fn new(block_state_management: Arc<StateManager<B>>, rpc_url: String, proof_verifier_program_id: &Pubkey) -> Self {
+        let (state_pda, _) = Pubkey::find_program_address(&[b"state"], proof_verifier_program_id);
+        L1AnchorTracker {
+            block_state_management,
+            rpc_client: RpcClient::new_with_commitment(rpc_url, CommitmentConfig::finalized()),
+            state_pda,
+        }
+    }
+
+    /// Resolves what it can and returns the number of blocks whose anchor was completed.
+    pub async fn resolve_pending(&self) -> anyhow::Result<usize> {
+        let Some(latest_block_number) = self.block_state_management
+            .get_latest_block_id()
+            .and_then(|id| self.block_state_management.get_state_record(&id))
+            .map(|block| block.block_number) else {
+            return Ok(0);
+        };
+
+        let mut resolved = 0;
+        let oldest = latest_block_number.saturating_sub(ANCHOR_LOOKBACK_BLOCKS - 1).max(1);
+        for block_number in (oldest..=latest_block_number).rev() {
+            let Some(mut block) = self.block_state_management.get_state_record(&Block::get_id(block_number)) else {
+                continue;
+            };
+            if block.l1_anchor.is_resolved() {
+                continue;
+            }
+            if let Some(l1_anchor) = self.resolve(&block.l1_anchor).await? {
+                block.l1_anchor = l1_anchor;
+                self.block_state_management.set_state_record(&block);
+                resolved += 1;
+            }
+        }
+        if resolved > 0 {
+            self.block_state_management.commit();
+            info!("Resolved L1 anchors of {} blocks", resolved);
+        }
+        Ok(resolved)
+    }
+
+    async fn resolve(&self, l1_anchor: &L1Anchor) -> anyhow::Result<Option<L1Anchor>> {
+        match (l1_anchor.slot, &l1_anchor.signature) {
+            (_, Some(signature)) => {
+                let statuses = self.rpc_client
+                    .get_signature_statuses_with_history(&[Signature::from_str(signature)?])
+                    .await?
+                    .value;
+                Ok(statuses.into_iter().next().flatten().map(|status| L1Anchor {
+                    slot: Some(status.slot),
+                    signature: Some(signature.clone()),
+                }))
+            }
+            (Some(slot), None) => Ok(self.find_state_update(slot).await?.map(|signature| L1Anchor {
+                slot: Some(slot),
+                signature: Some(signature),
+            })),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Finds the successful state PDA transaction landed in `slot`, paging back from the newest.
+    async fn find_state_update(&self, slot: u64) -> anyhow::Result<Option<String>> {
+        let mut before = None;
+        for _ in 0..MAX_SIGNATURE_PAGES {
+            let config = GetConfirmedSignaturesForAddress2Config {
+                before,
+                until: None,
+                limit: None,
+                commitment: Some(CommitmentConfig::finalized()),
+            };
+            let signatures = self.rpc_client.get_signatures_for_address_with_config(&self.state_pda, config).await?;
+            if let Some(found) = signatures.iter().find(|status| status.slot == slot && status.err.is_none()) {
+                return Ok(Some(found.signature.clone()));
+            }
+            match signatures.last() {
+                Some(oldest) if oldest.slot > slot => before = Some(Signature::from_str(&oldest.signature)?),
+                _ => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+use log::warn;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::UiTransactionEncoding;
+use state::block::L1Anchor;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Time between two status checks of a commitment transaction.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches commitment transactions on L1.
+#[async_trait]
+pub trait L1Confirmer: Send + Sync {
+    /// Waits until the transaction `signature` is confirmed and returns its anchor, `None` when
+    /// the transaction failed. Callers bound the wait, the confirmer doesn't give up by itself.
+    async fn confirm(&self, signature: &Signature) -> anyhow::Result<Option<L1Anchor>>;
+}
+
+/// Polls the L1 RPC for the transaction's status.
+pub struct RpcConfirmer {
+    rpc_url: String,
+}
+
+impl RpcConfirmer {
+    pub fn new(rpc_url: &str) -> Self {
+        RpcConfirmer { rpc_url: rpc_url.to_string() }
+    }
+}
+
+#[async_trait]
+impl L1Confirmer for RpcConfirmer {
+    async fn confirm(&self, signature: &Signature) -> anyhow::Result<Option<L1Anchor>> {
+        let client = RpcClient::new(self.rpc_url.clone());
+        while !client.confirm_transaction(signature).await? {
+            sleep(POLL_INTERVAL).await;
+        }
+        let transaction_status = client
+            .get_transaction(signature, UiTransactionEncoding::JsonParsed)
+            .await?;
+
+        match transaction_status.transaction.meta {
+            Some(meta) if meta.err.is_none() => Ok(Some(L1Anchor {
+                slot: Some(transaction_status.slot),
+                signature: Some(signature.to_string()),
+            })),
+            Some(meta) => {
+                warn!("Commitment transaction {} failed: {:?}", signature, meta.err);
+                Ok(None)
+            }
+            None => {
+                warn!("Status of commitment transaction {} is not available", signature);
+                Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use state::block::Block;
+use state_management::state_management::{ManageState, StateManager};
+use std::fmt;
+use std::sync::Arc;
+
+/// The signature verifier's state account holds the current root followed by its sequence.
+const STATE_ROOT_SIZE: usize = 32;
+const STATE_SIZE: usize = STATE_ROOT_SIZE + 8;
+
+/// Root committed on L1 by the signature verifier, `sequence` is its rollup block number.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct L1Root {
+    pub root: [u8; 32],
+    pub sequence: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RootComparison {
+    /// Nothing was committed on L1 yet
+    Uncommitted,
+    /// The L1 root is the account root of the local block with the same number
+    InSync(L1Root),
+    /// L1 committed a block this node doesn't have
+    Ahead(L1Root),
+    /// L1 committed a different root for a block this node has
+    Diverged { l1_root: L1Root, local_root: [u8; 32] },
+}
+
+impl fmt::Display for RootComparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RootComparison::Uncommitted => write!(f, "no root committed on L1"),
+            RootComparison::InSync(l1_root) => write!(f, "block {} matches L1", l1_root.sequence),
+            RootComparison::Ahead(l1_root) => write!(f, "L1 committed block {} which is not in the local chain", l1_root.sequence),
+            RootComparison::Diverged { l1_root, local_root } => write!(
+                f,
+                "L1 committed root {} for block {} but the local root is {}",
+                hex::encode(l1_root.root),
+                l1_root.sequence,
+                hex::encode(local_root),
+            ),
+        }
+    }
+}
+
+impl RootComparison {
+    pub fn is_divergent(&self) -> bool {
+        matches!(self, RootComparison::Ahead(_) | RootComparison::Diverged { .. })
+    }
+}
+
+/// Reads the root committed to the signature verifier's state PDA and compares it to the local
+/// block with the same number. L1 trailing the local chain is expected, commitments take a few
+/// slots to finalize, so the comparison is always against the block L1 committed.
+pub struct L1RootWatch<B: ManageState<Record=Block>> {
+    block_state_management: Arc<StateManager<B>>,
+    rpc_client: RpcClient,
+    signature_verifier_program_id: Pubkey,
+}
+
+impl<B: ManageState<Record=Block>> L1RootWatch<B> {
+    pub fn new(block_state_management: Arc<StateManager<B>>, rpc_url: String, signature_verifier_program_id: &Pubkey) -> Self {
+        L1RootWatch {
+            block_state_management,
+            rpc_client: RpcClient::new_with_commitment(rpc_url, CommitmentConfig::finalized()),
+            signature_verifier_program_id: *signature_verifier_program_id,
+        }
+    }
+
+    /// The finalized root on L1, `None` when the state PDA doesn't exist or holds no commitment.
+    pub async fn l1_root(&self) -> anyhow::Result<Option<L1Root>> {
+        fetch_l1_root(&self.rpc_client, &self.signature_verifier_program_id).await
+    }
+
+    pub async fn compare(&self) -> anyhow::Result<RootComparison> {
+        let Some(l1_root) = self.l1_root().await? else {
+            return Ok(RootComparison::Uncommitted);
+        };
+        Ok(match self.block_state_management.get_state_record(&Block::get_id(l1_root.sequence)) {
+            None => RootComparison::Ahead(l1_root),
+            Some(block) if *block.accounts_merkle_root == l1_root.root => RootComparison::InSync(l1_root),
+            Some(block) => RootComparison::Diverged { l1_root, local_root: *block.accounts_merkle_root },
+        })
+    }
+}
+
+/// The signature verifier's state account.
+pub fn state_pda(signature_verifier_program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"state"], signature_verifier_program_id).0
+}
+
+/// Reads the finalized root from the state account of the signature verifier
+/// `signature_verifier_program_id`, `None` when the account doesn't exist or holds no
+/// commitment. Clients use it to check the API against L1 without trusting the node.
+///
+/// Only the program writes an account it owns, and it only writes a root after verifying the
+/// validator's signature over it, so an account owned by anything else is an error.
+pub async fn fetch_l1_root(rpc_client: &RpcClient, signature_verifier_program_id: &Pubkey) -> anyhow::Result<Option<L1Root>> {
+    let state_pda = state_pda(signature_verifier_program_id);
+    let Some(account) = rpc_client
+        .get_account_with_commitment(&state_pda, CommitmentConfig::finalized())
+        .await?
+        .value else {
+        return Ok(None);
+    };
+    if account.owner != *signature_verifier_program_id {
+        anyhow::bail!("State account {} is owned by {}, not the signature verifier {}", state_pda, account.owner, signature_verifier_program_id);
+    }
+    if account.data.len() < STATE_SIZE {
+        return Ok(None);
+    }
+    let root: [u8; 32] = account.data[..STATE_ROOT_SIZE].try_into()?;
+    let sequence = u64::from_le_bytes(account.data[STATE_ROOT_SIZE..STATE_SIZE].try_into()?);
+    if sequence == 0 {
+        return Ok(None);
+    }
+    Ok(Some(L1Root { root, sequence }))
+}
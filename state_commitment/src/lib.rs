@@ -1,3 +1,25 @@
+pub mod batch_data;
+pub mod block_feed;
+pub mod bundles;
+pub mod challenges;
+pub mod checkpoints;
+pub mod commit_journal;
+pub mod fee_payer;
+pub mod geyser;
+pub mod governance;
+pub mod http_client;
+pub mod l1_anchor;
+pub mod l1_confirmation;
+pub mod l1_divergence;
+pub mod load_failures;
+pub mod merkle_index;
+pub mod message_bus;
+pub mod optimistic_commitments;
+pub mod pipeline;
+pub mod rollup_status;
 pub mod state_commitment_layer;
 pub mod state_commitment_pool;
-mod validator_client;
\ No newline at end of file
+pub mod transaction_index;
+pub mod validator_client;
+pub mod vault_reconciliation;
+pub mod withdrawal_aggregator;
\ No newline at end of file
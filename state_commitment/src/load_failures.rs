@@ -0,0 +1,135 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use solana_sdk::transaction::TransactionError;
+use state::transaction_status::LoadFailureCause;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// Failures kept for the windowed counts, older ones only count towards the totals.
+const RETENTION: Duration = Duration::from_secs(24 * 3600);
+const MAX_RECENT_FAILURES: usize = 100_000;
+/// Transactions whose cause is kept for their status, older ones are forgotten.
+const MAX_TRACKED_TRANSACTIONS: usize = 10_000;
+
+lazy_static! {
+    static ref LOAD_FAILURES: RwLock<LoadFailures> = RwLock::new(LoadFailures::default());
+}
+
+#[derive(Default)]
+struct LoadFailures {
+    totals: BTreeMap<LoadFailureCause, u64>,
+    recent: VecDeque<(Instant, LoadFailureCause)>,
+    by_transaction: HashMap<[u8; 32], LoadFailureCause>,
+    tracked: VecDeque<[u8; 32]>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadFailureCount {
+    pub cause: LoadFailureCause,
+    /// Failures within the window
+    pub count: u64,
+    /// Failures since the node started
+    pub total: u64,
+}
+
+/// Classifies the error the SVM returned for a transaction it couldn't load.
+pub fn classify(error: &TransactionError) -> LoadFailureCause {
+    match error {
+        TransactionError::AccountNotFound => LoadFailureCause::MissingAccount,
+        TransactionError::ProgramAccountNotFound => LoadFailureCause::MissingProgram,
+        TransactionError::InvalidProgramForExecution => LoadFailureCause::InvalidProgram,
+        TransactionError::InvalidAccountForFee
+        | TransactionError::InvalidWritableAccount
+        | TransactionError::InvalidAddressLookupTableOwner => LoadFailureCause::InvalidOwner,
+        TransactionError::InsufficientFundsForFee => LoadFailureCause::InsufficientFunds,
+        TransactionError::InsufficientFundsForRent { .. }
+        | TransactionError::InvalidRentPayingAccount => LoadFailureCause::RentViolation,
+        TransactionError::AccountInUse
+        | TransactionError::AccountLoadedTwice
+        | TransactionError::TooManyAccountLocks => LoadFailureCause::AccountLocks,
+        TransactionError::MaxLoadedAccountsDataSizeExceeded
+        | TransactionError::InvalidLoadedAccountsDataSizeLimit => LoadFailureCause::DataSizeLimit,
+        _ => LoadFailureCause::Other,
+    }
+}
+
+/// Counts the load failure of `transaction_id` and keeps its cause for its status.
+pub fn record(transaction_id: [u8; 32], cause: LoadFailureCause) {
+    let now = Instant::now();
+    let mut failures = LOAD_FAILURES.write().unwrap();
+    *failures.totals.entry(cause).or_default() += 1;
+    failures.recent.push_back((now, cause));
+    while failures.recent.len() > MAX_RECENT_FAILURES
+        || failures.recent.front().is_some_and(|(at, _)| now.duration_since(*at) > RETENTION)
+    {
+        failures.recent.pop_front();
+    }
+    if failures.by_transaction.insert(transaction_id, cause).is_none() {
+        failures.tracked.push_back(transaction_id);
+        if failures.tracked.len() > MAX_TRACKED_TRANSACTIONS {
+            if let Some(oldest) = failures.tracked.pop_front() {
+                failures.by_transaction.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Why `transaction_id` last failed to load, if it did recently.
+pub fn cause_of(transaction_id: &[u8; 32]) -> Option<LoadFailureCause> {
+    LOAD_FAILURES.read().unwrap().by_transaction.get(transaction_id).copied()
+}
+
+/// Failures per cause since the node started.
+pub fn totals() -> BTreeMap<LoadFailureCause, u64> {
+    LOAD_FAILURES.read().unwrap().totals.clone()
+}
+
+/// Causes of the failures within the last `window`, at most a day, most common first.
+pub fn most_common(window: Duration) -> Vec<LoadFailureCount> {
+    let failures = LOAD_FAILURES.read().unwrap();
+    let now = Instant::now();
+    let mut counts: BTreeMap<LoadFailureCause, u64> = BTreeMap::new();
+    for (_, cause) in failures.recent.iter().rev().take_while(|(at, _)| now.duration_since(*at) <= window) {
+        *counts.entry(*cause).or_default() += 1;
+    }
+    let mut most_common: Vec<LoadFailureCount> = counts
+        .into_iter()
+        .map(|(cause, count)| LoadFailureCount { cause, count, total: failures.totals[&cause] })
+        .collect();
+    most_common.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.cause.cmp(&b.cause)));
+    most_common
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classifies_load_errors() {
+        assert_eq!(classify(&TransactionError::AccountNotFound), LoadFailureCause::MissingAccount);
+        assert_eq!(classify(&TransactionError::InvalidAccountForFee), LoadFailureCause::InvalidOwner);
+        assert_eq!(classify(&TransactionError::InsufficientFundsForRent { account_index: 1 }), LoadFailureCause::RentViolation);
+        assert_eq!(classify(&TransactionError::AccountLoadedTwice), LoadFailureCause::AccountLocks);
+        assert_eq!(classify(&TransactionError::BlockhashNotFound), LoadFailureCause::Other);
+    }
+
+    #[test]
+    fn test_most_common_causes_come_first() {
+        let before = totals();
+        record([0xc1; 32], LoadFailureCause::DataSizeLimit);
+        record([0xc2; 32], LoadFailureCause::DataSizeLimit);
+        record([0xc3; 32], LoadFailureCause::InvalidProgram);
+
+        assert_eq!(cause_of(&[0xc2; 32]), Some(LoadFailureCause::DataSizeLimit));
+        assert_eq!(cause_of(&[0xc4; 32]), None);
+        let totals = totals();
+        assert_eq!(totals[&LoadFailureCause::DataSizeLimit], before.get(&LoadFailureCause::DataSizeLimit).copied().unwrap_or_default() + 2);
+
+        let most_common = most_common(Duration::from_secs(60));
+        let position = |cause| most_common.iter().position(|count| count.cause == cause).unwrap();
+        assert!(most_common[position(LoadFailureCause::DataSizeLimit)].count >= 2);
+        assert!(position(LoadFailureCause::DataSizeLimit) < position(LoadFailureCause::InvalidProgram));
+    }
+}
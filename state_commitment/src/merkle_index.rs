@@ -0,0 +1,73 @@
+use lazy_static::lazy_static;
+use state::block::Block;
+use state::config::TrollupConfig;
+use state::merkle::{AccountWriter, BlockMerkleIndex};
+use state::witness::AccountInclusion;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::StateManager;
+use std::collections::HashSet;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref INDEX: StateManager<SledStateManagement<BlockMerkleIndex>> = StateManager::new(&CONFIG.merkle_index_db_path);
+    static ref WRITERS: StateManager<SledStateManagement<AccountWriter>> = StateManager::new(&CONFIG.account_writer_index_db_path);
+}
+
+/// Stores the account tree layers and leaf ordering of a finalized block, and makes it the
+/// latest writer of its accounts. Blocks have to be recorded in order. The trees of blocks
+/// finalized before account leaves were stored are skipped, they can't be rebuilt.
+pub fn record_block(block: &Block) {
+    let mut seen = HashSet::new();
+    let writers: Vec<AccountWriter> = block.accounts
+        .iter()
+        .filter(|address| seen.insert(**address))
+        .map(|address| AccountWriter { address: *address, block_number: block.block_number })
+        .collect();
+    WRITERS.set_state_records(&writers);
+    if block.account_leaves.len() != block.accounts.len() {
+        return;
+    }
+    let index = BlockMerkleIndex::build(block.block_number, &block.accounts, block.account_leaves.clone());
+    INDEX.set_state_record(&index);
+}
+
+pub fn commit() {
+    INDEX.commit();
+    WRITERS.commit();
+}
+
+pub fn get(block_number: u64) -> Option<BlockMerkleIndex> {
+    INDEX.get_state_record(&Block::get_id(block_number))
+}
+
+/// Proves the account at `address` against the account tree of block `block_number`.
+pub fn account_inclusion(block_number: u64, address: &[u8; 32]) -> Option<AccountInclusion> {
+    get(block_number)?.inclusion(address)
+}
+
+/// Number of the latest recorded block that wrote the account at `address`.
+pub fn latest_block_writing(address: &[u8; 32]) -> Option<u64> {
+    WRITERS.get_state_record(address).map(|writer| writer.block_number)
+}
+
+/// Removes the tree of `block`, a block rolled back to L1, and forgets it as the writer of its
+/// accounts. Their earlier writer isn't tracked, a reindex finds it.
+pub fn remove_block(block: &Block) {
+    INDEX.delete_state_record(&Block::get_id(block.block_number));
+    for address in &block.accounts {
+        if latest_block_writing(address) == Some(block.block_number) {
+            WRITERS.delete_state_record(address);
+        }
+    }
+}
+
+/// Removes every entry, before the index is rebuilt from scratch.
+pub fn clear() {
+    for (key, _) in INDEX.get_all_entries() {
+        INDEX.delete_state_record(&key);
+    }
+    for (key, _) in WRITERS.get_all_entries() {
+        WRITERS.delete_state_record(&key);
+    }
+    commit();
+}
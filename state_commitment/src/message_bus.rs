@@ -0,0 +1,375 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use borsh::{BorshDeserialize, BorshSerialize};
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use state::account_state::AccountState;
+use state::block::Block;
+use state::canonical_json;
+use state::config::TrollupConfig;
+use state::outbox::OutboxEntry;
+use state::receipt::{InstructionWriteSet, TransactionReceipt};
+use state::state_record::StateRecord;
+use state::transaction::TrollupTransaction;
+use state::views::InstructionView;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::StateManager;
+use std::sync::Arc;
+
+/// Maximum number of outbox entries published per relay run.
+const RELAY_BATCH_SIZE: usize = 500;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref OUTBOX: Option<StateManager<SledStateManagement<OutboxEntry>>> = if CONFIG.message_bus_kind.is_empty() {
+        None
+    } else {
+        Some(StateManager::new(&CONFIG.outbox_state_manager_db_path))
+    };
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockHeaderMessage {
+    pub block_number: u64,
+    pub block_hash: String,
+    pub previous_block_hash: String,
+    pub transactions_merkle_root: String,
+    pub accounts_merkle_root: String,
+    pub transaction_count: u64,
+    pub account_count: u64,
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptMessage {
+    pub signature: String,
+    pub block_number: u64,
+    pub index: u64,
+    pub optimistic: bool,
+    /// Operator maintenance transaction from the system lane
+    pub system: bool,
+    pub compute_units: u64,
+    pub instruction_write_sets: Vec<InstructionWriteSetMessage>,
+}
+
+/// Base58 form of an `InstructionWriteSet`, with the instruction's data and its decoding.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InstructionWriteSetMessage {
+    pub instruction_index: u8,
+    pub program_id: String,
+    pub accounts: Vec<String>,
+    /// Base64 instruction data
+    pub data: String,
+    /// Canonical JSON of the decoded instruction, embedded as JSON in the JSON format. `None` for
+    /// programs without a decoder.
+    #[serde(with = "embedded_json")]
+    pub parsed: Option<String>,
+}
+
+impl InstructionWriteSetMessage {
+    fn new(write_set: &InstructionWriteSet, transaction: &TrollupTransaction) -> Self {
+        let account_keys: Vec<Pubkey> = transaction.message.account_keys.iter().map(|key| Pubkey::from(*key)).collect();
+        let instruction = transaction.message.instructions
+            .get(write_set.instruction_index as usize)
+            .map(|instruction| InstructionView::new(instruction, &account_keys));
+        InstructionWriteSetMessage {
+            instruction_index: write_set.instruction_index,
+            program_id: write_set.program_id.to_string(),
+            accounts: write_set.accounts.iter().map(ToString::to_string).collect(),
+            data: instruction.as_ref().map(|instruction| instruction.data.clone()).unwrap_or_default(),
+            parsed: instruction
+                .and_then(|instruction| instruction.parsed)
+                .map(|parsed| canonical_json::to_string(&parsed).expect("JSON value serializes")),
+        }
+    }
+}
+
+/// Borsh has no JSON value type, so decoded instructions are carried as text and written as a
+/// nested value by serde.
+mod embedded_json {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value;
+    use state::canonical_json;
+
+    pub fn serialize<S: Serializer>(text: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+        let value: Option<Value> = text.as_deref().map(serde_json::from_str).transpose().map_err(serde::ser::Error::custom)?;
+        value.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+        Option::<Value>::deserialize(deserializer)?
+            .map(|value| canonical_json::to_string(&value))
+            .transpose()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountSnapshot {
+    pub lamports: u64,
+    pub owner: String,
+    pub executable: bool,
+    pub rent_epoch: u64,
+    /// Base64 encoded account data
+    pub data: String,
+}
+
+/// State of an account before and after a block. `before` is `None` for accounts created in the
+/// block.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDiffMessage {
+    pub address: String,
+    pub block_number: u64,
+    pub before: Option<AccountSnapshot>,
+    pub after: AccountSnapshot,
+}
+
+impl From<&AccountState> for AccountSnapshot {
+    fn from(account: &AccountState) -> Self {
+        AccountSnapshot {
+            lamports: account.lamports,
+            owner: account.owner.to_string(),
+            executable: account.executable,
+            rent_epoch: account.rent_epoch,
+            data: general_purpose::STANDARD.encode(&account.data),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MessageFormat {
+    Json,
+    Borsh,
+}
+
+impl MessageFormat {
+    pub fn from_config(format: &str) -> Self {
+        match format {
+            "borsh" => MessageFormat::Borsh,
+            _ => MessageFormat::Json,
+        }
+    }
+
+    fn encode<M: Serialize + BorshSerialize>(&self, message: &M) -> Vec<u8> {
+        match self {
+            MessageFormat::Json => canonical_json::to_vec(message).expect("Message serializes to JSON"),
+            MessageFormat::Borsh => borsh::to_vec(message).expect("Message serializes to Borsh"),
+        }
+    }
+}
+
+/// Publishes outbox entries to a broker. `publish` must only return `Ok` once the broker has
+/// acknowledged the message.
+#[async_trait]
+pub trait MessagePublisher: Send + Sync {
+    async fn publish(&self, entry: &OutboxEntry) -> anyhow::Result<()>;
+}
+
+/// Connects the publisher selected by `MESSAGE_BUS_KIND`, `None` when the message bus is disabled.
+pub async fn build_publisher(config: &TrollupConfig) -> anyhow::Result<Option<Arc<dyn MessagePublisher>>> {
+    match config.message_bus_kind.as_str() {
+        "" => Ok(None),
+        #[cfg(feature = "kafka")]
+        "kafka" => Ok(Some(Arc::new(kafka::KafkaPublisher::new(&config.message_bus_brokers)?))),
+        #[cfg(feature = "nats")]
+        "nats" => Ok(Some(Arc::new(nats::NatsPublisher::connect(&config.message_bus_brokers).await?))),
+        kind => Err(anyhow::anyhow!("Message bus {:?} is unknown or not compiled in (features: kafka, nats)", kind)),
+    }
+}
+
+fn topic(kind: &str) -> String {
+    CONFIG.message_bus_topics
+        .get(kind)
+        .cloned()
+        .unwrap_or_else(|| format!("trollup.{}", kind))
+}
+
+/// Builds the outbox entries for a finalized block: the block header, a receipt per transaction
+/// and a diff per updated account.
+pub fn block_entries(
+    block: &Block,
+    parent: Option<&Block>,
+    previous_accounts: &[Option<AccountState>],
+    accounts: &[AccountState],
+    transactions: &[TrollupTransaction],
+    receipts: &[TransactionReceipt],
+    format: MessageFormat,
+) -> Vec<OutboxEntry> {
+    let block_number = block.block_number;
+    let mut entries = Vec::with_capacity(1 + transactions.len() + accounts.len());
+    let mut push = |topic: String, key: Vec<u8>, payload: Vec<u8>| {
+        let sequence = (block_number << 32) | entries.len() as u64;
+        entries.push(OutboxEntry { sequence, topic, key, payload });
+    };
+
+    push(topic("blocks"), block_number.to_be_bytes().to_vec(), format.encode(&BlockHeaderMessage {
+        block_number,
+        block_hash: Hash::new_from_array(block.hash()).to_string(),
+        previous_block_hash: parent.map(|parent| Hash::new_from_array(parent.hash()).to_string()).unwrap_or_default(),
+        transactions_merkle_root: Hash::new_from_array(*block.transactions_merkle_root).to_string(),
+        accounts_merkle_root: Hash::new_from_array(*block.accounts_merkle_root).to_string(),
+        transaction_count: transactions.len() as u64,
+        account_count: accounts.len() as u64,
+    }));
+
+    for (index, transaction) in transactions.iter().enumerate() {
+        let signature = transaction.signatures.first().copied().unwrap_or([0u8; 64]);
+        let transaction_id = transaction.get_key();
+        let receipt = receipts.iter().find(|receipt| receipt.transaction_id == transaction_id);
+        push(topic("receipts"), signature.to_vec(), format.encode(&ReceiptMessage {
+            signature: Signature::from(signature).to_string(),
+            block_number,
+            index: index as u64,
+            optimistic: transaction.optimistic,
+            system: transaction.system,
+            compute_units: receipt.map(|receipt| receipt.compute_units).unwrap_or_default(),
+            instruction_write_sets: receipt
+                .map(|receipt| receipt.instruction_write_sets
+                    .iter()
+                    .map(|write_set| InstructionWriteSetMessage::new(write_set, transaction))
+                    .collect())
+                .unwrap_or_default(),
+        }));
+    }
+
+    for (index, account) in accounts.iter().enumerate() {
+        push(topic("accounts"), account.address.to_bytes().to_vec(), format.encode(&AccountDiffMessage {
+            address: account.address.to_string(),
+            block_number,
+            before: previous_accounts.get(index).and_then(|before| before.as_ref()).map(AccountSnapshot::from),
+            after: AccountSnapshot::from(account),
+        }));
+    }
+
+    entries
+}
+
+/// The outbox at `OUTBOX_STATE_MANAGER_DB_PATH`, `None` when the message bus is disabled.
+pub fn outbox() -> Option<&'static StateManager<SledStateManagement<OutboxEntry>>> {
+    OUTBOX.as_ref()
+}
+
+/// The messages for a finalized block in the configured format, none when the message bus is
+/// disabled. They are journaled with the block and written to the outbox by
+/// `commit_journal::apply`, so a crash can't leave a block without its messages.
+pub fn outbox_entries(block: &Block, parent: Option<&Block>, previous_accounts: &[Option<AccountState>], accounts: &[AccountState], transactions: &[TrollupTransaction], receipts: &[TransactionReceipt]) -> Vec<OutboxEntry> {
+    if OUTBOX.is_none() {
+        return vec![];
+    }
+    block_entries(block, parent, previous_accounts, accounts, transactions, receipts, MessageFormat::from_config(&CONFIG.message_bus_format))
+}
+
+/// Writes `entries` to `outbox` and flushes it. Entries are keyed by their sequence, writing a
+/// block's entries again replaces them.
+pub fn write_outbox(outbox: &StateManager<SledStateManagement<OutboxEntry>>, entries: &[OutboxEntry]) {
+    if entries.is_empty() {
+        return;
+    }
+    outbox.set_state_records(&entries.to_vec());
+    outbox.commit();
+}
+
+/// Publishes pending outbox entries in order, deleting each once it was acknowledged. Stops at
+/// the first failure so later messages are never published ahead of an earlier one; the failed
+/// entry is retried on the next run, which makes delivery at-least-once.
+pub async fn relay_outbox(publisher: &dyn MessagePublisher) -> anyhow::Result<usize> {
+    let Some(outbox) = OUTBOX.as_ref() else {
+        return Ok(0);
+    };
+
+    let mut published = 0;
+    let mut result = Ok(());
+    for (key, entry) in outbox.get_all_entries().into_iter().take(RELAY_BATCH_SIZE) {
+        if let Err(error) = publisher.publish(&entry).await {
+            error!("Failed to publish outbox entry {} to {}: {}", entry.message_id(), entry.topic, error);
+            result = Err(error);
+            break;
+        }
+        outbox.delete_state_record(&key);
+        published += 1;
+    }
+    if published > 0 {
+        outbox.commit();
+        info!("Published {} outbox entries", published);
+    }
+    result.map(|_| published)
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use super::MessagePublisher;
+    use async_trait::async_trait;
+    use rdkafka::config::ClientConfig;
+    use rdkafka::producer::{FutureProducer, FutureRecord};
+    use state::outbox::OutboxEntry;
+    use std::time::Duration;
+
+    pub struct KafkaPublisher {
+        producer: FutureProducer,
+    }
+
+    impl KafkaPublisher {
+        pub fn new(brokers: &[String]) -> anyhow::Result<Self> {
+            let producer = ClientConfig::new()
+                .set("bootstrap.servers", brokers.join(","))
+                .set("acks", "all")
+                .set("enable.idempotence", "true")
+                .set("message.timeout.ms", "30000")
+                .create()?;
+            Ok(KafkaPublisher { producer })
+        }
+    }
+
+    #[async_trait]
+    impl MessagePublisher for KafkaPublisher {
+        async fn publish(&self, entry: &OutboxEntry) -> anyhow::Result<()> {
+            let record = FutureRecord::to(&entry.topic).key(&entry.key).payload(&entry.payload);
+            self.producer
+                .send(record, Duration::from_secs(0))
+                .await
+                .map(|_| ())
+                .map_err(|(error, _)| error.into())
+        }
+    }
+}
+
+#[cfg(feature = "nats")]
+mod nats {
+    use super::MessagePublisher;
+    use async_nats::jetstream::{self, context::Publish};
+    use async_trait::async_trait;
+    use state::outbox::OutboxEntry;
+
+    /// Publishes through JetStream so every message is acknowledged by the server. The outbox
+    /// sequence is sent as `Nats-Msg-Id`, letting JetStream drop redeliveries within its
+    /// deduplication window.
+    pub struct NatsPublisher {
+        jetstream: jetstream::Context,
+    }
+
+    impl NatsPublisher {
+        pub async fn connect(servers: &[String]) -> anyhow::Result<Self> {
+            let client = async_nats::connect(servers.join(",")).await?;
+            Ok(NatsPublisher { jetstream: jetstream::new(client) })
+        }
+    }
+
+    #[async_trait]
+    impl MessagePublisher for NatsPublisher {
+        async fn publish(&self, entry: &OutboxEntry) -> anyhow::Result<()> {
+            let publish = Publish::build()
+                .payload(entry.payload.clone().into())
+                .message_id(entry.message_id());
+            self.jetstream.send_publish(entry.topic.clone(), publish).await?.await?;
+            Ok(())
+        }
+    }
+}
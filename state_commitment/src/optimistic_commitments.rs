@@ -0,0 +1,216 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use state::account_state::AccountState;
+use state::state_record::StateCommitmentPackage;
+use std::collections::{HashMap, VecDeque};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Settled commitments whose record is kept, older ones are forgotten.
+const MAX_FINISHED_COMMITMENTS: usize = 10_000;
+
+lazy_static! {
+    static ref COMMITMENTS: RwLock<Commitments> = RwLock::new(Commitments::default());
+    static ref RESOLUTION_REQUESTED: Notify = Notify::new();
+}
+
+#[derive(Default)]
+struct Commitments {
+    by_root: HashMap<[u8; 32], CommitmentRecord>,
+    finished: VecDeque<[u8; 32]>,
+    /// Resolutions requested by an operator that the committer hasn't carried out yet
+    resolutions: Vec<([u8; 32], Resolution)>,
+}
+
+/// Where an optimistic commitment is. Every state but `Pending` is terminal.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum CommitmentState {
+    /// In the opti-queue, waiting for its root on L1 or for its window to end
+    Pending,
+    /// Its root showed up on L1 and the block was finalized
+    Finalized,
+    /// Its window ended and it was handed to the validator to be proved like any other block
+    Proved,
+    /// Discarded, its transactions were queued to execute again
+    RolledBack,
+    /// Pending past `OPTIMISTIC_COMMITMENT_MAX_AGE`, rolled back
+    Expired,
+    /// Persisted without being pending in memory, e.g. left behind by a restart. Rolled back
+    Orphaned,
+}
+
+/// How an operator settles a stuck commitment.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Resolution {
+    /// Prove it through the validator now, as if its window ended
+    Prove,
+    /// Discard it and execute its transactions again
+    RollBack,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitmentRecord {
+    /// Hex state root the commitment is pending under
+    pub state_root: String,
+    pub parent_state_root: Option<String>,
+    pub state: CommitmentState,
+    pub transactions: usize,
+    pub request_ids: Vec<String>,
+    /// Seconds it has been pending, or was pending until it was settled
+    pub age_secs: u64,
+    /// Why it was rolled back, expired or orphaned
+    pub reason: Option<String>,
+    /// Set once an operator requested a resolution, until the committer carried it out
+    pub requested_resolution: Option<Resolution>,
+    #[serde(skip)]
+    pending_since: Option<Instant>,
+    #[serde(skip)]
+    pending_for: Option<Duration>,
+}
+
+impl CommitmentRecord {
+    fn age(&self) -> Duration {
+        self.pending_for
+            .or_else(|| self.pending_since.map(|since| since.elapsed()))
+            .unwrap_or_default()
+    }
+
+    fn view(&self) -> CommitmentRecord {
+        CommitmentRecord { age_secs: self.age().as_secs(), ..self.clone() }
+    }
+}
+
+/// Starts tracking `package` as pending. A root pending again after it was settled starts over.
+pub fn pending(package: &StateCommitmentPackage<AccountState>) {
+    let Some(state_root) = package.state_root else {
+        return;
+    };
+    let mut commitments = COMMITMENTS.write().unwrap();
+    commitments.finished.retain(|root| *root != state_root);
+    commitments.by_root.insert(state_root, CommitmentRecord {
+        state_root: hex::encode(state_root),
+        parent_state_root: package.parent_state_root.map(hex::encode),
+        state: CommitmentState::Pending,
+        transactions: package.transactions.len(),
+        request_ids: package.request_ids.clone(),
+        age_secs: 0,
+        reason: None,
+        requested_resolution: None,
+        pending_since: Some(Instant::now()),
+        pending_for: None,
+    });
+}
+
+/// Settles the pending commitment `state_root`. Settled commitments stay settled.
+pub fn finish(state_root: &[u8; 32], state: CommitmentState, reason: Option<String>) {
+    let mut commitments = COMMITMENTS.write().unwrap();
+    let Some(record) = commitments.by_root.get_mut(state_root) else {
+        return;
+    };
+    if record.state != CommitmentState::Pending {
+        return;
+    }
+    record.pending_for = Some(record.age());
+    record.state = state;
+    record.reason = reason;
+    record.requested_resolution = None;
+    commitments.resolutions.retain(|(root, _)| root != state_root);
+    commitments.finished.push_back(*state_root);
+    if commitments.finished.len() > MAX_FINISHED_COMMITMENTS {
+        if let Some(oldest) = commitments.finished.pop_front() {
+            commitments.by_root.remove(&oldest);
+        }
+    }
+}
+
+pub fn status(state_root: &[u8; 32]) -> Option<CommitmentRecord> {
+    COMMITMENTS.read().unwrap().by_root.get(state_root).map(CommitmentRecord::view)
+}
+
+/// Commitments pending for at least `older_than`, oldest first.
+pub fn stuck(older_than: Duration) -> Vec<CommitmentRecord> {
+    let commitments = COMMITMENTS.read().unwrap();
+    let mut stuck: Vec<CommitmentRecord> = commitments
+        .by_root
+        .values()
+        .filter(|record| record.state == CommitmentState::Pending && record.age() >= older_than)
+        .map(CommitmentRecord::view)
+        .collect();
+    stuck.sort_by(|a, b| b.age_secs.cmp(&a.age_secs).then_with(|| a.state_root.cmp(&b.state_root)));
+    stuck
+}
+
+/// Asks the committer to settle the pending commitment `state_root` with `resolution`. Fails
+/// when it isn't pending.
+pub fn request_resolution(state_root: &[u8; 32], resolution: Resolution) -> Result<CommitmentRecord, String> {
+    let mut commitments = COMMITMENTS.write().unwrap();
+    let record = match commitments.by_root.get_mut(state_root) {
+        Some(record) if record.state == CommitmentState::Pending => record,
+        Some(record) => return Err(format!("Commitment {} is already settled: {:?}", record.state_root, record.state)),
+        None => return Err(format!("No pending commitment {}", hex::encode(state_root))),
+    };
+    record.requested_resolution = Some(resolution);
+    let view = record.view();
+    commitments.resolutions.retain(|(root, _)| root != state_root);
+    commitments.resolutions.push((*state_root, resolution));
+    drop(commitments);
+    RESOLUTION_REQUESTED.notify_one();
+    Ok(view)
+}
+
+/// The requested resolutions, for the committer to carry out.
+pub fn take_resolutions() -> Vec<([u8; 32], Resolution)> {
+    std::mem::take(&mut COMMITMENTS.write().unwrap().resolutions)
+}
+
+/// Waits until an operator requests a resolution.
+pub async fn resolution_requested() {
+    RESOLUTION_REQUESTED.notified().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trollup_zk::fixtures::accounts_fixture;
+
+    fn package(root: u8) -> StateCommitmentPackage<AccountState> {
+        StateCommitmentPackage {
+            state_root: Some([root; 32]),
+            parent_state_root: Some([0u8; 32]),
+            ..StateCommitmentPackage::new(true, accounts_fixture(1), Vec::new(), Vec::new())
+        }
+    }
+
+    #[test]
+    fn test_settled_commitments_stay_settled() {
+        pending(&package(0xa1));
+        assert_eq!(status(&[0xa1; 32]).unwrap().state, CommitmentState::Pending);
+        assert!(stuck(Duration::ZERO).iter().any(|record| record.state_root == hex::encode([0xa1; 32])));
+
+        finish(&[0xa1; 32], CommitmentState::Expired, Some("too old".to_string()));
+        finish(&[0xa1; 32], CommitmentState::Finalized, None);
+        let expired = status(&[0xa1; 32]).unwrap();
+        assert_eq!((expired.state, expired.reason.as_deref()), (CommitmentState::Expired, Some("too old")));
+        assert!(!stuck(Duration::ZERO).iter().any(|record| record.state_root == expired.state_root));
+        assert!(request_resolution(&[0xa1; 32], Resolution::Prove).is_err());
+    }
+
+    #[test]
+    fn test_resolutions_are_handed_to_the_committer_once() {
+        pending(&package(0xa2));
+        assert!(request_resolution(&[0xa3; 32], Resolution::RollBack).is_err());
+        let requested = request_resolution(&[0xa2; 32], Resolution::RollBack).unwrap();
+        assert_eq!(requested.requested_resolution, Some(Resolution::RollBack));
+        // A later request replaces the earlier one
+        request_resolution(&[0xa2; 32], Resolution::Prove).unwrap();
+
+        let taken: Vec<_> = take_resolutions().into_iter().filter(|(root, _)| *root == [0xa2; 32]).collect();
+        assert_eq!(taken, vec![([0xa2; 32], Resolution::Prove)]);
+        assert!(!take_resolutions().iter().any(|(root, _)| *root == [0xa2; 32]));
+        finish(&[0xa2; 32], CommitmentState::Proved, None);
+    }
+}
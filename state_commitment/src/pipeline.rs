@@ -0,0 +1,154 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use state::account_state::AccountState;
+use state::state_record::StateRecord;
+use state::transaction::TrollupTransaction;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    static ref BATCHES: RwLock<HashMap<[u8; 32], BatchStatus>> = RwLock::new(HashMap::new());
+}
+
+/// Orders the account writes of tracked batches, the engine executes batches one at a time
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// Where a batch is between leaving the transaction pool and being finalized or dropped.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Stage {
+    /// Drained from the transaction pool and being executed by the engine
+    Executing,
+    /// Executed, waiting in the commitment pool for the committer
+    AwaitingProof,
+    /// The committer is generating the batch's proof
+    Proving,
+    /// Proof sent to the validator, waiting for it to verify and sign
+    AwaitingValidator,
+    /// Signed by the validator, waiting for the L1 commitment transaction to finalize
+    AwaitingL1,
+    /// Pending optimistic commitment, finalized when its proof is seen on L1 or proved through
+    /// the validator when the window ends
+    Optimistic,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchStatus {
+    /// Hex hash of the batch's transaction keys
+    pub batch_id: String,
+    pub stage: Stage,
+    pub transaction_count: usize,
+    pub request_ids: Vec<String>,
+    /// Hex state root, once the batch's proof was generated
+    pub state_root: Option<String>,
+    /// Unix time in milliseconds the batch entered its current stage
+    pub stage_entered_at: u64,
+    /// Unix time in milliseconds an optimistic batch stops waiting for its proof on L1
+    pub window_ends_at: Option<u64>,
+    #[serde(skip)]
+    transaction_keys: HashSet<[u8; 32]>,
+    /// Accounts the batch writes, with the sequence of the write
+    #[serde(skip)]
+    accounts: Option<(u64, Vec<AccountState>)>,
+}
+
+/// The latest state of an account written by a batch that isn't finalized yet.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingAccount {
+    pub stage: Stage,
+    pub batch_id: String,
+    pub account: AccountState,
+}
+
+/// Identifies a batch by its transactions, so the engine and the committer refer to the same
+/// batch without sharing state.
+pub fn batch_id(transactions: &[TrollupTransaction]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for transaction in transactions {
+        hasher.update(transaction.get_key());
+    }
+    hasher.finalize().into()
+}
+
+/// Moves the batch of `transactions` to `stage`, adding it if it isn't tracked yet.
+pub fn enter(transactions: &[TrollupTransaction], stage: Stage, request_ids: &[String], state_root: Option<[u8; 32]>) {
+    enter_with_window(transactions, stage, request_ids, state_root, None);
+}
+
+/// Like `enter`, for optimistic batches whose window ends `window_ms` from now.
+pub fn enter_with_window(transactions: &[TrollupTransaction], stage: Stage, request_ids: &[String], state_root: Option<[u8; 32]>, window_ms: Option<u64>) {
+    if transactions.is_empty() {
+        return;
+    }
+    let id = batch_id(transactions);
+    let now = now_millis();
+    let mut batches = BATCHES.write().unwrap();
+    // The writes recorded by the engine stay with the batch through every stage
+    let accounts = batches.get_mut(&id).and_then(|batch| batch.accounts.take());
+    batches.insert(id, BatchStatus {
+        batch_id: hex::encode(id),
+        stage,
+        transaction_count: transactions.len(),
+        request_ids: request_ids.iter().filter(|id| !id.is_empty()).cloned().collect(),
+        state_root: state_root.map(hex::encode),
+        stage_entered_at: now,
+        window_ends_at: window_ms.map(|window_ms| now + window_ms),
+        transaction_keys: transactions.iter().map(StateRecord::get_key).collect(),
+        accounts,
+    });
+}
+
+/// Records the accounts the batch of `transactions` writes, once it was executed, so reads can
+/// see them before the batch is finalized.
+pub fn record_writes(transactions: &[TrollupTransaction], accounts: &[AccountState]) {
+    if let Some(batch) = BATCHES.write().unwrap().get_mut(&batch_id(transactions)) {
+        batch.accounts = Some((WRITES.fetch_add(1, Ordering::Relaxed), accounts.to_vec()));
+    }
+}
+
+/// Stops tracking the batch of `transactions`, once it was finalized, rolled back or dropped.
+pub fn leave(transactions: &[TrollupTransaction]) {
+    BATCHES.write().unwrap().remove(&batch_id(transactions));
+}
+
+/// Every tracked batch, oldest stage entry first.
+pub fn batches() -> Vec<BatchStatus> {
+    let mut batches: Vec<BatchStatus> = BATCHES.read().unwrap().values().cloned().collect();
+    batches.sort_by_key(|batch| batch.stage_entered_at);
+    batches
+}
+
+/// The batch holding the transaction with key `transaction_key`, if it is tracked.
+pub fn batch_of(transaction_key: &[u8; 32]) -> Option<BatchStatus> {
+    BATCHES.read().unwrap()
+        .values()
+        .find(|batch| batch.transaction_keys.contains(transaction_key))
+        .cloned()
+}
+
+/// Stage of the batch holding the transaction with key `transaction_key`, if it is tracked.
+pub fn stage_of(transaction_key: &[u8; 32]) -> Option<Stage> {
+    batch_of(transaction_key).map(|batch| batch.stage)
+}
+
+/// The state of the account at `address` after the latest tracked batch writing it, if any.
+pub fn pending_account(address: &[u8; 32]) -> Option<PendingAccount> {
+    BATCHES.read().unwrap()
+        .values()
+        .filter_map(|batch| {
+            let (sequence, accounts) = batch.accounts.as_ref()?;
+            let account = accounts.iter().find(|account| &account.get_key() == address)?;
+            Some((*sequence, PendingAccount { stage: batch.stage, batch_id: batch.batch_id.clone(), account: account.clone() }))
+        })
+        .max_by_key(|(sequence, _)| *sequence)
+        .map(|(_, pending)| pending)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default()
+}
@@ -0,0 +1,88 @@
+use solana_sdk::signature::{Keypair, Signer};
+use state::config::TrollupConfig;
+use state::parameters::GovernedParameters;
+use state::rollup_metadata::{FeeSchedule, RollupMetadata};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use trollup_commitment_message::BlockProducer;
+
+/// Version of the node software, signed into every block this node produces. The workspace
+/// crates share one version.
+pub const NODE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+static FINALIZED_BLOCK_NUMBER: AtomicU64 = AtomicU64::new(0);
+static FINALIZED_TRANSACTIONS: AtomicU64 = AtomicU64::new(0);
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static COMMITMENTS_HALTED: AtomicBool = AtomicBool::new(false);
+
+/// Called by the committer for every block it finalizes.
+pub fn set_finalized_block_number(block_number: u64) {
+    FINALIZED_BLOCK_NUMBER.store(block_number, Ordering::SeqCst);
+}
+
+pub fn finalized_block_number() -> u64 {
+    FINALIZED_BLOCK_NUMBER.load(Ordering::SeqCst)
+}
+
+/// Called by the committer with the transactions of every block it finalizes.
+pub fn count_finalized_transactions(count: usize) {
+    FINALIZED_TRANSACTIONS.fetch_add(count as u64, Ordering::SeqCst);
+}
+
+/// Transactions finalized since the node started.
+pub fn finalized_transactions() -> u64 {
+    FINALIZED_TRANSACTIONS.load(Ordering::SeqCst)
+}
+
+/// Set by the committer while it isn't committing new blocks.
+pub fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::SeqCst);
+}
+
+/// Whether the committer isn't committing new blocks, because commitments are halted or it
+/// stopped.
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+/// Stops the engine from executing and the committer from committing new blocks until
+/// `resume_commitments`. Pending optimistic commitments and the batches waiting in the
+/// commitment pool are rolled back. Called when the local chain no longer matches L1.
+pub fn halt_commitments() {
+    COMMITMENTS_HALTED.store(true, Ordering::SeqCst);
+}
+
+/// Lets the engine and the committer go on, once the local chain was rolled back to L1.
+pub fn resume_commitments() {
+    COMMITMENTS_HALTED.store(false, Ordering::SeqCst);
+}
+
+pub fn commitments_halted() -> bool {
+    COMMITMENTS_HALTED.load(Ordering::SeqCst)
+}
+
+/// The rollup metadata accounts as of now, for the execution engine to serve to programs. The
+/// fee schedule is the one of `parameters`, the governed set the block is produced under.
+pub fn metadata(config: &TrollupConfig, parameters: &GovernedParameters) -> RollupMetadata {
+    RollupMetadata {
+        finalized_block_number: FINALIZED_BLOCK_NUMBER.load(Ordering::SeqCst),
+        fee_schedule: FeeSchedule {
+            lamports_per_signature: parameters.lamports_per_signature,
+            max_transaction_compute_units: config.max_transaction_compute_units,
+        },
+        sequencer: Keypair::from_bytes(&config.trollup_api_keypair)
+            .map(|keypair| keypair.pubkey())
+            .unwrap_or_default(),
+        paused: PAUSED.load(Ordering::SeqCst),
+    }
+}
+
+/// The sequencer, the API keypair, and `NODE_VERSION`, recorded in every block this node
+/// produces and signed into its L1 commitment.
+pub fn producer(config: &TrollupConfig) -> BlockProducer {
+    BlockProducer {
+        sequencer: Keypair::from_bytes(&config.trollup_api_keypair)
+            .map(|keypair| keypair.pubkey().to_bytes())
+            .unwrap_or_default(),
+        version: NODE_VERSION.parse().unwrap_or_default(),
+    }
+}
@@ -1,30 +1,50 @@
-use crate::state_commitment_layer::CommitmentResultType::{OnChain, TimeOut};
+use crate::state_commitment_layer::CommitmentResultType::{Expired, ForceRollBack, OnChain, Reconcile, TimeOut};
+use crate::batch_data;
+use crate::block_feed::{self, FinalizedBlock};
+use crate::bundles;
+use crate::challenges;
+use crate::checkpoints;
+use crate::commit_journal::{self, PendingCommit};
+use crate::geyser;
+use crate::governance;
+use crate::l1_confirmation::{L1Confirmer, RpcConfirmer};
+use crate::merkle_index;
+use crate::message_bus;
+use crate::optimistic_commitments::{self, CommitmentState, Resolution};
+use crate::pipeline::{self, Stage};
+use crate::rollup_status;
 use crate::state_commitment_pool::{StateCommitmentPool, StatePool};
-use crate::validator_client::ValidatorClient;
+use crate::validator_client::{BatchEntry, Validator, ValidatorClient};
 use ark_serialize::{CanonicalSerialize, Compress};
+use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine as _};
-use borsh::{to_vec, BorshDeserialize, BorshSerialize};
 use futures_util::{SinkExt, StreamExt};
 use lazy_static::lazy_static;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use rs_merkle::algorithms::Sha256;
-use rs_merkle::{Hasher, MerkleTree};
+use rs_merkle::MerkleTree;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use sha2::Digest;
-use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_client::rpc_config::RpcTransactionConfig;
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::hash::Hash;
 use solana_sdk::pubkey::Pubkey;
-use solana_transaction_status::UiTransactionEncoding;
+use solana_sdk::signature::Signature;
 use state::account_state::AccountState;
-use state::block::Block;
-use state::config::TrollupConfig;
+use state::batch_data::BatchData;
+use state::blob::{Blob, BlobReferences};
+use state::block::{Block, L1Anchor};
+use state::challenge::ChallengeStatus;
+use state::config::{CommitmentSignatureScheme, TrollupConfig};
+use state::cross_rollup::{self, outbox_root, OutboxMessage};
 use state::state_record::{StateCommitmentPackage, StateRecord};
 use state::transaction::TrollupTransaction;
+use state::merkle;
+use state::witness::{account_leaf, AccountInclusion, WitnessBundle};
+use state_management::blob_store::BlobStore;
 use state_management::state_management::{ManageState, StateManager};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
 use std::future::Future;
 use std::io::{Read, Write};
@@ -33,32 +53,45 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::watch::error::RecvError;
-use tokio::sync::{mpsc, watch, Mutex, RwLock};
+use tokio::sync::{mpsc, watch, Mutex, Notify, RwLock};
 use tokio::time::error::Elapsed;
-use tokio::time::{interval, sleep, timeout, Instant};
+use tokio::time::{interval, interval_at, sleep, timeout, Instant};
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
 use trollup_zk::prove::{generate_proof_load_keys, setup, ProofPackage};
+use trollup_zk::verifying_key::VerifyingKeyBytes;
 use url::Url;
 
 lazy_static! {
     static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
 }
 
+/// Time the commit pipeline's prover waits before looking at an empty commitment pool again.
+const POOL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 enum CommitmentResultType {
     OnChain,
     TimeOut,
+    /// Pending past `OPTIMISTIC_COMMITMENT_MAX_AGE`
+    Expired,
+    /// An operator asked for it to be rolled back
+    ForceRollBack,
+    /// Time to reconcile the opti-queue with its persisted copies, the state root is unused
+    Reconcile,
 }
 
 #[derive(Clone, Debug)]
 struct CommitmentProcessorMessage {
     state_root: [u8; 32],
     processor_type: CommitmentResultType,
+    /// L1 slot at which the state root was seen on chain
+    l1_slot: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PdaListenerMessage {
     state_root: [u8; 32],
+    slot: Option<u64>,
 }
 
 #[derive(Clone, Debug)]
@@ -79,49 +112,52 @@ pub trait StateCommitter<T: StateRecord> {
     fn stop(&mut self) -> impl Future<Output = ()>;
 }
 
+/// Account and transaction trees of a batch. Leaves are hashed on the rayon pool and roots built
+/// from concurrently computed subtrees, matching the rs_merkle trees verifiers build.
+#[derive(Clone)]
 pub struct TreeComposite {
-    state_tree: MerkleTree<Sha256>,
-    transaction_tree: MerkleTree<Sha256>,
+    state_leaves: Vec<[u8; 32]>,
+    transaction_leaves: Vec<[u8; 32]>,
+    /// Leaves of each tree as of the last commit, the rest are uncommitted
+    committed_state_leaves: usize,
+    committed_transaction_leaves: usize,
     index_map: HashMap<[u8; 32], usize>,
 }
 
 impl TreeComposite {
     fn new() -> Self {
-        let state_tree = MerkleTree::<Sha256>::new();
-        let transaction_tree = MerkleTree::<Sha256>::new();
-        let index_map = HashMap::<[u8; 32], usize>::new();
         TreeComposite {
-            state_tree,
-            transaction_tree,
-            index_map,
+            state_leaves: Vec::new(),
+            transaction_leaves: Vec::new(),
+            committed_state_leaves: 0,
+            committed_transaction_leaves: 0,
+            index_map: HashMap::<[u8; 32], usize>::new(),
         }
     }
 
     fn add_states(&mut self, state_records: &Vec<AccountState>) {
-        for state_record in state_records {
-            let serialized = to_vec(state_record).unwrap();
-            let hash: [u8; 32] = Sha256::hash(&serialized).into();
-            match self.state_tree.leaves() {
-                None => {
-                    let index = 0;
-                    self.state_tree.insert(hash);
-                    self.index_map.insert(state_record.get_key(), index);
-                }
-                Some(leaves) => {
-                    let index = leaves.len();
-                    self.state_tree.insert(hash);
-                    self.index_map.insert(state_record.get_key(), index);
-                }
-            }
+        let first_index = self.state_leaves.len();
+        self.state_leaves.extend(merkle::hash_leaves(state_records, account_leaf));
+        // In record order, so the last write of an account keeps its index as before
+        for (offset, state_record) in state_records.iter().enumerate() {
+            self.index_map.insert(state_record.get_key(), first_index + offset);
         }
     }
 
     fn add_transactions(&mut self, transactions: &Vec<TrollupTransaction>) {
-        for transaction in transactions {
-            let serialized = to_vec(transaction).unwrap();
-            let hash: [u8; 32] = Sha256::hash(&serialized).into();
-            self.transaction_tree.insert(hash);
-        }
+        self.transaction_leaves.extend(merkle::hash_leaves(transactions, merkle::transaction_leaf));
+    }
+
+    fn commit(&mut self) {
+        self.committed_state_leaves = self.state_leaves.len();
+        self.committed_transaction_leaves = self.transaction_leaves.len();
+    }
+
+    fn abort_uncommitted(&mut self) {
+        self.state_leaves.truncate(self.committed_state_leaves);
+        self.transaction_leaves.truncate(self.committed_transaction_leaves);
+        let committed = self.committed_state_leaves;
+        self.index_map.retain(|_, index| *index < committed);
     }
 
     fn get_leaf_index(&self, id: &[u8; 32]) -> Option<usize> {
@@ -129,11 +165,248 @@ impl TreeComposite {
     }
 
     fn get_root(&self) -> Option<[u8; 32]> {
-        self.state_tree.root()
+        merkle::parallel_root(&self.state_leaves[..self.committed_state_leaves])
     }
 
     fn get_uncommitted_root(&self) -> Option<[u8; 32]> {
-        self.state_tree.uncommitted_root()
+        merkle::parallel_root(&self.state_leaves)
+    }
+
+    fn get_transaction_root(&self) -> Option<[u8; 32]> {
+        merkle::parallel_root(&self.transaction_leaves[..self.committed_transaction_leaves])
+    }
+}
+
+/// A block of an L1 batch or the commit pipeline, proven and not yet finalized. Blocks proven
+/// after it extend its root and prove accounts it wrote against its tree.
+#[derive(Clone)]
+struct BatchBlock {
+    block_number: u64,
+    tree_composite: TreeComposite,
+    account_state_root: [u8; 32],
+}
+
+impl BatchBlock {
+    /// Proves the last write of `address` in this block.
+    fn account_inclusion(&self, address: &[u8; 32]) -> Option<AccountInclusion> {
+        let leaf_index = self.tree_composite.get_leaf_index(address)?;
+        let tree = MerkleTree::<Sha256>::from_leaves(&self.tree_composite.state_leaves);
+        Some(AccountInclusion {
+            block_number: self.block_number,
+            root: self.account_state_root,
+            leaf_index: leaf_index as u64,
+            leaf_count: self.tree_composite.state_leaves.len() as u64,
+            proof: tree.proof(&[leaf_index]).proof_hashes().to_vec(),
+        })
+    }
+}
+
+/// A block proven by the commit pipeline, on its way to the validator and L1.
+struct ProvenBlock {
+    package: StateCommitmentPackage<AccountState>,
+    batch_block: BatchBlock,
+    proof_package: ProofPackage,
+}
+
+/// What the prover of the commit pipeline hands to the submitter.
+enum PipelineItem {
+    /// A heartbeat with its block number, nothing to prove or send
+    Heartbeat(StateCommitmentPackage<AccountState>, u64),
+    /// Blocks committed with one L1 transaction, in order, with their validator entries
+    Blocks(Vec<ProvenBlock>, Vec<BatchEntry>),
+}
+
+impl PipelineItem {
+    fn block_count(&self) -> usize {
+        match self {
+            PipelineItem::Heartbeat(..) => 1,
+            PipelineItem::Blocks(blocks, _) => blocks.len(),
+        }
+    }
+
+    fn first_block_number(&self) -> u64 {
+        match self {
+            PipelineItem::Heartbeat(_, block_number) => *block_number,
+            PipelineItem::Blocks(blocks, _) => blocks[0].batch_block.block_number,
+        }
+    }
+
+    fn into_packages(self) -> Vec<StateCommitmentPackage<AccountState>> {
+        match self {
+            PipelineItem::Heartbeat(package, _) => vec![package],
+            PipelineItem::Blocks(blocks, _) => blocks.into_iter().map(|block| block.package).collect(),
+        }
+    }
+}
+
+/// What the submitter of the commit pipeline did with an item.
+enum Submission {
+    /// The validator committed the blocks with this transaction
+    Sent(Signature),
+    /// The validator refused the blocks or couldn't be reached
+    Failed(ChallengeStatus),
+    /// Heartbeats and blocks proven after a failed block aren't sent
+    NotSent,
+}
+
+/// Blocks the prover sent down the commit pipeline that aren't finalized yet. The blocks it
+/// proves next extend them.
+#[derive(Default)]
+struct Unfinalized {
+    blocks: Vec<BatchBlock>,
+    /// Accounts written by each block, the pre-state of the blocks after it
+    writes: Vec<Vec<AccountState>>,
+}
+
+impl Unfinalized {
+    fn push(&mut self, block: BatchBlock, writes: Vec<AccountState>) {
+        self.blocks.push(block);
+        self.writes.push(writes);
+    }
+
+    /// Forgets the blocks before `next_block_number`, which were finalized meanwhile.
+    fn prune(&mut self, next_block_number: u64) {
+        let finalized = self.blocks.iter().take_while(|block| block.block_number < next_block_number).count();
+        self.blocks.drain(..finalized);
+        self.writes.drain(..finalized);
+    }
+
+    fn clear(&mut self) {
+        self.blocks.clear();
+        self.writes.clear();
+    }
+
+    fn latest_write(&self, address: &Pubkey) -> Option<AccountState> {
+        self.writes
+            .iter()
+            .rev()
+            .find_map(|writes| writes.iter().rev().find(|record| record.address == *address).cloned())
+    }
+}
+
+/// Shared by the stages of the commit pipeline to bound the blocks in flight and to hold the
+/// prover back while blocks have to leave the pipeline first.
+#[derive(Default)]
+struct PipelineControl {
+    state: std::sync::Mutex<PipelineState>,
+    /// Wakes the prover when blocks leave the pipeline or settling ends
+    changed: Notify,
+    /// Wakes the finalizer when the last block in flight left the pipeline
+    emptied: Notify,
+}
+
+#[derive(Default)]
+struct PipelineState {
+    /// Blocks taken by the prover and not yet finalized, dropped or handed back
+    in_flight: usize,
+    /// Number of the first block that failed, set until the blocks proven after it, which
+    /// extend its root, have been handed back
+    failed_block: Option<u64>,
+    /// Set while the finalizer waits for the pipeline to empty to settle optimistic commitments
+    settling: bool,
+    /// Packages handed back to the prover, proven one at a time before the pool is read again
+    returned: VecDeque<StateCommitmentPackage<AccountState>>,
+}
+
+impl PipelineControl {
+    /// Waits until the prover may take another block and reserves it. Returns how many blocks,
+    /// the reserved one included, could be taken.
+    async fn reserve(&self, depth: usize) -> usize {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.failed_block.is_none() && !state.settling && state.in_flight < depth {
+                    let capacity = depth - state.in_flight;
+                    state.in_flight += 1;
+                    return capacity;
+                }
+            }
+            self.changed.notified().await;
+        }
+    }
+
+    /// Waits until the block reserved by the prover is the only one in flight.
+    async fn wait_until_alone(&self) {
+        while self.state.lock().unwrap().in_flight > 1 {
+            self.changed.notified().await;
+        }
+    }
+
+    fn take(&self, count: usize) {
+        self.state.lock().unwrap().in_flight += count;
+    }
+
+    fn release(&self, count: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= count;
+        let emptied = state.in_flight == 0;
+        if emptied {
+            state.failed_block = None;
+        }
+        drop(state);
+        self.changed.notify_one();
+        if emptied {
+            self.emptied.notify_one();
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.state.lock().unwrap().in_flight == 0
+    }
+
+    fn is_draining(&self) -> bool {
+        self.state.lock().unwrap().failed_block.is_some()
+    }
+
+    /// Whether the block `block_number` extends a failed block.
+    fn extends_failed_block(&self, block_number: u64) -> bool {
+        self.state.lock().unwrap().failed_block.is_some_and(|failed_block| block_number > failed_block)
+    }
+
+    /// Holds the prover back and has the blocks after `block_number` handed back.
+    fn fail_block(&self, block_number: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.failed_block = Some(state.failed_block.map_or(block_number, |failed_block| failed_block.min(block_number)));
+    }
+
+    fn set_settling(&self, settling: bool) {
+        self.state.lock().unwrap().settling = settling;
+        self.changed.notify_one();
+    }
+
+    /// Queues `packages` to be proven again, ahead of the commitment pool.
+    fn hand_back(&self, packages: Vec<StateCommitmentPackage<AccountState>>) {
+        for package in &packages {
+            pipeline::enter(&package.transactions, Stage::AwaitingProof, &package.request_ids, None);
+        }
+        self.state.lock().unwrap().returned.extend(packages);
+    }
+
+    fn take_returned(&self) -> Option<StateCommitmentPackage<AccountState>> {
+        self.state.lock().unwrap().returned.pop_front()
+    }
+}
+
+/// The services outside the node a committer talks to. Tests swap them for mocks.
+#[derive(Clone)]
+pub struct CommitmentClients {
+    pub validator: Arc<dyn Validator>,
+    pub l1_confirmer: Arc<dyn L1Confirmer>,
+    pub pda_source: Arc<dyn PdaSource>,
+    /// How long a commitment transaction may take to confirm before its commitment is voided
+    pub l1_confirmation_timeout: Duration,
+}
+
+impl CommitmentClients {
+    pub fn from_config(config: &TrollupConfig) -> Self {
+        // Only listened to when optimistic commitments are accepted, which requires a program ID
+        let program_pubkey = Pubkey::from_str(config.proof_verifier_program_id_current_env()).unwrap_or_default();
+        CommitmentClients {
+            validator: Arc::new(ValidatorClient::new(config.validator_url_current_env(), rollup_status::producer(config))),
+            l1_confirmer: Arc::new(RpcConfirmer::new(config.rpc_url_current_env())),
+            pda_source: Arc::new(PdaListener::new(program_pubkey)),
+            l1_confirmation_timeout: config.l1_confirmation_timeout,
+        }
     }
 }
 
@@ -143,6 +416,8 @@ pub struct StateCommitment<
     B: ManageState<Record = Block>,
     T: ManageState<Record = TrollupTransaction>,
     O: ManageState<Record = StateCommitmentPackage<AccountState>>,
+    L: ManageState<Record = Blob>,
+    R: ManageState<Record = BlobReferences>,
 > {
     commitment_pool: Arc<Mutex<StateCommitmentPool<AccountState>>>,
     committer_state: CommitterState,
@@ -150,7 +425,12 @@ pub struct StateCommitment<
     block_state_management: &'a StateManager<B>,
     transaction_state_management: &'a StateManager<T>,
     optimistic_commitment_state_management: Arc<StateManager<O>>,
+    blob_store: Arc<BlobStore<L, R>>,
+    requeue_sender: mpsc::UnboundedSender<Vec<TrollupTransaction>>,
     commitments: Arc<RwLock<HashMap<[u8; 32], CommitmentEntry<AccountState>>>>,
+    clients: CommitmentClients,
+    /// Blocks that may be between proving and finalization at once
+    pipeline_depth: usize,
 }
 
 impl<
@@ -159,7 +439,9 @@ impl<
         B: ManageState<Record = Block>,
         T: ManageState<Record = TrollupTransaction>,
         O: ManageState<Record = StateCommitmentPackage<AccountState>>,
-    > StateCommitment<'a, A, B, T, O>
+        L: ManageState<Record = Blob>,
+        R: ManageState<Record = BlobReferences>,
+    > StateCommitment<'a, A, B, T, O, L, R>
 {
     pub fn new(
         account_state_management: &'a StateManager<A>,
@@ -167,6 +449,8 @@ impl<
         block_state_management: &'a StateManager<B>,
         transaction_state_management: &'a StateManager<T>,
         optimistic_commitment_state_management: Arc<StateManager<O>>,
+        blob_store: Arc<BlobStore<L, R>>,
+        requeue_sender: mpsc::UnboundedSender<Vec<TrollupTransaction>>,
     ) -> Self {
         StateCommitment {
             commitment_pool,
@@ -175,130 +459,336 @@ impl<
             block_state_management,
             transaction_state_management,
             optimistic_commitment_state_management,
+            blob_store,
+            requeue_sender,
             commitments: Arc::new(RwLock::new(HashMap::new())),
+            clients: CommitmentClients::from_config(&CONFIG),
+            pipeline_depth: CONFIG.commit_pipeline_depth.max(1) as usize,
         }
     }
 
+    /// Replaces the validator, L1 and PDA clients built from the configuration.
+    pub fn with_clients(mut self, clients: CommitmentClients) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    /// Replaces the `COMMIT_PIPELINE_DEPTH`.
+    pub fn with_pipeline_depth(mut self, pipeline_depth: usize) -> Self {
+        self.pipeline_depth = pipeline_depth.max(1);
+        self
+    }
+
     async fn read_from_pool(&mut self) {
+        if self.pause_if_halted().await {
+            return;
+        }
         let mut commitment_pool = self.commitment_pool.lock().await;
         let account_state_commitment_package = commitment_pool.get_next();
+        // Packages waiting behind this one are a backlog, commit them with the same L1 transaction.
+        let mut batch = Vec::new();
+        if account_state_commitment_package.as_ref().is_some_and(Self::is_batchable) {
+            batch = Self::take_batch(&mut commitment_pool, CONFIG.l1_batch_max_roots.saturating_sub(1) as usize);
+        }
         drop(commitment_pool);
 
         match account_state_commitment_package {
             None => return,
+            Some(commitment_package) if commitment_package.is_heartbeat() => {
+                self.finalize_heartbeat().await;
+            }
             Some(commitment_package) => {
                 // Create proof, send proof to validator, once validator commits to a verify, then commit account and block changes to db
-
-                // TODO send optimistic transactions to thread listening for PDA updates for proof verification
-                if commitment_package.optimistic {
-                    let mut tree_composite = TreeComposite::new();
-                    tree_composite.add_transactions(&commitment_package.transactions);
-
-                    let account_states = &commitment_package.state_records;
-
-                    tree_composite.add_states(account_states);
-                    let (proof_package_lite, proof_package_prepared, proof_package) =
-                        generate_proof_load_keys(account_states.clone());
-
-                    let account_state_root = tree_composite
-                        .get_uncommitted_root()
-                        .expect("Error getting account state root");
-
-                    let mut proof_compressed =
-                        Vec::with_capacity(proof_package.proof.serialized_size(Compress::Yes));
-                    proof_package
-                        .proof
-                        .serialize_compressed(&mut proof_compressed)
-                        .expect("Error serializing and compressing proof");
-                    // self.handle_optimistic_transactions(optimistic_txs, account_states.clone(), account_state_root);
-                    info!("Adding optimistic commitment to opti-q");
-                    let pending_state_commitment_package = StateCommitmentPackage {
-                        optimistic: true,
-                        proof: proof_package_prepared.proof,
-                        public_inputs: proof_package_prepared.public_inputs,
-                        verifying_key: proof_package_lite.verifying_key,
-                        state_root: Some(account_state_root),
-                        state_records: commitment_package.state_records,
-                        transactions: commitment_package.transactions,
-                        transaction_ids: commitment_package.transaction_ids,
-                    };
-                    self.add_commitment(pending_state_commitment_package).await;
+                if commitment_package.optimistic && CONFIG.mode.accepts_optimistic() {
+                    self.pend_optimistic(commitment_package).await;
                     return;
                 }
 
-                self.verify_with_validator(commitment_package).await;
+                if batch.is_empty() {
+                    self.verify_with_validator(commitment_package).await;
+                } else {
+                    batch.insert(0, commitment_package);
+                    self.verify_batch_with_validator(batch).await;
+                }
             }
         }
     }
 
+    /// One step of the serial committer: the opti-queue messages that arrived, then the next
+    /// package of the pool. They take turns rather than racing, a message interrupting
+    /// `read_from_pool` would drop the batch it already took from the pool.
+    async fn commit_serially(&mut self, optimistic_processor_receiver: &mut Receiver<CommitmentProcessorMessage>) {
+        while let Ok(commitment_processor_message) = optimistic_processor_receiver.try_recv() {
+            self.process_commitment_message(commitment_processor_message).await;
+        }
+        self.read_from_pool().await;
+    }
+
+    /// Rolls back the pending commitments and the commitment pool and pauses the committer while
+    /// commitments are halted, and unpauses it once they resume. Returns whether they are halted.
+    async fn pause_if_halted(&self) -> bool {
+        if !rollup_status::commitments_halted() {
+            // Commitments were resumed once the local chain was rolled back to L1
+            if rollup_status::is_paused() && self.committer_state == CommitterState::Running {
+                info!("Commitments resumed on block {}", self.next_block_number());
+                rollup_status::set_finalized_block_number(self.next_block_number() - 1);
+                rollup_status::set_paused(false);
+            }
+            return false;
+        }
+        self.roll_back_pending("commitments are halted").await;
+        self.roll_back_pool("commitments are halted").await;
+        rollup_status::set_paused(true);
+        sleep(Duration::from_secs(1)).await;
+        true
+    }
+
+    /// Proves an optimistic package and adds it to the opti-queue, where it waits for its proof
+    /// to show up on L1.
+    async fn pend_optimistic(&self, commitment_package: StateCommitmentPackage<AccountState>) {
+        pipeline::enter(&commitment_package.transactions, Stage::Proving, &commitment_package.request_ids, None);
+        let mut tree_composite = TreeComposite::new();
+        tree_composite.add_transactions(&commitment_package.transactions);
+
+        let account_states = &commitment_package.state_records;
+
+        tree_composite.add_states(account_states);
+        let parent_state_root = self.canonical_tip();
+        let account_state_root = tree_composite
+            .get_uncommitted_root()
+            .expect("Error getting account state root");
+        let (proof_package_lite, proof_package_prepared, proof_package) = generate_proof_load_keys(
+            account_states.clone(),
+            &parent_state_root.unwrap_or_default(),
+            &merkle::transactions_root(&commitment_package.transactions),
+            &account_state_root,
+        );
+
+        let mut proof_compressed =
+            Vec::with_capacity(proof_package.proof.serialized_size(Compress::Yes));
+        proof_package
+            .proof
+            .serialize_compressed(&mut proof_compressed)
+            .expect("Error serializing and compressing proof");
+        info!("Adding optimistic commitment to opti-q, request_ids={:?}", commitment_package.request_ids);
+        let pending_state_commitment_package = StateCommitmentPackage {
+            optimistic: true,
+            proof: proof_package_prepared.proof.to_vec(),
+            public_inputs: proof_package_prepared.public_inputs.to_vec(),
+            verifying_key: proof_package_lite.verifying_key.as_bytes().to_vec(),
+            state_root: Some(account_state_root),
+            state_records: commitment_package.state_records,
+            transactions: commitment_package.transactions,
+            transaction_ids: commitment_package.transaction_ids,
+            verifying_key_hash: None,
+            parent_state_root,
+            witness: commitment_package.witness,
+            request_ids: commitment_package.request_ids,
+            receipts: commitment_package.receipts,
+            parameters_hash: commitment_package.parameters_hash,
+            program_data_hashes: vec![],
+        };
+        self.add_commitment(pending_state_commitment_package).await;
+    }
+
+    /// Whether a package can share an L1 transaction with the packages around it. Heartbeats
+    /// aren't committed on L1 and optimistic packages wait in the opti-queue instead.
+    fn is_batchable(package: &StateCommitmentPackage<AccountState>) -> bool {
+        !package.is_heartbeat() && !(package.optimistic && CONFIG.mode.accepts_optimistic())
+    }
+
+    /// Takes up to `limit` batchable packages from the front of the pool, to share an L1
+    /// transaction with the package taken before them. Only secp256k1 commitments can be batched.
+    fn take_batch(commitment_pool: &mut StateCommitmentPool<AccountState>, limit: usize) -> Vec<StateCommitmentPackage<AccountState>> {
+        let mut batch = Vec::new();
+        if CONFIG.commitment_signature_scheme != CommitmentSignatureScheme::Secp256k1 {
+            return batch;
+        }
+        while batch.len() < limit && commitment_pool.peek_next().is_some_and(Self::is_batchable) {
+            batch.extend(commitment_pool.get_next());
+        }
+        batch
+    }
+
     async fn verify_with_validator(
         &self,
         commitment_package: StateCommitmentPackage<AccountState>,
     ) {
+        pipeline::enter(&commitment_package.transactions, Stage::Proving, &commitment_package.request_ids, None);
         let mut tree_composite = TreeComposite::new();
         tree_composite.add_transactions(&commitment_package.transactions);
 
         let account_states = &commitment_package.state_records;
 
         tree_composite.add_states(account_states);
-        let (_proof_package_lite, proof_package_prepared, proof_package) =
-            generate_proof_load_keys(account_states.clone());
-
         let account_state_root = tree_composite
             .get_uncommitted_root()
             .expect("Error getting account state root");
+        let (_proof_package_lite, proof_package_prepared, proof_package) = generate_proof_load_keys(
+            account_states.clone(),
+            &self.canonical_tip().unwrap_or_default(),
+            &merkle::transactions_root(&commitment_package.transactions),
+            &account_state_root,
+        );
 
-        let validator_client = ValidatorClient::new(&CONFIG.trollup_validator_url);
-        let validator_result = validator_client
-            .prove(proof_package_prepared, &account_state_root)
+        let witness = commitment_package.witness.clone().map(|witness| self.complete_witness(witness));
+        let outbox_root = outbox_root(&self.outbox_messages(account_states));
+        pipeline::enter(&commitment_package.transactions, Stage::AwaitingValidator, &commitment_package.request_ids, Some(account_state_root));
+        let validator_result = self.clients.validator
+            .prove(proof_package_prepared, witness.as_ref(), &account_state_root, self.next_block_number(), &outbox_root)
             .await;
         match validator_result {
             Ok(response) => {
                 if response.success {
-                    info!("Successful response from validator: {:?}", response);
-                    let client = RpcClient::new(CONFIG.rpc_url_current_env().to_string());
-                    // Check the transaction status
-                    loop {
-                        let is_transaction_finalized = client
-                            .confirm_transaction(&response.signature)
-                            .await
-                            .expect("Error confirming sig verifier transaction");
-                        if (is_transaction_finalized) {
-                            break;
+                    info!("Successful response from validator: {:?}, request_ids={:?}", response, commitment_package.request_ids);
+                    pipeline::enter(&commitment_package.transactions, Stage::AwaitingL1, &commitment_package.request_ids, Some(account_state_root));
+                    match self.confirm_on_l1(&response.signature).await {
+                        Some(l1_anchor) => {
+                            self.finalize(
+                                &mut tree_composite,
+                                commitment_package,
+                                proof_package,
+                                account_state_root,
+                                l1_anchor,
+                            )
+                            .await;
                         }
-                        //TODO bail out of this with a timeout and fail finalization
-                    }
-                    let transaction_status = client
-                        .get_transaction(&response.signature, UiTransactionEncoding::JsonParsed)
-                        .await
-                        .expect("Error getting transaction.");
-
-                    // Check if the transaction was successful
-                    match transaction_status.transaction.meta {
-                        Some(meta) => {
-                            if meta.err.is_none() {
-                                println!("Transaction was successful! Finalizing account state.");
-                                self.finalize(
-                                    &mut tree_composite,
-                                    commitment_package,
-                                    proof_package,
-                                    account_state_root,
-                                )
-                                .await;
-                            } else {
-                                println!("Transaction failed: {:?}", meta.err);
-                            }
+                        None => {
+                            pipeline::leave(&commitment_package.transactions);
+                            challenges::resolve(&account_state_root, ChallengeStatus::Void);
                         }
-                        None => println!("Transaction status not available"),
                     }
+                } else {
+                    pipeline::leave(&commitment_package.transactions);
+                    // The validator refused the proof, challengers of the root were right
+                    challenges::resolve(&account_state_root, ChallengeStatus::Upheld);
                 }
             }
             Err(response) => {
-                info!("Unsuccessful response from validator: {:?}", response);
+                info!("Unsuccessful response from validator: {:?}, request_ids={:?}", response, commitment_package.request_ids);
+                pipeline::leave(&commitment_package.transactions);
+                challenges::resolve(&account_state_root, ChallengeStatus::Void);
 
                 // If the validation failed, abort the uncommitted changes.
-                tree_composite.transaction_tree.abort_uncommitted();
-                tree_composite.state_tree.abort_uncommitted();
+                tree_composite.abort_uncommitted();
+            }
+        }
+    }
+
+    /// Commits consecutive packages with a single L1 transaction, each still finalized as a
+    /// block of its own and all anchored to that transaction. When the validator refuses the
+    /// batch or the transaction fails the packages are committed one at a time instead, so a
+    /// single bad block doesn't hold back the others.
+    async fn verify_batch_with_validator(
+        &self,
+        commitment_packages: Vec<StateCommitmentPackage<AccountState>>,
+    ) {
+        let first_block_number = self.next_block_number();
+        let mut parent_state_root = self.canonical_tip();
+        let mut batch_blocks: Vec<BatchBlock> = Vec::with_capacity(commitment_packages.len());
+        let mut proof_packages = Vec::with_capacity(commitment_packages.len());
+        let mut entries = Vec::with_capacity(commitment_packages.len());
+        for (offset, commitment_package) in commitment_packages.iter().enumerate() {
+            pipeline::enter(&commitment_package.transactions, Stage::Proving, &commitment_package.request_ids, None);
+            let mut tree_composite = TreeComposite::new();
+            tree_composite.add_transactions(&commitment_package.transactions);
+            tree_composite.add_states(&commitment_package.state_records);
+            let account_state_root = tree_composite
+                .get_uncommitted_root()
+                .expect("Error getting account state root");
+            let (_proof_package_lite, proof_package_prepared, proof_package) = generate_proof_load_keys(
+                commitment_package.state_records.clone(),
+                &parent_state_root.unwrap_or_default(),
+                &merkle::transactions_root(&commitment_package.transactions),
+                &account_state_root,
+            );
+
+            // Earlier blocks of the batch aren't finalized yet, their writes are the pre-state
+            let earlier_packages = &commitment_packages[..offset];
+            let witness = commitment_package.witness.clone()
+                .map(|witness| self.complete_batch_witness(witness, parent_state_root, &batch_blocks));
+            let outbox_root = outbox_root(&cross_rollup::outbox_messages(&commitment_package.state_records, |address| {
+                earlier_packages
+                    .iter()
+                    .rev()
+                    .find_map(|package| package.state_records.iter().rev().find(|record| record.address == *address).cloned())
+                    .or_else(|| self.account_state_management.get_state_record(&address.to_bytes()))
+            }));
+            pipeline::enter(&commitment_package.transactions, Stage::AwaitingValidator, &commitment_package.request_ids, Some(account_state_root));
+
+            let block_number = first_block_number + offset as u64;
+            entries.push(BatchEntry {
+                proof_package: proof_package_prepared,
+                witness,
+                new_state_root: account_state_root,
+                sequence: block_number,
+                outbox_root,
+            });
+            batch_blocks.push(BatchBlock {
+                block_number,
+                tree_composite,
+                account_state_root,
+            });
+            proof_packages.push(proof_package);
+            parent_state_root = Some(account_state_root);
+        }
+
+        info!("Committing blocks {} to {} with one L1 transaction", first_block_number, first_block_number + entries.len() as u64 - 1);
+        let l1_anchor = match self.clients.validator.prove_batch(&entries).await {
+            Ok(response) if response.success => {
+                for (commitment_package, batch_block) in commitment_packages.iter().zip(&batch_blocks) {
+                    pipeline::enter(&commitment_package.transactions, Stage::AwaitingL1, &commitment_package.request_ids, Some(batch_block.account_state_root));
+                }
+                self.confirm_on_l1(&response.signature).await
+            }
+            Ok(response) => {
+                info!("Validator refused the batch: {:?}", response);
+                None
+            }
+            Err(error) => {
+                info!("Unsuccessful response from validator for the batch: {:?}", error);
+                None
+            }
+        };
+
+        match l1_anchor {
+            Some(l1_anchor) => {
+                for ((commitment_package, mut batch_block), proof_package) in commitment_packages.into_iter().zip(batch_blocks).zip(proof_packages) {
+                    self.finalize(
+                        &mut batch_block.tree_composite,
+                        commitment_package,
+                        proof_package,
+                        batch_block.account_state_root,
+                        l1_anchor.clone(),
+                    )
+                    .await;
+                }
+            }
+            None => {
+                warn!("Committing the {} blocks of the batch one at a time", commitment_packages.len());
+                for commitment_package in commitment_packages {
+                    self.verify_with_validator(commitment_package).await;
+                }
+            }
+        }
+    }
+
+    /// Waits for the commitment transaction `signature` and returns its anchor, or `None` when
+    /// it failed, couldn't be looked up or wasn't confirmed within `L1_CONFIRMATION_TIMEOUT`.
+    async fn confirm_on_l1(&self, signature: &Signature) -> Option<L1Anchor> {
+        match timeout(self.clients.l1_confirmation_timeout, self.clients.l1_confirmer.confirm(signature)).await {
+            Ok(Ok(Some(l1_anchor))) => {
+                info!("Commitment transaction {} succeeded, finalizing account state", signature);
+                Some(l1_anchor)
+            }
+            Ok(Ok(None)) => None,
+            Ok(Err(error)) => {
+                error!("Failed to confirm commitment transaction {}: {}", signature, error);
+                None
+            }
+            Err(_) => {
+                warn!("Commitment transaction {} wasn't confirmed within {:?}", signature, self.clients.l1_confirmation_timeout);
+                None
             }
         }
     }
@@ -309,9 +799,10 @@ impl<
         account_state_commitment_package: StateCommitmentPackage<AccountState>,
         proof_package: ProofPackage,
         account_state_root: [u8; 32],
+        l1_anchor: L1Anchor,
     ) {
-        tree_composite.transaction_tree.commit();
-        tree_composite.state_tree.commit();
+        tree_composite.commit();
+        pipeline::leave(&account_state_commitment_package.transactions);
 
         let account_states = account_state_commitment_package.state_records;
         let account_addresses: Vec<[u8; 32]> = account_states
@@ -322,67 +813,359 @@ impl<
             })
             .collect();
 
-        self.account_state_management
-            .set_state_records(&account_states);
-        self.transaction_state_management
-            .set_state_records(&account_state_commitment_package.transactions);
-        self.account_state_management.commit();
-        self.transaction_state_management.commit();
+        let previous_account_states: Vec<Option<AccountState>> = account_states
+            .iter()
+            .map(|state| self.account_state_management.get_state_record(&state.address.to_bytes()))
+            .collect();
+        let outbox_messages = self.outbox_messages(&account_states);
+
         let mut compressed_proof = Vec::new();
         proof_package
             .proof
             .serialize_uncompressed(&mut compressed_proof)
             .expect("Failed to serialize proof");
+        let mut public_inputs = Vec::new();
+        proof_package
+            .public_inputs
+            .serialize_uncompressed(&mut public_inputs)
+            .expect("Failed to serialize public inputs");
 
-        let next_block_number = self
+        let parent_block = self
             .block_state_management
             .get_latest_block_id()
-            .and_then(|id| self.block_state_management.get_state_record(&id))
+            .and_then(|id| self.block_state_management.get_state_record(&id));
+        let next_block_number = parent_block
+            .as_ref()
             .map(|block| block.block_number + 1)
             .unwrap_or(1);
 
+        let verifying_key = VerifyingKeyBytes::from_verifying_key(&proof_package.prepared_verifying_key.vk)
+            .expect("Failed to serialize verifying key");
+        let verifying_key_hash = self.blob_store.put(verifying_key.as_bytes());
+        self.blob_store.commit();
+
         let tx_ids = account_state_commitment_package.transaction_ids;
-        let block = Block::new(
+        let mut block = Block::new(
             next_block_number,
             Block::get_id(next_block_number - 1),
             Box::new(
                 tree_composite
-                    .transaction_tree
-                    .root()
+                    .get_transaction_root()
                     .expect("Transaction tree root should exist"),
             ),
             Box::new(account_state_root),
             compressed_proof,
+            public_inputs,
+            verifying_key_hash,
             tx_ids,
             account_addresses,
         );
+        block.l1_anchor = l1_anchor;
+        // The batch's leaves, already hashed for the root
+        block.account_leaves = tree_composite.state_leaves.clone();
+        block.outbox_root = outbox_root(&outbox_messages);
+        block.outbox_messages = outbox_messages;
+        block.fees = account_state_commitment_package.receipts.iter().map(|receipt| receipt.fee).sum();
+        block.set_producer(rollup_status::producer(&CONFIG));
+        block.set_parameters_hash(account_state_commitment_package.parameters_hash);
+        let parameters = governance::by_hash(block.parameters_hash()).unwrap_or_else(|| governance::effective(block.block_number));
+        challenges::resolve(&account_state_root, ChallengeStatus::Rejected);
+        block.challenge_outcomes = challenges::take_unrecorded(block.block_number);
 
         info!("Saving new block: {:?}", block.get_key());
-        self.block_state_management
-            .set_latest_block_id(&block.get_key());
-        self.block_state_management.set_state_record(&block);
-        self.block_state_management.commit();
+        let outbox = message_bus::outbox_entries(
+            &block,
+            parent_block.as_ref(),
+            &previous_account_states,
+            &account_states,
+            &account_state_commitment_package.transactions,
+            &account_state_commitment_package.receipts,
+        );
+        let pending = PendingCommit {
+            block,
+            accounts: account_states,
+            transactions: account_state_commitment_package.transactions,
+            outbox,
+        };
+        self.commit_block(&pending);
+        let PendingCommit { block, accounts: account_states, transactions, .. } = pending;
+        rollup_status::set_finalized_block_number(block.block_number);
+        rollup_status::count_finalized_transactions(block.transactions.len());
+        bundles::finalize(&block.transactions, block.block_number);
+        self.checkpoint(&block);
+        batch_data::publish(BatchData {
+            block_number: block.block_number,
+            account_state_root,
+            transactions: transactions.clone(),
+            transaction_ids: block.transactions.clone(),
+            fees: block.fees,
+            witness: account_state_commitment_package.witness,
+            parameters,
+        }).await;
+
+        geyser::notify_block(&block, parent_block.as_ref(), &account_states, &transactions);
+        block_feed::publish(FinalizedBlock {
+            block,
+            accounts: account_states,
+            transactions,
+        });
+    }
+
+    /// Appends a heartbeat block on top of the latest one. Nothing changed, so there is nothing
+    /// to prove or commit to L1; the block carries its parent's accounts root.
+    async fn finalize_heartbeat(&self) {
+        let parent_block = self
+            .block_state_management
+            .get_latest_block_id()
+            .and_then(|id| self.block_state_management.get_state_record(&id));
+        let next_block_number = parent_block
+            .as_ref()
+            .map(|block| block.block_number + 1)
+            .unwrap_or(1);
+        let accounts_root = parent_block
+            .as_ref()
+            .map(|block| *block.accounts_merkle_root)
+            .unwrap_or_default();
+
+        let mut block = Block::new(
+            next_block_number,
+            Block::get_id(next_block_number - 1),
+            Box::new([0u8; 32]),
+            Box::new(accounts_root),
+            vec![],
+            vec![],
+            [0u8; 32],
+            vec![],
+            vec![],
+        );
+        block.set_producer(rollup_status::producer(&CONFIG));
+        let parameters = governance::effective(block.block_number);
+        block.set_parameters_hash(parameters.hash());
+        block.challenge_outcomes = challenges::take_unrecorded(block.block_number);
+
+        info!("Saving heartbeat block {}", block.block_number);
+        let outbox = message_bus::outbox_entries(&block, parent_block.as_ref(), &[], &[], &[], &[]);
+        let pending = PendingCommit { block, accounts: vec![], transactions: vec![], outbox };
+        self.commit_block(&pending);
+        let block = pending.block;
+        rollup_status::set_finalized_block_number(block.block_number);
+        self.checkpoint(&block);
+        batch_data::publish(BatchData {
+            block_number: block.block_number,
+            account_state_root: accounts_root,
+            transactions: vec![],
+            transaction_ids: vec![],
+            fees: 0,
+            witness: None,
+            parameters,
+        }).await;
+
+        geyser::notify_block(&block, parent_block.as_ref(), &[], &[]);
+        block_feed::publish(FinalizedBlock {
+            block,
+            accounts: vec![],
+            transactions: vec![],
+        });
+    }
+
+    /// Writes a finalized block and its outbox entries to every store through the commit
+    /// journal, so a crash part way leaves an entry `commit_journal::recover` repairs on the next
+    /// start.
+    fn commit_block(&self, pending: &PendingCommit) {
+        let journal = commit_journal::journal();
+        journal.prepare(pending);
+        commit_journal::apply(pending, self.account_state_management, self.block_state_management, self.transaction_state_management, message_bus::outbox());
+        journal.complete(&pending.block);
     }
 
     async fn start_pda_listener(&self, pda_sender: Sender<PdaListenerMessage>) {
-        let program_pubkey =
-            Pubkey::from_str(&CONFIG.proof_verifier_program_id).expect("Invalid program ID");
-        let pda_sender = pda_sender.clone();
+        let pda_source = Arc::clone(&self.clients.pda_source);
 
         // Start the PDA listener in a new thread
         tokio::spawn(async move {
-            let mut pda_listener = PdaListener::new(program_pubkey);
-            if let Err(e) = pda_listener.start(pda_sender).await {
-                eprintln!("PDA listener error: {:?}", e);
+            pda_source.listen(pda_sender).await;
+        });
+    }
+
+    /// Adds the parent root and an inclusion proof for every witnessed account, so the validator
+    /// can check the pre-state it re-executes against.
+    fn complete_witness(&self, mut witness: WitnessBundle) -> WitnessBundle {
+        witness.parent_state_root = self.canonical_tip();
+        for account_witness in witness.accounts.iter_mut() {
+            account_witness.inclusion = self.account_inclusion(&account_witness.account);
+            if account_witness.inclusion.is_none() {
+                warn!("No inclusion proof for account {}, the validator will reject the batch", account_witness.account.address);
+            }
+        }
+        witness
+    }
+
+    /// `complete_witness` for a block of an L1 batch. The block extends `parent_state_root`, the
+    /// block before it in the batch, and accounts written earlier in the batch are proven
+    /// against those blocks' trees.
+    fn complete_batch_witness(&self, mut witness: WitnessBundle, parent_state_root: Option<[u8; 32]>, batch_blocks: &[BatchBlock]) -> WitnessBundle {
+        witness.parent_state_root = parent_state_root;
+        for account_witness in witness.accounts.iter_mut() {
+            let address = account_witness.account.address.to_bytes();
+            account_witness.inclusion = batch_blocks
+                .iter()
+                .rev()
+                .find_map(|batch_block| batch_block.account_inclusion(&address))
+                .or_else(|| self.account_inclusion(&account_witness.account));
+            if account_witness.inclusion.is_none() {
+                warn!("No inclusion proof for account {}, the validator will reject the batch", account_witness.account.address);
+            }
+        }
+        witness
+    }
+
+    /// Produces the checkpoint of the epoch `block` completes, if any, and bridges it to L1 and
+    /// posts its account set root to the vault in the background.
+    fn checkpoint(&self, block: &Block) {
+        if checkpoints::completed_epoch(block.block_number).is_none() {
+            return;
+        }
+        let accounts: Vec<AccountState> = self.account_state_management
+            .get_all_entries()
+            .into_iter()
+            .map(|(_, account)| account)
+            .collect();
+        let Some(checkpoint) = checkpoints::produce(block, |block_number| self.block_state_management.get_state_record(&Block::get_id(block_number)), &accounts) else {
+            return;
+        };
+        tokio::spawn(async move {
+            match checkpoints::bridge(&checkpoint).await {
+                Ok(Some(signature)) => info!("Bridged the checkpoint of epoch {} to L1: {}", checkpoint.epoch, signature),
+                Ok(None) => {}
+                Err(error) => warn!("Failed to bridge the checkpoint of epoch {} to L1: {}", checkpoint.epoch, error),
+            }
+            match checkpoints::post_to_vault(&checkpoint).await {
+                Ok(Some(signature)) => info!("Posted the account set root of epoch {} to the vault: {}", checkpoint.epoch, signature),
+                Ok(None) => {}
+                Err(error) => warn!("Failed to post the account set root of epoch {} to the vault: {}", checkpoint.epoch, error),
             }
         });
     }
 
+    /// Proves `account` against the account tree of the latest block that wrote it, as the Merkle
+    /// index records it, from the stored tree layers when the block has them. Blocks finalized
+    /// before account leaves were stored can't be proven against, and accounts last written
+    /// before the index tracked writers need a reindex first.
+    fn account_inclusion(&self, account: &AccountState) -> Option<AccountInclusion> {
+        let address = account.address.to_bytes();
+        let block_number = merkle_index::latest_block_writing(&address)?;
+        if let Some(inclusion) = merkle_index::account_inclusion(block_number, &address) {
+            return Some(inclusion);
+        }
+        let block = self.block_state_management.get_state_record(&Block::get_id(block_number))?;
+        if block.account_leaves.len() != block.accounts.len() {
+            return None;
+        }
+        // The last write of an account within a block is the one that was stored
+        let leaf_index = block.accounts.iter().rposition(|candidate| *candidate == address)?;
+        let tree = MerkleTree::<Sha256>::from_leaves(&block.account_leaves);
+        Some(AccountInclusion {
+            block_number: block.block_number,
+            root: *block.accounts_merkle_root,
+            leaf_index: leaf_index as u64,
+            leaf_count: block.account_leaves.len() as u64,
+            proof: tree.proof(&[leaf_index]).proof_hashes().to_vec(),
+        })
+    }
+
+    /// Cross-rollup messages sent by a batch with the post-state `account_states`, judged against
+    /// the finalized state it is about to replace.
+    fn outbox_messages(&self, account_states: &[AccountState]) -> Vec<OutboxMessage> {
+        cross_rollup::outbox_messages(account_states, |address| {
+            self.account_state_management.get_state_record(&address.to_bytes())
+        })
+    }
+
+    fn next_block_number(&self) -> u64 {
+        self.block_state_management
+            .get_latest_block_id()
+            .and_then(|id| self.block_state_management.get_state_record(&id))
+            .map(|block| block.block_number + 1)
+            .unwrap_or(1)
+    }
+
+    /// Account root of the latest finalized block. Pending commitments must extend it to be
+    /// finalized.
+    fn canonical_tip(&self) -> Option<[u8; 32]> {
+        self.block_state_management
+            .get_latest_block_id()
+            .and_then(|id| self.block_state_management.get_state_record(&id))
+            .map(|block| *block.accounts_merkle_root)
+    }
+
+    /// Discards a commitment that lost against the canonical chain. Its state was never written,
+    /// so rolling back only means executing its transactions again on top of the canonical state.
+    fn roll_back(&self, package: StateCommitmentPackage<AccountState>, reason: &str) {
+        warn!(
+            "Rolling back pending commit {:?}, {}. Re-queuing {} transactions, request_ids={:?}",
+            package.state_root,
+            reason,
+            package.transactions.len(),
+            package.request_ids
+        );
+        pipeline::leave(&package.transactions);
+        bundles::requeue(&package.transactions);
+        if let Some(state_root) = &package.state_root {
+            challenges::resolve(state_root, ChallengeStatus::Void);
+        }
+        if self.requeue_sender.send(package.transactions).is_err() {
+            error!("Transaction pool is gone, dropping rolled back transactions");
+        }
+    }
+
+    /// Rolls back every pending optimistic commitment.
+    async fn roll_back_pending(&self, reason: &str) {
+        let state_roots: Vec<[u8; 32]> = self.commitments.read().await.keys().copied().collect();
+        for state_root in state_roots {
+            let Some(package) = self.commitments.read().await.get(&state_root).map(|entry| entry.package.clone()) else {
+                continue;
+            };
+            self.remove_commitment(&state_root).await;
+            self.roll_back(package, reason);
+            optimistic_commitments::finish(&state_root, CommitmentState::RolledBack, Some(reason.to_string()));
+        }
+    }
+
+    /// Rolls back the executed batches waiting in the commitment pool, they were executed on
+    /// state that may be about to be rolled back.
+    async fn roll_back_pool(&self, reason: &str) {
+        let packages = self.commitment_pool.lock().await.get_next_chunk(u32::MAX);
+        for package in packages.into_iter().filter(|package| !package.is_heartbeat()) {
+            self.roll_back(package, reason);
+        }
+    }
+
+    /// Adds a pending commitment unless it conflicts with one that is already pending. The first
+    /// commitment on a parent root is canonical, later ones on the same parent are rolled back.
     async fn add_commitment(&self, package: StateCommitmentPackage<AccountState>) {
-        info!("Added pending commit: {:?}", &package);
         let mut commitments = self.commitments.write().await;
-        self.optimistic_commitment_state_management
-            .set_state_record(&package);
+        let state_root = package.state_root.unwrap();
+        if let Some(existing) = commitments.get(&state_root) {
+            if existing.package.transaction_ids == package.transaction_ids {
+                info!("Ignoring duplicate pending commit: {:?}", state_root);
+            } else {
+                self.roll_back(package, "its state root is already claimed by another pending commit");
+            }
+            return;
+        }
+        if let Some(canonical) = commitments
+            .values()
+            .find(|entry| entry.package.parent_state_root == package.parent_state_root)
+        {
+            let reason = format!("pending commit {:?} already extends the same parent", canonical.package.state_root);
+            self.roll_back(package, &reason);
+            return;
+        }
+
+        info!("Added pending commit: {:?}", &package);
+        self.persist_commitment(&package);
+        pipeline::enter_with_window(&package.transactions, Stage::Optimistic, &package.request_ids, package.state_root, Some(governance::current().challenge_window().as_millis() as u64));
+        optimistic_commitments::pending(&package);
         commitments.insert(
             package.state_root.unwrap(),
             CommitmentEntry {
@@ -392,8 +1175,55 @@ impl<
         );
     }
 
+    /// Stores the pending record of `package`. It only keeps the hashes of the verifying key and
+    /// of the programs it holds, the full package stays in memory.
+    fn persist_commitment(&self, package: &StateCommitmentPackage<AccountState>) {
+        let stored_package = self.blob_store.put_package(package);
+        self.blob_store.commit();
+        self.optimistic_commitment_state_management
+            .set_state_record(&stored_package);
+    }
+
+    /// Brings the opti-queue and its persisted copies back in line. A stored commitment that
+    /// isn't pending in memory was left behind, by a restart or a removal that didn't go
+    /// through, and is rolled back so its transactions execute again. A pending one missing
+    /// from the store is stored again.
+    async fn reconcile_commitments(&self) {
+        // Held throughout so no commitment is added between reading the two
+        let pending = self.commitments.read().await;
+        let stored = self.optimistic_commitment_state_management.get_all_entries();
+        let mut orphaned = 0;
+        for (state_root, package) in &stored {
+            if pending.contains_key(state_root) {
+                continue;
+            }
+            self.blob_store.release_package(package);
+            self.blob_store.commit();
+            self.optimistic_commitment_state_management.delete_state_record(state_root);
+            let reason = "it was stored but no longer pending";
+            optimistic_commitments::pending(package);
+            self.roll_back(package.clone(), reason);
+            optimistic_commitments::finish(state_root, CommitmentState::Orphaned, Some(reason.to_string()));
+            orphaned += 1;
+        }
+        let mut restored = 0;
+        for (state_root, entry) in pending.iter() {
+            if !stored.iter().any(|(stored_root, _)| stored_root == state_root) {
+                self.persist_commitment(&entry.package);
+                restored += 1;
+            }
+        }
+        if orphaned > 0 || restored > 0 {
+            warn!("Reconciled the opti-queue: rolled back {} orphaned commits, stored {} missing ones again", orphaned, restored);
+        }
+    }
+
     async fn remove_commitment(&self, id: &[u8; 32]) {
         let mut commitments = self.commitments.write().await;
+        if let Some(package) = self.optimistic_commitment_state_management.get_state_record(id) {
+            self.blob_store.release_package(&package);
+            self.blob_store.commit();
+        }
         self.optimistic_commitment_state_management
             .delete_state_record(id);
         commitments.remove(id);
@@ -407,46 +1237,411 @@ impl<
         info!("Starting start_optimistic_commitment_processor");
 
         let commitments = Arc::clone(&self.commitments);
+        // Intervals rather than sleeps, a busy PDA listener would keep pushing a sleep back
+        let expiry_period = CONFIG.optimistic_timeout.max(Duration::from_secs(1));
+        let mut expiry_check = interval_at(Instant::now() + expiry_period, expiry_period);
+        let reconcile_period = CONFIG.optimistic_reconcile_interval.max(Duration::from_secs(1));
+        let mut reconcile = interval_at(Instant::now() + reconcile_period, reconcile_period);
+        let max_age = (!CONFIG.optimistic_commitment_max_age.is_zero()).then_some(CONFIG.optimistic_commitment_max_age);
 
         tokio::spawn(async move {
             loop {
                 tokio::select! {
                     Some(pda_listener_message) = pda_receiver.recv() => {
-                        // let state_root = value.
-                        // if success {
-                        //     self.remove_commitment(&id).await;
-                        // }
-                        //
-
-                            // self.verify_with_validator(s);
                         info!("Value received from PDA: {:?}", pda_listener_message);
-                        let read_guard = commitments.read().await;
-                        //TODO get key from pda account details
-                        let entry = read_guard.get(&pda_listener_message.state_root).expect("");
-                        optimistic_processor_sender.send(CommitmentProcessorMessage {processor_type: OnChain, state_root: entry.package.state_root.unwrap()}).await.expect("TODO: panic message");
-
+                        if !commitments.read().await.contains_key(&pda_listener_message.state_root) {
+                            warn!("No pending commit for PDA root {:?}, it was rolled back", pda_listener_message.state_root);
+                            continue;
+                        }
+                        let message = CommitmentProcessorMessage {
+                            processor_type: OnChain,
+                            state_root: pda_listener_message.state_root,
+                            l1_slot: pda_listener_message.slot,
+                        };
+                        if optimistic_processor_sender.send(message).await.is_err() {
+                            error!("Committer is gone, stopping the optimistic commitment processor");
+                            return;
+                        }
                     }
-                    _ = tokio::time::sleep(Duration::from_secs(CONFIG.optimistic_timeout)) => {
-                                info!("checking commit-q for old commits");
-
-                        let read_guard = commitments.read().await;
-
-                        for (key, entry) in read_guard.iter() {
-                            info!("{:?}", entry);
-                            if entry.timestamp.elapsed() < Duration::from_secs(CONFIG.optimistic_timeout) {
-                                info!("Old entry found:");
-                                    info!("  Key: {:?}", key);
-                                    info!("  Timestamp: {:?}", entry.timestamp);
-                                    info!("  Value: {:?}", entry.package);
-                                optimistic_processor_sender.send(CommitmentProcessorMessage {processor_type: TimeOut, state_root: entry.package.state_root.unwrap()}).await.expect("TODO: panic message");
+                    _ = expiry_check.tick() => {
+                        debug!("Checking the opti-queue for expired commits");
+                        let challenge_window = governance::current().challenge_window();
+                        let expired: Vec<([u8; 32], Duration)> = commitments
+                            .read()
+                            .await
+                            .iter()
+                            .map(|(state_root, entry)| (*state_root, entry.timestamp.elapsed()))
+                            .filter(|(_, elapsed)| *elapsed >= challenge_window)
+                            .collect();
+                        for (state_root, elapsed) in expired {
+                            let processor_type = if max_age.is_some_and(|max_age| elapsed >= max_age) {
+                                warn!("Pending commit {:?} is {} s old, past OPTIMISTIC_COMMITMENT_MAX_AGE, rolling it back", state_root, elapsed.as_secs());
+                                Expired
+                            } else {
+                                info!("Pending commit {:?} expired, proving it through the validator", state_root);
+                                TimeOut
+                            };
+                            let message = CommitmentProcessorMessage { processor_type, state_root, l1_slot: None };
+                            if optimistic_processor_sender.send(message).await.is_err() {
+                                error!("Committer is gone, stopping the optimistic commitment processor");
+                                return;
                             }
                         }
-                        drop(read_guard);
+                    }
+                    _ = optimistic_commitments::resolution_requested() => {
+                        for (state_root, resolution) in optimistic_commitments::take_resolutions() {
+                            info!("Resolving pending commit {:?} on an operator's request: {:?}", state_root, resolution);
+                            let processor_type = match resolution {
+                                Resolution::Prove => TimeOut,
+                                Resolution::RollBack => ForceRollBack,
+                            };
+                            let message = CommitmentProcessorMessage { processor_type, state_root, l1_slot: None };
+                            if optimistic_processor_sender.send(message).await.is_err() {
+                                error!("Committer is gone, stopping the optimistic commitment processor");
+                                return;
+                            }
+                        }
+                    }
+                    _ = reconcile.tick() => {
+                        let message = CommitmentProcessorMessage { processor_type: Reconcile, state_root: [0u8; 32], l1_slot: None };
+                        if optimistic_processor_sender.send(message).await.is_err() {
+                            error!("Committer is gone, stopping the optimistic commitment processor");
+                            return;
+                        }
                     }
                 }
             }
         });
     }
+
+    /// Finalizes a pending commitment whose root showed up on L1, or proves one that timed out
+    /// through the validator. Either may arrive for a commitment the other already settled, or
+    /// that was rolled back meanwhile, which is ignored.
+    async fn process_commitment_message(&self, message: CommitmentProcessorMessage) {
+        if let Some(package) = self.settle_commitment(message).await {
+            self.verify_with_validator(package).await;
+        }
+    }
+
+    /// `process_commitment_message` up to proving a timed out commitment, which is returned for
+    /// the caller to prove.
+    async fn settle_commitment(&self, message: CommitmentProcessorMessage) -> Option<StateCommitmentPackage<AccountState>> {
+        info!("Received from optimistic processor: {:?}", message);
+        if message.processor_type == Reconcile {
+            self.reconcile_commitments().await;
+            return None;
+        }
+        let Some(package) = self.commitments.read().await.get(&message.state_root).map(|entry| entry.package.clone()) else {
+            debug!("Pending commit {:?} is already settled", message.state_root);
+            return None;
+        };
+        self.remove_commitment(&message.state_root).await;
+        let rolled_back = match message.processor_type {
+            Expired => Some((CommitmentState::Expired, "it was pending past OPTIMISTIC_COMMITMENT_MAX_AGE")),
+            ForceRollBack => Some((CommitmentState::RolledBack, "an operator rolled it back")),
+            _ if package.parent_state_root != self.canonical_tip() => Some((CommitmentState::RolledBack, "its parent is no longer the canonical tip")),
+            _ => None,
+        };
+        if let Some((state, reason)) = rolled_back {
+            self.roll_back(package, reason);
+            optimistic_commitments::finish(&message.state_root, state, Some(reason.to_string()));
+            return None;
+        }
+        match message.processor_type {
+            OnChain => {
+                let mut tree_composite = TreeComposite::new();
+                tree_composite.add_transactions(&package.transactions);
+                tree_composite.add_states(&package.state_records);
+                let Some(account_state_root) = tree_composite.get_uncommitted_root() else {
+                    error!("Pending commit {:?} has no accounts, dropping it", message.state_root);
+                    optimistic_commitments::finish(&message.state_root, CommitmentState::RolledBack, Some("it has no accounts".to_string()));
+                    return None;
+                };
+                let (_proof_package_lite, _proof_package_prepared, proof_package) = generate_proof_load_keys(
+                    package.state_records.clone(),
+                    &package.parent_state_root.unwrap_or_default(),
+                    &merkle::transactions_root(&package.transactions),
+                    &account_state_root,
+                );
+                // The signature of the PDA update is resolved later by the L1 anchor tracker
+                let l1_anchor = L1Anchor { slot: message.l1_slot, signature: None };
+                self.finalize(&mut tree_composite, package, proof_package, account_state_root, l1_anchor).await;
+                optimistic_commitments::finish(&message.state_root, CommitmentState::Finalized, None);
+                None
+            }
+            TimeOut => {
+                optimistic_commitments::finish(&message.state_root, CommitmentState::Proved, None);
+                Some(package)
+            }
+            Expired | ForceRollBack | Reconcile => unreachable!("handled above"),
+        }
+    }
+
+    /// Commits blocks through three stages connected by bounded channels: the prover, the
+    /// submitter sending proofs to the validator, and the finalizer waiting for L1. Block N+1 is
+    /// proven and sent while block N waits for L1, and blocks are finalized in order. When a
+    /// block fails, the blocks after it extend a root that won't be committed; they are handed
+    /// back and proven again on top of the canonical tip.
+    async fn run_commit_pipeline(&self, optimistic_processor_receiver: Receiver<CommitmentProcessorMessage>) {
+        let control = PipelineControl::default();
+        let (item_sender, item_receiver) = mpsc::channel(self.pipeline_depth);
+        let (submission_sender, submission_receiver) = mpsc::channel(self.pipeline_depth);
+        tokio::join!(
+            self.prove_stage(&control, item_sender),
+            self.submit_stage(&control, item_receiver, submission_sender),
+            self.finalize_stage(&control, submission_receiver, optimistic_processor_receiver),
+        );
+    }
+
+    async fn prove_stage(&self, control: &PipelineControl, item_sender: Sender<PipelineItem>) {
+        let mut unfinalized = Unfinalized::default();
+        loop {
+            if self.pause_if_halted().await {
+                continue;
+            }
+            let capacity = control.reserve(self.pipeline_depth).await;
+            if capacity == self.pipeline_depth {
+                // Nothing in flight, whatever was proven before is finalized or handed back
+                unfinalized.clear();
+            } else {
+                unfinalized.prune(self.next_block_number());
+            }
+
+            // Handed back packages are proven one at a time, like a refused batch
+            let (package, batch) = match control.take_returned() {
+                Some(package) => (package, Vec::new()),
+                None => {
+                    let mut commitment_pool = self.commitment_pool.lock().await;
+                    let Some(package) = commitment_pool.get_next() else {
+                        drop(commitment_pool);
+                        control.release(1);
+                        sleep(POOL_POLL_INTERVAL).await;
+                        continue;
+                    };
+                    let mut batch = Vec::new();
+                    if Self::is_batchable(&package) {
+                        let limit = (CONFIG.l1_batch_max_roots as usize).min(capacity).saturating_sub(1);
+                        batch = Self::take_batch(&mut commitment_pool, limit);
+                    }
+                    (package, batch)
+                }
+            };
+
+            let item = if package.is_heartbeat() {
+                let (block_number, parent_state_root) = self.extended_tip(&unfinalized);
+                unfinalized.push(BatchBlock {
+                    block_number,
+                    tree_composite: TreeComposite::new(),
+                    account_state_root: parent_state_root.unwrap_or_default(),
+                }, vec![]);
+                PipelineItem::Heartbeat(package, block_number)
+            } else if package.optimistic && CONFIG.mode.accepts_optimistic() {
+                // Pending commitments extend the canonical tip, the blocks before have to be final
+                control.wait_until_alone().await;
+                self.pend_optimistic(package).await;
+                control.release(1);
+                continue;
+            } else {
+                control.take(batch.len());
+                let mut blocks = Vec::with_capacity(batch.len() + 1);
+                let mut entries = Vec::with_capacity(batch.len() + 1);
+                for package in std::iter::once(package).chain(batch) {
+                    let (block, entry) = self.prove_pipelined(package, &mut unfinalized).await;
+                    blocks.push(block);
+                    entries.push(entry);
+                }
+                PipelineItem::Blocks(blocks, entries)
+            };
+            if item_sender.send(item).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Number and parent root of the block after the ones in `unfinalized`, or after the
+    /// canonical tip when there are none.
+    fn extended_tip(&self, unfinalized: &Unfinalized) -> (u64, Option<[u8; 32]>) {
+        match unfinalized.blocks.last() {
+            Some(block) => (block.block_number + 1, Some(block.account_state_root)),
+            None => (self.next_block_number(), self.canonical_tip()),
+        }
+    }
+
+    /// Proves `package` as the block after the ones in `unfinalized` and adds it to them. The
+    /// proof is generated on the blocking pool, so the other stages keep going meanwhile.
+    async fn prove_pipelined(&self, package: StateCommitmentPackage<AccountState>, unfinalized: &mut Unfinalized) -> (ProvenBlock, BatchEntry) {
+        pipeline::enter(&package.transactions, Stage::Proving, &package.request_ids, None);
+        let (block_number, parent_state_root) = self.extended_tip(unfinalized);
+        let mut tree_composite = TreeComposite::new();
+        tree_composite.add_transactions(&package.transactions);
+        tree_composite.add_states(&package.state_records);
+        let account_state_root = tree_composite
+            .get_uncommitted_root()
+            .expect("Error getting account state root");
+        let state_records = package.state_records.clone();
+        let proven_parent_root = parent_state_root.unwrap_or_default();
+        let transactions_root = merkle::transactions_root(&package.transactions);
+        let (_proof_package_lite, proof_package_prepared, proof_package) =
+            tokio::task::spawn_blocking(move || generate_proof_load_keys(state_records, &proven_parent_root, &transactions_root, &account_state_root))
+                .await
+                .expect("Proof generation panicked");
+
+        let witness = package.witness.clone()
+            .map(|witness| self.complete_batch_witness(witness, parent_state_root, &unfinalized.blocks));
+        let outbox_root = outbox_root(&cross_rollup::outbox_messages(&package.state_records, |address| {
+            unfinalized
+                .latest_write(address)
+                .or_else(|| self.account_state_management.get_state_record(&address.to_bytes()))
+        }));
+        pipeline::enter(&package.transactions, Stage::AwaitingValidator, &package.request_ids, Some(account_state_root));
+
+        let batch_block = BatchBlock {
+            block_number,
+            tree_composite,
+            account_state_root,
+        };
+        unfinalized.push(batch_block.clone(), package.state_records.clone());
+        let entry = BatchEntry {
+            proof_package: proof_package_prepared,
+            witness,
+            new_state_root: account_state_root,
+            sequence: block_number,
+            outbox_root,
+        };
+        (ProvenBlock { package, batch_block, proof_package }, entry)
+    }
+
+    /// Sends proven blocks to the validator in order. Once a block failed the ones after it are
+    /// passed on without being sent.
+    async fn submit_stage(&self, control: &PipelineControl, mut item_receiver: Receiver<PipelineItem>, submission_sender: Sender<(PipelineItem, Submission)>) {
+        while let Some(item) = item_receiver.recv().await {
+            let submission = match &item {
+                PipelineItem::Blocks(blocks, entries) if !control.is_draining() => self.submit(blocks, entries).await,
+                _ => Submission::NotSent,
+            };
+            if let Submission::Failed(_) = submission {
+                control.fail_block(item.first_block_number());
+            }
+            if submission_sender.send((item, submission)).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    async fn submit(&self, blocks: &[ProvenBlock], entries: &[BatchEntry]) -> Submission {
+        let first_block_number = blocks[0].batch_block.block_number;
+        let validator_result = match entries {
+            [entry] => self.clients.validator
+                .prove(entry.proof_package, entry.witness.as_ref(), &entry.new_state_root, entry.sequence, &entry.outbox_root)
+                .await,
+            _ => {
+                info!("Committing blocks {} to {} with one L1 transaction", first_block_number, first_block_number + entries.len() as u64 - 1);
+                self.clients.validator.prove_batch(entries).await
+            }
+        };
+        match validator_result {
+            Ok(response) if response.success => {
+                info!("Successful response from validator for block {}: {:?}", first_block_number, response);
+                for block in blocks {
+                    pipeline::enter(&block.package.transactions, Stage::AwaitingL1, &block.package.request_ids, Some(block.batch_block.account_state_root));
+                }
+                Submission::Sent(response.signature)
+            }
+            Ok(response) => {
+                info!("Validator refused block {}: {:?}", first_block_number, response);
+                // The validator refused the proof, challengers of the root were right
+                Submission::Failed(ChallengeStatus::Upheld)
+            }
+            Err(error) => {
+                info!("Unsuccessful response from validator for block {}: {:?}", first_block_number, error);
+                Submission::Failed(ChallengeStatus::Void)
+            }
+        }
+    }
+
+    /// Waits for the L1 transactions of submitted blocks and finalizes the blocks in order.
+    /// Optimistic commitments are settled between blocks, once the pipeline is empty, as they
+    /// extend the canonical tip.
+    async fn finalize_stage(
+        &self,
+        control: &PipelineControl,
+        mut submission_receiver: Receiver<(PipelineItem, Submission)>,
+        mut optimistic_processor_receiver: Receiver<CommitmentProcessorMessage>,
+    ) {
+        let mut deferred = Vec::new();
+        loop {
+            tokio::select! {
+                Some(message) = optimistic_processor_receiver.recv() => {
+                    control.set_settling(true);
+                    deferred.push(message);
+                }
+                Some((item, submission)) = submission_receiver.recv() => {
+                    let block_count = item.block_count();
+                    self.finalize_pipelined(control, item, submission).await;
+                    control.release(block_count);
+                }
+                _ = control.emptied.notified(), if !deferred.is_empty() => {}
+                else => return,
+            }
+            if !deferred.is_empty() && control.is_empty() {
+                for message in std::mem::take(&mut deferred) {
+                    if let Some(mut package) = self.settle_commitment(message).await {
+                        // Its window ended, it is proved through the validator like any other block
+                        package.optimistic = false;
+                        control.hand_back(vec![package]);
+                    }
+                }
+                control.set_settling(false);
+            }
+        }
+    }
+
+    async fn finalize_pipelined(&self, control: &PipelineControl, item: PipelineItem, submission: Submission) {
+        let first_block_number = item.first_block_number();
+        if control.extends_failed_block(first_block_number) {
+            info!("Handing back block {}, it extends a failed block", first_block_number);
+            control.hand_back(item.into_packages());
+            return;
+        }
+        let blocks = match item {
+            PipelineItem::Heartbeat(..) => {
+                self.finalize_heartbeat().await;
+                return;
+            }
+            PipelineItem::Blocks(blocks, _) => blocks,
+        };
+        let challenge_status = match submission {
+            Submission::Sent(signature) => match self.confirm_on_l1(&signature).await {
+                Some(l1_anchor) => {
+                    for mut block in blocks {
+                        self.finalize(
+                            &mut block.batch_block.tree_composite,
+                            block.package,
+                            block.proof_package,
+                            block.batch_block.account_state_root,
+                            l1_anchor.clone(),
+                        )
+                        .await;
+                    }
+                    return;
+                }
+                None => ChallengeStatus::Void,
+            },
+            Submission::Failed(challenge_status) => challenge_status,
+            Submission::NotSent => unreachable!("blocks are only held back after a failed block"),
+        };
+
+        control.fail_block(first_block_number);
+        if blocks.len() > 1 {
+            warn!("Committing the {} blocks of the batch one at a time", blocks.len());
+            control.hand_back(blocks.into_iter().map(|block| block.package).collect());
+            return;
+        }
+        for block in blocks {
+            pipeline::leave(&block.package.transactions);
+            challenges::resolve(&block.batch_block.account_state_root, challenge_status);
+        }
+    }
 }
 
 impl<
@@ -455,76 +1650,52 @@ impl<
         B: ManageState<Record = Block>,
         T: ManageState<Record = TrollupTransaction>,
         O: ManageState<Record = StateCommitmentPackage<AccountState>> + Send + Sync + 'static,
-    > StateCommitter<AccountState> for StateCommitment<'a, A, B, T, O>
+        L: ManageState<Record = Blob>,
+        R: ManageState<Record = BlobReferences>,
+    > StateCommitter<AccountState> for StateCommitment<'a, A, B, T, O, L, R>
 {
     async fn start(&mut self) {
         let (pda_sender, pda_receiver) = mpsc::channel(100);
         let (optimistic_processor_sender, mut optimistic_processor_receiver) =
             mpsc::channel::<CommitmentProcessorMessage>(100);
 
-        self.start_optimistic_commitment_processor(pda_receiver, optimistic_processor_sender)
-            .await;
+        // Without optimistic commitments nothing feeds the opti-queue, so neither the PDA listener
+        // nor the timeout processor is needed. The sender is kept either way so the receiver below
+        // doesn't report a closed channel on every iteration.
+        if CONFIG.mode.accepts_optimistic() {
+            self.start_optimistic_commitment_processor(pda_receiver, optimistic_processor_sender.clone())
+                .await;
+        }
 
         self.committer_state = CommitterState::Running;
+        commit_journal::recover(commit_journal::journal(), self.account_state_management, self.block_state_management, self.transaction_state_management, message_bus::outbox());
+        // Commitments pending before a restart are only left in the store
+        self.reconcile_commitments().await;
+        let finalized_block_number = self
+            .block_state_management
+            .get_latest_block_id()
+            .and_then(|id| self.block_state_management.get_state_record(&id))
+            .map(|block| block.block_number)
+            .unwrap_or_default();
+        rollup_status::set_finalized_block_number(finalized_block_number);
+        rollup_status::set_paused(false);
         setup(true);
         info!("StateCommitter started.");
-        self.start_pda_listener(pda_sender).await;
-        let commitments = Arc::clone(&self.commitments);
+        if CONFIG.mode.accepts_optimistic() {
+            self.start_pda_listener(pda_sender).await;
+        }
+        if self.pipeline_depth > 1 {
+            info!("StateCommitter running in {} mode, pipelining up to {} blocks.", CONFIG.mode, self.pipeline_depth);
+            self.run_commit_pipeline(optimistic_processor_receiver).await;
+            return;
+        }
+        info!("StateCommitter running in {} mode.", CONFIG.mode);
         loop {
             if self.committer_state == CommitterState::Stopped {
                 info!("StateCommitter stopped.");
                 break;
             } else {
-                tokio::select! {
-
-                    result = optimistic_processor_receiver.recv() => {
-                        match result {
-                            Some(commitment_processor_message) => {
-                                info!("Received from optimistic processor: {:?}", commitment_processor_message);
-                                    match commitment_processor_message.processor_type {
-
-                                    //TODO clean this up
-                                        OnChain => {
-                                            let mut read_guard = commitments.read().await;
-                                            //TODO get key from pda account details
-                                            let entry = read_guard.get(&commitment_processor_message.state_root).expect("");
-                                            let mut tree_composite = TreeComposite::new();
-                                            tree_composite.add_transactions(&entry.package.transactions);
-
-                                            let account_states = &entry.package.state_records;
-
-                                            tree_composite.add_states(account_states);
-                                            let (_proof_package_lite, _proof_package_prepared, proof_package) =
-                                                generate_proof_load_keys(account_states.clone());
-
-                                            let account_state_root = tree_composite
-                                                .get_uncommitted_root()
-                                                .expect("Error getting account state root");
-                                            self.finalize(&mut tree_composite, entry.package.clone(), proof_package, account_state_root).await;
-                                            self.remove_commitment(&commitment_processor_message.state_root).await;
-                                        }
-                                        TimeOut => {
-                                            let mut read_guard = commitments.read().await;
-                                            //TODO get key from pda account details
-                                            let entry = read_guard.get(&commitment_processor_message.state_root).expect("");
-                                            self.verify_with_validator(entry.package.clone()).await;
-                                            self.remove_commitment(&commitment_processor_message.state_root).await;
-                                        }
-                                    }
-
-                            }
-                            None => {
-                                // info!("Optimistic processor channel closed");
-                                // Handle the channel being closed if necessary
-                                // break;
-                            }
-                        }
-                    }
-
-                    _ = self.read_from_pool() => {
-                        // read_from_pool completed, you can add any post-processing here if needed
-                    }
-                }
+                self.commit_serially(&mut optimistic_processor_receiver).await;
             }
         }
     }
@@ -532,20 +1703,42 @@ impl<
     async fn stop(&mut self) {
         info!("Stopping StateCommitter");
         self.committer_state = CommitterState::Stopped;
+        rollup_status::set_paused(true);
     }
 }
 
+/// Notifies the committer of state roots committed to the proof verifier's state PDA.
+#[async_trait]
+pub trait PdaSource: Send + Sync {
+    /// Sends every committed root to `pda_sender`, for as long as the committer runs.
+    async fn listen(&self, pda_sender: Sender<PdaListenerMessage>);
+}
+
+/// Subscribes to the state PDA over the L1 RPC websocket.
 pub struct PdaListener {
     program_pubkey: Pubkey,
 }
 
+#[async_trait]
+impl PdaSource for PdaListener {
+    async fn listen(&self, pda_sender: Sender<PdaListenerMessage>) {
+        if self.program_pubkey == Pubkey::default() {
+            error!("PROOF_VERIFIER_PROGRAM_ID is missing or invalid, not listening for state PDA updates");
+            return;
+        }
+        if let Err(e) = self.start(pda_sender).await {
+            error!("PDA listener error: {:?}", e);
+        }
+    }
+}
+
 impl PdaListener {
     pub fn new(program_pubkey: Pubkey) -> Self {
         PdaListener { program_pubkey }
     }
 
     pub async fn start(
-        &mut self,
+        &self,
         pda_sender: Sender<PdaListenerMessage>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let mut retry_interval = Duration::from_secs(1);
@@ -617,6 +1810,7 @@ impl PdaListener {
                                                         info!("Decoded account data: {:?}", decoded);
                                                         let pda_listener_message = PdaListenerMessage {
                                                             state_root: <[u8; 32]>::try_from(decoded).unwrap(),
+                                                            slot: result.get("context").and_then(|context| context.get("slot")).and_then(Value::as_u64),
                                                         };
                                                         if let Err(e) = pda_sender.send(pda_listener_message).await {
                                                             error!("Failed to send PDA message: {:?}", e);
@@ -661,3 +1855,567 @@ impl PdaListener {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validator_client::ApiResponse;
+    use solana_sdk::signature::{write_keypair_file, Signer};
+    use state::test_fixtures::{keypair, transfer_transaction};
+    use state::transaction::convert_to_trollup_transaction;
+    use state_management::sled_state_management::SledStateManagement;
+    use std::sync::Once;
+    use trollup_zk::fixtures::accounts_fixture;
+
+    static CONFIGURE: Once = Once::new();
+
+    lazy_static! {
+        /// The committer writes to process wide indexes, so tests run one at a time
+        static ref SERIAL: Mutex<()> = Mutex::new(());
+    }
+
+    /// Keypairs for the configuration and proving keys in a scratch directory, which becomes the
+    /// working directory the keys are loaded from.
+    fn configure() {
+        CONFIGURE.call_once(|| {
+            let directory = std::env::temp_dir().join(format!("trollup-state-commitment-{}", std::process::id()));
+            std::fs::create_dir_all(&directory).unwrap();
+            let keypair_path = directory.join("keypair.json");
+            write_keypair_file(&keypair("sequencer"), &keypair_path).unwrap();
+            std::env::set_var("TROLLUP_API_KEYPAIR_PATH", &keypair_path);
+            std::env::set_var("TROLLUP_VALIDATOR_KEYPAIR_PATH", &keypair_path);
+            std::env::set_var("OPTIMISTIC_TIMEOUT", "1");
+            std::env::set_current_dir(&directory).unwrap();
+            setup(true);
+        });
+    }
+
+    #[derive(Clone, Copy)]
+    enum Answer {
+        Accept,
+        Refuse,
+        Unreachable,
+    }
+
+    struct MockValidator {
+        answer: Answer,
+        batch_answer: Answer,
+        /// Single block proofs refused before answering with `answer`
+        refusals: std::sync::atomic::AtomicUsize,
+        /// Sequences of the single block proofs, in order
+        sequences: std::sync::Mutex<Vec<u64>>,
+        batch_sizes: std::sync::Mutex<Vec<usize>>,
+    }
+
+    impl MockValidator {
+        fn new(answer: Answer, batch_answer: Answer) -> Arc<Self> {
+            Arc::new(MockValidator {
+                answer,
+                batch_answer,
+                refusals: Default::default(),
+                sequences: Default::default(),
+                batch_sizes: Default::default(),
+            })
+        }
+
+        fn respond(answer: Answer) -> anyhow::Result<ApiResponse> {
+            match answer {
+                Answer::Accept => Ok(ApiResponse { success: true, signature: Signature::new_unique() }),
+                Answer::Refuse => Ok(ApiResponse { success: false, signature: Signature::default() }),
+                Answer::Unreachable => Err(anyhow::anyhow!("connection refused")),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Validator for MockValidator {
+        async fn prove(&self, _proof_package: trollup_zk::prove::ProofPackagePrepared, _witness: Option<&WitnessBundle>, _new_state_root: &[u8; 32], sequence: u64, _outbox_root: &[u8; 32]) -> anyhow::Result<ApiResponse> {
+            self.sequences.lock().unwrap().push(sequence);
+            let refuse = self.refusals
+                .fetch_update(std::sync::atomic::Ordering::SeqCst, std::sync::atomic::Ordering::SeqCst, |refusals| refusals.checked_sub(1))
+                .is_ok();
+            Self::respond(if refuse { Answer::Refuse } else { self.answer })
+        }
+
+        async fn prove_batch(&self, entries: &[BatchEntry]) -> anyhow::Result<ApiResponse> {
+            self.batch_sizes.lock().unwrap().push(entries.len());
+            Self::respond(self.batch_answer)
+        }
+    }
+
+    enum Confirmation {
+        Confirmed(u64),
+        /// Confirmed once the test adds a permit to the semaphore
+        Gated(Arc<tokio::sync::Semaphore>, u64),
+        Failed,
+        RpcError,
+        Never,
+    }
+
+    struct MockConfirmer(Confirmation);
+
+    #[async_trait]
+    impl L1Confirmer for MockConfirmer {
+        async fn confirm(&self, signature: &Signature) -> anyhow::Result<Option<L1Anchor>> {
+            match self.0 {
+                Confirmation::Confirmed(slot) => Ok(Some(L1Anchor { slot: Some(slot), signature: Some(signature.to_string()) })),
+                Confirmation::Gated(ref gate, slot) => {
+                    gate.acquire().await.unwrap().forget();
+                    Ok(Some(L1Anchor { slot: Some(slot), signature: Some(signature.to_string()) }))
+                }
+                Confirmation::Failed => Ok(None),
+                Confirmation::RpcError => Err(anyhow::anyhow!("rpc unavailable")),
+                Confirmation::Never => std::future::pending().await,
+            }
+        }
+    }
+
+    /// Sends its notifications once, then stays connected without further updates.
+    struct MockPdaSource(std::sync::Mutex<Vec<PdaListenerMessage>>);
+
+    #[async_trait]
+    impl PdaSource for MockPdaSource {
+        async fn listen(&self, pda_sender: Sender<PdaListenerMessage>) {
+            let notifications = std::mem::take(&mut *self.0.lock().unwrap());
+            for notification in notifications {
+                pda_sender.send(notification).await.unwrap();
+            }
+            std::future::pending::<()>().await;
+        }
+    }
+
+    fn clients(validator: Arc<MockValidator>, confirmation: Confirmation) -> CommitmentClients {
+        CommitmentClients {
+            validator,
+            l1_confirmer: Arc::new(MockConfirmer(confirmation)),
+            pda_source: Arc::new(MockPdaSource(Default::default())),
+            l1_confirmation_timeout: Duration::from_millis(200),
+        }
+    }
+
+    struct Fixture {
+        accounts: StateManager<SledStateManagement<AccountState>>,
+        blocks: StateManager<SledStateManagement<Block>>,
+        transactions: StateManager<SledStateManagement<TrollupTransaction>>,
+        optimistic: Arc<StateManager<SledStateManagement<StateCommitmentPackage<AccountState>>>>,
+        blob_store: Arc<BlobStore<SledStateManagement<Blob>, SledStateManagement<BlobReferences>>>,
+        pool: Arc<Mutex<StateCommitmentPool<AccountState>>>,
+        requeue_sender: mpsc::UnboundedSender<Vec<TrollupTransaction>>,
+        requeue_receiver: mpsc::UnboundedReceiver<Vec<TrollupTransaction>>,
+    }
+
+    type Committer<'a> = StateCommitment<
+        'a,
+        SledStateManagement<AccountState>,
+        SledStateManagement<Block>,
+        SledStateManagement<TrollupTransaction>,
+        SledStateManagement<StateCommitmentPackage<AccountState>>,
+        SledStateManagement<Blob>,
+        SledStateManagement<BlobReferences>,
+    >;
+
+    impl Fixture {
+        fn new() -> Self {
+            configure();
+            let (requeue_sender, requeue_receiver) = mpsc::unbounded_channel();
+            Fixture {
+                accounts: StateManager::new(""),
+                blocks: StateManager::new(""),
+                transactions: StateManager::new(""),
+                optimistic: Arc::new(StateManager::new("")),
+                blob_store: Arc::new(BlobStore::new(Arc::new(StateManager::new("")), Arc::new(StateManager::new("")))),
+                pool: Arc::new(Mutex::new(StateCommitmentPool::new())),
+                requeue_sender,
+                requeue_receiver,
+            }
+        }
+
+        fn committer(&self, clients: CommitmentClients) -> Committer<'_> {
+            StateCommitment::new(
+                &self.accounts,
+                Arc::clone(&self.pool),
+                &self.blocks,
+                &self.transactions,
+                Arc::clone(&self.optimistic),
+                Arc::clone(&self.blob_store),
+                self.requeue_sender.clone(),
+            )
+            .with_clients(clients)
+        }
+
+        fn latest_block(&self) -> Option<Block> {
+            self.blocks
+                .get_latest_block_id()
+                .and_then(|id| self.blocks.get_state_record(&id))
+        }
+    }
+
+    /// A batch of two accounts and a transfer, distinct for every `seed`.
+    fn package(seed: u64, optimistic: bool) -> StateCommitmentPackage<AccountState> {
+        let accounts: Vec<AccountState> = accounts_fixture(2)
+            .into_iter()
+            .map(|account| AccountState { lamports: account.lamports + seed, ..account })
+            .collect();
+        let transfer = transfer_transaction(&keypair("payer"), &Pubkey::new_unique(), seed + 1, Hash::default());
+        let transaction = convert_to_trollup_transaction(transfer).unwrap();
+        StateCommitmentPackage::new(optimistic, accounts, vec![transaction], vec![[seed as u8; 32]])
+    }
+
+    /// Queues an optimistic package and returns the root it is pending under.
+    async fn pend(committer: &mut Committer<'_>, fixture: &Fixture, seed: u64) -> [u8; 32] {
+        fixture.pool.lock().await.add(package(seed, true));
+        committer.read_from_pool().await;
+        let pending: Vec<[u8; 32]> = committer.commitments.read().await.keys().copied().collect();
+        assert_eq!(pending.len(), 1);
+        pending[0]
+    }
+
+    /// Runs the commit pipeline until block `block_number` is finalized.
+    async fn run_pipeline_until(committer: &Committer<'_>, fixture: &Fixture, block_number: u64) {
+        let (_processor_sender, processor_receiver) = mpsc::channel(10);
+        let finalized = async {
+            while fixture.latest_block().map(|block| block.block_number) < Some(block_number) {
+                sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::select! {
+            _ = committer.run_commit_pipeline(processor_receiver) => unreachable!("the pipeline runs until the committer stops"),
+            finalized = timeout(Duration::from_secs(60), finalized) => finalized.expect("blocks are finalized in time"),
+        }
+    }
+
+    fn on_chain(state_root: [u8; 32], slot: u64) -> CommitmentProcessorMessage {
+        CommitmentProcessorMessage { state_root, processor_type: OnChain, l1_slot: Some(slot) }
+    }
+
+    fn timed_out(state_root: [u8; 32]) -> CommitmentProcessorMessage {
+        CommitmentProcessorMessage { state_root, processor_type: TimeOut, l1_slot: None }
+    }
+
+    #[tokio::test]
+    async fn commits_a_block_the_validator_accepts_once_confirmed() {
+        let _serial = SERIAL.lock().await;
+        let fixture = Fixture::new();
+        let validator = MockValidator::new(Answer::Accept, Answer::Accept);
+        let mut committer = fixture.committer(clients(validator.clone(), Confirmation::Confirmed(42)));
+        let package = package(1, false);
+        let address = package.state_records[0].address;
+
+        fixture.pool.lock().await.add(package);
+        committer.read_from_pool().await;
+
+        let block = fixture.latest_block().expect("block is finalized");
+        assert_eq!(block.block_number, 1);
+        assert_eq!(block.l1_anchor.slot, Some(42));
+        assert!(block.l1_anchor.signature.is_some());
+        assert_eq!(*block.producer(), rollup_status::producer(&CONFIG));
+        assert_eq!(block.producer().sequencer, keypair("sequencer").pubkey().to_bytes());
+        assert!(fixture.accounts.get_state_record(&address.to_bytes()).is_some());
+        assert_eq!(*validator.sequences.lock().unwrap(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn opti_queue_messages_wait_for_the_pool_package() {
+        let _serial = SERIAL.lock().await;
+        let fixture = Fixture::new();
+        let validator = MockValidator::new(Answer::Accept, Answer::Accept);
+        let mut committer = fixture.committer(clients(validator.clone(), Confirmation::Confirmed(42)));
+        let (processor_sender, mut processor_receiver) = mpsc::channel(10);
+        fixture.pool.lock().await.add(package(3, false));
+        processor_sender
+            .send(CommitmentProcessorMessage { processor_type: Reconcile, state_root: [0u8; 32], l1_slot: None })
+            .await
+            .unwrap();
+
+        committer.commit_serially(&mut processor_receiver).await;
+
+        assert!(processor_receiver.try_recv().is_err());
+        assert_eq!(fixture.latest_block().expect("block is finalized").block_number, 1);
+        assert!(fixture.pool.lock().await.peek_next().is_none());
+    }
+
+    #[tokio::test]
+    async fn validator_rejection_finalizes_nothing() {
+        let _serial = SERIAL.lock().await;
+        for answer in [Answer::Refuse, Answer::Unreachable] {
+            let fixture = Fixture::new();
+            let validator = MockValidator::new(answer, answer);
+            let committer = fixture.committer(clients(validator.clone(), Confirmation::Confirmed(42)));
+            let package = package(2, false);
+            let address = package.state_records[0].address;
+
+            committer.verify_with_validator(package).await;
+
+            assert!(fixture.latest_block().is_none());
+            assert!(fixture.accounts.get_state_record(&address.to_bytes()).is_none());
+            assert_eq!(validator.sequences.lock().unwrap().len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn l1_timeout_voids_the_commitment() {
+        let _serial = SERIAL.lock().await;
+        let fixture = Fixture::new();
+        let committer = fixture.committer(clients(MockValidator::new(Answer::Accept, Answer::Accept), Confirmation::Never));
+
+        let started = Instant::now();
+        committer.verify_with_validator(package(3, false)).await;
+
+        assert!(started.elapsed() < Duration::from_secs(30));
+        assert!(fixture.latest_block().is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_or_unknown_l1_transactions_finalize_nothing() {
+        let _serial = SERIAL.lock().await;
+        for confirmation in [Confirmation::Failed, Confirmation::RpcError] {
+            let fixture = Fixture::new();
+            let committer = fixture.committer(clients(MockValidator::new(Answer::Accept, Answer::Accept), confirmation));
+
+            committer.verify_with_validator(package(4, false)).await;
+
+            assert!(fixture.latest_block().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn refused_batch_is_committed_block_by_block() {
+        let _serial = SERIAL.lock().await;
+        let fixture = Fixture::new();
+        let validator = MockValidator::new(Answer::Accept, Answer::Refuse);
+        let committer = fixture.committer(clients(validator.clone(), Confirmation::Confirmed(7)));
+
+        committer.verify_batch_with_validator(vec![package(5, false), package(6, false)]).await;
+
+        assert_eq!(fixture.latest_block().map(|block| block.block_number), Some(2));
+        assert_eq!(*validator.batch_sizes.lock().unwrap(), vec![2]);
+        assert_eq!(*validator.sequences.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn accepted_batch_anchors_every_block_to_one_transaction() {
+        let _serial = SERIAL.lock().await;
+        let fixture = Fixture::new();
+        let validator = MockValidator::new(Answer::Refuse, Answer::Accept);
+        let committer = fixture.committer(clients(validator.clone(), Confirmation::Confirmed(8)));
+
+        committer.verify_batch_with_validator(vec![package(7, false), package(8, false)]).await;
+
+        let second = fixture.latest_block().expect("batch is finalized");
+        let first = fixture.blocks.get_state_record(&Block::get_id(1)).expect("first block is finalized");
+        assert_eq!(second.block_number, 2);
+        assert_eq!(first.l1_anchor, second.l1_anchor);
+        assert!(validator.sequences.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn pda_notification_finalizes_the_pending_commitment() {
+        let _serial = SERIAL.lock().await;
+        let fixture = Fixture::new();
+        let validator = MockValidator::new(Answer::Accept, Answer::Accept);
+        let mut committer = fixture.committer(clients(validator.clone(), Confirmation::Never));
+        let state_root = pend(&mut committer, &fixture, 9).await;
+        assert!(fixture.optimistic.get_state_record(&state_root).is_some());
+
+        committer.process_commitment_message(on_chain(state_root, 11)).await;
+
+        let block = fixture.latest_block().expect("block is finalized");
+        assert_eq!(*block.accounts_merkle_root, state_root);
+        assert_eq!(block.l1_anchor, L1Anchor { slot: Some(11), signature: None });
+        assert!(committer.commitments.read().await.is_empty());
+        assert!(fixture.optimistic.get_state_record(&state_root).is_none());
+        assert!(validator.sequences.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn pda_notification_after_a_timeout_is_ignored() {
+        let _serial = SERIAL.lock().await;
+        let fixture = Fixture::new();
+        let validator = MockValidator::new(Answer::Accept, Answer::Accept);
+        let mut committer = fixture.committer(clients(validator.clone(), Confirmation::Confirmed(12)));
+        let state_root = pend(&mut committer, &fixture, 10).await;
+
+        committer.process_commitment_message(timed_out(state_root)).await;
+        committer.process_commitment_message(on_chain(state_root, 13)).await;
+
+        let block = fixture.latest_block().expect("block is finalized");
+        assert_eq!(block.block_number, 1);
+        assert_eq!(block.l1_anchor.slot, Some(12));
+        assert_eq!(*validator.sequences.lock().unwrap(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn timeout_after_a_pda_notification_is_ignored() {
+        let _serial = SERIAL.lock().await;
+        let fixture = Fixture::new();
+        let validator = MockValidator::new(Answer::Accept, Answer::Accept);
+        let mut committer = fixture.committer(clients(validator.clone(), Confirmation::Confirmed(14)));
+        let state_root = pend(&mut committer, &fixture, 11).await;
+
+        committer.process_commitment_message(on_chain(state_root, 15)).await;
+        committer.process_commitment_message(timed_out(state_root)).await;
+
+        let block = fixture.latest_block().expect("block is finalized");
+        assert_eq!(block.block_number, 1);
+        assert_eq!(block.l1_anchor.slot, Some(15));
+        assert!(validator.sequences.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn pda_notification_on_a_stale_parent_rolls_back() {
+        let _serial = SERIAL.lock().await;
+        let mut fixture = Fixture::new();
+        let validator = MockValidator::new(Answer::Accept, Answer::Accept);
+        let mut committer = fixture.committer(clients(validator, Confirmation::Confirmed(16)));
+        let state_root = pend(&mut committer, &fixture, 12).await;
+        // A proved block lands on the parent first
+        committer.verify_with_validator(package(13, false)).await;
+
+        committer.process_commitment_message(on_chain(state_root, 17)).await;
+
+        assert_eq!(fixture.latest_block().map(|block| block.block_number), Some(1));
+        assert!(committer.commitments.read().await.is_empty());
+        drop(committer);
+        assert_eq!(fixture.requeue_receiver.recv().await.map(|transactions| transactions.len()), Some(1));
+    }
+
+    #[tokio::test]
+    async fn reconciliation_rolls_back_orphaned_commitments() {
+        let _serial = SERIAL.lock().await;
+        let mut fixture = Fixture::new();
+        let mut committer = fixture.committer(clients(MockValidator::new(Answer::Accept, Answer::Accept), Confirmation::Never));
+        let orphaned = pend(&mut committer, &fixture, 40).await;
+        // Pending before a restart, only the stored copy is left
+        committer.commitments.write().await.clear();
+        let unstored = pend(&mut committer, &fixture, 41).await;
+        fixture.optimistic.delete_state_record(&unstored);
+
+        committer.process_commitment_message(CommitmentProcessorMessage { state_root: [0u8; 32], processor_type: Reconcile, l1_slot: None }).await;
+
+        assert!(fixture.optimistic.get_state_record(&orphaned).is_none());
+        assert!(fixture.optimistic.get_state_record(&unstored).is_some());
+        assert_eq!(optimistic_commitments::status(&orphaned).map(|record| record.state), Some(CommitmentState::Orphaned));
+        assert_eq!(optimistic_commitments::status(&unstored).map(|record| record.state), Some(CommitmentState::Pending));
+        drop(committer);
+        assert_eq!(fixture.requeue_receiver.recv().await.map(|transactions| transactions.len()), Some(1));
+    }
+
+    #[tokio::test]
+    async fn operators_force_roll_back_pending_commitments() {
+        let _serial = SERIAL.lock().await;
+        let mut fixture = Fixture::new();
+        let mut committer = fixture.committer(clients(MockValidator::new(Answer::Accept, Answer::Accept), Confirmation::Never));
+        let state_root = pend(&mut committer, &fixture, 42).await;
+
+        let (_pda_sender, pda_receiver) = mpsc::channel(10);
+        let (processor_sender, mut processor_receiver) = mpsc::channel(10);
+        committer.start_optimistic_commitment_processor(pda_receiver, processor_sender).await;
+        optimistic_commitments::request_resolution(&state_root, Resolution::RollBack).unwrap();
+
+        let message = timeout(Duration::from_secs(5), processor_receiver.recv()).await.unwrap().unwrap();
+        assert_eq!((&message.processor_type, message.state_root), (&ForceRollBack, state_root));
+        committer.process_commitment_message(message).await;
+
+        assert!(committer.commitments.read().await.is_empty());
+        assert!(fixture.optimistic.get_state_record(&state_root).is_none());
+        assert_eq!(optimistic_commitments::status(&state_root).map(|record| record.state), Some(CommitmentState::RolledBack));
+        drop(committer);
+        assert_eq!(fixture.requeue_receiver.recv().await.map(|transactions| transactions.len()), Some(1));
+    }
+
+    #[tokio::test]
+    async fn processor_forwards_notifications_of_pending_roots_only() {
+        let _serial = SERIAL.lock().await;
+        let fixture = Fixture::new();
+        let mut clients = clients(MockValidator::new(Answer::Accept, Answer::Accept), Confirmation::Never);
+        let mut committer = fixture.committer(clients.clone());
+        let state_root = pend(&mut committer, &fixture, 14).await;
+        clients.pda_source = Arc::new(MockPdaSource(std::sync::Mutex::new(vec![
+            PdaListenerMessage { state_root: [0xee; 32], slot: Some(20) },
+            PdaListenerMessage { state_root, slot: Some(21) },
+        ])));
+        let committer = committer.with_clients(clients);
+
+        let (pda_sender, pda_receiver) = mpsc::channel(10);
+        let (processor_sender, mut processor_receiver) = mpsc::channel(10);
+        committer.start_optimistic_commitment_processor(pda_receiver, processor_sender).await;
+        committer.start_pda_listener(pda_sender).await;
+
+        let message = timeout(Duration::from_secs(5), processor_receiver.recv()).await.unwrap().unwrap();
+        assert_eq!(message.processor_type, OnChain);
+        assert_eq!(message.state_root, state_root);
+        assert_eq!(message.l1_slot, Some(21));
+    }
+
+    #[tokio::test]
+    async fn processor_hands_back_expired_commitments() {
+        let _serial = SERIAL.lock().await;
+        let fixture = Fixture::new();
+        let mut committer = fixture.committer(clients(MockValidator::new(Answer::Accept, Answer::Accept), Confirmation::Never));
+        let state_root = pend(&mut committer, &fixture, 15).await;
+
+        let (_pda_sender, pda_receiver) = mpsc::channel(10);
+        let (processor_sender, mut processor_receiver) = mpsc::channel(10);
+        committer.start_optimistic_commitment_processor(pda_receiver, processor_sender).await;
+
+        let message = timeout(Duration::from_secs(5), processor_receiver.recv()).await.unwrap().unwrap();
+        assert_eq!(message.processor_type, TimeOut);
+        assert_eq!(message.state_root, state_root);
+    }
+
+    #[tokio::test]
+    async fn pipeline_sends_later_blocks_while_the_first_awaits_l1() {
+        let _serial = SERIAL.lock().await;
+        let fixture = Fixture::new();
+        let validator = MockValidator::new(Answer::Accept, Answer::Accept);
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let mut clients = clients(validator.clone(), Confirmation::Gated(Arc::clone(&gate), 30));
+        clients.l1_confirmation_timeout = Duration::from_secs(60);
+        let committer = fixture.committer(clients).with_pipeline_depth(3);
+        for seed in 20..23 {
+            fixture.pool.lock().await.add(package(seed, false));
+        }
+
+        let overlapped = async {
+            while validator.sequences.lock().unwrap().len() < 3 {
+                sleep(Duration::from_millis(10)).await;
+            }
+            // Every block was proven and sent with none confirmed on L1
+            assert!(fixture.latest_block().is_none());
+            gate.add_permits(3);
+            std::future::pending::<()>().await;
+        };
+        tokio::select! {
+            _ = overlapped => unreachable!(),
+            _ = run_pipeline_until(&committer, &fixture, 3) => {}
+        }
+
+        assert_eq!(*validator.sequences.lock().unwrap(), vec![1, 2, 3]);
+        for (block_number, seed) in (1..=3).zip(20u8..) {
+            let block = fixture.blocks.get_state_record(&Block::get_id(block_number)).expect("block is finalized");
+            assert_eq!(block.transactions, vec![[seed; 32]]);
+            assert_eq!(block.l1_anchor.slot, Some(30));
+        }
+    }
+
+    #[tokio::test]
+    async fn pipeline_proves_blocks_after_a_refused_one_again() {
+        let _serial = SERIAL.lock().await;
+        let fixture = Fixture::new();
+        let validator = MockValidator::new(Answer::Accept, Answer::Accept);
+        validator.refusals.store(1, std::sync::atomic::Ordering::SeqCst);
+        let committer = fixture.committer(clients(validator.clone(), Confirmation::Confirmed(31))).with_pipeline_depth(3);
+        for seed in 24..27 {
+            fixture.pool.lock().await.add(package(seed, false));
+        }
+
+        run_pipeline_until(&committer, &fixture, 2).await;
+
+        // Block 1 was refused, the blocks proven on top of it took its place
+        assert_eq!(*validator.sequences.lock().unwrap(), vec![1, 1, 2]);
+        let first = fixture.blocks.get_state_record(&Block::get_id(1)).expect("block is finalized");
+        let second = fixture.blocks.get_state_record(&Block::get_id(2)).expect("block is finalized");
+        assert_eq!(first.transactions, vec![[25u8; 32]]);
+        assert_eq!(second.transactions, vec![[26u8; 32]]);
+    }
+}
@@ -6,6 +6,7 @@ pub trait StatePool {
     fn new() -> Self;
     fn add(&mut self, package: StateCommitmentPackage<Self::Record>);
     fn get_next(&mut self) -> Option<StateCommitmentPackage<Self::Record>>;
+    fn peek_next(&self) -> Option<&StateCommitmentPackage<Self::Record>>;
     fn pool_size(&self) -> usize;
     fn get_next_chunk(&mut self, chunk: u32) -> Vec<StateCommitmentPackage<Self::Record>>;
 }
@@ -30,6 +31,10 @@ impl<S: StateRecord> StatePool for StateCommitmentPool<S> {
         self.pool.pop_front()
     }
 
+    fn peek_next(&self) -> Option<&StateCommitmentPackage<S>> {
+        self.pool.front()
+    }
+
     fn pool_size(&self) -> usize {
         self.pool.len()
     }
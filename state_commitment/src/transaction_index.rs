@@ -0,0 +1,53 @@
+use lazy_static::lazy_static;
+use state::block::Block;
+use state::config::TrollupConfig;
+use state::transaction_status::TransactionLocation;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::StateManager;
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref INDEX: StateManager<SledStateManagement<TransactionLocation>> = StateManager::new(&CONFIG.transaction_index_db_path);
+}
+
+/// Indexes the transactions of a finalized block by their key. Called by the committer for every
+/// block and by the reindex job for existing ones.
+pub fn record_block(block: &Block) {
+    let locations: Vec<TransactionLocation> = block.transactions
+        .iter()
+        .enumerate()
+        .map(|(index, transaction_id)| TransactionLocation {
+            transaction_id: *transaction_id,
+            block_number: block.block_number,
+            index: index as u32,
+        })
+        .collect();
+    INDEX.set_state_records(&locations);
+}
+
+pub fn commit() {
+    INDEX.commit();
+}
+
+/// Where the transaction with key `transaction_id` was finalized, `None` when it isn't indexed.
+pub fn locate(transaction_id: &[u8; 32]) -> Option<TransactionLocation> {
+    INDEX.get_state_record(transaction_id)
+}
+
+/// Removes the transactions of `block`, a block rolled back to L1, unless a later block
+/// finalized them again.
+pub fn remove_block(block: &Block) {
+    for transaction_id in &block.transactions {
+        if locate(transaction_id).is_some_and(|location| location.block_number == block.block_number) {
+            INDEX.delete_state_record(transaction_id);
+        }
+    }
+}
+
+/// Removes every entry, before the index is rebuilt from scratch.
+pub fn clear() {
+    for (key, _) in INDEX.get_all_entries() {
+        INDEX.delete_state_record(&key);
+    }
+    INDEX.commit();
+}
@@ -1,9 +1,14 @@
+use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
 use trollup_zk::prove::ProofPackagePrepared;
 use base64::{Engine as _, engine::general_purpose};
 use solana_sdk::signature::Signature;
+use state::witness::WitnessBundle;
+use crate::http_client::{json_body, shared_client};
+use solana_sdk::pubkey::Pubkey;
+use trollup_commitment_message::BlockProducer;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiResponse {
@@ -11,16 +16,66 @@ pub struct ApiResponse {
     pub signature: Signature
 }
 
+/// Body of a prove request, the proof package with the witness bundle of the batch as base64
+/// encoded Borsh, the block's outbox root and the block's producer.
+#[derive(Serialize)]
+struct ProveRequest<'a> {
+    #[serde(flatten)]
+    proof_package: &'a ProofPackagePrepared,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    witness: Option<String>,
+    /// Base64 encoded outbox root of the block
+    outbox_root: String,
+    /// Base58 key of the sequencer
+    sequencer: String,
+    /// `major.minor.patch` version of the sequencer's node
+    node_version: String,
+}
+
+#[derive(Serialize)]
+struct ProveBatchEntry<'a> {
+    #[serde(flatten)]
+    request: ProveRequest<'a>,
+    /// URL safe base64 encoded state root
+    new_state_root: String,
+    sequence: u64,
+}
+
+#[derive(Serialize)]
+struct ProveBatchRequest<'a> {
+    entries: Vec<ProveBatchEntry<'a>>,
+}
+
+/// One block of a `prove_batch` request.
+pub struct BatchEntry {
+    pub proof_package: ProofPackagePrepared,
+    pub witness: Option<WitnessBundle>,
+    pub new_state_root: [u8; 32],
+    pub sequence: u64,
+    pub outbox_root: [u8; 32],
+}
+
+/// The validator the committer sends proofs to. It verifies them and commits the roots it
+/// accepts on L1, answering with the signature of the commitment transaction.
+#[async_trait]
+pub trait Validator: Send + Sync {
+    async fn prove(&self, proof_package: ProofPackagePrepared, witness: Option<&WitnessBundle>, new_state_root: &[u8; 32], sequence: u64, outbox_root: &[u8; 32]) -> Result<ApiResponse>;
+    async fn prove_batch(&self, entries: &[BatchEntry]) -> Result<ApiResponse>;
+}
+
 pub struct ValidatorClient {
     client: Client,
     base_url: String,
+    /// Signed into every commitment the client requests
+    producer: BlockProducer,
 }
 
 impl ValidatorClient {
-    pub fn new(base_url: &str) -> Self {
+    pub fn new(base_url: &str, producer: BlockProducer) -> Self {
         ValidatorClient {
-            client: Client::new(),
+            client: shared_client(),
             base_url: base_url.to_string(),
+            producer,
         }
     }
 
@@ -33,10 +88,15 @@ impl ValidatorClient {
         Ok(response.status().is_success())
     }
 
-    pub async fn prove(&self, proof_package: ProofPackagePrepared, new_state_root: &[u8; 32]) -> Result<ApiResponse> {
-        let response = self.client
-            .post(&format!("{}/prove/{}", self.base_url, general_purpose::URL_SAFE.encode(new_state_root)))
-            .json(&proof_package)
+    /// Submits a proof for `new_state_root`. `sequence` is the block number the root will be
+    /// finalized as, the on-chain verifier rejects sequences that don't increase. With a
+    /// `witness` the validator re-executes the batch and only signs a matching root. The
+    /// `outbox_root` and the client's producer are signed along with the state root.
+    pub async fn prove(&self, proof_package: ProofPackagePrepared, witness: Option<&WitnessBundle>, new_state_root: &[u8; 32], sequence: u64, outbox_root: &[u8; 32]) -> Result<ApiResponse> {
+        let request = self.client
+            .post(&format!("{}/prove/{}/{}", self.base_url, general_purpose::URL_SAFE.encode(new_state_root), sequence));
+        let body = prove_request(&proof_package, witness, outbox_root, &self.producer)?;
+        let response = json_body(request, &body)?
             .send()
             .await?;
 
@@ -47,4 +107,50 @@ impl ValidatorClient {
             Err(anyhow::anyhow!("API request failed: {:?}", response.status()))
         }
     }
+
+    /// Submits consecutive blocks, oldest first, to be committed in a single L1 transaction. The
+    /// first root must extend the root committed on L1 and every other root the one before it.
+    /// The validator refuses the whole batch if any block fails verification.
+    pub async fn prove_batch(&self, entries: &[BatchEntry]) -> Result<ApiResponse> {
+        let request = self.client
+            .post(&format!("{}/prove-batch", self.base_url));
+        let body = ProveBatchRequest {
+            entries: entries.iter().map(|entry| Ok(ProveBatchEntry {
+                request: prove_request(&entry.proof_package, entry.witness.as_ref(), &entry.outbox_root, &self.producer)?,
+                new_state_root: general_purpose::URL_SAFE.encode(entry.new_state_root),
+                sequence: entry.sequence,
+            })).collect::<Result<_>>()?,
+        };
+        let response = json_body(request, &body)?
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            let api_response: ApiResponse = response.json().await?;
+            Ok(api_response)
+        } else {
+            Err(anyhow::anyhow!("API request failed: {:?}", response.status()))
+        }
+    }
+}
+
+#[async_trait]
+impl Validator for ValidatorClient {
+    async fn prove(&self, proof_package: ProofPackagePrepared, witness: Option<&WitnessBundle>, new_state_root: &[u8; 32], sequence: u64, outbox_root: &[u8; 32]) -> Result<ApiResponse> {
+        ValidatorClient::prove(self, proof_package, witness, new_state_root, sequence, outbox_root).await
+    }
+
+    async fn prove_batch(&self, entries: &[BatchEntry]) -> Result<ApiResponse> {
+        ValidatorClient::prove_batch(self, entries).await
+    }
+}
+
+fn prove_request<'a>(proof_package: &'a ProofPackagePrepared, witness: Option<&WitnessBundle>, outbox_root: &[u8; 32], producer: &BlockProducer) -> Result<ProveRequest<'a>> {
+    Ok(ProveRequest {
+        proof_package,
+        witness: witness.map(|witness| borsh::to_vec(witness).map(|bytes| general_purpose::STANDARD.encode(bytes))).transpose()?,
+        outbox_root: general_purpose::STANDARD.encode(outbox_root),
+        sequencer: Pubkey::from(producer.sequencer).to_string(),
+        node_version: producer.version.to_string(),
+    })
 }
\ No newline at end of file
@@ -0,0 +1,287 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use log::warn;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::RpcFilterType;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::UiTransactionEncoding;
+use state::account_state::AccountState;
+use state_management::state_management::{ManageState, StateManager};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub(crate) const VAULT_SEED: &[u8] = b"vault";
+pub(crate) const TOKEN_VAULT_SEED: &[u8] = b"token_vault";
+/// Size of the vault program's `MintLiability` accounts
+const MINT_LIABILITY_SIZE: u64 = 48;
+/// Borsh tags of the vault program's `VaultInstruction`s that pay out or unlock withdrawals:
+/// `ProcessWithdrawal`, `UnlockWithdrawalRoot`, `EmergencyWithdrawal` and
+/// `ProcessWithdrawalBatch`
+const WITHDRAWAL_INSTRUCTION_TAGS: [u8; 4] = [3, 5, 6, 8];
+/// Borsh tag of the vault program's `Deposit`
+const DEPOSIT_INSTRUCTION_TAG: u8 = 2;
+/// Borsh tag of the vault program's `PostAccountSetRoot`
+const POST_ACCOUNT_SET_ROOT_TAG: u8 = 7;
+
+/// Whether `data`, an instruction of the vault program, pays out or unlocks withdrawals.
+pub fn is_withdrawal_instruction(data: &[u8]) -> bool {
+    data.first().is_some_and(|tag| WITHDRAWAL_INSTRUCTION_TAGS.contains(tag))
+}
+
+/// Amount and rollup recipient of `data`, an instruction of the vault program, when it deposits
+/// lamports.
+pub fn lamport_deposit(data: &[u8]) -> Option<(u64, Pubkey)> {
+    let (tag, fields) = data.split_first()?;
+    if *tag != DEPOSIT_INSTRUCTION_TAG {
+        return None;
+    }
+    let (amount, rollup_recipient, mint) = <(u64, Pubkey, Option<Pubkey>)>::try_from_slice(fields).ok()?;
+    mint.is_none().then_some((amount, rollup_recipient))
+}
+
+/// The vault program's `PostAccountSetRoot`, making `root` the root emergency withdrawals are
+/// proven against. Only the vault's sequencer can sign it.
+pub fn post_account_set_root(vault_program_id: &Pubkey, sequencer: &Pubkey, epoch: u64, root: [u8; 32]) -> Instruction {
+    let (vault, _) = Pubkey::find_program_address(&[VAULT_SEED], vault_program_id);
+    let mut data = vec![POST_ACCOUNT_SET_ROOT_TAG];
+    data.extend(borsh::to_vec(&(epoch, root)).expect("Encoding to a vec can't fail"));
+    Instruction::new_with_bytes(*vault_program_id, &data, vec![AccountMeta::new(vault, false), AccountMeta::new_readonly(*sequencer, true)])
+}
+
+/// Checkpoint epoch of the account set root last posted to the vault, 0 before the first.
+pub async fn posted_account_set_epoch(rpc_client: &RpcClient, vault_program_id: &Pubkey) -> anyhow::Result<u64> {
+    let (vault, _) = Pubkey::find_program_address(&[VAULT_SEED], vault_program_id);
+    Ok(VaultState::try_from_slice(&rpc_client.get_account_data(&vault).await?)?.account_set_epoch)
+}
+
+/// A lamport deposit into the vault, read from a finalized L1 transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VaultDeposit {
+    pub l1_signature: Signature,
+    /// Index of the `Deposit` instruction in the transaction
+    pub instruction_index: u32,
+    pub amount: u64,
+    pub rollup_recipient: Pubkey,
+}
+
+/// Lamport deposits of the vault's finalized, successful transactions after `until`, oldest
+/// first, along with the newest signature seen to pass as `until` next time. Without `until`
+/// only the latest page of transactions is read. Deposits made through a CPI are inner
+/// instructions and aren't found.
+pub async fn fetch_lamport_deposits(rpc_client: &RpcClient, vault_program_id: &Pubkey, until: Option<Signature>) -> anyhow::Result<(Vec<VaultDeposit>, Option<Signature>)> {
+    let (vault, _) = Pubkey::find_program_address(&[VAULT_SEED], vault_program_id);
+    let mut statuses = Vec::new();
+    let mut before = None;
+    loop {
+        let config = GetConfirmedSignaturesForAddress2Config {
+            before,
+            until,
+            limit: None,
+            commitment: Some(CommitmentConfig::finalized()),
+        };
+        let page = rpc_client.get_signatures_for_address_with_config(&vault, config).await?;
+        let Some(oldest) = page.last() else {
+            break;
+        };
+        before = Some(Signature::from_str(&oldest.signature)?);
+        statuses.extend(page);
+        if until.is_none() {
+            break;
+        }
+    }
+    let newest = match statuses.first() {
+        Some(status) => Some(Signature::from_str(&status.signature)?),
+        None => until,
+    };
+
+    let mut deposits = Vec::new();
+    for status in statuses.iter().rev().filter(|status| status.err.is_none()) {
+        let l1_signature = Signature::from_str(&status.signature)?;
+        let transaction = rpc_client.get_transaction(&l1_signature, UiTransactionEncoding::Base64).await?;
+        let Some(decoded) = transaction.transaction.transaction.decode() else {
+            warn!("Skipping L1 transaction {}, it can't be decoded", l1_signature);
+            continue;
+        };
+        let account_keys = decoded.message.static_account_keys();
+        for (index, instruction) in decoded.message.instructions().iter().enumerate() {
+            if account_keys.get(instruction.program_id_index as usize) != Some(vault_program_id) {
+                continue;
+            }
+            if let Some((amount, rollup_recipient)) = lamport_deposit(&instruction.data) {
+                deposits.push(VaultDeposit { l1_signature, instruction_index: index as u32, amount, rollup_recipient });
+            }
+        }
+    }
+    Ok((deposits, newest))
+}
+
+/// Layout of the vault program's `VaultState` account.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct VaultState {
+    _verifier_program_id: Pubkey,
+    deposited_lamports: u64,
+    withdrawn_lamports: u64,
+    _withdrawal_root: [u8; 32],
+    _root_unlocked_at: i64,
+    _escape_delay_secs: u64,
+    _halted: bool,
+    _sequencer: Pubkey,
+    _account_set_root: [u8; 32],
+    account_set_epoch: u64,
+}
+
+/// Layout of the vault program's `MintLiability` account.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct MintLiability {
+    mint: Pubkey,
+    deposited: u64,
+    withdrawn: u64,
+}
+
+/// Balances of one asset held in the vault, `mint` is `None` for lamports.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetReconciliation {
+    pub mint: Option<String>,
+    /// Deposits minus withdrawals, as recorded by the vault program
+    pub liabilities: u64,
+    /// What the vault actually holds, excluding rent
+    pub vault_balance: u64,
+    /// Total held by rollup accounts
+    pub rollup_supply: u64,
+}
+
+impl AssetReconciliation {
+    /// The vault can pay out everything it owes.
+    pub fn is_solvent(&self) -> bool {
+        self.vault_balance >= self.liabilities
+    }
+
+    /// The rollup doesn't hold more of the asset than was deposited. Lamports also exist natively
+    /// on the rollup, so this is only meaningful for bridged tokens.
+    pub fn is_backed(&self) -> bool {
+        self.rollup_supply <= self.liabilities
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultReconciliation {
+    pub lamports: AssetReconciliation,
+    pub tokens: Vec<AssetReconciliation>,
+}
+
+/// Compares the L1 vault against the rollup state.
+pub struct VaultReconciler<A: ManageState<Record=AccountState>> {
+    account_state_management: Arc<StateManager<A>>,
+    rpc_client: RpcClient,
+    vault_program_id: Pubkey,
+}
+
+impl<A: ManageState<Record=AccountState>> VaultReconciler<A> {
+    pub fn new(account_state_management: Arc<StateManager<A>>, rpc_url: String, vault_program_id: Pubkey) -> Self {
+        VaultReconciler {
+            account_state_management,
+            rpc_client: RpcClient::new_with_commitment(rpc_url, CommitmentConfig::finalized()),
+            vault_program_id,
+        }
+    }
+
+    pub async fn reconcile(&self) -> anyhow::Result<VaultReconciliation> {
+        let (vault, _) = Pubkey::find_program_address(&[VAULT_SEED], &self.vault_program_id);
+        let vault_account = self.rpc_client.get_account(&vault).await?;
+        let vault_state = VaultState::try_from_slice(&vault_account.data)?;
+        let rent_exempt_minimum = self.rpc_client.get_minimum_balance_for_rent_exemption(vault_account.data.len()).await?;
+
+        let (rollup_lamports, rollup_tokens) = self.rollup_supply();
+        let lamports = AssetReconciliation {
+            mint: None,
+            liabilities: vault_state.deposited_lamports.saturating_sub(vault_state.withdrawn_lamports),
+            vault_balance: vault_account.lamports.saturating_sub(rent_exempt_minimum),
+            rollup_supply: rollup_lamports,
+        };
+
+        let mut tokens = Vec::new();
+        for mint_liability in self.mint_liabilities().await? {
+            let (token_vault, _) = Pubkey::find_program_address(&[TOKEN_VAULT_SEED, mint_liability.mint.as_ref()], &self.vault_program_id);
+            let token_account = spl_token::state::Account::unpack(&self.rpc_client.get_account_data(&token_vault).await?)?;
+            tokens.push(AssetReconciliation {
+                mint: Some(mint_liability.mint.to_string()),
+                liabilities: mint_liability.deposited.saturating_sub(mint_liability.withdrawn),
+                vault_balance: token_account.amount,
+                rollup_supply: rollup_tokens.get(&mint_liability.mint).copied().unwrap_or_default(),
+            });
+        }
+
+        Ok(VaultReconciliation { lamports, tokens })
+    }
+
+    async fn mint_liabilities(&self) -> anyhow::Result<Vec<MintLiability>> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::DataSize(MINT_LIABILITY_SIZE)]),
+            account_config: RpcAccountInfoConfig::default(),
+            with_context: None,
+            sort_results: None,
+        };
+        let accounts = self.rpc_client.get_program_accounts_with_config(&self.vault_program_id, config).await?;
+        accounts.into_iter()
+            .map(|(_, account)| Ok(MintLiability::try_from_slice(&account.data)?))
+            .collect()
+    }
+
+    /// Lamports and SPL token amounts per mint held by rollup accounts.
+    fn rollup_supply(&self) -> (u64, BTreeMap<Pubkey, u64>) {
+        let mut lamports = 0u64;
+        let mut tokens = BTreeMap::new();
+        for (_, account) in self.account_state_management.get_all_entries() {
+            lamports = lamports.saturating_add(account.lamports);
+            if account.owner != spl_token::id() {
+                continue;
+            }
+            if let Ok(token_account) = spl_token::state::Account::unpack(&account.data) {
+                let supply = tokens.entry(token_account.mint).or_insert(0u64);
+                *supply = supply.saturating_add(token_account.amount);
+            }
+        }
+        (lamports, tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lamport_deposit_decodes_the_vault_instruction() {
+        let recipient = Pubkey::new_unique();
+        let mut data = vec![DEPOSIT_INSTRUCTION_TAG];
+        data.extend(borsh::to_vec(&(5_000u64, recipient, None::<Pubkey>)).unwrap());
+        assert_eq!(lamport_deposit(&data), Some((5_000, recipient)));
+
+        // Token deposits and other instructions aren't credited as lamports
+        let mut token = vec![DEPOSIT_INSTRUCTION_TAG];
+        token.extend(borsh::to_vec(&(5_000u64, recipient, Some(Pubkey::new_unique()))).unwrap());
+        assert_eq!(lamport_deposit(&token), None);
+        data[0] = 3;
+        assert_eq!(lamport_deposit(&data), None);
+        assert_eq!(lamport_deposit(&[DEPOSIT_INSTRUCTION_TAG, 1]), None);
+    }
+
+    #[test]
+    fn test_post_account_set_root_encodes_the_vault_instruction() {
+        let vault_program_id = Pubkey::new_unique();
+        let sequencer = Pubkey::new_unique();
+        let instruction = post_account_set_root(&vault_program_id, &sequencer, 3, [7u8; 32]);
+        assert_eq!(instruction.data[0], POST_ACCOUNT_SET_ROOT_TAG);
+        assert_eq!(<(u64, [u8; 32])>::try_from_slice(&instruction.data[1..]).unwrap(), (3, [7u8; 32]));
+        assert!(instruction.accounts[1].is_signer);
+        assert_eq!(instruction.accounts[1].pubkey, sequencer);
+    }
+}
@@ -0,0 +1,347 @@
+use crate::fee_payer;
+use crate::vault_reconciliation::{TOKEN_VAULT_SEED, VAULT_SEED};
+use borsh::{BorshDeserialize, BorshSerialize};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use rs_merkle::algorithms::Sha256;
+use rs_merkle::MerkleTree;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::hashv;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::message::Message;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use solana_sdk::system_program;
+use solana_sdk::transaction::Transaction;
+use state::config::TrollupConfig;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const WITHDRAWAL_SEED: &[u8] = b"withdrawal";
+const LIABILITY_SEED: &[u8] = b"liability";
+/// Borsh tag of the vault program's `ProcessWithdrawalBatch`
+const PROCESS_WITHDRAWAL_BATCH_TAG: u8 = 8;
+/// Most accounts a transaction can lock
+const MAX_TRANSACTION_ACCOUNTS: usize = 64;
+/// Size of the vault program's withdrawal receipts
+const RECEIPT_SIZE: usize = 1;
+const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey = pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref AGGREGATOR: Mutex<WithdrawalAggregator> = Mutex::new(WithdrawalAggregator::new(Duration::from_secs(CONFIG.withdrawal_batch_window_secs)));
+}
+
+/// A withdrawal the rollup approved, as its leaf in the state tree commits it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApprovedWithdrawal {
+    pub recipient: Pubkey,
+    /// `None` for lamports
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+    pub nonce: u64,
+}
+
+impl ApprovedWithdrawal {
+    /// The leaf of the withdrawal, as the vault program's `Withdrawal::leaf` hashes it.
+    pub fn leaf(&self) -> [u8; 32] {
+        hashv(&[
+            b"trollup-withdrawal",
+            self.recipient.as_ref(),
+            self.mint.unwrap_or_default().as_ref(),
+            &self.amount.to_le_bytes(),
+            &self.nonce.to_le_bytes(),
+        ]).to_bytes()
+    }
+}
+
+/// Layout of the vault program's `BatchedWithdrawal`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+struct BatchedWithdrawal {
+    recipient: Pubkey,
+    mint: Option<Pubkey>,
+    amount: u64,
+    nonce: u64,
+    leaf_index: u64,
+}
+
+/// Layout of the vault program's `WithdrawalBatch`.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct WithdrawalBatch {
+    withdrawals: Vec<BatchedWithdrawal>,
+    leaf_count: u64,
+    proof: Vec<[u8; 32]>,
+}
+
+/// Withdrawals approved against one state root, waiting for their window to close.
+struct PendingRoot {
+    tree: MerkleTree<Sha256>,
+    leaf_count: u64,
+    withdrawals: BTreeMap<u64, BatchedWithdrawal>,
+    opened_at: Instant,
+}
+
+/// Collects approved withdrawals per state root over a window and pays them out with as few
+/// `ProcessWithdrawalBatch` instructions as the transaction limits allow, each proven by one
+/// multiproof instead of a proof per withdrawal.
+pub struct WithdrawalAggregator {
+    window: Duration,
+    pending: BTreeMap<[u8; 32], PendingRoot>,
+}
+
+impl WithdrawalAggregator {
+    pub fn new(window: Duration) -> Self {
+        WithdrawalAggregator { window, pending: BTreeMap::new() }
+    }
+
+    /// Queues `withdrawal` to be paid from the state root over `leaves`, the root's window opens
+    /// with its first withdrawal. Returns the root.
+    pub fn approve(&mut self, leaves: &[[u8; 32]], withdrawal: ApprovedWithdrawal, now: Instant) -> Result<[u8; 32], String> {
+        let leaf = withdrawal.leaf();
+        let leaf_index = leaves
+            .iter()
+            .position(|candidate| *candidate == leaf)
+            .ok_or_else(|| format!("Withdrawal of {} to {} isn't a leaf of the state tree", withdrawal.amount, withdrawal.recipient))? as u64;
+        let tree = MerkleTree::<Sha256>::from_leaves(leaves);
+        let root = tree.root().ok_or("The state tree has no leaves")?;
+        let pending = self.pending.entry(root).or_insert_with(|| PendingRoot {
+            tree,
+            leaf_count: leaves.len() as u64,
+            withdrawals: BTreeMap::new(),
+            opened_at: now,
+        });
+        pending.withdrawals.insert(leaf_index, BatchedWithdrawal {
+            recipient: withdrawal.recipient,
+            mint: withdrawal.mint,
+            amount: withdrawal.amount,
+            nonce: withdrawal.nonce,
+            leaf_index,
+        });
+        Ok(root)
+    }
+
+    /// Withdrawals waiting for their window to close.
+    pub fn pending(&self) -> usize {
+        self.pending.values().map(|pending| pending.withdrawals.len()).sum()
+    }
+
+    /// Takes the withdrawals whose window closed at `now` and builds the instructions paying
+    /// them, each sent in a transaction of its own paid by `payer`.
+    pub fn close_windows(&mut self, now: Instant, vault_program_id: &Pubkey, payer: &Pubkey) -> Vec<Instruction> {
+        let closed: Vec<[u8; 32]> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| now.saturating_duration_since(pending.opened_at) >= self.window)
+            .map(|(root, _)| *root)
+            .collect();
+        closed
+            .into_iter()
+            .filter_map(|root| self.pending.remove(&root))
+            .flat_map(|pending| batch_instructions(vault_program_id, payer, &pending))
+            .collect()
+    }
+}
+
+/// Splits the withdrawals of `pending`, in leaf order, into the fewest instructions whose
+/// transactions stay within the account and packet size limits.
+fn batch_instructions(vault_program_id: &Pubkey, payer: &Pubkey, pending: &PendingRoot) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut batch: Vec<BatchedWithdrawal> = Vec::new();
+    for withdrawal in pending.withdrawals.values() {
+        batch.push(withdrawal.clone());
+        if fits(&batch_instruction(vault_program_id, payer, pending, &batch), payer) {
+            continue;
+        }
+        batch.pop();
+        if !batch.is_empty() {
+            instructions.push(batch_instruction(vault_program_id, payer, pending, &batch));
+        }
+        batch = vec![withdrawal.clone()];
+        if !fits(&batch_instruction(vault_program_id, payer, pending, &batch), payer) {
+            warn!("Withdrawal of {} to {} doesn't fit a transaction, it isn't paid", withdrawal.amount, withdrawal.recipient);
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        instructions.push(batch_instruction(vault_program_id, payer, pending, &batch));
+    }
+    instructions
+}
+
+/// The vault program's `ProcessWithdrawalBatch` paying `withdrawals`, sorted by leaf index.
+fn batch_instruction(vault_program_id: &Pubkey, payer: &Pubkey, pending: &PendingRoot, withdrawals: &[BatchedWithdrawal]) -> Instruction {
+    let (vault, _) = Pubkey::find_program_address(&[VAULT_SEED], vault_program_id);
+    let mut accounts = vec![
+        AccountMeta::new(vault, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    for withdrawal in withdrawals {
+        let (receipt, _) = Pubkey::find_program_address(&[WITHDRAWAL_SEED, &leaf(withdrawal)], vault_program_id);
+        accounts.push(AccountMeta::new(receipt, false));
+        accounts.push(AccountMeta::new(withdrawal.recipient, false));
+        if let Some(mint) = withdrawal.mint {
+            let (token_vault, _) = Pubkey::find_program_address(&[TOKEN_VAULT_SEED, mint.as_ref()], vault_program_id);
+            let (recipient_token_account, _) = Pubkey::find_program_address(&[withdrawal.recipient.as_ref(), spl_token::id().as_ref(), mint.as_ref()], &ASSOCIATED_TOKEN_PROGRAM_ID);
+            let (liability, _) = Pubkey::find_program_address(&[LIABILITY_SEED, mint.as_ref()], vault_program_id);
+            accounts.push(AccountMeta::new(token_vault, false));
+            accounts.push(AccountMeta::new(recipient_token_account, false));
+            accounts.push(AccountMeta::new(liability, false));
+        }
+    }
+    let indices: Vec<usize> = withdrawals.iter().map(|withdrawal| withdrawal.leaf_index as usize).collect();
+    let batch = WithdrawalBatch {
+        withdrawals: withdrawals.to_vec(),
+        leaf_count: pending.leaf_count,
+        proof: pending.tree.proof(&indices).proof_hashes().to_vec(),
+    };
+    let mut data = vec![PROCESS_WITHDRAWAL_BATCH_TAG];
+    data.extend(borsh::to_vec(&batch).expect("Encoding to a vec can't fail"));
+    Instruction::new_with_bytes(*vault_program_id, &data, accounts)
+}
+
+fn leaf(withdrawal: &BatchedWithdrawal) -> [u8; 32] {
+    ApprovedWithdrawal { recipient: withdrawal.recipient, mint: withdrawal.mint, amount: withdrawal.amount, nonce: withdrawal.nonce }.leaf()
+}
+
+/// Whether a transaction of `instruction` alone, paid by `payer`, locks no more accounts than
+/// allowed and fits a packet once signed.
+fn fits(instruction: &Instruction, payer: &Pubkey) -> bool {
+    let message = Message::new(std::slice::from_ref(instruction), Some(payer));
+    // A one byte length prefix precedes the signatures, they are fewer than 128
+    let size = 1 + 64 * message.header.num_required_signatures as usize + message.serialize().len();
+    message.account_keys.len() <= MAX_TRANSACTION_ACCOUNTS && size <= PACKET_DATA_SIZE
+}
+
+/// Queues an approved withdrawal in the committer's aggregator, see `WithdrawalAggregator::approve`.
+pub fn approve(leaves: &[[u8; 32]], withdrawal: ApprovedWithdrawal) -> Result<[u8; 32], String> {
+    AGGREGATOR.lock().unwrap().approve(leaves, withdrawal, Instant::now())
+}
+
+/// Pays out the withdrawals whose window closed when `VAULT_PROGRAM_ID` is set, with one
+/// transaction per batch paid by the commitment fee payer, which also pays the receipts' rent.
+/// Returns the signatures of the transactions that landed. The vault proves withdrawals against
+/// the last unlocked root only, a batch whose root was replaced in the meantime fails.
+pub async fn pay_closed_windows() -> anyhow::Result<Vec<String>> {
+    let Ok(vault_program_id) = CONFIG.vault_program_id.parse::<Pubkey>() else {
+        return Ok(Vec::new());
+    };
+    let fee_payer = CONFIG.commitment_fee_payer().map_err(anyhow::Error::msg)?;
+    let instructions = AGGREGATOR.lock().unwrap().close_windows(Instant::now(), &vault_program_id, &fee_payer.pubkey());
+    if instructions.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let rpc_client = RpcClient::new_with_commitment(CONFIG.rpc_url_current_env().to_string(), CommitmentConfig::confirmed());
+    let receipt_rent = rpc_client.get_minimum_balance_for_rent_exemption(RECEIPT_SIZE).await?;
+    let mut signatures = Vec::new();
+    for instruction in instructions {
+        let withdrawals = WithdrawalBatch::try_from_slice(&instruction.data[1..])?.withdrawals.len();
+        let blockhash = rpc_client.get_latest_blockhash().await?;
+        let message = Message::new_with_blockhash(&[instruction], Some(&fee_payer.pubkey()), &blockhash);
+        fee_payer::ensure_can_pay(&rpc_client, &message, receipt_rent * withdrawals as u64, CONFIG.fee_payer_low_balance_lamports).await?;
+        let transaction = Transaction::new(&[&fee_payer], message, blockhash);
+        match rpc_client.send_and_confirm_transaction(&transaction).await {
+            Ok(signature) => {
+                info!("Paid out a batch of {} withdrawals in {}", withdrawals, signature);
+                signatures.push(signature.to_string());
+            }
+            Err(error) => error!("Batch of {} withdrawals wasn't paid out: {}", withdrawals, error),
+        }
+    }
+    Ok(signatures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn withdrawal(mint: Option<Pubkey>, nonce: u64) -> ApprovedWithdrawal {
+        ApprovedWithdrawal { recipient: Pubkey::new_unique(), mint, amount: 1_000, nonce }
+    }
+
+    /// A state tree of unrelated leaves followed by `withdrawals`.
+    fn leaves(withdrawals: &[ApprovedWithdrawal]) -> Vec<[u8; 32]> {
+        let mut leaves: Vec<[u8; 32]> = (0..5u8).map(|i| [i; 32]).collect();
+        leaves.extend(withdrawals.iter().map(ApprovedWithdrawal::leaf));
+        leaves
+    }
+
+    fn batch(instruction: &Instruction) -> WithdrawalBatch {
+        assert_eq!(instruction.data[0], PROCESS_WITHDRAWAL_BATCH_TAG);
+        WithdrawalBatch::try_from_slice(&instruction.data[1..]).unwrap()
+    }
+
+    #[test]
+    fn test_withdrawals_wait_for_the_window() {
+        let (vault_program_id, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let withdrawals: Vec<ApprovedWithdrawal> = (0..3).map(|nonce| withdrawal(None, nonce)).collect();
+        let leaves = leaves(&withdrawals);
+        let mut aggregator = WithdrawalAggregator::new(Duration::from_secs(30));
+        let opened_at = Instant::now();
+        for withdrawal in withdrawals.iter().rev() {
+            aggregator.approve(&leaves, withdrawal.clone(), opened_at).unwrap();
+        }
+
+        assert!(aggregator.close_windows(opened_at + Duration::from_secs(29), &vault_program_id, &payer).is_empty());
+        assert_eq!(aggregator.pending(), 3);
+
+        let instructions = aggregator.close_windows(opened_at + Duration::from_secs(30), &vault_program_id, &payer);
+        assert_eq!(instructions.len(), 1);
+        assert_eq!(aggregator.pending(), 0);
+        let batch = batch(&instructions[0]);
+        assert_eq!(batch.withdrawals.iter().map(|withdrawal| withdrawal.leaf_index).collect::<Vec<_>>(), vec![5, 6, 7]);
+        assert_eq!(batch.leaf_count, 8);
+        // Vault, payer, system and token programs, then a receipt and recipient per withdrawal
+        assert_eq!(instructions[0].accounts.len(), 4 + 2 * 3);
+    }
+
+    #[test]
+    fn test_multiproof_proves_the_batch() {
+        let (vault_program_id, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let withdrawals: Vec<ApprovedWithdrawal> = (0..4).map(|nonce| withdrawal(None, nonce)).collect();
+        let leaves = leaves(&withdrawals);
+        let mut aggregator = WithdrawalAggregator::new(Duration::ZERO);
+        let root = withdrawals.iter().map(|withdrawal| aggregator.approve(&leaves, withdrawal.clone(), Instant::now()).unwrap()).last().unwrap();
+
+        let instructions = aggregator.close_windows(Instant::now(), &vault_program_id, &payer);
+        let batch = batch(&instructions[0]);
+        let indices: Vec<usize> = batch.withdrawals.iter().map(|withdrawal| withdrawal.leaf_index as usize).collect();
+        let batch_leaves: Vec<[u8; 32]> = batch.withdrawals.iter().map(leaf).collect();
+        let proof = rs_merkle::MerkleProof::<Sha256>::new(batch.proof);
+        assert!(proof.verify(root, &indices, &batch_leaves, leaves.len()));
+    }
+
+    #[test]
+    fn test_batches_are_split_at_the_account_limit() {
+        let (vault_program_id, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let withdrawals: Vec<ApprovedWithdrawal> = (0..40).map(|nonce| withdrawal(Some(Pubkey::new_unique()), nonce)).collect();
+        let leaves = leaves(&withdrawals);
+        let mut aggregator = WithdrawalAggregator::new(Duration::ZERO);
+        for withdrawal in &withdrawals {
+            aggregator.approve(&leaves, withdrawal.clone(), Instant::now()).unwrap();
+        }
+
+        let instructions = aggregator.close_windows(Instant::now(), &vault_program_id, &payer);
+        assert!(instructions.len() > 1);
+        let mut paid = Vec::new();
+        for instruction in &instructions {
+            assert!(fits(instruction, &payer));
+            paid.extend(batch(instruction).withdrawals.iter().map(|withdrawal| withdrawal.leaf_index));
+        }
+        assert_eq!(paid, (5..45).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn test_withdrawal_outside_the_tree_is_refused() {
+        let mut aggregator = WithdrawalAggregator::new(Duration::ZERO);
+        let result = aggregator.approve(&leaves(&[]), withdrawal(None, 0), Instant::now());
+        assert!(result.is_err());
+        assert_eq!(aggregator.pending(), 0);
+    }
+}
@@ -12,13 +12,15 @@ use {
     solana_svm::transaction_processing_callback::TransactionProcessingCallback,
     std::{collections::HashMap, sync::RwLock},
 };
-use log::{debug, info};
+use log::{debug, info, warn};
 use state::account_state::AccountState;
-use state::config::TrollupConfig;
+use state::config::{AccountLoaderStrictness, TrollupConfig};
 use crate::state_management::{ManageState, StateManager};
 
 lazy_static! {
     static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    /// `PROGRAM_IDS_TO_LOAD` programs fetched from L1, shared by every loader of the process
+    static ref PROGRAMS: RwLock<HashMap<[u8; 32], AccountSharedData>> = RwLock::new(HashMap::new());
 }
 
 pub struct TrollupAccountLoader<'a, A: ManageState> {
@@ -45,10 +47,54 @@ impl<'a, A: ManageState<Record=AccountState>> TrollupAccountLoader<'a, A> {
         Self {
             cache: RwLock::new(HashMap::new()),
             account_state_management,
-            rpc_client: RpcClient::new_with_commitment(&CONFIG.rpc_urls.get("Dev").unwrap(), CommitmentConfig::confirmed()), //TODO load from config
+            rpc_client: RpcClient::new_with_commitment(CONFIG.rpc_url_current_env(), CommitmentConfig::confirmed()),
             program_ids,
         }
     }
+
+    /// Loads the account from L1 into the cache, `None` when L1 doesn't have it or can't be reached.
+    fn fetch(&self, pubkey: &Pubkey) -> Option<AccountSharedData> {
+        let account = match self.rpc_client.get_account_with_commitment(pubkey, CommitmentConfig::confirmed()) {
+            Ok(response) => response.value?,
+            Err(error) => {
+                warn!("Fetching account {} from L1 failed: {}", pubkey, error);
+                return None;
+            }
+        };
+        info!(target: "audit", "Fetched account {} from L1, owner {}, {} lamports, {} bytes", pubkey, account.owner, account.lamports, account.data.len());
+        let account_shared_data = AccountSharedData::from(account);
+        self.cache.write().unwrap().insert(pubkey.to_bytes(), account_shared_data.clone());
+        Some(account_shared_data)
+    }
+
+    /// Fetches a `PROGRAM_IDS_TO_LOAD` program from L1 once per process, later loaders are served
+    /// the same account from memory.
+    fn fetch_program(&self, program_id: &Pubkey) -> Option<AccountSharedData> {
+        if let Some(program) = PROGRAMS.read().unwrap().get(&program_id.to_bytes()) {
+            self.cache.write().unwrap().insert(program_id.to_bytes(), program.clone());
+            return Some(program.clone());
+        }
+        let program = self.fetch(program_id)?;
+        PROGRAMS.write().unwrap().insert(program_id.to_bytes(), program.clone());
+        Some(program)
+    }
+
+    /// Loads every `PROGRAM_IDS_TO_LOAD` program ahead of the first block, returning how many
+    /// could be loaded.
+    pub fn preload_programs(&self) -> usize {
+        self.program_ids
+            .iter()
+            .filter(|program_id| self.get_account_shared_data(program_id).is_some())
+            .count()
+    }
+
+    /// Serves `accounts` instead of looking them up, e.g. the sysvars of the block being executed.
+    pub fn add_accounts(&self, accounts: Vec<(Pubkey, AccountSharedData)>) {
+        let mut cache = self.cache.write().unwrap();
+        for (pubkey, account) in accounts {
+            cache.insert(pubkey.to_bytes(), account);
+        }
+    }
 }
 
 impl<'a, A: ManageState<Record=AccountState>> TransactionProcessingCallback for TrollupAccountLoader<'a, A> {
@@ -75,24 +121,25 @@ impl<'a, A: ManageState<Record=AccountState>> TransactionProcessingCallback for
         }
         
         if self.program_ids.contains(pubkey) {
-            let account_data = self.rpc_client.get_account_with_commitment(pubkey, CommitmentConfig::confirmed()).ok()?;
-            if let Some(account_data) = account_data.value {
-                let account_shared_data = AccountSharedData::from(account_data);
-                self.cache.write().unwrap().insert(pubkey.to_bytes(), account_shared_data.clone());
-                return Some(account_shared_data);
-            }
+            return self.fetch_program(pubkey);
         }
 
-        // If not found in state management, create a default account
-        info!("Not found... creating default account for {:?}", pubkey);
-        // TODO for now all new accounts are owned by the System program, this will need to change
-        let default_account = AccountSharedData::new(
-            10000000000000,
-            0,
-            &Pubkey::from_str("11111111111111111111111111111111").unwrap()
-        );
-        self.cache.write().unwrap().insert(pubkey.to_bytes(), default_account.clone());
-        Some(default_account)
+        // Unknown accounts don't exist yet and have to be created by the transaction, unless the
+        // loader is allowed to look them up on L1 or to fund them
+        match CONFIG.account_loader_strictness {
+            AccountLoaderStrictness::Strict => None,
+            AccountLoaderStrictness::Fetch => self.fetch(pubkey),
+            AccountLoaderStrictness::Dev => {
+                let default_account = AccountSharedData::new(
+                    10000000000000,
+                    0,
+                    &Pubkey::from_str("11111111111111111111111111111111").unwrap()
+                );
+                info!(target: "audit", "Fabricated account {} with {} lamports", pubkey, default_account.lamports());
+                self.cache.write().unwrap().insert(pubkey.to_bytes(), default_account.clone());
+                Some(default_account)
+            }
+        }
     }
 
     fn add_builtin_account(&self, name: &str, program_id: &Pubkey) {
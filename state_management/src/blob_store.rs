@@ -0,0 +1,211 @@
+use crate::state_management::{ManageState, StateManager};
+use state::account_state::AccountState;
+use state::blob::{Blob, BlobReferences};
+use state::state_record::StateCommitmentPackage;
+use std::sync::{Arc, Mutex};
+
+/// Content-addressed store for artifacts that are shared between records, such as the verifying
+/// key every block and pending commitment was proven with, or the programs their accounts hold.
+///
+/// Records keep the hash returned by `put` instead of the bytes. Every `put` takes a reference
+/// and every `release` drops one, the blob is deleted when the last reference is released. The
+/// blobs are written once, the references are counted in a store of their own.
+pub struct BlobStore<L: ManageState<Record=Blob>, R: ManageState<Record=BlobReferences>> {
+    blob_state_management: Arc<StateManager<L>>,
+    references_state_management: Arc<StateManager<R>>,
+    // Serializes the read-modify-write of the reference counts
+    lock: Mutex<()>,
+}
+
+impl<L: ManageState<Record=Blob>, R: ManageState<Record=BlobReferences>> BlobStore<L, R> {
+    pub fn new(blob_state_management: Arc<StateManager<L>>, references_state_management: Arc<StateManager<R>>) -> Self {
+        let blob_store = BlobStore { blob_state_management, references_state_management, lock: Mutex::new(()) };
+        blob_store.move_legacy_references();
+        blob_store
+    }
+
+    /// Moves the counts blobs were stored with before references had a store of their own.
+    fn move_legacy_references(&self) {
+        let legacy: Vec<Blob> = self
+            .blob_state_management
+            .iter_entries()
+            .filter(|(_, blob)| blob.ref_count > 0)
+            .map(|(_, blob)| blob)
+            .collect();
+        if legacy.is_empty() {
+            return;
+        }
+        for blob in legacy {
+            let count = self.references(&blob.hash) + blob.ref_count;
+            self.references_state_management.set_state_record(&BlobReferences { hash: blob.hash, count });
+            self.blob_state_management.set_state_record(&Blob { ref_count: 0, ..blob });
+        }
+        self.commit();
+    }
+
+    /// Stores `data` if it isn't stored yet and takes a reference to it.
+    pub fn put(&self, data: &[u8]) -> [u8; 32] {
+        let hash = Blob::content_hash(data);
+        let _guard = self.lock.lock().unwrap();
+        if self.blob_state_management.get_state_record(&hash).is_none() {
+            self.blob_state_management.set_state_record(&Blob { hash, data: data.to_vec(), ref_count: 0 });
+        }
+        let count = self.references(&hash) + 1;
+        self.references_state_management.set_state_record(&BlobReferences { hash, count });
+        hash
+    }
+
+    pub fn get(&self, hash: &[u8; 32]) -> Option<Vec<u8>> {
+        self.blob_state_management.get_state_record(hash).map(|blob| blob.data)
+    }
+
+    /// References taken to the blob `hash` and not released yet.
+    pub fn references(&self, hash: &[u8; 32]) -> u64 {
+        self.references_state_management.get_state_record(hash).map(|references| references.count).unwrap_or_default()
+    }
+
+    /// Drops a reference taken by `put`, deleting the blob once nothing references it.
+    pub fn release(&self, hash: &[u8; 32]) {
+        let _guard = self.lock.lock().unwrap();
+        match self.references(hash) {
+            0 => {}
+            1 => {
+                self.references_state_management.delete_state_record(hash);
+                self.blob_state_management.delete_state_record(hash);
+            }
+            count => {
+                self.references_state_management.set_state_record(&BlobReferences { hash: *hash, count: count - 1 });
+            }
+        }
+    }
+
+    /// The copy of `package` to store: its verifying key and the data of its executable
+    /// accounts are put in the blob store and left out, only their hashes are kept.
+    pub fn put_package(&self, package: &StateCommitmentPackage<AccountState>) -> StateCommitmentPackage<AccountState> {
+        let mut stored_package = StateCommitmentPackage {
+            verifying_key_hash: Some(self.put(&package.verifying_key)),
+            verifying_key: Vec::new(),
+            ..package.clone()
+        };
+        let program_data_hashes = programs(&mut stored_package)
+            .map(|account| self.put(&std::mem::take(&mut account.data)))
+            .collect();
+        stored_package.program_data_hashes = program_data_hashes;
+        stored_package
+    }
+
+    /// Puts back what `put_package` left out of a stored package.
+    pub fn get_package(&self, mut package: StateCommitmentPackage<AccountState>) -> StateCommitmentPackage<AccountState> {
+        if let Some(verifying_key) = package.verifying_key_hash.and_then(|hash| self.get(&hash)) {
+            package.verifying_key = verifying_key;
+        }
+        let program_data_hashes = std::mem::take(&mut package.program_data_hashes);
+        for (account, hash) in programs(&mut package).zip(&program_data_hashes) {
+            if let Some(data) = self.get(hash) {
+                account.data = data;
+            }
+        }
+        package
+    }
+
+    /// Drops the references `put_package` took for a stored package.
+    pub fn release_package(&self, package: &StateCommitmentPackage<AccountState>) {
+        package.verifying_key_hash.iter().chain(&package.program_data_hashes).for_each(|hash| self.release(hash));
+    }
+
+    pub fn commit(&self) {
+        self.blob_state_management.commit();
+        self.references_state_management.commit();
+    }
+}
+
+/// The executable accounts of `package`, its state records first and then its witness accounts.
+fn programs(package: &mut StateCommitmentPackage<AccountState>) -> impl Iterator<Item=&mut AccountState> {
+    let witness_accounts = package.witness.iter_mut().flat_map(|witness| witness.accounts.iter_mut().map(|account_witness| &mut account_witness.account));
+    package.state_records.iter_mut().chain(witness_accounts).filter(|account| account.executable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sled_state_management::SledStateManagement;
+    use solana_sdk::pubkey::Pubkey;
+
+    type Store = BlobStore<SledStateManagement<Blob>, SledStateManagement<BlobReferences>>;
+
+    fn store() -> Store {
+        BlobStore::new(Arc::new(StateManager::new("")), Arc::new(StateManager::new("")))
+    }
+
+    fn account(executable: bool, data: &[u8]) -> AccountState {
+        AccountState {
+            address: Pubkey::new_unique(),
+            lamports: 1,
+            data: data.to_vec(),
+            owner: Pubkey::new_unique(),
+            executable,
+            rent_epoch: 0,
+            data_root: None,
+        }
+    }
+
+    #[test]
+    fn test_blob_is_deleted_with_its_last_reference() {
+        let store = store();
+        let hash = store.put(b"verifying key");
+        assert_eq!(store.put(b"verifying key"), hash);
+        assert_eq!(store.references(&hash), 2);
+
+        store.release(&hash);
+        assert_eq!(store.references(&hash), 1);
+        assert_eq!(store.get(&hash).unwrap(), b"verifying key");
+
+        store.release(&hash);
+        assert_eq!(store.references(&hash), 0);
+        assert!(store.get(&hash).is_none());
+    }
+
+    #[test]
+    fn test_legacy_counts_are_moved_to_the_references() {
+        let blobs = Arc::new(StateManager::<SledStateManagement<Blob>>::new(""));
+        let hash = Blob::content_hash(b"verifying key");
+        blobs.set_state_record(&Blob { hash, data: b"verifying key".to_vec(), ref_count: 2 });
+
+        let store: Store = BlobStore::new(Arc::clone(&blobs), Arc::new(StateManager::new("")));
+
+        assert_eq!(store.references(&hash), 2);
+        assert_eq!(blobs.get_state_record(&hash).unwrap().ref_count, 0);
+        store.release(&hash);
+        store.release(&hash);
+        assert!(store.get(&hash).is_none());
+    }
+
+    #[test]
+    fn test_package_programs_are_stored_once() {
+        let store = store();
+        let program = account(true, b"program");
+        let mut package = StateCommitmentPackage::new(true, vec![program.clone(), account(false, b"data")], vec![], vec![]);
+        package.verifying_key = b"verifying key".to_vec();
+        let mut other = package.clone();
+        other.state_records[1] = account(false, b"other data");
+
+        let stored = store.put_package(&package);
+        let stored_other = store.put_package(&other);
+
+        assert!(stored.verifying_key.is_empty());
+        assert!(stored.state_records[0].data.is_empty());
+        assert_eq!(stored.state_records[1].data, b"data");
+        assert_eq!(stored.program_data_hashes, vec![Blob::content_hash(b"program")]);
+        assert_eq!(store.references(&Blob::content_hash(b"program")), 2);
+
+        let restored = store.get_package(stored.clone());
+        assert_eq!(restored.verifying_key, b"verifying key");
+        assert_eq!(restored.state_records[0].data, b"program");
+        assert!(restored.program_data_hashes.is_empty());
+
+        store.release_package(&stored);
+        store.release_package(&stored_other);
+        assert!(store.get(&Blob::content_hash(b"program")).is_none());
+        assert!(store.get(&Blob::content_hash(b"verifying key")).is_none());
+    }
+}
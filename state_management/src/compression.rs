@@ -0,0 +1,222 @@
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use state::config::TrollupConfig;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Prefix of values stored uncompressed. Every value carries one of the prefixes, so a record
+/// whose encoding happens to start with a compression prefix is never mistaken for compressed.
+const RAW_PREFIX: &[u8; 4] = b"TRW0";
+/// Prefix of values compressed without a dictionary.
+const ZSTD_PREFIX: &[u8; 4] = b"TZS0";
+/// Prefix of values compressed with the configured dictionary.
+const ZSTD_DICTIONARY_PREFIX: &[u8; 4] = b"TZD0";
+
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+    static ref DICTIONARY: Option<Vec<u8>> = load_dictionary(&CONFIG.state_compression_dictionary_path);
+    static ref STATS: CompressionCounters = CompressionCounters::default();
+}
+
+#[derive(Default)]
+struct CompressionCounters {
+    values_written: AtomicU64,
+    values_compressed: AtomicU64,
+    bytes_before: AtomicU64,
+    bytes_stored: AtomicU64,
+}
+
+/// Storage savings since the process started.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressionStats {
+    pub values_written: u64,
+    pub values_compressed: u64,
+    /// Serialized size of every value written
+    pub bytes_before: u64,
+    /// Size actually written to the store
+    pub bytes_stored: u64,
+}
+
+impl CompressionStats {
+    pub fn bytes_saved(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_stored)
+    }
+}
+
+pub fn stats() -> CompressionStats {
+    CompressionStats {
+        values_written: STATS.values_written.load(Ordering::Relaxed),
+        values_compressed: STATS.values_compressed.load(Ordering::Relaxed),
+        bytes_before: STATS.bytes_before.load(Ordering::Relaxed),
+        bytes_stored: STATS.bytes_stored.load(Ordering::Relaxed),
+    }
+}
+
+fn load_dictionary(path: &str) -> Option<Vec<u8>> {
+    if path.is_empty() {
+        return None;
+    }
+    match std::fs::read(path) {
+        Ok(dictionary) => {
+            info!("Loaded {} byte compression dictionary from {}", dictionary.len(), path);
+            Some(dictionary)
+        }
+        Err(error) => {
+            error!("Compressing without a dictionary, failed to read {}: {}", path, error);
+            None
+        }
+    }
+}
+
+/// Compresses a serialized record if it is at least `STATE_COMPRESSION_THRESHOLD` bytes and
+/// compression actually makes it smaller, and tags it raw otherwise. A threshold of `0` disables
+/// compression.
+pub fn encode(serialized: Vec<u8>) -> Vec<u8> {
+    let serialized_len = serialized.len();
+    let compressed = compress(&serialized, CONFIG.state_compression_threshold, CONFIG.state_compression_level, DICTIONARY.as_deref());
+
+    STATS.values_written.fetch_add(1, Ordering::Relaxed);
+    STATS.bytes_before.fetch_add(serialized_len as u64, Ordering::Relaxed);
+    if compressed.is_some() {
+        STATS.values_compressed.fetch_add(1, Ordering::Relaxed);
+    }
+    let stored = compressed.unwrap_or_else(|| tag_raw(&serialized));
+    STATS.bytes_stored.fetch_add(stored.len() as u64, Ordering::Relaxed);
+    stored
+}
+
+/// Returns the serialized record for a stored value, `None` for a value without a known prefix
+/// or one that fails to decompress.
+pub fn decode(stored: &[u8]) -> Option<Vec<u8>> {
+    decompress(stored, DICTIONARY.as_deref())
+}
+
+/// Reads a value stored before every value was tagged, when only compressed values had a prefix.
+/// A value that starts with a compression prefix but doesn't decompress was a raw record. Only
+/// used to migrate old stores, see `sled_state_management`.
+pub fn decode_untagged(stored: &[u8]) -> Option<Vec<u8>> {
+    if stored.starts_with(ZSTD_PREFIX) || stored.starts_with(ZSTD_DICTIONARY_PREFIX) {
+        if let Some(decoded) = decompress(stored, DICTIONARY.as_deref()) {
+            return Some(decoded);
+        }
+    }
+    Some(stored.to_vec())
+}
+
+fn tag_raw(serialized: &[u8]) -> Vec<u8> {
+    let mut stored = Vec::with_capacity(RAW_PREFIX.len() + serialized.len());
+    stored.extend_from_slice(RAW_PREFIX);
+    stored.extend_from_slice(serialized);
+    stored
+}
+
+fn compress(serialized: &[u8], threshold: usize, level: i32, dictionary: Option<&[u8]>) -> Option<Vec<u8>> {
+    if threshold == 0 || serialized.len() < threshold {
+        return None;
+    }
+    let (prefix, compressed) = match dictionary {
+        Some(dictionary) => (ZSTD_DICTIONARY_PREFIX, zstd::bulk::Compressor::with_dictionary(level, dictionary).and_then(|mut c| c.compress(serialized))),
+        None => (ZSTD_PREFIX, zstd::bulk::compress(serialized, level)),
+    };
+    let compressed = compressed.ok()?;
+    if compressed.len() + prefix.len() >= serialized.len() {
+        return None;
+    }
+    let mut stored = Vec::with_capacity(prefix.len() + compressed.len());
+    stored.extend_from_slice(prefix);
+    stored.extend_from_slice(&compressed);
+    Some(stored)
+}
+
+fn decompress(stored: &[u8], dictionary: Option<&[u8]>) -> Option<Vec<u8>> {
+    if let Some(serialized) = stored.strip_prefix(RAW_PREFIX) {
+        return Some(serialized.to_vec());
+    }
+    if let Some(frame) = stored.strip_prefix(ZSTD_PREFIX) {
+        return zstd::stream::decode_all(frame).ok();
+    }
+    if let Some(frame) = stored.strip_prefix(ZSTD_DICTIONARY_PREFIX) {
+        let mut decoder = zstd::stream::Decoder::with_dictionary(frame, dictionary?).ok()?;
+        let mut decoded = Vec::new();
+        std::io::Read::read_to_end(&mut decoder, &mut decoded).ok()?;
+        return Some(decoded);
+    }
+    None
+}
+
+/// Trains a zstd dictionary from sample values, e.g. the current contents of the account store.
+/// Records compressed with a dictionary can only be read with that same dictionary, so it must
+/// not be replaced once data was written with it.
+pub fn train_dictionary(samples: &[Vec<u8>], max_size: usize) -> std::io::Result<Vec<u8>> {
+    zstd::dict::from_samples(samples, max_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account_like_data() -> Vec<u8> {
+        (0..2048u32).flat_map(|i| (i % 7).to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn small_values_are_stored_raw() {
+        assert_eq!(compress(&[1, 2, 3], 512, 3, None), None);
+        assert_eq!(compress(&account_like_data(), 0, 3, None), None);
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        let data = account_like_data();
+        let stored = compress(&data, 512, 3, None).unwrap();
+        assert!(stored.starts_with(ZSTD_PREFIX));
+        assert!(stored.len() < data.len());
+        assert_eq!(decompress(&stored, None).unwrap(), data);
+    }
+
+    #[test]
+    fn dictionary_round_trip() {
+        let samples: Vec<Vec<u8>> = (0..200u32).map(|i| (0..256u32).flat_map(|j| ((i + j) % 11).to_le_bytes()).collect()).collect();
+        let dictionary = train_dictionary(&samples, 4096).unwrap();
+        let data = account_like_data();
+        let stored = compress(&data, 512, 3, Some(&dictionary)).unwrap();
+        assert!(stored.starts_with(ZSTD_DICTIONARY_PREFIX));
+        assert_eq!(decompress(&stored, Some(&dictionary)).unwrap(), data);
+        assert_eq!(decompress(&stored, None), None);
+    }
+
+    #[test]
+    fn raw_values_round_trip() {
+        let data = account_like_data();
+        let stored = tag_raw(&data);
+        assert!(stored.starts_with(RAW_PREFIX));
+        assert_eq!(decompress(&stored, None).unwrap(), data);
+    }
+
+    #[test]
+    fn raw_values_that_look_compressed_round_trip() {
+        let mut data = ZSTD_PREFIX.to_vec();
+        data.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(decompress(&tag_raw(&data), None).unwrap(), data);
+        let mut data = ZSTD_DICTIONARY_PREFIX.to_vec();
+        data.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(decompress(&tag_raw(&data), None).unwrap(), data);
+    }
+
+    #[test]
+    fn untagged_values_are_refused() {
+        assert_eq!(decompress(&account_like_data(), None), None);
+    }
+
+    #[test]
+    fn untagged_values_are_read_for_migration() {
+        let data = account_like_data();
+        assert_eq!(decode_untagged(&data).unwrap(), data);
+        let compressed = compress(&data, 512, 3, None).unwrap();
+        assert_eq!(decode_untagged(&compressed).unwrap(), data);
+        let mut looks_compressed = ZSTD_PREFIX.to_vec();
+        looks_compressed.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(decode_untagged(&looks_compressed).unwrap(), looks_compressed);
+    }
+}
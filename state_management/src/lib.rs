@@ -1,3 +1,5 @@
 pub mod state_management;
 pub mod sled_state_management;
-pub mod account_loader;
\ No newline at end of file
+pub mod account_loader;
+pub mod compression;
+pub mod blob_store;
\ No newline at end of file
@@ -1,9 +1,21 @@
 use std::marker::PhantomData;
 use borsh::{from_slice, to_vec};
+use log::info;
 use sled::{Config, Db};
 use state::state_record::StateRecord;
+use crate::compression;
 use crate::state_management::ManageState;
 
+/// Key of the encoding version the records of a store are written with, see
+/// `StateRecord::ENCODING_VERSION`. Stores written before records were versioned don't have it.
+const ENCODING_VERSION_KEY: &str = "ENCODING_VERSION";
+
+/// Key of the format the values of a store are written in. Stores without it were written before
+/// every value was tagged by `compression::encode`.
+const STORAGE_FORMAT_KEY: &str = "STORAGE_FORMAT";
+/// 1 tags every value, raw ones included
+const STORAGE_FORMAT: u32 = 1;
+
 /// Struct for managing state using Sled as the underlying database.
 ///
 /// # Generic Parameters
@@ -25,18 +37,23 @@ impl<S: StateRecord> ManageState for SledStateManagement<S> {
 
     #[allow(unused_variables)]
     fn new(path: &str) -> Self {
-        if path.is_empty() {
+        let db = if path.is_empty() {
             let config = Config::new().temporary(true);
-            let db = config.open().expect("");
-            Self { db, _marker: PhantomData }
+            config.open().expect("")
         } else {
-            let db = sled::open(path).expect("Failed to open database");
-            Self { db, _marker: PhantomData }
-        }
+            sled::open(path).expect("Failed to open database")
+        };
+        migrate_storage_format(&db, path);
+        migrate::<S>(&db, path);
+        Self { db, _marker: PhantomData }
     }
 
     fn get_all_entries(&self) -> Vec<([u8;32], S)> {
-        self.db
+        self.iter_entries().collect()
+    }
+
+    fn iter_entries(&self) -> Box<dyn Iterator<Item=([u8;32], S)> + '_> {
+        Box::new(self.db
             .iter()
             .filter_map(|result| {
                 result.ok().and_then(|(key, value)| {
@@ -45,14 +62,14 @@ impl<S: StateRecord> ManageState for SledStateManagement<S> {
 
                     // If the key conversion succeeds and we can deserialize the value,
                     // include this entry in the result
-                    if let (Ok(key_32), Ok(deserialized_value)) = (key_array, S::try_from_slice(&value)) {
+                    let deserialized = compression::decode(&value).and_then(|value| S::try_from_slice(&value).ok());
+                    if let (Ok(key_32), Some(deserialized_value)) = (key_array, deserialized) {
                         Some((key_32, deserialized_value))
                     } else {
                         None
                     }
                 })
-            })
-            .collect()
+            }))
     }
 
     fn get_state_record(&self, key: &[u8]) -> Option<S> {
@@ -60,18 +77,19 @@ impl<S: StateRecord> ManageState for SledStateManagement<S> {
             .get(key)
             .ok()
             .flatten()
-            .and_then(|ivec| from_slice::<S>(&ivec).ok())
+            .and_then(|ivec| compression::decode(&ivec))
+            .and_then(|value| from_slice::<S>(&value).ok())
     }
 
     fn set_state_record(&self, state: &S) {
-        let serialized = to_vec(&state).expect("Failed to serialize account state");
+        let serialized = compression::encode(to_vec(&state).expect("Failed to serialize account state"));
         self.db.insert(state.get_key(), serialized).expect("Failed to insert account state");
     }
 
     fn set_state_records(&self, states: &Vec<Self::Record>) {
         let mut batch = sled::Batch::default();
         for state in states {
-            let serialized = to_vec(&state).expect("Failed to serialize account state");
+            let serialized = compression::encode(to_vec(&state).expect("Failed to serialize account state"));
             batch.insert(&state.get_key(), serialized);
         }
         self.db.apply_batch(batch).expect("Failed to insert account state");
@@ -97,3 +115,211 @@ impl<S: StateRecord> ManageState for SledStateManagement<S> {
         self.db.flush().expect("Failed to commit database");
     }
 }
+
+/// Tags every value of a store written before values were tagged, in a single batch so a crash
+/// leaves the store as it was. Refuses to open a store of a newer format.
+fn migrate_storage_format(db: &Db, path: &str) {
+    let stored = db
+        .get(STORAGE_FORMAT_KEY)
+        .expect("Failed to read the storage format")
+        .map(|format| u32::from_le_bytes(format.as_ref().try_into().expect("Invalid storage format")))
+        .unwrap_or(0);
+    if stored == STORAGE_FORMAT {
+        return;
+    }
+    if stored > STORAGE_FORMAT {
+        panic!("{} was written with storage format {}, this node reads up to {}", path, stored, STORAGE_FORMAT);
+    }
+
+    let mut batch = sled::Batch::default();
+    let mut migrated = 0;
+    for (key, value) in db.iter().filter_map(Result::ok).filter(|(key, _)| key.len() == 32) {
+        let serialized = compression::decode_untagged(&value)
+            .unwrap_or_else(|| panic!("Failed to read record {} of {} for the storage format migration", hex::encode(&key), path));
+        batch.insert(key, compression::encode(serialized));
+        migrated += 1;
+    }
+    batch.insert(STORAGE_FORMAT_KEY, &STORAGE_FORMAT.to_le_bytes());
+    db.apply_batch(batch).expect("Failed to migrate the database");
+    db.flush().expect("Failed to commit database");
+    if migrated > 0 {
+        info!("Tagged {} records of {} for storage format {}", migrated, path, STORAGE_FORMAT);
+    }
+}
+
+/// Rewrites every record of a store written with an older encoding in the current one, in a
+/// single batch so a crash leaves the store as it was. Refuses to open a store of a newer version,
+/// or one with a record that can't be upgraded, rather than lose records.
+fn migrate<S: StateRecord>(db: &Db, path: &str) {
+    let stored = db
+        .get(ENCODING_VERSION_KEY)
+        .expect("Failed to read the encoding version")
+        .map(|version| u32::from_le_bytes(version.as_ref().try_into().expect("Invalid encoding version")))
+        .unwrap_or(0);
+    if stored == S::ENCODING_VERSION {
+        return;
+    }
+    if stored > S::ENCODING_VERSION {
+        panic!("{} was written with encoding version {}, this node reads up to {}", path, stored, S::ENCODING_VERSION);
+    }
+
+    let mut batch = sled::Batch::default();
+    let mut migrated = 0;
+    for (key, value) in db.iter().filter_map(Result::ok).filter(|(key, _)| key.len() == 32) {
+        let record = compression::decode(&value)
+            .and_then(|value| S::upgrade(stored, &value))
+            .unwrap_or_else(|| panic!("Failed to upgrade record {} of {} from encoding version {}", hex::encode(&key), path, stored));
+        batch.insert(key, compression::encode(to_vec(&record).expect("Failed to serialize record")));
+        migrated += 1;
+    }
+    batch.insert(ENCODING_VERSION_KEY, &S::ENCODING_VERSION.to_le_bytes());
+    db.apply_batch(batch).expect("Failed to migrate the database");
+    db.flush().expect("Failed to commit database");
+    if migrated > 0 {
+        info!("Migrated {} records of {} from encoding version {} to {}", migrated, path, stored, S::ENCODING_VERSION);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::{hash, Hash};
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::system_transaction;
+    use state::account_state::AccountState;
+    use state::block::Block;
+    use state::transaction::TrollupTransaction;
+    use state::witness::account_leaf;
+
+    fn database_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("trollup-sled-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn account_stores_written_before_data_roots_are_migrated() {
+        let path = database_path("account-migration");
+        let (address, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // The encoding of `AccountState` before it had a `data_root`
+        let legacy = to_vec(&(address, 5_000u64, vec![7u8; 16], owner, false, 3u64)).unwrap();
+        {
+            let db = sled::open(&path).unwrap();
+            db.insert(address.to_bytes(), legacy.clone()).unwrap();
+            db.insert("LATEST_BLOCK", &[1u8; 32]).unwrap();
+            db.flush().unwrap();
+        }
+
+        let store = SledStateManagement::<AccountState>::new(&path);
+        let account = store.get_state_record(&address.to_bytes()).unwrap();
+        assert_eq!((account.lamports, account.data.as_slice(), account.owner, account.rent_epoch), (5_000, &[7u8; 16][..], owner, 3));
+        assert_eq!(account.data_root, None);
+        // The leaf of an account without a data root is the hash of the old encoding, as the vault computes it
+        assert_eq!(account_leaf(&account), hash(&legacy).to_bytes());
+        assert_eq!(store.get_latest_block_id(), Some([1u8; 32]));
+        assert_eq!(store.db.get(ENCODING_VERSION_KEY).unwrap().unwrap().as_ref(), &1u32.to_le_bytes());
+        drop(store);
+
+        // Reopening a migrated store leaves it as it is
+        let store = SledStateManagement::<AccountState>::new(&path);
+        assert_eq!(store.get_all_entries().len(), 1);
+        drop(store);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn block_stores_written_before_public_inputs_are_migrated() {
+        let path = database_path("block-migration");
+        let (transactions_root, accounts_root) = ([1u8; 32], [2u8; 32]);
+        let block_hash = hash(&[transactions_root, accounts_root].concat()).to_bytes();
+        let id = Block::get_id(4);
+        // The encoding of `Block` up to its account addresses, with the hash over both roots
+        let legacy = to_vec(&(id, block_hash, Block::get_id(3), 4u64, transactions_root, accounts_root, vec![9u8; 8], vec![[3u8; 32]], vec![[4u8; 32]])).unwrap();
+        {
+            let db = sled::open(&path).unwrap();
+            db.insert(id, legacy).unwrap();
+            db.flush().unwrap();
+        }
+
+        let store = SledStateManagement::<Block>::new(&path);
+        let block = store.get_state_record(&id).unwrap();
+        assert_eq!((block.block_number, *block.accounts_merkle_root, block.accounts_zk_proof.as_slice()), (4, accounts_root, &[9u8; 8][..]));
+        assert_eq!((block.transactions.as_slice(), block.accounts.as_slice()), (&[[3u8; 32]][..], &[[4u8; 32]][..]));
+        assert!(block.accounts_zk_public_inputs.is_empty() && block.outbox_messages.is_empty());
+        assert_eq!(block.hash(), block_hash);
+        assert!(block.has_consistent_hash());
+        drop(store);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn transaction_stores_written_before_the_system_lane_are_migrated() {
+        let path = database_path("transaction-migration");
+        let keypair = Keypair::new();
+        let transfer = system_transaction::transfer(&keypair, &Pubkey::new_unique(), 10, Hash::default());
+        let transaction = TrollupTransaction { optimistic: true, ..TrollupTransaction::from(&transfer) };
+        // The encoding of `TrollupTransaction` before it had `system`
+        let legacy = to_vec(&(transaction.optimistic, &transaction.signatures, &transaction.message)).unwrap();
+        {
+            let db = sled::open(&path).unwrap();
+            db.insert(transaction.get_key(), legacy).unwrap();
+            db.flush().unwrap();
+        }
+
+        let store = SledStateManagement::<TrollupTransaction>::new(&path);
+        let migrated = store.get_state_record(&transaction.get_key()).unwrap();
+        assert!(migrated.optimistic && !migrated.system);
+        assert_eq!(migrated.signatures, transaction.signatures);
+        assert_eq!(to_vec(&migrated.message).unwrap(), to_vec(&transaction.message).unwrap());
+        drop(store);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn records_that_look_compressed_survive_the_format_migration() {
+        let path = database_path("storage-format");
+        let mut address = [7u8; 32];
+        address[..4].copy_from_slice(b"TZS0");
+        let account = AccountState {
+            address: Pubkey::new_from_array(address),
+            lamports: 9,
+            data: vec![],
+            owner: Pubkey::default(),
+            executable: false,
+            rent_epoch: 0,
+            data_root: None,
+        };
+        {
+            let db = sled::open(&path).unwrap();
+            // Written raw before values were tagged, and starting with the compression prefix
+            db.insert(address, to_vec(&account).unwrap()).unwrap();
+            db.insert(ENCODING_VERSION_KEY, &AccountState::ENCODING_VERSION.to_le_bytes()).unwrap();
+            db.flush().unwrap();
+        }
+
+        let store = SledStateManagement::<AccountState>::new(&path);
+        assert_eq!(store.get_state_record(&address).unwrap().lamports, 9);
+        assert_eq!(store.db.get(STORAGE_FORMAT_KEY).unwrap().unwrap().as_ref(), &STORAGE_FORMAT.to_le_bytes());
+        store.set_state_record(&AccountState { lamports: 10, ..account });
+        drop(store);
+
+        // Reopening a migrated store leaves it as it is
+        let store = SledStateManagement::<AccountState>::new(&path);
+        assert_eq!(store.get_state_record(&address).unwrap().lamports, 10);
+        drop(store);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "encoding version 2")]
+    fn stores_of_a_newer_version_are_refused() {
+        let path = database_path("newer-encoding");
+        {
+            let db = sled::open(&path).unwrap();
+            db.insert(ENCODING_VERSION_KEY, &2u32.to_le_bytes()).unwrap();
+            db.flush().unwrap();
+        }
+        SledStateManagement::<AccountState>::new(&path);
+    }
+}
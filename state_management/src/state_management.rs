@@ -6,6 +6,8 @@ pub trait ManageState {
 
     fn new(path: &str) -> Self;
     fn get_all_entries(&self) -> Vec<([u8;32], Self::Record)>;
+    /// Like `get_all_entries`, reading records one at a time as the iterator is advanced.
+    fn iter_entries(&self) -> Box<dyn Iterator<Item=([u8;32], Self::Record)> + '_>;
     fn get_state_record(&self, key: &[u8]) -> Option<Self::Record>;
     fn set_state_record(&self, state: &Self::Record);
     fn set_state_records(&self, records: &Vec<Self::Record>);
@@ -54,6 +56,10 @@ impl<T: ManageState> StateManager<T> {
         self.manage_state.get_all_entries()
     }
 
+    pub fn iter_entries(&self) -> Box<dyn Iterator<Item=([u8;32], T::Record)> + '_> {
+        self.manage_state.iter_entries()
+    }
+
     pub fn get_state_record(&self, key: &[u8; 32]) -> Option<T::Record> {
         self.manage_state.get_state_record(key)
     }
@@ -0,0 +1,321 @@
+//! Measures the compute units the on-chain verifiers consume, running the SBF builds of the
+//! programs under solana-program-test's compute meter. Build the programs with `cargo build-sbf`
+//! first and point `SBF_OUT_DIR` at their `target/deploy` directories.
+//!
+//! ```shell
+//! cargo run --release -- --out report.json --baseline baseline.json --max-regression-percent 5
+//! ```
+//!
+//! The report lists every scenario and the compute unit limits to configure in the committer
+//! (`PROOF_VERIFY_COMPUTE_UNIT_LIMIT` and `SIGNATURE_VERIFY_COMPUTE_UNIT_LIMIT`). With a baseline
+//! the run fails when a scenario got more expensive than the allowed regression.
+
+use borsh::{to_vec, BorshDeserialize};
+use libsecp256k1::{Message, PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::pubkey::Pubkey;
+use solana_program_test::{ProgramTest, ProgramTestContext};
+use solana_sdk::account::{Account, AccountSharedData};
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+use trollup_zk::fixtures::{accounts_fixture, proof_commitment_package_fixture_for};
+use validator_signature_verify::{BlockProducer, NodeVersion};
+
+/// Added on top of the most expensive scenario of a program for its recommended limit
+const HEADROOM_PERCENT: u64 = 20;
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+/// Batch sizes the proof verifier is measured with
+const PROOF_BATCH_SIZES: [u8; 4] = [1, 2, 8, 32];
+/// Roots per `VerifySigBatch` measured, about as many as fit in a transaction
+const SIGNATURE_BATCH_ROOTS: u64 = 4;
+/// Signed into every commitment, the size is what matters
+const PRODUCER: BlockProducer = BlockProducer { sequencer: [4u8; 32], version: NodeVersion::new(0, 1, 0) };
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Measurement {
+    program: String,
+    scenario: String,
+    compute_units: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    measurements: Vec<Measurement>,
+    /// Config key to compute unit limit
+    recommended_limits: BTreeMap<String, u64>,
+}
+
+struct Args {
+    out: Option<String>,
+    baseline: Option<String>,
+    max_regression_percent: u64,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args { out: None, baseline: None, max_regression_percent: 5 };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--out" => args.out = Some(value()?),
+            "--baseline" => args.baseline = Some(value()?),
+            "--max-regression-percent" => args.max_regression_percent = value()?.parse().map_err(|_| "--max-regression-percent must be a number".to_string())?,
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(args)
+}
+
+/// Starts a bank with the program and its state PDA initialized by `initialize`.
+async fn start(program_name: &'static str, initialize: Vec<u8>) -> (ProgramTestContext, Pubkey, Pubkey) {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::default();
+    // Native processors don't meter compute units, always load the SBF build
+    program_test.prefer_bpf(true);
+    program_test.add_program(program_name, program_id, None);
+    let mut context = program_test.start_with_context().await;
+    let (pda, _) = Pubkey::find_program_address(&[b"state"], &program_id);
+
+    let initialize = Instruction::new_with_bytes(
+        program_id,
+        &initialize,
+        vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new(context.payer.pubkey(), true),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        ],
+    );
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(&[initialize], Some(&context.payer.pubkey()), &[&context.payer], blockhash);
+    context.banks_client.process_transaction(transaction).await.expect("Initialize failed");
+    (context, program_id, pda)
+}
+
+/// Compute units `instruction` consumes, failing when the instruction does. It runs with the
+/// maximum limit so an expensive instruction is measured rather than cut off at the default.
+async fn measure(context: &mut ProgramTestContext, instruction: Instruction) -> Result<u64, String> {
+    measure_all(context, vec![instruction]).await
+}
+
+/// `measure` for instructions that only work together in one transaction.
+async fn measure_all(context: &mut ProgramTestContext, mut instructions: Vec<Instruction>) -> Result<u64, String> {
+    let blockhash = context.get_new_latest_blockhash().await.map_err(|e| e.to_string())?;
+    instructions.insert(0, ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT));
+    let transaction = Transaction::new_signed_with_payer(&instructions, Some(&context.payer.pubkey()), &[&context.payer], blockhash);
+    let simulation = context.banks_client.simulate_transaction(transaction).await.map_err(|e| e.to_string())?;
+    if let Some(Err(error)) = simulation.result {
+        return Err(format!("{:?}", error));
+    }
+    simulation
+        .simulation_details
+        .map(|details| details.units_consumed)
+        .ok_or_else(|| "No simulation details".to_string())
+}
+
+async fn bench_proof_verify() -> Result<Vec<Measurement>, String> {
+    let mut measurements = Vec::new();
+    for batch_size in PROOF_BATCH_SIZES {
+        let (mut context, program_id, pda) = start("trollup_proof_verifier", to_vec(&trollup_proof_verifier::ProgramInstruction::Initialize).unwrap()).await;
+        // Round trip through the program's decoder, like the program's tests do
+        let (package, verifying_key) = proof_commitment_package_fixture_for([7u8; 32], accounts_fixture(batch_size));
+        let package = trollup_proof_verifier::ProofCommitmentPackage::try_from_slice(&to_vec(&package).unwrap()).map_err(|e| e.to_string())?;
+        let data = to_vec(&trollup_proof_verifier::ProgramInstruction::VerifyProof(package)).unwrap();
+        // Only VerifyProof is measured, the key is written the way SetVerifyingKey stores it
+        let (verifying_key_pda, _) = Pubkey::find_program_address(&[trollup_proof_verifier::VERIFYING_KEY_SEED], &program_id);
+        let verifying_key_account = Account {
+            lamports: 1_000_000_000,
+            data: to_vec(&verifying_key).unwrap(),
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        };
+        context.set_account(&verifying_key_pda, &AccountSharedData::from(verifying_key_account));
+        let accounts = vec![AccountMeta::new(pda, false), AccountMeta::new_readonly(verifying_key_pda, false)];
+        let compute_units = measure(&mut context, Instruction::new_with_bytes(program_id, &data, accounts)).await?;
+        measurements.push(Measurement {
+            program: "proof-verify".to_string(),
+            scenario: format!("{} accounts", batch_size),
+            compute_units,
+        });
+    }
+    Ok(measurements)
+}
+
+async fn bench_signature_verify() -> Result<Vec<Measurement>, String> {
+    let mut measurements = Vec::new();
+    for (scenario, outbox_root) in [("empty outbox", [0u8; 32]), ("with outbox", [5u8; 32])] {
+        let (mut context, program_id, pda) = start("validator_signature_verify", to_vec(&validator_signature_verify::ProgramInstruction::Initialize).unwrap()).await;
+        let new_state_root = [9u8; 32];
+        let message_hash = validator_signature_verify::commitment_message_hash(&new_state_root, 1, &outbox_root, &PRODUCER);
+        let secret_key = SecretKey::parse(&[3u8; 32]).unwrap();
+        let (signature, recovery_id) = libsecp256k1::sign(&Message::parse_slice(&message_hash).unwrap(), &secret_key);
+        let commitment = validator_signature_verify::ZkProofCommitment {
+            verifier_signature: signature.serialize(),
+            recovery_id: recovery_id.serialize(),
+            public_key: PublicKey::from_secret_key(&secret_key).serialize(),
+            new_state_root,
+            sequence: 1,
+            outbox_root,
+            producer: PRODUCER,
+        };
+        let data = to_vec(&validator_signature_verify::ProgramInstruction::VerifySig(commitment)).unwrap();
+        let compute_units = measure(&mut context, Instruction::new_with_bytes(program_id, &data, vec![AccountMeta::new(pda, false)])).await?;
+        measurements.push(Measurement {
+            program: "signature-verify".to_string(),
+            scenario: scenario.to_string(),
+            compute_units,
+        });
+    }
+
+    // The ed25519 program's verification isn't metered, only the introspection is
+    let (mut context, program_id, pda) = start("validator_signature_verify", to_vec(&validator_signature_verify::ProgramInstruction::Initialize).unwrap()).await;
+    let message_hash = validator_signature_verify::commitment_message_hash(&[9u8; 32], 1, &[5u8; 32], &PRODUCER);
+    let keypair = Keypair::new();
+    let signature: [u8; 64] = keypair.sign_message(&message_hash).as_ref().try_into().unwrap();
+    let signature_instruction = validator_signature_verify::ed25519_signature_instruction(&keypair.pubkey().to_bytes(), &signature, &message_hash);
+    let commitment = validator_signature_verify::Ed25519Commitment {
+        public_key: keypair.pubkey().to_bytes(),
+        new_state_root: [9u8; 32],
+        sequence: 1,
+        outbox_root: [5u8; 32],
+        producer: PRODUCER,
+    };
+    let data = to_vec(&validator_signature_verify::ProgramInstruction::VerifyEd25519(commitment)).unwrap();
+    let verify = Instruction::new_with_bytes(program_id, &data, vec![
+        AccountMeta::new(pda, false),
+        AccountMeta::new_readonly(solana_program::sysvar::instructions::id(), false),
+    ]);
+    let compute_units = measure_all(&mut context, vec![signature_instruction, verify]).await?;
+    measurements.push(Measurement {
+        program: "signature-verify".to_string(),
+        scenario: "ed25519".to_string(),
+        compute_units,
+    });
+
+    // The validator requests the per root limit once per root of a batch, so that's what's
+    // measured
+    let (mut context, program_id, pda) = start("validator_signature_verify", to_vec(&validator_signature_verify::ProgramInstruction::Initialize).unwrap()).await;
+    let secret_key = SecretKey::parse(&[3u8; 32]).unwrap();
+    let mut parent_state_root = [0u8; 32];
+    let mut commitments = Vec::new();
+    for sequence in 1..=SIGNATURE_BATCH_ROOTS {
+        let new_state_root = [sequence as u8 + 10; 32];
+        let message_hash = validator_signature_verify::chained_commitment_message_hash(&parent_state_root, &new_state_root, sequence, &[5u8; 32], &PRODUCER);
+        let (signature, recovery_id) = libsecp256k1::sign(&Message::parse_slice(&message_hash).unwrap(), &secret_key);
+        commitments.push(validator_signature_verify::ChainedCommitment {
+            verifier_signature: signature.serialize(),
+            recovery_id: recovery_id.serialize(),
+            public_key: PublicKey::from_secret_key(&secret_key).serialize(),
+            parent_state_root,
+            new_state_root,
+            sequence,
+            outbox_root: [5u8; 32],
+            producer: PRODUCER,
+        });
+        parent_state_root = new_state_root;
+    }
+    let data = to_vec(&validator_signature_verify::ProgramInstruction::VerifySigBatch(commitments)).unwrap();
+    let compute_units = measure(&mut context, Instruction::new_with_bytes(program_id, &data, vec![AccountMeta::new(pda, false)])).await?;
+    measurements.push(Measurement {
+        program: "signature-verify".to_string(),
+        scenario: format!("batch of {}, per root", SIGNATURE_BATCH_ROOTS),
+        compute_units: compute_units.div_ceil(SIGNATURE_BATCH_ROOTS),
+    });
+    Ok(measurements)
+}
+
+/// The most expensive scenario plus headroom, rounded up to a thousand units.
+fn recommended_limit(measurements: &[Measurement], program: &str) -> u64 {
+    let max = measurements.iter().filter(|m| m.program == program).map(|m| m.compute_units).max().unwrap_or_default();
+    (max * (100 + HEADROOM_PERCENT) / 100).div_ceil(1_000) * 1_000
+}
+
+/// Scenarios more expensive than in `baseline` by more than `max_regression_percent`.
+fn regressions(report: &Report, baseline: &Report, max_regression_percent: u64) -> Vec<String> {
+    report
+        .measurements
+        .iter()
+        .filter_map(|current| {
+            let previous = baseline.measurements.iter().find(|m| m.program == current.program && m.scenario == current.scenario)?;
+            let allowed = previous.compute_units + previous.compute_units * max_regression_percent / 100;
+            (current.compute_units > allowed).then(|| format!(
+                "{} ({}): {} compute units, baseline {}",
+                current.program, current.scenario, current.compute_units, previous.compute_units,
+            ))
+        })
+        .collect()
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut measurements = Vec::new();
+    for result in [bench_proof_verify().await, bench_signature_verify().await] {
+        match result {
+            Ok(mut program_measurements) => measurements.append(&mut program_measurements),
+            Err(error) => {
+                eprintln!("Benchmark failed: {}", error);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let report = Report {
+        recommended_limits: BTreeMap::from([
+            ("PROOF_VERIFY_COMPUTE_UNIT_LIMIT".to_string(), recommended_limit(&measurements, "proof-verify")),
+            ("SIGNATURE_VERIFY_COMPUTE_UNIT_LIMIT".to_string(), recommended_limit(&measurements, "signature-verify")),
+        ]),
+        measurements,
+    };
+
+    for measurement in &report.measurements {
+        println!("{:<18} {:<28} {:>10}", measurement.program, measurement.scenario, measurement.compute_units);
+    }
+    for (key, limit) in &report.recommended_limits {
+        println!("{}={}", key, limit);
+    }
+    let json = serde_json::to_string_pretty(&report).unwrap();
+    if let Some(out) = &args.out {
+        if let Err(error) = std::fs::write(out, &json) {
+            eprintln!("Writing {}: {}", out, error);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let Some(baseline_path) = &args.baseline else {
+        return ExitCode::SUCCESS;
+    };
+    // Nothing to gate on until a report was committed as the baseline
+    if !std::path::Path::new(baseline_path).exists() {
+        println!("No baseline at {}, skipping the regression check", baseline_path);
+        return ExitCode::SUCCESS;
+    }
+    let baseline: Report = match std::fs::read_to_string(baseline_path).map_err(|e| e.to_string()).and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string())) {
+        Ok(baseline) => baseline,
+        Err(error) => {
+            eprintln!("Reading baseline {}: {}", baseline_path, error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let regressions = regressions(&report, &baseline, args.max_regression_percent);
+    if regressions.is_empty() {
+        return ExitCode::SUCCESS;
+    }
+    eprintln!("Compute unit regressions beyond {}%:", args.max_regression_percent);
+    for regression in regressions {
+        eprintln!("  {}", regression);
+    }
+    ExitCode::FAILURE
+}
@@ -20,10 +20,33 @@ use light_poseidon::{Poseidon, PoseidonHasher};
 use borsh::{BorshSerialize, BorshDeserialize, to_vec};
 use base64::{encode, decode};
 use tokio::fs;
+use trollup_zk::fixtures::build_on_chain_verifying_key;
+use trollup_zk::prove::load_verifying_key;
+use trollup_zk::verify_lite::{OnChainVerifyingKey, ProofCommitmentPackage};
+
+#[derive(BorshSerialize)]
+struct ValidatorKeys {
+    secp256k1: [u8; 65],
+    ed25519: [u8; 32],
+}
+
+/// The signature verifier's instructions, up to `SetValidator`
+#[derive(BorshSerialize)]
+#[allow(dead_code)]
+enum SignatureVerifierInstruction {
+    Initialize,
+    VerifySig,
+    VerifySigBatch,
+    VerifyEd25519,
+    SetValidator(ValidatorKeys),
+}
 
 #[derive(BorshSerialize)]
 enum ProgramInstruction {
-    Initialize
+    Initialize,
+    #[allow(dead_code)]
+    VerifyProof(ProofCommitmentPackage),
+    SetVerifyingKey(OnChainVerifyingKey),
 }
 
 const PROOF_VERIFIER_PROGRAM_ID: &str = "F68FK2Ai4vWVqFQpfx6RJjzpYieSzxWMqs179SBdcZVJ";
@@ -76,7 +99,62 @@ async fn main() {
     let signature = client.send_and_confirm_transaction(&transaction).await.unwrap();
     println!("Initialization transaction sent successfully!");
     println!("Transaction signature: {}", signature);
-    
+
+    // Store the verifying key of the keys in vk.bin, the payer must be the program's upgrade authority
+    let verifying_key = load_verifying_key().expect("Error loading vk.bin, run the sequencer's setup first");
+    let (verifying_key_pda, _) = Pubkey::find_program_address(&[b"verifying_key"], &program_id);
+    let (program_data, _) = Pubkey::find_program_address(&[program_id.as_ref()], &solana_sdk::bpf_loader_upgradeable::id());
+    let instruction = Instruction::new_with_bytes(
+        program_id,
+        &to_vec(&ProgramInstruction::SetVerifyingKey(build_on_chain_verifying_key(&verifying_key))).unwrap(),
+        vec![
+            AccountMeta::new(verifying_key_pda, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(program_data, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ],
+    );
+    let recent_blockhash = client.get_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], recent_blockhash);
+    let signature = client.send_and_confirm_transaction(&transaction).await.unwrap();
+    println!("Verifying key set, transaction signature: {}", signature);
+
+    // Create the signature verifier's state and pin the validator keys, the payer must be that
+    // program's upgrade authority too
+    let program_id = Pubkey::from_str(SIGNATURE_VERIFIER_PROGRAM_ID).unwrap();
+    let (pda, _) = Pubkey::find_program_address(&[b"state"], &program_id);
+    let (program_data, _) = Pubkey::find_program_address(&[program_id.as_ref()], &solana_sdk::bpf_loader_upgradeable::id());
+    // The validator's secp256k1 signer still uses the default secret key, see
+    // validator/src/signing.rs. Ed25519 commitments are signed with the API keypair unless
+    // COMMITMENT_SIGNER_KEYPAIR is set.
+    let validator_keys = ValidatorKeys {
+        secp256k1: libsecp256k1::PublicKey::from_secret_key(&libsecp256k1::SecretKey::default()).serialize(),
+        ed25519: payer.pubkey().to_bytes(),
+    };
+    let instructions = [
+        Instruction::new_with_bytes(
+            program_id,
+            &to_vec(&SignatureVerifierInstruction::Initialize).unwrap(),
+            vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            ],
+        ),
+        Instruction::new_with_bytes(
+            program_id,
+            &to_vec(&SignatureVerifierInstruction::SetValidator(validator_keys)).unwrap(),
+            vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(program_data, false),
+            ],
+        ),
+    ];
+    let recent_blockhash = client.get_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(&instructions, Some(&payer.pubkey()), &[&payer], recent_blockhash);
+    let signature = client.send_and_confirm_transaction(&transaction).await.unwrap();
+    println!("Signature verifier initialized, transaction signature: {}", signature);
 }
 
 async fn request_airdrop(client: &RpcClient, pubkey: &Pubkey, amount: u64) -> Result<(), Box<dyn std::error::Error>> {
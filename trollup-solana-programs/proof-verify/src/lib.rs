@@ -2,6 +2,7 @@ use crate::Groth16Error::ProofVerificationFailed;
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::account_info::next_account_info;
 use solana_program::alt_bn128::prelude::*;
+use solana_program::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
 use solana_program::program::invoke_signed;
 use solana_program::program_error::ProgramError;
 use solana_program::rent::Rent;
@@ -10,13 +11,32 @@ use solana_program::{account_info::AccountInfo, entrypoint, entrypoint::ProgramR
 use thiserror::Error;
 
 // Program's entrypoint
+#[cfg(not(feature = "no-entrypoint"))]
 entrypoint!(process_instruction);
 
+/// Public inputs of the batch circuit: account hash, lamports sum and the two halves of the
+/// state root.
+pub const PUBLIC_INPUT_COUNT: usize = 4;
+/// Public inputs the prover sends, the state root halves are derived here.
+pub const BATCH_INPUT_COUNT: usize = PUBLIC_INPUT_COUNT - 2;
+pub const VERIFYING_KEY_SEED: &[u8] = b"verifying_key";
+/// Borsh size of `Groth16VerifyingKey`
+pub const VERIFYING_KEY_SIZE: usize = 64 + 3 * 128 + (PUBLIC_INPUT_COUNT + 1) * 64;
+
+/// The BN254 scalar field modulus, big-endian. Public inputs must be below it.
+const FIELD_MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x28, 0x33, 0xe8, 0x48, 0x79, 0xb9, 0x70, 0x91, 0x43, 0xe1, 0xf5, 0x93, 0xf0, 0x00, 0x00, 0x01,
+];
+
 // Define the instruction enum
 #[derive(BorshSerialize, BorshDeserialize)]
 pub enum ProgramInstruction {
     Initialize,
     VerifyProof(ProofCommitmentPackage),
+    /// Stores the verifying key proofs are checked against. Only the program's upgrade authority
+    /// can set it.
+    SetVerifyingKey(Groth16VerifyingKey),
 }
 
 pub fn process_instruction(
@@ -29,6 +49,7 @@ pub fn process_instruction(
     match instruction {
         ProgramInstruction::Initialize => initialize(program_id, accounts),
         ProgramInstruction::VerifyProof(proof_package) => verify_proof(program_id, accounts, proof_package),
+        ProgramInstruction::SetVerifyingKey(verifying_key) => set_verifying_key(program_id, accounts, verifying_key),
     }
 }
 
@@ -73,9 +94,76 @@ fn initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     Ok(())
 }
 
+/// Creates or replaces the verifying key account. The signer must be the upgrade authority in
+/// the program's ProgramData account, so whoever can change the program's code is the only one
+/// who can change what it accepts.
+fn set_verifying_key(program_id: &Pubkey, accounts: &[AccountInfo], verifying_key: Groth16VerifyingKey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let verifying_key_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let program_data = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    let (pda, bump_seed) = Pubkey::find_program_address(&[VERIFYING_KEY_SEED], program_id);
+    if verifying_key_account.key != &pda {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+
+    if !authority.is_signer || upgrade_authority(program_id, program_data)? != Some(*authority.key) {
+        msg!("Only the upgrade authority can set the verifying key.");
+        return Err(ProgramError::MissingRequiredSignature.into());
+    }
+
+    if verifying_key_account.data_is_empty() {
+        let lamports = Rent::get()?.minimum_balance(VERIFYING_KEY_SIZE);
+        invoke_signed(
+            &system_instruction::create_account(
+                authority.key,
+                verifying_key_account.key,
+                lamports,
+                VERIFYING_KEY_SIZE as u64,
+                program_id,
+            ),
+            &[
+                authority.clone(),
+                verifying_key_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[VERIFYING_KEY_SEED, &[bump_seed]]],
+        )?;
+    } else if verifying_key_account.owner != program_id {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+
+    verifying_key.serialize(&mut &mut verifying_key_account.try_borrow_mut_data()?[..])?;
+    msg!("Verifying key set");
+    Ok(())
+}
+
+/// The upgrade authority recorded in `program_data`, which must be the ProgramData account of
+/// `program_id`. `None` once the program is immutable.
+fn upgrade_authority(program_id: &Pubkey, program_data: &AccountInfo) -> Result<Option<Pubkey>, ProgramError> {
+    let (expected, _) = Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    if program_data.key != &expected || program_data.owner != &bpf_loader_upgradeable::id() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // ProgramData metadata: u32 variant (3), u64 slot, Option<Pubkey> authority
+    let data = program_data.try_borrow_data()?;
+    if data.len() < UpgradeableLoaderState::size_of_programdata_metadata() || data[..4] != 3u32.to_le_bytes() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    match data[12] {
+        0 => Ok(None),
+        1 => Ok(Some(Pubkey::new_from_array(data[13..45].try_into().unwrap()))),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
 fn verify_proof(program_id: &Pubkey, accounts: &[AccountInfo], proof_package: ProofCommitmentPackage) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let state_account = next_account_info(account_info_iter)?;
+    let verifying_key_account = next_account_info(account_info_iter)?;
 
     let (pda, _) = Pubkey::find_program_address(&[b"state"], program_id);
 
@@ -87,12 +175,18 @@ fn verify_proof(program_id: &Pubkey, accounts: &[AccountInfo], proof_package: Pr
         return Err(ProgramError::InvalidAccountData.into());
     }
 
-    let mut prepared_verifier = proof_package.groth16_verifier_prepared;
-    let result = prepared_verifier.verify().expect("Error deserializing verifier");
+    let verifying_key = load_verifying_key(program_id, verifying_key_account)?;
+    let public_inputs = proof_package.public_inputs();
+    let result = prepare_public_inputs(&verifying_key, &public_inputs).and_then(|prepared_public_inputs| {
+        let proof = proof_package.proof;
+        Groth16VerifierPrepared::new(proof.proof_a, proof.proof_b, proof.proof_c, prepared_public_inputs, Box::new(verifying_key))?.verify()
+    });
 
-    if result {
+    if let Ok(true) = result {
         msg!("Proof is valid! Account properties verified.");
         update_on_chain_state(&proof_package.state_root, state_account)?;
+        #[cfg(feature = "vault-cpi")]
+        unlock_vault_root(program_id, account_info_iter, state_account, proof_package.state_root)?;
         Ok(())
     } else {
         msg!("Proof is invalid!");
@@ -100,6 +194,35 @@ fn verify_proof(program_id: &Pubkey, accounts: &[AccountInfo], proof_package: Pr
     }
 }
 
+/// The verifying key stored by `SetVerifyingKey`. Proofs can't be verified before it is set.
+fn load_verifying_key(program_id: &Pubkey, verifying_key_account: &AccountInfo) -> Result<Groth16VerifyingKey, ProgramError> {
+    let (pda, _) = Pubkey::find_program_address(&[VERIFYING_KEY_SEED], program_id);
+    if verifying_key_account.key != &pda || verifying_key_account.owner != program_id {
+        msg!("The verifying key is not set.");
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Groth16VerifyingKey::deserialize(&mut &verifying_key_account.try_borrow_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)
+}
+
+/// Folds `public_inputs` into the verifying key's IC points, `ic[0] + sum(input_i * ic[i + 1])`,
+/// with the alt_bn128 syscalls.
+pub fn prepare_public_inputs(verifying_key: &Groth16VerifyingKey, public_inputs: &[[u8; 32]; PUBLIC_INPUT_COUNT]) -> Result<[u8; 64], Groth16Error> {
+    let mut prepared = verifying_key.vk_ic[0];
+    for (input, ic) in public_inputs.iter().zip(&verifying_key.vk_ic[1..]) {
+        if input >= &FIELD_MODULUS {
+            return Err(Groth16Error::PublicInputGreaterThenFieldSize);
+        }
+        let product = alt_bn128_multiplication(&[&ic[..], &input[..]].concat())
+            .map_err(|_| Groth16Error::PreparingInputsG1MulFailed)?;
+        prepared = alt_bn128_addition(&[&product[..], &prepared[..]].concat())
+            .map_err(|_| Groth16Error::PreparingInputsG1AdditionFailed)?
+            .try_into()
+            .map_err(|_| Groth16Error::PreparingInputsG1AdditionFailed)?;
+    }
+    Ok(prepared)
+}
+
 
 fn update_on_chain_state(state_root: &[u8; 32], account: &AccountInfo) -> ProgramResult {
     msg!("Updating state account.");
@@ -109,6 +232,10 @@ fn update_on_chain_state(state_root: &[u8; 32], account: &AccountInfo) -> Progra
         return Err(ProgramError::InvalidAccountData.into());
     }
 
+    if account.data_len() < 32 {
+        return Err(ProgramError::AccountDataTooSmall.into());
+    }
+
     // Update the state root
     // invoke_signed(
     //     &system_instruction::transfer(account.key, account.key, 0),
@@ -121,18 +248,67 @@ fn update_on_chain_state(state_root: &[u8; 32], account: &AccountInfo) -> Progra
     Ok(())
 }
 
+/// Lets the vault pay out withdrawals included in the newly accepted root. The vault program and
+/// vault accounts follow the verifying key account and are required, a root the vault doesn't
+/// learn about would leave its withdrawals locked.
+#[cfg(feature = "vault-cpi")]
+fn unlock_vault_root<'a, 'b: 'a>(
+    program_id: &Pubkey,
+    account_info_iter: &mut impl Iterator<Item = &'a AccountInfo<'b>>,
+    state_account: &AccountInfo<'b>,
+    state_root: [u8; 32],
+) -> ProgramResult {
+    let vault_program = next_account_info(account_info_iter)?;
+    let vault = next_account_info(account_info_iter)?;
+    let (_, bump_seed) = Pubkey::find_program_address(&[b"state"], program_id);
+    msg!("Unlocking withdrawal root in the vault.");
+    invoke_signed(
+        &trollup_vault::unlock_withdrawal_root(vault_program.key, state_account.key, state_root),
+        &[vault.clone(), state_account.clone(), vault_program.clone()],
+        &[&[b"state", &[bump_seed]]],
+    )
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, BorshSerialize, BorshDeserialize)]
 pub struct ProofCommitmentPackage {
-    groth16_verifier_prepared: Groth16VerifierPrepared,
+    proof: Groth16Proof,
+    /// Account hash and lamports sum, big-endian
+    batch_inputs: [[u8; 32]; BATCH_INPUT_COUNT],
     state_root: [u8; 32]
 }
 
+impl ProofCommitmentPackage {
+    pub fn new(proof: Groth16Proof, batch_inputs: [[u8; 32]; BATCH_INPUT_COUNT], state_root: [u8; 32]) -> Self {
+        ProofCommitmentPackage { proof, batch_inputs, state_root }
+    }
+
+    /// The batch inputs followed by the state root's first and last 16 bytes, each zero padded to
+    /// a big-endian field element. The proof only verifies for the root it was generated for.
+    pub fn public_inputs(&self) -> [[u8; 32]; PUBLIC_INPUT_COUNT] {
+        let mut state_root_high = [0u8; 32];
+        state_root_high[16..].copy_from_slice(&self.state_root[..16]);
+        let mut state_root_low = [0u8; 32];
+        state_root_low[16..].copy_from_slice(&self.state_root[16..]);
+        [self.batch_inputs[0], self.batch_inputs[1], state_root_high, state_root_low]
+    }
+}
+
+/// A proof in the big-endian layout of the alt_bn128 syscalls, `a` negated.
+#[derive(PartialEq, Eq, Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct Groth16Proof {
+    pub proof_a: [u8; 64],
+    pub proof_b: [u8; 128],
+    pub proof_c: [u8; 64],
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, BorshSerialize, BorshDeserialize)]
 pub struct Groth16VerifyingKey {
     pub vk_alpha_g1: [u8; 64],
     pub vk_beta_g2: [u8; 128],
     pub vk_gamma_g2: [u8; 128],
     pub vk_delta_g2: [u8; 128],
+    /// One point per public input plus one
+    pub vk_ic: [[u8; 64]; PUBLIC_INPUT_COUNT + 1],
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, BorshSerialize, BorshDeserialize)]
@@ -0,0 +1,300 @@
+use borsh::{to_vec, BorshDeserialize};
+use solana_program::bpf_loader_upgradeable;
+use solana_program::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_program::pubkey::Pubkey;
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+use trollup_commitment_message::instruction_vectors::{instruction_vectors, Instruction as InstructionKind};
+use trollup_proof_verifier::{process_instruction, Groth16Proof, Groth16VerifyingKey, ProgramInstruction, ProofCommitmentPackage, VERIFYING_KEY_SEED};
+use trollup_zk::fixtures::proof_commitment_package_fixture;
+
+/// The verifier with a ProgramData account naming `authority` as its upgrade authority, as the
+/// upgradeable loader would have written it.
+fn program_test(program_id: Pubkey, authority: &Keypair) -> ProgramTest {
+    let mut program_test = ProgramTest::new("trollup_proof_verifier", program_id, processor!(process_instruction));
+    let mut data = 3u32.to_le_bytes().to_vec();
+    data.extend_from_slice(&0u64.to_le_bytes());
+    data.push(1);
+    data.extend_from_slice(authority.pubkey().as_ref());
+    program_test.add_account(program_data(program_id), Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: bpf_loader_upgradeable::id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+    program_test.add_account(authority.pubkey(), Account::new(1_000_000_000, 0, &solana_program::system_program::id()));
+    program_test
+}
+
+fn program_data(program_id: Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+fn verifying_key_pda(program_id: Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[VERIFYING_KEY_SEED], &program_id).0
+}
+
+async fn start() -> (ProgramTestContext, Pubkey, Pubkey, Keypair) {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let context = program_test(program_id, &authority).start_with_context().await;
+    let (pda, _) = Pubkey::find_program_address(&[b"state"], &program_id);
+    (context, program_id, pda, authority)
+}
+
+async fn send(context: &mut ProgramTestContext, instruction: Instruction) -> Result<(), TransactionError> {
+    send_signed(context, instruction, &[]).await
+}
+
+async fn send_signed(context: &mut ProgramTestContext, instruction: Instruction, signers: &[&Keypair]) -> Result<(), TransactionError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &[&[&context.payer], signers].concat(),
+        blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.map_err(|e| e.unwrap())
+}
+
+fn initialize_instruction(program_id: Pubkey, pda: Pubkey, payer: Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        program_id,
+        &to_vec(&ProgramInstruction::Initialize).unwrap(),
+        vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        ],
+    )
+}
+
+fn set_verifying_key_instruction(program_id: Pubkey, authority: Pubkey, data: &[u8]) -> Instruction {
+    Instruction::new_with_bytes(
+        program_id,
+        data,
+        vec![
+            AccountMeta::new(verifying_key_pda(program_id), false),
+            AccountMeta::new(authority, true),
+            AccountMeta::new_readonly(program_data(program_id), false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        ],
+    )
+}
+
+/// The `VerifyProof` data for a proof of `state_root` and the `SetVerifyingKey` data of the key
+/// it verifies with.
+fn fixture_instruction_data(state_root: [u8; 32]) -> (Vec<u8>, Vec<u8>) {
+    // Round trip the zk crate's encodings through the program's decoders to make sure they agree.
+    let (package, verifying_key) = proof_commitment_package_fixture(state_root);
+    let package = ProofCommitmentPackage::try_from_slice(&to_vec(&package).unwrap()).expect("zk crate and program encodings diverged");
+    let verifying_key = Groth16VerifyingKey::try_from_slice(&to_vec(&verifying_key).unwrap()).expect("zk crate and program encodings diverged");
+    (to_vec(&ProgramInstruction::VerifyProof(package)).unwrap(), to_vec(&ProgramInstruction::SetVerifyingKey(verifying_key)).unwrap())
+}
+
+fn verify_instruction(program_id: Pubkey, state_account: AccountMeta, data: &[u8]) -> Instruction {
+    Instruction::new_with_bytes(program_id, data, vec![state_account, AccountMeta::new_readonly(verifying_key_pda(program_id), false)])
+}
+
+async fn initialize(context: &mut ProgramTestContext, program_id: Pubkey, pda: Pubkey) {
+    let payer = context.payer.pubkey();
+    send(context, initialize_instruction(program_id, pda, payer)).await.unwrap();
+}
+
+/// Initializes the state account and stores the verifying key of a fresh proof of `state_root`,
+/// returns the `VerifyProof` data of that proof.
+async fn initialize_with_proof(context: &mut ProgramTestContext, program_id: Pubkey, pda: Pubkey, authority: &Keypair, state_root: [u8; 32]) -> Vec<u8> {
+    initialize(context, program_id, pda).await;
+    let (verify_data, verifying_key_data) = fixture_instruction_data(state_root);
+    send_signed(context, set_verifying_key_instruction(program_id, authority.pubkey(), &verifying_key_data), &[authority]).await.unwrap();
+    verify_data
+}
+
+#[tokio::test]
+async fn initialize_creates_state_account() {
+    let (mut context, program_id, pda, _) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let account = context.banks_client.get_account(pda).await.unwrap().expect("State account should exist");
+    assert_eq!(account.owner, program_id);
+    assert_eq!(account.data, vec![0u8; 32]);
+
+    // A second initialize must fail
+    let payer = context.payer.pubkey();
+    let result = send(&mut context, initialize_instruction(program_id, pda, payer)).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn set_verifying_key_requires_the_upgrade_authority() {
+    let (mut context, program_id, _, authority) = start().await;
+    let (_, verifying_key_data) = fixture_instruction_data([7u8; 32]);
+
+    let impostor = Keypair::new();
+    let result = send_signed(&mut context, set_verifying_key_instruction(program_id, impostor.pubkey(), &verifying_key_data), &[&impostor]).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)));
+    assert!(context.banks_client.get_account(verifying_key_pda(program_id)).await.unwrap().is_none());
+
+    send_signed(&mut context, set_verifying_key_instruction(program_id, authority.pubkey(), &verifying_key_data), &[&authority]).await.unwrap();
+    let account = context.banks_client.get_account(verifying_key_pda(program_id)).await.unwrap().unwrap();
+    assert_eq!(account.owner, program_id);
+    assert_eq!(account.data, verifying_key_data[1..]);
+
+    // The authority can replace the key
+    let (_, rotated) = fixture_instruction_data([7u8; 32]);
+    send_signed(&mut context, set_verifying_key_instruction(program_id, authority.pubkey(), &rotated), &[&authority]).await.unwrap();
+    let account = context.banks_client.get_account(verifying_key_pda(program_id)).await.unwrap().unwrap();
+    assert_eq!(account.data, rotated[1..]);
+}
+
+#[tokio::test]
+async fn verify_proof_updates_root() {
+    let (mut context, program_id, pda, authority) = start().await;
+    let state_root = [7u8; 32];
+    let data = initialize_with_proof(&mut context, program_id, pda, &authority, state_root).await;
+
+    send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), &data)).await.unwrap();
+
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    assert_eq!(&account.data[..32], &state_root);
+}
+
+#[tokio::test]
+async fn verify_proof_requires_the_verifying_key() {
+    let (mut context, program_id, pda, _) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let (data, _) = fixture_instruction_data([7u8; 32]);
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), &data)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::UninitializedAccount)));
+}
+
+#[tokio::test]
+async fn verify_proof_rejects_another_state_root() {
+    let (mut context, program_id, pda, authority) = start().await;
+    let data = initialize_with_proof(&mut context, program_id, pda, &authority, [7u8; 32]).await;
+
+    // The state root closes the instruction data, either half of it is a public input
+    for index in [data.len() - 32, data.len() - 1] {
+        let mut forged = data.clone();
+        forged[index] ^= 1;
+        let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), &forged)).await;
+        assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidAccountData)));
+    }
+
+    // So is a proof checked against a key other than the stored one
+    let (other, _) = fixture_instruction_data([7u8; 32]);
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), &other)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidAccountData)));
+
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    assert_eq!(account.data, vec![0u8; 32]);
+}
+
+#[tokio::test]
+async fn verify_proof_rejects_wrong_pda() {
+    let (mut context, program_id, pda, authority) = start().await;
+    let data = initialize_with_proof(&mut context, program_id, pda, &authority, [7u8; 32]).await;
+
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(Pubkey::new_unique(), false), &data)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidAccountData)));
+}
+
+#[tokio::test]
+async fn verify_proof_rejects_non_writable_account() {
+    let (mut context, program_id, pda, authority) = start().await;
+    let data = initialize_with_proof(&mut context, program_id, pda, &authority, [7u8; 32]).await;
+
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new_readonly(pda, false), &data)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidAccountData)));
+}
+
+#[tokio::test]
+async fn verify_proof_rejects_oversized_instruction_data() {
+    let (mut context, program_id, pda, authority) = start().await;
+    let mut data = initialize_with_proof(&mut context, program_id, pda, &authority, [7u8; 32]).await;
+
+    data.extend_from_slice(&[0u8; 16]);
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), &data)).await;
+    assert!(result.is_err());
+
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    assert_eq!(account.data, vec![0u8; 32]);
+}
+
+#[cfg(feature = "vault-cpi")]
+#[tokio::test]
+async fn verify_proof_unlocks_vault_root() {
+    use trollup_vault::{VaultInstruction, VaultState, VAULT_SEED};
+
+    let program_id = Pubkey::new_unique();
+    let vault_program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let mut program_test = program_test(program_id, &authority);
+    program_test.add_program("trollup_vault", vault_program_id, processor!(trollup_vault::process_instruction));
+    let mut context = program_test.start_with_context().await;
+    let (pda, _) = Pubkey::find_program_address(&[b"state"], &program_id);
+    let (vault, _) = Pubkey::find_program_address(&[VAULT_SEED], &vault_program_id);
+    let state_root = [7u8; 32];
+    let data = initialize_with_proof(&mut context, program_id, pda, &authority, state_root).await;
+
+    let payer = context.payer.pubkey();
+    let initialize_vault = Instruction::new_with_borsh(
+        vault_program_id,
+        &VaultInstruction::Initialize { verifier_program_id: program_id, sequencer: authority.pubkey(), escape_delay_secs: 0 },
+        vec![
+            AccountMeta::new(vault, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        ],
+    );
+    send(&mut context, initialize_vault).await.unwrap();
+
+    // Without the vault accounts the root would be stored and never reach the vault
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), &data)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)));
+
+    let mut instruction = verify_instruction(program_id, AccountMeta::new(pda, false), &data);
+    instruction.accounts.push(AccountMeta::new_readonly(vault_program_id, false));
+    instruction.accounts.push(AccountMeta::new(vault, false));
+    send(&mut context, instruction).await.unwrap();
+
+    let account = context.banks_client.get_account(vault).await.unwrap().unwrap();
+    assert_eq!(VaultState::try_from_slice(&account.data).unwrap().withdrawal_root, state_root);
+}
+
+#[test]
+fn instructions_match_the_instruction_vectors() {
+    let mut checked = 0;
+    for vector in instruction_vectors() {
+        let instruction = match vector.instruction {
+            InstructionKind::Initialize => ProgramInstruction::Initialize,
+            InstructionKind::VerifyProof => {
+                let proof = vector.proof();
+                let groth16_proof = Groth16Proof { proof_a: proof.proof_a(), proof_b: proof.proof_b(), proof_c: proof.proof_c() };
+                ProgramInstruction::VerifyProof(ProofCommitmentPackage::new(groth16_proof, proof.batch_inputs(), proof.state_root()))
+            }
+            InstructionKind::SetVerifyingKey => {
+                let fixture = vector.verifying_key();
+                ProgramInstruction::SetVerifyingKey(Groth16VerifyingKey {
+                    vk_alpha_g1: fixture.vk_alpha_g1(),
+                    vk_beta_g2: fixture.vk_beta_g2(),
+                    vk_gamma_g2: fixture.vk_gamma_g2(),
+                    vk_delta_g2: fixture.vk_delta_g2(),
+                    vk_ic: fixture.vk_ic(),
+                })
+            }
+            // The signature verifier's
+            _ => continue,
+        };
+        assert_eq!(to_vec(&instruction).unwrap(), vector.data(), "{}", vector.name);
+        // The decoder reads the whole golden encoding back
+        let decoded = ProgramInstruction::try_from_slice(&vector.data()).unwrap_or_else(|_| panic!("{} doesn't decode", vector.name));
+        assert_eq!(to_vec(&decoded).unwrap(), vector.data(), "{}", vector.name);
+        checked += 1;
+    }
+    assert!(checked > 0);
+}
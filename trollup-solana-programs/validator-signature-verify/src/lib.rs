@@ -1,11 +1,17 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::program_error::ProgramError;
 use solana_program::secp256k1_recover::{secp256k1_recover, Secp256k1Pubkey};
-use solana_program::{account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, keccak, msg, pubkey::Pubkey, system_instruction};
+use solana_program::{account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, msg, pubkey::Pubkey, system_instruction};
 use solana_program::account_info::next_account_info;
-use solana_program::program::invoke_signed;
+use solana_program::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use solana_program::program::{invoke, invoke_signed};
 use solana_program::rent::Rent;
 use solana_program::sysvar::Sysvar;
+use solana_program::sysvar::instructions::{load_current_index_checked, load_instruction_at_checked};
+use solana_program::{ed25519_program, instruction::Instruction};
+use trollup_commitment_message::MessageHash;
+
+pub use trollup_commitment_message::{BlockProducer, NodeVersion};
 
 // Off-chain generated proof and verification result
 #[derive(BorshDeserialize, BorshSerialize)]
@@ -14,14 +20,125 @@ pub struct ZkProofCommitment {
     pub recovery_id: u8,
     pub public_key: [u8; 65],
     pub new_state_root: [u8; 32],
+    /// Rollup block number of the commitment, must be greater than the last accepted one
+    pub sequence: u64,
+    /// Root of the block's cross-rollup outbox, all zeros when the block sent no messages
+    pub outbox_root: [u8; 32],
+    /// Sequencer and node version that produced the block. Only signed, the program doesn't
+    /// restrict who produces blocks
+    pub producer: BlockProducer,
+}
+
+/// A commitment submitted as part of a batch. It also signs the root it extends, so the program
+/// can check the batch forms a chain starting at the stored root.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ChainedCommitment {
+    pub verifier_signature: [u8; 64],
+    pub recovery_id: u8,
+    pub public_key: [u8; 65],
+    pub parent_state_root: [u8; 32],
+    pub new_state_root: [u8; 32],
+    pub sequence: u64,
+    pub outbox_root: [u8; 32],
+    pub producer: BlockProducer,
+}
+
+/// A commitment signed with an ed25519 key. The signature itself is checked by the ed25519
+/// program in the instruction right before this one, see `verify_ed25519`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Ed25519Commitment {
+    pub public_key: [u8; 32],
+    pub new_state_root: [u8; 32],
+    pub sequence: u64,
+    pub outbox_root: [u8; 32],
+    pub producer: BlockProducer,
+}
+
+/// Keys of the validator whose commitments the program accepts, set by the upgrade authority.
+/// A scheme whose key is all zeros is turned off.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ValidatorKeys {
+    /// Uncompressed secp256k1 key, `0x04` prefix included, for `VerifySig` and `VerifySigBatch`
+    pub secp256k1: [u8; 65],
+    /// Key of `VerifyEd25519` commitments
+    pub ed25519: [u8; 32],
+}
+
+/// The state account holds the current root, the sequence it was committed with and the
+/// `ValidatorKeys`.
+const STATE_ROOT_SIZE: usize = 32;
+const SEQUENCE_END: usize = STATE_ROOT_SIZE + 8;
+const SECP256K1_KEY_END: usize = SEQUENCE_END + 65;
+pub const STATE_SIZE: usize = SECP256K1_KEY_END + 32;
+
+/// Layout of the ed25519 program's instruction data: a signature count and a padding byte,
+/// then seven little endian u16 offsets per signature.
+const ED25519_OFFSETS_START: usize = 2;
+const ED25519_OFFSETS_SIZE: usize = 14;
+/// Instruction index the ed25519 program reads as "this instruction"
+const ED25519_CURRENT_INSTRUCTION: u16 = u16::MAX;
+
+/// Hash of the commitment messages. Keccak-256 unless the program is built with the
+/// `sha256-commitments` feature, the validator's `COMMITMENT_MESSAGE_HASH` must match.
+pub const MESSAGE_HASH: MessageHash = if cfg!(feature = "sha256-commitments") {
+    MessageHash::Sha256
+} else {
+    MessageHash::Keccak256
+};
+
+/// Hash the verifier signs, see `trollup_commitment_message::commitment_message`.
+pub fn commitment_message_hash(new_state_root: &[u8; 32], sequence: u64, outbox_root: &[u8; 32], producer: &BlockProducer) -> [u8; 32] {
+    trollup_commitment_message::commitment_message_hash(MESSAGE_HASH, new_state_root, sequence, outbox_root, producer)
 }
 
+/// Hash the verifier signs for a `ChainedCommitment`, see
+/// `trollup_commitment_message::chained_commitment_message`.
+pub fn chained_commitment_message_hash(parent_state_root: &[u8; 32], new_state_root: &[u8; 32], sequence: u64, outbox_root: &[u8; 32], producer: &BlockProducer) -> [u8; 32] {
+    trollup_commitment_message::chained_commitment_message_hash(MESSAGE_HASH, parent_state_root, new_state_root, sequence, outbox_root, producer)
+}
+
+/// The ed25519 program instruction to place right before `VerifyEd25519`, verifying `signature`
+/// by `public_key` over `message` with all data inside the instruction.
+pub fn ed25519_signature_instruction(public_key: &[u8; 32], signature: &[u8; 64], message: &[u8]) -> Instruction {
+    let public_key_offset = ED25519_OFFSETS_START + ED25519_OFFSETS_SIZE;
+    let signature_offset = public_key_offset + public_key.len();
+    let message_offset = signature_offset + signature.len();
+    let mut data = vec![1u8, 0];
+    for value in [
+        signature_offset as u16,
+        ED25519_CURRENT_INSTRUCTION,
+        public_key_offset as u16,
+        ED25519_CURRENT_INSTRUCTION,
+        message_offset as u16,
+        message.len() as u16,
+        ED25519_CURRENT_INSTRUCTION,
+    ] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(public_key);
+    data.extend_from_slice(signature);
+    data.extend_from_slice(message);
+    Instruction::new_with_bytes(ed25519_program::ID, &data, vec![])
+}
+
+#[cfg(not(feature = "no-entrypoint"))]
 entrypoint!(process_instruction);
 
 #[derive(BorshSerialize, BorshDeserialize)]
 pub enum ProgramInstruction {
     Initialize,
     VerifySig(ZkProofCommitment),
+    /// Consecutive commitments in one transaction, the first extending the stored root
+    VerifySigBatch(Vec<ChainedCommitment>),
+    /// A commitment whose ed25519 signature is verified by the preceding instruction. Takes the
+    /// instructions sysvar after the state account.
+    VerifyEd25519(Ed25519Commitment),
+    /// Pins the validator keys. Takes the state account, the upgrade authority as signer and the
+    /// program's ProgramData account.
+    SetValidator(ValidatorKeys),
+    /// Grows a state account created with an older layout to `STATE_SIZE`, keeping its root and
+    /// sequence. Takes the state account, a payer for the extra rent and the system program.
+    Migrate,
 }
 
 pub fn process_instruction(
@@ -34,6 +151,10 @@ pub fn process_instruction(
     match instruction {
         ProgramInstruction::Initialize => initialize(program_id, accounts),
         ProgramInstruction::VerifySig(proof_commitment) => verify_proof(program_id, accounts, proof_commitment),
+        ProgramInstruction::VerifySigBatch(commitments) => verify_batch(program_id, accounts, commitments),
+        ProgramInstruction::VerifyEd25519(commitment) => verify_ed25519(program_id, accounts, commitment),
+        ProgramInstruction::SetValidator(keys) => set_validator(program_id, accounts, keys),
+        ProgramInstruction::Migrate => migrate(program_id, accounts),
     }
 }
 
@@ -55,7 +176,7 @@ fn initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     }
 
     let rent = Rent::get()?;
-    let space = 32; // Size to store the state root
+    let space = STATE_SIZE; // Size to store the state root, sequence and validator keys
     let lamports = rent.minimum_balance(space);
 
     invoke_signed(
@@ -78,6 +199,125 @@ fn initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     Ok(())
 }
 
+/// Pins the keys commitments must be signed with. Until it runs every commitment is rejected.
+fn set_validator(program_id: &Pubkey, accounts: &[AccountInfo], keys: ValidatorKeys) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let program_data = next_account_info(account_info_iter)?;
+
+    check_state_account(program_id, state_account)?;
+
+    if !authority.is_signer || upgrade_authority(program_id, program_data)? != Some(*authority.key) {
+        msg!("Only the upgrade authority can set the validator.");
+        return Err(ProgramError::MissingRequiredSignature.into());
+    }
+
+    if !state_account.is_writable {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+
+    if state_account.data_len() < STATE_SIZE {
+        msg!("State account has an older layout, run Migrate first.");
+        return Err(ProgramError::AccountDataTooSmall.into());
+    }
+
+    let mut data = state_account.try_borrow_mut_data()?;
+    data[SEQUENCE_END..SECP256K1_KEY_END].copy_from_slice(&keys.secp256k1);
+    data[SECP256K1_KEY_END..STATE_SIZE].copy_from_slice(&keys.ed25519);
+    msg!("Validator set");
+    Ok(())
+}
+
+/// Reallocates a state account of an older layout: 32 bytes holding only the root, or 40 with
+/// the sequence. The new bytes are zeroed, so an old root continues at sequence 0 and no
+/// validator is pinned until `SetValidator`.
+fn migrate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    check_state_account(program_id, state_account)?;
+
+    if state_account.data_len() >= STATE_SIZE {
+        msg!("State account is already migrated.");
+        return Err(ProgramError::AccountAlreadyInitialized.into());
+    }
+
+    let lamports = Rent::get()?.minimum_balance(STATE_SIZE).saturating_sub(state_account.lamports());
+    if lamports > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, state_account.key, lamports),
+            &[payer.clone(), state_account.clone(), system_program.clone()],
+        )?;
+    }
+    state_account.realloc(STATE_SIZE, true)?;
+
+    msg!("State account migrated");
+    Ok(())
+}
+
+/// The upgrade authority recorded in `program_data`, which must be the ProgramData account of
+/// `program_id`. `None` once the program is immutable.
+fn upgrade_authority(program_id: &Pubkey, program_data: &AccountInfo) -> Result<Option<Pubkey>, ProgramError> {
+    let (expected, _) = Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    if program_data.key != &expected || program_data.owner != &bpf_loader_upgradeable::id() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // ProgramData metadata: u32 variant (3), u64 slot, Option<Pubkey> authority
+    let data = program_data.try_borrow_data()?;
+    if data.len() < UpgradeableLoaderState::size_of_programdata_metadata() || data[..4] != 3u32.to_le_bytes() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    match data[12] {
+        0 => Ok(None),
+        1 => Ok(Some(Pubkey::new_from_array(data[13..45].try_into().unwrap()))),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// Checks `state_account` is the program's state PDA.
+fn check_state_account(program_id: &Pubkey, state_account: &AccountInfo) -> ProgramResult {
+    let (pda, _) = Pubkey::find_program_address(&[b"state"], program_id);
+
+    if state_account.key != &pda {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+
+    if state_account.owner != program_id {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+    Ok(())
+}
+
+/// The pinned validator keys. Fails for accounts of an older layout, which have none.
+fn validator_keys(state_account: &AccountInfo) -> Result<ValidatorKeys, ProgramError> {
+    let data = state_account.try_borrow_data()?;
+    if data.len() < STATE_SIZE {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    Ok(ValidatorKeys {
+        secp256k1: data[SEQUENCE_END..SECP256K1_KEY_END].try_into().unwrap(),
+        ed25519: data[SECP256K1_KEY_END..STATE_SIZE].try_into().unwrap(),
+    })
+}
+
+/// Rejects commitments signed by anyone but the pinned validator. An all zero key means no
+/// validator is set for the scheme.
+fn check_validator(pinned: &[u8], public_key: &[u8]) -> ProgramResult {
+    if pinned.iter().all(|byte| *byte == 0) {
+        msg!("No validator is set for this signature scheme.");
+        return Err(ProgramError::MissingRequiredSignature.into());
+    }
+    if pinned != public_key {
+        msg!("Commitment isn't signed by the validator.");
+        return Err(ProgramError::MissingRequiredSignature.into());
+    }
+    Ok(())
+}
+
 /// Process the given instruction data and update on-chain state
 ///
 /// # Arguments
@@ -99,32 +339,137 @@ fn verify_proof(
 ) -> ProgramResult {
     msg!("Verifying proof commitment");
 
+    let account_info_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_info_iter)?;
+
+    check_state_account(program_id, state_account)?;
+    check_validator(&validator_keys(state_account)?.secp256k1, &proof_commitment.public_key)?;
+
     // Verify the proof commitment
-    let result = verify_signature_with_recover(&proof_commitment);
-    match result {
-        Ok(_) => {
-            // If valid, update on-chain state
-            let account_info_iter = &mut accounts.iter();
-            let state_account = next_account_info(account_info_iter)?;
-
-            let (pda, _) = Pubkey::find_program_address(&[b"state"], program_id);
-
-            if state_account.key != &pda {
-                return Err(ProgramError::InvalidAccountData.into());
-            }
-
-            if state_account.owner != program_id {
-                return Err(ProgramError::InvalidAccountData.into());
-            }
-            update_on_chain_state(&proof_commitment.new_state_root, state_account)?;
+    if verify_signature_with_recover(&proof_commitment).is_err() {
+        msg!("Invalid proof commitment");
+        return Err(ProgramError::InvalidInstructionData.into());
+    }
+
+    // If valid, update on-chain state
+    update_on_chain_state(&proof_commitment.new_state_root, proof_commitment.sequence, state_account)
+}
+
+/// Verifies every commitment of the batch and stores the last root. Each commitment must extend
+/// the root before it, starting at the stored root, with increasing sequences; one that doesn't
+/// fails the whole batch.
+fn verify_batch(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    commitments: Vec<ChainedCommitment>,
+) -> ProgramResult {
+    msg!("Verifying {} chained commitments", commitments.len());
+
+    let account_info_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_info_iter)?;
+
+    check_state_account(program_id, state_account)?;
+    let validator = validator_keys(state_account)?.secp256k1;
+
+    let Some(last) = commitments.last() else {
+        msg!("Empty commitment batch.");
+        return Err(ProgramError::InvalidInstructionData.into());
+    };
+
+    let mut parent_state_root = [0u8; 32];
+    let mut sequence = [0u8; 8];
+    {
+        let data = state_account.try_borrow_data()?;
+        parent_state_root.copy_from_slice(&data[..STATE_ROOT_SIZE]);
+        sequence.copy_from_slice(&data[STATE_ROOT_SIZE..SEQUENCE_END]);
+    }
+    let mut sequence = u64::from_le_bytes(sequence);
+
+    for (index, commitment) in commitments.iter().enumerate() {
+        if commitment.parent_state_root != parent_state_root {
+            msg!("Commitment {} doesn't extend the previous root.", index);
+            return Err(ProgramError::InvalidInstructionData.into());
         }
-        Err(_) => {
-            msg!("Invalid proof commitment");
+        if commitment.new_state_root == parent_state_root {
+            msg!("Commitment {} doesn't change the root.", index);
             return Err(ProgramError::InvalidInstructionData.into());
         }
+        if commitment.sequence <= sequence {
+            msg!("Sequence {} of commitment {} is not greater than {}.", commitment.sequence, index, sequence);
+            return Err(ProgramError::InvalidInstructionData.into());
+        }
+        check_validator(&validator, &commitment.public_key)?;
+        let message_hash = chained_commitment_message_hash(&commitment.parent_state_root, &commitment.new_state_root, commitment.sequence, &commitment.outbox_root, &commitment.producer);
+        if recover_and_compare(&message_hash, commitment.recovery_id, &commitment.verifier_signature, &commitment.public_key).is_err() {
+            msg!("Invalid signature on commitment {}.", index);
+            return Err(ProgramError::InvalidInstructionData.into());
+        }
+        parent_state_root = commitment.new_state_root;
+        sequence = commitment.sequence;
     }
 
-    Ok(())
+    update_on_chain_state(&last.new_state_root, last.sequence, state_account)
+}
+
+/// Stores the root of an ed25519 commitment. The program can't verify ed25519 signatures itself,
+/// instead the transaction must run the ed25519 program right before this instruction, over a
+/// single signature of `commitment_message_hash` by the commitment's key. The runtime fails the
+/// whole transaction if that signature is invalid.
+fn verify_ed25519(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    commitment: Ed25519Commitment,
+) -> ProgramResult {
+    msg!("Verifying ed25519 commitment");
+
+    let account_info_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_info_iter)?;
+    let instructions_sysvar = next_account_info(account_info_iter)?;
+
+    check_state_account(program_id, state_account)?;
+    check_validator(&validator_keys(state_account)?.ed25519, &commitment.public_key)?;
+
+    // Both loaders check the account is the instructions sysvar
+    let current_index = load_current_index_checked(instructions_sysvar)?;
+    if current_index == 0 {
+        msg!("Missing ed25519 signature instruction.");
+        return Err(ProgramError::MissingRequiredSignature.into());
+    }
+    let signature_instruction = load_instruction_at_checked(current_index as usize - 1, instructions_sysvar)?;
+
+    let message_hash = commitment_message_hash(&commitment.new_state_root, commitment.sequence, &commitment.outbox_root, &commitment.producer);
+    if !ed25519_instruction_signs(&signature_instruction, &commitment.public_key, &message_hash) {
+        msg!("Signature instruction doesn't sign this commitment.");
+        return Err(ProgramError::MissingRequiredSignature.into());
+    }
+
+    update_on_chain_state(&commitment.new_state_root, commitment.sequence, state_account)
+}
+
+/// Whether `instruction` makes the ed25519 program verify exactly one signature by `public_key`
+/// over `message`, with all of its data inside the instruction itself.
+fn ed25519_instruction_signs(instruction: &Instruction, public_key: &[u8; 32], message: &[u8]) -> bool {
+    if instruction.program_id != ed25519_program::ID {
+        return false;
+    }
+    let data = &instruction.data;
+    if data.len() < ED25519_OFFSETS_START + ED25519_OFFSETS_SIZE || data[0] != 1 {
+        return false;
+    }
+    let offset = |index: usize| {
+        let start = ED25519_OFFSETS_START + index * 2;
+        u16::from_le_bytes([data[start], data[start + 1]])
+    };
+    let (public_key_offset, public_key_instruction) = (offset(2) as usize, offset(3));
+    let (message_offset, message_size, message_instruction) = (offset(4) as usize, offset(5) as usize, offset(6));
+    // Data in another instruction could be swapped without touching this one
+    if offset(1) != ED25519_CURRENT_INSTRUCTION
+        || public_key_instruction != ED25519_CURRENT_INSTRUCTION
+        || message_instruction != ED25519_CURRENT_INSTRUCTION {
+        return false;
+    }
+    data.get(public_key_offset..public_key_offset + 32) == Some(&public_key[..])
+        && data.get(message_offset..message_offset + message_size) == Some(message)
 }
 
 fn verify_signature_with_recover(
@@ -132,17 +477,21 @@ fn verify_signature_with_recover(
 ) -> Result<bool, Box<dyn std::error::Error>> {
 
     // Verify the signature
-    let message_hash = {
-        let mut hasher = keccak::Hasher::default();
-        hasher.hash(&commitment.new_state_root);
-        hasher.result()
-    };
+    let message_hash = commitment_message_hash(&commitment.new_state_root, commitment.sequence, &commitment.outbox_root, &commitment.producer);
+    recover_and_compare(&message_hash, commitment.recovery_id, &commitment.verifier_signature, &commitment.public_key)
+}
 
+/// Checks `signature` over `message_hash` recovers to `public_key`.
+fn recover_and_compare(
+    message_hash: &[u8; 32],
+    recovery_id: u8,
+    signature: &[u8; 64],
+    public_key: &[u8; 65],
+) -> Result<bool, Box<dyn std::error::Error>> {
     // Perform the secp256k1 recovery
-    let recovered_pubkey = secp256k1_recover(&message_hash.0, commitment.recovery_id, &commitment.verifier_signature)?;
+    let recovered_pubkey = secp256k1_recover(message_hash, recovery_id, signature)?;
 
-    // TODO get public key from validator solana account
-    let expected_pubkey = Secp256k1Pubkey::new(&commitment.public_key[1..65]);
+    let expected_pubkey = Secp256k1Pubkey::new(&public_key[1..65]);
     // Check if the recovered public key matches the expected one
     if recovered_pubkey != expected_pubkey {
         msg!("Signature verification failed");
@@ -153,7 +502,7 @@ fn verify_signature_with_recover(
 }
 
 
-fn update_on_chain_state(state_root: &[u8; 32], account: &AccountInfo) -> ProgramResult {
+fn update_on_chain_state(state_root: &[u8; 32], sequence: u64, account: &AccountInfo) -> ProgramResult {
     msg!("Updating state account.");
 
     // Ensure the account is writable
@@ -161,6 +510,19 @@ fn update_on_chain_state(state_root: &[u8; 32], account: &AccountInfo) -> Progra
         return Err(ProgramError::InvalidAccountData.into());
     }
 
+    if account.data_len() < STATE_SIZE {
+        return Err(ProgramError::AccountDataTooSmall.into());
+    }
+
+    // Reject replays of older commitments
+    let mut current_sequence = [0u8; 8];
+    current_sequence.copy_from_slice(&account.try_borrow_data()?[STATE_ROOT_SIZE..SEQUENCE_END]);
+    let current_sequence = u64::from_le_bytes(current_sequence);
+    if sequence <= current_sequence {
+        msg!("Sequence {} is not greater than the current sequence {}.", sequence, current_sequence);
+        return Err(ProgramError::InvalidInstructionData.into());
+    }
+
     // Update the state root
     // invoke_signed(
     //     &system_instruction::transfer(account.key, account.key, 0),
@@ -168,7 +530,9 @@ fn update_on_chain_state(state_root: &[u8; 32], account: &AccountInfo) -> Progra
     //     &[&[b"state", &[bump_seed]]],
     // )?;
 
-    account.try_borrow_mut_data()?[..32].copy_from_slice(state_root);
+    let mut data = account.try_borrow_mut_data()?;
+    data[..STATE_ROOT_SIZE].copy_from_slice(state_root);
+    data[STATE_ROOT_SIZE..SEQUENCE_END].copy_from_slice(&sequence.to_le_bytes());
 
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,624 @@
+use borsh::{to_vec, BorshDeserialize};
+use libsecp256k1::{Message, PublicKey, SecretKey};
+use solana_program::bpf_loader_upgradeable;
+use solana_program::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::signer::keypair::keypair_from_seed;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use trollup_commitment_message::instruction_vectors::{instruction_vectors, CommitmentFixture, Instruction as InstructionKind};
+use trollup_commitment_message::test_vectors::test_vectors;
+use validator_signature_verify::{chained_commitment_message_hash, commitment_message_hash, ed25519_signature_instruction, process_instruction, BlockProducer, ChainedCommitment, Ed25519Commitment, NodeVersion, ProgramInstruction, ValidatorKeys, ZkProofCommitment, MESSAGE_HASH, STATE_SIZE};
+
+const PRODUCER: BlockProducer = BlockProducer { sequencer: [4u8; 32], version: NodeVersion::new(0, 1, 0) };
+
+/// Secret key of the secp256k1 commitments the tests sign
+const VALIDATOR_SECRET_KEY: [u8; 32] = [3u8; 32];
+
+/// Upgrade authority of the program under test
+fn authority() -> Keypair {
+    keypair_from_seed(&[5u8; 32]).unwrap()
+}
+
+/// Signer of the ed25519 commitments the tests send
+fn ed25519_validator() -> Keypair {
+    keypair_from_seed(&[6u8; 32]).unwrap()
+}
+
+fn validator_keys() -> ValidatorKeys {
+    ValidatorKeys {
+        secp256k1: PublicKey::from_secret_key(&SecretKey::parse(&VALIDATOR_SECRET_KEY).unwrap()).serialize(),
+        ed25519: ed25519_validator().pubkey().to_bytes(),
+    }
+}
+
+/// The verifier with a ProgramData account naming `authority()` as its upgrade authority, as the
+/// upgradeable loader would have written it.
+fn program_test(program_id: Pubkey) -> ProgramTest {
+    let mut program_test = ProgramTest::new("validator_signature_verify", program_id, processor!(process_instruction));
+    let mut data = 3u32.to_le_bytes().to_vec();
+    data.extend_from_slice(&0u64.to_le_bytes());
+    data.push(1);
+    data.extend_from_slice(authority().pubkey().as_ref());
+    program_test.add_account(program_data(program_id), Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: bpf_loader_upgradeable::id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+    program_test.add_account(authority().pubkey(), Account::new(1_000_000_000, 0, &solana_program::system_program::id()));
+    program_test
+}
+
+fn program_data(program_id: Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+fn state_pda(program_id: Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"state"], &program_id).0
+}
+
+async fn start() -> (ProgramTestContext, Pubkey, Pubkey) {
+    let program_id = Pubkey::new_unique();
+    let context = program_test(program_id).start_with_context().await;
+    (context, program_id, state_pda(program_id))
+}
+
+async fn send(context: &mut ProgramTestContext, instruction: Instruction) -> Result<(), TransactionError> {
+    send_all(context, &[instruction]).await
+}
+
+async fn send_all(context: &mut ProgramTestContext, instructions: &[Instruction]) -> Result<(), TransactionError> {
+    send_signed(context, instructions, &[]).await
+}
+
+async fn send_signed(context: &mut ProgramTestContext, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), TransactionError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &[&[&context.payer], signers].concat(),
+        blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.map_err(|e| e.unwrap())
+}
+
+/// Creates the state account, without pinning a validator.
+async fn create_state(context: &mut ProgramTestContext, program_id: Pubkey, pda: Pubkey) {
+    let instruction = Instruction::new_with_bytes(
+        program_id,
+        &to_vec(&ProgramInstruction::Initialize).unwrap(),
+        vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new(context.payer.pubkey(), true),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        ],
+    );
+    send(context, instruction).await.unwrap();
+}
+
+fn set_validator_instruction(program_id: Pubkey, authority: Pubkey, keys: ValidatorKeys) -> Instruction {
+    Instruction::new_with_bytes(
+        program_id,
+        &to_vec(&ProgramInstruction::SetValidator(keys)).unwrap(),
+        vec![
+            AccountMeta::new(state_pda(program_id), false),
+            AccountMeta::new_readonly(authority, true),
+            AccountMeta::new_readonly(program_data(program_id), false),
+        ],
+    )
+}
+
+fn migrate_instruction(program_id: Pubkey, payer: Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        program_id,
+        &to_vec(&ProgramInstruction::Migrate).unwrap(),
+        vec![
+            AccountMeta::new(state_pda(program_id), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        ],
+    )
+}
+
+/// Creates the state account and pins `validator_keys()`.
+async fn initialize(context: &mut ProgramTestContext, program_id: Pubkey, pda: Pubkey) {
+    create_state(context, program_id, pda).await;
+    let authority = authority();
+    send_signed(context, &[set_validator_instruction(program_id, authority.pubkey(), validator_keys())], &[&authority]).await.unwrap();
+}
+
+/// Signs `new_state_root` and `sequence` the same way the validator does, with an empty outbox.
+fn signed_commitment(new_state_root: [u8; 32], sequence: u64) -> ZkProofCommitment {
+    signed_commitment_by(&VALIDATOR_SECRET_KEY, new_state_root, sequence)
+}
+
+fn signed_commitment_by(secret_key: &[u8; 32], new_state_root: [u8; 32], sequence: u64) -> ZkProofCommitment {
+    let message_hash = commitment_message_hash(&new_state_root, sequence, &[0u8; 32], &PRODUCER);
+    let message = Message::parse_slice(&message_hash).unwrap();
+    let secret_key = SecretKey::parse(secret_key).unwrap();
+    let (signature, recovery_id) = libsecp256k1::sign(&message, &secret_key);
+
+    ZkProofCommitment {
+        verifier_signature: signature.serialize(),
+        recovery_id: recovery_id.serialize(),
+        public_key: PublicKey::from_secret_key(&secret_key).serialize(),
+        new_state_root,
+        sequence,
+        outbox_root: [0u8; 32],
+        producer: PRODUCER,
+    }
+}
+
+/// Signs a commitment extending `parent_state_root` the way the validator signs batches.
+fn chained_commitment(parent_state_root: [u8; 32], new_state_root: [u8; 32], sequence: u64) -> ChainedCommitment {
+    chained_commitment_by(&VALIDATOR_SECRET_KEY, parent_state_root, new_state_root, sequence)
+}
+
+fn chained_commitment_by(secret_key: &[u8; 32], parent_state_root: [u8; 32], new_state_root: [u8; 32], sequence: u64) -> ChainedCommitment {
+    let message_hash = chained_commitment_message_hash(&parent_state_root, &new_state_root, sequence, &[0u8; 32], &PRODUCER);
+    let message = Message::parse_slice(&message_hash).unwrap();
+    let secret_key = SecretKey::parse(secret_key).unwrap();
+    let (signature, recovery_id) = libsecp256k1::sign(&message, &secret_key);
+
+    ChainedCommitment {
+        verifier_signature: signature.serialize(),
+        recovery_id: recovery_id.serialize(),
+        public_key: PublicKey::from_secret_key(&secret_key).serialize(),
+        parent_state_root,
+        new_state_root,
+        sequence,
+        outbox_root: [0u8; 32],
+        producer: PRODUCER,
+    }
+}
+
+fn verify_batch_instruction(program_id: Pubkey, pda: Pubkey, commitments: Vec<ChainedCommitment>) -> Instruction {
+    Instruction::new_with_bytes(program_id, &to_vec(&ProgramInstruction::VerifySigBatch(commitments)).unwrap(), vec![AccountMeta::new(pda, false)])
+}
+
+/// The ed25519 signature instruction and the `VerifyEd25519` instruction for a commitment to
+/// `new_state_root`, signed by `signer` over `signed_sequence`.
+fn ed25519_instructions(program_id: Pubkey, pda: Pubkey, signer: &Keypair, new_state_root: [u8; 32], sequence: u64, signed_sequence: u64) -> [Instruction; 2] {
+    let message_hash = commitment_message_hash(&new_state_root, signed_sequence, &[0u8; 32], &PRODUCER);
+    let signature: [u8; 64] = signer.sign_message(&message_hash).as_ref().try_into().unwrap();
+    let commitment = Ed25519Commitment {
+        public_key: signer.pubkey().to_bytes(),
+        new_state_root,
+        sequence,
+        outbox_root: [0u8; 32],
+        producer: PRODUCER,
+    };
+    [
+        ed25519_signature_instruction(&commitment.public_key, &signature, &message_hash),
+        Instruction::new_with_bytes(
+            program_id,
+            &to_vec(&ProgramInstruction::VerifyEd25519(commitment)).unwrap(),
+            vec![AccountMeta::new(pda, false), AccountMeta::new_readonly(solana_program::sysvar::instructions::id(), false)],
+        ),
+    ]
+}
+
+fn verify_instruction(program_id: Pubkey, account: AccountMeta, commitment: ZkProofCommitment) -> Instruction {
+    Instruction::new_with_bytes(program_id, &to_vec(&ProgramInstruction::VerifySig(commitment)).unwrap(), vec![account])
+}
+
+#[tokio::test]
+async fn initialize_creates_state_account() {
+    let (mut context, program_id, pda) = start().await;
+    create_state(&mut context, program_id, pda).await;
+
+    let account = context.banks_client.get_account(pda).await.unwrap().expect("State account should exist");
+    assert_eq!(account.owner, program_id);
+    assert_eq!(account.data, vec![0u8; STATE_SIZE]);
+}
+
+#[tokio::test]
+async fn set_validator_pins_the_keys() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    let keys = validator_keys();
+    assert_eq!(&account.data[..40], &[0u8; 40]);
+    assert_eq!(&account.data[40..105], &keys.secp256k1);
+    assert_eq!(&account.data[105..], &keys.ed25519);
+}
+
+#[tokio::test]
+async fn set_validator_requires_the_upgrade_authority() {
+    let (mut context, program_id, pda) = start().await;
+    create_state(&mut context, program_id, pda).await;
+
+    let impostor = Keypair::new();
+    let result = send_signed(&mut context, &[set_validator_instruction(program_id, impostor.pubkey(), validator_keys())], &[&impostor]).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)));
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_commitments_before_a_validator_is_set() {
+    let (mut context, program_id, pda) = start().await;
+    create_state(&mut context, program_id, pda).await;
+
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), signed_commitment([9u8; 32], 1))).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)));
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_self_signed_commitments() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    // Validly signed, but by a key other than the pinned validator
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), signed_commitment_by(&[8u8; 32], [9u8; 32], u64::MAX))).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)));
+
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    assert_eq!(&account.data[..40], &[0u8; 40]);
+}
+
+#[tokio::test]
+async fn migrate_grows_old_state_accounts() {
+    let program_id = Pubkey::new_unique();
+    let pda = state_pda(program_id);
+    let mut program_test = program_test(program_id);
+    // A state account of the first layout, holding only the root
+    program_test.add_account(pda, Account {
+        lamports: Rent::default().minimum_balance(32),
+        data: vec![7u8; 32],
+        owner: program_id,
+        executable: false,
+        rent_epoch: 0,
+    });
+    let mut context = program_test.start_with_context().await;
+
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), signed_commitment([9u8; 32], 1))).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::AccountDataTooSmall)));
+
+    let payer = context.payer.pubkey();
+    send(&mut context, migrate_instruction(program_id, payer)).await.unwrap();
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    assert_eq!(account.data.len(), STATE_SIZE);
+    assert_eq!(&account.data[..32], &[7u8; 32]);
+    assert!(account.data[32..].iter().all(|byte| *byte == 0));
+
+    // Migrating twice is refused
+    let result = send(&mut context, migrate_instruction(program_id, payer)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::AccountAlreadyInitialized)));
+
+    let authority = authority();
+    send_signed(&mut context, &[set_validator_instruction(program_id, authority.pubkey(), validator_keys())], &[&authority]).await.unwrap();
+    send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), signed_commitment([9u8; 32], 1))).await.unwrap();
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    assert_eq!(&account.data[..32], &[9u8; 32]);
+}
+
+#[tokio::test]
+async fn verify_sig_updates_root() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let state_root = [9u8; 32];
+    send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), signed_commitment(state_root, 1))).await.unwrap();
+
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    assert_eq!(&account.data[..32], &state_root);
+    assert_eq!(&account.data[32..40], &1u64.to_le_bytes());
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_wrong_pda() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(Pubkey::new_unique(), false), signed_commitment([9u8; 32], 1))).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidAccountData)));
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_non_writable_account() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new_readonly(pda, false), signed_commitment([9u8; 32], 1))).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidAccountData)));
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_replayed_sequence() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), signed_commitment([9u8; 32], 1))).await.unwrap();
+    send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), signed_commitment([10u8; 32], 2))).await.unwrap();
+
+    // Re-submitting the older, validly signed commitment must not roll the root back
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), signed_commitment([9u8; 32], 1))).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    assert_eq!(&account.data[..32], &[10u8; 32]);
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_tampered_sequence() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let mut commitment = signed_commitment([9u8; 32], 1);
+    commitment.sequence = 5;
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), commitment)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_tampered_outbox_root() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let mut commitment = signed_commitment([9u8; 32], 1);
+    commitment.outbox_root = [7u8; 32];
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), commitment)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_tampered_producer() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let mut commitment = signed_commitment([9u8; 32], 1);
+    commitment.producer.version = NodeVersion::new(9, 9, 9);
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), commitment)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_invalid_signature() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let mut commitment = signed_commitment([9u8; 32], 1);
+    commitment.verifier_signature[10] ^= 0xff;
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), commitment)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_invalid_recovery_id() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let mut commitment = signed_commitment([9u8; 32], 1);
+    commitment.recovery_id = 4;
+    let result = send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), commitment)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_oversized_instruction_data() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let mut data = to_vec(&ProgramInstruction::VerifySig(signed_commitment([9u8; 32], 1))).unwrap();
+    data.extend_from_slice(&[0u8; 16]);
+    let result = send(&mut context, Instruction::new_with_bytes(program_id, &data, vec![AccountMeta::new(pda, false)])).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn verify_sig_batch_stores_last_root() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+    send(&mut context, verify_instruction(program_id, AccountMeta::new(pda, false), signed_commitment([9u8; 32], 1))).await.unwrap();
+
+    let batch = vec![
+        chained_commitment([9u8; 32], [10u8; 32], 2),
+        chained_commitment([10u8; 32], [11u8; 32], 3),
+        chained_commitment([11u8; 32], [12u8; 32], 5),
+    ];
+    send(&mut context, verify_batch_instruction(program_id, pda, batch)).await.unwrap();
+
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    assert_eq!(&account.data[..32], &[12u8; 32]);
+    assert_eq!(&account.data[32..40], &5u64.to_le_bytes());
+}
+
+#[tokio::test]
+async fn verify_sig_batch_rejects_broken_chain() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    // The first commitment must extend the stored root, all zeros after initialization
+    let result = send(&mut context, verify_batch_instruction(program_id, pda, vec![chained_commitment([1u8; 32], [10u8; 32], 1)])).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+
+    // A later commitment skipping its predecessor's root fails the whole batch
+    let batch = vec![
+        chained_commitment([0u8; 32], [10u8; 32], 1),
+        chained_commitment([0u8; 32], [11u8; 32], 2),
+    ];
+    let result = send(&mut context, verify_batch_instruction(program_id, pda, batch)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    assert_eq!(&account.data[..40], &[0u8; 40]);
+}
+
+#[tokio::test]
+async fn verify_sig_batch_rejects_unordered_sequences() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let batch = vec![
+        chained_commitment([0u8; 32], [10u8; 32], 2),
+        chained_commitment([10u8; 32], [11u8; 32], 2),
+    ];
+    let result = send(&mut context, verify_batch_instruction(program_id, pda, batch)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+}
+
+#[tokio::test]
+async fn verify_sig_batch_rejects_tampered_parent() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let mut commitment = chained_commitment([5u8; 32], [10u8; 32], 1);
+    // Matches the stored root, but isn't what the verifier signed
+    commitment.parent_state_root = [0u8; 32];
+    let result = send(&mut context, verify_batch_instruction(program_id, pda, vec![commitment])).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+}
+
+#[tokio::test]
+async fn verify_sig_batch_rejects_self_signed_commitments() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let batch = vec![
+        chained_commitment([0u8; 32], [10u8; 32], 1),
+        chained_commitment_by(&[8u8; 32], [10u8; 32], [11u8; 32], u64::MAX),
+    ];
+    let result = send(&mut context, verify_batch_instruction(program_id, pda, batch)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)));
+}
+
+#[tokio::test]
+async fn verify_sig_batch_rejects_empty_batch() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let result = send(&mut context, verify_batch_instruction(program_id, pda, vec![])).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+}
+
+#[tokio::test]
+async fn verify_ed25519_updates_root() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    send_all(&mut context, &ed25519_instructions(program_id, pda, &ed25519_validator(), [9u8; 32], 1, 1)).await.unwrap();
+
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    assert_eq!(&account.data[..32], &[9u8; 32]);
+    assert_eq!(&account.data[32..40], &1u64.to_le_bytes());
+}
+
+#[tokio::test]
+async fn verify_ed25519_rejects_self_signed_commitments() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let result = send_all(&mut context, &ed25519_instructions(program_id, pda, &Keypair::new(), [9u8; 32], 1, 1)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(1, InstructionError::MissingRequiredSignature)));
+}
+
+#[tokio::test]
+async fn verify_ed25519_rejects_missing_signature_instruction() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let [_, verify] = ed25519_instructions(program_id, pda, &ed25519_validator(), [9u8; 32], 1, 1);
+    let result = send(&mut context, verify).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)));
+}
+
+#[tokio::test]
+async fn verify_ed25519_rejects_signature_over_other_commitment() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    // A valid signature, but over sequence 1
+    let result = send_all(&mut context, &ed25519_instructions(program_id, pda, &ed25519_validator(), [9u8; 32], 7, 1)).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(1, InstructionError::MissingRequiredSignature)));
+}
+
+#[tokio::test]
+async fn verify_ed25519_rejects_invalid_signature() {
+    let (mut context, program_id, pda) = start().await;
+    initialize(&mut context, program_id, pda).await;
+
+    let [mut signature, verify] = ed25519_instructions(program_id, pda, &ed25519_validator(), [9u8; 32], 1, 1);
+    // First signature byte, after the header, offsets and public key
+    signature.data[48] ^= 1;
+    let result = send_all(&mut context, &[signature, verify]).await;
+    assert!(result.is_err());
+
+    let account = context.banks_client.get_account(pda).await.unwrap().unwrap();
+    assert_eq!(&account.data[..40], &[0u8; 40]);
+}
+
+#[test]
+fn message_hashes_match_the_test_vectors() {
+    let vectors: Vec<_> = test_vectors().into_iter().filter(|vector| vector.hash == MESSAGE_HASH).collect();
+    assert!(!vectors.is_empty());
+    for vector in vectors {
+        let digest = match vector.parent_state_root() {
+            Some(parent_state_root) => chained_commitment_message_hash(&parent_state_root, &vector.new_state_root(), vector.sequence, &vector.outbox_root(), &vector.producer()),
+            None => commitment_message_hash(&vector.new_state_root(), vector.sequence, &vector.outbox_root(), &vector.producer()),
+        };
+        assert_eq!(digest, vector.digest(), "{}", vector.name);
+    }
+}
+
+fn fixture_chained_commitment(fixture: &CommitmentFixture) -> ChainedCommitment {
+    ChainedCommitment {
+        verifier_signature: fixture.verifier_signature(),
+        recovery_id: fixture.recovery_id(),
+        public_key: fixture.public_key(),
+        parent_state_root: fixture.parent_state_root(),
+        new_state_root: fixture.new_state_root(),
+        sequence: fixture.sequence,
+        outbox_root: fixture.outbox_root(),
+        producer: fixture.producer(),
+    }
+}
+
+#[test]
+fn instructions_match_the_instruction_vectors() {
+    let mut checked = 0;
+    for vector in instruction_vectors() {
+        let instruction = match vector.instruction {
+            InstructionKind::Initialize => ProgramInstruction::Initialize,
+            InstructionKind::VerifySig => {
+                let fixture = vector.commitment();
+                ProgramInstruction::VerifySig(ZkProofCommitment {
+                    verifier_signature: fixture.verifier_signature(),
+                    recovery_id: fixture.recovery_id(),
+                    public_key: fixture.public_key(),
+                    new_state_root: fixture.new_state_root(),
+                    sequence: fixture.sequence,
+                    outbox_root: fixture.outbox_root(),
+                    producer: fixture.producer(),
+                })
+            }
+            InstructionKind::VerifySigBatch => ProgramInstruction::VerifySigBatch(vector.commitments.iter().map(fixture_chained_commitment).collect()),
+            InstructionKind::VerifyEd25519 => {
+                let fixture = vector.commitment();
+                ProgramInstruction::VerifyEd25519(Ed25519Commitment {
+                    public_key: fixture.public_key(),
+                    new_state_root: fixture.new_state_root(),
+                    sequence: fixture.sequence,
+                    outbox_root: fixture.outbox_root(),
+                    producer: fixture.producer(),
+                })
+            }
+            InstructionKind::SetValidator => {
+                let fixture = vector.validator();
+                ProgramInstruction::SetValidator(ValidatorKeys { secp256k1: fixture.secp256k1(), ed25519: fixture.ed25519() })
+            }
+            InstructionKind::Migrate => ProgramInstruction::Migrate,
+            InstructionKind::VerifyProof | InstructionKind::SetVerifyingKey => continue,
+        };
+        assert_eq!(to_vec(&instruction).unwrap(), vector.data(), "{}", vector.name);
+        // The decoder reads the whole golden encoding back
+        let decoded = ProgramInstruction::try_from_slice(&vector.data()).unwrap_or_else(|_| panic!("{} doesn't decode", vector.name));
+        assert_eq!(to_vec(&decoded).unwrap(), vector.data(), "{}", vector.name);
+        checked += 1;
+    }
+    assert!(checked > 0);
+}
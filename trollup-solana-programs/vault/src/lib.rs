@@ -0,0 +1,788 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::account_info::next_account_info;
+use solana_program::clock::Clock;
+use solana_program::hash::{hash, hashv};
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::program::{invoke, invoke_signed, set_return_data};
+use solana_program::program_error::ProgramError;
+use solana_program::program_pack::Pack;
+use solana_program::rent::Rent;
+use solana_program::sysvar::Sysvar;
+use solana_program::{account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, msg, pubkey::Pubkey, system_instruction, system_program};
+
+pub const VAULT_SEED: &[u8] = b"vault";
+pub const TOKEN_VAULT_SEED: &[u8] = b"token_vault";
+pub const LIABILITY_SEED: &[u8] = b"liability";
+pub const WITHDRAWAL_SEED: &[u8] = b"withdrawal";
+pub const ESCAPE_SEED: &[u8] = b"escape";
+/// Seed of the verifier program's state account holding the current rollup root
+pub const VERIFIER_STATE_SEED: &[u8] = b"state";
+
+#[cfg(not(feature = "no-entrypoint"))]
+entrypoint!(process_instruction);
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub enum VaultInstruction {
+    /// Creates the vault account. `sequencer` is the key that posts the account set roots
+    /// emergency withdrawals are proven against.
+    ///
+    /// Accounts: vault (writable), payer (signer, writable), system program
+    Initialize { verifier_program_id: Pubkey, sequencer: Pubkey, escape_delay_secs: u64 },
+    /// Creates the token account and liability record for an SPL mint.
+    ///
+    /// Accounts: vault, mint, token vault (writable), liability (writable), payer (signer,
+    /// writable), system program, token program
+    InitializeTokenVault,
+    /// Escrows lamports (`mint` is `None`) or SPL tokens, to be credited to `rollup_recipient`
+    /// on the rollup.
+    ///
+    /// Lamport accounts: vault (writable), depositor (signer, writable), system program
+    /// Token accounts: vault, depositor (signer), depositor token account (writable), token
+    /// vault (writable), liability (writable), token program
+    Deposit { amount: u64, rollup_recipient: Pubkey, mint: Option<Pubkey> },
+    /// Pays out a withdrawal included in the last state root unlocked by the verifier.
+    ///
+    /// Accounts: vault (writable), withdrawal receipt (writable), recipient (writable), payer
+    /// (signer, writable), system program
+    /// Token withdrawals add: token vault (writable), recipient token account (writable),
+    /// liability (writable), token program
+    ProcessWithdrawal(Withdrawal),
+    /// Sets an `AuditReport` as return data, simulate the transaction to read it.
+    ///
+    /// Accounts: vault, then a liability and token vault account per mint to include
+    Audit,
+    /// Makes `root` the root withdrawals are proven against. Only the verifier program can call
+    /// this, through a CPI signed by its state PDA once it accepted `root`.
+    ///
+    /// Accounts: vault (writable), verifier state (signer)
+    UnlockWithdrawalRoot { root: [u8; 32] },
+    /// Pays a rollup account's balance out directly, proven against the last posted account set
+    /// root, once no new root was unlocked for `escape_delay_secs`. The first one halts the vault
+    /// for good: no root is unlocked or posted after it.
+    ///
+    /// Accounts: vault (writable), escape receipt (writable), rollup account (signer), recipient
+    /// (writable), payer (signer, writable), system program
+    EmergencyWithdrawal(EscapeClaim),
+    /// Makes `root`, the root over every rollup account of the checkpoint of `epoch`, the root
+    /// emergency withdrawals are proven against. Epochs only move forward.
+    ///
+    /// Accounts: vault (writable), sequencer (signer)
+    PostAccountSetRoot { epoch: u64, root: [u8; 32] },
+    /// Pays out several withdrawals included in the last state root unlocked by the verifier,
+    /// proven together by one Merkle multiproof.
+    ///
+    /// Accounts: vault (writable), payer (signer, writable), system program, token program, then
+    /// per withdrawal a withdrawal receipt (writable) and recipient (writable)
+    /// Token withdrawals add: token vault (writable), recipient token account (writable),
+    /// liability (writable)
+    ProcessWithdrawalBatch(WithdrawalBatch),
+}
+
+/// Builds the `UnlockWithdrawalRoot` instruction the verifier program invokes.
+pub fn unlock_withdrawal_root(vault_program_id: &Pubkey, verifier_state: &Pubkey, root: [u8; 32]) -> Instruction {
+    let (vault, _) = Pubkey::find_program_address(&[VAULT_SEED], vault_program_id);
+    Instruction::new_with_borsh(
+        *vault_program_id,
+        &VaultInstruction::UnlockWithdrawalRoot { root },
+        vec![AccountMeta::new(vault, false), AccountMeta::new_readonly(*verifier_state, true)],
+    )
+}
+
+/// Builds the `PostAccountSetRoot` instruction the sequencer sends for each checkpoint.
+pub fn post_account_set_root(vault_program_id: &Pubkey, sequencer: &Pubkey, epoch: u64, root: [u8; 32]) -> Instruction {
+    let (vault, _) = Pubkey::find_program_address(&[VAULT_SEED], vault_program_id);
+    Instruction::new_with_borsh(
+        *vault_program_id,
+        &VaultInstruction::PostAccountSetRoot { epoch, root },
+        vec![AccountMeta::new(vault, false), AccountMeta::new_readonly(*sequencer, true)],
+    )
+}
+
+/// Lamport totals of the vault. Liabilities are what the vault owes rollup accounts.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct VaultState {
+    pub verifier_program_id: Pubkey,
+    pub deposited_lamports: u64,
+    pub withdrawn_lamports: u64,
+    /// Last state root accepted by the verifier, all zeroes until the first one
+    pub withdrawal_root: [u8; 32],
+    /// Unix timestamp of the last unlocked root, or of initialization before the first one
+    pub root_unlocked_at: i64,
+    /// Seconds without a new root after which emergency withdrawals are accepted, 0 never
+    pub escape_delay_secs: u64,
+    /// Set by the first emergency withdrawal
+    pub halted: bool,
+    /// Key allowed to post account set roots
+    pub sequencer: Pubkey,
+    /// Root over every rollup account, sorted by address, at the last posted checkpoint. All
+    /// zeroes until the first one.
+    pub account_set_root: [u8; 32],
+    /// Checkpoint epoch of `account_set_root`
+    pub account_set_epoch: u64,
+}
+
+impl VaultState {
+    pub const SIZE: usize = 32 + 8 + 8 + 32 + 8 + 8 + 1 + 32 + 32 + 8;
+
+    pub fn liabilities(&self) -> u64 {
+        self.deposited_lamports.saturating_sub(self.withdrawn_lamports)
+    }
+
+    /// Whether the rollup counts as halted at `now`: it has an account set root to escape
+    /// against and hasn't had a root unlocked for `escape_delay_secs`.
+    pub fn escape_open(&self, now: i64) -> bool {
+        self.halted
+            || (self.escape_delay_secs > 0
+                && self.account_set_root != [0u8; 32]
+                && now.saturating_sub(self.root_unlocked_at) >= self.escape_delay_secs as i64)
+    }
+}
+
+/// Token totals of the vault for one mint.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MintLiability {
+    pub mint: Pubkey,
+    pub deposited: u64,
+    pub withdrawn: u64,
+}
+
+impl MintLiability {
+    pub const SIZE: usize = 32 + 8 + 8;
+
+    pub fn liabilities(&self) -> u64 {
+        self.deposited.saturating_sub(self.withdrawn)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TokenAudit {
+    pub mint: Pubkey,
+    pub liabilities: u64,
+    pub vault_balance: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditReport {
+    pub lamport_liabilities: u64,
+    /// Lamports held by the vault above its rent exempt minimum
+    pub vault_lamports: u64,
+    pub tokens: Vec<TokenAudit>,
+}
+
+/// A withdrawal and its inclusion proof against the rollup state root.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Withdrawal {
+    pub recipient: Pubkey,
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+    /// Distinguishes otherwise identical withdrawals
+    pub nonce: u64,
+    pub leaf_index: u64,
+    pub leaf_count: u64,
+    /// Sibling hashes from the leaf up to the root
+    pub proof: Vec<[u8; 32]>,
+}
+
+impl Withdrawal {
+    /// The leaf the rollup inserts into its state tree for this withdrawal.
+    pub fn leaf(&self) -> [u8; 32] {
+        withdrawal_leaf(&self.recipient, self.mint, self.amount, self.nonce)
+    }
+}
+
+/// A withdrawal of a `WithdrawalBatch`, its proof is the batch's.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BatchedWithdrawal {
+    pub recipient: Pubkey,
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+    pub nonce: u64,
+    pub leaf_index: u64,
+}
+
+impl BatchedWithdrawal {
+    pub fn leaf(&self) -> [u8; 32] {
+        withdrawal_leaf(&self.recipient, self.mint, self.amount, self.nonce)
+    }
+}
+
+/// Withdrawals of one state root and their Merkle multiproof.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct WithdrawalBatch {
+    /// Sorted by `leaf_index`, with no index twice
+    pub withdrawals: Vec<BatchedWithdrawal>,
+    pub leaf_count: u64,
+    /// The hashes `merkle_multiproof_root` can't compute from the leaves, layer by layer from the
+    /// leaves up and left to right in a layer, as `rs_merkle` orders them
+    pub proof: Vec<[u8; 32]>,
+}
+
+fn withdrawal_leaf(recipient: &Pubkey, mint: Option<Pubkey>, amount: u64, nonce: u64) -> [u8; 32] {
+    hashv(&[
+        b"trollup-withdrawal",
+        recipient.as_ref(),
+        mint.unwrap_or_default().as_ref(),
+        &amount.to_le_bytes(),
+        &nonce.to_le_bytes(),
+    ]).to_bytes()
+}
+
+/// A rollup account as the state tree commits it, the fields of the rollup's `AccountState`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RollupAccount {
+    pub address: Pubkey,
+    pub lamports: u64,
+    /// Left out of the leaf of an account with a `data_root`
+    pub data: Vec<u8>,
+    pub owner: Pubkey,
+    pub executable: bool,
+    pub rent_epoch: u64,
+    /// Root of the chunk tree over the data of large accounts
+    pub data_root: Option<[u8; 32]>,
+}
+
+impl RollupAccount {
+    /// The account's leaf in the state tree, as the rollup's `AccountState::leaf_bytes` hashes
+    /// it: the Borsh encoding of every field but `data_root`, followed by the data root in place
+    /// of the data when there is one.
+    pub fn leaf(&self) -> [u8; 32] {
+        let data: &[u8] = if self.data_root.is_some() { &[] } else { &self.data };
+        let mut bytes = Vec::with_capacity(32 + 8 + 4 + data.len() + 32 + 1 + 8 + 32);
+        self.address.serialize(&mut bytes).unwrap();
+        self.lamports.serialize(&mut bytes).unwrap();
+        data.serialize(&mut bytes).unwrap();
+        self.owner.serialize(&mut bytes).unwrap();
+        self.executable.serialize(&mut bytes).unwrap();
+        self.rent_epoch.serialize(&mut bytes).unwrap();
+        if let Some(data_root) = &self.data_root {
+            bytes.extend_from_slice(data_root);
+        }
+        hash(&bytes).to_bytes()
+    }
+}
+
+/// A rollup account and its inclusion proof against the last posted account set root, paid out to
+/// `recipient` by an emergency withdrawal.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EscapeClaim {
+    pub account: RollupAccount,
+    pub recipient: Pubkey,
+    pub leaf_index: u64,
+    pub leaf_count: u64,
+    /// Sibling hashes from the leaf up to the root
+    pub proof: Vec<[u8; 32]>,
+}
+
+/// Computes the root of a SHA-256 Merkle tree built like `rs_merkle`, where the last node of an
+/// odd layer is promoted to the next layer unhashed.
+pub fn merkle_root(leaf: [u8; 32], mut index: u64, mut count: u64, proof: &[[u8; 32]]) -> Option<[u8; 32]> {
+    if index >= count {
+        return None;
+    }
+    let mut node = leaf;
+    let mut siblings = proof.iter();
+    while count > 1 {
+        if index % 2 == 1 {
+            node = hashv(&[siblings.next()?, &node]).to_bytes();
+        } else if index + 1 < count {
+            node = hashv(&[&node, siblings.next()?]).to_bytes();
+        }
+        index /= 2;
+        count = (count + 1) / 2;
+    }
+    if siblings.next().is_some() {
+        return None;
+    }
+    Some(node)
+}
+
+/// Computes the root of the same tree as `merkle_root` from several of its leaves, given as
+/// `(index, leaf)` sorted by index, and the proof hashes of all of them.
+pub fn merkle_multiproof_root(leaves: &[(u64, [u8; 32])], mut count: u64, proof: &[[u8; 32]]) -> Option<[u8; 32]> {
+    if leaves.is_empty() || leaves.windows(2).any(|pair| pair[0].0 >= pair[1].0) || leaves.last()?.0 >= count {
+        return None;
+    }
+    let mut layer = leaves.to_vec();
+    let mut siblings = proof.iter();
+    while count > 1 {
+        let mut next_layer = Vec::with_capacity(layer.len());
+        let mut nodes = layer.into_iter().peekable();
+        while let Some((index, node)) = nodes.next() {
+            let parent = if index % 2 == 1 {
+                // A known left sibling was consumed along with its right one
+                hashv(&[siblings.next()?, &node]).to_bytes()
+            } else if index + 1 < count {
+                match nodes.next_if(|(next_index, _)| *next_index == index + 1) {
+                    Some((_, sibling)) => hashv(&[&node, &sibling]).to_bytes(),
+                    None => hashv(&[&node, siblings.next()?]).to_bytes(),
+                }
+            } else {
+                node
+            };
+            next_layer.push((index / 2, parent));
+        }
+        layer = next_layer;
+        count = count.div_ceil(2);
+    }
+    if siblings.next().is_some() {
+        return None;
+    }
+    Some(layer[0].1)
+}
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let instruction = VaultInstruction::try_from_slice(instruction_data)?;
+
+    match instruction {
+        VaultInstruction::Initialize { verifier_program_id, sequencer, escape_delay_secs } => initialize(program_id, accounts, verifier_program_id, sequencer, escape_delay_secs),
+        VaultInstruction::InitializeTokenVault => initialize_token_vault(program_id, accounts),
+        VaultInstruction::Deposit { amount, rollup_recipient, mint } => deposit(program_id, accounts, amount, rollup_recipient, mint),
+        VaultInstruction::ProcessWithdrawal(withdrawal) => process_withdrawal(program_id, accounts, withdrawal),
+        VaultInstruction::Audit => audit(program_id, accounts),
+        VaultInstruction::UnlockWithdrawalRoot { root } => unlock_root(program_id, accounts, root),
+        VaultInstruction::EmergencyWithdrawal(claim) => emergency_withdrawal(program_id, accounts, claim),
+        VaultInstruction::PostAccountSetRoot { epoch, root } => post_root(program_id, accounts, epoch, root),
+        VaultInstruction::ProcessWithdrawalBatch(batch) => process_withdrawal_batch(program_id, accounts, batch),
+    }
+}
+
+fn initialize(program_id: &Pubkey, accounts: &[AccountInfo], verifier_program_id: Pubkey, sequencer: Pubkey, escape_delay_secs: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let vault = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    let bump_seed = check_pda(vault, &[VAULT_SEED], program_id)?;
+    if !vault.data_is_empty() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    create_pda_account(payer, vault, system_program, VaultState::SIZE, program_id, &[VAULT_SEED, &[bump_seed]])?;
+    write_state(vault, &VaultState {
+        verifier_program_id,
+        root_unlocked_at: Clock::get()?.unix_timestamp,
+        escape_delay_secs,
+        sequencer,
+        ..VaultState::default()
+    })?;
+
+    msg!("Vault initialized");
+    Ok(())
+}
+
+fn initialize_token_vault(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let vault = next_account_info(account_info_iter)?;
+    let mint = next_account_info(account_info_iter)?;
+    let token_vault = next_account_info(account_info_iter)?;
+    let liability = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    check_pda(vault, &[VAULT_SEED], program_id)?;
+    check_token_program(token_program)?;
+    let token_vault_bump = check_pda(token_vault, &[TOKEN_VAULT_SEED, mint.key.as_ref()], program_id)?;
+    let liability_bump = check_pda(liability, &[LIABILITY_SEED, mint.key.as_ref()], program_id)?;
+    if !token_vault.data_is_empty() || !liability.data_is_empty() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    create_pda_account(payer, token_vault, system_program, spl_token::state::Account::LEN, token_program.key, &[TOKEN_VAULT_SEED, mint.key.as_ref(), &[token_vault_bump]])?;
+    invoke(
+        &spl_token::instruction::initialize_account3(token_program.key, token_vault.key, mint.key, vault.key)?,
+        &[token_vault.clone(), mint.clone()],
+    )?;
+
+    create_pda_account(payer, liability, system_program, MintLiability::SIZE, program_id, &[LIABILITY_SEED, mint.key.as_ref(), &[liability_bump]])?;
+    write_state(liability, &MintLiability { mint: *mint.key, ..MintLiability::default() })?;
+
+    msg!("Token vault initialized for {}", mint.key);
+    Ok(())
+}
+
+fn deposit(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64, rollup_recipient: Pubkey, mint: Option<Pubkey>) -> ProgramResult {
+    if amount == 0 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let account_info_iter = &mut accounts.iter();
+    let vault = next_account_info(account_info_iter)?;
+    let depositor = next_account_info(account_info_iter)?;
+    check_pda(vault, &[VAULT_SEED], program_id)?;
+    if !depositor.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    match mint {
+        None => {
+            let system_program = next_account_info(account_info_iter)?;
+            invoke(
+                &system_instruction::transfer(depositor.key, vault.key, amount),
+                &[depositor.clone(), vault.clone(), system_program.clone()],
+            )?;
+            let mut state: VaultState = read_state(vault, program_id)?;
+            state.deposited_lamports = state.deposited_lamports.checked_add(amount).ok_or(ProgramError::ArithmeticOverflow)?;
+            write_state(vault, &state)?;
+        }
+        Some(mint) => {
+            let depositor_token_account = next_account_info(account_info_iter)?;
+            let token_vault = next_account_info(account_info_iter)?;
+            let liability = next_account_info(account_info_iter)?;
+            let token_program = next_account_info(account_info_iter)?;
+            check_token_program(token_program)?;
+            check_pda(token_vault, &[TOKEN_VAULT_SEED, mint.as_ref()], program_id)?;
+            check_pda(liability, &[LIABILITY_SEED, mint.as_ref()], program_id)?;
+            invoke(
+                &spl_token::instruction::transfer(token_program.key, depositor_token_account.key, token_vault.key, depositor.key, &[], amount)?,
+                &[depositor_token_account.clone(), token_vault.clone(), depositor.clone()],
+            )?;
+            let mut mint_liability: MintLiability = read_state(liability, program_id)?;
+            mint_liability.deposited = mint_liability.deposited.checked_add(amount).ok_or(ProgramError::ArithmeticOverflow)?;
+            write_state(liability, &mint_liability)?;
+        }
+    }
+
+    // The sequencer credits lamport deposits from the instruction, see the rollup's `vault-deposits` job
+    msg!("Deposit {} {} to {}", amount, mint.map(|mint| mint.to_string()).unwrap_or_else(|| "lamports".to_string()), rollup_recipient);
+    Ok(())
+}
+
+fn process_withdrawal(program_id: &Pubkey, accounts: &[AccountInfo], withdrawal: Withdrawal) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let vault = next_account_info(account_info_iter)?;
+    let receipt = next_account_info(account_info_iter)?;
+    let recipient = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    let vault_bump = check_pda(vault, &[VAULT_SEED], program_id)?;
+    let mut state: VaultState = read_state(vault, program_id)?;
+    if recipient.key != &withdrawal.recipient {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // The withdrawal must be part of the last root the verifier unlocked
+    let leaf = withdrawal.leaf();
+    let root = merkle_root(leaf, withdrawal.leaf_index, withdrawal.leaf_count, &withdrawal.proof);
+    if state.withdrawal_root == [0u8; 32] || root != Some(state.withdrawal_root) {
+        msg!("Withdrawal isn't included in the unlocked state root.");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let token_accounts = match withdrawal.mint {
+        None => None,
+        Some(_) => {
+            let token_vault = next_account_info(account_info_iter)?;
+            let recipient_token_account = next_account_info(account_info_iter)?;
+            let liability = next_account_info(account_info_iter)?;
+            let token_program = next_account_info(account_info_iter)?;
+            Some(TokenAccounts { token_vault, recipient_token_account, liability, token_program })
+        }
+    };
+    let payout = Payout { leaf, recipient, mint: withdrawal.mint, amount: withdrawal.amount, token_accounts };
+    pay_out(program_id, vault, vault_bump, &mut state, receipt, payer, system_program, payout)?;
+    write_state(vault, &state)?;
+
+    msg!("Withdrawal of {} to {} processed", withdrawal.amount, withdrawal.recipient);
+    Ok(())
+}
+
+fn process_withdrawal_batch(program_id: &Pubkey, accounts: &[AccountInfo], batch: WithdrawalBatch) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let vault = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    let vault_bump = check_pda(vault, &[VAULT_SEED], program_id)?;
+    let mut state: VaultState = read_state(vault, program_id)?;
+
+    // Every withdrawal must be part of the last root the verifier unlocked
+    let leaves: Vec<(u64, [u8; 32])> = batch.withdrawals.iter().map(|withdrawal| (withdrawal.leaf_index, withdrawal.leaf())).collect();
+    let root = merkle_multiproof_root(&leaves, batch.leaf_count, &batch.proof);
+    if state.withdrawal_root == [0u8; 32] || root != Some(state.withdrawal_root) {
+        msg!("Withdrawals aren't included in the unlocked state root.");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    for (withdrawal, (_, leaf)) in batch.withdrawals.iter().zip(leaves) {
+        let receipt = next_account_info(account_info_iter)?;
+        let recipient = next_account_info(account_info_iter)?;
+        if recipient.key != &withdrawal.recipient {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let token_accounts = match withdrawal.mint {
+            None => None,
+            Some(_) => {
+                let token_vault = next_account_info(account_info_iter)?;
+                let recipient_token_account = next_account_info(account_info_iter)?;
+                let liability = next_account_info(account_info_iter)?;
+                Some(TokenAccounts { token_vault, recipient_token_account, liability, token_program })
+            }
+        };
+        let payout = Payout { leaf, recipient, mint: withdrawal.mint, amount: withdrawal.amount, token_accounts };
+        pay_out(program_id, vault, vault_bump, &mut state, receipt, payer, system_program, payout)?;
+    }
+    write_state(vault, &state)?;
+
+    msg!("Batch of {} withdrawals processed", batch.withdrawals.len());
+    Ok(())
+}
+
+/// Accounts a token withdrawal is paid from and to.
+struct TokenAccounts<'a, 'b> {
+    token_vault: &'a AccountInfo<'b>,
+    recipient_token_account: &'a AccountInfo<'b>,
+    liability: &'a AccountInfo<'b>,
+    token_program: &'a AccountInfo<'b>,
+}
+
+/// A proven withdrawal to pay, `token_accounts` is set when it has a `mint`.
+struct Payout<'a, 'b> {
+    leaf: [u8; 32],
+    recipient: &'a AccountInfo<'b>,
+    mint: Option<Pubkey>,
+    amount: u64,
+    token_accounts: Option<TokenAccounts<'a, 'b>>,
+}
+
+/// Creates the receipt of a proven withdrawal and pays it out. Lamport withdrawals are added to
+/// `state`, which the caller writes back.
+#[allow(clippy::too_many_arguments)]
+fn pay_out<'a>(
+    program_id: &Pubkey,
+    vault: &AccountInfo<'a>,
+    vault_bump: u8,
+    state: &mut VaultState,
+    receipt: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    payout: Payout<'_, 'a>,
+) -> ProgramResult {
+    // The receipt marks the withdrawal as paid, creating it fails if it already exists
+    let receipt_bump = check_pda(receipt, &[WITHDRAWAL_SEED, &payout.leaf], program_id)?;
+    if !receipt.data_is_empty() {
+        msg!("Withdrawal was already processed.");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    create_pda_account(payer, receipt, system_program, 1, program_id, &[WITHDRAWAL_SEED, &payout.leaf, &[receipt_bump]])?;
+
+    match (payout.mint, payout.token_accounts) {
+        (None, _) => {
+            let minimum_balance = Rent::get()?.minimum_balance(vault.data_len());
+            if vault.lamports().saturating_sub(payout.amount) < minimum_balance || payout.amount > state.liabilities() {
+                return Err(ProgramError::InsufficientFunds);
+            }
+            **vault.try_borrow_mut_lamports()? -= payout.amount;
+            **payout.recipient.try_borrow_mut_lamports()? += payout.amount;
+            state.withdrawn_lamports = state.withdrawn_lamports.checked_add(payout.amount).ok_or(ProgramError::ArithmeticOverflow)?;
+        }
+        (Some(mint), Some(TokenAccounts { token_vault, recipient_token_account, liability, token_program })) => {
+            check_token_program(token_program)?;
+            check_pda(token_vault, &[TOKEN_VAULT_SEED, mint.as_ref()], program_id)?;
+            check_pda(liability, &[LIABILITY_SEED, mint.as_ref()], program_id)?;
+            let mut mint_liability: MintLiability = read_state(liability, program_id)?;
+            if payout.amount > mint_liability.liabilities() {
+                return Err(ProgramError::InsufficientFunds);
+            }
+            invoke_signed(
+                &spl_token::instruction::transfer(token_program.key, token_vault.key, recipient_token_account.key, vault.key, &[], payout.amount)?,
+                &[token_vault.clone(), recipient_token_account.clone(), vault.clone()],
+                &[&[VAULT_SEED, &[vault_bump]]],
+            )?;
+            mint_liability.withdrawn = mint_liability.withdrawn.checked_add(payout.amount).ok_or(ProgramError::ArithmeticOverflow)?;
+            write_state(liability, &mint_liability)?;
+        }
+        (Some(_), None) => return Err(ProgramError::NotEnoughAccountKeys),
+    }
+    Ok(())
+}
+
+fn audit(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let vault = next_account_info(account_info_iter)?;
+    check_pda(vault, &[VAULT_SEED], program_id)?;
+    let state: VaultState = read_state(vault, program_id)?;
+    let minimum_balance = Rent::get()?.minimum_balance(vault.data_len());
+
+    let mut tokens = Vec::new();
+    while let (Ok(liability), Ok(token_vault)) = (next_account_info(account_info_iter), next_account_info(account_info_iter)) {
+        let mint_liability: MintLiability = read_state(liability, program_id)?;
+        check_pda(liability, &[LIABILITY_SEED, mint_liability.mint.as_ref()], program_id)?;
+        check_pda(token_vault, &[TOKEN_VAULT_SEED, mint_liability.mint.as_ref()], program_id)?;
+        let token_account = spl_token::state::Account::unpack(&token_vault.try_borrow_data()?)?;
+        tokens.push(TokenAudit {
+            mint: mint_liability.mint,
+            liabilities: mint_liability.liabilities(),
+            vault_balance: token_account.amount,
+        });
+    }
+
+    let report = AuditReport {
+        lamport_liabilities: state.liabilities(),
+        vault_lamports: vault.lamports().saturating_sub(minimum_balance),
+        tokens,
+    };
+    msg!("Lamport liabilities {}, vault holds {}", report.lamport_liabilities, report.vault_lamports);
+    set_return_data(&borsh::to_vec(&report)?);
+    Ok(())
+}
+
+fn unlock_root(program_id: &Pubkey, accounts: &[AccountInfo], root: [u8; 32]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let vault = next_account_info(account_info_iter)?;
+    let verifier_state = next_account_info(account_info_iter)?;
+
+    check_pda(vault, &[VAULT_SEED], program_id)?;
+    let mut state: VaultState = read_state(vault, program_id)?;
+    check_pda(verifier_state, &[VERIFIER_STATE_SEED], &state.verifier_program_id)?;
+    if !verifier_state.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    // Emergency withdrawals paid balances out of the last root, a newer one would pay them twice
+    if state.halted {
+        msg!("Vault is halted, no new root is accepted.");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    state.withdrawal_root = root;
+    state.root_unlocked_at = Clock::get()?.unix_timestamp;
+    write_state(vault, &state)?;
+    msg!("Withdrawal root unlocked");
+    Ok(())
+}
+
+fn emergency_withdrawal(program_id: &Pubkey, accounts: &[AccountInfo], claim: EscapeClaim) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let vault = next_account_info(account_info_iter)?;
+    let receipt = next_account_info(account_info_iter)?;
+    let rollup_account = next_account_info(account_info_iter)?;
+    let recipient = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    check_pda(vault, &[VAULT_SEED], program_id)?;
+    let mut state: VaultState = read_state(vault, program_id)?;
+    if !state.escape_open(Clock::get()?.unix_timestamp) {
+        msg!("The rollup is still advancing its root, emergency withdrawals are closed.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    // Only the holder of the rollup account's key can take its balance
+    if rollup_account.key != &claim.account.address || !rollup_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if recipient.key != &claim.recipient {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // The account set root holds every account's latest balance, a block's root only the
+    // accounts the block wrote
+    let root = merkle_root(claim.account.leaf(), claim.leaf_index, claim.leaf_count, &claim.proof);
+    if root != Some(state.account_set_root) {
+        msg!("Account isn't included in the last posted account set root.");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // One escape per rollup account, its balance in the account set is all it can claim
+    let receipt_bump = check_pda(receipt, &[ESCAPE_SEED, claim.account.address.as_ref()], program_id)?;
+    if !receipt.data_is_empty() {
+        msg!("Account already escaped.");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    create_pda_account(payer, receipt, system_program, 1, program_id, &[ESCAPE_SEED, claim.account.address.as_ref(), &[receipt_bump]])?;
+
+    let amount = claim.account.lamports;
+    let minimum_balance = Rent::get()?.minimum_balance(vault.data_len());
+    if vault.lamports().saturating_sub(amount) < minimum_balance || amount > state.liabilities() {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    **vault.try_borrow_mut_lamports()? -= amount;
+    **recipient.try_borrow_mut_lamports()? += amount;
+    state.withdrawn_lamports = state.withdrawn_lamports.checked_add(amount).ok_or(ProgramError::ArithmeticOverflow)?;
+    state.halted = true;
+    write_state(vault, &state)?;
+
+    msg!("Emergency withdrawal of {} from {} to {}", amount, claim.account.address, claim.recipient);
+    Ok(())
+}
+
+fn post_root(program_id: &Pubkey, accounts: &[AccountInfo], epoch: u64, root: [u8; 32]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let vault = next_account_info(account_info_iter)?;
+    let sequencer = next_account_info(account_info_iter)?;
+
+    check_pda(vault, &[VAULT_SEED], program_id)?;
+    let mut state: VaultState = read_state(vault, program_id)?;
+    if sequencer.key != &state.sequencer || !sequencer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    // Balances were paid out of the posted root, a newer one would pay them twice
+    if state.halted {
+        msg!("Vault is halted, no new root is accepted.");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if epoch <= state.account_set_epoch {
+        msg!("Account set root of epoch {} is older than the posted one of epoch {}.", epoch, state.account_set_epoch);
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    state.account_set_root = root;
+    state.account_set_epoch = epoch;
+    write_state(vault, &state)?;
+    msg!("Account set root of epoch {} posted", epoch);
+    Ok(())
+}
+
+/// Checks `account` is the PDA of `seeds` and returns its bump seed.
+fn check_pda(account: &AccountInfo, seeds: &[&[u8]], program_id: &Pubkey) -> Result<u8, ProgramError> {
+    let (pda, bump_seed) = Pubkey::find_program_address(seeds, program_id);
+    if account.key != &pda {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(bump_seed)
+}
+
+fn check_token_program(token_program: &AccountInfo) -> ProgramResult {
+    if token_program.key != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    space: usize,
+    owner: &Pubkey,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    if system_program.key != &system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let lamports = Rent::get()?.minimum_balance(space);
+    invoke_signed(
+        &system_instruction::create_account(payer.key, account.key, lamports, space as u64, owner),
+        &[payer.clone(), account.clone(), system_program.clone()],
+        &[signer_seeds],
+    )
+}
+
+fn read_state<T: BorshDeserialize>(account: &AccountInfo, program_id: &Pubkey) -> Result<T, ProgramError> {
+    if account.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+    T::try_from_slice(&account.try_borrow_data()?).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+fn write_state<T: BorshSerialize>(account: &AccountInfo, state: &T) -> ProgramResult {
+    if !account.is_writable {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    state.serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
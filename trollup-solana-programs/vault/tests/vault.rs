@@ -0,0 +1,578 @@
+use borsh::{to_vec, BorshDeserialize};
+use rs_merkle::algorithms::Sha256;
+use rs_merkle::MerkleTree;
+use solana_program::account_info::AccountInfo;
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::hash::hash;
+use solana_program::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_program::program::invoke_signed;
+use solana_program::pubkey::Pubkey;
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+use trollup_vault::{merkle_multiproof_root, post_account_set_root, process_instruction, unlock_withdrawal_root, AuditReport, BatchedWithdrawal, EscapeClaim, RollupAccount, VaultInstruction, VaultState, Withdrawal, WithdrawalBatch, ESCAPE_SEED, VAULT_SEED, VERIFIER_STATE_SEED, WITHDRAWAL_SEED};
+
+/// Escape delay of the test vault
+const ESCAPE_DELAY_SECS: u64 = 3_600;
+
+struct Vault {
+    program_id: Pubkey,
+    verifier_program_id: Pubkey,
+    vault: Pubkey,
+    verifier_state: Pubkey,
+    sequencer: Keypair,
+}
+
+/// Stands in for the verifier program, unlocking the root in the instruction data as if it had
+/// just been accepted.
+fn mock_verifier(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let (vault, verifier_state, vault_program) = (&accounts[0], &accounts[1], &accounts[2]);
+    let (_, bump_seed) = Pubkey::find_program_address(&[VERIFIER_STATE_SEED], program_id);
+    invoke_signed(
+        &unlock_withdrawal_root(vault_program.key, verifier_state.key, instruction_data.try_into().unwrap()),
+        &[vault.clone(), verifier_state.clone()],
+        &[&[VERIFIER_STATE_SEED, &[bump_seed]]],
+    )
+}
+
+/// Starts a test validator with the vault program and a mock verifier program.
+async fn start() -> (ProgramTestContext, Vault) {
+    let program_id = Pubkey::new_unique();
+    let verifier_program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new("trollup_vault", program_id, processor!(process_instruction));
+    program_test.add_program("mock_verifier", verifier_program_id, processor!(mock_verifier));
+    let (verifier_state, _) = Pubkey::find_program_address(&[VERIFIER_STATE_SEED], &verifier_program_id);
+
+    let context = program_test.start_with_context().await;
+    let (vault, _) = Pubkey::find_program_address(&[VAULT_SEED], &program_id);
+    (context, Vault { program_id, verifier_program_id, vault, verifier_state, sequencer: Keypair::new() })
+}
+
+async fn send(context: &mut ProgramTestContext, instruction: Instruction) -> Result<(), TransactionError> {
+    send_signed(context, instruction, &[]).await
+}
+
+async fn send_signed(context: &mut ProgramTestContext, instruction: Instruction, signers: &[&Keypair]) -> Result<(), TransactionError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.map_err(|e| e.unwrap())
+}
+
+async fn initialize(context: &mut ProgramTestContext, vault: &Vault) {
+    let instruction = Instruction::new_with_bytes(
+        vault.program_id,
+        &to_vec(&VaultInstruction::Initialize {
+            verifier_program_id: vault.verifier_program_id,
+            sequencer: vault.sequencer.pubkey(),
+            escape_delay_secs: ESCAPE_DELAY_SECS,
+        }).unwrap(),
+        vec![
+            AccountMeta::new(vault.vault, false),
+            AccountMeta::new(context.payer.pubkey(), true),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        ],
+    );
+    send(context, instruction).await.unwrap();
+}
+
+async fn deposit(context: &mut ProgramTestContext, vault: &Vault, amount: u64) {
+    let instruction = Instruction::new_with_bytes(
+        vault.program_id,
+        &to_vec(&VaultInstruction::Deposit { amount, rollup_recipient: Pubkey::new_unique(), mint: None }).unwrap(),
+        vec![
+            AccountMeta::new(vault.vault, false),
+            AccountMeta::new(context.payer.pubkey(), true),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        ],
+    );
+    send(context, instruction).await.unwrap();
+}
+
+async fn unlock(context: &mut ProgramTestContext, vault: &Vault, root: [u8; 32]) -> Result<(), TransactionError> {
+    let instruction = Instruction::new_with_bytes(
+        vault.verifier_program_id,
+        &root,
+        vec![
+            AccountMeta::new(vault.vault, false),
+            AccountMeta::new_readonly(vault.verifier_state, false),
+            AccountMeta::new_readonly(vault.program_id, false),
+        ],
+    );
+    send(context, instruction).await
+}
+
+async fn post_root(context: &mut ProgramTestContext, vault: &Vault, epoch: u64, root: [u8; 32]) -> Result<(), TransactionError> {
+    let instruction = post_account_set_root(&vault.program_id, &vault.sequencer.pubkey(), epoch, root);
+    send_signed(context, instruction, &[&vault.sequencer]).await
+}
+
+async fn vault_state(context: &mut ProgramTestContext, vault: &Vault) -> VaultState {
+    let account = context.banks_client.get_account(vault.vault).await.unwrap().unwrap();
+    VaultState::try_from_slice(&account.data).unwrap()
+}
+
+fn withdrawal_instruction(context: &ProgramTestContext, vault: &Vault, withdrawal: &Withdrawal) -> Instruction {
+    let (receipt, _) = Pubkey::find_program_address(&[WITHDRAWAL_SEED, &withdrawal.leaf()], &vault.program_id);
+    Instruction::new_with_bytes(
+        vault.program_id,
+        &to_vec(&VaultInstruction::ProcessWithdrawal(withdrawal.clone())).unwrap(),
+        vec![
+            AccountMeta::new(vault.vault, false),
+            AccountMeta::new(receipt, false),
+            AccountMeta::new(withdrawal.recipient, false),
+            AccountMeta::new(context.payer.pubkey(), true),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        ],
+    )
+}
+
+fn batch_instruction(context: &ProgramTestContext, vault: &Vault, batch: &WithdrawalBatch) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(vault.vault, false),
+        AccountMeta::new(context.payer.pubkey(), true),
+        AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    for withdrawal in &batch.withdrawals {
+        let (receipt, _) = Pubkey::find_program_address(&[WITHDRAWAL_SEED, &withdrawal.leaf()], &vault.program_id);
+        accounts.push(AccountMeta::new(receipt, false));
+        accounts.push(AccountMeta::new(withdrawal.recipient, false));
+    }
+    Instruction::new_with_bytes(vault.program_id, &to_vec(&VaultInstruction::ProcessWithdrawalBatch(batch.clone())).unwrap(), accounts)
+}
+
+/// Builds a state tree of `withdrawals` plus unrelated leaves, returning its root and a batch of
+/// the withdrawals with their multiproof.
+fn batch_tree(withdrawals: &[Withdrawal]) -> ([u8; 32], WithdrawalBatch) {
+    let mut leaves: Vec<[u8; 32]> = (0..4u8).map(|i| [i; 32]).collect();
+    leaves.extend(withdrawals.iter().map(Withdrawal::leaf));
+    let tree = MerkleTree::<Sha256>::from_leaves(&leaves);
+    let indices: Vec<usize> = (4..leaves.len()).collect();
+    let batch = WithdrawalBatch {
+        withdrawals: withdrawals
+            .iter()
+            .zip(&indices)
+            .map(|(withdrawal, index)| BatchedWithdrawal {
+                recipient: withdrawal.recipient,
+                mint: withdrawal.mint,
+                amount: withdrawal.amount,
+                nonce: withdrawal.nonce,
+                leaf_index: *index as u64,
+            })
+            .collect(),
+        leaf_count: leaves.len() as u64,
+        proof: tree.proof(&indices).proof_hashes().to_vec(),
+    };
+    (tree.root().unwrap(), batch)
+}
+
+/// Builds a state tree of `withdrawals` plus unrelated leaves, returning its root and the
+/// withdrawals with their inclusion proofs.
+fn state_tree(mut withdrawals: Vec<Withdrawal>) -> ([u8; 32], Vec<Withdrawal>) {
+    let mut leaves: Vec<[u8; 32]> = (0..4u8).map(|i| [i; 32]).collect();
+    leaves.extend(withdrawals.iter().map(Withdrawal::leaf));
+    let tree = MerkleTree::<Sha256>::from_leaves(&leaves);
+    for (i, withdrawal) in withdrawals.iter_mut().enumerate() {
+        let leaf_index = 4 + i;
+        withdrawal.leaf_index = leaf_index as u64;
+        withdrawal.leaf_count = leaves.len() as u64;
+        withdrawal.proof = tree.proof(&[leaf_index]).proof_hashes().to_vec();
+    }
+    (tree.root().unwrap(), withdrawals)
+}
+
+/// Builds a state tree of `accounts` plus unrelated leaves, returning its root and an escape
+/// claim for each account to a new recipient.
+fn account_tree(accounts: &[RollupAccount]) -> ([u8; 32], Vec<EscapeClaim>) {
+    let mut leaves: Vec<[u8; 32]> = (0..3u8).map(|i| [i; 32]).collect();
+    leaves.extend(accounts.iter().map(RollupAccount::leaf));
+    let tree = MerkleTree::<Sha256>::from_leaves(&leaves);
+    let claims = accounts
+        .iter()
+        .enumerate()
+        .map(|(i, account)| EscapeClaim {
+            account: account.clone(),
+            recipient: Pubkey::new_unique(),
+            leaf_index: 3 + i as u64,
+            leaf_count: leaves.len() as u64,
+            proof: tree.proof(&[3 + i]).proof_hashes().to_vec(),
+        })
+        .collect();
+    (tree.root().unwrap(), claims)
+}
+
+fn rollup_account(owner: &Keypair, lamports: u64) -> RollupAccount {
+    RollupAccount {
+        address: owner.pubkey(),
+        lamports,
+        data: vec![],
+        owner: solana_program::system_program::id(),
+        executable: false,
+        rent_epoch: 0,
+        data_root: None,
+    }
+}
+
+fn escape_instruction(context: &ProgramTestContext, vault: &Vault, claim: &EscapeClaim) -> Instruction {
+    let (receipt, _) = Pubkey::find_program_address(&[ESCAPE_SEED, claim.account.address.as_ref()], &vault.program_id);
+    Instruction::new_with_bytes(
+        vault.program_id,
+        &to_vec(&VaultInstruction::EmergencyWithdrawal(claim.clone())).unwrap(),
+        vec![
+            AccountMeta::new(vault.vault, false),
+            AccountMeta::new(receipt, false),
+            AccountMeta::new_readonly(claim.account.address, true),
+            AccountMeta::new(claim.recipient, false),
+            AccountMeta::new(context.payer.pubkey(), true),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        ],
+    )
+}
+
+/// Moves the validator clock `seconds` past the last unlock.
+async fn advance_clock(context: &mut ProgramTestContext, seconds: i64) {
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += seconds;
+    context.set_sysvar(&clock);
+}
+
+fn lamport_withdrawal(amount: u64, nonce: u64) -> Withdrawal {
+    Withdrawal {
+        recipient: Pubkey::new_unique(),
+        mint: None,
+        amount,
+        nonce,
+        leaf_index: 0,
+        leaf_count: 0,
+        proof: vec![],
+    }
+}
+
+#[tokio::test]
+async fn initialize_creates_vault() {
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+
+    let state = vault_state(&mut context, &vault).await;
+    assert_eq!(state.verifier_program_id, vault.verifier_program_id);
+    assert_eq!(state.liabilities(), 0);
+}
+
+#[tokio::test]
+async fn deposit_increases_liabilities() {
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+    let before = context.banks_client.get_balance(vault.vault).await.unwrap();
+
+    deposit(&mut context, &vault, 5_000_000_000).await;
+    deposit(&mut context, &vault, 2_000_000_000).await;
+
+    assert_eq!(vault_state(&mut context, &vault).await.deposited_lamports, 7_000_000_000);
+    assert_eq!(context.banks_client.get_balance(vault.vault).await.unwrap(), before + 7_000_000_000);
+}
+
+#[tokio::test]
+async fn withdrawal_in_current_root_is_paid_once() {
+    let (root, withdrawals) = state_tree(vec![lamport_withdrawal(3_000_000_000, 0), lamport_withdrawal(1_000_000_000, 1)]);
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+    unlock(&mut context, &vault, root).await.unwrap();
+    deposit(&mut context, &vault, 5_000_000_000).await;
+
+    let withdrawal = &withdrawals[0];
+    let instruction = withdrawal_instruction(&context, &vault, withdrawal);
+    send(&mut context, instruction).await.unwrap();
+    assert_eq!(context.banks_client.get_balance(withdrawal.recipient).await.unwrap(), 3_000_000_000);
+    assert_eq!(vault_state(&mut context, &vault).await.liabilities(), 2_000_000_000);
+
+    let instruction = withdrawal_instruction(&context, &vault, withdrawal);
+    let replay = send(&mut context, instruction).await;
+    assert_eq!(replay, Err(TransactionError::InstructionError(0, InstructionError::AccountAlreadyInitialized)));
+}
+
+#[tokio::test]
+async fn withdrawal_waits_for_unlocked_root() {
+    let (root, withdrawals) = state_tree(vec![lamport_withdrawal(3_000_000_000, 0)]);
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+    deposit(&mut context, &vault, 5_000_000_000).await;
+
+    let instruction = withdrawal_instruction(&context, &vault, &withdrawals[0]);
+    let result = send(&mut context, instruction).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+
+    unlock(&mut context, &vault, root).await.unwrap();
+    let instruction = withdrawal_instruction(&context, &vault, &withdrawals[0]);
+    send(&mut context, instruction).await.unwrap();
+}
+
+#[tokio::test]
+async fn unlock_requires_verifier_signature() {
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+
+    let mut instruction = unlock_withdrawal_root(&vault.program_id, &vault.verifier_state, [7u8; 32]);
+    instruction.accounts[1].is_signer = false;
+    let result = send(&mut context, instruction).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)));
+}
+
+#[tokio::test]
+async fn withdrawal_rejects_invalid_proof() {
+    let (root, withdrawals) = state_tree(vec![lamport_withdrawal(3_000_000_000, 0)]);
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+    unlock(&mut context, &vault, root).await.unwrap();
+    deposit(&mut context, &vault, 5_000_000_000).await;
+
+    let mut withdrawal = withdrawals[0].clone();
+    withdrawal.amount = 4_000_000_000;
+    let instruction = withdrawal_instruction(&context, &vault, &withdrawal);
+    let result = send(&mut context, instruction).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+}
+
+#[tokio::test]
+async fn withdrawal_rejects_amount_above_liabilities() {
+    let (root, withdrawals) = state_tree(vec![lamport_withdrawal(3_000_000_000, 0)]);
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+    unlock(&mut context, &vault, root).await.unwrap();
+    deposit(&mut context, &vault, 1_000_000_000).await;
+
+    let instruction = withdrawal_instruction(&context, &vault, &withdrawals[0]);
+    let result = send(&mut context, instruction).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InsufficientFunds)));
+}
+
+#[tokio::test]
+async fn withdrawal_batch_is_paid_once() {
+    let withdrawals: Vec<Withdrawal> = (0..3).map(|nonce| lamport_withdrawal(1_000_000_000 + nonce, nonce)).collect();
+    let (root, batch) = batch_tree(&withdrawals);
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+    unlock(&mut context, &vault, root).await.unwrap();
+    deposit(&mut context, &vault, 5_000_000_000).await;
+
+    let instruction = batch_instruction(&context, &vault, &batch);
+    send(&mut context, instruction).await.unwrap();
+    for withdrawal in &withdrawals {
+        assert_eq!(context.banks_client.get_balance(withdrawal.recipient).await.unwrap(), withdrawal.amount);
+    }
+    assert_eq!(vault_state(&mut context, &vault).await.liabilities(), 5_000_000_000 - 3_000_000_003);
+
+    // A batch overlapping a paid withdrawal fails as a whole
+    let instruction = batch_instruction(&context, &vault, &batch);
+    let replay = send(&mut context, instruction).await;
+    assert_eq!(replay, Err(TransactionError::InstructionError(0, InstructionError::AccountAlreadyInitialized)));
+}
+
+#[tokio::test]
+async fn withdrawal_batch_rejects_invalid_multiproof() {
+    let withdrawals: Vec<Withdrawal> = (0..2).map(|nonce| lamport_withdrawal(1_000_000_000, nonce)).collect();
+    let (root, batch) = batch_tree(&withdrawals);
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+    unlock(&mut context, &vault, root).await.unwrap();
+    deposit(&mut context, &vault, 5_000_000_000).await;
+
+    let mut altered = batch.clone();
+    altered.withdrawals[1].amount = 2_000_000_000;
+    let instruction = batch_instruction(&context, &vault, &altered);
+    let result = send(&mut context, instruction).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+
+    let mut unsorted = batch.clone();
+    unsorted.withdrawals.reverse();
+    let instruction = batch_instruction(&context, &vault, &unsorted);
+    let result = send(&mut context, instruction).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+}
+
+#[test]
+fn multiproof_root_matches_rs_merkle() {
+    for leaf_count in 1..=13usize {
+        let leaves: Vec<[u8; 32]> = (0..leaf_count).map(|i| [i as u8; 32]).collect();
+        let tree = MerkleTree::<Sha256>::from_leaves(&leaves);
+        let index_sets = [vec![0], vec![leaf_count - 1], (0..leaf_count).collect(), (0..leaf_count).step_by(3).collect(), (leaf_count / 2..leaf_count).collect()];
+        for indices in index_sets {
+            let known: Vec<(u64, [u8; 32])> = indices.iter().map(|index| (*index as u64, leaves[*index])).collect();
+            let proof = tree.proof(&indices).proof_hashes().to_vec();
+            assert_eq!(merkle_multiproof_root(&known, leaf_count as u64, &proof), tree.root(), "{} leaves, indices {:?}", leaf_count, indices);
+        }
+    }
+}
+
+#[tokio::test]
+async fn audit_reports_liabilities() {
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+    deposit(&mut context, &vault, 5_000_000_000).await;
+
+    let instruction = Instruction::new_with_bytes(
+        vault.program_id,
+        &to_vec(&VaultInstruction::Audit).unwrap(),
+        vec![AccountMeta::new_readonly(vault.vault, false)],
+    );
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&context.payer.pubkey()), &[&context.payer], blockhash);
+    let simulation = context.banks_client.simulate_transaction(transaction).await.unwrap();
+    let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+
+    let report = AuditReport::try_from_slice(&return_data.data).unwrap();
+    assert_eq!(report.lamport_liabilities, 5_000_000_000);
+    assert_eq!(report.vault_lamports, 5_000_000_000);
+    assert!(report.tokens.is_empty());
+}
+
+#[test]
+fn account_leaf_matches_the_rollup_account_leaf() {
+    let account = rollup_account(&Keypair::new(), 1_000);
+    // Without a data root the leaf hashes the encoding the rollup stored before data roots
+    let encoded = to_vec(&(account.address, account.lamports, account.data.clone(), account.owner, account.executable, account.rent_epoch)).unwrap();
+    assert_eq!(account.leaf(), hash(&encoded).to_bytes());
+
+    let large = RollupAccount { data: vec![1; 64], data_root: Some([9; 32]), ..account.clone() };
+    assert_eq!(large.leaf(), RollupAccount { data: vec![], ..large.clone() }.leaf());
+    assert_ne!(large.leaf(), RollupAccount { data_root: Some([8; 32]), ..large.clone() }.leaf());
+}
+
+#[test]
+fn withdrawal_instruction_tags_are_stable() {
+    // The rollup's `withdrawals` block filter matches the vault's instructions on these tags
+    let withdrawal = Withdrawal { recipient: Pubkey::new_unique(), mint: None, amount: 1, nonce: 0, leaf_index: 0, leaf_count: 1, proof: vec![] };
+    let claim = EscapeClaim { account: rollup_account(&Keypair::new(), 1), recipient: Pubkey::new_unique(), leaf_index: 0, leaf_count: 1, proof: vec![] };
+    let tag = |instruction: VaultInstruction| to_vec(&instruction).unwrap()[0];
+    assert_eq!(tag(VaultInstruction::ProcessWithdrawal(withdrawal)), 3);
+    assert_eq!(tag(VaultInstruction::UnlockWithdrawalRoot { root: [0; 32] }), 5);
+    assert_eq!(tag(VaultInstruction::EmergencyWithdrawal(claim)), 6);
+    // The rollup decodes deposits to credit them and builds the posted account set roots
+    assert_eq!(tag(VaultInstruction::Deposit { amount: 1, rollup_recipient: Pubkey::new_unique(), mint: None }), 2);
+    assert_eq!(tag(VaultInstruction::PostAccountSetRoot { epoch: 1, root: [0; 32] }), 7);
+    let batch = WithdrawalBatch { withdrawals: vec![], leaf_count: 1, proof: vec![] };
+    assert_eq!(tag(VaultInstruction::ProcessWithdrawalBatch(batch)), 8);
+}
+
+#[tokio::test]
+async fn emergency_withdrawal_waits_for_escape_delay() {
+    let owner = Keypair::new();
+    let (root, claims) = account_tree(&[rollup_account(&owner, 2_000_000_000)]);
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+    unlock(&mut context, &vault, [1u8; 32]).await.unwrap();
+    post_root(&mut context, &vault, 1, root).await.unwrap();
+    deposit(&mut context, &vault, 5_000_000_000).await;
+
+    let claim = &claims[0];
+    let instruction = escape_instruction(&context, &vault, claim);
+    let early = send_signed(&mut context, instruction, &[&owner]).await;
+    assert_eq!(early, Err(TransactionError::InstructionError(0, InstructionError::InvalidArgument)));
+
+    advance_clock(&mut context, ESCAPE_DELAY_SECS as i64).await;
+    let instruction = escape_instruction(&context, &vault, claim);
+    send_signed(&mut context, instruction, &[&owner]).await.unwrap();
+    assert_eq!(context.banks_client.get_balance(claim.recipient).await.unwrap(), 2_000_000_000);
+    let state = vault_state(&mut context, &vault).await;
+    assert!(state.halted);
+    assert_eq!(state.liabilities(), 3_000_000_000);
+
+    let instruction = escape_instruction(&context, &vault, claim);
+    let replay = send_signed(&mut context, instruction, &[&owner]).await;
+    assert_eq!(replay, Err(TransactionError::InstructionError(0, InstructionError::AccountAlreadyInitialized)));
+}
+
+#[tokio::test]
+async fn halted_vault_accepts_no_new_root() {
+    let owner = Keypair::new();
+    let (root, claims) = account_tree(&[rollup_account(&owner, 1_000_000_000)]);
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+    unlock(&mut context, &vault, [1u8; 32]).await.unwrap();
+    post_root(&mut context, &vault, 1, root).await.unwrap();
+    deposit(&mut context, &vault, 5_000_000_000).await;
+    advance_clock(&mut context, ESCAPE_DELAY_SECS as i64).await;
+    let instruction = escape_instruction(&context, &vault, &claims[0]);
+    send_signed(&mut context, instruction, &[&owner]).await.unwrap();
+
+    let result = unlock(&mut context, &vault, [9u8; 32]).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidAccountData)));
+    let result = post_root(&mut context, &vault, 2, [9u8; 32]).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidAccountData)));
+}
+
+#[tokio::test]
+async fn emergency_withdrawal_requires_account_signature_and_proof() {
+    let owner = Keypair::new();
+    let (root, claims) = account_tree(&[rollup_account(&owner, 1_000_000_000)]);
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+    unlock(&mut context, &vault, [1u8; 32]).await.unwrap();
+    post_root(&mut context, &vault, 1, root).await.unwrap();
+    deposit(&mut context, &vault, 5_000_000_000).await;
+    advance_clock(&mut context, ESCAPE_DELAY_SECS as i64).await;
+
+    let mut unsigned = escape_instruction(&context, &vault, &claims[0]);
+    unsigned.accounts[2].is_signer = false;
+    let result = send(&mut context, unsigned).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)));
+
+    let mut inflated = claims[0].clone();
+    inflated.account.lamports = 4_000_000_000;
+    let instruction = escape_instruction(&context, &vault, &inflated);
+    let result = send_signed(&mut context, instruction, &[&owner]).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+}
+
+#[tokio::test]
+async fn account_set_root_is_posted_by_the_sequencer_only() {
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+
+    let impostor = Keypair::new();
+    let instruction = post_account_set_root(&vault.program_id, &impostor.pubkey(), 1, [5u8; 32]);
+    let result = send_signed(&mut context, instruction, &[&impostor]).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)));
+
+    post_root(&mut context, &vault, 2, [5u8; 32]).await.unwrap();
+    let state = vault_state(&mut context, &vault).await;
+    assert_eq!((state.account_set_epoch, state.account_set_root), (2, [5u8; 32]));
+
+    let result = post_root(&mut context, &vault, 2, [6u8; 32]).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidArgument)));
+    let result = post_root(&mut context, &vault, 1, [6u8; 32]).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidArgument)));
+}
+
+#[tokio::test]
+async fn emergency_withdrawal_proves_against_the_account_set_root() {
+    let owner = Keypair::new();
+    let (root, claims) = account_tree(&[rollup_account(&owner, 1_000_000_000)]);
+    let (mut context, vault) = start().await;
+    initialize(&mut context, &vault).await;
+    deposit(&mut context, &vault, 5_000_000_000).await;
+    // A block root holding the account isn't enough, the account set root is what pays out
+    unlock(&mut context, &vault, root).await.unwrap();
+    advance_clock(&mut context, ESCAPE_DELAY_SECS as i64).await;
+
+    let instruction = escape_instruction(&context, &vault, &claims[0]);
+    let result = send_signed(&mut context, instruction, &[&owner]).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidArgument)));
+
+    post_root(&mut context, &vault, 1, [7u8; 32]).await.unwrap();
+    let instruction = escape_instruction(&context, &vault, &claims[0]);
+    let result = send_signed(&mut context, instruction, &[&owner]).await;
+    assert_eq!(result, Err(TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)));
+
+    post_root(&mut context, &vault, 2, root).await.unwrap();
+    let instruction = escape_instruction(&context, &vault, &claims[0]);
+    send_signed(&mut context, instruction, &[&owner]).await.unwrap();
+    assert_eq!(context.banks_client.get_balance(claims[0].recipient).await.unwrap(), 1_000_000_000);
+}
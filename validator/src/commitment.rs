@@ -1,78 +1,156 @@
 use crate::error::ValidationError;
 use crate::error::ValidationError::CommitmentTransactionFailed;
+use crate::error::ValidationError::InsufficientFeePayerBalance;
+use crate::error::ValidationError::InvalidBatch;
+use crate::error::ValidationError::MalformedProofPackage;
+use crate::error::ValidationError::MissingWitness;
 use crate::error::ValidationError::ProofVerificationFailed;
-use ark_serialize::CanonicalSerializeHashExt;
+use crate::witness::{verify_witness, AccountRoot, PendingRoot, SignedHistory, SignedRoot};
 use borsh::{to_vec, BorshDeserialize, BorshSerialize};
 use lazy_static::lazy_static;
-use libsecp256k1::{Message, PublicKey, SecretKey};
-use log::info;
-use sha2::Sha256;
+use log::{error, info, warn};
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_program::keccak;
 use solana_sdk::{
     commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction,
     instruction::{AccountMeta, Instruction},
     pubkey::Pubkey,
-    signature::{Keypair, Signer},
+    signature::{Signature, Signer},
     transaction::Transaction,
 };
 use state::config::TrollupConfig;
-use state::state_record::{ZkProofCommitment};
+use state::state_record::{ChainedCommitment, Ed25519Commitment, ZkProofCommitment};
+use state::witness::WitnessBundle;
+use state_commitment::fee_payer;
+use state_management::sled_state_management::SledStateManagement;
+use state_management::state_management::StateManager;
 use std::str::FromStr;
-use serde_json::{json, Value};
+use trollup_commitment_message::{commitment_message_hash, BlockProducer};
 use trollup_zk::prove::{ProofPackage, ProofPackagePrepared};
 use trollup_zk::verify::verify_proof_package;
-use crate::models::ApiResponse;
+use crate::metrics;
+use crate::models::{ApiResponse, DryRun};
+use crate::signing::{commitment_signer, SignedCommitment};
+use solana_program::{ed25519_program, sysvar};
+use std::time::Instant;
 
 lazy_static! {
     static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
 }
 
+/// Most compute units a transaction can request.
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
 lazy_static! {
+    static ref SIGNED_ROOTS: StateManager<SledStateManagement<SignedRoot>> = StateManager::new(&CONFIG.validator_db_path);
+    static ref ACCOUNT_ROOTS: StateManager<SledStateManagement<AccountRoot>> = StateManager::new(&CONFIG.validator_account_roots_db_path);
 }
 
 #[derive(BorshSerialize, BorshDeserialize)]
 pub enum ProgramInstruction {
     Initialize,
     VerifySig(ZkProofCommitment),
+    VerifySigBatch(Vec<ChainedCommitment>),
+    VerifyEd25519(Ed25519Commitment),
 }
 
-fn create_and_sign_commitment(
-    new_state_root: [u8; 32],
-    verifier_secret_key: &[u8; 32],
-) -> Result<ZkProofCommitment, Box<dyn std::error::Error>> {
-    let message_hash = {
-        let mut hasher = keccak::Hasher::default();
-        hasher.hash(&new_state_root);
-        hasher.result()
+pub async fn verify_and_commit(proof_package_prepared: ProofPackagePrepared, witness: Option<WitnessBundle>, new_state_root: [u8; 32], sequence: u64, outbox_root: [u8; 32], producer: BlockProducer) -> Result<ApiResponse, ValidationError> {
+    let pending = verify_package(proof_package_prepared, witness.as_ref(), &new_state_root, &outbox_root, &[])?;
+
+    // Create and sign the commitment (this would normally be done by the trusted off-chain verifier)
+    let signer = commitment_signer(&CONFIG)?;
+
+    //TODO update to call specific instruction and call initialize
+    let signing_started = Instant::now();
+    let commitment = signer.sign(new_state_root, sequence, outbox_root, producer)?;
+    metrics::record_signing(signing_started.elapsed());
+
+    let instructions = match commitment {
+        SignedCommitment::Secp256k1(commitment) => vec![verifier_instruction(&ProgramInstruction::VerifySig(commitment), vec![])],
+        SignedCommitment::Ed25519(commitment, signature) => {
+            let message_hash = commitment_message_hash(CONFIG.commitment_message_hash, &new_state_root, sequence, &outbox_root, &producer);
+            vec![
+                ed25519_signature_instruction(&commitment.public_key, &signature, &message_hash),
+                verifier_instruction(&ProgramInstruction::VerifyEd25519(commitment), vec![AccountMeta::new_readonly(sysvar::instructions::id(), false)]),
+            ]
+        }
     };
+    if CONFIG.validator_dry_run {
+        return simulate(instructions, CONFIG.signature_verify_compute_unit_limit).await;
+    }
+    let signature = submit(instructions, CONFIG.signature_verify_compute_unit_limit).await?;
+    record_signed(&[SignedRoot { root: new_state_root, sequence }], pending.as_slice());
+    Ok(ApiResponse {
+        success: true,
+        signature,
+        dry_run: None,
+    })
+}
 
-    // If verification succeeds, create and sign the commitment
-    let message = Message::parse_slice(&message_hash.0)?;
+/// One root of a batch, see `verify_and_commit_chain`.
+pub struct ChainEntry {
+    pub proof_package: ProofPackagePrepared,
+    pub witness: Option<WitnessBundle>,
+    pub new_state_root: [u8; 32],
+    pub sequence: u64,
+    pub outbox_root: [u8; 32],
+    pub producer: BlockProducer,
+}
 
-    // Create secret key from input bytes
-    let secret_key = SecretKey::parse(verifier_secret_key)?;
-    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+/// Verifies consecutive roots, oldest first, and commits them in a single L1 transaction. The
+/// first root extends the one the signature verifier stores and every other root the one before
+/// it, which the program checks again. Nothing is committed unless every root verifies.
+pub async fn verify_and_commit_chain(entries: Vec<ChainEntry>) -> Result<ApiResponse, ValidationError> {
+    let max_roots = CONFIG.l1_batch_max_roots.max(1);
+    if entries.is_empty() || entries.len() as u64 > max_roots {
+        return Err(InvalidBatch(format!("expected 1 to {} roots, got {}", max_roots, entries.len())));
+    }
+    let (mut parent_state_root, mut sequence) = stored_commitment().await?;
+
+    let signer = commitment_signer(&CONFIG)?;
+    let mut commitments = Vec::with_capacity(entries.len());
+    let mut signed_roots: Vec<SignedRoot> = Vec::with_capacity(entries.len());
+    let mut pending_roots: Vec<PendingRoot> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        if entry.sequence <= sequence {
+            return Err(InvalidBatch(format!("sequence {} doesn't follow {}", entry.sequence, sequence)));
+        }
+        let pending = verify_package(entry.proof_package, entry.witness.as_ref(), &entry.new_state_root, &entry.outbox_root, &pending_roots)?;
 
-    // Sign the message
-    let (signature, recovery_id) = libsecp256k1::sign(&message, &secret_key);
+        let signing_started = Instant::now();
+        commitments.push(signer.sign_chained(parent_state_root, entry.new_state_root, entry.sequence, entry.outbox_root, entry.producer)?);
+        metrics::record_signing(signing_started.elapsed());
 
-    // Combine signature and recovery ID into 64 bytes
-    let mut signature_bytes = [0u8; 64];
-    signature_bytes[..64].copy_from_slice(&signature.serialize());
+        signed_roots.push(SignedRoot { root: entry.new_state_root, sequence: entry.sequence });
+        // Without a witness nothing is known about the accounts, a later block can't build on them
+        pending_roots.push(pending.unwrap_or(PendingRoot { root: entry.new_state_root, accounts: vec![] }));
+        parent_state_root = entry.new_state_root;
+        sequence = entry.sequence;
+    }
 
-    Ok(ZkProofCommitment {
-        verifier_signature: signature_bytes,
-        recovery_id: recovery_id.serialize(),
-        public_key,
-        new_state_root,
+    // Every commitment costs one signature verification
+    let compute_unit_limit = CONFIG.signature_verify_compute_unit_limit
+        .saturating_mul(commitments.len() as u32)
+        .min(MAX_COMPUTE_UNIT_LIMIT);
+    let instructions = vec![verifier_instruction(&ProgramInstruction::VerifySigBatch(commitments), vec![])];
+    if CONFIG.validator_dry_run {
+        return simulate(instructions, compute_unit_limit).await;
+    }
+    info!("Committing {} roots in one transaction, up to sequence {}", signed_roots.len(), sequence);
+    let signature = submit(instructions, compute_unit_limit).await?;
+    record_signed(&signed_roots, &pending_roots);
+    Ok(ApiResponse {
+        success: true,
+        signature,
+        dry_run: None,
     })
 }
 
-pub async fn verify_and_commit(proof_package_prepared: ProofPackagePrepared, new_state_root: [u8; 32]) -> Result<ApiResponse, ValidationError> {
-    let client = RpcClient::new_with_commitment(CONFIG.rpc_url_current_env().to_string(), CommitmentConfig::confirmed());
-
-    let proof_package: ProofPackage = proof_package_prepared.into();
+/// Checks the proof and the witness, when there is one, returning what the witness showed the
+/// block wrote. `batch` was verified earlier in the same batch and isn't signed yet, the witness
+/// may build on it.
+fn verify_package(proof_package_prepared: ProofPackagePrepared, witness: Option<&WitnessBundle>, new_state_root: &[u8; 32], outbox_root: &[u8; 32], batch: &[PendingRoot]) -> Result<Option<PendingRoot>, ValidationError> {
+    let proof_package = ProofPackage::try_from(proof_package_prepared).map_err(|_| MalformedProofPackage)?;
     let is_valid = verify_proof_package(&proof_package);
 
     info!("Proof is valid. Creating commitment.");
@@ -80,128 +158,211 @@ pub async fn verify_and_commit(proof_package_prepared: ProofPackagePrepared, new
     if !is_valid {
         return Err(ProofVerificationFailed);
     }
+    metrics::record_proof_verified();
+
+    let history = SignedHistory { roots: &SIGNED_ROOTS, account_roots: &ACCOUNT_ROOTS };
+    match witness {
+        Some(witness) => verify_witness(&CONFIG, witness, new_state_root, outbox_root, &history, batch).map(Some),
+        None if CONFIG.validator_require_witness => Err(MissingWitness),
+        None => Ok(None),
+    }
+}
 
-    // TODO thinking about using these for on chain data and/or logging...
-    let proof = proof_package.proof;
-    let hash: [u8; 32] = proof.hash::<Sha256>().into();
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+/// Records roots committed to L1, and the latest root of each account their blocks wrote, so
+/// later witnesses can build on them.
+fn record_signed(signed_roots: &[SignedRoot], pending: &[PendingRoot]) {
+    let account_roots: Vec<AccountRoot> = pending.iter().flat_map(PendingRoot::account_roots).collect();
+    ACCOUNT_ROOTS.set_state_records(&account_roots);
+    ACCOUNT_ROOTS.commit();
+    SIGNED_ROOTS.set_state_records(&signed_roots.to_vec());
+    SIGNED_ROOTS.commit();
+}
 
-    // Load your Solana wallet keypair
-    let payer = Keypair::from_bytes(&CONFIG.trollup_api_keypair).expect("Error loading API keypair");
+/// Root and sequence the signature verifier stores, the root a batch has to extend.
+async fn stored_commitment() -> Result<([u8; 32], u64), ValidationError> {
+    let client = RpcClient::new_with_commitment(CONFIG.rpc_url_current_env().to_string(), CommitmentConfig::confirmed());
+    let program_id = Pubkey::from_str(CONFIG.signature_verifier_program_id_current_env()).expect("");
+    let (pda, _) = Pubkey::find_program_address(&[b"state"], &program_id);
+    let data = client.get_account_data(&pda).await.map_err(|error| {
+        error!("Failed to read the signature verifier state: {}", error);
+        CommitmentTransactionFailed
+    })?;
+    if data.len() < 40 {
+        error!("Signature verifier state is {} bytes, expected 40", data.len());
+        return Err(CommitmentTransactionFailed);
+    }
+    let mut root = [0u8; 32];
+    root.copy_from_slice(&data[..32]);
+    let mut sequence = [0u8; 8];
+    sequence.copy_from_slice(&data[32..40]);
+    Ok((root, u64::from_le_bytes(sequence)))
+}
 
+/// Instruction to the signature verifier, with the state account followed by `accounts`.
+fn verifier_instruction(instruction: &ProgramInstruction, accounts: Vec<AccountMeta>) -> Instruction {
     // Your program ID (replace with your actual program ID)
-    let program_id = Pubkey::from_str(&CONFIG.signature_verifier_program_id).expect("");
+    let program_id = Pubkey::from_str(CONFIG.signature_verifier_program_id_current_env()).expect("");
 
-    // Create and sign the commitment (this would normally be done by the trusted off-chain verifier)
-    // TODO create and load this from somewhere else
-    let secret = SecretKey::default().serialize();
+    // Create the instruction to call our program
+    let instruction_data = to_vec(instruction).unwrap();
+    let (pda, _) = Pubkey::find_program_address(&[b"state"], &program_id);
+    let mut account_metas = vec![
+        AccountMeta::new(pda, false),  // PDA account (writable, not signer)
+    ];
+    account_metas.extend(accounts);
+    Instruction::new_with_bytes(program_id, instruction_data.as_slice(), account_metas)
+}
 
-    //TODO update to call specific instruction and call initialize
-    let commitment = create_and_sign_commitment(
-        new_state_root,
-        &secret).unwrap();
-
-    // Serialize the commitment
-    let instruction_data = to_vec(&commitment).unwrap();
-
-    // Calculate the exact size needed for the account
-    let account_size = instruction_data.len();
-
-    // Create the program account that will store the state
-    let state_account = Keypair::new();
-    let create_account_ix = solana_sdk::system_instruction::create_account(
-        &payer.pubkey(),
-        &state_account.pubkey(),
-        client.get_minimum_balance_for_rent_exemption(account_size).await.unwrap(), // Size of the state (32 bytes)
-        account_size as u64, // Size of the account data
-        &program_id,
-    );
+/// The ed25519 program instruction verifying `signature` for `VerifyEd25519`, with all data in
+/// the instruction itself. Must match what the signature verifier program reads back.
+fn ed25519_signature_instruction(public_key: &[u8; 32], signature: &[u8; 64], message: &[u8]) -> Instruction {
+    // Signature count and padding, then one set of offsets
+    const DATA_START: usize = 2 + 14;
+    const CURRENT_INSTRUCTION: u16 = u16::MAX;
+    let public_key_offset = DATA_START;
+    let signature_offset = public_key_offset + public_key.len();
+    let message_offset = signature_offset + signature.len();
+    let mut data = vec![1u8, 0];
+    for value in [
+        signature_offset as u16,
+        CURRENT_INSTRUCTION,
+        public_key_offset as u16,
+        CURRENT_INSTRUCTION,
+        message_offset as u16,
+        message.len() as u16,
+        CURRENT_INSTRUCTION,
+    ] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(public_key);
+    data.extend_from_slice(signature);
+    data.extend_from_slice(message);
+    Instruction::new_with_bytes(ed25519_program::ID, &data, vec![])
+}
 
-    // Create the instruction to call our program
-    let instruction_data = to_vec(&ProgramInstruction::VerifySig(commitment)).unwrap();
-    let (pda, bump_seed) = Pubkey::find_program_address(&[b"state"], &program_id);
-    let instruction = Instruction::new_with_bytes(
-        program_id,
-        instruction_data.as_slice(),
-        vec![
-            AccountMeta::new(pda, false),  // PDA account (writable, not signer)
-        ],
-    );
-
-    // Create and send the transaction
+/// The transaction sending `instructions` to the signature verifier, paid by the commitment fee
+/// payer, unless the fee payer can't cover it.
+async fn commitment_transaction(client: &RpcClient, instructions: Vec<Instruction>, compute_unit_limit: u32) -> Result<Transaction, ValidationError> {
+    let payer = CONFIG.commitment_fee_payer().map_err(|error| {
+        error!("Commitment fee payer unavailable: {}", error);
+        CommitmentTransactionFailed
+    })?;
+
+    // Create the transaction, unless the fee payer can't cover it
     let recent_blockhash = client.get_latest_blockhash().await.unwrap();
-    let transaction = Transaction::new_signed_with_payer(
-        &[instruction],
-        Some(&payer.pubkey()),
-        &[&payer],
-        recent_blockhash,
-    );
+    let mut transaction_instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit)];
+    transaction_instructions.extend(instructions);
+    let message = solana_sdk::message::Message::new_with_blockhash(&transaction_instructions, Some(&payer.pubkey()), &recent_blockhash);
+    match fee_payer::ensure_can_pay(client, &message, 0, CONFIG.fee_payer_low_balance_lamports).await {
+        Ok(balance) if balance.is_low() => warn!("Low commitment fee payer balance: {}", balance),
+        Ok(_) => {}
+        Err(error) => {
+            error!("{}", error);
+            metrics::record_l1_submission(false);
+            return Err(InsufficientFeePayerBalance);
+        }
+    }
+    Ok(Transaction::new(&[&payer], message, recent_blockhash))
+}
+
+/// Sends `instructions` to the signature verifier, see `commitment_transaction`.
+async fn submit(instructions: Vec<Instruction>, compute_unit_limit: u32) -> Result<Signature, ValidationError> {
+    let client = RpcClient::new_with_commitment(CONFIG.rpc_url_current_env().to_string(), CommitmentConfig::confirmed());
+    let transaction = commitment_transaction(&client, instructions, compute_unit_limit).await?;
 
     // Send and confirm transaction
     match client.send_and_confirm_transaction(&transaction).await {
         Ok(signature) => {
             info!("Transaction succeeded: {:?}", &signature);
-            let response = ApiResponse {
-                success: true,
-                signature,
-            };
-            Ok(response)
+            metrics::record_l1_submission(true);
+            Ok(signature)
         }
         Err(err) => {
             info!("Error sending transaction: {}", err);
+            metrics::record_l1_submission(false);
             Err(CommitmentTransactionFailed)
         }
     }
 }
 
+/// Simulates the commitment transaction instead of sending it, with `VALIDATOR_DRY_RUN`. Nothing
+/// is committed, so the response is never a success and the root isn't recorded as signed; the
+/// signature is the one the transaction would have had.
+async fn simulate(instructions: Vec<Instruction>, compute_unit_limit: u32) -> Result<ApiResponse, ValidationError> {
+    let client = RpcClient::new_with_commitment(CONFIG.rpc_url_current_env().to_string(), CommitmentConfig::confirmed());
+    let transaction = commitment_transaction(&client, instructions, compute_unit_limit).await?;
+    let result = client.simulate_transaction(&transaction).await.map_err(|error| {
+        error!("Simulating the commitment transaction failed: {}", error);
+        CommitmentTransactionFailed
+    })?.value;
+    let dry_run = DryRun {
+        would_succeed: result.err.is_none(),
+        err: result.err.map(|error| error.to_string()),
+        logs: result.logs.unwrap_or_default(),
+        units_consumed: result.units_consumed,
+    };
+    info!("Dry run of the commitment transaction: {:?}", dry_run);
+    Ok(ApiResponse {
+        success: false,
+        signature: transaction.signatures[0],
+        dry_run: Some(dry_run),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use libsecp256k1::{PublicKey, SecretKey};
-    use rand::thread_rng;
+    use trollup_commitment_message::instruction_vectors::{instruction_vectors, CommitmentFixture, Instruction};
+
+    fn chained_commitment(fixture: &CommitmentFixture) -> ChainedCommitment {
+        ChainedCommitment {
+            verifier_signature: fixture.verifier_signature(),
+            recovery_id: fixture.recovery_id(),
+            public_key: fixture.public_key(),
+            parent_state_root: fixture.parent_state_root(),
+            new_state_root: fixture.new_state_root(),
+            sequence: fixture.sequence,
+            outbox_root: fixture.outbox_root(),
+            producer: fixture.producer(),
+        }
+    }
 
     #[test]
-    fn test_create_and_sign_commitment() {
-        let rng = &mut thread_rng();
-
-        // Create test inputs
-        let proof_hash = [1u8; 32];
-        let new_state_root = [2u8; 32];
-        let timestamp = 1632825600; // Example timestamp
-
-        // Generate a test secret key
-        let secret_key = SecretKey::default();
-        let secret_key_bytes = secret_key.serialize();
-        // let secret_key = SecretKey::random(&mut rng);
-        // let secret_key_bytes = secret_key.serialize();
-
-        // Call the function
-        let result = create_and_sign_commitment(
-            new_state_root,
-            &secret_key_bytes,
-        );
-
-        // Assert that the result is Ok
-        assert!(result.is_ok());
-
-        // Unwrap the result
-        let commitment = result.unwrap();
-
-        // Verify the fields of the commitment
-        // assert_eq!(commitment.proof_hash, proof_hash);
-        assert_eq!(commitment.new_state_root, new_state_root);
-        // assert_eq!(commitment.timestamp, timestamp);
-
-        // Verify the public key
-        let expected_public_key = PublicKey::from_secret_key(&secret_key).serialize();
-        assert_eq!(commitment.public_key, expected_public_key);
-
-        // Verify the signature
-        let message = Message::parse_slice(&new_state_root).unwrap();
-        let signature = libsecp256k1::Signature::parse_standard_slice(&commitment.verifier_signature[..64]).unwrap();
-        assert!(libsecp256k1::verify(&message, &signature, &PublicKey::parse(&commitment.public_key).unwrap()));
+    fn instructions_match_the_instruction_vectors() {
+        let mut checked = 0;
+        for vector in instruction_vectors() {
+            let instruction = match vector.instruction {
+                Instruction::Initialize => ProgramInstruction::Initialize,
+                Instruction::VerifySig => {
+                    let fixture = vector.commitment();
+                    ProgramInstruction::VerifySig(ZkProofCommitment {
+                        verifier_signature: fixture.verifier_signature(),
+                        recovery_id: fixture.recovery_id(),
+                        public_key: fixture.public_key(),
+                        new_state_root: fixture.new_state_root(),
+                        sequence: fixture.sequence,
+                        outbox_root: fixture.outbox_root(),
+                        producer: fixture.producer(),
+                    })
+                }
+                Instruction::VerifySigBatch => ProgramInstruction::VerifySigBatch(vector.commitments.iter().map(chained_commitment).collect()),
+                Instruction::VerifyEd25519 => {
+                    let fixture = vector.commitment();
+                    ProgramInstruction::VerifyEd25519(Ed25519Commitment {
+                        public_key: fixture.public_key(),
+                        new_state_root: fixture.new_state_root(),
+                        sequence: fixture.sequence,
+                        outbox_root: fixture.outbox_root(),
+                        producer: fixture.producer(),
+                    })
+                }
+                // The proof verifier's and the signature verifier's admin instructions, not
+                // submitted by the validator
+                Instruction::VerifyProof | Instruction::SetVerifyingKey | Instruction::SetValidator | Instruction::Migrate => continue,
+            };
+            assert_eq!(to_vec(&instruction).unwrap(), vector.data(), "{}", vector.name);
+            checked += 1;
+        }
+        assert!(checked > 0);
     }
-}
\ No newline at end of file
+}
@@ -4,6 +4,40 @@ use thiserror::Error;
 pub enum ValidationError {
     #[error("Commitment transaction failed.")]
     CommitmentTransactionFailed,
+    #[error("The commitment fee payer can't cover the commitment transaction.")]
+    InsufficientFeePayerBalance,
     #[error("Proof verification failed. Public inputs are not valid for the given proof.")]
-    ProofVerificationFailed
-}
\ No newline at end of file
+    ProofVerificationFailed,
+    #[error("Proof package could not be deserialized.")]
+    MalformedProofPackage,
+    #[error("A witness bundle is required to sign a state root.")]
+    MissingWitness,
+    #[error("Signing the commitment failed: {0}")]
+    SigningFailed(String),
+    #[error("Batch is invalid: {0}")]
+    InvalidBatch(String),
+    #[error("Witness bundle is invalid: {0}")]
+    InvalidWitness(String),
+    #[error("Re-executing the witness bundle produced a different state root.")]
+    StateRootMismatch,
+    #[error("Re-executing the witness bundle produced a different outbox root.")]
+    OutboxRootMismatch,
+}
+
+impl ValidationError {
+    /// Short name of the error, used as the rejection reason in the validator metrics.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ValidationError::CommitmentTransactionFailed => "commitment_transaction_failed",
+            ValidationError::InsufficientFeePayerBalance => "insufficient_fee_payer_balance",
+            ValidationError::ProofVerificationFailed => "proof_verification_failed",
+            ValidationError::MalformedProofPackage => "malformed_proof_package",
+            ValidationError::MissingWitness => "missing_witness",
+            ValidationError::SigningFailed(_) => "signing_failed",
+            ValidationError::InvalidBatch(_) => "invalid_batch",
+            ValidationError::InvalidWitness(_) => "invalid_witness",
+            ValidationError::StateRootMismatch => "state_root_mismatch",
+            ValidationError::OutboxRootMismatch => "outbox_root_mismatch",
+        }
+    }
+}
@@ -1,34 +1,72 @@
-use crate::commitment::verify_and_commit;
+use crate::commitment::{verify_and_commit, verify_and_commit_chain, ChainEntry};
+use crate::metrics;
 use base64::{engine::general_purpose, Engine as _};
+use borsh::BorshDeserialize;
 use log::info;
 use serde_derive::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signature;
-use trollup_zk::prove::ProofPackagePrepared;
+use std::str::FromStr;
+use trollup_commitment_message::BlockProducer;
+use state::witness::WitnessBundle;
+use state_commitment::fee_payer;
 use warp::reply::json;
 use warp::{http::StatusCode, Rejection, Reply};
-use crate::models::ApiResponse;
+use crate::models::{ApiResponse, ProveBatchEntry, ProveBatchRequest, ProveRequest};
 
 type Result<T> = std::result::Result<T, Rejection>;
 
 #[utoipa::path(
     post,
-    path = "/prove/{new_state_root}",
-    request_body = ProofPackagePrepared,
+    path = "/prove/{new_state_root}/{sequence}",
+    request_body = ProveRequest,
     params(
-        ("new_state_root" = i64, Path, description = "The new state root for the transaction batch")
+        ("new_state_root" = i64, Path, description = "The new state root for the transaction batch"),
+        ("sequence" = u64, Path, description = "Block number of the batch, must increase with every commitment")
     ),
     tag = "",
     responses(
         (status = 200, description = "Result of proof verification")
     ),
 )]
-pub async fn prove(proof_package_prepared: ProofPackagePrepared, new_state_root: String) -> Result<impl Reply> {
+pub async fn prove(prove_request: ProveRequest, new_state_root: String, sequence: u64) -> Result<impl Reply> {
     //todo validate input
+    let witness = match prove_request.witness.as_deref().map(decode_witness).transpose() {
+        Ok(witness) => witness,
+        Err(error) => {
+            info!("result invalid witness bundle {}", error);
+            metrics::record_rejection("malformed_witness");
+            return Ok(json(&ApiResponse{ success: false, signature: Default::default(), dry_run: None }));
+        }
+    };
+    let outbox_root = match prove_request.outbox_root.as_deref().map(decode_root).transpose() {
+        Ok(outbox_root) => outbox_root.unwrap_or_default(),
+        Err(error) => {
+            info!("result invalid outbox root {}", error);
+            metrics::record_rejection("malformed_outbox_root");
+            return Ok(json(&ApiResponse{ success: false, signature: Default::default(), dry_run: None }));
+        }
+    };
+    let producer = match decode_producer(&prove_request) {
+        Ok(producer) => producer,
+        Err(error) => {
+            info!("result invalid producer {}", error);
+            metrics::record_rejection("malformed_producer");
+            return Ok(json(&ApiResponse{ success: false, signature: Default::default(), dry_run: None }));
+        }
+    };
     let state_root_result = general_purpose::URL_SAFE.decode(new_state_root);
     match state_root_result {
         Ok(state_root) => {
-            let new_state_root_bytes: &[u8; 32] = <&[u8; 32]>::try_from(state_root.as_slice()).unwrap();
-            let result = verify_and_commit(proof_package_prepared, new_state_root_bytes.clone()).await;
+            let new_state_root_bytes: [u8; 32] = match state_root.try_into() {
+                Ok(bytes) => bytes,
+                Err(state_root) => {
+                    info!("result invalid state root length {}", state_root.len());
+                    metrics::record_rejection("malformed_state_root");
+                    return Ok(json(&ApiResponse{ success: false, signature: Default::default(), dry_run: None }));
+                }
+            };
+            let result = verify_and_commit(prove_request.proof_package, witness, new_state_root_bytes, sequence, outbox_root, producer).await;
             match result {
                 // TODO finalize results response
                 Ok(response) => {
@@ -37,19 +75,96 @@ pub async fn prove(proof_package_prepared: ProofPackagePrepared, new_state_root:
                 }
                 Err(error) => {
                     info!("result {:?}", &error);
-                    Ok(json(&ApiResponse{ success: false, signature: Default::default() }))
+                    metrics::record_rejection(error.reason());
+                    Ok(json(&ApiResponse{ success: false, signature: Default::default(), dry_run: None }))
                 }
             }
         }
 
         Err(error) => {
             info!("result {:?}", &error);
-            Ok(json(&ApiResponse{ success: false, signature: Default::default() }))
+            metrics::record_rejection("malformed_state_root");
+            Ok(json(&ApiResponse{ success: false, signature: Default::default(), dry_run: None }))
         }
     }
 
 }
 
+#[utoipa::path(
+    post,
+    path = "/prove-batch",
+    request_body = ProveBatchRequest,
+    tag = "",
+    responses(
+        (status = 200, description = "Result of verifying and committing every block of the batch")
+    ),
+)]
+pub async fn prove_batch(prove_batch_request: ProveBatchRequest) -> Result<impl Reply> {
+    let entries = match prove_batch_request.entries.into_iter().map(decode_entry).collect::<anyhow::Result<Vec<_>>>() {
+        Ok(entries) => entries,
+        Err(error) => {
+            info!("result invalid batch entry {}", error);
+            metrics::record_rejection("malformed_batch");
+            return Ok(json(&ApiResponse{ success: false, signature: Default::default(), dry_run: None }));
+        }
+    };
+    match verify_and_commit_chain(entries).await {
+        Ok(response) => {
+            info!("result {:?}", &response);
+            Ok(json(&response))
+        }
+        Err(error) => {
+            info!("result {:?}", &error);
+            metrics::record_rejection(error.reason());
+            Ok(json(&ApiResponse{ success: false, signature: Default::default(), dry_run: None }))
+        }
+    }
+}
+
+pub async fn metrics_handler() -> Result<impl Reply> {
+    Ok(json(&metrics::snapshot()))
+}
+
+fn decode_root(root: &str) -> anyhow::Result<[u8; 32]> {
+    general_purpose::STANDARD
+        .decode(root)?
+        .try_into()
+        .map_err(|root: Vec<u8>| anyhow::anyhow!("expected 32 bytes, got {}", root.len()))
+}
+
+fn decode_entry(entry: ProveBatchEntry) -> anyhow::Result<ChainEntry> {
+    let new_state_root = general_purpose::URL_SAFE
+        .decode(&entry.new_state_root)?
+        .try_into()
+        .map_err(|root: Vec<u8>| anyhow::anyhow!("expected a 32 byte state root, got {}", root.len()))?;
+    Ok(ChainEntry {
+        producer: decode_producer(&entry.request)?,
+        witness: entry.request.witness.as_deref().map(decode_witness).transpose()?,
+        outbox_root: entry.request.outbox_root.as_deref().map(decode_root).transpose()?.unwrap_or_default(),
+        proof_package: entry.request.proof_package,
+        new_state_root,
+        sequence: entry.sequence,
+    })
+}
+
+/// The sequencer and node version of a request. Both are signed, so neither may be missing.
+fn decode_producer(request: &ProveRequest) -> anyhow::Result<BlockProducer> {
+    let sequencer = request.sequencer.as_deref().ok_or_else(|| anyhow::anyhow!("missing sequencer"))?;
+    let node_version = request.node_version.as_deref().ok_or_else(|| anyhow::anyhow!("missing node version"))?;
+    Ok(BlockProducer {
+        sequencer: Pubkey::from_str(sequencer)?.to_bytes(),
+        version: node_version.parse().map_err(|error: String| anyhow::anyhow!(error))?,
+    })
+}
+
+fn decode_witness(witness: &str) -> anyhow::Result<WitnessBundle> {
+    Ok(WitnessBundle::try_from_slice(&general_purpose::STANDARD.decode(witness)?)?)
+}
+
+/// Unavailable while the commitment fee payer can't pay for commitments.
 pub async fn health_handler() -> Result<impl Reply> {
-    Ok(StatusCode::OK)
+    match fee_payer::last_balance() {
+        Some(balance) if balance.is_refusing() => Ok(warp::reply::with_status(json(&balance), StatusCode::SERVICE_UNAVAILABLE)),
+        _ => Ok(warp::reply::with_status(json(&"ok"), StatusCode::OK)),
+    }
 }
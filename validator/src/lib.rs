@@ -1,4 +1,7 @@
 pub mod handler;
 pub mod commitment;
 pub mod error;
-pub mod models;
\ No newline at end of file
+pub mod models;
+pub mod witness;
+pub mod metrics;
+pub mod signing;
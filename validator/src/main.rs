@@ -1,34 +1,35 @@
-use std::convert::Infallible;
-use std::env;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
-use std::sync::Arc;
-
-use anyhow::Result as AnyResult;
-use log::{info, trace};
+use lazy_static::lazy_static;
+use log::{error, info};
+use server::access_log::{self, AccessLog, AccessLogSettings};
+use server::auth::AdminToken;
+use server::body;
+use server::client_ip::TrustedProxies;
+use server::error::handle_rejection;
+use server::http::serve;
+use server::logging::{self, LogSettings};
+use server::swagger;
 use state::config::TrollupConfig;
 use trollup_validator::handler;
-use utoipa::{Modify, OpenApi};
-use utoipa_swagger_ui::Config as SwaggerConfig;
-use warp::body::json;
-use warp::{
-    http::Uri,
-    hyper::{Response, StatusCode},
-    path::{FullPath, Tail},
-    Filter, Rejection, Reply,
-};
+use utoipa::OpenApi;
+use warp::Filter;
 
-type Result<T> = std::result::Result<T, Rejection>;
+lazy_static! {
+    static ref CONFIG: TrollupConfig = TrollupConfig::build().unwrap();
+}
 
 #[tokio::main]
 async fn main() {
     let _ = TrollupConfig::load();
 
-    env_logger::init();
+    let _log_guard = logging::init(&LogSettings {
+        level: CONFIG.log_level.clone(),
+        file_path: CONFIG.log_file_path.clone(),
+        rotation: CONFIG.log_rotation.clone(),
+        max_file_size: CONFIG.log_max_file_size,
+        max_files: CONFIG.log_max_files,
+        format: CONFIG.log_format.clone(),
+    });
     
-    let api_doc_config = Arc::new(SwaggerConfig::from("/api-doc.json"));
-
     #[derive(OpenApi)]
     #[openapi(
         info(
@@ -36,75 +37,54 @@ async fn main() {
             description = "The Trollup API provides functionality to get and validate proofs",
             version = "0.0.1"
         ),
-        paths(handler::prove),
+        paths(handler::prove, handler::prove_batch),
         tags(
         (name = "handler", description = "Trollup-Validator API endpoints")
         )
     )]
     struct ApiDoc;
 
-    let api_doc = warp::path("api-doc.json")
-        .and(warp::get())
-        .map(|| warp::reply::json(&ApiDoc::openapi()));
+    let health_route = warp::path!("health").and_then(handler::health_handler);
 
-    let swagger_ui = warp::path("swagger-ui")
+    let metrics_route = warp::path!("metrics")
         .and(warp::get())
-        .and(warp::path::full())
-        .and(warp::path::tail())
-        .and(warp::any().map(move || api_doc_config.clone()))
-        .and_then(serve_swagger);
-
-    let health_route = warp::path!("health").and_then(handler::health_handler);
+        .and_then(handler::metrics_handler);
 
     let prove_route = warp::path("prove")
         .and(warp::post())
-        .and(json())
+        .and(body::json())
+        .and(warp::path::param())
         .and(warp::path::param())
         .and_then(handler::prove);
 
+    let prove_batch_route = warp::path!("prove-batch")
+        .and(warp::post())
+        .and(body::json())
+        .and_then(handler::prove_batch);
+
+    let access_log = AccessLog::new("trollup-validator", AccessLogSettings {
+        enabled: CONFIG.access_log_enabled,
+        sample_percent: CONFIG.access_log_sample_percent,
+    });
+
     let routes = health_route
         .or(prove_route)
-        .or(swagger_ui)
-        .or(api_doc)
+        .or(prove_batch_route)
+        .or(metrics_route)
+        .or(swagger::routes(ApiDoc::openapi()))
+        .or(access_log::admin_route(access_log.clone(), AdminToken::new(CONFIG.admin_token.clone())))
+        .recover(handle_rejection)
         .with(warp::cors().allow_any_origin());
 
+    let proxies = match TrustedProxies::new(&CONFIG.trusted_proxies, CONFIG.proxy_protocol) {
+        Ok(proxies) => proxies,
+        Err(message) => {
+            error!("Inconsistent configuration: {}", message);
+            std::process::exit(1);
+        }
+    };
+
     info!("Starting web server");
     //TODO add port to config
-    warp::serve(routes).run(([0, 0, 0, 0], 27183)).await;
+    serve(routes, ([0, 0, 0, 0], 27183), access_log, proxies).await;
 }
-
-fn with_value(value: String) -> impl Filter<Extract=(String,), Error=Infallible> + Clone {
-    warp::any().map(move || value.clone())
-}
-
-async fn serve_swagger(
-    full_path: FullPath,
-    tail: Tail,
-    config: Arc<SwaggerConfig<'static>>,
-) -> AnyResult<Box<dyn Reply + 'static>, Rejection> {
-    if full_path.as_str() == "/swagger-ui" {
-        return Ok(Box::new(warp::redirect::found(Uri::from_static(
-            "/swagger-ui/",
-        ))));
-    }
-
-    let path = tail.as_str();
-    match utoipa_swagger_ui::serve(path, config) {
-        Ok(file) => {
-            if let Some(file) = file {
-                Ok(Box::new(
-                    Response::builder()
-                        .header("Content-Type", file.content_type)
-                        .body(file.bytes),
-                ))
-            } else {
-                Ok(Box::new(StatusCode::NOT_FOUND))
-            }
-        }
-        Err(error) => Ok(Box::new(
-            Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(error.to_string()),
-        )),
-    }
-}
\ No newline at end of file
@@ -0,0 +1,117 @@
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref VERIFIED_AT: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+    static ref REJECTIONS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+}
+
+static PROOFS_VERIFIED: AtomicU64 = AtomicU64::new(0);
+static SIGNATURES: AtomicU64 = AtomicU64::new(0);
+static SIGNING_MICROS_TOTAL: AtomicU64 = AtomicU64::new(0);
+static SIGNING_MICROS_MAX: AtomicU64 = AtomicU64::new(0);
+static L1_SUBMISSIONS_SUCCEEDED: AtomicU64 = AtomicU64::new(0);
+static L1_SUBMISSIONS_FAILED: AtomicU64 = AtomicU64::new(0);
+
+/// Body of `/metrics`, counters since the validator started.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidatorMetrics {
+    pub proofs_verified: u64,
+    /// Proofs verified in the last minute
+    pub proofs_verified_per_minute: u64,
+    /// Refused requests per reason
+    pub rejections: BTreeMap<String, u64>,
+    pub signatures: u64,
+    pub signing_latency_avg_micros: u64,
+    pub signing_latency_max_micros: u64,
+    pub l1_submissions_succeeded: u64,
+    pub l1_submissions_failed: u64,
+    /// Share of L1 commitment transactions that confirmed, `None` before the first submission
+    pub l1_submission_success_rate: Option<f64>,
+}
+
+/// Called for every proof that passed verification.
+pub fn record_proof_verified() {
+    PROOFS_VERIFIED.fetch_add(1, Ordering::Relaxed);
+    let mut verified_at = VERIFIED_AT.lock().unwrap();
+    let now = Instant::now();
+    verified_at.push_back(now);
+    prune(&mut verified_at, now);
+}
+
+pub fn record_rejection(reason: &str) {
+    *REJECTIONS.lock().unwrap().entry(reason.to_string()).or_default() += 1;
+}
+
+/// Called with the time it took to create and sign a commitment.
+pub fn record_signing(latency: Duration) {
+    let micros = latency.as_micros() as u64;
+    SIGNATURES.fetch_add(1, Ordering::Relaxed);
+    SIGNING_MICROS_TOTAL.fetch_add(micros, Ordering::Relaxed);
+    SIGNING_MICROS_MAX.fetch_max(micros, Ordering::Relaxed);
+}
+
+pub fn record_l1_submission(succeeded: bool) {
+    if succeeded {
+        L1_SUBMISSIONS_SUCCEEDED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        L1_SUBMISSIONS_FAILED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn snapshot() -> ValidatorMetrics {
+    let proofs_verified_per_minute = {
+        let mut verified_at = VERIFIED_AT.lock().unwrap();
+        prune(&mut verified_at, Instant::now());
+        verified_at.len() as u64
+    };
+    let signatures = SIGNATURES.load(Ordering::Relaxed);
+    let succeeded = L1_SUBMISSIONS_SUCCEEDED.load(Ordering::Relaxed);
+    let failed = L1_SUBMISSIONS_FAILED.load(Ordering::Relaxed);
+    ValidatorMetrics {
+        proofs_verified: PROOFS_VERIFIED.load(Ordering::Relaxed),
+        proofs_verified_per_minute,
+        rejections: REJECTIONS.lock().unwrap().clone(),
+        signatures,
+        signing_latency_avg_micros: SIGNING_MICROS_TOTAL.load(Ordering::Relaxed).checked_div(signatures).unwrap_or_default(),
+        signing_latency_max_micros: SIGNING_MICROS_MAX.load(Ordering::Relaxed),
+        l1_submissions_succeeded: succeeded,
+        l1_submissions_failed: failed,
+        l1_submission_success_rate: (succeeded + failed > 0).then(|| succeeded as f64 / (succeeded + failed) as f64),
+    }
+}
+
+fn prune(verified_at: &mut VecDeque<Instant>, now: Instant) {
+    while verified_at.front().is_some_and(|at| now.duration_since(*at) > RATE_WINDOW) {
+        verified_at.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_counts_recorded_events() {
+        let before = snapshot();
+        record_proof_verified();
+        record_rejection("state_root_mismatch");
+        record_signing(Duration::from_micros(40));
+        record_l1_submission(true);
+        record_l1_submission(false);
+
+        let after = snapshot();
+        assert!(after.proofs_verified > before.proofs_verified);
+        assert!(after.proofs_verified_per_minute >= 1);
+        assert!(after.rejections["state_root_mismatch"] >= 1);
+        assert!(after.signing_latency_max_micros >= 40);
+        assert!(after.l1_submission_success_rate.is_some_and(|rate| rate > 0.0 && rate < 1.0));
+    }
+}
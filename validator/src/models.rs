@@ -1,8 +1,58 @@
 use serde_derive::{Deserialize, Serialize};
 use solana_sdk::signature::Signature;
+use trollup_zk::prove::ProofPackagePrepared;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiResponse {
     pub success: bool,
-    pub signature: Signature
-}
\ No newline at end of file
+    pub signature: Signature,
+    /// What the commitment transaction would have done, only set with `VALIDATOR_DRY_RUN`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<DryRun>,
+}
+
+/// Result of simulating a commitment transaction on L1 instead of sending it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DryRun {
+    /// Whether L1 would have accepted the transaction
+    pub would_succeed: bool,
+    pub err: Option<String>,
+    pub logs: Vec<String>,
+    pub units_consumed: Option<u64>,
+}
+
+/// Body of `/prove`, the proof package optionally accompanied by the batch's witness bundle.
+#[derive(Serialize, Deserialize)]
+pub struct ProveRequest {
+    #[serde(flatten)]
+    pub proof_package: ProofPackagePrepared,
+    /// Base64 encoded Borsh `WitnessBundle`
+    #[serde(default)]
+    pub witness: Option<String>,
+    /// Base64 encoded root of the block's cross-rollup outbox, signed with the state root.
+    /// Missing means the block sent no messages.
+    #[serde(default)]
+    pub outbox_root: Option<String>,
+    /// Base58 key of the sequencer that produced the block, signed with the state root
+    #[serde(default)]
+    pub sequencer: Option<String>,
+    /// `major.minor.patch` version of the sequencer's node software, signed with the state root
+    #[serde(default)]
+    pub node_version: Option<String>,
+}
+
+/// Body of `/prove-batch`, consecutive blocks to commit in one L1 transaction, oldest first.
+#[derive(Serialize, Deserialize)]
+pub struct ProveBatchRequest {
+    pub entries: Vec<ProveBatchEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProveBatchEntry {
+    #[serde(flatten)]
+    pub request: ProveRequest,
+    /// URL safe base64 encoded state root, as in the `/prove` path
+    pub new_state_root: String,
+    pub sequence: u64,
+}
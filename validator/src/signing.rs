@@ -0,0 +1,269 @@
+use crate::error::ValidationError;
+use crate::error::ValidationError::{InvalidBatch, SigningFailed};
+use libsecp256k1::{Message, PublicKey, SecretKey};
+use solana_sdk::signature::{Keypair, Signer};
+use state::config::{CommitmentSignatureScheme, TrollupConfig};
+use state::state_record::{ChainedCommitment, Ed25519Commitment, ZkProofCommitment};
+use trollup_commitment_message::{chained_commitment_message_hash, commitment_message_hash, BlockProducer, MessageHash};
+
+/// Signs the validator's commitments to the signature verifier program.
+pub trait CommitmentSigner {
+    /// Signs a commitment to `new_state_root` at `sequence`, produced by `producer`.
+    fn sign(&self, new_state_root: [u8; 32], sequence: u64, outbox_root: [u8; 32], producer: BlockProducer) -> Result<SignedCommitment, ValidationError>;
+
+    /// Signs a commitment of a batch, extending `parent_state_root`.
+    fn sign_chained(&self, parent_state_root: [u8; 32], new_state_root: [u8; 32], sequence: u64, outbox_root: [u8; 32], producer: BlockProducer) -> Result<ChainedCommitment, ValidationError>;
+}
+
+/// A signed commitment, ready for its signature verifier instruction.
+pub enum SignedCommitment {
+    Secp256k1(ZkProofCommitment),
+    /// The commitment and its signature, which goes into the ed25519 program's instruction
+    Ed25519(Ed25519Commitment, [u8; 64]),
+}
+
+/// The signer for `COMMITMENT_SIGNATURE_SCHEME`.
+pub fn commitment_signer(config: &TrollupConfig) -> Result<Box<dyn CommitmentSigner>, ValidationError> {
+    match config.commitment_signature_scheme {
+        CommitmentSignatureScheme::Secp256k1 => Ok(Box::new(Secp256k1Signer {
+            // TODO create and load this from somewhere else
+            secret_key: SecretKey::default().serialize(),
+            message_hash: config.commitment_message_hash,
+        })),
+        CommitmentSignatureScheme::Ed25519 => {
+            let keypair = config.commitment_signer().map_err(SigningFailed)?;
+            Ok(Box::new(Ed25519Signer { keypair, message_hash: config.commitment_message_hash }))
+        }
+    }
+}
+
+pub struct Secp256k1Signer {
+    pub secret_key: [u8; 32],
+    pub message_hash: MessageHash,
+}
+
+impl CommitmentSigner for Secp256k1Signer {
+    fn sign(&self, new_state_root: [u8; 32], sequence: u64, outbox_root: [u8; 32], producer: BlockProducer) -> Result<SignedCommitment, ValidationError> {
+        create_and_sign_commitment(self.message_hash, new_state_root, sequence, outbox_root, producer, &self.secret_key)
+            .map(SignedCommitment::Secp256k1)
+            .map_err(|error| SigningFailed(error.to_string()))
+    }
+
+    fn sign_chained(&self, parent_state_root: [u8; 32], new_state_root: [u8; 32], sequence: u64, outbox_root: [u8; 32], producer: BlockProducer) -> Result<ChainedCommitment, ValidationError> {
+        create_and_sign_chained_commitment(self.message_hash, parent_state_root, new_state_root, sequence, outbox_root, producer, &self.secret_key)
+            .map_err(|error| SigningFailed(error.to_string()))
+    }
+}
+
+/// Signs the same message hash as `Secp256k1Signer`. The signature verifier has no ed25519
+/// batch instruction, so batches are refused.
+pub struct Ed25519Signer {
+    pub keypair: Keypair,
+    pub message_hash: MessageHash,
+}
+
+impl CommitmentSigner for Ed25519Signer {
+    fn sign(&self, new_state_root: [u8; 32], sequence: u64, outbox_root: [u8; 32], producer: BlockProducer) -> Result<SignedCommitment, ValidationError> {
+        let message_hash = commitment_message_hash(self.message_hash, &new_state_root, sequence, &outbox_root, &producer);
+        let signature = self.keypair.sign_message(&message_hash);
+        let signature: [u8; 64] = signature.as_ref().try_into().map_err(|_| SigningFailed("ed25519 signature isn't 64 bytes".to_string()))?;
+        Ok(SignedCommitment::Ed25519(Ed25519Commitment {
+            public_key: self.keypair.pubkey().to_bytes(),
+            new_state_root,
+            sequence,
+            outbox_root,
+            producer,
+        }, signature))
+    }
+
+    fn sign_chained(&self, _parent_state_root: [u8; 32], _new_state_root: [u8; 32], _sequence: u64, _outbox_root: [u8; 32], _producer: BlockProducer) -> Result<ChainedCommitment, ValidationError> {
+        Err(InvalidBatch("batches need secp256k1 commitments".to_string()))
+    }
+}
+
+fn create_and_sign_chained_commitment(
+    message_hash: MessageHash,
+    parent_state_root: [u8; 32],
+    new_state_root: [u8; 32],
+    sequence: u64,
+    outbox_root: [u8; 32],
+    producer: BlockProducer,
+    verifier_secret_key: &[u8; 32],
+) -> Result<ChainedCommitment, Box<dyn std::error::Error>> {
+    let message_hash = chained_commitment_message_hash(message_hash, &parent_state_root, &new_state_root, sequence, &outbox_root, &producer);
+    let message = Message::parse_slice(&message_hash)?;
+    let secret_key = SecretKey::parse(verifier_secret_key)?;
+    let (signature, recovery_id) = libsecp256k1::sign(&message, &secret_key);
+
+    Ok(ChainedCommitment {
+        verifier_signature: signature.serialize(),
+        recovery_id: recovery_id.serialize(),
+        public_key: PublicKey::from_secret_key(&secret_key).serialize(),
+        parent_state_root,
+        new_state_root,
+        sequence,
+        outbox_root,
+        producer,
+    })
+}
+
+fn create_and_sign_commitment(
+    message_hash: MessageHash,
+    new_state_root: [u8; 32],
+    sequence: u64,
+    outbox_root: [u8; 32],
+    producer: BlockProducer,
+    verifier_secret_key: &[u8; 32],
+) -> Result<ZkProofCommitment, Box<dyn std::error::Error>> {
+    let message_hash = commitment_message_hash(message_hash, &new_state_root, sequence, &outbox_root, &producer);
+
+    // If verification succeeds, create and sign the commitment
+    let message = Message::parse_slice(&message_hash)?;
+
+    // Create secret key from input bytes
+    let secret_key = SecretKey::parse(verifier_secret_key)?;
+    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+
+    // Sign the message
+    let (signature, recovery_id) = libsecp256k1::sign(&message, &secret_key);
+
+    // Combine signature and recovery ID into 64 bytes
+    let mut signature_bytes = [0u8; 64];
+    signature_bytes[..64].copy_from_slice(&signature.serialize());
+
+    Ok(ZkProofCommitment {
+        verifier_signature: signature_bytes,
+        recovery_id: recovery_id.serialize(),
+        public_key,
+        new_state_root,
+        sequence,
+        outbox_root,
+        producer,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+    use trollup_commitment_message::test_vectors::test_vectors;
+    use trollup_commitment_message::NodeVersion;
+
+    const PRODUCER: BlockProducer = BlockProducer { sequencer: [4u8; 32], version: NodeVersion::new(0, 1, 0) };
+
+    #[test]
+    fn test_create_and_sign_commitment() {
+        let rng = &mut thread_rng();
+
+        // Create test inputs
+        let proof_hash = [1u8; 32];
+        let new_state_root = [2u8; 32];
+        let timestamp = 1632825600; // Example timestamp
+
+        // Generate a test secret key
+        let secret_key = SecretKey::default();
+        let secret_key_bytes = secret_key.serialize();
+        // let secret_key = SecretKey::random(&mut rng);
+        // let secret_key_bytes = secret_key.serialize();
+
+        // Call the function
+        let result = create_and_sign_commitment(
+            MessageHash::Keccak256,
+            new_state_root,
+            7,
+            [3u8; 32],
+            PRODUCER,
+            &secret_key_bytes,
+        );
+
+        // Assert that the result is Ok
+        assert!(result.is_ok());
+
+        // Unwrap the result
+        let commitment = result.unwrap();
+
+        // Verify the fields of the commitment
+        // assert_eq!(commitment.proof_hash, proof_hash);
+        assert_eq!(commitment.new_state_root, new_state_root);
+        assert_eq!(commitment.sequence, 7);
+        assert_eq!(commitment.outbox_root, [3u8; 32]);
+        assert_eq!(commitment.producer, PRODUCER);
+        // assert_eq!(commitment.timestamp, timestamp);
+
+        // Verify the public key
+        let expected_public_key = PublicKey::from_secret_key(&secret_key).serialize();
+        assert_eq!(commitment.public_key, expected_public_key);
+
+        // Verify the signature
+        let message = Message::parse_slice(&commitment_message_hash(MessageHash::Keccak256, &new_state_root, 7, &[3u8; 32], &PRODUCER)).unwrap();
+        let signature = libsecp256k1::Signature::parse_standard_slice(&commitment.verifier_signature[..64]).unwrap();
+        assert!(libsecp256k1::verify(&message, &signature, &PublicKey::parse(&commitment.public_key).unwrap()));
+    }
+
+    #[test]
+    fn test_create_and_sign_chained_commitment() {
+        let secret_key = SecretKey::default();
+        let commitment = create_and_sign_chained_commitment(MessageHash::Keccak256, [1u8; 32], [2u8; 32], 8, [3u8; 32], PRODUCER, &secret_key.serialize()).unwrap();
+
+        assert_eq!(commitment.parent_state_root, [1u8; 32]);
+        assert_eq!(commitment.new_state_root, [2u8; 32]);
+        assert_eq!(commitment.sequence, 8);
+
+        // The parent is part of the signed message
+        let message = Message::parse_slice(&chained_commitment_message_hash(MessageHash::Keccak256, &[1u8; 32], &[2u8; 32], 8, &[3u8; 32], &PRODUCER)).unwrap();
+        let signature = libsecp256k1::Signature::parse_standard_slice(&commitment.verifier_signature).unwrap();
+        let public_key = PublicKey::parse(&commitment.public_key).unwrap();
+        assert!(libsecp256k1::verify(&message, &signature, &public_key));
+        let message = Message::parse_slice(&commitment_message_hash(MessageHash::Keccak256, &[2u8; 32], 8, &[3u8; 32], &PRODUCER)).unwrap();
+        assert!(!libsecp256k1::verify(&message, &signature, &public_key));
+        // So is the producer
+        let other_version = BlockProducer { version: NodeVersion::new(0, 2, 0), ..PRODUCER };
+        let message = Message::parse_slice(&chained_commitment_message_hash(MessageHash::Keccak256, &[1u8; 32], &[2u8; 32], 8, &[3u8; 32], &other_version)).unwrap();
+        assert!(!libsecp256k1::verify(&message, &signature, &public_key));
+    }
+
+    #[test]
+    fn test_ed25519_signer_signs_commitment_hash() {
+        let signer = Ed25519Signer { keypair: Keypair::new(), message_hash: MessageHash::Keccak256 };
+        let Ok(SignedCommitment::Ed25519(commitment, signature)) = signer.sign([2u8; 32], 7, [3u8; 32], PRODUCER) else {
+            panic!("Expected an ed25519 commitment");
+        };
+
+        assert_eq!(commitment.public_key, signer.keypair.pubkey().to_bytes());
+        assert_eq!(commitment.sequence, 7);
+        let signature = solana_sdk::signature::Signature::from(signature);
+        let message_hash = commitment_message_hash(MessageHash::Keccak256, &[2u8; 32], 7, &[3u8; 32], &PRODUCER);
+        assert!(signature.verify(&commitment.public_key, &message_hash));
+        assert!(matches!(signer.sign_chained([1u8; 32], [2u8; 32], 7, [3u8; 32], PRODUCER), Err(InvalidBatch(_))));
+    }
+
+    #[test]
+    fn test_signers_sign_the_test_vector_digests() {
+        let secret_key = SecretKey::default();
+        let public_key = PublicKey::from_secret_key(&secret_key);
+        for vector in test_vectors() {
+            let message = Message::parse_slice(&vector.digest()).unwrap();
+            let secp256k1 = Secp256k1Signer { secret_key: secret_key.serialize(), message_hash: vector.hash };
+            let signature = match vector.parent_state_root() {
+                Some(parent_state_root) => secp256k1
+                    .sign_chained(parent_state_root, vector.new_state_root(), vector.sequence, vector.outbox_root(), vector.producer())
+                    .unwrap()
+                    .verifier_signature,
+                None => {
+                    let Ok(SignedCommitment::Secp256k1(commitment)) = secp256k1.sign(vector.new_state_root(), vector.sequence, vector.outbox_root(), vector.producer()) else {
+                        panic!("Expected a secp256k1 commitment");
+                    };
+                    let ed25519 = Ed25519Signer { keypair: Keypair::new(), message_hash: vector.hash };
+                    let Ok(SignedCommitment::Ed25519(ed25519_commitment, ed25519_signature)) = ed25519.sign(vector.new_state_root(), vector.sequence, vector.outbox_root(), vector.producer()) else {
+                        panic!("Expected an ed25519 commitment");
+                    };
+                    let ed25519_signature = solana_sdk::signature::Signature::from(ed25519_signature);
+                    assert!(ed25519_signature.verify(&ed25519_commitment.public_key, &vector.digest()), "{}", vector.name);
+                    commitment.verifier_signature
+                }
+            };
+            let signature = libsecp256k1::Signature::parse_standard_slice(&signature).unwrap();
+            assert!(libsecp256k1::verify(&message, &signature, &public_key), "{}", vector.name);
+        }
+    }
+}
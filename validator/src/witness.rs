@@ -0,0 +1,292 @@
+use crate::error::ValidationError;
+use crate::error::ValidationError::{InvalidWitness, OutboxRootMismatch, StateRootMismatch};
+use borsh::{BorshDeserialize, BorshSerialize};
+use execution::replay::replay_witness;
+use execution::signature_verification::retain_valid_signatures;
+use log::info;
+use rs_merkle::algorithms::Sha256;
+use rs_merkle::MerkleTree;
+use state::config::TrollupConfig;
+use state::cross_rollup::{outbox_messages, outbox_root};
+use state::state_record::StateRecord;
+use state::witness::{account_leaf, WitnessBundle};
+use solana_sdk::pubkey::Pubkey;
+use state_management::state_management::{ManageState, StateManager};
+use std::collections::HashSet;
+
+/// A state root this validator signed. Witness bundles may only build on these.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct SignedRoot {
+    pub root: [u8; 32],
+    pub sequence: u64,
+}
+
+impl StateRecord for SignedRoot {
+    fn get_key(&self) -> [u8; 32] {
+        self.root
+    }
+}
+
+/// Root of the latest block this validator signed that wrote an account, keyed by the account's
+/// address. A witnessed account must be proven against exactly this root, so an older state of
+/// it can't be passed off as current, and only accounts without one may be left out of a witness
+/// as new.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct AccountRoot {
+    pub address: [u8; 32],
+    pub root: [u8; 32],
+}
+
+impl StateRecord for AccountRoot {
+    fn get_key(&self) -> [u8; 32] {
+        self.address
+    }
+}
+
+/// What the validator signed before: the roots and the latest root of every account.
+pub struct SignedHistory<'a, S: ManageState<Record=SignedRoot>, A: ManageState<Record=AccountRoot>> {
+    pub roots: &'a StateManager<S>,
+    pub account_roots: &'a StateManager<A>,
+}
+
+/// A root verified earlier in the same `/prove-batch` request and the accounts its block wrote.
+/// Not signed yet, but later blocks of the batch may build on it.
+#[derive(Debug, Clone)]
+pub struct PendingRoot {
+    pub root: [u8; 32],
+    pub accounts: Vec<[u8; 32]>,
+}
+
+impl PendingRoot {
+    /// The `AccountRoot` of every account the block wrote, to record once the root is signed.
+    pub fn account_roots(&self) -> Vec<AccountRoot> {
+        self.accounts.iter().map(|address| AccountRoot { address: *address, root: self.root }).collect()
+    }
+}
+
+/// Re-executes the batch in `witness` and checks that it produces `new_state_root` and sends the
+/// cross-rollup messages committed to by `expected_outbox_root`. Returns the block's root and
+/// the accounts it wrote.
+///
+/// The pre-state is only trusted when each witnessed account is proven against the root of the
+/// latest signed block that wrote it, and every other account the transactions reference was
+/// never written by a signed block. The batch must extend a signed root as well. `batch` holds
+/// the roots verified earlier in the same `/prove-batch` request, they count as signed.
+pub fn verify_witness<S: ManageState<Record=SignedRoot>, A: ManageState<Record=AccountRoot>>(
+    config: &TrollupConfig,
+    witness: &WitnessBundle,
+    new_state_root: &[u8; 32],
+    expected_outbox_root: &[u8; 32],
+    history: &SignedHistory<S, A>,
+    batch: &[PendingRoot],
+) -> Result<PendingRoot, ValidationError> {
+    let is_signed = |root: &[u8; 32]| batch.iter().any(|pending| &pending.root == root) || history.roots.get_state_record(root).is_some();
+    let latest_root = |address: &[u8; 32]| {
+        batch
+            .iter()
+            .rev()
+            .find(|pending| pending.accounts.contains(address))
+            .map(|pending| pending.root)
+            .or_else(|| history.account_roots.get_state_record(address).map(|account_root| account_root.root))
+    };
+    if let Some(parent_state_root) = &witness.parent_state_root {
+        if !is_signed(parent_state_root) {
+            return Err(InvalidWitness("parent state root was not signed by this validator".to_string()));
+        }
+    }
+    let mut witnessed = HashSet::new();
+    for account_witness in &witness.accounts {
+        let address = account_witness.account.address;
+        if !witnessed.insert(address.to_bytes()) {
+            return Err(InvalidWitness(format!("account {} is witnessed twice", address)));
+        }
+        let Some(inclusion) = &account_witness.inclusion else {
+            return Err(InvalidWitness(format!("no inclusion proof for account {}", address)));
+        };
+        if !inclusion.verify(&account_witness.account) {
+            return Err(InvalidWitness(format!("invalid inclusion proof for account {}", address)));
+        }
+        match latest_root(&address.to_bytes()) {
+            Some(root) if root == inclusion.root => {}
+            Some(_) => return Err(InvalidWitness(format!("account {} is proven against a stale root", address))),
+            None => return Err(InvalidWitness(format!("account {} was never written by a signed block", address))),
+        }
+    }
+    // Accounts left out of the witness are executed as new, which only holds for accounts no
+    // signed block wrote
+    let omitted = witness.transactions
+        .iter()
+        .flat_map(|transaction| transaction.message.account_keys.iter())
+        .find(|address| !witnessed.contains(*address) && latest_root(*address).is_some());
+    if let Some(address) = omitted {
+        return Err(InvalidWitness(format!("account {} exists but has no witness", Pubkey::from(*address))));
+    }
+
+    if retain_valid_signatures(witness.transactions.clone()).len() != witness.transactions.len() {
+        return Err(InvalidWitness("batch contains a transaction with an invalid signature".to_string()));
+    }
+
+    let post_state = replay_witness(config, witness);
+    let leaves: Vec<[u8; 32]> = post_state.iter().map(account_leaf).collect();
+    let recomputed_root = MerkleTree::<Sha256>::from_leaves(&leaves).root();
+    info!("Re-executed {} transactions from witness, root {:?}", witness.transactions.len(), recomputed_root);
+    if recomputed_root.as_ref() != Some(new_state_root) {
+        return Err(StateRootMismatch);
+    }
+
+    let messages = outbox_messages(&post_state, |address| {
+        witness.accounts.iter().find(|account_witness| &account_witness.account.address == address).map(|account_witness| account_witness.account.clone())
+    });
+    if &outbox_root(&messages) != expected_outbox_root {
+        return Err(OutboxRootMismatch);
+    }
+    let mut written = HashSet::new();
+    Ok(PendingRoot {
+        root: *new_state_root,
+        accounts: post_state.iter().map(|account| account.address.to_bytes()).filter(|address| written.insert(*address)).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::signature::Signer;
+    use state::account_state::AccountState;
+    use state::test_fixtures::{keypair, system_account, transfer_transaction};
+    use state::transaction::TrollupTransaction;
+    use state_management::sled_state_management::SledStateManagement;
+    use state::witness::{AccountInclusion, AccountWitness};
+
+    type Roots = StateManager<SledStateManagement<SignedRoot>>;
+    type AccountRoots = StateManager<SledStateManagement<AccountRoot>>;
+
+    fn account(lamports: u64) -> AccountState {
+        AccountState {
+            address: Pubkey::new_unique(),
+            lamports,
+            data: vec![],
+            owner: Pubkey::default(),
+            executable: false,
+            rent_epoch: 0,
+            data_root: None,
+        }
+    }
+
+    fn stores() -> (Roots, AccountRoots) {
+        (StateManager::new(""), StateManager::new(""))
+    }
+
+    /// Records `root` as signed, by a block that wrote `accounts`.
+    fn sign(roots: &Roots, account_roots: &AccountRoots, root: [u8; 32], accounts: &[&AccountState]) {
+        roots.set_state_record(&SignedRoot { root, sequence: 1 });
+        for account in accounts {
+            account_roots.set_state_record(&AccountRoot { address: account.address.to_bytes(), root });
+        }
+    }
+
+    /// Witness of `accounts[0]`, proven against a block containing all of `accounts`.
+    fn witness(accounts: &[AccountState]) -> WitnessBundle {
+        let leaves: Vec<[u8; 32]> = accounts.iter().map(account_leaf).collect();
+        let tree = MerkleTree::<Sha256>::from_leaves(&leaves);
+        let inclusion = AccountInclusion {
+            block_number: 1,
+            root: tree.root().unwrap(),
+            leaf_index: 0,
+            leaf_count: leaves.len() as u64,
+            proof: tree.proof(&[0]).proof_hashes().to_vec(),
+        };
+        WitnessBundle {
+            parent_state_root: Some(inclusion.root),
+            slot: 2,
+            unix_timestamp: 0,
+            epoch_start_timestamp: 0,
+            rollup_metadata: Default::default(),
+            transactions: vec![],
+            accounts: vec![AccountWitness { account: accounts[0].clone(), inclusion: Some(inclusion) }],
+        }
+    }
+
+    fn verify(witness: &WitnessBundle, roots: &Roots, account_roots: &AccountRoots, batch: &[PendingRoot]) -> Result<PendingRoot, ValidationError> {
+        let history = SignedHistory { roots, account_roots };
+        verify_witness(&TrollupConfig::default(), witness, &[0u8; 32], &[0u8; 32], &history, batch)
+    }
+
+    #[test]
+    fn test_rejects_unsigned_parent_root() {
+        let (roots, account_roots) = stores();
+        let witness = witness(&[account(1), account(2)]);
+
+        let result = verify(&witness, &roots, &account_roots, &[]);
+        assert!(matches!(result, Err(InvalidWitness(_))));
+    }
+
+    #[test]
+    fn test_rejects_tampered_pre_state() {
+        let (roots, account_roots) = stores();
+        let mut witness = witness(&[account(1), account(2)]);
+        sign(&roots, &account_roots, witness.parent_state_root.unwrap(), &[&witness.accounts[0].account]);
+        assert!(witness.accounts[0].inclusion.as_ref().unwrap().verify(&witness.accounts[0].account));
+
+        witness.accounts[0].account.lamports = 1_000_000;
+        let result = verify(&witness, &roots, &account_roots, &[]);
+        assert!(matches!(result, Err(InvalidWitness(_))));
+    }
+
+    #[test]
+    fn test_checks_pass_against_the_latest_root() {
+        let (roots, account_roots) = stores();
+        let witness = witness(&[account(1), account(2)]);
+        sign(&roots, &account_roots, witness.parent_state_root.unwrap(), &[&witness.accounts[0].account]);
+
+        // Past the pre-state checks, the empty batch just doesn't reproduce the proposed root
+        let result = verify(&witness, &roots, &account_roots, &[]);
+        assert_eq!(result.unwrap_err(), StateRootMismatch);
+    }
+
+    #[test]
+    fn test_rejects_proofs_against_a_stale_root() {
+        let (roots, account_roots) = stores();
+        let witness = witness(&[account(1), account(2)]);
+        let account = &witness.accounts[0].account;
+        sign(&roots, &account_roots, witness.parent_state_root.unwrap(), &[account]);
+        // A later signed block wrote the account again
+        sign(&roots, &account_roots, [7u8; 32], &[account]);
+
+        let result = verify(&witness, &roots, &account_roots, &[]);
+        assert_eq!(result.unwrap_err(), InvalidWitness(format!("account {} is proven against a stale root", account.address)));
+
+        // The same holds for a write earlier in the batch
+        let (roots, account_roots) = stores();
+        sign(&roots, &account_roots, witness.parent_state_root.unwrap(), &[account]);
+        let batch = [PendingRoot { root: [7u8; 32], accounts: vec![account.address.to_bytes()] }];
+        let result = verify(&witness, &roots, &account_roots, &batch);
+        assert!(matches!(result, Err(InvalidWitness(_))));
+    }
+
+    #[test]
+    fn test_rejects_accounts_no_signed_block_wrote() {
+        let (roots, account_roots) = stores();
+        let witness = witness(&[account(1), account(2)]);
+        // The root is signed, but the account isn't recorded as written by it
+        roots.set_state_record(&SignedRoot { root: witness.parent_state_root.unwrap(), sequence: 1 });
+
+        let result = verify(&witness, &roots, &account_roots, &[]);
+        assert!(matches!(result, Err(InvalidWitness(_))));
+    }
+
+    #[test]
+    fn test_rejects_existing_accounts_left_out_of_the_witness() {
+        let (roots, account_roots) = stores();
+        let sender = keypair("witness-sender");
+        let recipient = system_account(keypair("witness-recipient").pubkey(), 5);
+        let sender_account = system_account(sender.pubkey(), 1_000_000);
+        let mut witness = witness(&[sender_account.clone(), recipient.clone()]);
+        witness.transactions = vec![TrollupTransaction::from(&transfer_transaction(&sender, &recipient.address, 10, Hash::default()))];
+        sign(&roots, &account_roots, witness.parent_state_root.unwrap(), &[&sender_account, &recipient]);
+
+        // The recipient exists, executing it as a new account would mint its balance away
+        let result = verify(&witness, &roots, &account_roots, &[]);
+        assert_eq!(result.unwrap_err(), InvalidWitness(format!("account {} exists but has no witness", recipient.address)));
+    }
+}
@@ -8,6 +8,7 @@ use sha2::{Digest, Sha256};
 use solana_program::pubkey::Pubkey;
 use state::account_state::AccountState;
 use crate::byte_utils::field_to_bytes;
+use crate::public_inputs::{compute_public_inputs, roots_hash, state_root_limbs};
 
 // Circuit for proving knowledge of a Solana account's state changes
 // The idea behind this example circuit is that the rollup that generates this proof for a batch of
@@ -21,6 +22,10 @@ pub struct AccountStateCircuit {
     pub account_states: Vec<AccountState>,
     pub account_hash: Option<Fr>,
     pub lamports_sum: Option<Fr>,
+    /// Start of the account hash chain, binding the proof to the batch's parent and transactions
+    pub roots_hash: Fr,
+    /// The account root the batch results in, see `state_root_limbs`
+    pub state_root: Option<[Fr; 2]>,
 }
 
 impl AccountStateCircuit {
@@ -31,33 +36,28 @@ impl AccountStateCircuit {
             account_states: vec![],
             account_hash: None,
             lamports_sum: None,
+            roots_hash: Fr::zero(),
+            state_root: None,
         }
     }
 
-    pub fn new(account_states: Vec<AccountState>) -> Self {
+    /// The circuit for a batch writing `account_states` on top of `parent_root` and resulting in
+    /// `state_root`, with the public inputs `compute_public_inputs` gives for it.
+    pub fn new(account_states: Vec<AccountState>, parent_root: &[u8; 32], transactions_root: &[u8; 32], state_root: &[u8; 32]) -> Self {
 
         let mut hasher = Sha256::new();
         hasher.update(&Pubkey::new_unique().to_bytes());
         let merkle_node_hash: [u8; 32] = hasher.finalize().into();
 
-        // Compute addresses_hash and lamports_sum
-        let mut poseidon = Poseidon::<Fr>::new_circom(3).unwrap();
-
-        let mut addresses_hash = Fr::zero();
-        let mut lamports_sum = 0u64;
-
-        for account in &account_states {
-            let address_fr = Fr::from_be_bytes_mod_order(&account.address.to_bytes());
-            let datum_fr = Fr::from_be_bytes_mod_order(&account.data.as_slice());
-            addresses_hash = poseidon.hash(&[addresses_hash, address_fr, datum_fr]).unwrap();
-            lamports_sum += account.lamports;
-        }
+        let public_inputs = compute_public_inputs(&account_states, parent_root, transactions_root, state_root);
 
         let circuit = AccountStateCircuit {
             merkle_node_hash: Some(Fr::from_be_bytes_mod_order(&merkle_node_hash)),
             account_states,
-            account_hash: Some(addresses_hash),
-            lamports_sum: Some(Fr::from(lamports_sum)),
+            account_hash: Some(public_inputs.account_hash),
+            lamports_sum: Some(Fr::from(public_inputs.lamports_sum)),
+            roots_hash: roots_hash(parent_root, transactions_root),
+            state_root: Some(state_root_limbs(state_root)),
         };
 
         circuit
@@ -67,6 +67,8 @@ impl AccountStateCircuit {
         let public_inputs: Vec<[u8; 32]> = vec![
             field_to_bytes(self.account_hash.unwrap()),
             field_to_bytes(self.lamports_sum.unwrap()),
+            field_to_bytes(self.state_root.unwrap()[0]),
+            field_to_bytes(self.state_root.unwrap()[1]),
         ];
 
         public_inputs
@@ -106,7 +108,7 @@ impl ConstraintSynthesizer<Fr> for AccountStateCircuit {
         }
 
         // Compute addresses_hash
-        let mut current_hash = Fr::zero();
+        let mut current_hash = self.roots_hash;
         for &address_var in &address_vars {
             current_hash = poseidon.hash(&[current_hash, address_var.0, address_var.1]).unwrap();
         }
@@ -126,6 +128,12 @@ impl ConstraintSynthesizer<Fr> for AccountStateCircuit {
         let lamports_sum_public = cs.new_input_variable(|| {
             self.lamports_sum.map(Fr::from).ok_or(SynthesisError::AssignmentMissing)
         })?;
+        let state_root_high = cs.new_input_variable(|| {
+            self.state_root.map(|limbs| limbs[0]).ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        let state_root_low = cs.new_input_variable(|| {
+            self.state_root.map(|limbs| limbs[1]).ok_or(SynthesisError::AssignmentMissing)
+        })?;
 
         // Constraint: Ensure computed addresses_hash matches the provided addresses_hash
         cs.enforce_constraint(
@@ -141,6 +149,18 @@ impl ConstraintSynthesizer<Fr> for AccountStateCircuit {
             lc!() + lamports_sum_public,
         )?;
 
+        // Constraints: Use both halves of the state root, so a proof only verifies for its root
+        cs.enforce_constraint(
+            lc!() + state_root_high,
+            lc!() + Variable::One,
+            lc!() + state_root_high,
+        )?;
+        cs.enforce_constraint(
+            lc!() + state_root_low,
+            lc!() + Variable::One,
+            lc!() + state_root_low,
+        )?;
+
         // Add a constraint linking merkle_node_hash and addresses_hash
         // This is a placeholder constraint; replace with actual relationship if known
         cs.enforce_constraint(
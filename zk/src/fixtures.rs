@@ -0,0 +1,121 @@
+use crate::byte_utils::field_to_bytes;
+use crate::prove::{generate_proof, setup};
+use crate::public_inputs::{compute_public_inputs, PublicInputs};
+use crate::verify_lite::{convert_arkworks_vk_to_solana_example, Groth16Proof, Groth16VerifierPrepared, Groth16VerifyingKeyPrepared, OnChainVerifyingKey, ProofCommitmentPackage};
+use ark_bn254::{Bn254, G1Projective};
+use ark_groth16::{Proof, VerifyingKey};
+use ark_serialize::{CanonicalSerialize, Compress};
+use solana_program::alt_bn128::compression::prelude::convert_endianness;
+use solana_sdk::pubkey::Pubkey;
+use state::account_state::AccountState;
+use std::ops::Neg;
+
+/// Builds the `Groth16VerifierPrepared` expected by the on-chain proof verifier.
+///
+/// The proof's `a` point is negated and every point is converted to the big-endian layout used by
+/// the alt_bn128 syscalls.
+///
+/// # Arguments
+///
+/// * `proof` - The Groth16 proof.
+/// * `prepared_public_inputs` - The public inputs already prepared against the verifying key.
+/// * `verifying_key` - The full (unprepared) verifying key the proof was generated with.
+pub fn build_verifier_prepared(proof: &Proof<Bn254>, prepared_public_inputs: &G1Projective, verifying_key: &VerifyingKey<Bn254>) -> Groth16VerifierPrepared {
+    let Groth16Proof { proof_a, proof_b, proof_c } = build_proof(proof);
+
+    let mut public_input_bytes = [0u8; 64];
+    prepared_public_inputs.serialize_uncompressed(&mut public_input_bytes[..]).expect("Error serializing public inputs");
+    let prepared_public_input = convert_endianness::<32, 64>(&public_input_bytes);
+
+    let groth_vk = convert_arkworks_vk_to_solana_example(verifying_key);
+    let groth_vk_prepared = Groth16VerifyingKeyPrepared {
+        vk_alpha_g1: groth_vk.vk_alpha_g1,
+        vk_beta_g2: groth_vk.vk_beta_g2,
+        vk_gamma_g2: groth_vk.vk_gamma_g2,
+        vk_delta_g2: groth_vk.vk_delta_g2,
+    };
+
+    Groth16VerifierPrepared::new(
+        proof_a,
+        proof_b,
+        proof_c,
+        prepared_public_input,
+        Box::new(groth_vk_prepared),
+    ).expect("Error building prepared verifier")
+}
+
+/// The proof in the layout of the alt_bn128 syscalls, `a` negated as the pairing check expects.
+pub fn build_proof(proof: &Proof<Bn254>) -> Groth16Proof {
+    let proof_with_neg_a = Proof::<Bn254> {
+        a: proof.a.neg(),
+        b: proof.b,
+        c: proof.c,
+    };
+    let mut proof_bytes = Vec::with_capacity(proof_with_neg_a.serialized_size(Compress::No));
+    proof_with_neg_a.serialize_uncompressed(&mut proof_bytes).expect("Error serializing proof");
+
+    Groth16Proof {
+        proof_a: convert_endianness::<32, 64>(proof_bytes[0..64].try_into().unwrap()),
+        proof_b: convert_endianness::<64, 128>(proof_bytes[64..192].try_into().unwrap()),
+        proof_c: convert_endianness::<32, 64>(proof_bytes[192..256].try_into().unwrap()),
+    }
+}
+
+/// The `VerifyProof` payload for `proof` of a batch with `public_inputs`. Only the account hash
+/// and lamports sum are sent, the verifier derives the state root inputs itself.
+pub fn build_proof_commitment_package(proof: &Proof<Bn254>, public_inputs: &PublicInputs) -> ProofCommitmentPackage {
+    let [account_hash, lamports_sum, ..] = public_inputs.field_elements();
+    ProofCommitmentPackage {
+        proof: build_proof(proof),
+        batch_inputs: [account_hash, lamports_sum].map(|input| convert_endianness::<32, 32>(&field_to_bytes(input))),
+        state_root: public_inputs.state_root,
+    }
+}
+
+/// The `SetVerifyingKey` payload for `verifying_key`.
+pub fn build_on_chain_verifying_key(verifying_key: &VerifyingKey<Bn254>) -> OnChainVerifyingKey {
+    let groth_vk = convert_arkworks_vk_to_solana_example(verifying_key);
+    OnChainVerifyingKey {
+        vk_alpha_g1: groth_vk.vk_alpha_g1,
+        vk_beta_g2: groth_vk.vk_beta_g2,
+        vk_gamma_g2: groth_vk.vk_gamma_g2,
+        vk_delta_g2: groth_vk.vk_delta_g2,
+        vk_ic: groth_vk.vk_ic.to_vec().try_into().expect("One IC point per public input plus one"),
+    }
+}
+
+/// A small, deterministic batch of account states used to generate fixture proofs.
+pub fn dummy_accounts() -> Vec<AccountState> {
+    accounts_fixture(2)
+}
+
+/// `count` deterministic account states with 8 bytes of data each.
+pub fn accounts_fixture(count: u8) -> Vec<AccountState> {
+    (1u8..=count)
+        .map(|i| AccountState {
+            address: Pubkey::new_from_array([i; 32]),
+            lamports: 1_000_000 * i as u64,
+            data: vec![i; 8],
+            owner: solana_sdk::system_program::id(),
+            executable: false,
+            rent_epoch: 0,
+            data_root: None,
+        })
+        .collect()
+}
+
+/// Runs a fresh Groth16 setup, proves the `dummy_accounts` batch and returns a
+/// `ProofCommitmentPackage` for `state_root` that the on-chain proof verifier accepts once it
+/// stores the verifying key returned with it.
+pub fn proof_commitment_package_fixture(state_root: [u8; 32]) -> (ProofCommitmentPackage, OnChainVerifyingKey) {
+    proof_commitment_package_fixture_for(state_root, dummy_accounts())
+}
+
+/// Like `proof_commitment_package_fixture`, proving `accounts` instead.
+pub fn proof_commitment_package_fixture_for(state_root: [u8; 32], accounts: Vec<AccountState>) -> (ProofCommitmentPackage, OnChainVerifyingKey) {
+    let (proving_key, verifying_key) = setup(false);
+    let public_inputs = compute_public_inputs(&accounts, &[0u8; 32], &[0u8; 32], &state_root);
+    let (_, _, proof_package) = generate_proof(&proving_key, &verifying_key, accounts, &[0u8; 32], &[0u8; 32], &state_root);
+
+    (build_proof_commitment_package(&proof_package.proof, &public_inputs), build_on_chain_verifying_key(&verifying_key))
+}
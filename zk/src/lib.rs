@@ -2,8 +2,11 @@ pub mod account_state_circuit;
 mod errors;
 mod byte_utils;
 pub mod prove;
+pub mod public_inputs;
 pub mod verify;
 pub mod verify_lite;
+pub mod verifying_key;
+pub mod fixtures;
 
 
 #[cfg(test)]
@@ -11,6 +14,10 @@ mod test {
     use crate::byte_utils::{convert_endianness_32, convert_endianness_64, field_to_bytes};
     use crate::byte_utils::{fr_to_g1, g1_affine_to_bytes};
     use crate::errors::Groth16Error;
+    use crate::fixtures::dummy_accounts;
+    use crate::prove::{generate_proof, setup, ProofPackage, ProofPackagePrepared, G1_SIZE, PROOF_SIZE, VERIFYING_KEY_SIZE};
+    use crate::public_inputs::compute_public_inputs;
+    use crate::verify::verify_proof_package;
     use crate::verify_lite::Groth16Verifier;
     use crate::verify_lite::Groth16VerifyingKey;
     use ark_bn254::{Bn254, Fr};
@@ -219,4 +226,63 @@ mod test {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn proof_package_prepared_has_fixed_size_encoding() {
+        let (proving_key, verifying_key) = setup(false);
+        assert_eq!(verifying_key.serialized_size(Compress::No), VERIFYING_KEY_SIZE);
+        let (_, prepared, proof_package) = generate_proof(&proving_key, &verifying_key, dummy_accounts(), &[0u8; 32], &[0u8; 32], &[0u8; 32]);
+
+        let borsh_bytes = borsh::to_vec(&prepared).unwrap();
+        assert_eq!(borsh_bytes.len(), PROOF_SIZE + G1_SIZE + VERIFYING_KEY_SIZE);
+        assert_eq!(bytemuck::bytes_of(&prepared), borsh_bytes.as_slice());
+
+        // The Vec based package this replaced carried the prepared verifying key behind length prefixes
+        let previous_size = 3 * 4 + PROOF_SIZE + G1_SIZE + proof_package.prepared_verifying_key.serialized_size(Compress::No);
+        println!("ProofPackagePrepared borsh size: {} bytes, previously {} bytes", borsh_bytes.len(), previous_size);
+        assert!(borsh_bytes.len() < previous_size);
+
+        let json = serde_json::to_vec(&prepared).unwrap();
+        let decoded: ProofPackagePrepared = serde_json::from_slice(&json).unwrap();
+        assert!(verify_proof_package(&ProofPackage::try_from(decoded).unwrap()));
+    }
+
+    #[test]
+    fn public_inputs_bind_the_parent_and_transactions_roots() {
+        let (proving_key, verifying_key) = setup(false);
+        let (parent_root, transactions_root, state_root) = ([1u8; 32], [2u8; 32], [4u8; 32]);
+        let (lite, prepared, proof_package) = generate_proof(&proving_key, &verifying_key, dummy_accounts(), &parent_root, &transactions_root, &state_root);
+        assert!(verify_proof_package(&proof_package));
+
+        let public_inputs = compute_public_inputs(&dummy_accounts(), &parent_root, &transactions_root, &state_root);
+        assert_eq!(lite.public_inputs, public_inputs.to_bytes());
+        assert_eq!(public_inputs.prepare(&proof_package.prepared_verifying_key).unwrap(), prepared.public_inputs);
+
+        // The same accounts on another parent need another proof
+        let elsewhere = compute_public_inputs(&dummy_accounts(), &[3u8; 32], &transactions_root, &state_root);
+        assert_eq!(elsewhere.lamports_sum, public_inputs.lamports_sum);
+        assert_ne!(elsewhere.account_hash, public_inputs.account_hash);
+        let moved = ProofPackage { public_inputs: elsewhere.prepare_projective(&proof_package.prepared_verifying_key).unwrap(), ..proof_package };
+        assert!(!verify_proof_package(&moved));
+    }
+
+    #[test]
+    fn public_inputs_bind_the_state_root() {
+        let (proving_key, verifying_key) = setup(false);
+        let (parent_root, transactions_root, state_root) = ([1u8; 32], [2u8; 32], [4u8; 32]);
+        let (_, _, proof_package) = generate_proof(&proving_key, &verifying_key, dummy_accounts(), &parent_root, &transactions_root, &state_root);
+
+        // Flipping a byte of either half of the root breaks the proof
+        for index in [0, 31] {
+            let mut other_root = state_root;
+            other_root[index] ^= 1;
+            let other = compute_public_inputs(&dummy_accounts(), &parent_root, &transactions_root, &other_root);
+            let moved = ProofPackage {
+                proof: proof_package.proof.clone(),
+                public_inputs: other.prepare_projective(&proof_package.prepared_verifying_key).unwrap(),
+                prepared_verifying_key: proof_package.prepared_verifying_key.clone(),
+            };
+            assert!(!verify_proof_package(&moved));
+        }
+    }
+}
@@ -1,29 +1,59 @@
 use crate::account_state_circuit::AccountStateCircuit;
-use crate::byte_utils::bytes_to_field;
-use ark_bn254::{Bn254, Fr, G1Projective};
+use crate::public_inputs::compute_public_inputs;
+use crate::verifying_key::VerifyingKeyBytes;
+use ark_bn254::{Bn254, G1Projective};
 use ark_groth16::{prepare_verifying_key, Groth16, PreparedVerifyingKey, Proof, ProvingKey, VerifyingKey};
-use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Compress};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
 use ark_snark::SNARK;
 use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::{Pod, Zeroable};
 use rand::thread_rng;
 use serde::{Deserialize, Serialize};
+use serde_with::{base64::Base64, serde_as};
 use state::account_state::AccountState;
 use std::fs::File;
-use std::io::{Read, Write};
-
-//TODO we know the size of the proof and vk, so change from vec
-#[derive(BorshSerialize, BorshDeserialize)]
+use std::io::Write;
+use std::sync::{Arc, RwLock};
+
+/// Number of public inputs exposed by `AccountStateCircuit`.
+pub const PUBLIC_INPUT_COUNT: usize = 4;
+/// Uncompressed BN254 G1 point.
+pub const G1_SIZE: usize = 64;
+/// Uncompressed BN254 G2 point.
+pub const G2_SIZE: usize = 128;
+/// Uncompressed Groth16 proof: a (G1), b (G2), c (G1).
+pub const PROOF_SIZE: usize = G1_SIZE + G2_SIZE + G1_SIZE;
+/// Uncompressed `VerifyingKey<Bn254>` for `AccountStateCircuit`: alpha (G1), beta, gamma, delta (G2)
+/// and the length prefixed gamma_abc points, one per public input plus one.
+pub const VERIFYING_KEY_SIZE: usize = G1_SIZE + 3 * G2_SIZE + 8 + (PUBLIC_INPUT_COUNT + 1) * G1_SIZE;
+
+/// Keys saved by `setup(true)`, kept in memory once loaded, see `proving_keys`.
+static KEYS: RwLock<Option<Arc<(ProvingKey<Bn254>, VerifyingKey<Bn254>)>>> = RwLock::new(None);
+
+#[derive(BorshSerialize, BorshDeserialize, Pod, Zeroable, Clone, Copy)]
+#[repr(C)]
 pub struct ProofPackageLite {
-    pub proof: Vec<u8>,
-    pub public_inputs: Vec<[u8; 32]>,
-    pub verifying_key: Vec<u8>
+    pub proof: [u8; PROOF_SIZE],
+    pub public_inputs: [[u8; 32]; PUBLIC_INPUT_COUNT],
+    pub verifying_key: VerifyingKeyBytes
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+/// Proof with its public inputs already prepared against the verifying key. This is the payload
+/// sent to the validator.
+///
+/// Every field is a fixed size array, so the Borsh encoding has no length prefixes and the struct
+/// can be viewed as bytes without copying (`bytemuck::bytes_of`). Over JSON the fields are base64
+/// strings rather than arrays of numbers.
+#[serde_as]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Pod, Zeroable, Clone, Copy)]
+#[repr(C)]
 pub struct ProofPackagePrepared {
-    pub proof: Vec<u8>,
-    pub public_inputs: Vec<u8>,
-    pub verifying_key: Vec<u8>
+    #[serde_as(as = "Base64")]
+    pub proof: [u8; PROOF_SIZE],
+    #[serde_as(as = "Base64")]
+    pub public_inputs: [u8; G1_SIZE],
+    /// The full (unprepared) verifying key, the validator prepares it after deserializing.
+    pub verifying_key: VerifyingKeyBytes
 }
 
 pub struct ProofPackage {
@@ -32,16 +62,21 @@ pub struct ProofPackage {
     pub prepared_verifying_key: PreparedVerifyingKey<Bn254>
 }
 
-impl Into<ProofPackage> for ProofPackagePrepared {
-    fn into(self) -> ProofPackage {
-        let proof = Proof::<Bn254>::deserialize_uncompressed_unchecked(&self.proof[..]).expect("Error deserializing Proof");
-        let prepared_verifying_key = PreparedVerifyingKey::<Bn254>::deserialize_uncompressed_unchecked(&self.verifying_key[..]).expect("Error deserializing PreparedVerifyingKey");
-        let projective = G1Projective::deserialize_uncompressed_unchecked(&self.public_inputs[..]).expect("Error deserializing public inputs to Projective");
-        ProofPackage {
+impl TryFrom<ProofPackagePrepared> for ProofPackage {
+    type Error = SerializationError;
+
+    /// Deserializes and validates the proof, public inputs and verifying key. The bytes
+    /// come from outside the process (API requests, validator calls), so malformed or off-curve
+    /// points are reported as errors instead of panicking.
+    fn try_from(value: ProofPackagePrepared) -> Result<Self, Self::Error> {
+        let proof = Proof::<Bn254>::deserialize_uncompressed(&value.proof[..])?;
+        let prepared_verifying_key = value.verifying_key.prepare()?;
+        let projective = G1Projective::deserialize_uncompressed(&value.public_inputs[..])?;
+        Ok(ProofPackage {
             proof,
             public_inputs: projective,
             prepared_verifying_key,
-        }
+        })
     }
 }
 
@@ -60,41 +95,45 @@ pub fn setup(save_keys: bool) -> (ProvingKey<Bn254>, VerifyingKey<Bn254>){
         let mut vk_bytes = Vec::new();
         verifying_key.serialize_uncompressed(&mut vk_bytes).expect("");
         file.write(&vk_bytes).expect("TODO: panic message");
+
+        *KEYS.write().unwrap() = Some(Arc::new((proving_key.clone(), verifying_key.clone())));
     };
 
     (proving_key, verifying_key)
 }
 
-//TODO clean this up
-pub fn generate_proof_load_keys(accounts: Vec<AccountState>) -> (ProofPackageLite, ProofPackagePrepared, ProofPackage) {
-    // Open the file
-    let mut pk_file = File::open("pk.bin").expect("");
-
-    // Read the contents of the file
-    let mut pk_buffer = Vec::new();
-    pk_file.read_to_end(&mut pk_buffer).expect("");
-
-    // Deserialize the buffer into a VerifyingKey
-    let pk = ProvingKey::<Bn254>::deserialize_uncompressed_unchecked(&pk_buffer[..]).expect("");
-
-    // Open the file
-    let mut vk_file = File::open("vk.bin").expect("");
-
-    // Read the contents of the file
-    let mut vk_buffer = Vec::new();
-    vk_file.read_to_end(&mut vk_buffer).expect("");
+/// Loads the verifying key saved by `setup(true)`.
+pub fn load_verifying_key() -> Result<VerifyingKey<Bn254>, SerializationError> {
+    let vk_buffer = std::fs::read("vk.bin")?;
+    VerifyingKey::<Bn254>::deserialize_uncompressed(&vk_buffer[..])
+}
 
-    // Deserialize the buffer into a VerifyingKey
-    let vk = VerifyingKey::<Bn254>::deserialize_uncompressed_unchecked(&vk_buffer[..]).expect("");
+/// The keys saved by `setup(true)`. They are read from `pk.bin` and `vk.bin` the first time and
+/// served from memory after, `setup(true)` replaces them.
+pub fn proving_keys() -> Result<Arc<(ProvingKey<Bn254>, VerifyingKey<Bn254>)>, SerializationError> {
+    if let Some(keys) = KEYS.read().unwrap().as_ref() {
+        return Ok(Arc::clone(keys));
+    }
+    let proving_key = ProvingKey::<Bn254>::deserialize_uncompressed_unchecked(&std::fs::read("pk.bin")?[..])?;
+    let verifying_key = VerifyingKey::<Bn254>::deserialize_uncompressed_unchecked(&std::fs::read("vk.bin")?[..])?;
+    let keys = Arc::new((proving_key, verifying_key));
+    *KEYS.write().unwrap() = Some(Arc::clone(&keys));
+    Ok(keys)
+}
 
-    generate_proof(&pk, &vk, accounts)
+/// Proves with the keys saved by `setup(true)`, see `proving_keys`.
+pub fn generate_proof_load_keys(accounts: Vec<AccountState>, parent_root: &[u8; 32], transactions_root: &[u8; 32], state_root: &[u8; 32]) -> (ProofPackageLite, ProofPackagePrepared, ProofPackage) {
+    let keys = proving_keys().expect("Failed to load the proving keys");
+    generate_proof(&keys.0, &keys.1, accounts, parent_root, transactions_root, state_root)
 }
 
-pub fn generate_proof(proving_key: &ProvingKey<Bn254>, verifying_key: &VerifyingKey<Bn254>, accounts: Vec<AccountState>) -> (ProofPackageLite, ProofPackagePrepared, ProofPackage) {
+/// Proves the batch writing `accounts` on top of `parent_root` with the transactions of
+/// `transactions_root`, resulting in `state_root`, see `compute_public_inputs`.
+pub fn generate_proof(proving_key: &ProvingKey<Bn254>, verifying_key: &VerifyingKey<Bn254>, accounts: Vec<AccountState>, parent_root: &[u8; 32], transactions_root: &[u8; 32], state_root: &[u8; 32]) -> (ProofPackageLite, ProofPackagePrepared, ProofPackage) {
     let rng = &mut thread_rng();
 
-    let account_state_circuit = AccountStateCircuit::new(accounts);
-    let public_inputs = account_state_circuit.public_inputs();
+    let public_inputs = compute_public_inputs(&accounts, parent_root, transactions_root, state_root);
+    let account_state_circuit = AccountStateCircuit::new(accounts, parent_root, transactions_root, state_root);
 
     // Create a proof
     let proof = Groth16::<Bn254>::prove(&proving_key,
@@ -102,34 +141,25 @@ pub fn generate_proof(proving_key: &ProvingKey<Bn254>, verifying_key: &Verifying
                                         rng,
     ).unwrap();
 
-    let mut proof_bytes = Vec::with_capacity(proof.serialized_size(Compress::No));
-    proof.serialize_uncompressed(&mut proof_bytes).expect("Error serializing proof");
-
-    let public_inputs_fr = public_inputs
-        .iter()
-        .map(|input| bytes_to_field(input))
-        .collect::<Result<Vec<Fr>, _>>().expect("");
+    let mut proof_bytes = [0u8; PROOF_SIZE];
+    proof.serialize_uncompressed(&mut proof_bytes[..]).expect("Error serializing proof");
 
     let prepared_verifying_key = prepare_verifying_key(&verifying_key);
+    let g1_projective = public_inputs.prepare_projective(&prepared_verifying_key).expect("Error preparing inputs with public inputs and prepared verifying key");
 
-    let g1_projective: G1Projective = Groth16::<Bn254>::prepare_inputs(&prepared_verifying_key, &public_inputs_fr).expect("Error preparing inputs with public inputs and prepared verifying key");
-
-    let mut projective_bytes: Vec<u8> = Vec::new();
-    let _ = g1_projective.serialize_uncompressed(&mut projective_bytes);
-    let mut verifying_key_bytes: Vec<u8> = Vec::with_capacity(verifying_key.serialized_size(Compress::No));
-    let _ = verifying_key.serialize_uncompressed(&mut verifying_key_bytes);
-    let mut prepared_verifying_key_bytes: Vec<u8> = Vec::new();
-    let _ = prepared_verifying_key.serialize_uncompressed(&mut prepared_verifying_key_bytes);
+    let mut projective_bytes = [0u8; G1_SIZE];
+    g1_projective.serialize_uncompressed(&mut projective_bytes[..]).expect("Error serializing prepared inputs");
+    let verifying_key_bytes = VerifyingKeyBytes::from_verifying_key(verifying_key).expect("Error serializing verifying key");
 
     (ProofPackageLite {
-        proof: proof_bytes.clone(),
-        public_inputs: public_inputs.clone(),
-        verifying_key: prepared_verifying_key_bytes.clone(),
+        proof: proof_bytes,
+        public_inputs: public_inputs.to_bytes(),
+        verifying_key: verifying_key_bytes,
     },
      ProofPackagePrepared {
          proof: proof_bytes,
          public_inputs: projective_bytes,
-         verifying_key: prepared_verifying_key_bytes,
+         verifying_key: verifying_key_bytes,
      },
      ProofPackage {
          proof,
@@ -0,0 +1,93 @@
+//! The public inputs of a batch proof. The prover builds them here, and anyone holding a block's
+//! accounts and roots can recompute them and compare with the prepared inputs the block stores.
+
+use crate::byte_utils::field_to_bytes;
+use crate::prove::{G1_SIZE, PUBLIC_INPUT_COUNT};
+use ark_bn254::{Bn254, Fr, G1Projective};
+use ark_ff::PrimeField;
+use ark_groth16::{Groth16, PreparedVerifyingKey};
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::CanonicalSerialize;
+use light_poseidon::{Poseidon, PoseidonHasher};
+use sha2::{Digest, Sha256};
+use state::account_state::AccountState;
+
+/// Public inputs of `AccountStateCircuit` for one batch, with the roots they are bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PublicInputs {
+    /// Account root of the block the batch extends, all zeros for the first block
+    pub parent_root: [u8; 32],
+    /// Root of the batch's transaction tree, `state::merkle::transactions_root`
+    pub transactions_root: [u8; 32],
+    /// Account root the batch commits to, the block's `accounts_merkle_root`
+    pub state_root: [u8; 32],
+    /// Poseidon chain over the address and data of every account the batch writes, in write
+    /// order, starting from `roots_hash`
+    pub account_hash: Fr,
+    /// Lamports of every account the batch writes
+    pub lamports_sum: u64,
+}
+
+/// Computes the public inputs of the proof for a batch writing `accounts` on top of
+/// `parent_root` and committing to `state_root`. The account hash starts from a hash of both
+/// parent and transactions roots, so a proof only verifies for the parent and transactions it
+/// was generated for. The state root is an input of its own, so the proof verifier can derive it
+/// on chain from the root it is asked to store.
+pub fn compute_public_inputs(accounts: &[AccountState], parent_root: &[u8; 32], transactions_root: &[u8; 32], state_root: &[u8; 32]) -> PublicInputs {
+    let mut poseidon = Poseidon::<Fr>::new_circom(3).unwrap();
+    let mut account_hash = roots_hash(parent_root, transactions_root);
+    let mut lamports_sum = 0u64;
+    for account in accounts {
+        let address_fr = Fr::from_be_bytes_mod_order(&account.address.to_bytes());
+        let datum_fr = Fr::from_be_bytes_mod_order(account.data.as_slice());
+        account_hash = poseidon.hash(&[account_hash, address_fr, datum_fr]).unwrap();
+        lamports_sum += account.lamports;
+    }
+    PublicInputs {
+        parent_root: *parent_root,
+        transactions_root: *transactions_root,
+        state_root: *state_root,
+        account_hash,
+        lamports_sum,
+    }
+}
+
+/// Where the account hash chain of a batch starts.
+pub fn roots_hash(parent_root: &[u8; 32], transactions_root: &[u8; 32]) -> Fr {
+    let digest = Sha256::new().chain_update(parent_root).chain_update(transactions_root).finalize();
+    Fr::from_be_bytes_mod_order(&digest)
+}
+
+/// `state_root` as two field elements, its first and last 16 bytes read big-endian. A root
+/// doesn't fit in one, and the halves are what the proof verifier feeds the alt_bn128 syscalls
+/// zero padded to 32 bytes.
+pub fn state_root_limbs(state_root: &[u8; 32]) -> [Fr; 2] {
+    [Fr::from_be_bytes_mod_order(&state_root[..16]), Fr::from_be_bytes_mod_order(&state_root[16..])]
+}
+
+impl PublicInputs {
+    /// The inputs in the order the circuit allocates them.
+    pub fn field_elements(&self) -> [Fr; PUBLIC_INPUT_COUNT] {
+        let [state_root_high, state_root_low] = state_root_limbs(&self.state_root);
+        [self.account_hash, Fr::from(self.lamports_sum), state_root_high, state_root_low]
+    }
+
+    /// The inputs as `ProofPackageLite` carries them.
+    pub fn to_bytes(&self) -> [[u8; 32]; PUBLIC_INPUT_COUNT] {
+        self.field_elements().map(field_to_bytes)
+    }
+
+    /// The inputs prepared against `prepared_verifying_key`, as an uncompressed G1 point. This is
+    /// what blocks store in `accounts_zk_public_inputs` and what verifiers check proofs with.
+    pub fn prepare(&self, prepared_verifying_key: &PreparedVerifyingKey<Bn254>) -> Result<[u8; G1_SIZE], SynthesisError> {
+        let prepared = self.prepare_projective(prepared_verifying_key)?;
+        let mut bytes = [0u8; G1_SIZE];
+        prepared.serialize_uncompressed(&mut bytes[..]).map_err(|_| SynthesisError::Unsatisfiable)?;
+        Ok(bytes)
+    }
+
+    /// Like `prepare`, as the point itself.
+    pub fn prepare_projective(&self, prepared_verifying_key: &PreparedVerifyingKey<Bn254>) -> Result<G1Projective, SynthesisError> {
+        Groth16::<Bn254>::prepare_inputs(prepared_verifying_key, &self.field_elements())
+    }
+}
@@ -4,7 +4,7 @@ use ark_bn254::{Bn254, G1Projective};
 use ark_ec::pairing::Pairing;
 use ark_ff::{BigInteger, BigInteger256};
 use ark_groth16::{prepare_verifying_key, Groth16, PreparedVerifyingKey, Proof, VerifyingKey};
-use ark_serialize::CanonicalSerialize;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
 use ark_std::One;
 use solana_program::alt_bn128::prelude::{alt_bn128_pairing, ALT_BN128_PAIRING_ELEMENT_LEN, ALT_BN128_POINT_SIZE};
 use solana_program::alt_bn128::{AltBn128Error, PodG1, PodG2};
@@ -19,13 +19,25 @@ pub fn verify(
     vk: &VerifyingKey<Bn254>,
 ) -> bool {
     let pvk = prepare_verifying_key(vk);
-    Groth16::<Bn254>::verify_proof_with_prepared_inputs(&pvk, proof, public_inputs).unwrap()
+    Groth16::<Bn254>::verify_proof_with_prepared_inputs(&pvk, proof, public_inputs).unwrap_or(false)
 }
 
 pub fn verify_proof_package(
     proof_package: &ProofPackage
 ) -> bool {
-    Groth16::<Bn254>::verify_proof_with_prepared_inputs(&proof_package.prepared_verifying_key, &proof_package.proof, &proof_package.public_inputs).unwrap()
+    Groth16::<Bn254>::verify_proof_with_prepared_inputs(&proof_package.prepared_verifying_key, &proof_package.proof, &proof_package.public_inputs).unwrap_or(false)
+}
+
+/// Verifies an uncompressed proof against uncompressed prepared public inputs, e.g. the ones stored
+/// in a `Block`. Malformed bytes are reported as an error rather than as a failed verification.
+pub fn verify_serialized(
+    proof: &[u8],
+    prepared_public_inputs: &[u8],
+    pvk: &PreparedVerifyingKey<Bn254>,
+) -> Result<bool, SerializationError> {
+    let proof = Proof::<Bn254>::deserialize_uncompressed(proof)?;
+    let public_inputs = G1Projective::deserialize_uncompressed(prepared_public_inputs)?;
+    Ok(Groth16::<Bn254>::verify_proof_with_prepared_inputs(pvk, &proof, &public_inputs).unwrap_or(false))
 }
 
 pub fn verify_proof_with_prepared_inputs(
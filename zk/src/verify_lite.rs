@@ -1,6 +1,7 @@
 use crate::byte_utils::convert_endianness_32;
 use crate::errors::Groth16Error;
 use crate::errors::Groth16Error::{PairingVerificationError, ProofVerificationFailed};
+use crate::prove::PUBLIC_INPUT_COUNT;
 use ark_bn254::{Bn254, Fr, G1Projective};
 use ark_ec::AffineRepr;
 use ark_ff::PrimeField;
@@ -13,12 +14,38 @@ use solana_program::alt_bn128::prelude::{alt_bn128_addition, alt_bn128_multiplic
 use std::ops::AddAssign;
 use borsh::{BorshDeserialize, BorshSerialize};
 
+/// Public inputs the prover sends along with a proof, the proof verifier derives the others from
+/// the state root.
+pub const BATCH_INPUT_COUNT: usize = PUBLIC_INPUT_COUNT - 2;
+
+/// Payload of the proof verifier's `VerifyProof`. The verifier checks the proof against the
+/// verifying key it stores, with the state root halves as the last two public inputs.
 #[derive(PartialEq, Eq, Debug, Clone, BorshSerialize, BorshDeserialize)]
 pub struct ProofCommitmentPackage {
-    pub groth16_verifier_prepared: Groth16VerifierPrepared,
+    pub proof: Groth16Proof,
+    /// Account hash and lamports sum, big-endian
+    pub batch_inputs: [[u8; 32]; BATCH_INPUT_COUNT],
     pub state_root: [u8; 32]
 }
 
+/// A proof in the big-endian layout of the alt_bn128 syscalls, `a` negated.
+#[derive(PartialEq, Eq, Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct Groth16Proof {
+    pub proof_a: [u8; 64],
+    pub proof_b: [u8; 128],
+    pub proof_c: [u8; 64],
+}
+
+/// Payload of the proof verifier's `SetVerifyingKey`, the verifying key it stores.
+#[derive(PartialEq, Eq, Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct OnChainVerifyingKey {
+    pub vk_alpha_g1: [u8; 64],
+    pub vk_beta_g2: [u8; 128],
+    pub vk_gamma_g2: [u8; 128],
+    pub vk_delta_g2: [u8; 128],
+    pub vk_ic: [[u8; 64]; PUBLIC_INPUT_COUNT + 1],
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, BorshSerialize, BorshDeserialize)]
 pub struct Groth16VerifyingKey {
     pub nr_pubinputs: usize,
@@ -292,4 +319,42 @@ pub fn prepare_inputs(
     }
 
     Ok(g_ic)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trollup_commitment_message::instruction_vectors::{instruction_vectors, Instruction};
+
+    #[test]
+    fn proof_packages_match_the_instruction_vectors() {
+        let mut checked = 0;
+        for vector in instruction_vectors() {
+            let payload = match vector.instruction {
+                Instruction::VerifyProof => {
+                    let proof = vector.proof();
+                    let package = ProofCommitmentPackage {
+                        proof: Groth16Proof { proof_a: proof.proof_a(), proof_b: proof.proof_b(), proof_c: proof.proof_c() },
+                        batch_inputs: proof.batch_inputs(),
+                        state_root: proof.state_root(),
+                    };
+                    borsh::to_vec(&package).unwrap()
+                }
+                Instruction::SetVerifyingKey => {
+                    let fixture = vector.verifying_key();
+                    let verifying_key = OnChainVerifyingKey {
+                        vk_alpha_g1: fixture.vk_alpha_g1(),
+                        vk_beta_g2: fixture.vk_beta_g2(),
+                        vk_gamma_g2: fixture.vk_gamma_g2(),
+                        vk_delta_g2: fixture.vk_delta_g2(),
+                        vk_ic: fixture.vk_ic(),
+                    };
+                    borsh::to_vec(&verifying_key).unwrap()
+                }
+                _ => continue,
+            };
+            assert_eq!(payload, vector.payload(), "{}", vector.name);
+            checked += 1;
+        }
+        assert_eq!(checked, 2);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::prove::VERIFYING_KEY_SIZE;
+use ark_bn254::Bn254;
+use ark_groth16::{prepare_verifying_key, PreparedVerifyingKey, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+use serde_with::{base64::Base64, serde_as};
+
+/// Uncompressed bytes of a full (unprepared) `VerifyingKey<Bn254>`.
+///
+/// This is what travels in proof packages and what the on-chain verifier key is derived from. Use
+/// `prepare` to get the key used for off-chain verification.
+#[serde_as]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Pod, Zeroable, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct VerifyingKeyBytes(#[serde_as(as = "Base64")] pub [u8; VERIFYING_KEY_SIZE]);
+
+/// Uncompressed bytes of a `PreparedVerifyingKey<Bn254>`.
+///
+/// The prepared key contains precomputed pairing values and has no fixed size, it is kept in its
+/// own type so it can't be handed to anything expecting a `VerifyingKeyBytes`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct PreparedVerifyingKeyBytes(pub Vec<u8>);
+
+impl VerifyingKeyBytes {
+    pub fn from_verifying_key(verifying_key: &VerifyingKey<Bn254>) -> Result<Self, SerializationError> {
+        let mut bytes = [0u8; VERIFYING_KEY_SIZE];
+        if verifying_key.uncompressed_size() != VERIFYING_KEY_SIZE {
+            return Err(SerializationError::InvalidData);
+        }
+        verifying_key.serialize_uncompressed(&mut bytes[..])?;
+        Ok(VerifyingKeyBytes(bytes))
+    }
+
+    pub fn to_verifying_key(&self) -> Result<VerifyingKey<Bn254>, SerializationError> {
+        VerifyingKey::<Bn254>::deserialize_uncompressed(&self.0[..])
+    }
+
+    /// Deserializes the key and prepares it for verification.
+    pub fn prepare(&self) -> Result<PreparedVerifyingKey<Bn254>, SerializationError> {
+        Ok(prepare_verifying_key(&self.to_verifying_key()?))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl TryFrom<&[u8]> for VerifyingKeyBytes {
+    type Error = SerializationError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        value.try_into().map(VerifyingKeyBytes).map_err(|_| SerializationError::InvalidData)
+    }
+}
+
+impl PreparedVerifyingKeyBytes {
+    pub fn from_prepared_verifying_key(prepared_verifying_key: &PreparedVerifyingKey<Bn254>) -> Result<Self, SerializationError> {
+        let mut bytes = Vec::with_capacity(prepared_verifying_key.uncompressed_size());
+        prepared_verifying_key.serialize_uncompressed(&mut bytes)?;
+        Ok(PreparedVerifyingKeyBytes(bytes))
+    }
+
+    pub fn to_prepared_verifying_key(&self) -> Result<PreparedVerifyingKey<Bn254>, SerializationError> {
+        PreparedVerifyingKey::<Bn254>::deserialize_uncompressed(&self.0[..])
+    }
+
+    /// The full key the prepared key was derived from.
+    pub fn to_verifying_key_bytes(&self) -> Result<VerifyingKeyBytes, SerializationError> {
+        VerifyingKeyBytes::from_verifying_key(&self.to_prepared_verifying_key()?.vk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prove::setup;
+
+    #[test]
+    fn verifying_key_round_trip() {
+        let (_, verifying_key) = setup(false);
+        let bytes = VerifyingKeyBytes::from_verifying_key(&verifying_key).unwrap();
+        assert_eq!(bytes.to_verifying_key().unwrap(), verifying_key);
+        assert_eq!(VerifyingKeyBytes::try_from(bytes.as_bytes()).unwrap(), bytes);
+    }
+
+    #[test]
+    fn prepared_verifying_key_round_trip() {
+        let (_, verifying_key) = setup(false);
+        let prepared = prepare_verifying_key(&verifying_key);
+        let bytes = PreparedVerifyingKeyBytes::from_prepared_verifying_key(&prepared).unwrap();
+        assert_eq!(bytes.to_prepared_verifying_key().unwrap(), prepared);
+        assert_eq!(bytes.to_verifying_key_bytes().unwrap(), VerifyingKeyBytes::from_verifying_key(&verifying_key).unwrap());
+    }
+
+    #[test]
+    fn prepare_matches_arkworks() {
+        let (_, verifying_key) = setup(false);
+        let bytes = VerifyingKeyBytes::from_verifying_key(&verifying_key).unwrap();
+        assert_eq!(bytes.prepare().unwrap(), prepare_verifying_key(&verifying_key));
+    }
+
+    #[test]
+    fn prepared_bytes_are_not_a_verifying_key() {
+        let (_, verifying_key) = setup(false);
+        let prepared = PreparedVerifyingKeyBytes::from_prepared_verifying_key(&prepare_verifying_key(&verifying_key)).unwrap();
+        assert!(VerifyingKeyBytes::try_from(prepared.0.as_slice()).is_err());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let (_, verifying_key) = setup(false);
+        let bytes = VerifyingKeyBytes::from_verifying_key(&verifying_key).unwrap();
+        let json = serde_json::to_string(&bytes).unwrap();
+        assert!(json.starts_with('"'));
+        assert_eq!(serde_json::from_str::<VerifyingKeyBytes>(&json).unwrap(), bytes);
+        assert_eq!(borsh::from_slice::<VerifyingKeyBytes>(&borsh::to_vec(&bytes).unwrap()).unwrap(), bytes);
+    }
+}